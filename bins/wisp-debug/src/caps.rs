@@ -0,0 +1,339 @@
+//! `wisp-debug caps`: what the running notification daemon advertises next to what wispd
+//! would advertise with the current config, for questions like "why does this client not
+//! send actions?". Runs as a plain client; nothing is served.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use wisp_source::{CapabilityDiff, DBUS_INTERFACE, DEFAULT_DBUS_NAME, DEFAULT_DBUS_PATH, Features};
+
+const USAGE: &str = "usage: wisp-debug caps [--json] [--config <path>]";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CapsOptions {
+    pub(crate) json: bool,
+    /// wispd config to read instead of `$XDG_CONFIG_HOME/wispd/config.toml`.
+    pub(crate) config: Option<PathBuf>,
+}
+
+impl CapsOptions {
+    pub(crate) fn parse<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--json" => options.json = true,
+                "--config" => options.config = Some(args.next().ok_or(USAGE)?.into()),
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// The parts of wispd's config that decide its capabilities, with wispd's defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WispdConfig {
+    source: SourceSection,
+    ui: UiSection,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct SourceSection {
+    capabilities: Vec<String>,
+}
+
+impl Default for SourceSection {
+    fn default() -> Self {
+        Self {
+            capabilities: vec![
+                "body".to_string(),
+                "actions".to_string(),
+                "action-icons".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct UiSection {
+    show_icons: bool,
+    sound: SoundSection,
+}
+
+impl Default for UiSection {
+    fn default() -> Self {
+        Self {
+            show_icons: true,
+            sound: SoundSection::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SoundSection {
+    enabled: bool,
+}
+
+/// Everything `caps` prints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CapsReport {
+    /// `(name, vendor, version, spec_version)` of the bus owner.
+    pub(crate) server: (String, String, String, String),
+    pub(crate) live: Features,
+    pub(crate) config_path: PathBuf,
+    /// What wispd advertises with that config.
+    pub(crate) configured: Features,
+}
+
+impl CapsReport {
+    fn diff(&self) -> CapabilityDiff {
+        self.configured.diff(&self.live)
+    }
+}
+
+pub(crate) async fn run(options: &CapsOptions) -> Result<()> {
+    let config_path = options.config.clone().unwrap_or_else(default_config_path);
+    let configured = configured_features(&config_path)?;
+    let (server, live) = query_daemon().await?;
+    let report = CapsReport {
+        server,
+        live,
+        config_path,
+        configured,
+    };
+    if options.json {
+        println!("{}", render_json(&report));
+    } else {
+        print!("{}", render_table(&report));
+    }
+    Ok(())
+}
+
+async fn query_daemon() -> Result<((String, String, String, String), Features)> {
+    let conn = zbus::Connection::session()
+        .await
+        .context("failed to connect to the session bus")?;
+    let call = |method: &'static str| {
+        conn.call_method(
+            Some(DEFAULT_DBUS_NAME),
+            DEFAULT_DBUS_PATH,
+            Some(DBUS_INTERFACE),
+            method,
+            &(),
+        )
+    };
+    let server = call("GetServerInformation")
+        .await
+        .context("no notification daemon answered GetServerInformation")?
+        .body()
+        .deserialize()
+        .context("GetServerInformation did not return (ssss)")?;
+    let capabilities: Vec<String> = call("GetCapabilities")
+        .await
+        .context("GetCapabilities failed")?
+        .body()
+        .deserialize()
+        .context("GetCapabilities did not return as")?;
+    Ok((server, Features::from_capabilities(capabilities)))
+}
+
+/// wispd's advertised features for the config at `path`; a missing file means defaults,
+/// as in wispd.
+fn configured_features(path: &Path) -> Result<Features> {
+    let cfg = match fs::read_to_string(path) {
+        Ok(raw) => toml::from_str::<WispdConfig>(&raw)
+            .with_context(|| format!("failed to parse {}", path.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => WispdConfig::default(),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let mut features =
+        Features::from_capabilities(&cfg.source.capabilities).honoured_by_ui(cfg.ui.show_icons);
+    features.sound = cfg.ui.sound.enabled;
+    Ok(features)
+}
+
+fn default_config_path() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("wispd")
+        .join("config.toml")
+}
+
+pub(crate) fn render_table(report: &CapsReport) -> String {
+    let (name, vendor, version, spec) = &report.server;
+    let diff = report.diff();
+    let mut out = String::new();
+    let _ = writeln!(out, "daemon: {name} {version} ({vendor}, spec {spec})");
+    let _ = writeln!(out, "config: {}", report.config_path.display());
+    let _ = writeln!(out, "{:<20} {:<5} {:<5}", "CAPABILITY", "LIVE", "WISPD");
+    let rows = diff
+        .matching
+        .iter()
+        .map(|cap| (cap, "yes", "yes", ""))
+        .chain(
+            diff.not_advertised
+                .iter()
+                .map(|cap| (cap, "no", "yes", "  <- wispd would advertise this")),
+        )
+        .chain(
+            diff.unexpected
+                .iter()
+                .map(|cap| (cap, "yes", "no", "  <- not from this wispd config")),
+        );
+    for (cap, live, wispd, note) in rows {
+        let _ = writeln!(out, "{cap:<20} {live:<5} {wispd:<5}{note}");
+    }
+    if diff.is_empty() {
+        let _ = writeln!(out, "live capabilities match the wispd config");
+    } else {
+        let _ = writeln!(
+            out,
+            "{} missing from the daemon, {} not in the wispd config",
+            diff.not_advertised.len(),
+            diff.unexpected.len()
+        );
+    }
+    out
+}
+
+pub(crate) fn render_json(report: &CapsReport) -> String {
+    let (name, vendor, version, spec_version) = &report.server;
+    let diff = report.diff();
+    serde_json::json!({
+        "server": {
+            "name": name,
+            "vendor": vendor,
+            "version": version,
+            "spec_version": spec_version,
+        },
+        "config": report.config_path.display().to_string(),
+        "live": report.live.capabilities(),
+        "configured": report.configured.capabilities(),
+        "diff": {
+            "matching": diff.matching,
+            "not_advertised": diff.not_advertised,
+            "unexpected": diff.unexpected,
+        },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(live: &[&str], configured: &[&str]) -> CapsReport {
+        CapsReport {
+            server: (
+                "wispd".to_string(),
+                "wispd".to_string(),
+                "0.1.0".to_string(),
+                "1.2".to_string(),
+            ),
+            live: Features::from_capabilities(live),
+            config_path: PathBuf::from("/tmp/wispd/config.toml"),
+            configured: Features::from_capabilities(configured),
+        }
+    }
+
+    #[test]
+    fn options_parse_json_and_config() {
+        assert_eq!(
+            CapsOptions::parse(["--json", "--config", "/etc/wispd.toml"]),
+            Ok(CapsOptions {
+                json: true,
+                config: Some(PathBuf::from("/etc/wispd.toml")),
+            })
+        );
+        assert!(CapsOptions::parse(["--config"]).is_err());
+        assert!(CapsOptions::parse(["--yaml"]).is_err());
+    }
+
+    #[test]
+    fn table_marks_each_side_of_a_mismatch() {
+        let table = render_table(&report(&["body", "body-markup"], &["body", "actions"]));
+        assert_eq!(
+            table,
+            "daemon: wispd 0.1.0 (wispd, spec 1.2)\n\
+             config: /tmp/wispd/config.toml\n\
+             CAPABILITY           LIVE  WISPD\n\
+             body                 yes   yes  \n\
+             actions              no    yes    <- wispd would advertise this\n\
+             body-markup          yes   no     <- not from this wispd config\n\
+             1 missing from the daemon, 1 not in the wispd config\n"
+        );
+        assert!(
+            render_table(&report(&["body"], &["body"]))
+                .ends_with("live capabilities match the wispd config\n")
+        );
+    }
+
+    #[test]
+    fn json_carries_both_lists_and_the_diff() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render_json(&report(&["body"], &["body", "actions"]))).unwrap();
+        assert_eq!(json["server"]["spec_version"], "1.2");
+        assert_eq!(json["live"], serde_json::json!(["body"]));
+        assert_eq!(json["configured"], serde_json::json!(["body", "actions"]));
+        assert_eq!(
+            json["diff"]["not_advertised"],
+            serde_json::json!(["actions"])
+        );
+        assert_eq!(json["diff"]["unexpected"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn configured_features_follow_the_wispd_config() {
+        let dir = std::env::temp_dir().join(format!("wisp-debug-caps-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+
+        assert_eq!(
+            configured_features(&path).unwrap().capabilities(),
+            vec!["body", "actions", "action-icons"],
+            "a missing config means wispd's defaults"
+        );
+
+        fs::write(
+            &path,
+            "[source]\ncapabilities = [\"body\", \"icon-static\"]\n[ui]\nshow_icons = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            configured_features(&path).unwrap().capabilities(),
+            vec!["body"]
+        );
+
+        fs::write(
+            &path,
+            "[source]\ncapabilities = [\"body\", \"sound\"]\n[ui.sound]\nenabled = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            configured_features(&path).unwrap().capabilities(),
+            vec!["body"],
+            "sound follows whether wispd plays sounds"
+        );
+        fs::write(&path, "[ui.sound]\nenabled = true\n").unwrap();
+        assert_eq!(
+            configured_features(&path).unwrap().capabilities(),
+            vec!["body", "actions", "action-icons", "sound"]
+        );
+
+        fs::write(&path, "[source\n").unwrap();
+        assert!(configured_features(&path).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use wisp_types::NotificationEvent;
+
+/// Samples kept per event kind; older ones are dropped first.
+const MAX_SAMPLES: usize = 4096;
+
+/// Printed with every summary until events carry a source-side timestamp.
+pub(crate) const NO_SOURCE_TIMESTAMPS: &str = "events carry no source timestamps; \
+     showing intervals between receipts of each event kind instead of end-to-end latency";
+
+pub(crate) fn event_kind(event: &NotificationEvent) -> &'static str {
+    match event {
+        NotificationEvent::Received { .. } => "received",
+        NotificationEvent::Replaced { .. } => "replaced",
+        NotificationEvent::Closed { .. } => "closed",
+        NotificationEvent::ActionInvoked { .. } => "action-invoked",
+        NotificationEvent::Replied { .. } => "replied",
+        NotificationEvent::DndChanged { .. } => "dnd-changed",
+        NotificationEvent::Historic { .. } => "historic",
+        NotificationEvent::BatchStarted { .. } => "batch-started",
+        NotificationEvent::BatchEnded { .. } => "batch-ended",
+        NotificationEvent::Overflowed { .. } => "overflowed",
+        NotificationEvent::RateLimited { .. } => "rate-limited",
+        _ => "other",
+    }
+}
+
+/// Per-kind receipt timings since start.
+#[derive(Debug, Default)]
+pub(crate) struct EventTimings {
+    last_receipt: BTreeMap<&'static str, Instant>,
+    samples: BTreeMap<&'static str, VecDeque<Duration>>,
+}
+
+/// Percentiles for one event kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimingSummary {
+    pub(crate) kind: &'static str,
+    pub(crate) count: usize,
+    pub(crate) p50: Duration,
+    pub(crate) p95: Duration,
+    pub(crate) max: Duration,
+}
+
+impl EventTimings {
+    /// Records a receipt at `now`; returns the interval since the previous event of `kind`.
+    pub(crate) fn record(&mut self, kind: &'static str, now: Instant) -> Option<Duration> {
+        let interval = self
+            .last_receipt
+            .insert(kind, now)
+            .map(|previous| now.saturating_duration_since(previous))?;
+        let samples = self.samples.entry(kind).or_default();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(interval);
+        Some(interval)
+    }
+
+    pub(crate) fn summaries(&self) -> Vec<TimingSummary> {
+        self.samples
+            .iter()
+            .filter(|(_, samples)| !samples.is_empty())
+            .map(|(&kind, samples)| {
+                let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+                sorted.sort_unstable();
+                TimingSummary {
+                    kind,
+                    count: sorted.len(),
+                    p50: percentile(&sorted, 50),
+                    p95: percentile(&sorted, 95),
+                    max: sorted[sorted.len() - 1],
+                }
+            })
+            .collect()
+    }
+
+    /// Table for the `lat` command.
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "note: {NO_SOURCE_TIMESTAMPS}");
+        let summaries = self.summaries();
+        if summaries.is_empty() {
+            let _ = writeln!(out, "no intervals yet (need two events of a kind)");
+            return out;
+        }
+        let _ = writeln!(
+            out,
+            "{:<15} {:>6} {:>10} {:>10} {:>10}",
+            "EVENT", "N", "P50", "P95", "MAX"
+        );
+        for s in summaries {
+            let _ = writeln!(
+                out,
+                "{:<15} {:>6} {:>10} {:>10} {:>10}",
+                s.kind,
+                s.count,
+                format_duration(s.p50),
+                format_duration(s.p95),
+                format_duration(s.max)
+            );
+        }
+        out
+    }
+}
+
+/// Nearest-rank percentile of an ascending, non-empty slice.
+fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{}µs", duration.as_micros())
+    } else if duration < Duration::from_secs(1) {
+        format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_event_of_a_kind_has_no_interval() {
+        let mut timings = EventTimings::default();
+        let start = Instant::now();
+        assert_eq!(timings.record("received", start), None);
+        assert_eq!(timings.record("closed", start), None);
+        assert_eq!(
+            timings.record("received", start + Duration::from_millis(40)),
+            Some(Duration::from_millis(40))
+        );
+        assert!(timings.summaries().iter().all(|s| s.kind == "received"));
+    }
+
+    #[test]
+    fn summaries_use_nearest_rank_percentiles() {
+        let mut timings = EventTimings::default();
+        let mut now = Instant::now();
+        timings.record("received", now);
+        for ms in 1..=100 {
+            now += Duration::from_millis(ms);
+            timings.record("received", now);
+        }
+
+        let summary = timings.summaries()[0];
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p95, Duration::from_millis(95));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn samples_are_bounded() {
+        let mut timings = EventTimings::default();
+        let mut now = Instant::now();
+        for _ in 0..=MAX_SAMPLES + 10 {
+            now += Duration::from_millis(1);
+            timings.record("replaced", now);
+        }
+        assert_eq!(timings.summaries()[0].count, MAX_SAMPLES);
+    }
+
+    #[test]
+    fn render_says_it_is_showing_receipt_intervals() {
+        let mut timings = EventTimings::default();
+        assert!(timings.render().contains("no intervals yet"));
+
+        let now = Instant::now();
+        timings.record("closed", now);
+        timings.record("closed", now + Duration::from_micros(250));
+        let rendered = timings.render();
+        assert!(rendered.starts_with("note: events carry no source timestamps"));
+        assert!(rendered.contains("closed"));
+        assert!(rendered.contains("250µs"));
+    }
+
+    #[test]
+    fn durations_pick_a_readable_unit() {
+        assert_eq!(format_duration(Duration::from_micros(12)), "12µs");
+        assert_eq!(format_duration(Duration::from_micros(1500)), "1.5ms");
+        assert_eq!(format_duration(Duration::from_millis(2500)), "2.50s");
+    }
+}
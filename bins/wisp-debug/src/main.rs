@@ -1,17 +1,30 @@
-use std::io::{self, BufRead};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    time::Instant,
+};
 
 use anyhow::Result;
 use tokio::{signal, sync::mpsc};
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
-use wisp_source::{SourceConfig, WispSource};
-use wisp_types::CloseReason;
+use wisp_source::{CommandError, SourceConfig, WispSource};
+use wisp_types::{CloseReason, NotificationEvent};
+
+mod caps;
+mod latency;
+mod table;
+
+use latency::EventTimings;
+use table::{ListEntry, ListOptions};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum DebugCommand {
     Help,
-    List,
+    List(ListOptions),
+    Latency,
     Close(u32),
+    CloseAll,
     Action { id: u32, key: String },
     Quit,
 }
@@ -24,8 +37,10 @@ fn parse_command(line: &str) -> Result<Option<DebugCommand>, String> {
 
     match cmd {
         "help" => Ok(Some(DebugCommand::Help)),
-        "list" => Ok(Some(DebugCommand::List)),
+        "list" => Ok(Some(DebugCommand::List(ListOptions::parse(parts)?))),
+        "lat" => Ok(Some(DebugCommand::Latency)),
         "quit" | "exit" => Ok(Some(DebugCommand::Quit)),
+        "closeall" => Ok(Some(DebugCommand::CloseAll)),
         "close" => {
             let id = parts
                 .next()
@@ -46,7 +61,9 @@ fn parse_command(line: &str) -> Result<Option<DebugCommand>, String> {
                 .to_string();
             Ok(Some(DebugCommand::Action { id, key }))
         }
-        _ => Err("unknown command; use: help, list, close, action, quit".to_string()),
+        _ => {
+            Err("unknown command; use: help, list, lat, close, closeall, action, quit".to_string())
+        }
     }
 }
 
@@ -56,6 +73,13 @@ async fn main() -> Result<()> {
         .with_env_filter(EnvFilter::from_default_env().add_directive("wisp_debug=info".parse()?))
         .init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("caps") {
+        let options = caps::CapsOptions::parse(args[1..].iter().map(String::as_str))
+            .map_err(anyhow::Error::msg)?;
+        return caps::run(&options).await;
+    }
+
     let cfg = SourceConfig::default();
     let (source, mut events, _dbus) = WispSource::start_dbus(cfg.clone()).await?;
 
@@ -66,8 +90,14 @@ async fn main() -> Result<()> {
         "wisp-debug listening for notifications"
     );
     info!("send one with: notify-send 'hello from notify-send'");
-    info!("commands: help | list | close <id> | action <id> <action-key> | quit");
+    info!(
+        "commands: help | list [--full] [--app X] [--sort age|urgency] | lat | close <id> | closeall | action <id> <action-key> | quit"
+    );
 
+    // When each live notification last arrived or changed, for the `list` age column.
+    let mut seen_at: HashMap<u32, Instant> = HashMap::new();
+    let mut timings = EventTimings::default();
+    info!("{}", latency::NO_SOURCE_TIMESTAMPS);
     let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<DebugCommand>();
     tokio::task::spawn_blocking(move || {
         let stdin = io::stdin();
@@ -101,7 +131,20 @@ async fn main() -> Result<()> {
                     warn!("event stream ended");
                     break;
                 };
-                info!(?event, "notification event");
+                let kind = latency::event_kind(&event);
+                let since_previous = timings
+                    .record(kind, Instant::now())
+                    .map(latency::format_duration);
+                match &event {
+                    NotificationEvent::Received { id, .. } | NotificationEvent::Replaced { id, .. } => {
+                        seen_at.insert(*id, Instant::now());
+                    }
+                    NotificationEvent::Closed { id, .. } => {
+                        seen_at.remove(id);
+                    }
+                    _ => {}
+                }
+                info!(?event, since_previous_of_kind = since_previous.as_deref().unwrap_or("-"), "notification event");
             }
             maybe_cmd = cmd_rx.recv() => {
                 let Some(cmd) = maybe_cmd else {
@@ -111,22 +154,43 @@ async fn main() -> Result<()> {
 
                 match cmd {
                     DebugCommand::Help => {
-                        info!("commands: help | list | close <id> | action <id> <action-key> | quit");
+                        info!("commands: help | list [--full] [--app X] [--sort age|urgency] | lat | close <id> | closeall | action <id> <action-key> | quit");
                     }
-                    DebugCommand::List => {
-                        let snapshot = source.snapshot().await;
-                        info!(count = snapshot.len(), "current notifications");
-                        for (id, n) in snapshot {
-                            info!(id, app = %n.app_name, summary = %n.summary, "notification");
-                        }
+                    DebugCommand::List(options) => {
+                        let now = Instant::now();
+                        let entries = source
+                            .snapshot_detailed()
+                            .into_iter()
+                            .map(|entry| ListEntry {
+                                id: entry.id,
+                                age: seen_at
+                                    .get(&entry.id)
+                                    .map(|seen| now.duration_since(*seen))
+                                    .unwrap_or_default(),
+                                remaining: entry.remaining,
+                                notification: entry.notification,
+                            })
+                            .collect();
+                        print!("{}", table::render_list(entries, &options, terminal_width()));
                     }
+                    DebugCommand::Latency => print!("{}", timings.render()),
                     DebugCommand::Close(id) => {
-                        let closed = source.close(id, CloseReason::ClosedByCall).await?;
-                        info!(id, closed, "close command handled");
+                        match source.close(id, CloseReason::ClosedByCall).await {
+                            Ok(()) => info!(id, "close command handled"),
+                            Err(err @ CommandError::Backend(_)) => return Err(err.into()),
+                            Err(err) => warn!(id, %err, "close command rejected"),
+                        }
+                    }
+                    DebugCommand::CloseAll => {
+                        let closed = source.close_all(CloseReason::ClosedByCall).await?;
+                        info!(?closed, "closeall command handled");
                     }
                     DebugCommand::Action { id, key } => {
-                        let invoked = source.invoke_action(id, &key).await?;
-                        info!(id, action_key = %key, invoked, "action command handled");
+                        match source.invoke_action(id, &key).await {
+                            Ok(()) => info!(id, action_key = %key, "action command handled"),
+                            Err(err @ CommandError::Backend(_)) => return Err(err.into()),
+                            Err(err) => warn!(id, action_key = %key, %err, "action command rejected"),
+                        }
                     }
                     DebugCommand::Quit => {
                         info!("quitting");
@@ -144,6 +208,13 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Columns available on stdout, or [`table::DEFAULT_WIDTH`] when it is not a terminal.
+fn terminal_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(width, _)| usize::from(width.0))
+        .unwrap_or(table::DEFAULT_WIDTH)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,6 +227,25 @@ mod tests {
     #[test]
     fn parse_close_command() {
         assert_eq!(parse_command("close 42"), Ok(Some(DebugCommand::Close(42))));
+        assert_eq!(parse_command("closeall"), Ok(Some(DebugCommand::CloseAll)));
+    }
+
+    #[test]
+    fn parse_list_command_with_options() {
+        assert_eq!(
+            parse_command("list --app mail --full"),
+            Ok(Some(DebugCommand::List(ListOptions {
+                full: true,
+                app: Some("mail".to_string()),
+                ..ListOptions::default()
+            })))
+        );
+        assert!(parse_command("list --bogus").is_err());
+    }
+
+    #[test]
+    fn parse_latency_command() {
+        assert_eq!(parse_command("lat"), Ok(Some(DebugCommand::Latency)));
     }
 
     #[test]
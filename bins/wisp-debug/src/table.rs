@@ -0,0 +1,318 @@
+use std::{fmt::Write as _, time::Duration};
+
+use unicode_segmentation::UnicodeSegmentation;
+use wisp_types::{Notification, Urgency};
+
+/// Width used when the terminal size cannot be detected (e.g. output is piped).
+pub(crate) const DEFAULT_WIDTH: usize = 100;
+
+const ID_WIDTH: usize = 6;
+const AGE_WIDTH: usize = 5;
+const LEFT_WIDTH: usize = 5;
+const APP_WIDTH: usize = 16;
+const ACTIONS_WIDTH: usize = 3;
+const MIN_SUMMARY_WIDTH: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SortKey {
+    /// Newest first.
+    #[default]
+    Age,
+    /// Most urgent first, newest first within an urgency.
+    Urgency,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ListOptions {
+    /// Also print bodies and hints under each row.
+    pub(crate) full: bool,
+    /// Only show notifications from this app (case-insensitive).
+    pub(crate) app: Option<String>,
+    pub(crate) sort: SortKey,
+}
+
+impl ListOptions {
+    pub(crate) fn parse<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        const USAGE: &str = "usage: list [--full] [--app <name>] [--sort age|urgency]";
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--full" => options.full = true,
+                "--app" => options.app = Some(args.next().ok_or(USAGE)?.to_string()),
+                "--sort" => {
+                    options.sort = match args.next() {
+                        Some("age") => SortKey::Age,
+                        Some("urgency") => SortKey::Urgency,
+                        _ => return Err(USAGE.to_string()),
+                    }
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A live notification plus how long ago wisp-debug saw it arrive or change and how
+/// long it has left before it expires (`None`: never).
+#[derive(Debug, Clone)]
+pub(crate) struct ListEntry {
+    pub(crate) id: u32,
+    pub(crate) age: Duration,
+    pub(crate) remaining: Option<Duration>,
+    pub(crate) notification: Notification,
+}
+
+/// Renders `entries` as a table fitting `width` columns.
+pub(crate) fn render_list(
+    mut entries: Vec<ListEntry>,
+    options: &ListOptions,
+    width: usize,
+) -> String {
+    if let Some(app) = &options.app {
+        entries.retain(|e| e.notification.app_name.eq_ignore_ascii_case(app));
+    }
+    match options.sort {
+        SortKey::Age => entries.sort_by(|a, b| a.age.cmp(&b.age).then(b.id.cmp(&a.id))),
+        SortKey::Urgency => entries.sort_by(|a, b| {
+            b.notification
+                .urgency
+                .cmp(&a.notification.urgency)
+                .then(a.age.cmp(&b.age))
+                .then(b.id.cmp(&a.id))
+        }),
+    }
+
+    // id, age, time left, urgency glyph, app, summary, actions; one space between columns.
+    let fixed = ID_WIDTH + AGE_WIDTH + LEFT_WIDTH + 1 + APP_WIDTH + ACTIONS_WIDTH + 6;
+    let summary_width = width.saturating_sub(fixed).max(MIN_SUMMARY_WIDTH);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:>ID_WIDTH$} {:>AGE_WIDTH$} {:>LEFT_WIDTH$} U {} {} {:>ACTIONS_WIDTH$}",
+        "ID",
+        "AGE",
+        "LEFT",
+        pad("APP", APP_WIDTH),
+        pad("SUMMARY", summary_width),
+        "ACT",
+    );
+    for entry in &entries {
+        let n = &entry.notification;
+        let _ = writeln!(
+            out,
+            "{:>ID_WIDTH$} {:>AGE_WIDTH$} {:>LEFT_WIDTH$} {} {} {} {:>ACTIONS_WIDTH$}",
+            entry.id,
+            format_age(entry.age),
+            entry.remaining.map_or_else(|| "-".to_string(), format_age),
+            urgency_glyph(&n.urgency),
+            pad(&truncate(&n.app_name, APP_WIDTH), APP_WIDTH),
+            pad(
+                &truncate(&one_line(&n.summary), summary_width),
+                summary_width
+            ),
+            n.actions.len(),
+        );
+        if options.full {
+            write_details(&mut out, n);
+        }
+    }
+    let _ = writeln!(out, "{} notification(s)", entries.len());
+    out
+}
+
+fn write_details(out: &mut String, n: &Notification) {
+    const INDENT: &str = "         ";
+    for line in n.body.lines() {
+        let _ = writeln!(out, "{INDENT}| {line}");
+    }
+    for action in &n.actions {
+        let _ = writeln!(out, "{INDENT}action {} = {}", action.key, action.label);
+    }
+
+    let mut hints = Vec::new();
+    if let Some(category) = &n.hints.category {
+        hints.push(format!("category={category}"));
+    }
+    if let Some(desktop_entry) = &n.hints.desktop_entry {
+        hints.push(format!("desktop-entry={desktop_entry}"));
+    }
+    if let Some(transient) = n.hints.transient {
+        hints.push(format!("transient={transient}"));
+    }
+    if let Some(app_id) = &n.hints.sandbox_app_id {
+        hints.push(format!("sandbox-app-id={app_id}"));
+    }
+    let mut extra: Vec<_> = n.hints.extra.iter().collect();
+    extra.sort_unstable_by_key(|(key, _)| *key);
+    hints.extend(
+        extra
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}")),
+    );
+    for hint in hints {
+        let _ = writeln!(out, "{INDENT}hint {hint}");
+    }
+}
+
+fn urgency_glyph(urgency: &Urgency) -> char {
+    match urgency {
+        Urgency::Low => '-',
+        Urgency::Normal => '·',
+        Urgency::Critical => '!',
+    }
+}
+
+pub(crate) fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Cuts `text` to at most `max` graphemes, marking the cut with `…`.
+fn truncate(text: &str, max: usize) -> String {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    if graphemes.len() <= max {
+        return text.to_string();
+    }
+    let mut out = graphemes[..max.saturating_sub(1)].concat();
+    out.push('…');
+    out
+}
+
+/// Left-aligns `text` in `width` grapheme cells.
+fn pad(text: &str, width: usize) -> String {
+    let len = text.graphemes(true).count();
+    format!("{text}{}", " ".repeat(width.saturating_sub(len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use wisp_types::NotificationAction;
+
+    use super::*;
+
+    fn entry(id: u32, age_secs: u64, app: &str, summary: &str, urgency: Urgency) -> ListEntry {
+        ListEntry {
+            id,
+            age: Duration::from_secs(age_secs),
+            remaining: None,
+            notification: Notification {
+                app_name: app.to_string(),
+                summary: summary.to_string(),
+                urgency,
+                ..Notification::default()
+            },
+        }
+    }
+
+    fn fixture() -> Vec<ListEntry> {
+        let mut mail = entry(
+            3,
+            5,
+            "thunderbird",
+            "New message from Ada about the quarterly report",
+            Urgency::Normal,
+        );
+        mail.notification.body = "Hi,\nsee attached.".to_string();
+        mail.notification.actions = vec![NotificationAction {
+            key: "default".to_string(),
+            label: "Open".to_string(),
+        }];
+        mail.notification.hints.category = Some("email.arrived".to_string());
+        mail.remaining = Some(Duration::from_secs(25));
+        vec![
+            entry(1, 7200, "backup", "Backup finished", Urgency::Low),
+            entry(2, 90, "battery", "Battery low: 5%", Urgency::Critical),
+            mail,
+        ]
+    }
+
+    #[test]
+    fn list_renders_table_sorted_by_age() {
+        let rendered = render_list(fixture(), &ListOptions::default(), 66);
+        let expected = [
+            "    ID   AGE  LEFT U APP              SUMMARY                  ACT",
+            "     3    5s   25s · thunderbird      New message from Ada ab…   1",
+            "     2    1m     - ! battery          Battery low: 5%            0",
+            "     1    2h     - - backup           Backup finished            0",
+            "3 notification(s)",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn list_filters_by_app_sorts_by_urgency_and_shows_details() {
+        let options = ListOptions {
+            full: true,
+            app: None,
+            sort: SortKey::Urgency,
+        };
+        let rendered = render_list(fixture(), &options, 66);
+        let expected = [
+            "    ID   AGE  LEFT U APP              SUMMARY                  ACT",
+            "     2    1m     - ! battery          Battery low: 5%            0",
+            "     3    5s   25s · thunderbird      New message from Ada ab…   1",
+            "         | Hi,",
+            "         | see attached.",
+            "         action default = Open",
+            "         hint category=email.arrived",
+            "     1    2h     - - backup           Backup finished            0",
+            "3 notification(s)",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(rendered, expected);
+
+        let only_battery = ListOptions {
+            app: Some("Battery".to_string()),
+            ..ListOptions::default()
+        };
+        let rendered = render_list(fixture(), &only_battery, 60);
+        assert!(rendered.contains("Battery low"));
+        assert!(!rendered.contains("backup"));
+        assert!(rendered.ends_with("1 notification(s)\n"));
+    }
+
+    #[test]
+    fn truncation_is_grapheme_aware() {
+        assert_eq!(truncate("héllo wörld", 20), "héllo wörld");
+        assert_eq!(truncate("👩‍👩‍👧👍🏽abc", 3), "👩‍👩‍👧👍🏽…");
+        assert_eq!(pad("é", 3), "é  ");
+    }
+
+    #[test]
+    fn parse_list_options() {
+        assert_eq!(ListOptions::parse([]), Ok(ListOptions::default()));
+        assert_eq!(
+            ListOptions::parse(["--full", "--app", "mail", "--sort", "urgency"]),
+            Ok(ListOptions {
+                full: true,
+                app: Some("mail".to_string()),
+                sort: SortKey::Urgency,
+            })
+        );
+        assert!(ListOptions::parse(["--sort", "size"]).is_err());
+        assert!(ListOptions::parse(["--app"]).is_err());
+    }
+
+    #[test]
+    fn ages_use_the_largest_whole_unit() {
+        assert_eq!(format_age(Duration::from_secs(59)), "59s");
+        assert_eq!(format_age(Duration::from_secs(61)), "1m");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2d");
+    }
+}
@@ -0,0 +1,533 @@
+//! Round-trips actions: forwarded notifications keep their actions on the remote side, and
+//! actions invoked there are replayed on the local notification through wispd's control
+//! interface.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info, warn};
+use wisp_source::{CONTROL_INTERFACE, CONTROL_PATH, CommandError};
+use wisp_types::origin::{self, HASH_HINT, ORIGIN_HINT};
+
+use crate::{ForwardConfig, ForwardPayload, connect_session, sh_quote};
+
+/// Mappings kept before the oldest are forgotten; remote notifications rarely live longer.
+const MAX_MAPPED: usize = 512;
+
+/// Signal seen on the remote notification daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RemoteSignal {
+    ActionInvoked { remote_id: u32, action_key: String },
+    Closed { remote_id: u32, reason: u32 },
+}
+
+/// Joins the id the local daemon gave a notification with the id the remote daemon gave its
+/// forwarded copy. Either half may be learned first; `token` ties them together.
+#[derive(Debug, Default)]
+pub(crate) struct IdMap {
+    pending: HashMap<u64, (Option<u32>, Option<u32>)>,
+    pending_order: VecDeque<u64>,
+    remote_to_local: HashMap<u32, u32>,
+    mapped_order: VecDeque<u32>,
+}
+
+pub(crate) type SharedIdMap = Arc<Mutex<IdMap>>;
+
+impl IdMap {
+    pub(crate) fn set_local(&mut self, token: u64, local_id: u32) {
+        self.update(token, |half| half.0 = Some(local_id));
+    }
+
+    pub(crate) fn set_remote(&mut self, token: u64, remote_id: u32) {
+        self.update(token, |half| half.1 = Some(remote_id));
+    }
+
+    pub(crate) fn local_for(&self, remote_id: u32) -> Option<u32> {
+        self.remote_to_local.get(&remote_id).copied()
+    }
+
+    pub(crate) fn forget_remote(&mut self, remote_id: u32) {
+        self.remote_to_local.remove(&remote_id);
+    }
+
+    fn update(&mut self, token: u64, apply: impl FnOnce(&mut (Option<u32>, Option<u32>))) {
+        if !self.pending.contains_key(&token) {
+            if self.pending_order.len() == MAX_MAPPED
+                && let Some(oldest) = self.pending_order.pop_front()
+            {
+                self.pending.remove(&oldest);
+            }
+            self.pending_order.push_back(token);
+        }
+        let half = self.pending.entry(token).or_default();
+        apply(half);
+        if let (Some(local_id), Some(remote_id)) = *half {
+            self.pending.remove(&token);
+            self.pending_order.retain(|t| *t != token);
+            if self.mapped_order.len() == MAX_MAPPED
+                && let Some(oldest) = self.mapped_order.pop_front()
+            {
+                self.remote_to_local.remove(&oldest);
+            }
+            self.mapped_order.push_back(remote_id);
+            self.remote_to_local.insert(remote_id, local_id);
+        }
+    }
+}
+
+/// Forwarded `Notify` calls awaiting the local daemon's reply, keyed by caller and serial.
+#[derive(Debug, Default)]
+pub(crate) struct PendingCalls {
+    tokens: HashMap<(String, u32), u64>,
+    order: VecDeque<(String, u32)>,
+}
+
+impl PendingCalls {
+    pub(crate) fn insert(&mut self, sender: String, serial: u32, token: u64) {
+        if self.order.len() == MAX_MAPPED
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.tokens.remove(&oldest);
+        }
+        self.order.push_back((sender.clone(), serial));
+        self.tokens.insert((sender, serial), token);
+    }
+
+    /// Token of the call a method return with `reply_serial` sent to `destination` answers.
+    pub(crate) fn take(&mut self, destination: &str, reply_serial: u32) -> Option<u64> {
+        let key = (destination.to_string(), reply_serial);
+        let token = self.tokens.remove(&key)?;
+        self.order.retain(|k| *k != key);
+        Some(token)
+    }
+}
+
+/// `gdbus call` invocation of `Notify` on the remote daemon, keeping actions; prints the
+/// remote id as `(uint32 N,)`.
+pub(crate) fn build_remote_gdbus_command(gdbus: &str, payload: &ForwardPayload) -> String {
+    let actions = if payload.actions.is_empty() {
+        "@as []".to_string()
+    } else {
+        let items: Vec<String> = payload.actions.iter().map(|a| gvariant_str(a)).collect();
+        format!("[{}]", items.join(", "))
+    };
+    let urgency = match payload.urgency.as_str() {
+        "low" => 0,
+        "critical" => 2,
+        _ => 1,
+    };
+    [
+        sh_quote(gdbus),
+        "call --session --dest org.freedesktop.Notifications".to_string(),
+        "--object-path /org/freedesktop/Notifications".to_string(),
+        "--method org.freedesktop.Notifications.Notify".to_string(),
+        sh_quote(&gvariant_str(&payload.app_name)),
+        "0".to_string(),
+        "''".to_string(),
+        sh_quote(&gvariant_str(&payload.summary)),
+        sh_quote(&gvariant_str(&payload.body)),
+        sh_quote(&actions),
+        sh_quote(&format!(
+            "{{'urgency': <byte {urgency}>, '{ORIGIN_HINT}': <{}>, '{HASH_HINT}': <{}>}}",
+            gvariant_str(&payload.identity.origin),
+            gvariant_str(&origin::format_hash(payload.identity.hash)),
+        )),
+        // Typed so a negative timeout is not mistaken for an option.
+        sh_quote(&format!("int32 {}", payload.expire_timeout)),
+    ]
+    .join(" ")
+}
+
+/// GVariant text form of a string.
+fn gvariant_str(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Parses the `(uint32 N,)` reply printed by `gdbus call ... Notify`.
+pub(crate) fn parse_notify_reply(stdout: &str) -> Option<u32> {
+    stdout
+        .trim()
+        .strip_prefix("(uint32 ")?
+        .strip_suffix(",)")?
+        .parse()
+        .ok()
+}
+
+/// Parses one line of `gdbus monitor` output, e.g.
+/// `/org/freedesktop/Notifications: org.freedesktop.Notifications.ActionInvoked (uint32 7, 'default')`.
+pub(crate) fn parse_monitor_line(line: &str) -> Option<RemoteSignal> {
+    let (_, rest) = line.split_once(": org.freedesktop.Notifications.")?;
+    let (member, args) = rest.split_once(' ')?;
+    let args = args.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let (id, rest) = args.split_once(", ")?;
+    let remote_id = id.strip_prefix("uint32 ")?.parse().ok()?;
+    match member {
+        "ActionInvoked" => Some(RemoteSignal::ActionInvoked {
+            remote_id,
+            action_key: parse_gvariant_str(rest)?,
+        }),
+        "NotificationClosed" => Some(RemoteSignal::Closed {
+            remote_id,
+            reason: rest.strip_prefix("uint32 ")?.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Reverses GVariant string printing, which uses `"..."` when the text holds a `'`.
+fn parse_gvariant_str(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.push(chars.next()?);
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+/// Streams remote `ActionInvoked`/`NotificationClosed` signals over a dedicated SSH channel
+/// running `gdbus monitor`, reconnecting with a fixed delay.
+pub(crate) fn spawn_remote_monitor(cfg: ForwardConfig, signals: UnboundedSender<RemoteSignal>) {
+    thread::spawn(move || {
+        loop {
+            match monitor_remote(&cfg, &signals) {
+                Ok(()) => return,
+                Err(err) => warn!(?err, "remote signal monitor failed; retrying in 5s"),
+            }
+            thread::sleep(Duration::from_secs(5));
+        }
+    });
+}
+
+/// Returns `Ok` once the receiving side is gone.
+fn monitor_remote(cfg: &ForwardConfig, signals: &UnboundedSender<RemoteSignal>) -> Result<()> {
+    let session = connect_session(cfg, None)?;
+    let mut channel = session
+        .channel_session()
+        .context("failed to open ssh channel")?;
+    let cmd = format!(
+        "{} monitor --session --dest org.freedesktop.Notifications --object-path /org/freedesktop/Notifications",
+        sh_quote(&cfg.remote_gdbus)
+    );
+    channel
+        .exec(&cmd)
+        .with_context(|| format!("failed to exec remote command: {cmd}"))?;
+    info!("watching remote notification signals");
+
+    for line in BufReader::new(&mut channel).lines() {
+        let line = line.context("failed reading remote monitor output")?;
+        let Some(signal) = parse_monitor_line(&line) else {
+            debug!(%line, "ignoring remote monitor line");
+            continue;
+        };
+        if signals.send(signal).is_err() {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("remote gdbus monitor exited")
+}
+
+/// Applies a remote signal locally: invoked actions are replayed on the local notification
+/// through wispd's control interface at `local_dest`.
+pub(crate) async fn relay_remote_signal(
+    ids: &SharedIdMap,
+    conn: &zbus::Connection,
+    local_dest: &str,
+    signal: RemoteSignal,
+) -> Result<bool> {
+    match signal {
+        RemoteSignal::ActionInvoked {
+            remote_id,
+            action_key,
+        } => {
+            let Some(local_id) = ids.lock().expect("id map poisoned").local_for(remote_id) else {
+                debug!(remote_id, "remote action for an unmapped notification");
+                return Ok(false);
+            };
+            let reply = match conn
+                .call_method(
+                    Some(local_dest),
+                    CONTROL_PATH,
+                    Some(CONTROL_INTERFACE),
+                    "InvokeAction",
+                    &(local_id, action_key.as_str()),
+                )
+                .await
+            {
+                Ok(reply) => reply,
+                Err(err) => {
+                    return match CommandError::from_dbus_error(&err) {
+                        // The local popup closed or changed since it was forwarded.
+                        Some(err @ (CommandError::NotFound | CommandError::UnknownAction)) => {
+                            debug!(remote_id, local_id, action_key = %action_key, %err, "remote action no longer applies locally");
+                            Ok(false)
+                        }
+                        Some(err) => Err(err).context("local wispd failed to replay the action"),
+                        None => Err(err).with_context(|| {
+                            format!(
+                                "{local_dest} does not expose {CONTROL_INTERFACE} (is wispd running?)"
+                            )
+                        }),
+                    };
+                }
+            };
+            let invoked: bool = reply.body().deserialize()?;
+            info!(remote_id, local_id, action_key = %action_key, invoked, "replayed remote action locally");
+            Ok(invoked)
+        }
+        RemoteSignal::Closed { remote_id, reason } => {
+            debug!(remote_id, reason, "remote notification closed");
+            ids.lock()
+                .expect("id map poisoned")
+                .forget_remote(remote_id);
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use futures_util::StreamExt;
+    use wisp_source::{DBUS_INTERFACE, SourceConfig, WispSource};
+    use wisp_types::{Notification, NotificationAction, NotificationEvent};
+
+    use super::*;
+    use crate::dedup::Identity;
+
+    fn payload(actions: &[&str]) -> ForwardPayload {
+        ForwardPayload {
+            app_name: "Signal".to_string(),
+            summary: "Ada's message".to_string(),
+            body: "see you".to_string(),
+            expire_timeout: 5000,
+            urgency: "critical".to_string(),
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+            token: None,
+            identity: Identity {
+                origin: "machine-a".to_string(),
+                hash: 0xabc,
+                forwarded: false,
+            },
+        }
+    }
+
+    #[test]
+    fn id_map_joins_halves_in_either_order() {
+        let mut ids = IdMap::default();
+        ids.set_local(1, 10);
+        assert_eq!(ids.local_for(100), None);
+        ids.set_remote(1, 100);
+        assert_eq!(ids.local_for(100), Some(10));
+
+        ids.set_remote(2, 200);
+        ids.set_local(2, 20);
+        assert_eq!(ids.local_for(200), Some(20));
+
+        ids.forget_remote(100);
+        assert_eq!(ids.local_for(100), None);
+    }
+
+    #[test]
+    fn id_map_is_bounded() {
+        let mut ids = IdMap::default();
+        for n in 0..(MAX_MAPPED as u32 + 5) {
+            ids.set_local(u64::from(n), n);
+            ids.set_remote(u64::from(n), n + 10_000);
+        }
+        assert_eq!(ids.local_for(10_000), None, "oldest mapping evicted");
+        assert_eq!(
+            ids.local_for(MAX_MAPPED as u32 + 4 + 10_000),
+            Some(MAX_MAPPED as u32 + 4)
+        );
+        for token in 0..(MAX_MAPPED as u64 + 5) {
+            ids.set_local(token + 1_000_000, 1);
+        }
+        assert_eq!(ids.pending.len(), MAX_MAPPED);
+    }
+
+    #[test]
+    fn pending_calls_match_replies_by_caller_and_serial() {
+        let mut pending = PendingCalls::default();
+        pending.insert(":1.5".to_string(), 9, 1);
+        pending.insert(":1.6".to_string(), 9, 2);
+        assert_eq!(pending.take(":1.5", 10), None);
+        assert_eq!(pending.take(":1.6", 9), Some(2));
+        assert_eq!(pending.take(":1.6", 9), None);
+
+        for serial in 0..(MAX_MAPPED as u32) {
+            pending.insert(":1.7".to_string(), serial, 3);
+        }
+        assert_eq!(pending.take(":1.5", 9), None, "oldest call evicted");
+        assert_eq!(pending.tokens.len(), MAX_MAPPED);
+    }
+
+    #[test]
+    fn gdbus_command_keeps_actions_and_escapes_text() {
+        let cmd = build_remote_gdbus_command("gdbus", &payload(&["reply", "Reply"]));
+        assert!(cmd.starts_with(
+            "'gdbus' call --session --dest org.freedesktop.Notifications \
+             --object-path /org/freedesktop/Notifications \
+             --method org.freedesktop.Notifications.Notify "
+        ));
+        assert!(cmd.contains(&sh_quote("'Ada\\'s message'")));
+        assert!(cmd.contains(&sh_quote("['reply', 'Reply']")));
+        assert!(cmd.contains(&sh_quote(
+            "{'urgency': <byte 2>, 'x-wispd-origin': <'machine-a'>, \
+             'x-wispd-hash': <'0000000000000abc'>}"
+        )));
+        assert!(cmd.ends_with(&sh_quote("int32 5000")));
+        assert!(build_remote_gdbus_command("gdbus", &payload(&[])).contains("'@as []'"));
+    }
+
+    #[test]
+    fn notify_reply_and_monitor_lines_parse() {
+        assert_eq!(parse_notify_reply("(uint32 42,)\n"), Some(42));
+        assert_eq!(parse_notify_reply("Error: GDBus.Error"), None);
+
+        assert_eq!(
+            parse_monitor_line(
+                "/org/freedesktop/Notifications: org.freedesktop.Notifications.ActionInvoked (uint32 7, 'default')"
+            ),
+            Some(RemoteSignal::ActionInvoked {
+                remote_id: 7,
+                action_key: "default".to_string()
+            })
+        );
+        assert_eq!(
+            parse_monitor_line(
+                "/org/freedesktop/Notifications: org.freedesktop.Notifications.ActionInvoked (uint32 8, \"it's\")"
+            ),
+            Some(RemoteSignal::ActionInvoked {
+                remote_id: 8,
+                action_key: "it's".to_string()
+            })
+        );
+        assert_eq!(
+            parse_monitor_line(
+                "/org/freedesktop/Notifications: org.freedesktop.Notifications.NotificationClosed (uint32 7, uint32 2)"
+            ),
+            Some(RemoteSignal::Closed {
+                remote_id: 7,
+                reason: 2
+            })
+        );
+        assert_eq!(
+            parse_monitor_line("The name org.freedesktop.Notifications is owned by :1.5"),
+            None
+        );
+    }
+
+    async fn start_source(
+        role: &str,
+    ) -> Option<(
+        SourceConfig,
+        WispSource,
+        tokio::sync::mpsc::Receiver<NotificationEvent>,
+        wisp_source::DbusService,
+    )> {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let cfg = SourceConfig::builder()
+            .dbus_name(format!("org.wispd.Forward{role}.{unique}"))
+            .build();
+        let (source, rx, service) = WispSource::start_dbus(cfg.clone()).await.ok()?;
+        Some((cfg, source, rx, service))
+    }
+
+    fn with_reply_action(summary: &str) -> Notification {
+        Notification {
+            app_name: "chat".to_string(),
+            summary: summary.to_string(),
+            timeout_ms: -1,
+            actions: vec![NotificationAction {
+                key: "reply".to_string(),
+                label: "Reply".to_string(),
+            }],
+            ..Notification::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_action_is_replayed_on_the_local_notification() {
+        let (
+            Some((remote_cfg, remote, _remote_rx, _remote_service)),
+            Some((local_cfg, local, mut local_rx, _local_service)),
+        ) = (start_source("Remote").await, start_source("Local").await)
+        else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+        let Ok(client) = zbus::Connection::session().await else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+
+        let local_id = local.notify(with_reply_action("local"), 0).await.unwrap();
+        let _ = local_rx.recv().await;
+        let remote_id = remote
+            .notify(with_reply_action("forwarded"), 0)
+            .await
+            .unwrap();
+        let ids: SharedIdMap = Arc::default();
+        ids.lock().unwrap().set_remote(1, remote_id);
+        ids.lock().unwrap().set_local(1, local_id);
+
+        // Stands in for `gdbus monitor` on the remote machine.
+        let remote_proxy = zbus::Proxy::new(
+            &client,
+            remote_cfg.dbus_name.as_str(),
+            remote_cfg.dbus_path.as_str(),
+            DBUS_INTERFACE,
+        )
+        .await
+        .unwrap();
+        let mut remote_actions = remote_proxy.receive_signal("ActionInvoked").await.unwrap();
+        remote.invoke_action(remote_id, "reply").await.unwrap();
+        let signal = tokio::time::timeout(Duration::from_secs(2), remote_actions.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let (signal_id, action_key): (u32, String) = signal.body().deserialize().unwrap();
+
+        let invoked = relay_remote_signal(
+            &ids,
+            &client,
+            local_cfg.dbus_name.as_str(),
+            RemoteSignal::ActionInvoked {
+                remote_id: signal_id,
+                action_key,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(invoked);
+
+        let event = tokio::time::timeout(Duration::from_secs(2), local_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            NotificationEvent::ActionInvoked {
+                id: local_id,
+                action_key: "reply".to_string()
+            }
+        );
+    }
+}
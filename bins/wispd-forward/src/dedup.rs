@@ -0,0 +1,305 @@
+//! Loop and duplicate suppression for forwarding in both directions between machines.
+//!
+//! Every forwarded payload carries the instance it first appeared on and a content hash,
+//! as the `x-wispd-origin` and `x-wispd-hash` hints of the remote notification. A
+//! forwarder seeing such a notification on its own bus keeps both, so the identity
+//! survives any number of hops and templates. Before queueing, [`LoopGuard`] drops
+//! payloads that came back to their origin, would go straight back to it, or repeat
+//! content already forwarded within the dedup window.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    time::{Duration, Instant},
+};
+
+use wisp_types::origin::{self, HASH_HINT, ORIGIN_HINT};
+use zbus::zvariant;
+
+/// Prints the target's machine id; read once per forwarder run.
+pub(crate) const REMOTE_MACHINE_ID_COMMAND: &str =
+    "cat /etc/machine-id 2>/dev/null || cat /var/lib/dbus/machine-id";
+
+/// Where a payload first appeared and what it said.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Identity {
+    pub(crate) origin: String,
+    pub(crate) hash: u64,
+    /// Read from the hints of a notification another forwarder delivered.
+    pub(crate) forwarded: bool,
+}
+
+impl Identity {
+    /// Identity for content first seen on this machine.
+    pub(crate) fn local(
+        local_origin: &str,
+        app_name: &str,
+        summary: &str,
+        body: &str,
+        urgency: &str,
+    ) -> Self {
+        Self {
+            origin: local_origin.to_string(),
+            hash: origin::content_hash(app_name, summary, body, urgency),
+            forwarded: false,
+        }
+    }
+
+    /// The identity a monitored `Notify` carries, or a local one when it has none. A
+    /// missing or unreadable hash is computed from the call.
+    pub(crate) fn of_call(
+        hints: &HashMap<String, zvariant::OwnedValue>,
+        local_origin: &str,
+        app_name: &str,
+        summary: &str,
+        body: &str,
+        urgency: &str,
+    ) -> Self {
+        let hint = |key| hints.get(key).and_then(|raw| <&str>::try_from(raw).ok());
+        let local = Self::local(local_origin, app_name, summary, body, urgency);
+        match hint(ORIGIN_HINT).filter(|origin| !origin.is_empty()) {
+            Some(origin) => Self {
+                origin: origin.to_string(),
+                hash: hint(HASH_HINT)
+                    .and_then(origin::parse_hash)
+                    .unwrap_or(local.hash),
+                forwarded: true,
+            },
+            None => local,
+        }
+    }
+}
+
+/// Why a payload is or is not forwarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Forward,
+    /// Forwarded here from elsewhere, but it started on this machine.
+    Looped,
+    /// It started on the forward target.
+    Bounce,
+    /// The same content was forwarded within the window.
+    Duplicate,
+}
+
+impl Verdict {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Looped => "looped",
+            Self::Bounce => "bounce",
+            Self::Duplicate => "duplicate",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct LoopGuard {
+    local_origin: String,
+    /// How long a forwarded hash suppresses the same content; zero turns that off.
+    window: Duration,
+    /// Hashes forwarded within `window`, oldest first.
+    recent: VecDeque<(Instant, u64)>,
+}
+
+impl LoopGuard {
+    pub(crate) fn new(local_origin: String, window: Duration) -> Self {
+        Self {
+            local_origin,
+            window,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn local_origin(&self) -> &str {
+        &self.local_origin
+    }
+
+    /// Decides on `identity` at `now`; `target` is the forward target's origin, once
+    /// known. Forwarded hashes are remembered for the window.
+    pub(crate) fn check(
+        &mut self,
+        identity: &Identity,
+        target: Option<&str>,
+        now: Instant,
+    ) -> Verdict {
+        while self
+            .recent
+            .front()
+            .is_some_and(|(seen, _)| now.saturating_duration_since(*seen) >= self.window)
+        {
+            self.recent.pop_front();
+        }
+        if identity.forwarded && identity.origin == self.local_origin {
+            return Verdict::Looped;
+        }
+        if target == Some(identity.origin.as_str()) {
+            return Verdict::Bounce;
+        }
+        if self.recent.iter().any(|(_, hash)| *hash == identity.hash) {
+            return Verdict::Duplicate;
+        }
+        if !self.window.is_zero() {
+            self.recent.push_back((now, identity.hash));
+        }
+        Verdict::Forward
+    }
+}
+
+/// This machine's id from `/etc/machine-id` (or D-Bus's copy of it).
+pub(crate) fn local_machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .into_iter()
+        .find_map(|path| parse_machine_id(&fs::read_to_string(path).ok()?))
+}
+
+pub(crate) fn parse_machine_id(raw: &str) -> Option<String> {
+    let id = raw.trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    /// One end of a bidirectional setup: the forwarder's guard plus what the daemon on
+    /// that machine presented.
+    struct Machine {
+        guard: LoopGuard,
+        presented: Vec<String>,
+    }
+
+    impl Machine {
+        fn new(id: &str) -> Self {
+            Self {
+                guard: LoopGuard::new(id.to_string(), WINDOW),
+                presented: Vec::new(),
+            }
+        }
+
+        /// A notification shows up on this machine's bus (natively without hints,
+        /// forwarded with them); returns the hints it is forwarded with, if it is.
+        fn observe(
+            &mut self,
+            summary: &str,
+            hints: &HashMap<String, zvariant::OwnedValue>,
+            target: Option<&str>,
+            now: Instant,
+        ) -> Option<HashMap<String, zvariant::OwnedValue>> {
+            self.presented.push(summary.to_string());
+            let identity = Identity::of_call(
+                hints,
+                self.guard.local_origin(),
+                "mail",
+                summary,
+                "",
+                "normal",
+            );
+            (self.guard.check(&identity, target, now) == Verdict::Forward)
+                .then(|| hints_of(&identity))
+        }
+    }
+
+    fn hints_of(identity: &Identity) -> HashMap<String, zvariant::OwnedValue> {
+        HashMap::from([
+            (
+                ORIGIN_HINT.to_string(),
+                zvariant::OwnedValue::from(zvariant::Str::from(identity.origin.clone())),
+            ),
+            (
+                HASH_HINT.to_string(),
+                zvariant::OwnedValue::from(zvariant::Str::from(origin::format_hash(identity.hash))),
+            ),
+        ])
+    }
+
+    #[test]
+    fn a_two_machine_loop_presents_each_notification_once() {
+        let now = Instant::now();
+        let (mut a, mut b) = (Machine::new("machine-a"), Machine::new("machine-b"));
+
+        // Each side knows its target, so neither sends the other's notification back.
+        let hints = a.observe("from a", &HashMap::new(), Some("machine-b"), now);
+        let hints = hints.expect("native notifications are forwarded");
+        assert!(
+            b.observe("from a", &hints, Some("machine-a"), now)
+                .is_none()
+        );
+        let hints = b.observe("from b", &HashMap::new(), Some("machine-a"), now);
+        assert!(
+            a.observe("from b", &hints.unwrap(), Some("machine-b"), now)
+                .is_none()
+        );
+
+        assert_eq!(a.presented, ["from a", "from b"]);
+        assert_eq!(b.presented, ["from a", "from b"]);
+    }
+
+    #[test]
+    fn without_a_known_target_the_origin_stops_the_loop_after_one_round_trip() {
+        let now = Instant::now();
+        let (mut a, mut b) = (Machine::new("machine-a"), Machine::new("machine-b"));
+
+        let hints = b.observe("from b", &HashMap::new(), None, now).unwrap();
+        let back = a.observe("from b", &hints, None, now).unwrap();
+        assert!(b.observe("from b", &back, None, now).is_none());
+        assert_eq!(a.presented, ["from b"]);
+        assert_eq!(b.presented.len(), 2);
+    }
+
+    #[test]
+    fn content_forwarded_within_the_window_is_dropped() {
+        let start = Instant::now();
+        let mut guard = LoopGuard::new("machine-b".to_string(), WINDOW);
+        let native = Identity::local("machine-b", "mail", "Ada", "see you", "normal");
+        let forwarded = Identity {
+            origin: "machine-a".to_string(),
+            forwarded: true,
+            ..native.clone()
+        };
+
+        // The same message arrives natively and forwarded from A.
+        assert_eq!(guard.check(&native, None, start), Verdict::Forward);
+        assert_eq!(
+            guard.check(&forwarded, None, start + Duration::from_secs(1)),
+            Verdict::Duplicate
+        );
+        assert_eq!(guard.check(&native, None, start + WINDOW), Verdict::Forward);
+
+        let mut unwindowed = LoopGuard::new("machine-b".to_string(), Duration::ZERO);
+        assert_eq!(unwindowed.check(&native, None, start), Verdict::Forward);
+        assert_eq!(unwindowed.check(&native, None, start), Verdict::Forward);
+    }
+
+    #[test]
+    fn identity_is_read_from_hints_or_made_locally() {
+        let local = Identity::local("machine-b", "mail", "Ada", "", "critical");
+        assert_eq!(
+            Identity::of_call(&HashMap::new(), "machine-b", "mail", "Ada", "", "critical"),
+            local
+        );
+
+        let carried = Identity {
+            origin: "machine-a".to_string(),
+            hash: 42,
+            forwarded: true,
+        };
+        // Templates on the way may change the text; the carried hash still wins.
+        assert_eq!(
+            Identity::of_call(
+                &hints_of(&carried),
+                "machine-b",
+                "mail",
+                "[a] Ada",
+                "",
+                "critical"
+            ),
+            carried
+        );
+
+        assert_eq!(parse_machine_id("0123abcd\n"), Some("0123abcd".to_string()));
+        assert_eq!(parse_machine_id(" \n"), None);
+    }
+}
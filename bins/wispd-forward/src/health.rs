@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+use crate::stats::{ForwardStats, StatsSnapshot};
+
+/// Serves `/healthz` and `/metrics` until the listener fails.
+pub(crate) async fn serve(listener: TcpListener, stats: Arc<ForwardStats>, target: String) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!(%peer, "health request");
+                let stats = stats.clone();
+                let target = target.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle(stream, &stats, &target).await {
+                        debug!(%err, "health request failed");
+                    }
+                });
+            }
+            Err(err) => {
+                warn!(%err, "health listener stopped");
+                return;
+            }
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, stats: &ForwardStats, target: &str) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..read]);
+    let path = request.lines().next().and_then(|line| {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["GET", path, ..] => Some(path),
+            _ => None,
+        }
+    });
+
+    let response = respond(path, &stats.snapshot(), target);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Full HTTP/1.1 response for a `GET` of `path` (`None` for anything else).
+fn respond(path: Option<&str>, snapshot: &StatsSnapshot, target: &str) -> String {
+    let (status, content_type, body) = match path {
+        Some("/healthz") if snapshot.is_healthy() => (
+            "200 OK",
+            "text/plain",
+            format!("ok {}\n", snapshot.connection.label()),
+        ),
+        Some("/healthz") => (
+            "503 Service Unavailable",
+            "text/plain",
+            format!(
+                "unhealthy {} queued={}\n",
+                snapshot.connection.label(),
+                snapshot.queued
+            ),
+        ),
+        Some("/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            snapshot.prometheus(target),
+        ),
+        Some(_) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        None => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::ConnectionState;
+
+    #[test]
+    fn healthz_reflects_target_state() {
+        let stats = ForwardStats::default();
+        assert!(respond(Some("/healthz"), &stats.snapshot(), "t").starts_with("HTTP/1.1 200 OK"));
+
+        stats.set_connection(ConnectionState::Disconnected);
+        let response = respond(Some("/healthz"), &stats.snapshot(), "t");
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.ends_with("unhealthy disconnected queued=0\n"));
+    }
+
+    #[test]
+    fn metrics_and_unknown_paths() {
+        let stats = ForwardStats::default();
+        let response = respond(Some("/metrics"), &stats.snapshot(), "t");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("wispd_forward_forwarded_total 0\n"));
+
+        let body = stats.snapshot().prometheus("t");
+        assert!(response.contains(&format!("Content-Length: {}\r\n", body.len())));
+
+        assert!(respond(Some("/"), &stats.snapshot(), "t").starts_with("HTTP/1.1 404"));
+        assert!(respond(None, &stats.snapshot(), "t").starts_with("HTTP/1.1 405"));
+    }
+}
@@ -1,8 +1,8 @@
 use std::{
     env,
     io::Read,
-    net::{TcpStream, ToSocketAddrs},
-    sync::mpsc,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Arc, OnceLock, mpsc},
     time::{Duration, Instant},
 };
 
@@ -13,9 +13,37 @@ use tokio::{net, signal, time};
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 use wisp_monitor::{
-    NotificationMessage, become_monitor, parse_notification_message, rules_notify_only,
+    NotificationMessage, become_monitor, method_return_rule, notification_rule,
+    parse_notification_message, rules_notify_only,
 };
-use zbus::MessageStream;
+use wisp_source::{CONTROL_INTERFACE, DEFAULT_DBUS_NAME};
+use wisp_types::{
+    loggable::{self, Loggable},
+    origin::{self, HASH_HINT, ORIGIN_HINT},
+    privacy::PrivacyRule,
+    systemd::{self as sd, Notifier},
+};
+use zbus::{MessageStream, fdo::DBusProxy, message::Type as MessageType, names::BusName};
+
+mod actions;
+mod dedup;
+mod health;
+mod settle;
+mod stats;
+mod systemd;
+mod transform;
+
+use actions::{PendingCalls, SharedIdMap};
+use dedup::{Identity, LoopGuard, Verdict};
+use settle::{FinalHold, ForwardMode, HoldKeys};
+use stats::{ConnectionState, ForwardStats};
+use systemd::{Readiness, StreamProgress};
+use transform::PayloadTransform;
+
+/// Default for `WISPD_FORWARD_DEDUP_SECS`.
+const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(30);
+/// Default for `WISPD_FORWARD_QUIET_MS`.
+const DEFAULT_QUIET_PERIOD: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Clone)]
 struct ForwardConfig {
@@ -26,6 +54,21 @@ struct ForwardConfig {
     remote_notify_send: String,
     startup_wait_secs: u64,
     startup_poll_interval_ms: u64,
+    transform: PayloadTransform,
+    summary_interval_secs: u64,
+    health_addr: Option<SocketAddr>,
+    /// Forward actions via `gdbus` and replay remote invocations locally.
+    forward_actions: bool,
+    remote_gdbus: String,
+    /// Re-send critical notifications the local daemon escalates.
+    forward_escalations: bool,
+    /// This instance in the origin of forwarded payloads; the machine id by default.
+    origin: String,
+    /// How long forwarded content suppresses the same content; zero disables that.
+    dedup_window: Duration,
+    forward_mode: ForwardMode,
+    /// In `final` mode, how long a notification goes without updates before it is sent.
+    quiet_period: Duration,
 }
 
 impl ForwardConfig {
@@ -60,6 +103,63 @@ impl ForwardConfig {
             .context("WISPD_FORWARD_SSH_STARTUP_POLL_MS must be a valid u64")?
             .unwrap_or(500);
 
+        let summary_interval_secs = env::var("WISPD_FORWARD_SUMMARY_INTERVAL_SECS")
+            .ok()
+            .map(|s| s.parse::<u64>())
+            .transpose()
+            .context("WISPD_FORWARD_SUMMARY_INTERVAL_SECS must be a valid u64")?
+            .unwrap_or(300);
+
+        let health_addr = non_empty_var("WISPD_FORWARD_HEALTH_ADDR")
+            .map(|s| s.parse::<SocketAddr>())
+            .transpose()
+            .context("WISPD_FORWARD_HEALTH_ADDR must be a socket address")?;
+
+        let forward_actions = env::var("WISPD_FORWARD_ACTIONS")
+            .is_ok_and(|value| matches!(value.as_str(), "1" | "true"));
+        let remote_gdbus = env::var("WISPD_FORWARD_GDBUS").unwrap_or_else(|_| "gdbus".to_string());
+        let forward_escalations = env::var("WISPD_FORWARD_ESCALATIONS")
+            .is_ok_and(|value| matches!(value.as_str(), "1" | "true"));
+
+        let origin = match non_empty_var("WISPD_FORWARD_ORIGIN").or_else(dedup::local_machine_id) {
+            Some(origin) => origin,
+            None => {
+                let fallback = format!("pid-{}", std::process::id());
+                warn!(%fallback, "no machine id; set WISPD_FORWARD_ORIGIN to name this instance");
+                fallback
+            }
+        };
+        let dedup_window = env::var("WISPD_FORWARD_DEDUP_SECS")
+            .ok()
+            .map(|s| s.parse::<u64>())
+            .transpose()
+            .context("WISPD_FORWARD_DEDUP_SECS must be a valid u64")?
+            .map_or(DEFAULT_DEDUP_WINDOW, Duration::from_secs);
+        let forward_mode = non_empty_var("WISPD_FORWARD_MODE")
+            .map(|mode| mode.parse::<ForwardMode>())
+            .transpose()
+            .map_err(|err| anyhow::anyhow!("WISPD_FORWARD_MODE: {err}"))?
+            .unwrap_or_default();
+        let quiet_period = env::var("WISPD_FORWARD_QUIET_MS")
+            .ok()
+            .map(|s| s.parse::<u64>())
+            .transpose()
+            .context("WISPD_FORWARD_QUIET_MS must be a valid u64")?
+            .map_or(DEFAULT_QUIET_PERIOD, Duration::from_millis);
+
+        let privacy_apps = non_empty_var("WISPD_FORWARD_PRIVACY_APPS")
+            .map(|list| parse_privacy_apps(&list))
+            .transpose()?
+            .unwrap_or_default();
+        let defaults = PayloadTransform::default();
+        let transform = PayloadTransform {
+            summary_template: non_empty_var("WISPD_FORWARD_SUMMARY_TEMPLATE"),
+            body_template: non_empty_var("WISPD_FORWARD_BODY_TEMPLATE"),
+            privacy_apps,
+            redaction_placeholder: non_empty_var("WISPD_FORWARD_REDACTION_PLACEHOLDER")
+                .unwrap_or(defaults.redaction_placeholder),
+        };
+
         Ok(Self {
             ssh_host,
             ssh_port,
@@ -68,8 +168,61 @@ impl ForwardConfig {
             remote_notify_send,
             startup_wait_secs,
             startup_poll_interval_ms,
+            transform,
+            summary_interval_secs,
+            health_addr,
+            forward_actions,
+            remote_gdbus,
+            forward_escalations,
+            origin,
+            dedup_window,
+            forward_mode,
+            quiet_period,
         })
     }
+
+    /// Applies command-line flags, which win over the environment.
+    fn apply_args<I>(&mut self, args: I) -> Result<()>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--health-addr" => {
+                    let value = args.next().context("missing value for --health-addr")?;
+                    self.health_addr = Some(
+                        value
+                            .parse()
+                            .context("--health-addr must be a socket address")?,
+                    );
+                }
+                other => anyhow::bail!(
+                    "unknown argument: {other} (expected --health-addr <addr> or --print-systemd-unit)"
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn target_label(&self) -> String {
+        format!("{}@{}:{}", self.ssh_user, self.ssh_host, self.ssh_port)
+    }
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Comma-separated rules in the compact `pattern[:body|:all]` form.
+fn parse_privacy_apps(list: &str) -> Result<Vec<PrivacyRule>> {
+    list.split(',')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(|rule| {
+            rule.parse()
+                .map_err(|err| anyhow::anyhow!("WISPD_FORWARD_PRIVACY_APPS: {rule:?}: {err}"))
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -79,36 +232,152 @@ struct ForwardPayload {
     body: String,
     expire_timeout: i32,
     urgency: String,
+    /// Flat key/label pairs, as sent on the bus.
+    actions: Vec<String>,
+    /// Ties the local reply to the remote one when actions are forwarded.
+    token: Option<u64>,
+    /// Sent along as the origin and hash hints.
+    identity: Identity,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--print-systemd-unit") {
+        let exe = env::current_exe().context("failed to resolve the wispd-forward binary path")?;
+        print!("{}", systemd::render_unit(&exe));
+        return Ok(());
+    }
+
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env().add_directive("wispd_forward=info".parse()?))
         .init();
+    loggable::set_log_content(
+        !env::var("WISPD_FORWARD_LOG_CONTENT")
+            .is_ok_and(|value| matches!(value.as_str(), "0" | "false")),
+    );
 
-    let cfg = ForwardConfig::from_env()?;
+    let mut cfg = ForwardConfig::from_env()?;
+    cfg.apply_args(args)?;
     info!(
         ssh_host = %cfg.ssh_host,
         ssh_port = cfg.ssh_port,
         ssh_user = %cfg.ssh_user,
         startup_wait_secs = cfg.startup_wait_secs,
+        summary_interval_secs = cfg.summary_interval_secs,
+        health_addr = ?cfg.health_addr,
+        forward_actions = cfg.forward_actions,
+        forward_escalations = cfg.forward_escalations,
+        origin = %cfg.origin,
+        dedup_window_secs = cfg.dedup_window.as_secs(),
+        forward_mode = ?cfg.forward_mode,
+        quiet_period_ms = cfg.quiet_period.as_millis(),
         "starting notification forwarder"
     );
 
+    let stats = Arc::new(ForwardStats::default());
+    if let Some(addr) = cfg.health_addr {
+        let listener = net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to bind health listener on {addr}"))?;
+        info!(%addr, "serving /healthz and /metrics");
+        tokio::spawn(health::serve(listener, stats.clone(), cfg.target_label()));
+    }
+
+    let notifier = Notifier::from_env();
+    let mut readiness = Readiness::default();
+    systemd::warn_on_failure(notifier.status(&format!("waiting for {}", cfg.target_label())));
     wait_for_ssh_startup(&cfg).await?;
+    // The target is the one SSH endpoint; reachable counts as initialized.
+    if readiness.target_ready() {
+        systemd::warn_on_failure(notifier.ready());
+    }
 
+    let ids = SharedIdMap::default();
+    let target_origin = Arc::new(OnceLock::new());
     let (tx, rx) = mpsc::channel::<ForwardPayload>();
     let worker_cfg = cfg.clone();
-    let worker = std::thread::spawn(move || run_forward_worker(worker_cfg, rx));
+    let worker_stats = stats.clone();
+    let worker_ids = ids.clone();
+    let worker_target = target_origin.clone();
+    let worker = std::thread::spawn(move || {
+        run_forward_worker(worker_cfg, rx, &worker_stats, &worker_ids, &worker_target)
+    });
+
+    let final_mode = cfg.forward_mode == ForwardMode::Final;
+    let mut rules = rules_notify_only();
+    if final_mode {
+        rules.push(notification_rule(
+            Some(MessageType::Signal),
+            Some("NotificationClosed"),
+            None,
+        )?);
+    }
+    // Monitoring connections cannot call methods, so lookups and replays use their own one.
+    let control_conn = if cfg.forward_actions || final_mode {
+        let conn = zbus::Connection::session().await?;
+        match local_daemon_owner(&conn).await {
+            Some(owner) => rules.push(method_return_rule(&owner)?),
+            None => warn!(
+                "no local notification daemon; local ids stay unknown until restart, so remote \
+                 actions are not replayed and final mode only joins replacements by stack tag"
+            ),
+        }
+        Some(conn)
+    } else {
+        None
+    };
+    if cfg.forward_actions
+        && let Some(control_conn) = control_conn
+    {
+        let (signal_tx, mut signal_rx) = tokio::sync::mpsc::unbounded_channel();
+        actions::spawn_remote_monitor(cfg.clone(), signal_tx);
+        let relay_ids = ids.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = signal_rx.recv().await {
+                if let Err(err) = actions::relay_remote_signal(
+                    &relay_ids,
+                    &control_conn,
+                    DEFAULT_DBUS_NAME,
+                    signal,
+                )
+                .await
+                {
+                    warn!(?err, "failed to replay remote action locally");
+                }
+            }
+        });
+    }
+
+    if cfg.forward_escalations {
+        rules.push(format!(
+            "type='signal',interface='{CONTROL_INTERFACE}',member='Escalated'"
+        ));
+    }
 
     let conn = zbus::Connection::session().await?;
-    become_monitor(&conn, rules_notify_only()).await?;
+    become_monitor(&conn, rules).await?;
 
     info!("attached to session bus; forwarding Notify calls to VM");
+    if readiness.monitor_attached() {
+        systemd::warn_on_failure(notifier.ready());
+    }
+    systemd::warn_on_failure(notifier.status(&format!("forwarding to {}", cfg.target_label())));
 
     let mut stream = MessageStream::from(&conn);
     let mut shutdown = Box::pin(signal::ctrl_c());
+    // An interval of zero disables the summary; the branch below then never fires.
+    let summary_period = Duration::from_secs(cfg.summary_interval_secs.max(1));
+    let mut summary = time::interval_at(time::Instant::now() + summary_period, summary_period);
+    let mut last_summary = stats.snapshot();
+    let mut pending = PendingCalls::default();
+    let mut next_token = 0u64;
+    let mut guard = LoopGuard::new(cfg.origin.clone(), cfg.dedup_window);
+    let mut hold = final_mode.then(|| FinalHold::new(cfg.quiet_period));
+    let mut settle = time::interval(cfg.quiet_period.max(Duration::from_millis(100)) / 4);
+    let watchdog = sd::watchdog_interval();
+    let mut watchdog_ping = time::interval(watchdog.map_or(Duration::from_secs(3600), |w| w / 2));
+    let mut progress = StreamProgress::default();
 
     loop {
         tokio::select! {
@@ -116,6 +385,27 @@ async fn main() -> Result<()> {
                 info!("received Ctrl+C; exiting");
                 break;
             }
+            _ = summary.tick(), if cfg.summary_interval_secs > 0 => {
+                let current = stats.snapshot();
+                let line = current.summary_line(&last_summary);
+                info!(target_addr = %cfg.target_label(), "{line}");
+                systemd::warn_on_failure(notifier.status(&line));
+                last_summary = current;
+            }
+            _ = watchdog_ping.tick(), if watchdog.is_some() => {
+                let interval = watchdog.unwrap_or_default();
+                if progress.should_ping(Instant::now(), interval) {
+                    systemd::warn_on_failure(notifier.watchdog());
+                } else {
+                    warn!("monitor stream keeps failing; withholding the watchdog ping");
+                }
+            }
+            _ = settle.tick(), if hold.is_some() => {
+                let settled = hold.as_mut().map(|hold| hold.settled(Instant::now())).unwrap_or_default();
+                if !release(settled, &tx, &mut guard, &target_origin, &stats) {
+                    break;
+                }
+            }
             maybe_msg = stream.next() => {
                 let Some(msg) = maybe_msg else {
                     warn!("dbus stream ended");
@@ -123,18 +413,58 @@ async fn main() -> Result<()> {
                 };
 
                 let Ok(msg) = msg else {
+                    progress.on_error(Instant::now());
                     warn!(error = %msg.unwrap_err(), "failed to decode dbus message");
                     continue;
                 };
+                progress.on_message();
+
+                if msg.message_type() == MessageType::MethodReturn {
+                    let header = msg.header();
+                    let (Some(dest), Some(serial)) = (header.destination(), header.reply_serial()) else {
+                        continue;
+                    };
+                    let Ok(local_id) = msg.body().deserialize::<u32>() else {
+                        continue;
+                    };
+                    if let Some(token) = pending.take(dest.as_str(), serial.get()) {
+                        ids.lock().expect("id map poisoned").set_local(token, local_id);
+                    }
+                    if let Some(hold) = hold.as_mut() {
+                        hold.on_reply(dest.as_str(), serial.get(), local_id);
+                    }
+                    continue;
+                }
+
+                if let Some(payload) = escalation_payload(&msg, &cfg.origin) {
+                    info!(app = %payload.app_name, "forwarding escalated notification again");
+                    if let Err(err) = tx.send(payload) {
+                        warn!(?err, "forward worker channel closed");
+                        break;
+                    }
+                    stats.record_queued();
+                    continue;
+                }
 
                 let Ok(parsed) = parse_notification_message(&msg) else {
                     warn!("failed to parse monitored message");
+                    stats.record_filtered();
                     continue;
                 };
 
+                if let Some(NotificationMessage::NotificationClosed { id, .. }) = parsed {
+                    let last = hold.as_mut().and_then(|hold| hold.on_closed(id));
+                    if !release(last, &tx, &mut guard, &target_origin, &stats) {
+                        break;
+                    }
+                    continue;
+                }
+
                 let Some(NotificationMessage::Notify(call)) = parsed else {
+                    stats.record_filtered();
                     continue;
                 };
+                stats.record_observed();
 
                 let urgency = call
                     .hints
@@ -148,28 +478,146 @@ async fn main() -> Result<()> {
                     .unwrap_or("normal")
                     .to_string();
 
+                let identity = Identity::of_call(
+                    &call.hints,
+                    guard.local_origin(),
+                    &call.app_name,
+                    &call.summary,
+                    &call.body,
+                    &urgency,
+                );
+                // Held payloads pass the guard when they are released.
+                if !final_mode && !admit(&mut guard, &identity, &call.app_name, &target_origin) {
+                    stats.record_filtered();
+                    continue;
+                }
+
+                let token = if cfg.forward_actions && !call.actions.is_empty() {
+                    next_token += 1;
+                    if let Some(sender) = msg.header().sender() {
+                        pending.insert(sender.to_string(), msg.primary_header().serial_num().get(), next_token);
+                    }
+                    Some(next_token)
+                } else {
+                    None
+                };
+
+                let caller = msg
+                    .header()
+                    .sender()
+                    .map(|sender| (sender.to_string(), msg.primary_header().serial_num().get()));
+                let keys = HoldKeys::of_call(&call, caller);
                 let payload = ForwardPayload {
                     app_name: call.app_name,
                     summary: call.summary,
                     body: call.body,
                     expire_timeout: call.expire_timeout,
                     urgency,
+                    actions: call.actions,
+                    token,
+                    identity,
                 };
 
+                if let Some(hold) = hold.as_mut() {
+                    let evicted = hold.hold(keys, payload, Instant::now());
+                    if !release(evicted, &tx, &mut guard, &target_origin, &stats) {
+                        break;
+                    }
+                    continue;
+                }
+
                 if let Err(err) = tx.send(payload) {
                     warn!(?err, "forward worker channel closed");
                     break;
                 }
+                stats.record_queued();
             }
         }
     }
 
+    systemd::warn_on_failure(notifier.stopping());
+    if let Some(hold) = hold.as_mut() {
+        release(hold.drain(), &tx, &mut guard, &target_origin, &stats);
+    }
     drop(tx);
     let _ = worker.join();
+    info!("{}", stats.snapshot().summary_line(&last_summary));
 
     Ok(())
 }
 
+/// Runs `identity` through the loop guard, logging why it is dropped.
+fn admit(
+    guard: &mut LoopGuard,
+    identity: &Identity,
+    app_name: &str,
+    target_origin: &OnceLock<String>,
+) -> bool {
+    let verdict = guard.check(
+        identity,
+        target_origin.get().map(String::as_str),
+        Instant::now(),
+    );
+    if verdict != Verdict::Forward {
+        info!(
+            app = %app_name,
+            origin = %identity.origin,
+            hash = %origin::format_hash(identity.hash),
+            verdict = verdict.label(),
+            "not forwarding notification"
+        );
+    }
+    verdict == Verdict::Forward
+}
+
+/// Queues payloads leaving the final-mode hold. Returns false once the worker is gone.
+fn release(
+    payloads: impl IntoIterator<Item = ForwardPayload>,
+    tx: &mpsc::Sender<ForwardPayload>,
+    guard: &mut LoopGuard,
+    target_origin: &OnceLock<String>,
+    stats: &ForwardStats,
+) -> bool {
+    for payload in payloads {
+        if !admit(guard, &payload.identity, &payload.app_name, target_origin) {
+            stats.record_filtered();
+            continue;
+        }
+        if let Err(err) = tx.send(payload) {
+            warn!(?err, "forward worker channel closed");
+            return false;
+        }
+        stats.record_queued();
+    }
+    true
+}
+
+/// A critical re-send of the notification in an `org.wispd.Control1.Escalated` signal.
+/// Deliberate repeats, so they bypass the duplicate check.
+fn escalation_payload(msg: &zbus::Message, local_origin: &str) -> Option<ForwardPayload> {
+    let header = msg.header();
+    if msg.message_type() != MessageType::Signal
+        || header.interface()?.as_str() != CONTROL_INTERFACE
+        || header.member()?.as_str() != "Escalated"
+    {
+        return None;
+    }
+    let (_id, app_name, summary, body, _count): (u32, String, String, String, u32) =
+        msg.body().deserialize().ok()?;
+    let urgency = "critical".to_string();
+    let identity = Identity::local(local_origin, &app_name, &summary, &body, &urgency);
+    Some(ForwardPayload {
+        app_name,
+        summary,
+        body,
+        expire_timeout: 0,
+        urgency,
+        actions: Vec::new(),
+        token: None,
+        identity,
+    })
+}
+
 async fn wait_for_ssh_startup(cfg: &ForwardConfig) -> Result<()> {
     let deadline = Instant::now() + Duration::from_secs(cfg.startup_wait_secs);
     let addr = format!("{}:{}", cfg.ssh_host, cfg.ssh_port);
@@ -190,46 +638,116 @@ async fn wait_for_ssh_startup(cfg: &ForwardConfig) -> Result<()> {
     }
 }
 
-fn run_forward_worker(cfg: ForwardConfig, rx: mpsc::Receiver<ForwardPayload>) {
+/// Unique name of the local notification daemon, whose `Notify` replies carry local ids.
+async fn local_daemon_owner(conn: &zbus::Connection) -> Option<String> {
+    let proxy = DBusProxy::new(conn).await.ok()?;
+    match proxy
+        .get_name_owner(BusName::try_from(DEFAULT_DBUS_NAME).ok()?)
+        .await
+    {
+        Ok(owner) => Some(owner.to_string()),
+        Err(err) => {
+            warn!(%err, "failed to resolve {DEFAULT_DBUS_NAME} owner");
+            None
+        }
+    }
+}
+
+fn run_forward_worker(
+    cfg: ForwardConfig,
+    rx: mpsc::Receiver<ForwardPayload>,
+    stats: &ForwardStats,
+    ids: &SharedIdMap,
+    target_origin: &OnceLock<String>,
+) {
     let mut session: Option<Session> = None;
+    let mut probed_target = false;
 
     for payload in rx {
-        if let Err(err) = forward_with_reconnect(&cfg, &mut session, &payload) {
-            warn!(?err, app = %payload.app_name, summary = %payload.summary, "failed to forward notification");
+        let payload = cfg.transform.apply(payload);
+        let with_actions = cfg.forward_actions && !payload.actions.is_empty();
+        let cmd = if with_actions {
+            actions::build_remote_gdbus_command(&cfg.remote_gdbus, &payload)
+        } else {
+            build_remote_notify_command(&cfg.remote_notify_send, &payload)
+        };
+        let result = forward_with_reconnect(&cfg, &mut session, &cmd, stats);
+        if !probed_target && let Some(session) = session.as_mut() {
+            probed_target = true;
+            probe_target_origin(session, target_origin);
+        }
+        if let (Ok(stdout), Some(token)) = (&result, payload.token) {
+            match actions::parse_notify_reply(stdout) {
+                Some(remote_id) => ids
+                    .lock()
+                    .expect("id map poisoned")
+                    .set_remote(token, remote_id),
+                None => warn!(stdout = %stdout.trim(), "could not read remote notification id"),
+            }
+        }
+        stats.record_done(result.is_ok());
+        if let Err(err) = result {
+            warn!(?err, app = %payload.app_name, summary = %Loggable(&payload.summary), "failed to forward notification");
         } else {
-            info!(app_name = %payload.app_name, summary = %payload.summary, "forwarded notification");
+            info!(app_name = %payload.app_name, summary = %Loggable(&payload.summary), "forwarded notification");
         }
     }
 }
 
+/// Learns the target's machine id so its own notifications are not sent back to it.
+fn probe_target_origin(session: &mut Session, target_origin: &OnceLock<String>) {
+    match exec_remote(session, dedup::REMOTE_MACHINE_ID_COMMAND) {
+        Ok(stdout) => match dedup::parse_machine_id(&stdout) {
+            Some(id) => {
+                info!(target_origin = %id, "forward target identified");
+                let _ = target_origin.set(id);
+            }
+            None => warn!("forward target reported an empty machine id"),
+        },
+        Err(err) => warn!(?err, "could not read the forward target's machine id"),
+    }
+}
+
 fn forward_with_reconnect(
     cfg: &ForwardConfig,
     session: &mut Option<Session>,
-    payload: &ForwardPayload,
-) -> Result<()> {
+    cmd: &str,
+    stats: &ForwardStats,
+) -> Result<String> {
     if session.is_none() {
-        *session = Some(connect_session(cfg)?);
+        *session = Some(connect_tracked(cfg, stats)?);
     }
 
     let first_try = session
         .as_mut()
         .context("ssh session unexpectedly absent")
-        .and_then(|s| exec_notify(s, cfg, payload));
+        .and_then(|s| exec_remote(s, cmd));
 
-    if first_try.is_ok() {
-        return Ok(());
+    if let Ok(stdout) = first_try {
+        return Ok(stdout);
     }
 
     warn!("ssh session failed; reconnecting and retrying once");
-    *session = Some(connect_session(cfg)?);
+    *session = Some(connect_tracked(cfg, stats)?);
 
     let s = session
         .as_mut()
         .context("ssh session unexpectedly absent after reconnect")?;
-    exec_notify(s, cfg, payload)
+    exec_remote(s, cmd)
 }
 
-fn connect_session(cfg: &ForwardConfig) -> Result<Session> {
+fn connect_tracked(cfg: &ForwardConfig, stats: &ForwardStats) -> Result<Session> {
+    let session = connect_session(cfg, Some(Duration::from_secs(5)));
+    stats.set_connection(if session.is_ok() {
+        ConnectionState::Connected
+    } else {
+        ConnectionState::Disconnected
+    });
+    session
+}
+
+/// Opens an authenticated session; `read_timeout` of `None` blocks reads indefinitely.
+fn connect_session(cfg: &ForwardConfig, read_timeout: Option<Duration>) -> Result<Session> {
     let addr = (cfg.ssh_host.as_str(), cfg.ssh_port)
         .to_socket_addrs()
         .context("failed to resolve ssh host")?
@@ -238,7 +756,7 @@ fn connect_session(cfg: &ForwardConfig) -> Result<Session> {
 
     let tcp = TcpStream::connect_timeout(&addr, Duration::from_secs(3))
         .with_context(|| format!("failed to connect to {}:{}", cfg.ssh_host, cfg.ssh_port))?;
-    tcp.set_read_timeout(Some(Duration::from_secs(5))).ok();
+    tcp.set_read_timeout(read_timeout).ok();
     tcp.set_write_timeout(Some(Duration::from_secs(5))).ok();
 
     let mut session = Session::new().context("failed to create ssh session")?;
@@ -256,14 +774,14 @@ fn connect_session(cfg: &ForwardConfig) -> Result<Session> {
     Ok(session)
 }
 
-fn exec_notify(session: &mut Session, cfg: &ForwardConfig, payload: &ForwardPayload) -> Result<()> {
+/// Runs `cmd` on the remote side and returns its stdout.
+fn exec_remote(session: &mut Session, cmd: &str) -> Result<String> {
     let mut channel = session
         .channel_session()
         .context("failed to open ssh channel")?;
 
-    let cmd = build_remote_notify_command(cfg, payload);
     channel
-        .exec(&cmd)
+        .exec(cmd)
         .with_context(|| format!("failed to exec remote command: {cmd}"))?;
 
     let mut stdout = String::new();
@@ -280,19 +798,19 @@ fn exec_notify(session: &mut Session, cfg: &ForwardConfig, payload: &ForwardPayl
 
     if status != 0 {
         anyhow::bail!(
-            "remote notify-send failed with status {status}, stderr: {}, stdout: {}",
+            "remote command failed with status {status}, stderr: {}, stdout: {}",
             stderr.trim(),
             stdout.trim()
         );
     }
 
-    Ok(())
+    Ok(stdout)
 }
 
-fn build_remote_notify_command(cfg: &ForwardConfig, payload: &ForwardPayload) -> String {
+fn build_remote_notify_command(notify_send: &str, payload: &ForwardPayload) -> String {
     let mut cmd = format!(
         "{} -a {} -u {}",
-        sh_quote(&cfg.remote_notify_send),
+        sh_quote(notify_send),
         sh_quote(&payload.app_name),
         sh_quote(&payload.urgency)
     );
@@ -301,6 +819,14 @@ fn build_remote_notify_command(cfg: &ForwardConfig, payload: &ForwardPayload) ->
         cmd.push_str(&format!(" -t {}", payload.expire_timeout));
     }
 
+    for (key, value) in [
+        (ORIGIN_HINT, payload.identity.origin.clone()),
+        (HASH_HINT, origin::format_hash(payload.identity.hash)),
+    ] {
+        cmd.push_str(" -h ");
+        cmd.push_str(&sh_quote(&format!("string:{key}:{value}")));
+    }
+
     cmd.push(' ');
     cmd.push_str(&sh_quote(&payload.summary));
 
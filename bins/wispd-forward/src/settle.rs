@@ -0,0 +1,281 @@
+//! The `final` forward mode: a notification that keeps being replaced (download progress,
+//! volume popups) is forwarded once, with its last content, instead of once per update.
+//!
+//! Every `Notify` is held here under the ids it can be recognised by: the local id (from
+//! its `replaces_id`, or the daemon's reply to the first call) and a stack tag hint. A
+//! later call with the same id or tag replaces the held payload. A payload is released when
+//! the local daemon closes its notification, or after the quiet period without updates.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use wisp_monitor::NotifyCall;
+use zbus::zvariant;
+
+use crate::ForwardPayload;
+
+/// Hints apps set so that notifications with the same value replace each other.
+const STACK_TAG_HINTS: [&str; 2] = ["x-dunst-stack-tag", "x-canonical-private-synchronous"];
+
+/// Held notifications before the oldest is released early.
+const MAX_HELD: usize = 256;
+
+/// `WISPD_FORWARD_MODE`: every `Notify` (`all`), or only the last content of each
+/// notification (`final`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ForwardMode {
+    #[default]
+    All,
+    Final,
+}
+
+impl FromStr for ForwardMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(Self::All),
+            "final" => Ok(Self::Final),
+            other => Err(format!(
+                "unknown forward mode {other:?} (expected all or final)"
+            )),
+        }
+    }
+}
+
+/// What a `Notify` call can be matched with later.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HoldKeys {
+    /// The local id, when the call replaces one.
+    pub(crate) id: Option<u32>,
+    /// App name and stack tag.
+    pub(crate) tag: Option<(String, String)>,
+    /// Caller and serial, to learn the id from the daemon's reply.
+    pub(crate) call: Option<(String, u32)>,
+}
+
+impl HoldKeys {
+    pub(crate) fn of_call(call: &NotifyCall, caller: Option<(String, u32)>) -> Self {
+        Self {
+            id: (call.replaces_id != 0).then_some(call.replaces_id),
+            tag: stack_tag(&call.hints).map(|tag| (call.app_name.clone(), tag)),
+            call: caller,
+        }
+    }
+}
+
+fn stack_tag(hints: &HashMap<String, zvariant::OwnedValue>) -> Option<String> {
+    STACK_TAG_HINTS.iter().find_map(|key| {
+        let tag = <&str>::try_from(hints.get(*key)?).ok()?;
+        (!tag.is_empty()).then(|| tag.to_string())
+    })
+}
+
+#[derive(Debug)]
+struct Held {
+    keys: HoldKeys,
+    payload: ForwardPayload,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct FinalHold {
+    quiet: Duration,
+    /// Oldest first.
+    held: Vec<Held>,
+}
+
+impl FinalHold {
+    pub(crate) fn new(quiet: Duration) -> Self {
+        Self {
+            quiet,
+            held: Vec::new(),
+        }
+    }
+
+    /// Holds `payload`, replacing a held notification that shares its id or tag. Returns
+    /// the oldest payload when the hold is full.
+    pub(crate) fn hold(
+        &mut self,
+        keys: HoldKeys,
+        payload: ForwardPayload,
+        now: Instant,
+    ) -> Option<ForwardPayload> {
+        let same = |held: &Held| {
+            (keys.id.is_some() && held.keys.id == keys.id)
+                || (keys.tag.is_some() && held.keys.tag == keys.tag)
+        };
+        if let Some(held) = self.held.iter_mut().find(|held| same(held)) {
+            held.keys.id = keys.id.or(held.keys.id);
+            held.keys.tag = keys.tag.or(held.keys.tag.take());
+            held.keys.call = keys.call;
+            held.payload = payload;
+            held.updated = now;
+            return None;
+        }
+        let evicted = (self.held.len() == MAX_HELD).then(|| self.held.remove(0).payload);
+        self.held.push(Held {
+            keys,
+            payload,
+            updated: now,
+        });
+        evicted
+    }
+
+    /// Records the local id the daemon returned for the call `serial` from `caller`.
+    pub(crate) fn on_reply(&mut self, caller: &str, serial: u32, id: u32) {
+        if let Some(held) = self.held.iter_mut().find(|held| {
+            held.keys
+                .call
+                .as_ref()
+                .is_some_and(|(sender, s)| sender == caller && *s == serial)
+        }) {
+            held.keys.id = Some(id);
+            held.keys.call = None;
+        }
+    }
+
+    /// The final content of local notification `id`, which just closed.
+    pub(crate) fn on_closed(&mut self, id: u32) -> Option<ForwardPayload> {
+        let index = self.held.iter().position(|held| held.keys.id == Some(id))?;
+        Some(self.held.remove(index).payload)
+    }
+
+    /// Payloads that saw no update for the quiet period, oldest first.
+    pub(crate) fn settled(&mut self, now: Instant) -> Vec<ForwardPayload> {
+        let (settled, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|held| now.saturating_duration_since(held.updated) >= self.quiet);
+        self.held = held;
+        settled.into_iter().map(|held| held.payload).collect()
+    }
+
+    /// Everything still held, for shutdown.
+    pub(crate) fn drain(&mut self) -> Vec<ForwardPayload> {
+        self.held.drain(..).map(|held| held.payload).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedup::Identity;
+
+    const QUIET: Duration = Duration::from_secs(2);
+
+    fn payload(body: &str) -> ForwardPayload {
+        ForwardPayload {
+            app_name: "firefox".to_string(),
+            summary: "Downloading".to_string(),
+            body: body.to_string(),
+            expire_timeout: -1,
+            urgency: "normal".to_string(),
+            actions: vec![],
+            token: None,
+            identity: Identity::local("machine-a", "firefox", "Downloading", body, "normal"),
+        }
+    }
+
+    fn call(replaces_id: u32, tag: Option<&str>) -> NotifyCall {
+        NotifyCall {
+            app_name: "firefox".to_string(),
+            replaces_id,
+            app_icon: String::new(),
+            summary: "Downloading".to_string(),
+            body: String::new(),
+            actions: vec![],
+            hints: tag
+                .map(|tag| {
+                    HashMap::from([(
+                        "x-dunst-stack-tag".to_string(),
+                        zvariant::OwnedValue::from(zvariant::Str::from(tag.to_string())),
+                    )])
+                })
+                .unwrap_or_default(),
+            expire_timeout: -1,
+        }
+    }
+
+    #[test]
+    fn twenty_replacements_forward_the_final_body_once_on_close() {
+        let start = Instant::now();
+        let mut hold = FinalHold::new(QUIET);
+        let mut sent = Vec::new();
+
+        let first = HoldKeys::of_call(&call(0, None), Some((":1.42".to_string(), 10)));
+        sent.extend(hold.hold(first, payload("0%"), start));
+        hold.on_reply(":1.42", 10, 7);
+        for step in 1..=20u32 {
+            let now = start + Duration::from_millis(u64::from(step) * 500);
+            let keys = HoldKeys::of_call(&call(7, None), Some((":1.42".to_string(), 10 + step)));
+            sent.extend(hold.hold(keys, payload(&format!("{}%", step * 5)), now));
+            sent.extend(hold.settled(now));
+        }
+        assert!(sent.is_empty(), "nothing leaves while updates keep coming");
+
+        sent.extend(hold.on_closed(7));
+        sent.extend(hold.settled(start + Duration::from_secs(60)));
+        let bodies: Vec<&str> = sent.iter().map(|p| p.body.as_str()).collect();
+        assert_eq!(bodies, ["100%"]);
+    }
+
+    #[test]
+    fn quiet_period_releases_and_stack_tags_join_unrelated_ids() {
+        let start = Instant::now();
+        let mut hold = FinalHold::new(QUIET);
+
+        hold.hold(
+            HoldKeys::of_call(&call(0, Some("volume")), None),
+            payload("40"),
+            start,
+        );
+        let later = start + Duration::from_secs(1);
+        hold.hold(
+            HoldKeys::of_call(&call(0, Some("volume")), None),
+            payload("45"),
+            later,
+        );
+        hold.hold(
+            HoldKeys::of_call(&call(0, None), None),
+            payload("other"),
+            later,
+        );
+
+        assert!(hold.settled(start + QUIET).is_empty());
+        let bodies: Vec<String> = hold
+            .settled(later + QUIET)
+            .into_iter()
+            .map(|p| p.body)
+            .collect();
+        assert_eq!(bodies, ["45", "other"]);
+        assert!(hold.on_closed(7).is_none());
+        assert!(hold.drain().is_empty());
+
+        assert_eq!("final".parse(), Ok(ForwardMode::Final));
+        assert_eq!(" all ".parse(), Ok(ForwardMode::All));
+        assert!("last".parse::<ForwardMode>().is_err());
+    }
+
+    #[test]
+    fn a_full_hold_releases_its_oldest_payload() {
+        let now = Instant::now();
+        let mut hold = FinalHold::new(QUIET);
+        for id in 1..=MAX_HELD as u32 {
+            let keys = HoldKeys {
+                id: Some(id),
+                ..HoldKeys::default()
+            };
+            assert!(hold.hold(keys, payload(&id.to_string()), now).is_none());
+        }
+        let keys = HoldKeys {
+            id: Some(9999),
+            ..HoldKeys::default()
+        };
+        let evicted = hold.hold(keys, payload("new"), now).map(|p| p.body);
+        assert_eq!(evicted.as_deref(), Some("1"));
+        assert_eq!(hold.drain().len(), MAX_HELD);
+    }
+}
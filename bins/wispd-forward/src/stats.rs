@@ -0,0 +1,270 @@
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU8, AtomicU64, Ordering},
+};
+
+/// SSH session state of the forward target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConnectionState {
+    /// No notification has needed a session yet.
+    Idle,
+    Connected,
+    Disconnected,
+}
+
+impl ConnectionState {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Connected => "connected",
+            Self::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// Counters shared by the bus loop, the forward worker, and the health listener.
+#[derive(Debug)]
+pub(crate) struct ForwardStats {
+    observed: AtomicU64,
+    filtered: AtomicU64,
+    forwarded: AtomicU64,
+    failed: AtomicU64,
+    queued: AtomicU64,
+    connection: AtomicU8,
+}
+
+impl Default for ForwardStats {
+    fn default() -> Self {
+        Self {
+            observed: AtomicU64::new(0),
+            filtered: AtomicU64::new(0),
+            forwarded: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            connection: AtomicU8::new(ConnectionState::Idle as u8),
+        }
+    }
+}
+
+/// Point-in-time copy of [`ForwardStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StatsSnapshot {
+    pub(crate) observed: u64,
+    pub(crate) filtered: u64,
+    pub(crate) forwarded: u64,
+    pub(crate) failed: u64,
+    pub(crate) queued: u64,
+    pub(crate) connection: ConnectionState,
+}
+
+impl ForwardStats {
+    /// A monitored `Notify` call was seen.
+    pub(crate) fn record_observed(&self) {
+        self.observed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A monitored message was dropped without being queued.
+    pub(crate) fn record_filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The worker took a payload off the queue and finished with it.
+    pub(crate) fn record_done(&self, ok: bool) {
+        let _ = self
+            .queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        if ok {
+            self.forwarded.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn set_connection(&self, state: ConnectionState) {
+        self.connection.store(state as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            observed: self.observed.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            forwarded: self.forwarded.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            connection: match self.connection.load(Ordering::Relaxed) {
+                s if s == ConnectionState::Connected as u8 => ConnectionState::Connected,
+                s if s == ConnectionState::Disconnected as u8 => ConnectionState::Disconnected,
+                _ => ConnectionState::Idle,
+            },
+        }
+    }
+}
+
+impl StatsSnapshot {
+    /// Healthy while the target is connected, or has never been needed and nothing waits.
+    pub(crate) fn is_healthy(&self) -> bool {
+        match self.connection {
+            ConnectionState::Connected => true,
+            ConnectionState::Idle => self.queued == 0,
+            ConnectionState::Disconnected => false,
+        }
+    }
+
+    /// One-line summary with totals and the change since `previous`.
+    pub(crate) fn summary_line(&self, previous: &StatsSnapshot) -> String {
+        format!(
+            "observed={} (+{}) filtered={} (+{}) forwarded={} (+{}) failed={} (+{}) queued={} target={}",
+            self.observed,
+            self.observed.saturating_sub(previous.observed),
+            self.filtered,
+            self.filtered.saturating_sub(previous.filtered),
+            self.forwarded,
+            self.forwarded.saturating_sub(previous.forwarded),
+            self.failed,
+            self.failed.saturating_sub(previous.failed),
+            self.queued,
+            self.connection.label(),
+        )
+    }
+
+    /// Prometheus text exposition of the same counters.
+    pub(crate) fn prometheus(&self, target: &str) -> String {
+        let mut out = String::new();
+        for (name, kind, help, value) in [
+            (
+                "observed",
+                "counter",
+                "Notify calls observed on the bus.",
+                self.observed,
+            ),
+            (
+                "filtered",
+                "counter",
+                "Monitored messages not forwarded.",
+                self.filtered,
+            ),
+            (
+                "forwarded",
+                "counter",
+                "Notifications forwarded successfully.",
+                self.forwarded,
+            ),
+            (
+                "failed",
+                "counter",
+                "Notifications that failed to forward.",
+                self.failed,
+            ),
+            (
+                "queued",
+                "gauge",
+                "Notifications waiting for the worker.",
+                self.queued,
+            ),
+        ] {
+            let metric = if kind == "counter" {
+                format!("wispd_forward_{name}_total")
+            } else {
+                format!("wispd_forward_{name}")
+            };
+            let _ = writeln!(out, "# HELP {metric} {help}");
+            let _ = writeln!(out, "# TYPE {metric} {kind}");
+            let _ = writeln!(out, "{metric} {value}");
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wispd_forward_target_connected Whether the target has a live session."
+        );
+        let _ = writeln!(out, "# TYPE wispd_forward_target_connected gauge");
+        let _ = writeln!(
+            out,
+            "wispd_forward_target_connected{{target=\"{}\"}} {}",
+            target.replace('\\', "\\\\").replace('"', "\\\""),
+            u8::from(self.connection == ConnectionState::Connected)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_track_queue_and_outcomes() {
+        let stats = ForwardStats::default();
+        for _ in 0..3 {
+            stats.record_observed();
+            stats.record_queued();
+        }
+        stats.record_filtered();
+        stats.record_done(true);
+        stats.record_done(false);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            (
+                snapshot.observed,
+                snapshot.filtered,
+                snapshot.forwarded,
+                snapshot.failed
+            ),
+            (3, 1, 1, 1)
+        );
+        assert_eq!(snapshot.queued, 1);
+
+        stats.record_done(true);
+        stats.record_done(true);
+        assert_eq!(stats.snapshot().queued, 0, "queue depth never underflows");
+    }
+
+    #[test]
+    fn health_follows_connection_and_queue() {
+        let stats = ForwardStats::default();
+        assert!(stats.snapshot().is_healthy(), "idle with an empty queue");
+
+        stats.record_queued();
+        assert!(!stats.snapshot().is_healthy(), "idle with work waiting");
+
+        stats.set_connection(ConnectionState::Connected);
+        assert!(stats.snapshot().is_healthy());
+
+        stats.set_connection(ConnectionState::Disconnected);
+        assert!(!stats.snapshot().is_healthy());
+    }
+
+    #[test]
+    fn summary_line_reports_totals_and_deltas() {
+        let stats = ForwardStats::default();
+        stats.record_observed();
+        let previous = stats.snapshot();
+        stats.record_observed();
+        stats.record_queued();
+        stats.record_done(true);
+        stats.set_connection(ConnectionState::Connected);
+
+        assert_eq!(
+            stats.snapshot().summary_line(&previous),
+            "observed=2 (+1) filtered=0 (+0) forwarded=1 (+1) failed=0 (+0) queued=0 target=connected"
+        );
+    }
+
+    #[test]
+    fn prometheus_exposes_each_counter() {
+        let stats = ForwardStats::default();
+        stats.record_observed();
+        stats.set_connection(ConnectionState::Connected);
+        let text = stats.snapshot().prometheus("wisp@127.0.0.1:2222");
+
+        assert!(text.contains("# TYPE wispd_forward_observed_total counter\n"));
+        assert!(text.contains("wispd_forward_observed_total 1\n"));
+        assert!(text.contains("wispd_forward_queued 0\n"));
+        assert!(
+            text.contains("wispd_forward_target_connected{target=\"wisp@127.0.0.1:2222\"} 1\n")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Running as a systemd user service: the unit `--print-systemd-unit` prints, and when
+//! the forwarder reports `READY=1` and pings the watchdog. The notify protocol itself is
+//! [`wisp_types::systemd`], shared with `wispd`.
+
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+use wisp_types::systemd::quote_exec_arg;
+
+/// `WatchdogSec=` of the generated unit; the forwarder pings at half of it.
+const WATCHDOG_SEC: u64 = 60;
+
+/// A user unit for `~/.config/systemd/user/wispd-forward.service` that starts `exe`.
+pub(crate) fn render_unit(exe: &Path) -> String {
+    let exec = quote_exec_arg(&exe.to_string_lossy());
+    format!(
+        "[Unit]\n\
+         Description=wispd notification forwarder\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={exec}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         WatchdogSec={WATCHDOG_SEC}\n\
+         # Settings come from the environment: one WISPD_FORWARD_*=value per line here,\n\
+         EnvironmentFile=-%h/.config/wispd/forward.env\n\
+         # or as Environment= lines, e.g.\n\
+         #Environment=WISPD_FORWARD_SSH_HOST=127.0.0.1\n\
+         #Environment=WISPD_FORWARD_SSH_PORT=2222\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n"
+    )
+}
+
+/// Notifying is best effort: the forwarder works the same without systemd.
+pub(crate) fn warn_on_failure(result: io::Result<()>) {
+    if let Err(err) = result {
+        warn!(%err, "failed to notify systemd");
+    }
+}
+
+/// Startup milestones. The forwarder is ready once the monitor is attached and the
+/// target is initialized, in whichever order they happen.
+#[derive(Debug, Default)]
+pub(crate) struct Readiness {
+    monitor_attached: bool,
+    target_ready: bool,
+    announced: bool,
+}
+
+impl Readiness {
+    /// Records the target as initialized; true when that completes startup.
+    pub(crate) fn target_ready(&mut self) -> bool {
+        self.target_ready = true;
+        self.became_ready()
+    }
+
+    /// Records the monitor as attached; true when that completes startup.
+    pub(crate) fn monitor_attached(&mut self) -> bool {
+        self.monitor_attached = true;
+        self.became_ready()
+    }
+
+    fn became_ready(&mut self) -> bool {
+        let ready = self.monitor_attached && self.target_ready && !self.announced;
+        self.announced |= ready;
+        ready
+    }
+}
+
+/// Whether the monitor stream makes progress. A quiet bus does; a stream that has yielded
+/// only errors for a whole watchdog interval does not, and the pings stop so systemd
+/// restarts the forwarder.
+#[derive(Debug, Default)]
+pub(crate) struct StreamProgress {
+    failing_since: Option<Instant>,
+}
+
+impl StreamProgress {
+    pub(crate) fn on_message(&mut self) {
+        self.failing_since = None;
+    }
+
+    pub(crate) fn on_error(&mut self, now: Instant) {
+        self.failing_since.get_or_insert(now);
+    }
+
+    pub(crate) fn should_ping(&self, now: Instant, interval: Duration) -> bool {
+        self.failing_since
+            .is_none_or(|since| now.saturating_duration_since(since) < interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_unit_waits_for_readiness_and_restarts_on_failure() {
+        let unit = render_unit(Path::new("/opt/wisp d/wispd-forward"));
+        assert!(unit.starts_with("[Unit]\n"));
+        for line in [
+            "After=graphical-session.target",
+            "Type=notify",
+            "ExecStart=\"/opt/wisp d/wispd-forward\"",
+            "Restart=on-failure",
+            "WatchdogSec=60",
+            "EnvironmentFile=-%h/.config/wispd/forward.env",
+            "WantedBy=graphical-session.target",
+        ] {
+            assert!(
+                unit.lines().any(|l| l == line),
+                "missing {line:?} in\n{unit}"
+            );
+        }
+    }
+
+    #[test]
+    fn ready_once_both_the_monitor_and_the_target_are_up() {
+        let mut readiness = Readiness::default();
+        assert!(!readiness.target_ready(), "monitor not attached yet");
+        assert!(readiness.monitor_attached());
+        assert!(!readiness.target_ready(), "announced once");
+
+        let mut readiness = Readiness::default();
+        assert!(!readiness.monitor_attached());
+        assert!(readiness.target_ready());
+    }
+
+    #[test]
+    fn watchdog_pings_stop_when_the_stream_only_fails() {
+        let interval = Duration::from_secs(30);
+        let start = Instant::now();
+        let mut progress = StreamProgress::default();
+        assert!(progress.should_ping(start, interval), "a quiet bus is fine");
+
+        progress.on_error(start);
+        progress.on_error(start + Duration::from_secs(20));
+        assert!(progress.should_ping(start + Duration::from_secs(29), interval));
+        assert!(!progress.should_ping(start + Duration::from_secs(30), interval));
+
+        progress.on_message();
+        assert!(progress.should_ping(start + Duration::from_secs(31), interval));
+    }
+}
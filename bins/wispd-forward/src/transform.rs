@@ -0,0 +1,148 @@
+use wisp_types::{
+    privacy::{self, PrivacyRule},
+    template,
+};
+
+use crate::ForwardPayload;
+
+/// Reshapes payloads before the remote command is built, e.g. to tag the source machine or
+/// shorten bodies for a smaller screen.
+#[derive(Debug, Clone)]
+pub(crate) struct PayloadTransform {
+    pub(crate) summary_template: Option<String>,
+    pub(crate) body_template: Option<String>,
+    /// Same rules as wispd's `ui.privacy_apps`, applied before the templates.
+    pub(crate) privacy_apps: Vec<PrivacyRule>,
+    pub(crate) redaction_placeholder: String,
+}
+
+impl Default for PayloadTransform {
+    fn default() -> Self {
+        Self {
+            summary_template: None,
+            body_template: None,
+            privacy_apps: Vec::new(),
+            redaction_placeholder: privacy::DEFAULT_PLACEHOLDER.to_string(),
+        }
+    }
+}
+
+impl PayloadTransform {
+    /// Redacts private apps, then applies the templates. A body that renders blank is
+    /// dropped; a blank summary keeps the original because `notify-send` requires one.
+    pub(crate) fn apply(&self, mut payload: ForwardPayload) -> ForwardPayload {
+        privacy::redact(
+            &self.privacy_apps,
+            &self.redaction_placeholder,
+            &payload.app_name,
+            &mut payload.summary,
+            &mut payload.body,
+        );
+        let summary = self
+            .summary_template
+            .as_deref()
+            .map(|t| render(t, &payload))
+            .filter(|summary| !summary.trim().is_empty())
+            .unwrap_or_else(|| payload.summary.clone());
+        let body = match self.body_template.as_deref() {
+            Some(t) => {
+                let body = render(t, &payload);
+                if body.trim().is_empty() {
+                    String::new()
+                } else {
+                    body
+                }
+            }
+            None => payload.body.clone(),
+        };
+        ForwardPayload {
+            summary,
+            body,
+            ..payload
+        }
+    }
+}
+
+fn render(template: &str, payload: &ForwardPayload) -> String {
+    template::render(template, |name| match name {
+        "app_name" => Some(payload.app_name.clone()),
+        "summary" => Some(payload.summary.clone()),
+        "body" => Some(payload.body.clone()),
+        "urgency" => Some(payload.urgency.clone()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedup::Identity;
+
+    fn payload(summary: &str, body: &str) -> ForwardPayload {
+        ForwardPayload {
+            app_name: "Signal".to_string(),
+            summary: summary.to_string(),
+            body: body.to_string(),
+            expire_timeout: -1,
+            urgency: "normal".to_string(),
+            actions: vec![],
+            token: None,
+            identity: Identity::local("machine-a", "Signal", summary, body, "normal"),
+        }
+    }
+
+    #[test]
+    fn templates_reshape_summary_and_truncate_body() {
+        let transform = PayloadTransform {
+            summary_template: Some("[desktop] {app_name}: {summary}".to_string()),
+            body_template: Some("{body|trunc:12}".to_string()),
+            ..PayloadTransform::default()
+        };
+        let out = transform.apply(payload("Ada", "Wir sehen uns später 🎉🎉 im Café"));
+        assert_eq!(out.summary, "[desktop] Signal: Ada");
+        assert_eq!(out.body, "Wir sehen u…");
+        assert_eq!(out.urgency, "normal");
+
+        let out = transform.apply(payload("Ada", "🎉🎉"));
+        assert_eq!(out.body, "🎉🎉");
+    }
+
+    #[test]
+    fn blank_results_skip_the_field() {
+        let transform = PayloadTransform {
+            summary_template: Some("{summary}".to_string()),
+            body_template: Some("{body}".to_string()),
+            ..PayloadTransform::default()
+        };
+        let out = transform.apply(payload("Ada", "   "));
+        assert_eq!(out.body, "");
+        // The summary is the last argument: no empty body follows it.
+        let command = crate::build_remote_notify_command("notify-send", &out);
+        assert!(command.ends_with(" 'Ada'"), "{command}");
+
+        let out = PayloadTransform {
+            summary_template: Some("{body}".to_string()),
+            ..PayloadTransform::default()
+        }
+        .apply(payload("kept", ""));
+        assert_eq!(out.summary, "kept");
+    }
+
+    #[test]
+    fn private_apps_are_redacted_before_templates_see_the_body() {
+        let transform = PayloadTransform {
+            body_template: Some("{body} (via desktop)".to_string()),
+            privacy_apps: vec!["sig*".parse().unwrap()],
+            ..PayloadTransform::default()
+        };
+        let out = transform.apply(payload("Ada", "the door code is 4711"));
+        assert_eq!(out.summary, "Ada");
+        assert_eq!(out.body, "New message (via desktop)");
+    }
+
+    #[test]
+    fn no_templates_forward_unchanged() {
+        let out = PayloadTransform::default().apply(payload("hi", "there"));
+        assert_eq!((out.summary.as_str(), out.body.as_str()), ("hi", "there"));
+    }
+}
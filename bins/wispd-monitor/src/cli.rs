@@ -0,0 +1,283 @@
+use clap::Parser;
+use wisp_monitor::{member_type, notification_rule, rules_all_notifications};
+use zbus::message::Type as MessageType;
+
+/// Passively log freedesktop notification traffic on the session bus.
+#[derive(Debug, Parser)]
+#[command(name = "wispd-monitor")]
+pub(crate) struct Args {
+    /// Only `Notify` method calls.
+    #[arg(
+        long,
+        env = "WISPD_MONITOR_NOTIFY_ONLY",
+        conflicts_with = "signals_only"
+    )]
+    pub(crate) notify_only: bool,
+    /// Only `NotificationClosed`/`ActionInvoked` (and other) signals.
+    #[arg(long, env = "WISPD_MONITOR_SIGNALS_ONLY")]
+    pub(crate) signals_only: bool,
+    /// Restrict to these members; repeatable (env: comma-separated).
+    #[arg(
+        long = "member",
+        value_name = "MEMBER",
+        env = "WISPD_MONITOR_MEMBERS",
+        value_delimiter = ','
+    )]
+    pub(crate) members: Vec<String>,
+    /// Only messages sent by this unique name (e.g. `:1.234`).
+    #[arg(long, value_name = "UNIQUE_NAME", env = "WISPD_MONITOR_SENDER")]
+    pub(crate) sender: Option<String>,
+    /// Only messages sent by the current owner of this well-known name.
+    #[arg(long, value_name = "NAME", env = "WISPD_MONITOR_SENDER_NAME")]
+    pub(crate) sender_name: Option<String>,
+    /// Skip the startup banner.
+    #[arg(short, long, env = "WISPD_MONITOR_QUIET")]
+    pub(crate) quiet: bool,
+}
+
+/// Match rules for `BecomeMonitor`, plus what was adjusted to get there.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct RulePlan {
+    pub(crate) rules: Vec<String>,
+    pub(crate) warnings: Vec<String>,
+}
+
+impl Args {
+    /// Translates the flags into match rules. `sender_name_owner` is the unique name that
+    /// currently owns `--sender-name`, if it could be resolved.
+    ///
+    /// Monitors match on the unique sender only, which is why well-known names are resolved
+    /// up front. Signals come from the notification daemon, so a sender filter narrows
+    /// method calls and leaves signals alone unless `--signals-only` asks for them.
+    pub(crate) fn rule_plan(&self, sender_name_owner: Option<&str>) -> RulePlan {
+        let mut plan = RulePlan::default();
+        let calls = !self.signals_only;
+        let signals = !self.notify_only;
+
+        let members: Vec<&str> = if self.notify_only {
+            if self.members.iter().any(|m| m != "Notify") {
+                plan.warnings
+                    .push("--notify-only ignores other --member values".to_string());
+            }
+            vec!["Notify"]
+        } else {
+            let kept: Vec<&str> = self
+                .members
+                .iter()
+                .map(String::as_str)
+                .filter(|member| match member_type(member) {
+                    Some(MessageType::MethodCall) => calls,
+                    Some(MessageType::Signal) => signals,
+                    _ => true,
+                })
+                .collect();
+            if kept.len() < self.members.len() {
+                plan.warnings
+                    .push("--signals-only drops method-call --member values".to_string());
+            }
+            kept
+        };
+
+        let sender = match (&self.sender, &self.sender_name, sender_name_owner) {
+            (Some(sender), Some(_), _) => {
+                plan.warnings
+                    .push("--sender takes precedence over --sender-name".to_string());
+                Some(sender.as_str())
+            }
+            (Some(sender), None, _) => Some(sender.as_str()),
+            (None, Some(_), Some(owner)) => Some(owner),
+            (None, Some(name), None) => {
+                plan.warnings.push(format!(
+                    "{name} has no owner on the bus; monitoring without a sender filter"
+                ));
+                None
+            }
+            (None, None, _) => None,
+        };
+        let kind_only = match (calls, signals) {
+            (true, false) => Some(MessageType::MethodCall),
+            (false, true) => Some(MessageType::Signal),
+            _ => None,
+        };
+        let targets: Vec<(Option<MessageType>, Option<&str>)> = if members.is_empty() {
+            [
+                (calls, MessageType::MethodCall),
+                (signals, MessageType::Signal),
+            ]
+            .into_iter()
+            .filter(|(wanted, _)| *wanted)
+            .map(|(_, msg_type)| (Some(msg_type), None))
+            .collect()
+        } else {
+            members
+                .iter()
+                .map(|member| (member_type(member).or(kind_only), Some(*member)))
+                .collect()
+        };
+
+        let mut signals_unfiltered = false;
+        for (msg_type, member) in targets {
+            let rule_sender = if msg_type == Some(MessageType::Signal) && calls {
+                signals_unfiltered |= sender.is_some();
+                None
+            } else {
+                sender
+            };
+            match notification_rule(msg_type, member, rule_sender) {
+                Ok(rule) => plan.rules.push(rule),
+                Err(err) if rule_sender.is_some() => {
+                    plan.warnings
+                        .push(format!("{err:#}; monitoring without a sender filter"));
+                    if let Ok(rule) = notification_rule(msg_type, member, None) {
+                        plan.rules.push(rule);
+                    }
+                }
+                Err(err) => plan.warnings.push(format!("{err:#}; skipping rule")),
+            }
+        }
+
+        if signals_unfiltered {
+            plan.warnings.push(
+                "signals come from the notification daemon and are not filtered by sender"
+                    .to_string(),
+            );
+        }
+        if plan.rules.is_empty() {
+            plan.warnings
+                .push("no usable rules; monitoring all notification traffic".to_string());
+            plan.rules = rules_all_notifications();
+        }
+        plan.rules.dedup();
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(flags: &[&str]) -> Args {
+        Args::try_parse_from(std::iter::once("wispd-monitor").chain(flags.iter().copied()))
+            .expect("flags parse")
+    }
+
+    fn rule(msg_type: Option<MessageType>, member: Option<&str>, sender: Option<&str>) -> String {
+        notification_rule(msg_type, member, sender).unwrap()
+    }
+
+    #[test]
+    fn default_monitors_calls_and_signals() {
+        let plan = args(&[]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![
+                rule(Some(MessageType::MethodCall), None, None),
+                rule(Some(MessageType::Signal), None, None),
+            ]
+        );
+        assert!(plan.warnings.is_empty());
+        assert!(plan.rules[0].contains("type='method_call'"));
+        assert!(plan.rules[0].contains("interface='org.freedesktop.Notifications'"));
+    }
+
+    #[test]
+    fn kind_and_member_flags_narrow_rules() {
+        let plan = args(&["--notify-only"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::MethodCall), Some("Notify"), None)]
+        );
+
+        let plan = args(&["--signals-only"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::Signal), None, None)]
+        );
+
+        let plan = args(&["--member", "Notify", "--member", "ActionInvoked"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![
+                rule(Some(MessageType::MethodCall), Some("Notify"), None),
+                rule(Some(MessageType::Signal), Some("ActionInvoked"), None),
+            ]
+        );
+        assert!(plan.rules[1].contains("member='ActionInvoked'"));
+    }
+
+    #[test]
+    fn conflicting_member_flags_warn_and_fall_back() {
+        let plan = args(&["--signals-only", "--member", "Notify"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::Signal), None, None)]
+        );
+        assert_eq!(plan.warnings.len(), 1);
+
+        let plan = args(&["--notify-only", "--member", "ActionInvoked"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::MethodCall), Some("Notify"), None)]
+        );
+        assert_eq!(plan.warnings.len(), 1);
+
+        assert!(
+            Args::try_parse_from(["wispd-monitor", "--notify-only", "--signals-only"]).is_err()
+        );
+    }
+
+    #[test]
+    fn sender_filters_calls_and_resolved_names() {
+        let plan = args(&["--notify-only", "--sender", ":1.234"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(
+                Some(MessageType::MethodCall),
+                Some("Notify"),
+                Some(":1.234")
+            )]
+        );
+        assert!(plan.rules[0].contains("sender=':1.234'"));
+        assert!(plan.warnings.is_empty());
+
+        let plan = args(&["--sender-name", "org.kde.plasma"]).rule_plan(Some(":1.77"));
+        assert_eq!(
+            plan.rules,
+            vec![
+                rule(Some(MessageType::MethodCall), None, Some(":1.77")),
+                rule(Some(MessageType::Signal), None, None),
+            ]
+        );
+        assert_eq!(plan.warnings.len(), 1, "signals stay unfiltered");
+
+        let plan = args(&["--signals-only", "--sender", ":1.9"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::Signal), None, Some(":1.9"))]
+        );
+    }
+
+    #[test]
+    fn unusable_senders_fall_back_to_unfiltered_rules() {
+        let plan = args(&["--notify-only", "--sender-name", "org.gone"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::MethodCall), Some("Notify"), None)]
+        );
+        assert_eq!(plan.warnings.len(), 1);
+
+        let plan = args(&["--notify-only", "--sender", "not a bus name"]).rule_plan(None);
+        assert_eq!(
+            plan.rules,
+            vec![rule(Some(MessageType::MethodCall), Some("Notify"), None)]
+        );
+        assert_eq!(plan.warnings.len(), 1);
+    }
+
+    #[test]
+    fn quiet_flag_parses() {
+        assert!(args(&["--quiet"]).quiet);
+        assert!(args(&["-q"]).quiet);
+        assert!(!args(&[]).quiet);
+    }
+}
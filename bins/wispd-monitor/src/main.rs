@@ -1,12 +1,13 @@
 use anyhow::Result;
+use clap::Parser;
 use futures_util::StreamExt;
 use tokio::signal;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
-use wisp_monitor::{
-    NotificationMessage, become_monitor, parse_notification_message, rules_all_notifications,
-};
-use zbus::MessageStream;
+use wisp_monitor::{NotificationMessage, become_monitor, parse_notification_message};
+use zbus::{MessageStream, fdo::DBusProxy, names::BusName};
+
+mod cli;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -14,11 +15,23 @@ async fn main() -> Result<()> {
         .with_env_filter(EnvFilter::from_default_env().add_directive("wispd_monitor=info".parse()?))
         .init();
 
+    let args = cli::Args::parse();
+
     let conn = zbus::Connection::session().await?;
-    become_monitor(&conn, rules_all_notifications()).await?;
+    let sender_name_owner = match &args.sender_name {
+        Some(name) => resolve_name_owner(&conn, name).await,
+        None => None,
+    };
+    let plan = args.rule_plan(sender_name_owner.as_deref());
+    for warning in &plan.warnings {
+        warn!("{warning}");
+    }
+    become_monitor(&conn, plan.rules.clone()).await?;
 
-    info!("wispd-monitor attached to session bus without owning org.freedesktop.Notifications");
-    info!("monitoring Notify/CloseNotification calls and NotificationClosed/ActionInvoked signals");
+    if !args.quiet {
+        info!("wispd-monitor attached to session bus without owning org.freedesktop.Notifications");
+        info!(rules = ?plan.rules, "monitoring notification traffic");
+    }
 
     let mut stream = MessageStream::from(&conn);
     let mut shutdown = Box::pin(signal::ctrl_c());
@@ -61,6 +74,9 @@ async fn main() -> Result<()> {
                     Ok(Some(NotificationMessage::ActionInvoked { id, action_key })) => {
                         info!(kind = "ActionInvoked", id, action_key = %action_key);
                     }
+                    Ok(Some(NotificationMessage::NotificationReplied { id, text })) => {
+                        info!(kind = "NotificationReplied", id, text = %text);
+                    }
                     Ok(None) => {}
                     Err(err) => warn!(?err, "failed to parse notifications message"),
                 }
@@ -70,3 +86,22 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Unique name currently owning `name`; monitors cannot match on well-known senders.
+async fn resolve_name_owner(conn: &zbus::Connection, name: &str) -> Option<String> {
+    let bus_name = match BusName::try_from(name) {
+        Ok(bus_name) => bus_name,
+        Err(err) => {
+            warn!(%name, %err, "invalid --sender-name");
+            return None;
+        }
+    };
+    let proxy = DBusProxy::new(conn).await.ok()?;
+    match proxy.get_name_owner(bus_name).await {
+        Ok(owner) => Some(owner.to_string()),
+        Err(err) => {
+            warn!(%name, %err, "failed to resolve --sender-name owner");
+            None
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Pure hot paths on the way to a popup: hint parsing, popup measurement and template
+//! rendering. Run with `cargo bench -p wispd --features profiling`.
+
+use std::{collections::HashMap, hint::black_box};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use wisp_types::{Notification, NotificationAction, Urgency, template};
+use zbus::zvariant::{OwnedValue, Str, Value};
+
+// The daemon is a binary, so its private helpers are reached by compiling it as a module.
+#[allow(dead_code)]
+#[path = "../src/main.rs"]
+mod wispd;
+
+fn large_hint_map() -> HashMap<String, OwnedValue> {
+    let mut hints = HashMap::new();
+    hints.insert("urgency".to_string(), OwnedValue::from(2_u8));
+    hints.insert(
+        "category".to_string(),
+        OwnedValue::from(Str::from("email.arrived")),
+    );
+    hints.insert(
+        "desktop-entry".to_string(),
+        OwnedValue::from(Str::from("org.example.Mail")),
+    );
+    hints.insert(
+        "image-data".to_string(),
+        OwnedValue::try_from(Value::from(vec![0_u8; 64 * 64 * 4])).expect("byte array hint"),
+    );
+    for i in 0..200 {
+        hints.insert(
+            format!("x-example-{i}"),
+            OwnedValue::from(Str::from(format!("value number {i}"))),
+        );
+    }
+    hints
+}
+
+fn sample_notifications() -> Vec<Notification> {
+    let long_body = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(12);
+    vec![
+        Notification {
+            app_name: "mail".into(),
+            summary: "New message".into(),
+            body: "Short body".into(),
+            ..Notification::default()
+        },
+        Notification {
+            app_name: "chat".into(),
+            summary: "A fairly long summary line that has to wrap at least once".into(),
+            body: long_body.clone(),
+            urgency: Urgency::Critical,
+            actions: vec![
+                NotificationAction {
+                    key: "reply".into(),
+                    label: "Reply".into(),
+                },
+                NotificationAction {
+                    key: "open".into(),
+                    label: "Open conversation in the browser".into(),
+                },
+            ],
+            ..Notification::default()
+        },
+        Notification {
+            app_name: "emoji".into(),
+            summary: "👍🏽 e\u{301} multi\nline".into(),
+            body: format!("{long_body}\n\n{long_body}"),
+            ..Notification::default()
+        },
+    ]
+}
+
+fn parse_hints(c: &mut Criterion) {
+    let hints = large_hint_map();
+    let mut group = c.benchmark_group("parse_hints");
+    group.bench_function("large_map", |b| {
+        b.iter(|| wisp_source::parse_raw_hints(black_box(&hints)))
+    });
+    group.finish();
+}
+
+fn estimate_popup_height(c: &mut Criterion) {
+    // A full stack of ten popups, relaid out from scratch and with everything cached.
+    let stack = sample_notifications()
+        .into_iter()
+        .cycle()
+        .take(10)
+        .collect();
+    let mut measure = wispd::bench::MeasureBench::new(stack);
+    let mut group = c.benchmark_group("estimate_popup_height");
+    group.bench_function("stack_cold", |b| b.iter(|| black_box(measure.run_cold())));
+    measure.run_warm();
+    group.bench_function("stack_warm", |b| b.iter(|| black_box(measure.run_warm())));
+    group.finish();
+}
+
+fn render_template(c: &mut Criterion) {
+    let format = "{app_name|trunc:12}: {summary}\n{body|trunc:80} [{urgency}] {missing}";
+    let body = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(4);
+    let mut group = c.benchmark_group("template");
+    group.bench_function("render", |b| {
+        b.iter(|| {
+            template::render(black_box(format), |name| match name {
+                "app_name" => Some("thunderbird-nightly".to_string()),
+                "summary" => Some("New message".to_string()),
+                "body" => Some(body.clone()),
+                "urgency" => Some("normal".to_string()),
+                _ => None,
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    hot_paths,
+    parse_hints,
+    estimate_popup_height,
+    render_template
+);
+criterion_main!(hot_paths);
@@ -0,0 +1,141 @@
+//! Keyboard accelerators for popup actions. GTK-style labels mark a mnemonic with an
+//! underscore ("_Open"); the underscore is dropped from the label and the marked
+//! character triggers the action. Every action can also be triggered by its position,
+//! 1 to 9. Both only apply in keyboard mode (`org.wispd.Ui1.SetKeyboardMode`), which
+//! gives the front popup the keyboard and shows each action's accelerator.
+
+use super::UiAction;
+
+/// The key an action shows and answers to in keyboard mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Accelerator {
+    Mnemonic(char),
+    Number(u32),
+}
+
+/// `label` without its mnemonic marker, and the marked character. `__` is a literal
+/// underscore; only the first marker counts.
+pub(crate) fn parse_mnemonic(label: &str) -> (String, Option<char>) {
+    let mut clean = String::with_capacity(label.len());
+    let mut mnemonic = None;
+    let mut chars = label.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '_' {
+            clean.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some('_') => {
+                chars.next();
+                clean.push('_');
+            }
+            Some(next) if mnemonic.is_none() && !next.is_whitespace() => mnemonic = Some(next),
+            Some(_) => {}
+            None => clean.push('_'),
+        }
+    }
+    (clean, mnemonic)
+}
+
+/// What `action`, at `index` among its notification's actions, shows in keyboard mode.
+pub(crate) fn accelerator(action: &UiAction, index: usize) -> Option<Accelerator> {
+    action
+        .mnemonic
+        .map(Accelerator::Mnemonic)
+        .or_else(|| position_key(index).map(Accelerator::Number))
+}
+
+/// The action `key` triggers: a mnemonic match first, then the action at that position.
+pub(crate) fn action_for_key(actions: &[UiAction], key: char) -> Option<&UiAction> {
+    actions
+        .iter()
+        .find(|action| action.mnemonic.is_some_and(|m| same_key(m, key)))
+        .or_else(|| {
+            let position = key.to_digit(10).filter(|digit| *digit > 0)?;
+            actions.get(position as usize - 1)
+        })
+}
+
+/// Where `mnemonic` falls in `label`, as a byte range, for underlining it.
+pub(crate) fn mnemonic_span(label: &str, mnemonic: char) -> Option<(usize, usize)> {
+    label
+        .char_indices()
+        .find(|(_, c)| same_key(*c, mnemonic))
+        .map(|(start, c)| (start, start + c.len_utf8()))
+}
+
+fn position_key(index: usize) -> Option<u32> {
+    u32::try_from(index + 1).ok().filter(|key| *key <= 9)
+}
+
+fn same_key(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(key: &str, label: &str) -> UiAction {
+        let (label, mnemonic) = parse_mnemonic(label);
+        UiAction {
+            key: key.to_string(),
+            label,
+            mnemonic,
+        }
+    }
+
+    #[test]
+    fn mnemonic_markers_are_extracted_and_cleaned() {
+        assert_eq!(parse_mnemonic("_Open"), ("Open".to_string(), Some('O')));
+        assert_eq!(
+            parse_mnemonic("Sa_ve As"),
+            ("Save As".to_string(), Some('v'))
+        );
+        assert_eq!(parse_mnemonic("Open"), ("Open".to_string(), None));
+        assert_eq!(
+            parse_mnemonic("snake__case"),
+            ("snake_case".to_string(), None)
+        );
+        assert_eq!(
+            parse_mnemonic("_Reply _All"),
+            ("Reply All".to_string(), Some('R')),
+            "only the first marker counts"
+        );
+        assert_eq!(parse_mnemonic("Trailing_"), ("Trailing_".to_string(), None));
+        assert_eq!(parse_mnemonic("a_ b"), ("a b".to_string(), None));
+        assert_eq!(mnemonic_span("Save As", 'v'), Some((2, 3)));
+        assert_eq!(mnemonic_span("Öffnen", 'ö'), Some((0, 2)));
+        assert_eq!(mnemonic_span("Save", 'x'), None);
+    }
+
+    #[test]
+    fn mnemonics_beat_positions_when_dispatching_keys() {
+        let actions = [
+            action("open", "_Open"),
+            action("one-file", "_1 file"),
+            action("archive", "Archive"),
+        ];
+        let key = |c| action_for_key(&actions, c).map(|a| a.key.as_str());
+
+        assert_eq!(key('o'), Some("open"));
+        assert_eq!(key('O'), Some("open"));
+        assert_eq!(
+            key('1'),
+            Some("one-file"),
+            "a mnemonic wins over position 1"
+        );
+        assert_eq!(key('2'), Some("one-file"));
+        assert_eq!(key('3'), Some("archive"));
+        assert_eq!(key('4'), None);
+        assert_eq!(key('0'), None);
+        assert_eq!(key('x'), None);
+
+        assert_eq!(
+            accelerator(&actions[0], 0),
+            Some(Accelerator::Mnemonic('O'))
+        );
+        assert_eq!(accelerator(&actions[2], 2), Some(Accelerator::Number(3)));
+        assert_eq!(accelerator(&actions[2], 9), None, "only nine positions");
+    }
+}
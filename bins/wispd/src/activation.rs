@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use wisp_source::DEFAULT_DBUS_NAME;
+use wisp_types::systemd::quote_exec_arg;
+
+/// Unit name the D-Bus service file hands activation to.
+const SYSTEMD_UNIT_NAME: &str = "wispd.service";
+
+/// Which file `--print-service-file` renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum ServiceFile {
+    /// `org.freedesktop.Notifications.service` for `$XDG_DATA_HOME/dbus-1/services/`.
+    #[default]
+    Dbus,
+    /// `wispd.service` for `~/.config/systemd/user/`.
+    Systemd,
+}
+
+impl ServiceFile {
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "dbus" => Some(Self::Dbus),
+            "systemd" => Some(Self::Systemd),
+            _ => None,
+        }
+    }
+
+    /// File contents that start `exe` in `--activated` mode.
+    pub(crate) fn render(self, exe: &Path) -> String {
+        let exec = format!("{} --activated", quote_exec_arg(&exe.to_string_lossy()));
+        match self {
+            Self::Dbus => format!(
+                "[D-BUS Service]\nName={DEFAULT_DBUS_NAME}\nExec={exec}\nSystemdService={SYSTEMD_UNIT_NAME}\n"
+            ),
+            Self::Systemd => format!(
+                "[Unit]\nDescription=wispd notification daemon\nPartOf=graphical-session.target\nAfter=graphical-session.target\n\n[Service]\nType=dbus\nBusName={DEFAULT_DBUS_NAME}\nExecStart={exec}\nRestart=on-failure\n"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbus_service_file_activates_this_binary() {
+        assert_eq!(
+            ServiceFile::Dbus.render(Path::new("/usr/bin/wispd")),
+            "[D-BUS Service]\n\
+             Name=org.freedesktop.Notifications\n\
+             Exec=/usr/bin/wispd --activated\n\
+             SystemdService=wispd.service\n"
+        );
+    }
+
+    #[test]
+    fn systemd_unit_declares_the_bus_name() {
+        let unit = ServiceFile::Systemd.render(Path::new("/opt/wisp d/wispd"));
+        assert!(unit.contains("\nType=dbus\n"));
+        assert!(unit.contains("\nBusName=org.freedesktop.Notifications\n"));
+        assert!(unit.contains("\nExecStart=\"/opt/wisp d/wispd\" --activated\n"));
+    }
+
+    #[test]
+    fn service_file_kinds_parse() {
+        assert_eq!(ServiceFile::parse("dbus"), Some(ServiceFile::Dbus));
+        assert_eq!(ServiceFile::parse("systemd"), Some(ServiceFile::Systemd));
+        assert_eq!(ServiceFile::parse("launchd"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+
+use iced::Color;
+use serde::{Deserialize, Serialize};
+
+/// Layer-surface margin as `(top, right, bottom, left)`, matching `MarginChange`.
+pub(crate) type Margin = (i32, i32, i32, i32);
+
+/// Frame interval used while any animation is running.
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum AnimationKind {
+    #[default]
+    Fade,
+    /// Fades while sliding in from (and out to) the anchored horizontal edge.
+    Slide,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct AnimationSection {
+    pub(crate) enabled: bool,
+    pub(crate) duration_ms: u64,
+    pub(crate) kind: AnimationKind,
+}
+
+impl Default for AnimationSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duration_ms: 180,
+            kind: AnimationKind::Fade,
+        }
+    }
+}
+
+impl AnimationSection {
+    pub(crate) fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// Linear progress of an animation started at `started`, clamped to `0.0..=1.0`.
+pub(crate) fn progress(started: Instant, now: Instant, duration: Duration) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    let elapsed = now.saturating_duration_since(started);
+    (elapsed.as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0)
+}
+
+/// Cubic ease-out: fast start, gentle settle.
+pub(crate) fn ease_out(t: f32) -> f32 {
+    let inv = 1.0 - t.clamp(0.0, 1.0);
+    1.0 - inv * inv * inv
+}
+
+fn lerp(from: i32, to: i32, t: f32) -> i32 {
+    from + ((to - from) as f32 * t).round() as i32
+}
+
+pub(crate) fn lerp_margin(from: Margin, to: Margin, t: f32) -> Margin {
+    (
+        lerp(from.0, to.0, t),
+        lerp(from.1, to.1, t),
+        lerp(from.2, to.2, t),
+        lerp(from.3, to.3, t),
+    )
+}
+
+/// How much of a popup is "present": 0.0 fully hidden, 1.0 fully shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Presence(pub(crate) f32);
+
+impl Presence {
+    pub(crate) const SHOWN: Self = Self(1.0);
+
+    pub(crate) fn entering(started: Instant, now: Instant, duration: Duration) -> Self {
+        Self(ease_out(progress(started, now, duration)))
+    }
+
+    pub(crate) fn leaving(started: Instant, now: Instant, duration: Duration) -> Self {
+        Self(1.0 - ease_out(progress(started, now, duration)))
+    }
+
+    pub(crate) fn opacity(self) -> f32 {
+        self.0.clamp(0.0, 1.0)
+    }
+
+    /// Pushes `margin` off the anchored horizontal edge by the hidden share of `width`.
+    /// Popups centered horizontally only fade.
+    pub(crate) fn slide_margin(
+        self,
+        kind: AnimationKind,
+        margin: Margin,
+        width: u32,
+        anchored_right: bool,
+        anchored_left: bool,
+    ) -> Margin {
+        if kind != AnimationKind::Slide || anchored_right == anchored_left {
+            return margin;
+        }
+        let offset = ((1.0 - self.opacity()) * width as f32).round() as i32;
+        let (top, right, bottom, left) = margin;
+        if anchored_right {
+            (top, right - offset, bottom, left)
+        } else {
+            (top, right, bottom, left - offset)
+        }
+    }
+}
+
+/// In-flight move of a popup between two stack slots.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MarginTween {
+    pub(crate) from: Margin,
+    pub(crate) to: Margin,
+    pub(crate) started: Instant,
+}
+
+impl MarginTween {
+    pub(crate) fn at(&self, now: Instant, duration: Duration) -> Margin {
+        lerp_margin(
+            self.from,
+            self.to,
+            ease_out(progress(self.started, now, duration)),
+        )
+    }
+
+    pub(crate) fn is_done(&self, now: Instant, duration: Duration) -> bool {
+        progress(self.started, now, duration) >= 1.0
+    }
+}
+
+pub(crate) fn fade_color(color: Color, opacity: f32) -> Color {
+    Color {
+        a: color.a * opacity.clamp(0.0, 1.0),
+        ..color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATION: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn progress_is_clamped_and_handles_zero_duration() {
+        let start = Instant::now();
+        assert_eq!(progress(start, start, DURATION), 0.0);
+        assert_eq!(progress(start, start + DURATION / 2, DURATION), 0.5);
+        assert_eq!(progress(start, start + DURATION * 3, DURATION), 1.0);
+        assert_eq!(progress(start + DURATION, start, DURATION), 0.0);
+        assert_eq!(progress(start, start, Duration::ZERO), 1.0);
+    }
+
+    #[test]
+    fn ease_out_is_monotonic_with_fixed_endpoints() {
+        assert_eq!(ease_out(0.0), 0.0);
+        assert_eq!(ease_out(1.0), 1.0);
+        let mut previous = 0.0;
+        for step in 1..=10 {
+            let value = ease_out(step as f32 / 10.0);
+            assert!(value > previous);
+            previous = value;
+        }
+        assert!(ease_out(0.5) > 0.5, "ease-out front-loads motion");
+    }
+
+    #[test]
+    fn lerp_margin_interpolates_each_side() {
+        let from = (16, 16, 0, 0);
+        let to = (116, 16, -20, 40);
+        assert_eq!(lerp_margin(from, to, 0.0), from);
+        assert_eq!(lerp_margin(from, to, 1.0), to);
+        assert_eq!(lerp_margin(from, to, 0.5), (66, 16, -10, 20));
+    }
+
+    #[test]
+    fn margin_tween_reaches_target_after_duration() {
+        let start = Instant::now();
+        let tween = MarginTween {
+            from: (16, 16, 16, 16),
+            to: (96, 16, 16, 16),
+            started: start,
+        };
+        assert_eq!(tween.at(start, DURATION), tween.from);
+        assert!(!tween.is_done(start + DURATION / 2, DURATION));
+        assert_eq!(tween.at(start + DURATION, DURATION), tween.to);
+        assert!(tween.is_done(start + DURATION, DURATION));
+    }
+
+    #[test]
+    fn presence_runs_opposite_directions_for_entry_and_exit() {
+        let start = Instant::now();
+        assert_eq!(Presence::entering(start, start, DURATION).opacity(), 0.0);
+        assert_eq!(
+            Presence::entering(start, start + DURATION, DURATION).opacity(),
+            1.0
+        );
+        assert_eq!(Presence::leaving(start, start, DURATION).opacity(), 1.0);
+        assert_eq!(
+            Presence::leaving(start, start + DURATION, DURATION).opacity(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn slide_pushes_margin_off_the_anchored_edge() {
+        let margin = (16, 16, 16, 16);
+        let hidden = Presence(0.0);
+        let half = Presence(0.5);
+
+        assert_eq!(
+            hidden.slide_margin(AnimationKind::Slide, margin, 400, true, false),
+            (16, -384, 16, 16)
+        );
+        assert_eq!(
+            half.slide_margin(AnimationKind::Slide, margin, 400, false, true),
+            (16, 16, 16, -184)
+        );
+        assert_eq!(
+            Presence::SHOWN.slide_margin(AnimationKind::Slide, margin, 400, true, false),
+            margin
+        );
+        assert_eq!(
+            hidden.slide_margin(AnimationKind::Fade, margin, 400, true, false),
+            margin
+        );
+        assert_eq!(
+            hidden.slide_margin(AnimationKind::Slide, margin, 400, true, true),
+            margin,
+            "horizontally centered popups only fade"
+        );
+    }
+
+    #[test]
+    fn fade_color_scales_alpha_only() {
+        let color = Color::from_rgba(0.2, 0.4, 0.6, 0.8);
+        let faded = fade_color(color, 0.5);
+        assert_eq!((faded.r, faded.g, faded.b), (0.2, 0.4, 0.6));
+        assert!((faded.a - 0.4).abs() < f32::EPSILON);
+    }
+}
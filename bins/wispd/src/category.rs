@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::icons;
+
+/// Built-in accents keyed by category prefix; `[ui.category_icons]` entries take precedence.
+const DEFAULT_CATEGORY_ICONS: &[(&str, &str)] = &[
+    ("email", "✉"),
+    ("im", "💬"),
+    ("device", "🔌"),
+    ("call", "📞"),
+];
+
+/// `[ui.category_icons]`: `enabled` plus `prefix = "glyph"` entries. A value may also be an
+/// icon-theme name or image path; an empty value turns a prefix off.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct CategoryIconsSection {
+    pub(crate) enabled: bool,
+    #[serde(flatten)]
+    pub(crate) icons: BTreeMap<String, String>,
+}
+
+impl Default for CategoryIconsSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            icons: BTreeMap::new(),
+        }
+    }
+}
+
+/// What a popup draws before the app name.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CategoryIcon {
+    Glyph(String),
+    Image(PathBuf),
+}
+
+impl CategoryIcon {
+    /// Horizontal space taken at `size_px`, excluding row spacing.
+    pub(crate) fn width_px(&self, size_px: f32) -> f32 {
+        match self {
+            // Accent glyphs and emoji render close to one em each.
+            Self::Glyph(glyph) => glyph.graphemes(true).count() as f32 * size_px,
+            Self::Image(_) => size_px,
+        }
+    }
+}
+
+impl CategoryIconsSection {
+    /// Configured value for `category`: the longest matching prefix wins, where `email`
+    /// matches `email` and `email.arrived` but not `emailer`.
+    pub(crate) fn lookup(&self, category: &str) -> Option<&str> {
+        if !self.enabled {
+            return None;
+        }
+        let defaults = DEFAULT_CATEGORY_ICONS
+            .iter()
+            .filter(|(key, _)| !self.icons.contains_key(*key))
+            .map(|(key, value)| (*key, *value));
+        let configured = self
+            .icons
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()));
+        defaults
+            .chain(configured)
+            .filter(|(key, _)| prefix_matches(key, category))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Accent for `category` at `size_px`; theme names that do not resolve draw nothing.
+    pub(crate) fn icon_for(&self, category: Option<&str>, size_px: u32) -> Option<CategoryIcon> {
+        let value = self.lookup(category?)?;
+        if let Some(path) = value
+            .strip_prefix("file://")
+            .or(value.starts_with('/').then_some(value))
+        {
+            let path = PathBuf::from(path);
+            return path.is_file().then_some(CategoryIcon::Image(path));
+        }
+        if looks_like_icon_name(value) {
+            return icons::resolve_icon_name(value, size_px).map(CategoryIcon::Image);
+        }
+        Some(CategoryIcon::Glyph(value.to_string()))
+    }
+}
+
+fn prefix_matches(prefix: &str, category: &str) -> bool {
+    category
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// `mail-unread-symbolic` and similar; single characters and non-ASCII text are glyphs.
+fn looks_like_icon_name(value: &str) -> bool {
+    value.len() > 1
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn section(toml_src: &str) -> CategoryIconsSection {
+        toml::from_str(toml_src).expect("category icons should parse")
+    }
+
+    #[test]
+    fn defaults_match_whole_prefix_segments() {
+        let icons = CategoryIconsSection::default();
+        assert_eq!(icons.lookup("email"), Some("✉"));
+        assert_eq!(icons.lookup("email.arrived"), Some("✉"));
+        assert_eq!(icons.lookup("im.received"), Some("💬"));
+        assert_eq!(icons.lookup("device.added"), Some("🔌"));
+        assert_eq!(icons.lookup("call.incoming"), Some("📞"));
+        assert_eq!(icons.lookup("emailer"), None);
+        assert_eq!(icons.lookup("image.new"), None);
+        assert_eq!(icons.lookup("presence.online"), None);
+    }
+
+    #[test]
+    fn config_overrides_extends_and_disables_prefixes() {
+        let icons = section(
+            r#"
+email = "@"
+"email.bounced" = "!"
+presence = "●"
+call = ""
+"#,
+        );
+        assert_eq!(icons.lookup("email.arrived"), Some("@"));
+        assert_eq!(icons.lookup("email.bounced"), Some("!"), "longest prefix");
+        assert_eq!(icons.lookup("presence.offline"), Some("●"));
+        assert_eq!(icons.lookup("im.received"), Some("💬"), "defaults kept");
+        assert_eq!(icons.lookup("call.incoming"), None);
+
+        let off = section("enabled = false\nemail = \"@\"");
+        assert_eq!(off.lookup("email.arrived"), None);
+        assert_eq!(off.icon_for(Some("im"), 16), None);
+    }
+
+    #[test]
+    fn values_become_glyphs_or_images() {
+        let image = temp_dir("category-icon").join("device.png");
+        std::fs::write(&image, b"png").unwrap();
+
+        let mut icons = CategoryIconsSection::default();
+        icons
+            .icons
+            .insert("device".into(), image.to_string_lossy().into_owned());
+        icons.icons.insert(
+            "transfer".into(),
+            format!("file://{}", image.to_string_lossy()),
+        );
+        icons
+            .icons
+            .insert("network".into(), "wispd-no-such-icon-symbolic".into());
+
+        assert_eq!(
+            icons.icon_for(Some("email.arrived"), 16),
+            Some(CategoryIcon::Glyph("✉".into()))
+        );
+        assert_eq!(
+            icons.icon_for(Some("device.added"), 16),
+            Some(CategoryIcon::Image(image.clone()))
+        );
+        assert_eq!(
+            icons.icon_for(Some("transfer.complete"), 16),
+            Some(CategoryIcon::Image(image.clone()))
+        );
+        assert_eq!(icons.icon_for(Some("network.connected"), 16), None);
+        assert_eq!(icons.icon_for(None, 16), None);
+        let _ = std::fs::remove_file(image);
+    }
+
+    #[test]
+    fn accent_width_scales_with_size() {
+        assert_eq!(CategoryIcon::Glyph("✉".into()).width_px(15.0), 15.0);
+        assert_eq!(CategoryIcon::Glyph("💬!".into()).width_px(10.0), 20.0);
+        assert_eq!(CategoryIcon::Image(PathBuf::new()).width_px(16.0), 16.0);
+    }
+}
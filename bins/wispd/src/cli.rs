@@ -0,0 +1,318 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result, bail};
+
+use super::{
+    activation::ServiceFile,
+    logging::LogOverrides,
+    overrides::{self, Override},
+    startup::{ACTIVATED_STARTUP_TIMEOUT, DEFAULT_STARTUP_TIMEOUT},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CliArgs {
+    pub(crate) log: LogOverrides,
+    /// Print a service file for this binary and exit.
+    pub(crate) print_service_file: Option<ServiceFile>,
+    /// Check the state files and exit.
+    pub(crate) repair_state: Option<RepairMode>,
+    /// Validate the config, print how its fonts resolve and exit.
+    pub(crate) check_config: bool,
+    /// Started by D-Bus activation; a `Notify` is already waiting for the name.
+    pub(crate) activated: bool,
+    /// Follow the daemon that owns the bus name instead of replacing it.
+    pub(crate) mirror: bool,
+    /// Run the source and sinks only, without a UI.
+    pub(crate) headless: bool,
+    /// Post webhook events again even when the delivery log has them.
+    pub(crate) resend_all: bool,
+    pub(crate) backend: Backend,
+    /// Overall deadline for the source to own the bus name.
+    pub(crate) startup_timeout: Option<Duration>,
+    /// `--set` config overrides, in order.
+    pub(crate) set: Vec<Override>,
+}
+
+impl CliArgs {
+    /// `--startup-timeout`, else the default for how wispd was started.
+    pub(crate) fn startup_timeout(&self) -> Duration {
+        self.startup_timeout.unwrap_or(if self.activated {
+            ACTIVATED_STARTUP_TIMEOUT
+        } else {
+            DEFAULT_STARTUP_TIMEOUT
+        })
+    }
+}
+
+/// What `--repair-state` does with broken state files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum RepairMode {
+    /// Report them only.
+    #[default]
+    Check,
+    /// Move them aside to `*.broken` so the next start begins afresh.
+    Quarantine,
+}
+
+impl RepairMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "check" => Some(Self::Check),
+            "quarantine" => Some(Self::Quarantine),
+            _ => None,
+        }
+    }
+}
+
+/// Display protocol the popups are presented on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Backend {
+    /// Wayland, falling back to X11 when only `DISPLAY` is set and X11 support is built in.
+    #[default]
+    Auto,
+    Wayland,
+    X11,
+}
+
+impl Backend {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "wayland" => Some(Self::Wayland),
+            "x11" => Some(Self::X11),
+            _ => None,
+        }
+    }
+
+    /// Settles `Auto` from the session's display variables.
+    pub(crate) fn resolve(self, wayland_display: bool, x11_display: bool) -> Result<Self> {
+        match self {
+            Self::Auto if !wayland_display && x11_display && cfg!(feature = "x11") => Ok(Self::X11),
+            Self::Auto => Ok(Self::Wayland),
+            Self::X11 if !cfg!(feature = "x11") => {
+                bail!("--backend x11 needs wispd built with the `x11` feature")
+            }
+            backend => Ok(backend),
+        }
+    }
+}
+
+pub(crate) fn parse_args<I>(args: I) -> Result<CliArgs>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut cli = CliArgs::default();
+    let mut args = args.into_iter().peekable();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-level" => {
+                let value = args.next().context("missing value for --log-level")?;
+                cli.log.level = Some(value);
+            }
+            "--log-file" => {
+                let value = args.next().context("missing value for --log-file")?;
+                cli.log.file = Some(PathBuf::from(value));
+            }
+            "--print-service-file" => {
+                let kind = args.peek().and_then(|value| ServiceFile::parse(value));
+                if kind.is_some() {
+                    args.next();
+                }
+                cli.print_service_file = Some(kind.unwrap_or_default());
+            }
+            "--repair-state" => {
+                let mode = args.peek().and_then(|value| RepairMode::parse(value));
+                if mode.is_some() {
+                    args.next();
+                }
+                cli.repair_state = Some(mode.unwrap_or_default());
+            }
+            "--check-config" => cli.check_config = true,
+            "--activated" => cli.activated = true,
+            "--mirror" => cli.mirror = true,
+            "--headless" => cli.headless = true,
+            "--resend-all" => cli.resend_all = true,
+            "--backend" => {
+                let value = args.next().context("missing value for --backend")?;
+                cli.backend = Backend::parse(&value)
+                    .with_context(|| format!("unknown backend: {value} (auto, wayland, x11)"))?;
+            }
+            "--startup-timeout" => {
+                let value = args.next().context("missing value for --startup-timeout")?;
+                let secs: f64 = value
+                    .parse()
+                    .ok()
+                    .filter(|secs: &f64| secs.is_finite() && *secs > 0.0)
+                    .with_context(|| format!("invalid --startup-timeout: {value} (seconds)"))?;
+                cli.startup_timeout = Some(Duration::from_secs_f64(secs));
+            }
+            "--set" => {
+                let value = args.next().context("missing value for --set")?;
+                cli.set.push(overrides::parse_set(&value)?);
+            }
+            "-h" | "--help" => {
+                print_help();
+                std::process::exit(0);
+            }
+            other => bail!("unknown argument: {other}"),
+        }
+    }
+    if cli.headless && cli.mirror {
+        bail!("--headless owns the bus name and cannot be combined with --mirror");
+    }
+
+    Ok(cli)
+}
+
+fn print_help() {
+    println!(
+        "wispd\n\nUSAGE:\n  wispd [OPTIONS]\n\nOPTIONS:\n      --log-level FILTER  Override log.level (tracing filter directives, e.g. debug)\n      --log-file PATH     Override log.file\n      --activated         Started by D-Bus activation (waits longer for the bus name)\n      --startup-timeout SECS\n                          Give up when the bus name is not owned after SECS (default 10, 20 with --activated)\n      --mirror            Show another daemon's notifications read-only instead of owning the bus name\n      --headless          Serve D-Bus and run the sinks without any UI; SIGTERM/SIGINT shut down cleanly\n      --resend-all        Post webhook events again that the delivery log says were delivered\n      --backend auto|wayland|x11\n                          Popup display backend (auto: X11 only without a Wayland display)\n      --print-service-file [dbus|systemd]\n                          Print a D-Bus service file (default) or systemd unit for this binary\n      --repair-state [check|quarantine]\n                          Validate the state files and report problems; quarantine moves broken ones to *.broken\n      --check-config      Validate the config and print how its font families resolve\n      --set PATH=VALUE    Override a config key, e.g. ui.width=300 (repeatable; wins over WISPD_<SECTION>_<KEY>)\n  -h, --help              Show this help\n"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_defaults_to_no_overrides() {
+        assert_eq!(parse_args(args(&[])).expect("parse"), CliArgs::default());
+    }
+
+    #[test]
+    fn parse_args_reads_log_overrides() {
+        let cli =
+            parse_args(args(&["--log-level", "debug", "--log-file", "/tmp/w.log"])).expect("parse");
+        assert_eq!(cli.log.level.as_deref(), Some("debug"));
+        assert_eq!(cli.log.file, Some(PathBuf::from("/tmp/w.log")));
+    }
+
+    #[test]
+    fn parse_args_reads_activation_flags() {
+        let cli = parse_args(args(&["--print-service-file"])).expect("parse");
+        assert_eq!(cli.print_service_file, Some(ServiceFile::Dbus));
+
+        let cli =
+            parse_args(args(&["--print-service-file", "systemd", "--activated"])).expect("parse");
+        assert_eq!(cli.print_service_file, Some(ServiceFile::Systemd));
+        assert!(cli.activated);
+
+        assert!(parse_args(args(&["--print-service-file", "launchd"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_resend_all() {
+        assert!(!parse_args(args(&[])).expect("parse").resend_all);
+        assert!(
+            parse_args(args(&["--headless", "--resend-all"]))
+                .expect("parse")
+                .resend_all
+        );
+    }
+
+    #[test]
+    fn parse_args_reads_check_config() {
+        assert!(!parse_args(args(&[])).expect("parse").check_config);
+        assert!(
+            parse_args(args(&["--check-config"]))
+                .expect("parse")
+                .check_config
+        );
+    }
+
+    #[test]
+    fn parse_args_reads_repair_state() {
+        let cli = parse_args(args(&["--repair-state"])).expect("parse");
+        assert_eq!(cli.repair_state, Some(RepairMode::Check));
+        let cli = parse_args(args(&["--repair-state", "quarantine"])).expect("parse");
+        assert_eq!(cli.repair_state, Some(RepairMode::Quarantine));
+        assert!(parse_args(args(&["--repair-state", "delete"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_startup_timeout() {
+        assert_eq!(
+            parse_args(args(&[])).expect("parse").startup_timeout(),
+            DEFAULT_STARTUP_TIMEOUT
+        );
+        assert_eq!(
+            parse_args(args(&["--activated"]))
+                .expect("parse")
+                .startup_timeout(),
+            ACTIVATED_STARTUP_TIMEOUT
+        );
+        let cli = parse_args(args(&["--activated", "--startup-timeout", "2.5"])).expect("parse");
+        assert_eq!(cli.startup_timeout(), Duration::from_millis(2500));
+
+        assert!(parse_args(args(&["--startup-timeout"])).is_err());
+        assert!(parse_args(args(&["--startup-timeout", "0"])).is_err());
+        assert!(parse_args(args(&["--startup-timeout", "soon"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_config_overrides() {
+        let cli = parse_args(args(&["--set", "ui.width=300", "--set", "log.level=debug"]))
+            .expect("parse");
+        let paths: Vec<String> = cli.set.iter().map(|o| o.path.join(".")).collect();
+        assert_eq!(paths, ["ui.width", "log.level"]);
+        assert_eq!(cli.set[0].raw, "300");
+        assert!(parse_args(args(&["--set"])).is_err());
+        assert!(parse_args(args(&["--set", "ui.width"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_mirror_flag() {
+        assert!(parse_args(args(&["--mirror"])).expect("parse").mirror);
+        assert!(!parse_args(args(&[])).expect("parse").mirror);
+    }
+
+    #[test]
+    fn parse_args_reads_headless_flag() {
+        assert!(parse_args(args(&["--headless"])).expect("parse").headless);
+        assert!(!parse_args(args(&[])).expect("parse").headless);
+        assert!(parse_args(args(&["--headless", "--mirror"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_backend() {
+        assert_eq!(parse_args(args(&[])).expect("parse").backend, Backend::Auto);
+        let cli = parse_args(args(&["--backend", "x11"])).expect("parse");
+        assert_eq!(cli.backend, Backend::X11);
+        assert!(parse_args(args(&["--backend", "xcb"])).is_err());
+        assert!(parse_args(args(&["--backend"])).is_err());
+    }
+
+    #[test]
+    fn auto_backend_prefers_wayland() {
+        assert_eq!(Backend::Auto.resolve(true, true).unwrap(), Backend::Wayland);
+        assert_eq!(
+            Backend::Auto.resolve(false, false).unwrap(),
+            Backend::Wayland
+        );
+        assert_eq!(
+            Backend::Wayland.resolve(false, true).unwrap(),
+            Backend::Wayland
+        );
+
+        let x11_only = Backend::Auto.resolve(false, true).unwrap();
+        let forced = Backend::X11.resolve(true, false);
+        if cfg!(feature = "x11") {
+            assert_eq!(x11_only, Backend::X11);
+            assert_eq!(forced.unwrap(), Backend::X11);
+        } else {
+            assert_eq!(x11_only, Backend::Wayland);
+            assert!(forced.is_err());
+        }
+    }
+
+    #[test]
+    fn parse_args_rejects_unknown_and_missing_values() {
+        assert!(parse_args(args(&["--verbose"])).is_err());
+        assert!(parse_args(args(&["--log-level"])).is_err());
+    }
+}
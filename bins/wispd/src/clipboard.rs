@@ -0,0 +1,146 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use iced::Task;
+use tracing::{debug, warn};
+
+/// Text a "copy" on a popup places on the clipboard: the body, or the summary when the
+/// body is empty, with markup removed.
+pub(crate) fn clipboard_text(summary: &str, body: &str) -> Option<String> {
+    [body, summary]
+        .into_iter()
+        .map(strip_markup)
+        .find(|text| !text.trim().is_empty())
+        .map(|text| text.trim().to_string())
+}
+
+/// Drops markup tags and decodes the entities allowed by the notification spec's body
+/// markup.
+pub(crate) fn strip_markup(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find(['<', '&']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with('<') {
+            match rest.find('>') {
+                Some(end) => rest = &rest[end + 1..],
+                None => {
+                    out.push_str(rest);
+                    rest = "";
+                }
+            }
+            continue;
+        }
+
+        let entity = [
+            ("&amp;", '&'),
+            ("&lt;", '<'),
+            ("&gt;", '>'),
+            ("&quot;", '"'),
+            ("&apos;", '\''),
+        ]
+        .into_iter()
+        .find(|(name, _)| rest.starts_with(name));
+        match entity {
+            Some((name, decoded)) => {
+                out.push(decoded);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Copies `text` with `command` (e.g. `wl-copy`, fed on stdin) and falls back to the
+/// toolkit clipboard when no command is configured or it fails.
+pub(crate) fn copy<T>(command: Option<&str>, text: String) -> Task<T>
+where
+    T: Send + 'static,
+{
+    if let Some(command) = command.map(str::trim).filter(|c| !c.is_empty()) {
+        match run_clipboard_command(command, &text) {
+            Ok(()) => {
+                debug!(command = %command, chars = text.chars().count(), "copied popup text");
+                return Task::none();
+            }
+            Err(err) => {
+                warn!(command = %command, %err, "clipboard command failed; using toolkit clipboard");
+            }
+        }
+    }
+    iced::clipboard::write(text)
+}
+
+fn run_clipboard_command(command: &str, text: &str) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("exited with {status}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    #[test]
+    fn clipboard_text_prefers_body_then_summary() {
+        assert_eq!(
+            clipboard_text("Sign-in code", "Your code is 123456").as_deref(),
+            Some("Your code is 123456")
+        );
+        assert_eq!(
+            clipboard_text("Build failed", "  ").as_deref(),
+            Some("Build failed")
+        );
+        assert_eq!(
+            clipboard_text("Build failed", "<b></b>").as_deref(),
+            Some("Build failed")
+        );
+        assert_eq!(clipboard_text("", ""), None);
+    }
+
+    #[test]
+    fn strip_markup_removes_tags_and_decodes_entities() {
+        assert_eq!(
+            strip_markup("<b>error:</b> <i>a &lt; b</i> &amp;&amp; c"),
+            "error: a < b && c"
+        );
+        assert_eq!(strip_markup("<a href=\"https://x\">link</a>"), "link");
+        assert_eq!(strip_markup("AT&T 5 > 3"), "AT&T 5 > 3");
+        assert_eq!(strip_markup("unterminated <tag"), "unterminated <tag");
+    }
+
+    #[test]
+    fn clipboard_command_receives_text_on_stdin() {
+        let path = temp_dir("clipboard").join("copied");
+
+        run_clipboard_command(&format!("cat > '{}'", path.display()), "code 987654")
+            .expect("command runs");
+        assert_eq!(fs::read_to_string(&path).expect("copied"), "code 987654");
+
+        assert!(run_clipboard_command("exit 3", "x").is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,230 @@
+//! Confirmation for destructive actions. Invoking an action matched by
+//! `[ui.confirm_actions]` turns the popup's button row into "Really delete? ✓ / ✗"; only
+//! ✓ sends the action. The prompt goes back to normal after `timeout_ms`, and replacing
+//! the notification cancels it.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use wisp_types::matcher::Pattern;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct ConfirmActionsSection {
+    /// Actions that ask first.
+    pub(crate) actions: Vec<ConfirmRule>,
+    /// How long the prompt waits for an answer.
+    pub(crate) timeout_ms: u64,
+}
+
+impl Default for ConfirmActionsSection {
+    fn default() -> Self {
+        Self {
+            actions: Vec::new(),
+            timeout_ms: 4_000,
+        }
+    }
+}
+
+impl ConfirmActionsSection {
+    /// Whether invoking `key` (labelled `label`) on a notification from `app_name` asks
+    /// first.
+    pub(crate) fn requires_confirmation(&self, app_name: &str, key: &str, label: &str) -> bool {
+        self.actions
+            .iter()
+            .any(|rule| rule.matches(app_name, key, label))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// One confirmation rule. In config either a bare pattern matched against action keys
+/// and labels (`"delete*"`), or a table scoping it to apps
+/// (`{ action = "Archive", app = "Thunderbird" }`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "RuleSpec")]
+pub(crate) struct ConfirmRule {
+    pub(crate) action: Pattern,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) app: Option<Pattern>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RuleSpec {
+    Action(String),
+    Rule { action: String, app: Option<String> },
+}
+
+impl TryFrom<RuleSpec> for ConfirmRule {
+    type Error = String;
+
+    fn try_from(spec: RuleSpec) -> Result<Self, Self::Error> {
+        let (action, app) = match spec {
+            RuleSpec::Action(action) => (action, None),
+            RuleSpec::Rule { action, app } => (action, app),
+        };
+        let parse = |raw: &str| {
+            raw.parse::<Pattern>()
+                .map_err(|err| format!("confirm rule {raw:?}: {err}"))
+        };
+        Ok(Self {
+            action: parse(&action)?,
+            app: app.as_deref().map(parse).transpose()?,
+        })
+    }
+}
+
+impl ConfirmRule {
+    fn matches(&self, app_name: &str, key: &str, label: &str) -> bool {
+        self.app.as_ref().is_none_or(|app| app.matches(app_name))
+            && (self.action.matches(key) || self.action.matches(label))
+    }
+}
+
+/// An action waiting for ✓.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PendingConfirm {
+    pub(crate) key: String,
+    pub(crate) label: String,
+    until: Instant,
+}
+
+/// Confirmation prompts showing on popups, keyed by notification id.
+#[derive(Debug, Default)]
+pub(crate) struct Confirmations {
+    pending: HashMap<u32, PendingConfirm>,
+}
+
+impl Confirmations {
+    /// Shows the prompt for `key` on `id`, replacing any other prompt there.
+    pub(crate) fn request(
+        &mut self,
+        id: u32,
+        key: String,
+        label: String,
+        now: Instant,
+        cfg: &ConfirmActionsSection,
+    ) {
+        self.pending.insert(
+            id,
+            PendingConfirm {
+                key,
+                label,
+                until: now + cfg.timeout(),
+            },
+        );
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<&PendingConfirm> {
+        self.pending.get(&id)
+    }
+
+    /// Ends `id`'s prompt, returning the action key to send if it had not timed out.
+    pub(crate) fn confirm(&mut self, id: u32, now: Instant) -> Option<String> {
+        self.pending
+            .remove(&id)
+            .filter(|pending| now < pending.until)
+            .map(|pending| pending.key)
+    }
+
+    /// Drops `id`'s prompt without sending anything. Returns whether one was showing.
+    pub(crate) fn cancel(&mut self, id: u32) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    /// Drops prompts left unanswered by `now`. Returns their ids, ordered.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<u32> {
+        let mut expired: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.until <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            self.pending.remove(id);
+        }
+        expired.sort_unstable();
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> ConfirmActionsSection {
+        ConfirmActionsSection {
+            actions: vec![
+                ConfirmRule {
+                    action: "delete*".parse().unwrap(),
+                    app: None,
+                },
+                ConfirmRule {
+                    action: "Archive".parse().unwrap(),
+                    app: Some("thunder*".parse().unwrap()),
+                },
+            ],
+            timeout_ms: 1_000,
+        }
+    }
+
+    #[test]
+    fn rules_match_keys_or_labels_and_respect_app_scope() {
+        let cfg = cfg();
+        assert!(cfg.requires_confirmation("mail", "delete", "Remove"));
+        assert!(cfg.requires_confirmation("mail", "trash", "Delete forever"));
+        assert!(cfg.requires_confirmation("Thunderbird", "archive-1", "Archive"));
+
+        assert!(!cfg.requires_confirmation("mail", "archive-1", "Archive"));
+        assert!(!cfg.requires_confirmation("mail", "default", "Open"));
+        assert!(
+            !ConfirmActionsSection::default().requires_confirmation("mail", "delete", "Delete")
+        );
+    }
+
+    #[test]
+    fn prompts_confirm_once_and_time_out() {
+        let cfg = cfg();
+        let start = Instant::now();
+        let mut confirmations = Confirmations::default();
+
+        confirmations.request(1, "delete".to_string(), "Delete".to_string(), start, &cfg);
+        assert_eq!(confirmations.get(1).map(|p| p.key.as_str()), Some("delete"));
+        assert_eq!(
+            confirmations.confirm(1, start + Duration::from_millis(500)),
+            Some("delete".to_string())
+        );
+        assert!(confirmations.confirm(1, start).is_none(), "only sent once");
+
+        confirmations.request(2, "delete".to_string(), "Delete".to_string(), start, &cfg);
+        assert!(
+            confirmations
+                .expire(start + Duration::from_millis(999))
+                .is_empty()
+        );
+        assert_eq!(
+            confirmations.expire(start + Duration::from_secs(1)),
+            vec![2]
+        );
+        assert!(confirmations.get(2).is_none());
+
+        confirmations.request(3, "delete".to_string(), "Delete".to_string(), start, &cfg);
+        assert!(
+            confirmations
+                .confirm(3, start + Duration::from_secs(2))
+                .is_none(),
+            "a late ✓ sends nothing"
+        );
+
+        confirmations.request(4, "delete".to_string(), "Delete".to_string(), start, &cfg);
+        assert!(confirmations.cancel(4));
+        assert!(!confirmations.cancel(4));
+        assert!(confirmations.confirm(4, start).is_none());
+    }
+}
@@ -0,0 +1,236 @@
+//! Webhook events already delivered, so a restart does not post them again. With
+//! `ui.persist_history` on, each delivered event is recorded by its content hash
+//! ([`wisp_types::origin::notification_hash`], the identity the forwarder dedups on), id
+//! and position in the notification's life, and the webhook skips an event it finds
+//! here. Records expire after [`TTL`] and at most [`CAPACITY`] are kept, oldest
+//! dropped first. `wispd --resend-all` posts everything again for one run.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use wisp_types::origin;
+
+use super::state::{self, StateFile};
+
+/// `webhook-delivered.toml` in the state dir.
+pub(crate) const STATE_FILE: StateFile = StateFile {
+    name: "webhook-delivered.toml",
+    migrations: &[],
+    validate: state::validate_as::<DeliveredFile>,
+};
+
+/// How long a delivery is remembered.
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Deliveries remembered at most.
+const CAPACITY: usize = 4096;
+
+/// One webhook event of one notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct DeliveryKey {
+    pub(crate) hash: u64,
+    pub(crate) id: u32,
+    /// 0 for `received`, 1 for `closed`.
+    pub(crate) seq: u32,
+}
+
+/// On-disk shape of `webhook-delivered.toml`, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DeliveredFile {
+    #[serde(default)]
+    delivered: Vec<Record>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    /// [`origin::format_hash`]; TOML integers are signed.
+    hash: String,
+    id: u32,
+    seq: u32,
+    /// Unix seconds.
+    at: u64,
+}
+
+/// Delivered events; [`Default`] is an empty log that is never written to disk.
+#[derive(Debug, Default)]
+pub(crate) struct DeliveredLog {
+    dir: Option<PathBuf>,
+    /// `--resend-all`: deliveries are still recorded but never skipped.
+    resend_all: bool,
+    at: HashMap<DeliveryKey, u64>,
+    /// Oldest first.
+    order: VecDeque<DeliveryKey>,
+}
+
+impl DeliveredLog {
+    /// Loads the log from the state dir `dir`; a missing or unreadable file yields an
+    /// empty log.
+    pub(crate) fn load(dir: PathBuf, resend_all: bool) -> Self {
+        let records = match state::load::<DeliveredFile>(&dir, &STATE_FILE) {
+            Ok(file) => file.map(|file| file.delivered).unwrap_or_default(),
+            Err(err) => {
+                warn!(
+                    file = STATE_FILE.name,
+                    %err,
+                    "ignoring unreadable webhook delivery log; `wispd --repair-state` checks state files"
+                );
+                Vec::new()
+            }
+        };
+
+        let mut log = Self {
+            dir: Some(dir),
+            resend_all,
+            ..Self::default()
+        };
+        for record in records {
+            if let Some(hash) = origin::parse_hash(&record.hash) {
+                let key = DeliveryKey {
+                    hash,
+                    id: record.id,
+                    seq: record.seq,
+                };
+                log.insert(key, record.at);
+            }
+        }
+        log.prune(SystemTime::now());
+        log
+    }
+
+    /// Whether `key` still has to be posted.
+    pub(crate) fn should_send(&self, key: &DeliveryKey, now: SystemTime) -> bool {
+        self.resend_all
+            || self
+                .at
+                .get(key)
+                .is_none_or(|at| unix_secs(now) >= at.saturating_add(TTL.as_secs()))
+    }
+
+    /// Records `key` as delivered at `now` and writes the log.
+    pub(crate) fn record(&mut self, key: DeliveryKey, now: SystemTime) {
+        self.insert(key, unix_secs(now));
+        self.prune(now);
+        self.persist();
+    }
+
+    fn insert(&mut self, key: DeliveryKey, at: u64) {
+        if self.at.insert(key, at).is_some() {
+            self.order.retain(|queued| *queued != key);
+        }
+        self.order.push_back(key);
+    }
+
+    /// Drops expired records, then the oldest beyond [`CAPACITY`].
+    fn prune(&mut self, now: SystemTime) {
+        let now = unix_secs(now);
+        while let Some(oldest) = self.order.front() {
+            let expired = self
+                .at
+                .get(oldest)
+                .is_none_or(|at| now >= at.saturating_add(TTL.as_secs()));
+            if !expired && self.order.len() <= CAPACITY {
+                break;
+            }
+            if let Some(key) = self.order.pop_front() {
+                self.at.remove(&key);
+            }
+        }
+    }
+
+    fn persist(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let file = DeliveredFile {
+            delivered: self
+                .order
+                .iter()
+                .map(|key| Record {
+                    hash: origin::format_hash(key.hash),
+                    id: key.id,
+                    seq: key.seq,
+                    at: self.at.get(key).copied().unwrap_or_default(),
+                })
+                .collect(),
+        };
+        if let Err(err) = state::save(dir, &STATE_FILE, &file) {
+            warn!(dir = %dir.display(), %err, "failed to persist webhook delivery log");
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn key(id: u32, seq: u32) -> DeliveryKey {
+        DeliveryKey {
+            hash: 0xdead_beef_0000_0001,
+            id,
+            seq,
+        }
+    }
+
+    #[test]
+    fn deliveries_are_skipped_after_a_restart_until_they_expire() {
+        let dir = temp_dir("delivered-restart");
+        let now = SystemTime::now();
+        let mut log = DeliveredLog::load(dir.clone(), false);
+        assert!(log.should_send(&key(1, 0), now));
+        log.record(key(1, 0), now);
+
+        let restarted = DeliveredLog::load(dir.clone(), false);
+        assert!(!restarted.should_send(&key(1, 0), now), "already posted");
+        assert!(restarted.should_send(&key(1, 1), now), "its close was not");
+        assert!(restarted.should_send(&key(2, 0), now), "another id");
+        assert!(
+            restarted.should_send(
+                &DeliveryKey {
+                    hash: 7,
+                    ..key(1, 0)
+                },
+                now
+            ),
+            "other content under the same id"
+        );
+        assert!(restarted.should_send(&key(1, 0), now + TTL), "expired");
+
+        let resend = DeliveredLog::load(dir.clone(), true);
+        assert!(resend.should_send(&key(1, 0), now), "--resend-all");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_oldest_deliveries_are_forgotten_beyond_capacity() {
+        let now = SystemTime::now();
+        let mut log = DeliveredLog::default();
+        for id in 0..=CAPACITY as u32 {
+            log.record(key(id, 0), now);
+        }
+        assert_eq!(log.order.len(), CAPACITY);
+        assert!(log.should_send(&key(0, 0), now));
+        assert!(!log.should_send(&key(1, 0), now));
+
+        log.record(key(1, 0), now);
+        assert_eq!(
+            log.order.back(),
+            Some(&key(1, 0)),
+            "recorded again as newest"
+        );
+        assert_eq!(log.order.len(), CAPACITY);
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use wisp_source::{HistoryEntry, SourceStats};
+use wisp_types::{CloseReason, Urgency, loggable::Loggable, privacy::Redaction};
+
+use super::{UiSection, WispdUi, state, text_metrics::CacheStats};
+
+/// Bumped whenever the dump layout changes so bug-report tooling can tell them apart.
+const DEBUG_DUMP_VERSION: u32 = 5;
+/// Newest history entries a dump carries.
+pub(crate) const HISTORY_TAIL: usize = 20;
+
+/// What the source thread adds to a dump, fetched right before it is written.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SourceSnapshot {
+    pub(crate) stats: SourceStats,
+    /// Newest first, at most [`HISTORY_TAIL`].
+    pub(crate) history: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DebugDump<'a> {
+    version: u32,
+    build: BuildInfo,
+    generated_at_unix_ms: u64,
+    redacted: bool,
+    config: ConfigDump<'a>,
+    layout: LayoutDump,
+    notifications: Vec<NotificationDump<'a>>,
+    /// `None` under `--mirror`, where no source answers.
+    source_stats: Option<StatsDump>,
+    /// Newest first; `None` under `--mirror`.
+    history: Option<Vec<HistoryDump<'a>>>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsDump {
+    oversized_hints: u64,
+    oversized_hints_by_app: BTreeMap<String, u64>,
+    dropped_events: u64,
+    failed_signal_emissions: u64,
+    rate_limited: u64,
+}
+
+impl From<&SourceStats> for StatsDump {
+    fn from(stats: &SourceStats) -> Self {
+        Self {
+            oversized_hints: stats.oversized_hints,
+            oversized_hints_by_app: stats
+                .oversized_hints_by_app
+                .iter()
+                .map(|(app, count)| (app.clone(), *count))
+                .collect(),
+            dropped_events: stats.dropped_events,
+            failed_signal_emissions: stats.failed_signal_emissions,
+            rate_limited: stats.rate_limited,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryDump<'a> {
+    id: u32,
+    app_name: &'a str,
+    summary: Cow<'a, str>,
+    body: BodyDump<'a>,
+    reason: &'a CloseReason,
+    received_at_unix_ms: u64,
+    starred: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct BuildInfo {
+    wispd_version: &'static str,
+    debug_assertions: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigDump<'a> {
+    default_timeout_ms: Option<i32>,
+    ui: &'a UiSection,
+}
+
+#[derive(Debug, Serialize)]
+struct LayoutDump {
+    stack_output_policy: Option<String>,
+    /// Front of the stack first.
+    windows: Vec<WindowDump>,
+    /// Text measurement cache behind height estimates, counted since startup.
+    text_metrics: CacheStats,
+    /// Relayout passes since startup; a `notify_batch` costs one.
+    relayouts: u64,
+    /// Margin and size updates sent to popup windows since startup; a relayout sends
+    /// only the ones that changed.
+    layer_updates: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct WindowDump {
+    window_id: String,
+    notification_id: u32,
+    height: u32,
+    measured: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct NotificationDump<'a> {
+    id: u32,
+    local: bool,
+    app_name: &'a str,
+    app_icon: &'a str,
+    summary: Cow<'a, str>,
+    body: BodyDump<'a>,
+    urgency: &'a Urgency,
+    actions: Vec<&'a str>,
+    timeout_ms: Option<u32>,
+    age_ms: u128,
+    activating: bool,
+    error_flash: bool,
+    pinned: bool,
+    visible: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum BodyDump<'a> {
+    Text(&'a str),
+    Redacted { redacted_chars: usize },
+}
+
+impl WispdUi {
+    /// Captures UI state for a bug report, with `source`'s stats and history tail. Called
+    /// from the update loop so the dump is a consistent snapshot. Without `log_content`
+    /// (`log.log_content`), summaries are hidden as in the logs and bodies are always
+    /// redacted; history entries of `ui.privacy_apps` are redacted as their popups are.
+    pub(crate) fn debug_dump<'a>(
+        &'a self,
+        redact_bodies: bool,
+        log_content: bool,
+        source: Option<&'a SourceSnapshot>,
+    ) -> DebugDump<'a> {
+        let hide_content = !log_content;
+        let redact_bodies = redact_bodies || hide_content;
+        let windows = self
+            .windows
+            .iter()
+            .map(|binding| WindowDump {
+                window_id: format!("{:?}", binding.window_id),
+                notification_id: binding.notification_id,
+                height: self.popup_height_for_id(binding.notification_id),
+                measured: self.measured_heights.contains_key(&binding.notification_id),
+            })
+            .collect();
+
+        let mut notifications: Vec<_> = self
+            .notifications
+            .values()
+            .map(|n| NotificationDump {
+                id: n.id,
+                local: n.id > self.next_local_notification_id,
+                app_name: &n.app_name,
+                app_icon: &n.app_icon,
+                summary: if hide_content {
+                    Cow::Owned(Loggable(&n.summary).redacted())
+                } else {
+                    Cow::Borrowed(&n.summary)
+                },
+                body: if redact_bodies {
+                    BodyDump::Redacted {
+                        redacted_chars: n.body.chars().count(),
+                    }
+                } else {
+                    BodyDump::Text(&n.body)
+                },
+                urgency: &n.urgency,
+                actions: n.actions.iter().map(|a| a.key.as_str()).collect(),
+                timeout_ms: n.timeout_ms,
+                age_ms: n.created_at.elapsed().as_millis(),
+                activating: self.activating.contains(&n.id),
+                error_flash: self.is_error_flashing(n.id),
+                pinned: self.is_pinned(n.id),
+                visible: self.windows.iter().any(|w| w.notification_id == n.id),
+            })
+            .collect();
+        notifications.sort_by_key(|n| n.id);
+
+        let history = source.map(|source| {
+            source
+                .history
+                .iter()
+                .take(HISTORY_TAIL)
+                .map(|entry| {
+                    let n = &entry.notification;
+                    let privacy = self
+                        .ui
+                        .privacy_apps
+                        .iter()
+                        .find(|rule| rule.matches(&n.app_name))
+                        .map(|rule| rule.redact);
+                    HistoryDump {
+                        id: entry.id,
+                        app_name: &n.app_name,
+                        summary: if hide_content || privacy == Some(Redaction::All) {
+                            Cow::Owned(Loggable(&n.summary).redacted())
+                        } else {
+                            Cow::Borrowed(&n.summary)
+                        },
+                        body: if redact_bodies || privacy.is_some() {
+                            BodyDump::Redacted {
+                                redacted_chars: n.body.chars().count(),
+                            }
+                        } else {
+                            BodyDump::Text(&n.body)
+                        },
+                        reason: &entry.reason,
+                        received_at_unix_ms: unix_ms(entry.received_at),
+                        starred: entry.starred,
+                    }
+                })
+                .collect()
+        });
+
+        DebugDump {
+            version: DEBUG_DUMP_VERSION,
+            build: BuildInfo {
+                wispd_version: env!("CARGO_PKG_VERSION"),
+                debug_assertions: cfg!(debug_assertions),
+            },
+            generated_at_unix_ms: unix_ms(SystemTime::now()),
+            redacted: redact_bodies,
+            config: ConfigDump {
+                default_timeout_ms: self.default_timeout_ms,
+                ui: &self.ui,
+            },
+            layout: LayoutDump {
+                stack_output_policy: self.stack_output_policy.as_ref().map(|p| p.log_label()),
+                windows,
+                text_metrics: self.text_metrics.borrow().stats(),
+                relayouts: self.relayouts,
+                layer_updates: self.layer_updates,
+            },
+            notifications,
+            source_stats: source.map(|source| StatsDump::from(&source.stats)),
+            history,
+        }
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Writes `dump` as pretty JSON into `dir` and returns the file path.
+pub(crate) fn write_debug_dump(dir: &Path, dump: &DebugDump<'_>) -> io::Result<PathBuf> {
+    let path = dir.join(format!("debug-dump-{}.json", dump.generated_at_unix_ms));
+    let raw = serde_json::to_string_pretty(dump).map_err(io::Error::other)?;
+    state::write_atomic(&path, raw.as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs,
+        sync::{Arc, Mutex, mpsc},
+        time::Duration,
+    };
+
+    use tokio::sync::mpsc as tokio_mpsc;
+    use wisp_types::{Notification, NotificationEvent, ReplaceChain};
+
+    use super::super::mutes::MuteList;
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn ui_with_notification(body: &str) -> WispdUi {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, _cmd_rx) = tokio_mpsc::unbounded_channel();
+        let mut ui = WispdUi::new(
+            Arc::new(Mutex::new(event_rx)),
+            Arc::new(Mutex::new(signal_rx)),
+            Arc::new(Mutex::new(reply_rx)),
+            cmd_tx,
+            UiSection::default(),
+            Some(5000),
+            MuteList::in_memory(),
+        );
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id: 7,
+            notification: Box::new(Notification {
+                app_name: "mail".to_string(),
+                app_icon: String::new(),
+                summary: "New message".to_string(),
+                body: body.to_string(),
+                urgency: Urgency::Normal,
+                timeout_ms: -1,
+                actions: vec![],
+                hints: Default::default(),
+            }),
+            silent: false,
+            remaining_ms: None,
+        });
+        ui
+    }
+
+    fn source_snapshot(body: &str) -> SourceSnapshot {
+        let entry = |id| HistoryEntry {
+            id,
+            notification: Notification {
+                app_name: "chat".to_string(),
+                summary: format!("closed {id}"),
+                body: body.to_string(),
+                ..Notification::default()
+            },
+            sender: None,
+            reason: CloseReason::Dismissed,
+            received_at: UNIX_EPOCH + Duration::from_secs(60),
+            chain: ReplaceChain::default(),
+            starred: false,
+        };
+        SourceSnapshot {
+            stats: SourceStats {
+                rate_limited: 3,
+                oversized_hints: 1,
+                oversized_hints_by_app: HashMap::from([("electron".to_string(), 1)]),
+                ..SourceStats::default()
+            },
+            history: (0..HISTORY_TAIL as u32 + 5).rev().map(entry).collect(),
+        }
+    }
+
+    #[test]
+    fn debug_dump_contains_each_section() {
+        let ui = ui_with_notification("hello");
+        let source = source_snapshot("bye");
+        let value = serde_json::to_value(ui.debug_dump(true, true, Some(&source)))
+            .expect("dump serializes");
+
+        for section in [
+            "version",
+            "build",
+            "generated_at_unix_ms",
+            "redacted",
+            "config",
+            "layout",
+            "notifications",
+            "source_stats",
+            "history",
+        ] {
+            assert!(value.get(section).is_some(), "missing section {section}");
+        }
+        assert_eq!(value["version"], DEBUG_DUMP_VERSION);
+        assert_eq!(value["config"]["default_timeout_ms"], 5000);
+        assert!(value["config"]["ui"].get("max_visible").is_some());
+        assert_eq!(value["layout"]["windows"][0]["notification_id"], 7);
+        assert!(value["layout"]["text_metrics"]["hits"].is_u64());
+        assert_eq!(value["notifications"][0]["id"], 7);
+        assert_eq!(value["notifications"][0]["visible"], true);
+        assert_eq!(value["source_stats"]["rate_limited"], 3);
+        assert_eq!(
+            value["source_stats"]["oversized_hints_by_app"]["electron"],
+            1
+        );
+        let history = value["history"].as_array().expect("history listed");
+        assert_eq!(history.len(), HISTORY_TAIL, "only the newest entries");
+        assert_eq!(history[0]["id"], HISTORY_TAIL as u32 + 4);
+        assert_eq!(history[0]["reason"], "Dismissed");
+        assert_eq!(history[0]["received_at_unix_ms"], 60_000);
+
+        let mirrored = serde_json::to_value(ui.debug_dump(true, true, None)).unwrap();
+        assert!(mirrored["source_stats"].is_null() && mirrored["history"].is_null());
+    }
+
+    #[test]
+    fn debug_dump_redacts_bodies_by_request() {
+        let ui = ui_with_notification("secret code 1234");
+
+        let redacted =
+            serde_json::to_string(&ui.debug_dump(true, true, None)).expect("dump serializes");
+        assert!(!redacted.contains("secret code 1234"));
+        assert!(redacted.contains("\"redacted_chars\":16"));
+
+        let plain =
+            serde_json::to_string(&ui.debug_dump(false, true, None)).expect("dump serializes");
+        assert!(plain.contains("secret code 1234"));
+
+        let source = source_snapshot("old secret 5678");
+        let redacted = serde_json::to_string(&ui.debug_dump(true, true, Some(&source))).unwrap();
+        assert!(!redacted.contains("old secret 5678"), "history bodies too");
+        let plain = serde_json::to_string(&ui.debug_dump(false, true, Some(&source))).unwrap();
+        assert!(plain.contains("old secret 5678"));
+    }
+
+    #[test]
+    fn debug_dump_hides_content_when_logs_do() {
+        let ui = ui_with_notification("secret code 1234");
+
+        let dump =
+            serde_json::to_string(&ui.debug_dump(false, false, None)).expect("dump serializes");
+
+        assert!(!dump.contains("secret code 1234"));
+        assert!(!dump.contains("New message"));
+        assert!(dump.contains(&Loggable("New message").redacted()));
+        assert!(dump.contains("\"redacted\":true"));
+    }
+
+    #[test]
+    fn write_debug_dump_creates_pretty_json_file() {
+        let dir = temp_dir("dump");
+        let ui = ui_with_notification("hello");
+
+        let path = write_debug_dump(&dir, &ui.debug_dump(true, true, None)).expect("dump written");
+        let raw = fs::read_to_string(&path).expect("dump readable");
+        let parsed: HashMap<String, serde_json::Value> =
+            serde_json::from_str(&raw).expect("dump is json");
+        assert!(parsed.contains_key("notifications"));
+        assert!(raw.contains('\n'), "dump is pretty-printed");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
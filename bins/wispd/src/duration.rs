@@ -0,0 +1,118 @@
+//! Durations written the way people say them in the config: `"48h"`, `"90m"`,
+//! `"1h30m"`, `"7d"`. Units are `d`, `h`, `m` and `s`; parts add up and may repeat.
+
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+const UNITS: &[(char, u64)] = &[('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)];
+
+/// A duration read from a string such as `"48h"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct HumanDuration(Duration);
+
+impl HumanDuration {
+    pub(crate) fn get(self) -> Duration {
+        self.0
+    }
+}
+
+impl TryFrom<String> for HumanDuration {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        parse(&raw).map(Self)
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(duration: Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<HumanDuration> for String {
+    fn from(duration: HumanDuration) -> Self {
+        duration.to_string()
+    }
+}
+
+/// Largest units first, e.g. `1d2h`; zero is `0s`.
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut secs = self.0.as_secs();
+        if secs == 0 {
+            return f.write_str("0s");
+        }
+        for (unit, size) in UNITS {
+            if secs >= *size {
+                write!(f, "{}{unit}", secs / size)?;
+                secs %= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse(raw: &str) -> Result<Duration, String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+    for c in raw.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let Some((_, size)) = UNITS.iter().find(|(unit, _)| *unit == c) else {
+            return Err(format!("unknown unit `{c}` in {raw:?}; use d, h, m or s"));
+        };
+        let count: u64 = digits
+            .parse()
+            .map_err(|_| format!("missing number before `{c}` in {raw:?}"))?;
+        digits.clear();
+        total = count
+            .checked_mul(*size)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("{raw:?} is too long"))?;
+    }
+    if !digits.is_empty() {
+        return Err(format!(
+            "{raw:?} needs a unit after {digits} (d, h, m or s)"
+        ));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_parse_from_unit_strings_and_print_back() {
+        let secs = |raw: &str| parse(raw).map(|d| d.as_secs());
+        assert_eq!(secs("48h"), Ok(48 * 3_600));
+        assert_eq!(secs(" 1h30m "), Ok(5_400));
+        assert_eq!(secs("7d"), Ok(7 * 86_400));
+        assert_eq!(secs("90s"), Ok(90));
+        assert_eq!(secs("0s"), Ok(0));
+
+        assert!(secs("").is_err());
+        assert!(secs("48").unwrap_err().contains("needs a unit"));
+        assert!(secs("2w").unwrap_err().contains("unknown unit `w`"));
+        assert!(secs("h").unwrap_err().contains("missing number"));
+        assert!(secs("99999999999999999999d").is_err());
+
+        let shown = |raw: &str| {
+            HumanDuration::try_from(raw.to_string())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(shown("48h"), "2d");
+        assert_eq!(shown("90m"), "1h30m");
+        assert_eq!(shown("0h"), "0s");
+    }
+}
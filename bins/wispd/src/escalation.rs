@@ -0,0 +1,204 @@
+//! Escalation of critical notifications nobody acknowledged. After `after_ms` on screen
+//! the popup flashes, gains a "!" badge and the source announces it on the control
+//! interface; with `repeat`, this happens again every `after_ms`, at most `max_repeats`
+//! more times. Any interaction with the popup cancels it.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// How long an escalated popup shows its flash.
+const FLASH_DURATION: Duration = Duration::from_millis(800);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct EscalationSection {
+    pub(crate) enabled: bool,
+    /// Time a critical popup may sit unacknowledged before escalating, and between
+    /// repeats.
+    pub(crate) after_ms: u64,
+    /// Keeps escalating every `after_ms` after the first time.
+    pub(crate) repeat: bool,
+    /// Escalations allowed after the first one when `repeat` is on.
+    pub(crate) max_repeats: u32,
+    /// Hovering a popup acknowledges it, like a click does.
+    pub(crate) hover_acknowledges: bool,
+}
+
+impl Default for EscalationSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            after_ms: 300_000,
+            repeat: true,
+            max_repeats: 3,
+            hover_acknowledges: true,
+        }
+    }
+}
+
+impl EscalationSection {
+    fn after(&self) -> Duration {
+        Duration::from_millis(self.after_ms)
+    }
+
+    /// Total escalations one notification can get.
+    fn limit(&self) -> u32 {
+        if self.repeat {
+            self.max_repeats.saturating_add(1)
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Escalation {
+    /// Escalations so far; the badge shows once this is non-zero.
+    count: u32,
+    /// Next escalation; `None` once the cap is reached.
+    due: Option<Instant>,
+    last_fired: Option<Instant>,
+}
+
+/// Escalation timers and badges of the visible critical notifications, keyed by id.
+#[derive(Debug, Default)]
+pub(crate) struct Escalations {
+    tracked: HashMap<u32, Escalation>,
+}
+
+impl Escalations {
+    /// Starts `id`'s timer, unless it already has one or was escalated before.
+    pub(crate) fn arm(&mut self, id: u32, now: Instant, cfg: &EscalationSection) {
+        self.tracked.entry(id).or_insert(Escalation {
+            count: 0,
+            due: Some(now + cfg.after()),
+            last_fired: None,
+        });
+    }
+
+    /// Stops `id`'s timer and clears its badge. Returns whether anything was tracked.
+    pub(crate) fn acknowledge(&mut self, id: u32) -> bool {
+        self.tracked.remove(&id).is_some()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.tracked.clear();
+    }
+
+    /// Escalations `id` has had so far.
+    pub(crate) fn count(&self, id: u32) -> u32 {
+        self.tracked
+            .get(&id)
+            .map_or(0, |escalation| escalation.count)
+    }
+
+    pub(crate) fn is_flashing(&self, id: u32, now: Instant) -> bool {
+        self.tracked
+            .get(&id)
+            .and_then(|escalation| escalation.last_fired)
+            .is_some_and(|fired| now.saturating_duration_since(fired) < FLASH_DURATION)
+    }
+
+    /// Escalates every notification due by `now`, re-arming those with repeats left.
+    /// Returns `(id, count)` for each, ordered by id.
+    pub(crate) fn fire_due(&mut self, now: Instant, cfg: &EscalationSection) -> Vec<(u32, u32)> {
+        let limit = cfg.limit();
+        let mut fired: Vec<_> = self
+            .tracked
+            .iter_mut()
+            .filter(|(_, escalation)| escalation.due.is_some_and(|due| due <= now))
+            .map(|(id, escalation)| {
+                escalation.count = escalation.count.saturating_add(1);
+                escalation.due = (escalation.count < limit).then(|| now + cfg.after());
+                escalation.last_fired = Some(now);
+                (*id, escalation.count)
+            })
+            .collect();
+        fired.sort_unstable();
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(repeat: bool, max_repeats: u32) -> EscalationSection {
+        EscalationSection {
+            enabled: true,
+            after_ms: 1_000,
+            repeat,
+            max_repeats,
+            ..EscalationSection::default()
+        }
+    }
+
+    #[test]
+    fn timer_fires_after_the_delay_and_rearms_until_the_cap() {
+        let cfg = cfg(true, 2);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut escalations = Escalations::default();
+        escalations.arm(7, start, &cfg);
+
+        assert!(escalations.fire_due(at(999), &cfg).is_empty());
+        assert_eq!(escalations.count(7), 0);
+
+        assert_eq!(escalations.fire_due(at(1_000), &cfg), vec![(7, 1)]);
+        assert!(escalations.is_flashing(7, at(1_000)));
+        assert!(!escalations.is_flashing(7, at(1_900)));
+        assert!(escalations.fire_due(at(1_500), &cfg).is_empty());
+        assert_eq!(escalations.fire_due(at(2_000), &cfg), vec![(7, 2)]);
+        assert_eq!(escalations.fire_due(at(3_000), &cfg), vec![(7, 3)]);
+
+        assert!(escalations.fire_due(at(60_000), &cfg).is_empty(), "capped");
+        assert_eq!(escalations.count(7), 3, "the badge stays after the cap");
+    }
+
+    #[test]
+    fn without_repeat_a_notification_escalates_once() {
+        let cfg = cfg(false, 5);
+        let start = Instant::now();
+        let mut escalations = Escalations::default();
+        escalations.arm(1, start, &cfg);
+
+        assert_eq!(
+            escalations.fire_due(start + Duration::from_secs(1), &cfg),
+            vec![(1, 1)]
+        );
+        assert!(
+            escalations
+                .fire_due(start + Duration::from_secs(60), &cfg)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn acknowledging_cancels_the_timer_and_clears_the_badge() {
+        let cfg = cfg(true, 3);
+        let start = Instant::now();
+        let mut escalations = Escalations::default();
+        escalations.arm(1, start, &cfg);
+        escalations.arm(2, start, &cfg);
+        escalations.fire_due(start + Duration::from_secs(1), &cfg);
+
+        assert!(escalations.acknowledge(1));
+        assert!(!escalations.acknowledge(1));
+        assert_eq!(escalations.count(1), 0);
+        assert_eq!(
+            escalations.fire_due(start + Duration::from_secs(2), &cfg),
+            vec![(2, 2)]
+        );
+
+        // Arming again does not restart a running timer.
+        escalations.arm(2, start + Duration::from_millis(2_500), &cfg);
+        assert_eq!(
+            escalations.fire_due(start + Duration::from_secs(3), &cfg),
+            vec![(2, 3)]
+        );
+    }
+}
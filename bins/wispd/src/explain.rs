@@ -0,0 +1,388 @@
+//! Why a popup looks and behaves the way it does. `ExplainNotification(id)` on
+//! `org.wispd.Ui1` returns, as JSON, every config layer and popup state that applied to
+//! the notification (with the fields each one set) and the values they resolved to.
+
+use std::{collections::BTreeMap, sync::mpsc, time::Instant};
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tracing::info;
+use wisp_types::privacy::Redaction;
+
+use super::{SignalRequest, UiNotification, WispdUi, effective_timeout_ms, style::StyleLayer};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Explanation<'a> {
+    id: u32,
+    app_name: &'a str,
+    /// One of wispd's own popups rather than a notification from the bus.
+    local: bool,
+    /// In the order applied; later layers override earlier ones.
+    layers: Vec<StyleLayer>,
+    resolved: Resolved<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct Resolved<'a> {
+    colors: BTreeMap<&'static str, String>,
+    /// `None` never expires.
+    timeout_ms: Option<u32>,
+    actions: Vec<ActionDump<'a>>,
+    actions_enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ActionDump<'a> {
+    key: &'a str,
+    label: &'a str,
+    /// Matched by `[ui.confirm_actions]`.
+    confirm: bool,
+}
+
+impl WispdUi {
+    /// Traces the layers behind `id`'s popup; `None` when the UI has no such notification.
+    pub(crate) fn explain(&self, id: u32, now: Instant) -> Option<Explanation<'_>> {
+        let n = self.notifications.get(&id)?;
+        let style = self.resolve_style(n, now);
+        let mut layers = style.layers;
+        layers.extend(self.behavior_layers(n));
+
+        Some(Explanation {
+            id,
+            app_name: &n.app_name,
+            local: self.is_local(id),
+            layers,
+            resolved: Resolved {
+                colors: style.colors.to_hex_map(),
+                timeout_ms: n.timeout_ms,
+                actions: n
+                    .actions
+                    .iter()
+                    .map(|action| ActionDump {
+                        key: &action.key,
+                        label: &action.label,
+                        confirm: self.ui.confirm_actions.requires_confirmation(
+                            &n.app_name,
+                            &action.key,
+                            &action.label,
+                        ),
+                    })
+                    .collect(),
+                actions_enabled: self.actions_enabled(id),
+            },
+        })
+    }
+
+    /// Layers that decided content and lifetime rather than colors.
+    fn behavior_layers(&self, n: &UiNotification) -> Vec<StyleLayer> {
+        let mut layers = Vec::new();
+
+        if let Some((index, rule)) = self
+            .ui
+            .privacy_apps
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(&n.app_name))
+        {
+            let redact = match rule.redact {
+                Redaction::Body => "body",
+                Redaction::All => "all",
+            };
+            layers.push(layer(
+                "privacy",
+                format!("ui.privacy_apps[{index}] ({})", rule.app),
+                [("redact", redact.to_string())],
+            ));
+        }
+
+        if let Some(category) = n.category.as_deref()
+            && let Some(accent) = self.ui.category_icons.lookup(category)
+        {
+            layers.push(layer(
+                "category",
+                "ui.category_icons",
+                [
+                    ("category", category.to_string()),
+                    ("accent", accent.to_string()),
+                ],
+            ));
+        }
+
+        let requested = n.requested_timeout_ms;
+        let (source, timeout) = if requested < 0 {
+            (
+                "source.default_timeout_ms",
+                effective_timeout_ms(requested, self.default_timeout_ms),
+            )
+        } else {
+            ("sender", effective_timeout_ms(requested, None))
+        };
+        layers.push(layer("timeout", source, [("timeout_ms", ms(timeout))]));
+        let clamped = n
+            .timeout_clamp
+            .apply(timeout, n.urgency == wisp_types::Urgency::Critical);
+        if clamped != timeout {
+            let source = if n.timeout_clamp == self.ui.timeout_clamp() {
+                "ui.min_timeout_ms / ui.max_timeout_ms"
+            } else {
+                "[[ui.rules]]"
+            };
+            layers.push(layer(
+                "timeout_clamp",
+                source,
+                [("timeout_ms", ms(clamped))],
+            ));
+        }
+        if self.ui.never_expires(&n.urgency) {
+            layers.push(layer(
+                "critical",
+                "ui.critical_never_expires",
+                [("timeout_ms", ms(None))],
+            ));
+        }
+        if self.is_pinned(n.id) {
+            layers.push(layer("pin", "pinned popup", [("timeout_ms", ms(None))]));
+        }
+
+        layers
+    }
+}
+
+fn layer<const N: usize>(
+    name: &'static str,
+    source: impl Into<String>,
+    set: [(&'static str, String); N],
+) -> StyleLayer {
+    StyleLayer {
+        layer: name,
+        source: source.into(),
+        set: set.into_iter().collect(),
+    }
+}
+
+fn ms(timeout: Option<u32>) -> String {
+    timeout.map_or_else(|| "never".to_string(), |ms| ms.to_string())
+}
+
+/// `org.wispd.Ui1`, served next to `org.wispd.Control1` for questions only the UI can
+/// answer. Calls are queued for the UI thread, which replies on its next tick.
+pub(crate) struct UiInterface {
+    pub(crate) requests: mpsc::Sender<SignalRequest>,
+}
+
+#[zbus::interface(name = "org.wispd.Ui1")]
+impl UiInterface {
+    /// JSON trace of the layers behind notification `id`'s popup.
+    async fn explain_notification(&self, id: u32) -> zbus::fdo::Result<String> {
+        info!(id, "dbus ExplainNotification called");
+        let (reply, answer) = oneshot::channel();
+        self.requests
+            .send(SignalRequest::Explain { id, reply })
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))?;
+        match answer.await {
+            Ok(Some(json)) => Ok(json),
+            Ok(None) => Err(zbus::fdo::Error::InvalidArgs(format!(
+                "no notification with id {id}"
+            ))),
+            Err(_) => Err(zbus::fdo::Error::Failed(
+                "the UI stopped before answering".to_string(),
+            )),
+        }
+    }
+
+    /// Gives the front popup the keyboard so its actions can be triggered by mnemonic or
+    /// position, or takes it back.
+    async fn set_keyboard_mode(&self, enabled: bool) -> zbus::fdo::Result<()> {
+        info!(enabled, "dbus SetKeyboardMode called");
+        self.requests
+            .send(SignalRequest::KeyboardMode(enabled))
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))
+    }
+
+    /// Writes a debug dump, as SIGQUIT does, and returns the path of the file.
+    async fn debug_dump(&self) -> zbus::fdo::Result<String> {
+        info!("dbus DebugDump called");
+        let (reply, answer) = oneshot::channel();
+        self.requests
+            .send(SignalRequest::DebugDump { reply: Some(reply) })
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))?;
+        answer
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("the UI stopped before answering".to_string()))?
+            .map_err(zbus::fdo::Error::Failed)
+    }
+
+    /// Muted apps as `a(st)`: app name and seconds until the mute lapses.
+    async fn list_mutes(&self) -> zbus::fdo::Result<Vec<(String, u64)>> {
+        let (reply, answer) = oneshot::channel();
+        self.requests
+            .send(SignalRequest::ListMutes { reply })
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))?;
+        answer
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("the UI stopped before answering".to_string()))
+    }
+
+    /// Lifts `app`'s mute early. Returns whether it was muted.
+    async fn unmute(&self, app: String) -> zbus::fdo::Result<bool> {
+        info!(app = %app, "dbus Unmute called");
+        let (reply, answer) = oneshot::channel();
+        self.requests
+            .send(SignalRequest::Unmute {
+                app_name: app,
+                reply,
+            })
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))?;
+        answer
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("the UI stopped before answering".to_string()))
+    }
+
+    /// Opens the history panel, or closes it when it is open.
+    async fn toggle_history_panel(&self) -> zbus::fdo::Result<()> {
+        info!("dbus ToggleHistoryPanel called");
+        self.requests
+            .send(SignalRequest::ToggleHistoryPanel)
+            .map_err(|_| zbus::fdo::Error::Failed("the UI is not running".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::mpsc as tokio_mpsc;
+    use wisp_types::{Notification, NotificationAction, NotificationEvent, NotificationHints};
+
+    use super::super::{
+        UiSection,
+        confirm::{ConfirmActionsSection, ConfirmRule},
+        mutes::MuteList,
+    };
+    use super::*;
+
+    fn ui_with(ui_cfg: UiSection) -> WispdUi {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, _cmd_rx) = tokio_mpsc::unbounded_channel();
+        WispdUi::new(
+            Arc::new(Mutex::new(event_rx)),
+            Arc::new(Mutex::new(signal_rx)),
+            Arc::new(Mutex::new(reply_rx)),
+            cmd_tx,
+            ui_cfg,
+            Some(5000),
+            MuteList::in_memory(),
+        )
+    }
+
+    #[test]
+    fn trace_lists_every_layer_that_matched_in_order() {
+        let mut ui_cfg = UiSection {
+            max_timeout_ms: Some(20_000),
+            privacy_apps: vec!["Sig*".parse().expect("rule parses")],
+            confirm_actions: ConfirmActionsSection {
+                actions: vec![ConfirmRule {
+                    action: "delete".parse().unwrap(),
+                    app: None,
+                }],
+                ..ConfirmActionsSection::default()
+            },
+            ..UiSection::default()
+        };
+        ui_cfg.colors.normal = "#00ff00".to_string();
+        let mut ui = ui_with(ui_cfg);
+        let mut hints = NotificationHints::default();
+        hints.category = Some("im.received".to_string());
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id: 4,
+            notification: Box::new(Notification {
+                app_name: "Signal".to_string(),
+                summary: "Alice".to_string(),
+                body: "secret".to_string(),
+                timeout_ms: 60_000,
+                actions: vec![NotificationAction {
+                    key: "delete".to_string(),
+                    label: "Delete".to_string(),
+                }],
+                hints,
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        });
+        ui.pending_measure.clear();
+
+        let explanation = ui.explain(4, Instant::now()).expect("notification known");
+        let value = serde_json::to_value(&explanation).expect("explanation serializes");
+        let layers: Vec<(&str, &str)> = value["layers"]
+            .as_array()
+            .expect("layers are a list")
+            .iter()
+            .map(|l| (l["layer"].as_str().unwrap(), l["source"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            layers,
+            [
+                ("colors", "ui.colors"),
+                ("text", "ui.text"),
+                ("buttons", "ui.buttons"),
+                ("urgency", "ui.colors.normal"),
+                ("privacy", "ui.privacy_apps[0] (Sig*)"),
+                ("category", "ui.category_icons"),
+                ("timeout", "sender"),
+                ("timeout_clamp", "ui.min_timeout_ms / ui.max_timeout_ms"),
+            ]
+        );
+        assert_eq!(value["layers"][3]["set"]["border"], "#00ff00ff");
+        assert_eq!(value["layers"][4]["set"]["redact"], "body");
+        assert_eq!(value["layers"][5]["set"]["accent"], "💬");
+        assert_eq!(value["layers"][6]["set"]["timeout_ms"], "60000");
+        assert_eq!(value["layers"][7]["set"]["timeout_ms"], "20000");
+
+        let resolved = &value["resolved"];
+        assert_eq!(resolved["colors"]["border"], "#00ff00ff");
+        assert_eq!(resolved["timeout_ms"], 20_000);
+        assert_eq!(resolved["actions"][0]["confirm"], true);
+        assert_eq!(resolved["actions_enabled"], true);
+
+        assert!(ui.explain(99, Instant::now()).is_none());
+    }
+
+    #[test]
+    fn popup_state_layers_follow_config_and_pinning_wins_the_timeout() {
+        let mut ui = ui_with(UiSection::default());
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id: 1,
+            notification: Box::new(Notification {
+                app_name: "mail".to_string(),
+                timeout_ms: -1,
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        });
+        ui.pinned.insert(1);
+        ui.activating.insert(1);
+        ui.pending_measure.clear();
+
+        let explanation = ui.explain(1, Instant::now()).expect("notification known");
+        let names: Vec<&str> = explanation.layers.iter().map(|l| l.layer).collect();
+        assert_eq!(
+            names,
+            [
+                "colors",
+                "text",
+                "buttons",
+                "urgency",
+                "activating",
+                "timeout",
+                "pin"
+            ]
+        );
+        assert_eq!(explanation.layers[5].source, "source.default_timeout_ms");
+        assert_eq!(explanation.layers[5].set["timeout_ms"], "5000");
+        assert_eq!(explanation.layers[6].set["timeout_ms"], "never");
+    }
+}
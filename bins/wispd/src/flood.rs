@@ -0,0 +1,164 @@
+//! Per-app flood collapsing. When one app posts more than `ui.flood_threshold`
+//! notifications within `ui.flood_window_ms`, the first ones keep their popups and each
+//! further one is tracked without a popup. One local summary popup per app ("Slack: 14
+//! new notifications") counts them instead; its default action opens the history panel
+//! filtered to that app. The summary never reaches the source. Critical notifications
+//! are never collapsed.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// A summary popup and how many notifications it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    id: u32,
+    count: u32,
+}
+
+/// Recent arrivals per app, the notifications collapsed, and the summaries up.
+#[derive(Debug, Default)]
+pub(crate) struct Floods {
+    /// Arrival times within the window, oldest first; at most `threshold + 1` per app.
+    arrivals: HashMap<String, VecDeque<Instant>>,
+    /// Notifications tracked without a popup because their app was flooding.
+    collapsed: HashSet<u32>,
+    /// Summary popup per app.
+    summaries: HashMap<String, Summary>,
+}
+
+impl Floods {
+    /// Records an arrival from `app` at `now` and returns whether it is beyond
+    /// `threshold` arrivals within `window`. A threshold of 0 never floods.
+    pub(crate) fn arrive(
+        &mut self,
+        app: &str,
+        now: Instant,
+        threshold: u32,
+        window: Duration,
+    ) -> bool {
+        if threshold == 0 {
+            return false;
+        }
+        let recent = |at: &Instant| now.saturating_duration_since(*at) < window;
+        self.arrivals
+            .retain(|_, times| times.back().is_some_and(recent));
+        let times = self.arrivals.entry(app.to_string()).or_default();
+        times.retain(recent);
+        times.push_back(now);
+        let limit = threshold as usize;
+        while times.len() > limit + 1 {
+            times.pop_front();
+        }
+        times.len() > limit
+    }
+
+    /// Tracks `id` as collapsed. Returns the id of `app`'s summary and its new count when
+    /// one is up; otherwise the caller opens one with [`Self::start_summary`].
+    pub(crate) fn collapse(&mut self, id: u32, app: &str) -> Option<(u32, u32)> {
+        self.collapsed.insert(id);
+        let summary = self.summaries.get_mut(app)?;
+        summary.count = summary.count.saturating_add(1);
+        Some((summary.id, summary.count))
+    }
+
+    /// Records `summary_id` as `app`'s summary, counting one notification.
+    pub(crate) fn start_summary(&mut self, app: &str, summary_id: u32) {
+        self.summaries.insert(
+            app.to_string(),
+            Summary {
+                id: summary_id,
+                count: 1,
+            },
+        );
+    }
+
+    pub(crate) fn is_collapsed(&self, id: u32) -> bool {
+        self.collapsed.contains(&id)
+    }
+
+    /// The app `id` summarizes, when it is a summary popup.
+    pub(crate) fn summary_app(&self, id: u32) -> Option<&str> {
+        self.summaries
+            .iter()
+            .find(|(_, summary)| summary.id == id)
+            .map(|(app, _)| app.as_str())
+    }
+
+    pub(crate) fn summary_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.summaries.values().map(|summary| summary.id)
+    }
+
+    /// Forgets `id`, a collapsed notification or a summary; the next arrival beyond the
+    /// threshold opens a new summary.
+    pub(crate) fn remove(&mut self, id: u32) {
+        self.collapsed.remove(&id);
+        self.summaries.retain(|_, summary| summary.id != id);
+    }
+}
+
+/// Summary line of a summary popup standing for `count` notifications.
+pub(crate) fn summary_text(app: &str, count: u32) -> String {
+    let noun = if count == 1 {
+        "notification"
+    } else {
+        "notifications"
+    };
+    format!("{app}: {count} new {noun}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn only_arrivals_beyond_the_threshold_within_the_window_flood() {
+        let mut floods = Floods::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let flooded: Vec<bool> = (0..5)
+            .map(|n| floods.arrive("Slack", at(n * 100), 3, WINDOW))
+            .collect();
+        assert_eq!(flooded, [false, false, false, true, true]);
+        assert!(!floods.arrive("Mail", at(500), 3, WINDOW), "per app");
+
+        // A window after the last one, it counts from one again.
+        assert!(!floods.arrive("Slack", at(10_400), 3, WINDOW));
+        assert!(!floods.arrive("Slack", at(10_500), 3, WINDOW));
+        assert!(!floods.arrive("Slack", at(10_600), 3, WINDOW));
+        assert!(floods.arrive("Slack", at(10_700), 3, WINDOW), "four again");
+
+        assert!(!Floods::default().arrive("Slack", at(0), 0, WINDOW), "off");
+    }
+
+    #[test]
+    fn collapsed_notifications_count_towards_the_app_summary() {
+        let mut floods = Floods::default();
+        assert_eq!(floods.collapse(4, "Slack"), None, "no summary yet");
+        floods.start_summary("Slack", u32::MAX);
+        assert_eq!(floods.collapse(5, "Slack"), Some((u32::MAX, 2)));
+        assert_eq!(floods.collapse(9, "Mail"), None);
+        assert!(floods.is_collapsed(4));
+        assert_eq!(floods.summary_app(u32::MAX), Some("Slack"));
+        assert_eq!(floods.summary_ids().collect::<Vec<_>>(), [u32::MAX]);
+
+        floods.remove(u32::MAX);
+        floods.remove(4);
+        assert!(!floods.is_collapsed(4));
+        assert_eq!(floods.summary_app(u32::MAX), None);
+        assert_eq!(
+            floods.collapse(6, "Slack"),
+            None,
+            "the next one starts over"
+        );
+    }
+
+    #[test]
+    fn summary_text_counts_notifications() {
+        assert_eq!(summary_text("Slack", 1), "Slack: 1 new notification");
+        assert_eq!(summary_text("Slack", 14), "Slack: 14 new notifications");
+    }
+}
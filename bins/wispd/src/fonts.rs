@@ -0,0 +1,252 @@
+//! Whether the configured font families are installed. At startup and on reload
+//! `ui.font_family` and `ui.buttons.font_family` are looked up in the font database; a
+//! family that is not there is drawn in the database's sans-serif fallback, so the height
+//! estimator takes that font's average advance instead of its default guess and one
+//! warning names what is missing. `wispd --check-config` prints the same resolution.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Mutex, OnceLock},
+};
+
+use iced::advanced::graphics::text::{cosmic_text, font_system, to_attributes};
+use tracing::warn;
+
+use super::{UiSection, resolve_font};
+
+/// Generic names the renderer maps itself; they are always available.
+const GENERIC_FAMILIES: &[&str] = &[
+    "sans",
+    "sans-serif",
+    "serif",
+    "monospace",
+    "mono",
+    "cursive",
+    "fantasy",
+];
+
+/// Measured for a font's average advance.
+const SAMPLE: &str = "The quick brown fox jumps over the lazy dog 0123456789";
+
+/// Installed font families.
+pub(crate) trait FontDatabase {
+    fn has_family(&self, family: &str) -> bool;
+    /// The family missing ones are drawn in.
+    fn fallback_family(&self) -> String;
+    /// Average advance of `family` in ems, when it can be measured.
+    fn char_width_em(&self, family: &str) -> Option<f32>;
+}
+
+/// The renderer's font system.
+pub(crate) struct SystemFonts;
+
+impl FontDatabase for SystemFonts {
+    fn has_family(&self, family: &str) -> bool {
+        let Ok(mut fonts) = font_system().write() else {
+            warn!("font system lock poisoned; assuming fonts are installed");
+            return true;
+        };
+        fonts.raw().db().faces().any(|face| {
+            face.families
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(family))
+        })
+    }
+
+    fn fallback_family(&self) -> String {
+        let Ok(mut fonts) = font_system().write() else {
+            return "sans-serif".to_string();
+        };
+        fonts
+            .raw()
+            .db()
+            .family_name(&cosmic_text::fontdb::Family::SansSerif)
+            .to_string()
+    }
+
+    fn char_width_em(&self, family: &str) -> Option<f32> {
+        const SIZE: f32 = 100.0;
+        let mut fonts = font_system().write().ok()?;
+        let fonts = fonts.raw();
+        let mut buffer = cosmic_text::Buffer::new(fonts, cosmic_text::Metrics::new(SIZE, SIZE));
+        buffer.set_text(
+            fonts,
+            SAMPLE,
+            &to_attributes(resolve_font(family)),
+            cosmic_text::Shaping::Advanced,
+            None,
+        );
+        buffer.shape_until_scroll(fonts, false);
+        let width: f32 = buffer.layout_runs().map(|run| run.line_w).sum();
+        (width > 0.0).then(|| width / (SAMPLE.chars().count() as f32 * SIZE))
+    }
+}
+
+/// How the configured families resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FontReport {
+    /// Configured families that are installed, in config order.
+    pub(crate) found: Vec<String>,
+    /// Configured families that are not.
+    pub(crate) missing: Vec<String>,
+    /// What missing families are drawn in.
+    pub(crate) fallback: String,
+    /// Average advance the estimator uses; `None` keeps its default.
+    pub(crate) char_width_em: Option<f32>,
+}
+
+impl FontReport {
+    /// Resolves `ui.font_family` and `ui.buttons.font_family` against `db`. The fallback
+    /// is only measured when the popup text family is missing.
+    pub(crate) fn check(ui: &UiSection, db: &impl FontDatabase) -> Self {
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        let configured = std::iter::once(ui.font_family.trim())
+            .chain(ui.buttons.font_family.as_deref().map(str::trim));
+        for family in configured {
+            if found.iter().chain(&missing).any(|seen| seen == family) {
+                continue;
+            }
+            if is_generic(family) || db.has_family(family) {
+                found.push(family.to_string());
+            } else {
+                missing.push(family.to_string());
+            }
+        }
+
+        let fallback = db.fallback_family();
+        let char_width_em = missing
+            .iter()
+            .any(|family| family == ui.font_family.trim())
+            .then(|| db.char_width_em(&fallback))
+            .flatten();
+        Self {
+            found,
+            missing,
+            fallback,
+            char_width_em,
+        }
+    }
+
+    /// Logs one warning when families are missing.
+    pub(crate) fn warn_missing(&self) {
+        if self.missing.is_empty() {
+            return;
+        }
+        warn!(
+            missing = ?self.missing,
+            fallback = %self.fallback,
+            char_width_em = ?self.char_width_em,
+            "configured font families are not installed; using the fallback"
+        );
+    }
+}
+
+impl fmt::Display for FontReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for family in &self.found {
+            writeln!(f, "font {family:?}: found")?;
+        }
+        for family in &self.missing {
+            writeln!(f, "font {family:?}: missing, using {:?}", self.fallback)?;
+        }
+        match self.char_width_em {
+            Some(em) => write!(f, "estimator char width: {em:.3} em ({})", self.fallback),
+            None => write!(f, "estimator char width: default"),
+        }
+    }
+}
+
+fn is_generic(family: &str) -> bool {
+    GENERIC_FAMILIES
+        .iter()
+        .any(|generic| generic.eq_ignore_ascii_case(family))
+}
+
+/// `name` with a `'static` lifetime, as [`iced::Font::with_name`] needs; each distinct
+/// name is leaked once, however often the view asks for it.
+pub(crate) fn intern(name: &str) -> &'static str {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut names = NAMES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(interned) = names.get(name) {
+        return interned;
+    }
+    let leaked: &'static str = Box::leak(name.to_string().into_boxed_str());
+    names.insert(leaked);
+    leaked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ButtonStyleConfig;
+    use super::*;
+
+    /// Has "Inter" installed and falls back to "DejaVu Sans".
+    struct FixtureFonts;
+
+    impl FontDatabase for FixtureFonts {
+        fn has_family(&self, family: &str) -> bool {
+            family.eq_ignore_ascii_case("Inter")
+        }
+
+        fn fallback_family(&self) -> String {
+            "DejaVu Sans".to_string()
+        }
+
+        fn char_width_em(&self, family: &str) -> Option<f32> {
+            (family == "DejaVu Sans").then_some(0.6)
+        }
+    }
+
+    fn ui(font_family: &str, buttons: Option<&str>) -> UiSection {
+        UiSection {
+            font_family: font_family.to_string(),
+            buttons: ButtonStyleConfig {
+                font_family: buttons.map(str::to_string),
+                ..ButtonStyleConfig::default()
+            },
+            ..UiSection::default()
+        }
+    }
+
+    #[test]
+    fn missing_text_families_use_the_fallback_metrics() {
+        let report = FontReport::check(&ui("Nonexistent Sans", Some("Inter")), &FixtureFonts);
+        assert_eq!(report.found, ["Inter"]);
+        assert_eq!(report.missing, ["Nonexistent Sans"]);
+        assert_eq!(report.fallback, "DejaVu Sans");
+        assert_eq!(report.char_width_em, Some(0.6));
+        assert_eq!(
+            report.to_string(),
+            "font \"Inter\": found\n\
+             font \"Nonexistent Sans\": missing, using \"DejaVu Sans\"\n\
+             estimator char width: 0.600 em (DejaVu Sans)"
+        );
+    }
+
+    #[test]
+    fn installed_and_generic_families_keep_the_default_metrics() {
+        let report = FontReport::check(&ui("inter", Some("monospace")), &FixtureFonts);
+        assert_eq!(report.found, ["inter", "monospace"]);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.char_width_em, None);
+
+        let buttons_only = FontReport::check(&ui("Inter", Some("Gone")), &FixtureFonts);
+        assert_eq!(buttons_only.missing, ["Gone"]);
+        assert_eq!(
+            buttons_only.char_width_em, None,
+            "button text is not estimated"
+        );
+    }
+
+    #[test]
+    fn names_are_interned_once() {
+        let first = intern("Fira Sans");
+        let again = intern(&String::from("Fira Sans"));
+        assert!(std::ptr::eq(first, again));
+    }
+}
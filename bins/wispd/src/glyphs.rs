@@ -0,0 +1,224 @@
+//! Glyphs on the built-in popup buttons. Each has a fallback chain that ends in ASCII; at
+//! startup and on reload the first candidate the button font can draw wins, so a font
+//! without "✕" shows "×" or "x" instead of tofu. The close button can also be a themed
+//! icon.
+
+use std::path::PathBuf;
+
+use iced::advanced::graphics::text::{cosmic_text, font_system, to_attributes};
+use tracing::{info, warn};
+
+use super::{ButtonStyleConfig, icons, resolve_font};
+
+const CLOSE_CANDIDATES: &[&str] = &["✕", "×", "x"];
+const PIN_CANDIDATES: &[&str] = &["📌", "⚲", "P"];
+const COPY_CANDIDATES: &[&str] = &["⧉", "⎘", "c"];
+const ACTIONS_CANDIDATES: &[&str] = &["⚡", "›", ">"];
+
+/// Whether a font can draw a glyph.
+pub(crate) trait GlyphCoverage {
+    fn covers(&self, font_family: &str, glyph: &str) -> bool;
+}
+
+/// Shapes the glyph with the renderer's font system, fallback fonts included; it is
+/// covered unless some part shapes to `.notdef`.
+pub(crate) struct ShapedCoverage;
+
+impl GlyphCoverage for ShapedCoverage {
+    fn covers(&self, font_family: &str, glyph: &str) -> bool {
+        let Ok(mut fonts) = font_system().write() else {
+            warn!("font system lock poisoned; assuming glyphs are covered");
+            return true;
+        };
+        let fonts = fonts.raw();
+        let mut buffer = cosmic_text::Buffer::new(fonts, cosmic_text::Metrics::new(16.0, 20.0));
+        buffer.set_text(
+            fonts,
+            glyph,
+            &to_attributes(resolve_font(font_family)),
+            cosmic_text::Shaping::Advanced,
+            None,
+        );
+        buffer.shape_until_scroll(fonts, false);
+        let mut shaped = buffer
+            .layout_runs()
+            .flat_map(|run| run.glyphs.iter())
+            .peekable();
+        shaped.peek().is_some() && shaped.all(|g| g.glyph_id != 0)
+    }
+}
+
+/// What the close button shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CloseFace {
+    Glyph(String),
+    Icon(PathBuf),
+}
+
+/// The chosen face of each built-in button.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glyphs {
+    pub(crate) close: CloseFace,
+    pub(crate) pin: String,
+    pub(crate) copy: String,
+    /// The affordance for actions `max_visible_actions` hides.
+    pub(crate) actions: String,
+}
+
+impl Glyphs {
+    /// The preferred faces, unchecked; used until [`Glyphs::resolve`] has run.
+    pub(crate) fn preferred(cfg: &ButtonStyleConfig) -> Self {
+        Self {
+            close: CloseFace::Glyph(
+                cfg.close_glyph
+                    .clone()
+                    .unwrap_or_else(|| CLOSE_CANDIDATES[0].to_string()),
+            ),
+            pin: PIN_CANDIDATES[0].to_string(),
+            copy: COPY_CANDIDATES[0].to_string(),
+            actions: cfg
+                .actions_glyph
+                .clone()
+                .unwrap_or_else(|| ACTIONS_CANDIDATES[0].to_string()),
+        }
+    }
+
+    /// Picks every face `coverage` says `font_family` can draw, logging the choices.
+    pub(crate) fn resolve(
+        cfg: &ButtonStyleConfig,
+        font_family: &str,
+        icon_size: u32,
+        coverage: &impl GlyphCoverage,
+    ) -> Self {
+        let icon = cfg.close_icon.as_deref().and_then(|name| {
+            let path = icons::resolve_icon_name(name, icon_size);
+            if path.is_none() {
+                warn!(
+                    icon = name,
+                    "close_icon not found in the icon theme; using a glyph"
+                );
+            }
+            path
+        });
+        let close = match icon {
+            Some(path) => {
+                info!(path = %path.display(), "close button uses a themed icon");
+                CloseFace::Icon(path)
+            }
+            None => CloseFace::Glyph(choose(
+                "close",
+                cfg.close_glyph.as_deref(),
+                CLOSE_CANDIDATES,
+                font_family,
+                coverage,
+            )),
+        };
+        Self {
+            close,
+            pin: choose("pin", None, PIN_CANDIDATES, font_family, coverage),
+            copy: choose("copy", None, COPY_CANDIDATES, font_family, coverage),
+            actions: choose(
+                "actions",
+                cfg.actions_glyph.as_deref(),
+                ACTIONS_CANDIDATES,
+                font_family,
+                coverage,
+            ),
+        }
+    }
+}
+
+/// `configured`, then `candidates` in order; the last candidate when nothing is covered.
+fn choose(
+    button: &str,
+    configured: Option<&str>,
+    candidates: &[&str],
+    font_family: &str,
+    coverage: &impl GlyphCoverage,
+) -> String {
+    let mut tried = configured.into_iter().chain(candidates.iter().copied());
+    let chosen = tried
+        .find(|glyph| coverage.covers(font_family, glyph))
+        .unwrap_or(candidates[candidates.len() - 1]);
+    if let Some(configured) = configured.filter(|configured| *configured != chosen) {
+        warn!(
+            button,
+            configured,
+            chosen,
+            font = font_family,
+            "configured glyph is missing from the button font"
+        );
+    }
+    info!(
+        button,
+        glyph = chosen,
+        font = font_family,
+        "button glyph chosen"
+    );
+    chosen.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashSet};
+
+    use super::*;
+
+    /// Covers exactly the listed glyphs and records every query.
+    struct Covers {
+        glyphs: HashSet<&'static str>,
+        asked: RefCell<Vec<String>>,
+    }
+
+    impl Covers {
+        fn only(glyphs: &[&'static str]) -> Self {
+            Self {
+                glyphs: glyphs.iter().copied().collect(),
+                asked: RefCell::default(),
+            }
+        }
+    }
+
+    impl GlyphCoverage for Covers {
+        fn covers(&self, _font_family: &str, glyph: &str) -> bool {
+            self.asked.borrow_mut().push(glyph.to_string());
+            self.glyphs.contains(glyph)
+        }
+    }
+
+    #[test]
+    fn the_first_covered_candidate_wins_and_ascii_is_the_last_resort() {
+        let everything = Covers::only(&["✕", "×", "x", "📌", "⧉", "⚡"]);
+        let glyphs = Glyphs::resolve(&ButtonStyleConfig::default(), "sans", 16, &everything);
+        assert_eq!(glyphs, Glyphs::preferred(&ButtonStyleConfig::default()));
+
+        let no_symbols = Covers::only(&["×", "⎘"]);
+        let glyphs = Glyphs::resolve(&ButtonStyleConfig::default(), "sans", 16, &no_symbols);
+        assert_eq!(glyphs.close, CloseFace::Glyph("×".to_string()));
+        assert_eq!(glyphs.pin, "P", "nothing covered falls back to ASCII");
+        assert_eq!(glyphs.copy, "⎘");
+        assert_eq!(glyphs.actions, ">");
+        assert_eq!(no_symbols.asked.borrow()[..2], ["✕", "×"]);
+    }
+
+    #[test]
+    fn a_configured_glyph_is_tried_first_and_an_unknown_icon_falls_back() {
+        let cfg = ButtonStyleConfig {
+            close_glyph: Some("✖".to_string()),
+            close_icon: Some("no-such-icon-anywhere".to_string()),
+            ..ButtonStyleConfig::default()
+        };
+        let covered = Covers::only(&["✖", "✕"]);
+        assert_eq!(
+            Glyphs::resolve(&cfg, "sans", 16, &covered).close,
+            CloseFace::Glyph("✖".to_string())
+        );
+
+        let missing = Covers::only(&["x"]);
+        assert_eq!(
+            Glyphs::resolve(&cfg, "sans", 16, &missing).close,
+            CloseFace::Glyph("x".to_string())
+        );
+        assert_eq!(missing.asked.borrow()[..4], ["✖", "✕", "×", "x"]);
+    }
+}
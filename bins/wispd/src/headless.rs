@@ -0,0 +1,288 @@
+//! `wispd --headless`: the notification source, its control interface and the sinks,
+//! with no UI. The source's runtime runs on the main thread, events go straight to the
+//! sinks, and SIGTERM or SIGINT shut the source down cleanly: every open notification is
+//! closed, senders are told, and the bus name is released before exiting.
+
+use std::{future::Future, time::Duration};
+
+use anyhow::{Result, anyhow};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use wisp_source::{SourceConfig, WispSource};
+use wisp_types::{NotificationEvent, loggable::LoggableEvent, systemd};
+
+use super::{
+    delivered::DeliveredLog,
+    sink::{SinkSection, Sinks},
+};
+
+/// `[ui]` keys the source reads; every other UI setting is ignored without a UI.
+const SOURCE_UI_KEYS: &[&str] = &[
+    "dnd",
+    "min_timeout_ms",
+    "max_timeout_ms",
+    "timeout_clamp_exempt_critical",
+    "critical_never_expires",
+    "show_icons",
+    "history_ttl",
+    "persist_history",
+];
+
+/// Top-level sections only the UI daemon reads; headless mode serves no metrics.
+const UI_ONLY_SECTIONS: &[&str] = &["mirror", "metrics"];
+
+/// How long queued webhook events may take to go out after a shutdown signal.
+const SINK_GRACE: Duration = Duration::from_secs(5);
+
+/// Config keys in `raw` that have no effect in headless mode, as dotted paths.
+pub(crate) fn ignored_keys(raw: &str) -> Vec<String> {
+    let Ok(table) = raw.parse::<toml::Table>() else {
+        return Vec::new();
+    };
+    let mut ignored: Vec<String> = UI_ONLY_SECTIONS
+        .iter()
+        .filter(|section| table.contains_key(**section))
+        .map(|section| section.to_string())
+        .collect();
+    if let Some(ui) = table.get("ui").and_then(toml::Value::as_table) {
+        ignored.extend(
+            ui.keys()
+                .filter(|key| !SOURCE_UI_KEYS.contains(&key.as_str()))
+                .map(|key| format!("ui.{key}")),
+        );
+    }
+    ignored
+}
+
+/// Runs the source until a shutdown signal, blocking the calling thread.
+pub(crate) fn run(
+    source_cfg: SourceConfig,
+    sink_cfg: SinkSection,
+    delivered: DeliveredLog,
+    startup_timeout: Duration,
+) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .map_err(|err| anyhow!("failed to build tokio runtime: {err}"))?;
+
+    runtime.block_on(async move {
+        let dbus_name = source_cfg.dbus_name.clone();
+        let (source, events, service) =
+            tokio::time::timeout(startup_timeout, WispSource::start_dbus(source_cfg))
+                .await
+                .map_err(|_| anyhow!("bus name not owned after {startup_timeout:?}"))?
+                .map_err(|err| anyhow!(err))?;
+        info!(dbus_name = %dbus_name, "wispd started headless (D-Bus and sinks only)");
+        if let Err(err) = systemd::Notifier::from_env().ready() {
+            warn!(%err, "failed to notify systemd of readiness");
+        }
+
+        let sinks = Sinks::start(sink_cfg, delivered);
+        forward(&source, events, sinks, shutdown_signal()).await;
+        drop(service);
+        Ok(())
+    })
+}
+
+/// Hands source events to the sinks until `shutdown` resolves or the source stops, then
+/// shuts the source down and delivers the `Closed` events that produces.
+pub(crate) async fn forward(
+    source: &WispSource,
+    mut events: mpsc::Receiver<NotificationEvent>,
+    mut sinks: Sinks,
+    shutdown: impl Future<Output = ()>,
+) {
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            maybe_event = events.recv() => {
+                let Some(event) = maybe_event else {
+                    info!("source events channel ended");
+                    break;
+                };
+                debug!(event = ?LoggableEvent(&event), "headless event");
+                sinks.offer(&event);
+            }
+            () = &mut shutdown => {
+                info!("shutdown requested");
+                break;
+            }
+        }
+    }
+
+    source.shutdown().await;
+    while let Ok(event) = events.try_recv() {
+        sinks.offer(&event);
+    }
+    sinks.finish(SINK_GRACE).await;
+}
+
+/// Resolves on the first SIGTERM or SIGINT.
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let (mut term, mut int) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(term), Ok(int)) => (term, int),
+        (Err(err), _) | (_, Err(err)) => {
+            warn!(%err, "failed to subscribe to SIGTERM/SIGINT; only the bus ending stops wispd");
+            return std::future::pending().await;
+        }
+    };
+    tokio::select! {
+        _ = term.recv() => info!("SIGTERM received"),
+        _ = int.recv() => info!("SIGINT received"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        warn!(%err, "failed to listen for ctrl-c");
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use tokio::sync::oneshot;
+
+    use super::super::{
+        sink::{AuditConfig, AuditLog, HttpClient, WebhookConfig, WebhookSink},
+        testing::temp_dir,
+    };
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<serde_json::Value>>>);
+
+    impl HttpClient for Recorder {
+        async fn post_json(&self, _url: &str, body: String) -> Result<()> {
+            self.0.lock().unwrap().push(serde_json::from_str(&body)?);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ui_only_keys_are_reported() {
+        let raw = r#"
+            [source]
+            default_timeout_ms = 5000
+            [ui]
+            width = 300
+            max_timeout_ms = 60000
+            [ui.animation]
+            enabled = true
+            [[ui.dnd.schedule]]
+            from = "22:00"
+            until = "07:00"
+            [mirror]
+            show_popups = true
+        "#;
+        assert_eq!(ignored_keys(raw), ["mirror", "ui.animation", "ui.width"]);
+        assert!(ignored_keys("[sink.webhook]\nurl = \"http://x\"").is_empty());
+    }
+
+    #[tokio::test]
+    async fn headless_mode_forwards_notify_to_the_sinks_and_closes_on_shutdown() {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let cfg = SourceConfig::builder()
+            .dbus_name(format!("org.wispd.Headless.{unique}"))
+            .build();
+        let Ok((source, events, service)) = WispSource::start_dbus(cfg.clone()).await else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+        let Ok(client) = zbus::Connection::session().await else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+
+        let recorder = Recorder::default();
+        let webhook = WebhookSink::spawn(
+            WebhookConfig {
+                url: "http://127.0.0.1:9/hook".to_string(),
+                ..WebhookConfig::default()
+            },
+            recorder.clone(),
+            DeliveredLog::default(),
+        );
+        let audit_path = temp_dir("headless-audit").join("audit.ndjson");
+        let audit = AuditLog::open(&AuditConfig {
+            path: audit_path.clone(),
+        })
+        .unwrap();
+        let sinks = Sinks {
+            webhook: Some(webhook),
+            audit: Some(audit),
+        };
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let headless = tokio::spawn(async move {
+            forward(&source, events, sinks, async {
+                let _ = stop_rx.await;
+            })
+            .await;
+            drop(service);
+        });
+
+        client
+            .call_method(
+                Some(cfg.dbus_name.as_str()),
+                cfg.dbus_path.as_str(),
+                Some(wisp_source::DBUS_INTERFACE),
+                "Notify",
+                &(
+                    "backup",
+                    0u32,
+                    "",
+                    "nightly backup failed",
+                    "",
+                    Vec::<String>::new(),
+                    HashMap::<&str, zbus::zvariant::Value>::new(),
+                    -1i32,
+                ),
+            )
+            .await
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while recorder.0.lock().unwrap().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        stop_tx.send(()).unwrap();
+        headless.await.unwrap();
+        let bodies = recorder.0.lock().unwrap().clone();
+        let kinds: Vec<&str> = bodies.iter().filter_map(|b| b["event"].as_str()).collect();
+        assert_eq!(kinds, ["received", "closed"]);
+        assert_eq!(
+            bodies[0]["notification"]["summary"],
+            "nightly backup failed"
+        );
+
+        let audited: Vec<serde_json::Value> = std::fs::read_to_string(&audit_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = audited.iter().filter_map(|b| b["event"].as_str()).collect();
+        assert_eq!(kinds, ["received", "closed"]);
+        assert_eq!(
+            audited[0]["notification"]["summary"],
+            "nightly backup failed"
+        );
+    }
+}
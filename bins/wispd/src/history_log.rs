@@ -0,0 +1,259 @@
+//! `history.ndjson` in the state dir (`ui.persist_history`): the source's
+//! [`HistoryStore`], kept as a versioned [`LogFile`]. Each closed notification is
+//! appended as a line, and the source rewrites the file with only its live entries when
+//! it starts, after a star change or a deletion, and once dropped entries take up more
+//! than [`COMPACT_THRESHOLD_BYTES`].
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use tracing::{debug, warn};
+use wisp_source::{HistoryEntry, HistoryStore};
+
+use super::state::{self, LogFile, StateError};
+
+/// `history.ndjson` in the state dir.
+pub(crate) const LOG_FILE: LogFile = LogFile {
+    name: "history.ndjson",
+    migrations: &[add_version],
+    validate: state::validate_record_as::<HistoryEntry>,
+};
+
+/// Size past which the file is compacted after an append or a prune.
+const COMPACT_THRESHOLD_BYTES: u64 = 256 * 1024;
+
+/// The history file in the state dir `dir`.
+#[derive(Debug)]
+pub(crate) struct HistoryLog {
+    dir: PathBuf,
+    /// Set when the file came from a newer wispd, which it is left to.
+    read_only: AtomicBool,
+}
+
+impl HistoryLog {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    fn writable(&self) -> bool {
+        let read_only = self.read_only.load(Ordering::Relaxed);
+        if read_only {
+            debug!(
+                file = LOG_FILE.name,
+                "history file from a newer wispd left alone"
+            );
+        }
+        !read_only
+    }
+}
+
+impl HistoryStore for HistoryLog {
+    /// A missing or unreadable file yields an empty history.
+    fn load(&self) -> Vec<HistoryEntry> {
+        match state::load_log::<HistoryEntry>(&self.dir, &LOG_FILE) {
+            Ok(mut entries) => {
+                entries.reverse();
+                entries
+            }
+            Err(err) => {
+                if matches!(err, StateError::TooNew { .. }) {
+                    self.read_only.store(true, Ordering::Relaxed);
+                }
+                warn!(
+                    file = LOG_FILE.name,
+                    %err,
+                    "ignoring unreadable history; `wispd --repair-state` checks state files"
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    fn append(&self, entry: &HistoryEntry) {
+        if !self.writable() {
+            return;
+        }
+        if let Err(err) = state::append_log(&self.dir, &LOG_FILE, entry) {
+            warn!(
+                file = LOG_FILE.name,
+                id = entry.id,
+                %err,
+                "failed to append to the history file"
+            );
+        }
+    }
+
+    fn rewrite(&self, entries: &[HistoryEntry]) {
+        if !self.writable() {
+            return;
+        }
+        let oldest_first: Vec<&HistoryEntry> = entries.iter().rev().collect();
+        if let Err(err) = state::save_log(&self.dir, &LOG_FILE, &oldest_first) {
+            warn!(file = LOG_FILE.name, %err, "failed to rewrite the history file");
+        }
+    }
+
+    fn wants_compaction(&self) -> bool {
+        state::log_len(&self.dir, &LOG_FILE) > COMPACT_THRESHOLD_BYTES
+    }
+}
+
+/// Version 0 is the file from before it had a header, with the same lines.
+fn add_version(_: &mut serde_json::Value) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use wisp_types::{CloseReason, Notification, ReplaceChain};
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn entry(id: u32) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            notification: Notification {
+                summary: format!("n{id}"),
+                ..Notification::default()
+            },
+            sender: None,
+            reason: CloseReason::Expired,
+            received_at: SystemTime::UNIX_EPOCH,
+            chain: ReplaceChain::default(),
+            starred: false,
+        }
+    }
+
+    fn ids(entries: &[HistoryEntry]) -> Vec<u32> {
+        entries.iter().map(|entry| entry.id).collect()
+    }
+
+    fn lines(log: &HistoryLog) -> Vec<String> {
+        fs::read_to_string(log.dir.join(LOG_FILE.name))
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_appended_after_a_header_and_rewritten_oldest_first() {
+        let log = HistoryLog::new(temp_dir("history-log"));
+        assert!(log.load().is_empty(), "no file yet");
+
+        log.append(&entry(1));
+        log.append(&entry(2));
+        let written = lines(&log);
+        assert_eq!(written[0], r#"{"version":1}"#);
+        assert_eq!(written.len(), 3);
+        assert_eq!(ids(&log.load()), [2, 1], "newest first");
+
+        let mut starred = entry(3);
+        starred.starred = true;
+        log.rewrite(&[starred, entry(1)]);
+        assert_eq!(lines(&log)[0], r#"{"version":1}"#);
+        let loaded = log.load();
+        assert_eq!(ids(&loaded), [3, 1]);
+        assert!(loaded[0].starred);
+
+        log.rewrite(&[]);
+        assert_eq!(lines(&log), [r#"{"version":1}"#]);
+        assert!(!log.wants_compaction());
+        let _ = fs::remove_dir_all(&log.dir);
+    }
+
+    #[test]
+    fn headerless_files_migrate_and_bad_lines_are_skipped() {
+        let log = HistoryLog::new(temp_dir("history-log-v0"));
+        let path = log.dir.join(LOG_FILE.name);
+        let old = [entry(1), entry(2)]
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect::<Vec<_>>();
+        fs::write(&path, format!("{}\nnot json\n{}\n", old[0], old[1])).unwrap();
+
+        assert_eq!(ids(&log.load()), [2, 1], "the bad line is skipped");
+        let migrated = lines(&log);
+        assert_eq!(
+            migrated[0], r#"{"version":1}"#,
+            "written back with a header"
+        );
+        assert_eq!(migrated.len(), 3);
+
+        fs::write(&path, "{\"version\":9}\n").unwrap();
+        assert!(log.load().is_empty(), "a newer file is not read");
+        log.rewrite(&[entry(3)]);
+        log.append(&entry(4));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"version\":9}\n",
+            "nor written"
+        );
+        let _ = fs::remove_dir_all(&log.dir);
+    }
+
+    #[test]
+    fn a_large_file_asks_for_compaction() {
+        let log = HistoryLog::new(temp_dir("history-log-large"));
+        let padded = HistoryEntry {
+            notification: Notification {
+                body: "x".repeat(4_096),
+                ..Notification::default()
+            },
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1),
+            ..entry(1)
+        };
+        while !log.wants_compaction() {
+            log.append(&padded);
+        }
+        assert!(state::log_len(&log.dir, &LOG_FILE) > COMPACT_THRESHOLD_BYTES);
+        log.rewrite(&[padded]);
+        assert!(!log.wants_compaction());
+        let _ = fs::remove_dir_all(&log.dir);
+    }
+
+    #[test]
+    fn repair_checks_every_line_of_the_history() {
+        let dir = temp_dir("history-log-repair");
+        let path = dir.join(LOG_FILE.name);
+        let line = serde_json::to_string(&entry(1)).unwrap();
+
+        let report = |quarantine| state::repair_logs(&dir, &[LOG_FILE], quarantine).remove(0);
+        assert!(
+            report(false)
+                .to_string()
+                .ends_with("history.ndjson: not present")
+        );
+        fs::write(&path, format!("{line}\n")).unwrap();
+        assert!(report(false).to_string().ends_with("ok (version 0)"));
+        fs::write(&path, format!("{{\"version\":1}}\n{line}\n")).unwrap();
+        assert!(report(false).to_string().ends_with("ok (version 1)"));
+
+        fs::write(&path, format!("{{\"version\":1}}\n{line}\n{{\"id\":2}}\n")).unwrap();
+        let broken = report(false);
+        assert!(broken.needs_repair());
+        assert!(broken.to_string().contains("line 3"), "{broken}");
+        fs::write(&path, "{\"version\":\"one\"}\n").unwrap();
+        assert!(
+            report(false)
+                .to_string()
+                .contains("not a non-negative integer")
+        );
+
+        assert!(!report(true).needs_repair());
+        assert!(!path.exists());
+        assert!(dir.join("history.ndjson.broken").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,803 @@
+//! The history panel: recently closed notifications in a list, opened and closed with
+//! `org.wispd.Ui1.ToggleHistoryPanel`. An entry can be shown again or deleted from the
+//! keyboard, and entries ticked with their checkboxes are deleted together. Deletions
+//! go to the source, which drops them from its history file too; the panel only mirrors
+//! the source's list and takes it again after every change. Opened from a flood summary
+//! it lists one app's entries only.
+//!
+//! A search field and urgency/"today" chips narrow the list. Their state,
+//! [`HistoryFilter`], lives on the UI and is cleared when the panel closes; typing while
+//! the panel has the keyboard goes to the search, and Enter then invokes the top
+//! result's default action.
+//!
+//! The panel sits at the top of the popup stack, which makes room for it, and with
+//! `[ui.history] auto_close_ms` closes once nothing has touched it for that long.
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant, SystemTime},
+};
+
+use iced::keyboard::{self, key::Named};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use wisp_source::HistoryEntry;
+use wisp_types::Urgency;
+
+/// Size of the panel surface.
+pub(crate) const PANEL_WIDTH: u32 = 480;
+pub(crate) const PANEL_HEIGHT: u32 = 420;
+
+/// `[ui.history]`: behaviour of the history panel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct HistorySection {
+    /// Closes the panel after this long without keys, clicks, scrolling or the pointer
+    /// over it; 0 leaves it open until closed.
+    pub(crate) auto_close_ms: u64,
+}
+
+impl HistorySection {
+    pub(crate) fn auto_close(&self) -> Option<Duration> {
+        (self.auto_close_ms > 0).then(|| Duration::from_millis(self.auto_close_ms))
+    }
+}
+
+/// One history entry as the panel lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HistoryRow {
+    pub(crate) id: u32,
+    pub(crate) app_name: String,
+    pub(crate) summary: String,
+    pub(crate) starred: bool,
+    /// Whether the notification has a `default` action.
+    pub(crate) default_action: bool,
+}
+
+impl From<&HistoryEntry> for HistoryRow {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            id: entry.id,
+            app_name: entry.notification.app_name.clone(),
+            summary: entry.notification.summary.clone(),
+            starred: entry.starred,
+            default_action: entry
+                .notification
+                .actions
+                .iter()
+                .any(|action| action.key == DEFAULT_ACTION_KEY),
+        }
+    }
+}
+
+const DEFAULT_ACTION_KEY: &str = "default";
+
+/// Whether `haystack` matches the search `query`: every whitespace-separated term of
+/// the query occurs in it, ignoring case, either as a run of graphemes or spread in
+/// order over one word ("thndrbrd" finds "Thunderbird"). Graphemes are compared whole,
+/// so "e" does not match the "é" of a decomposed "é". An empty query matches anything.
+pub(crate) fn matches(query: &str, haystack: &str) -> bool {
+    let haystack = folded(haystack);
+    query.split_whitespace().all(|term| {
+        let term = folded(term);
+        haystack.windows(term.len()).any(|run| run == term)
+            || haystack
+                .split(|grapheme| grapheme.chars().all(char::is_whitespace))
+                .any(|word| is_subsequence(&term, word))
+    })
+}
+
+/// `text`'s graphemes, each lowercased.
+fn folded(text: &str) -> Vec<String> {
+    text.graphemes(true).map(str::to_lowercase).collect()
+}
+
+fn is_subsequence(needle: &[String], haystack: &[String]) -> bool {
+    let mut rest = haystack.iter();
+    needle.iter().all(|grapheme| rest.any(|g| g == grapheme))
+}
+
+/// Search and quick filters of the history panel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HistoryFilter {
+    /// Matched against app name, summary and body with [`matches`].
+    pub(crate) query: String,
+    /// Only entries of this urgency.
+    pub(crate) urgency: Option<Urgency>,
+    /// Only entries received since the start of the day.
+    pub(crate) today: bool,
+}
+
+/// A change to the [`HistoryFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum FilterEdit {
+    /// The search field's new text.
+    Query(String),
+    /// Typed while the panel has the keyboard but not the search field.
+    Type(String),
+    /// Backspace, likewise.
+    Erase,
+    /// An urgency chip; picking the active one again clears it.
+    Urgency(Urgency),
+    /// The "today" chip.
+    Today,
+}
+
+impl HistoryFilter {
+    /// Whether anything narrows the list.
+    pub(crate) fn is_active(&self) -> bool {
+        !self.query.trim().is_empty() || self.urgency.is_some() || self.today
+    }
+
+    pub(crate) fn edit(&mut self, edit: FilterEdit) {
+        match edit {
+            FilterEdit::Query(query) => self.query = query,
+            FilterEdit::Type(text) => self.query.push_str(&text),
+            FilterEdit::Erase => {
+                let kept = self
+                    .query
+                    .grapheme_indices(true)
+                    .last()
+                    .map_or(0, |(at, _)| at);
+                self.query.truncate(kept);
+            }
+            FilterEdit::Urgency(urgency) => {
+                self.urgency = (self.urgency.as_ref() != Some(&urgency)).then_some(urgency);
+            }
+            FilterEdit::Today => self.today = !self.today,
+        }
+    }
+
+    /// Whether `entry` is listed; "today" means received at or after `today_start`.
+    pub(crate) fn accepts(&self, entry: &HistoryEntry, today_start: SystemTime) -> bool {
+        let n = &entry.notification;
+        self.urgency
+            .as_ref()
+            .is_none_or(|urgency| n.urgency == *urgency)
+            && (!self.today || entry.received_at >= today_start)
+            && matches(
+                &self.query,
+                &format!("{}\n{}\n{}", n.app_name, n.summary, n.body),
+            )
+    }
+}
+
+/// Keys the panel answers to while it has the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PanelKey {
+    /// Down, or Ctrl+J; plain letters are search text.
+    Next,
+    /// Up, or Ctrl+K.
+    Previous,
+    /// Space ticks or unticks the highlighted row while nothing is searched; the UI
+    /// turns it into this then, and into search text otherwise.
+    Toggle,
+    /// Enter shows the highlighted entry again; while filtered, it invokes its default
+    /// action if it has one.
+    Open,
+    /// Delete removes the highlighted entry.
+    Delete,
+    /// Escape.
+    Close,
+}
+
+impl PanelKey {
+    pub(crate) fn from_key(
+        key: keyboard::Key<&str>,
+        modifiers: keyboard::Modifiers,
+    ) -> Option<Self> {
+        match key {
+            keyboard::Key::Named(Named::ArrowDown) => Some(Self::Next),
+            keyboard::Key::Named(Named::ArrowUp) => Some(Self::Previous),
+            keyboard::Key::Character("j") if modifiers.control() => Some(Self::Next),
+            keyboard::Key::Character("k") if modifiers.control() => Some(Self::Previous),
+            keyboard::Key::Named(Named::Enter) => Some(Self::Open),
+            keyboard::Key::Named(Named::Delete) => Some(Self::Delete),
+            keyboard::Key::Named(Named::Escape) => Some(Self::Close),
+            _ => None,
+        }
+    }
+}
+
+/// Input for the panel, from its surface and its buttons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PanelMessage {
+    Key(PanelKey),
+    /// The panel surface gained or lost the keyboard.
+    Focused(bool),
+    /// A row's checkbox.
+    Toggle(u32),
+    /// "Select all", which unticks everything once all rows are ticked.
+    ToggleAll,
+    DeleteSelected,
+    ClearAll,
+    /// A row clicked.
+    Show(u32),
+    /// A row's star.
+    ToggleStar(u32),
+    Close,
+    /// The pointer moved or scrolled over the panel.
+    Pointer,
+    /// The search or a chip; applied by the UI, which holds the filter.
+    Filter(FilterEdit),
+}
+
+impl PanelMessage {
+    /// Whether this is the user at the panel, which keeps it from closing as idle.
+    /// Losing the keyboard is not.
+    pub(crate) fn is_activity(&self) -> bool {
+        *self != Self::Focused(false)
+    }
+}
+
+/// What the UI has to do for the panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PanelCommand {
+    /// Show this entry again as a popup.
+    Show(u32),
+    /// Invoke this entry's default action.
+    InvokeDefault(u32),
+    /// Delete these entries from the history.
+    Remove(Vec<u32>),
+    /// Delete the whole history.
+    Clear,
+    /// Star or unstar this entry.
+    SetStarred {
+        id: u32,
+        starred: bool,
+    },
+    Close,
+}
+
+/// Rows, selection and keyboard navigation of the open panel.
+#[derive(Debug, Default)]
+pub(crate) struct HistoryPanel {
+    /// The source's history, newest first.
+    entries: Vec<HistoryEntry>,
+    /// The entries the filters let through.
+    rows: Vec<HistoryRow>,
+    /// Whether a [`HistoryFilter`] narrows `rows`.
+    filtered: bool,
+    /// Index into `rows` of the row keyboard navigation is on.
+    highlighted: usize,
+    /// Ids ticked for a bulk delete.
+    selected: BTreeSet<u32>,
+    /// Whether the panel surface has the keyboard; the highlight only shows then.
+    focused: bool,
+    /// Only entries from this app are listed.
+    app: Option<String>,
+    /// When the panel was opened or last used.
+    last_activity: Option<Instant>,
+}
+
+impl HistoryPanel {
+    /// A panel listing `app`'s entries only.
+    pub(crate) fn for_app(app: String) -> Self {
+        Self {
+            app: Some(app),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn app(&self) -> Option<&str> {
+        self.app.as_deref()
+    }
+
+    /// Takes the source's current history, listing what `filter` accepts. The highlight
+    /// stays on its entry while that is listed, else on the same position; ticks on
+    /// entries that went are dropped.
+    pub(crate) fn set_entries(
+        &mut self,
+        entries: &[HistoryEntry],
+        filter: &HistoryFilter,
+        today_start: SystemTime,
+    ) {
+        self.entries = entries.to_vec();
+        self.list(filter, today_start);
+    }
+
+    /// Lists the entries `filter` accepts after it changed, highlighting the top result.
+    pub(crate) fn refilter(&mut self, filter: &HistoryFilter, today_start: SystemTime) {
+        self.rows.clear();
+        self.highlighted = 0;
+        self.list(filter, today_start);
+    }
+
+    fn list(&mut self, filter: &HistoryFilter, today_start: SystemTime) {
+        let highlighted = self.highlighted_id();
+        self.filtered = filter.is_active();
+        self.rows = self
+            .entries
+            .iter()
+            .filter(|entry| {
+                self.app
+                    .as_ref()
+                    .is_none_or(|app| *app == entry.notification.app_name)
+                    && filter.accepts(entry, today_start)
+            })
+            .map(HistoryRow::from)
+            .collect();
+        self.selected
+            .retain(|id| self.rows.iter().any(|row| row.id == *id));
+        if let Some(position) =
+            highlighted.and_then(|id| self.rows.iter().position(|row| row.id == id))
+        {
+            self.highlighted = position;
+        }
+        self.highlighted = self.highlighted.min(self.rows.len().saturating_sub(1));
+    }
+
+    pub(crate) fn rows(&self) -> &[HistoryRow] {
+        &self.rows
+    }
+
+    /// The row drawn highlighted: the navigation row while the panel has the keyboard.
+    pub(crate) fn highlighted(&self) -> Option<u32> {
+        self.focused.then(|| self.highlighted_id()).flatten()
+    }
+
+    pub(crate) fn is_selected(&self, id: u32) -> bool {
+        self.selected.contains(&id)
+    }
+
+    pub(crate) fn selected_count(&self) -> usize {
+        self.selected.len()
+    }
+
+    pub(crate) fn all_selected(&self) -> bool {
+        !self.rows.is_empty() && self.selected.len() == self.rows.len()
+    }
+
+    /// Restarts the inactivity timer.
+    pub(crate) fn touch(&mut self, now: Instant) {
+        self.last_activity = Some(now);
+    }
+
+    /// Whether `timeout` has passed since the panel was last touched.
+    pub(crate) fn is_idle(&self, now: Instant, timeout: Duration) -> bool {
+        self.last_activity
+            .is_some_and(|at| now.saturating_duration_since(at) >= timeout)
+    }
+
+    /// Applies `message`, returning what the UI has to do about it.
+    pub(crate) fn update(&mut self, message: PanelMessage) -> Option<PanelCommand> {
+        match message {
+            // A key only reaches the surface that has the keyboard.
+            PanelMessage::Key(key) => {
+                self.focused = true;
+                self.key(key)
+            }
+            PanelMessage::Focused(focused) => {
+                self.focused = focused;
+                None
+            }
+            PanelMessage::Toggle(id) => {
+                self.toggle(id);
+                None
+            }
+            PanelMessage::ToggleAll => {
+                if self.all_selected() {
+                    self.selected.clear();
+                } else {
+                    self.selected = self.rows.iter().map(|row| row.id).collect();
+                }
+                None
+            }
+            PanelMessage::DeleteSelected => {
+                let ids: Vec<u32> = self
+                    .rows
+                    .iter()
+                    .map(|row| row.id)
+                    .filter(|id| self.selected.contains(id))
+                    .collect();
+                (!ids.is_empty()).then_some(PanelCommand::Remove(ids))
+            }
+            PanelMessage::ClearAll if self.rows.is_empty() => None,
+            // Filtered, "clear all" means every entry listed.
+            PanelMessage::ClearAll if self.app.is_some() => Some(PanelCommand::Remove(
+                self.rows.iter().map(|row| row.id).collect(),
+            )),
+            PanelMessage::ClearAll => Some(PanelCommand::Clear),
+            PanelMessage::Show(id) => {
+                if let Some(position) = self.rows.iter().position(|row| row.id == id) {
+                    self.highlighted = position;
+                }
+                Some(PanelCommand::Show(id))
+            }
+            PanelMessage::ToggleStar(id) => {
+                let row = self.rows.iter().find(|row| row.id == id)?;
+                Some(PanelCommand::SetStarred {
+                    id,
+                    starred: !row.starred,
+                })
+            }
+            PanelMessage::Close => Some(PanelCommand::Close),
+            PanelMessage::Pointer | PanelMessage::Filter(_) => None,
+        }
+    }
+
+    /// Navigation wraps around at both ends.
+    fn key(&mut self, key: PanelKey) -> Option<PanelCommand> {
+        let len = self.rows.len();
+        match key {
+            PanelKey::Next if len > 0 => self.highlighted = (self.highlighted + 1) % len,
+            PanelKey::Previous if len > 0 => self.highlighted = (self.highlighted + len - 1) % len,
+            PanelKey::Next | PanelKey::Previous => {}
+            PanelKey::Toggle => {
+                if let Some(id) = self.highlighted_id() {
+                    self.toggle(id);
+                }
+            }
+            PanelKey::Open => {
+                let row = self.rows.get(self.highlighted)?;
+                return Some(if self.filtered && row.default_action {
+                    PanelCommand::InvokeDefault(row.id)
+                } else {
+                    PanelCommand::Show(row.id)
+                });
+            }
+            PanelKey::Delete => {
+                return self
+                    .highlighted_id()
+                    .map(|id| PanelCommand::Remove(vec![id]));
+            }
+            PanelKey::Close => return Some(PanelCommand::Close),
+        }
+        None
+    }
+
+    fn highlighted_id(&self) -> Option<u32> {
+        self.rows.get(self.highlighted).map(|row| row.id)
+    }
+
+    fn toggle(&mut self, id: u32) {
+        if !self.selected.remove(&id) && self.rows.iter().any(|row| row.id == id) {
+            self.selected.insert(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use wisp_types::{CloseReason, Notification, ReplaceChain};
+
+    use super::*;
+
+    fn entries(ids: &[u32]) -> Vec<HistoryEntry> {
+        ids.iter()
+            .map(|&id| HistoryEntry {
+                id,
+                notification: Notification {
+                    summary: format!("n{id}"),
+                    ..Notification::default()
+                },
+                sender: None,
+                reason: CloseReason::Expired,
+                received_at: SystemTime::UNIX_EPOCH,
+                chain: ReplaceChain::default(),
+                starred: false,
+            })
+            .collect()
+    }
+
+    fn panel(ids: &[u32]) -> HistoryPanel {
+        let mut panel = HistoryPanel::default();
+        panel.set_entries(
+            &entries(ids),
+            &HistoryFilter::default(),
+            SystemTime::UNIX_EPOCH,
+        );
+        panel
+    }
+
+    fn panel_with_focus(ids: &[u32]) -> HistoryPanel {
+        let mut panel = panel(ids);
+        panel.update(PanelMessage::Focused(true));
+        panel
+    }
+
+    #[test]
+    fn keyboard_navigation_wraps_and_acts_on_the_highlighted_row() {
+        let mut panel = panel(&[9, 7, 2]);
+        assert_eq!(
+            panel.highlighted(),
+            None,
+            "no highlight without the keyboard"
+        );
+        panel = panel_with_focus(&[9, 7, 2]);
+        assert_eq!(panel.highlighted(), Some(9));
+
+        panel.update(PanelMessage::Key(PanelKey::Previous));
+        assert_eq!(panel.highlighted(), Some(2), "up from the top wraps");
+        panel.update(PanelMessage::Key(PanelKey::Next));
+        assert_eq!(panel.highlighted(), Some(9), "down from the bottom wraps");
+        panel.update(PanelMessage::Key(PanelKey::Next));
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Open)),
+            Some(PanelCommand::Show(7))
+        );
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Delete)),
+            Some(PanelCommand::Remove(vec![7]))
+        );
+
+        panel.set_entries(
+            &entries(&[9, 2]),
+            &HistoryFilter::default(),
+            SystemTime::UNIX_EPOCH,
+        );
+        assert_eq!(
+            panel.highlighted(),
+            Some(2),
+            "stays at the deleted row's place"
+        );
+        panel.set_entries(
+            &entries(&[4, 9, 2]),
+            &HistoryFilter::default(),
+            SystemTime::UNIX_EPOCH,
+        );
+        assert_eq!(panel.highlighted(), Some(2), "follows its entry");
+
+        panel.update(PanelMessage::Focused(false));
+        assert_eq!(panel.highlighted(), None);
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Close)),
+            Some(PanelCommand::Close)
+        );
+
+        let mut empty = panel_with_focus(&[]);
+        for key in [
+            PanelKey::Next,
+            PanelKey::Previous,
+            PanelKey::Open,
+            PanelKey::Delete,
+        ] {
+            assert_eq!(empty.update(PanelMessage::Key(key)), None);
+        }
+        assert_eq!(empty.highlighted(), None);
+
+        let none = keyboard::Modifiers::empty();
+        let ctrl = keyboard::Modifiers::CTRL;
+        assert_eq!(
+            PanelKey::from_key(keyboard::Key::Named(Named::ArrowDown), none),
+            Some(PanelKey::Next)
+        );
+        assert_eq!(
+            PanelKey::from_key(keyboard::Key::Named(Named::ArrowUp), none),
+            Some(PanelKey::Previous)
+        );
+        assert_eq!(
+            PanelKey::from_key(keyboard::Key::Character("j"), ctrl),
+            Some(PanelKey::Next)
+        );
+        assert_eq!(
+            PanelKey::from_key(keyboard::Key::Character("k"), ctrl),
+            Some(PanelKey::Previous)
+        );
+        assert_eq!(
+            PanelKey::from_key(keyboard::Key::Character("j"), none),
+            None,
+            "letters go to the search"
+        );
+    }
+
+    #[test]
+    fn ticked_rows_are_deleted_together() {
+        let mut panel = panel_with_focus(&[5, 4, 3, 2]);
+        assert_eq!(panel.update(PanelMessage::DeleteSelected), None);
+
+        panel.update(PanelMessage::Toggle(2));
+        panel.update(PanelMessage::Key(PanelKey::Toggle));
+        panel.update(PanelMessage::Toggle(42));
+        assert!(panel.is_selected(5) && panel.is_selected(2));
+        assert_eq!(panel.selected_count(), 2, "unknown ids are not ticked");
+        assert_eq!(
+            panel.update(PanelMessage::DeleteSelected),
+            Some(PanelCommand::Remove(vec![5, 2])),
+            "in list order"
+        );
+
+        panel.set_entries(
+            &entries(&[4, 3]),
+            &HistoryFilter::default(),
+            SystemTime::UNIX_EPOCH,
+        );
+        assert_eq!(panel.selected_count(), 0, "deleted rows lose their ticks");
+
+        panel.update(PanelMessage::ToggleAll);
+        assert!(panel.all_selected());
+        assert_eq!(
+            panel.update(PanelMessage::DeleteSelected),
+            Some(PanelCommand::Remove(vec![4, 3]))
+        );
+        panel.update(PanelMessage::ToggleAll);
+        assert_eq!(panel.selected_count(), 0, "select all again unticks");
+
+        assert_eq!(
+            panel.update(PanelMessage::ClearAll),
+            Some(PanelCommand::Clear)
+        );
+        panel.set_entries(&[], &HistoryFilter::default(), SystemTime::UNIX_EPOCH);
+        assert_eq!(panel.update(PanelMessage::ClearAll), None);
+        assert!(!panel.all_selected());
+    }
+    #[test]
+    fn a_row_star_flips_its_entry() {
+        let mut all = entries(&[3, 2]);
+        all[1].starred = true;
+        let mut panel = HistoryPanel::default();
+        panel.set_entries(&all, &HistoryFilter::default(), SystemTime::UNIX_EPOCH);
+        assert_eq!(
+            panel.update(PanelMessage::ToggleStar(3)),
+            Some(PanelCommand::SetStarred {
+                id: 3,
+                starred: true
+            })
+        );
+        assert_eq!(
+            panel.update(PanelMessage::ToggleStar(2)),
+            Some(PanelCommand::SetStarred {
+                id: 2,
+                starred: false
+            })
+        );
+        assert_eq!(panel.update(PanelMessage::ToggleStar(42)), None);
+    }
+
+    #[test]
+    fn a_panel_for_one_app_lists_and_clears_only_its_entries() {
+        let mut all = entries(&[5, 4, 3]);
+        for entry in &mut all {
+            entry.notification.app_name = if entry.id == 4 { "Mail" } else { "Slack" }.into();
+        }
+        let mut panel = HistoryPanel::for_app("Slack".to_string());
+        panel.set_entries(&all, &HistoryFilter::default(), SystemTime::UNIX_EPOCH);
+        assert_eq!(panel.app(), Some("Slack"));
+        let ids: Vec<u32> = panel.rows().iter().map(|row| row.id).collect();
+        assert_eq!(ids, [5, 3]);
+        assert_eq!(
+            panel.update(PanelMessage::ClearAll),
+            Some(PanelCommand::Remove(vec![5, 3])),
+            "the other apps' entries stay"
+        );
+    }
+
+    #[test]
+    fn inactivity_counts_from_the_last_touch() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let timeout = Duration::from_millis(1_000);
+        let mut panel = panel(&[1]);
+        assert!(!panel.is_idle(at(5_000), timeout), "never opened");
+
+        panel.touch(start);
+        assert!(!panel.is_idle(at(999), timeout));
+        assert!(panel.is_idle(at(1_000), timeout));
+        panel.touch(at(800));
+        assert!(!panel.is_idle(at(1_500), timeout), "touching restarts it");
+        assert!(panel.is_idle(at(1_800), timeout));
+
+        for message in [
+            PanelMessage::Pointer,
+            PanelMessage::Key(PanelKey::Next),
+            PanelMessage::Focused(true),
+            PanelMessage::Toggle(1),
+        ] {
+            assert!(message.is_activity(), "{message:?}");
+        }
+        assert!(!PanelMessage::Focused(false).is_activity());
+
+        assert_eq!(HistorySection::default().auto_close(), None);
+        let section = HistorySection {
+            auto_close_ms: 30_000,
+        };
+        assert_eq!(section.auto_close(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn matching_ignores_case_and_compares_whole_graphemes() {
+        assert!(matches("", "anything"));
+        assert!(matches("  ", ""));
+        assert!(matches("ÉCOLE", "rentrée à l'école"));
+        assert!(
+            matches("école rentrée", "rentrée à l'école"),
+            "terms in any order"
+        );
+        assert!(!matches("école gare", "rentrée à l'école"), "every term");
+        assert!(matches("σοφια", "ΣΟΦΙΑ"));
+        assert!(matches("京", "東京 weather"));
+        assert!(
+            matches("thndrbrd", "Thunderbird: 3 new"),
+            "fuzzy within a word"
+        );
+        assert!(!matches("thmail", "Thunderbird mail"), "not across words");
+
+        let decomposed = "e\u{301}cole";
+        assert!(!matches("ecole", decomposed), "é is one grapheme");
+        assert!(matches("e\u{301}", decomposed));
+        let family = "👨\u{200d}👩\u{200d}👧 photos";
+        assert!(!matches("👩", family), "inside a ZWJ sequence");
+        assert!(matches("👨\u{200d}👩\u{200d}👧", family));
+        assert!(matches("🇩🇪", "Reise 🇩🇪"));
+        assert!(!matches("🇩", "Reise 🇩🇪"));
+    }
+
+    #[test]
+    fn the_filter_narrows_by_text_urgency_and_day() {
+        let today = SystemTime::UNIX_EPOCH + Duration::from_secs(86_400);
+        let mut all = entries(&[3, 2, 1]);
+        all[0].notification.app_name = "Thunderbird".into();
+        all[0].notification.urgency = Urgency::Critical;
+        all[0].received_at = today + Duration::from_secs(60);
+        all[1].notification.body = "Straße gesperrt".into();
+        let listed = |filter: &HistoryFilter| -> Vec<u32> {
+            all.iter()
+                .filter(|entry| filter.accepts(entry, today))
+                .map(|entry| entry.id)
+                .collect()
+        };
+
+        let mut filter = HistoryFilter::default();
+        assert!(!filter.is_active());
+        assert_eq!(listed(&filter), [3, 2, 1]);
+        filter.edit(FilterEdit::Type("STRA".into()));
+        assert_eq!(listed(&filter), [2], "bodies are searched");
+        filter.edit(FilterEdit::Query("thunder".into()));
+        assert_eq!(listed(&filter), [3]);
+        filter.edit(FilterEdit::Query("n1".into()));
+        filter.edit(FilterEdit::Erase);
+        assert_eq!(filter.query, "n");
+
+        filter.edit(FilterEdit::Query(String::new()));
+        filter.edit(FilterEdit::Urgency(Urgency::Critical));
+        assert!(filter.is_active());
+        assert_eq!(listed(&filter), [3]);
+        filter.edit(FilterEdit::Urgency(Urgency::Critical));
+        assert_eq!(filter.urgency, None, "picking it again clears it");
+        filter.edit(FilterEdit::Today);
+        assert_eq!(listed(&filter), [3]);
+
+        let mut query = HistoryFilter {
+            query: "e\u{301}".into(),
+            ..HistoryFilter::default()
+        };
+        query.edit(FilterEdit::Erase);
+        assert_eq!(query.query, "", "erases a whole grapheme");
+    }
+
+    #[test]
+    fn enter_invokes_the_default_action_of_the_top_result_while_filtered() {
+        let mut all = entries(&[3, 2, 1]);
+        all[1].notification.actions = vec![wisp_types::NotificationAction {
+            key: "default".into(),
+            label: "Open".into(),
+        }];
+        let mut panel = panel_with_focus(&[]);
+        let mut filter = HistoryFilter::default();
+        panel.set_entries(&all, &filter, SystemTime::UNIX_EPOCH);
+        panel.update(PanelMessage::Key(PanelKey::Next));
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Open)),
+            Some(PanelCommand::Show(2)),
+            "unfiltered, Enter shows the entry again"
+        );
+
+        filter.edit(FilterEdit::Type("n".into()));
+        panel.refilter(&filter, SystemTime::UNIX_EPOCH);
+        assert_eq!(panel.highlighted(), Some(3), "the top result");
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Open)),
+            Some(PanelCommand::Show(3)),
+            "no default action"
+        );
+        filter.edit(FilterEdit::Type("2".into()));
+        panel.refilter(&filter, SystemTime::UNIX_EPOCH);
+        assert_eq!(panel.rows().len(), 1);
+        assert_eq!(
+            panel.update(PanelMessage::Key(PanelKey::Open)),
+            Some(PanelCommand::InvokeDefault(2))
+        );
+    }
+}
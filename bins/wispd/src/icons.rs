@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+/// Themes searched in order; `hicolor` is the spec's mandatory fallback.
+const THEMES: &[&str] = &["Adwaita", "hicolor"];
+/// Fixed-size directories probed, besides the requested size itself.
+const SIZES: &[u32] = &[16, 22, 24, 32, 48, 64, 96, 128, 256];
+
+static RESOLVER: OnceLock<IconResolver> = OnceLock::new();
+
+/// Resolves an icon-theme name (e.g. `mail-unread-symbolic`) against the session's data
+/// dirs, caching the result.
+pub(crate) fn resolve_icon_name(name: &str, size: u32) -> Option<PathBuf> {
+    RESOLVER
+        .get_or_init(|| IconResolver::new(data_dirs()))
+        .resolve(name, size)
+}
+
+/// `$XDG_DATA_HOME` then `$XDG_DATA_DIRS`, with the spec's defaults.
+pub(crate) fn data_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let system = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    home.into_iter()
+        .chain(
+            system
+                .split(':')
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from),
+        )
+        .collect()
+}
+
+/// PNG-only lookup, since popups render raster images: `<dir>/icons/<theme>/<NxN>/<context>/`
+/// closest to the requested size first, then `<dir>/pixmaps/`.
+#[derive(Debug)]
+pub(crate) struct IconResolver {
+    data_dirs: Vec<PathBuf>,
+    cache: Mutex<HashMap<(String, u32), Option<PathBuf>>>,
+}
+
+impl IconResolver {
+    pub(crate) fn new(data_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            data_dirs,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn resolve(&self, name: &str, size: u32) -> Option<PathBuf> {
+        if name.is_empty() || name.contains('/') {
+            return None;
+        }
+        let key = (name.to_string(), size);
+        if let Some(hit) = self.cache.lock().expect("icon cache poisoned").get(&key) {
+            return hit.clone();
+        }
+        let found = self.lookup(name, size);
+        self.cache
+            .lock()
+            .expect("icon cache poisoned")
+            .insert(key, found.clone());
+        found
+    }
+
+    fn lookup(&self, name: &str, size: u32) -> Option<PathBuf> {
+        let mut sizes: Vec<u32> = SIZES.iter().copied().chain([size]).collect();
+        sizes.sort_by_key(|s| (s.abs_diff(size), *s));
+        sizes.dedup();
+        let file = format!("{name}.png");
+
+        for theme in THEMES {
+            for dir in &self.data_dirs {
+                let theme_dir = dir.join("icons").join(theme);
+                for s in &sizes {
+                    if let Some(path) = find_in_contexts(&theme_dir.join(format!("{s}x{s}")), &file)
+                    {
+                        return Some(path);
+                    }
+                }
+            }
+        }
+        self.data_dirs
+            .iter()
+            .map(|dir| dir.join("pixmaps").join(&file))
+            .find(|path| path.is_file())
+    }
+}
+
+/// `<size_dir>/<context>/<file>` for any context (`apps`, `status`, ...).
+fn find_in_contexts(size_dir: &Path, file: &str) -> Option<PathBuf> {
+    let mut contexts: Vec<PathBuf> = size_dir
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    contexts.sort();
+    contexts
+        .into_iter()
+        .map(|context| context.join(file))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let root = temp_dir(&format!("icons-{name}"));
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"png").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn prefers_the_closest_size_then_falls_back_to_pixmaps() {
+        let root = fixture(
+            "sizes",
+            &[
+                "icons/hicolor/48x48/apps/mail-unread.png",
+                "icons/hicolor/16x16/status/mail-unread.png",
+                "pixmaps/phone.png",
+            ],
+        );
+        let resolver = IconResolver::new(vec![root.clone()]);
+
+        assert_eq!(
+            resolver.resolve("mail-unread", 16),
+            Some(root.join("icons/hicolor/16x16/status/mail-unread.png"))
+        );
+        assert_eq!(
+            resolver.resolve("mail-unread", 40),
+            Some(root.join("icons/hicolor/48x48/apps/mail-unread.png"))
+        );
+        assert_eq!(
+            resolver.resolve("phone", 16),
+            Some(root.join("pixmaps/phone.png"))
+        );
+        assert_eq!(resolver.resolve("missing", 16), None);
+        assert_eq!(resolver.resolve("../pixmaps/phone", 16), None);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn earlier_themes_and_data_dirs_win_and_results_are_cached() {
+        let user = fixture("user", &["icons/hicolor/16x16/apps/chat.png"]);
+        let system = fixture("system", &["icons/Adwaita/16x16/apps/chat.png"]);
+        let resolver = IconResolver::new(vec![user.clone(), system.clone()]);
+
+        let adwaita = system.join("icons/Adwaita/16x16/apps/chat.png");
+        assert_eq!(resolver.resolve("chat", 16), Some(adwaita.clone()));
+
+        let _ = fs::remove_dir_all(&system);
+        assert_eq!(resolver.resolve("chat", 16), Some(adwaita), "cached");
+        let _ = fs::remove_dir_all(user);
+    }
+}
@@ -0,0 +1,195 @@
+//! Popup icons decoded off the UI thread. A new or replaced notification asks for its icon
+//! here; the file is decoded on a blocking task and the handle comes back as
+//! `Message::ImageReady`. Until then the popup keeps the icon's space empty, so its height
+//! does not change when the image arrives. Handles are cached by path and decoded again
+//! when the file's modification time changes, since screenshot tools reuse one path.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use iced::widget::image::Handle;
+
+/// Decoded icons kept once no popup shows them.
+const CAPACITY: usize = 64;
+
+/// Turns an icon file into pixels.
+pub(crate) trait Decoder: fmt::Debug + Send + Sync {
+    fn decode(&self, path: &Path) -> Result<Handle, String>;
+}
+
+/// Decodes with the `image` crate into RGBA.
+#[derive(Debug)]
+pub(crate) struct FileDecoder;
+
+impl Decoder for FileDecoder {
+    fn decode(&self, path: &Path) -> Result<Handle, String> {
+        let pixels = image::open(path)
+            .map_err(|err| err.to_string())?
+            .into_rgba8();
+        let (width, height) = pixels.dimensions();
+        Ok(Handle::from_rgba(width, height, pixels.into_raw()))
+    }
+}
+
+/// Decodes `path` on tokio's blocking pool.
+pub(crate) async fn decode(
+    decoder: Arc<dyn Decoder>,
+    path: PathBuf,
+) -> (PathBuf, Result<Handle, String>) {
+    let task_path = path.clone();
+    let result = tokio::task::spawn_blocking(move || decoder.decode(&task_path))
+        .await
+        .unwrap_or_else(|err| Err(format!("decoder task failed: {err}")));
+    (path, result)
+}
+
+#[derive(Debug)]
+enum State {
+    Pending,
+    Ready(Handle),
+    Failed,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Modification time of the file that was decoded.
+    modified: Option<SystemTime>,
+    state: State,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ImageCache {
+    entries: HashMap<PathBuf, Entry>,
+    /// Oldest first.
+    order: VecDeque<PathBuf>,
+}
+
+impl ImageCache {
+    /// Whether `path` has to be decoded; it is then pending until [`Self::finish`].
+    pub(crate) fn request(&mut self, path: &Path) -> bool {
+        let modified = modified(path);
+        if self
+            .entries
+            .get(path)
+            .is_some_and(|entry| entry.modified == modified)
+        {
+            return false;
+        }
+        self.insert(path.to_path_buf(), modified, State::Pending);
+        true
+    }
+
+    /// Stores a decode result.
+    pub(crate) fn finish(&mut self, path: PathBuf, result: &Result<Handle, String>) {
+        let modified = match self.entries.get(&path) {
+            Some(entry) => entry.modified,
+            None => modified(&path),
+        };
+        let state = match result {
+            Ok(handle) => State::Ready(handle.clone()),
+            Err(_) => State::Failed,
+        };
+        self.insert(path, modified, state);
+    }
+
+    /// The decoded icon; `None` while it is pending or when it failed.
+    pub(crate) fn handle(&self, path: &Path) -> Option<Handle> {
+        match &self.entries.get(path)?.state {
+            State::Ready(handle) => Some(handle.clone()),
+            State::Pending | State::Failed => None,
+        }
+    }
+
+    /// Whether the file as it is now failed to decode before.
+    pub(crate) fn failed(&self, path: &Path) -> bool {
+        self.entries.get(path).is_some_and(|entry| {
+            matches!(entry.state, State::Failed) && entry.modified == modified(path)
+        })
+    }
+
+    fn insert(&mut self, path: PathBuf, modified: Option<SystemTime>, state: State) {
+        if self
+            .entries
+            .insert(path.clone(), Entry { modified, state })
+            .is_some()
+        {
+            self.order.retain(|queued| *queued != path);
+        }
+        self.order.push_back(path);
+        // Pending entries stay: their result is on the way.
+        while self.entries.len() > CAPACITY {
+            let Some(index) = self.order.iter().position(|queued| {
+                self.entries
+                    .get(queued)
+                    .is_some_and(|entry| !matches!(entry.state, State::Pending))
+            }) else {
+                break;
+            };
+            if let Some(evicted) = self.order.remove(index) {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn pixel() -> Handle {
+        Handle::from_rgba(1, 1, vec![0, 0, 0, 255])
+    }
+
+    #[test]
+    fn icons_are_decoded_once_per_file_version_and_the_oldest_are_evicted() {
+        let dir = temp_dir("images");
+        let icon = dir.join("shot.png");
+        fs::write(&icon, b"png").unwrap();
+
+        let mut cache = ImageCache::default();
+        assert!(cache.request(&icon));
+        assert!(!cache.request(&icon), "already on the way");
+        assert_eq!(cache.handle(&icon), None);
+
+        let handle = pixel();
+        cache.finish(icon.clone(), &Ok(handle.clone()));
+        assert_eq!(cache.handle(&icon), Some(handle));
+        assert!(!cache.request(&icon), "cached");
+
+        let rewritten = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&icon)
+            .unwrap()
+            .set_modified(rewritten)
+            .unwrap();
+        assert!(cache.request(&icon), "the file changed");
+        cache.finish(icon.clone(), &Err("bad data".to_string()));
+        assert!(cache.failed(&icon));
+        assert_eq!(cache.handle(&icon), None);
+        assert!(!cache.request(&icon), "not retried until the file changes");
+
+        let waiting = dir.join("waiting.png");
+        assert!(cache.request(&waiting));
+        for n in 0..CAPACITY {
+            let path = dir.join(format!("{n}.png"));
+            cache.request(&path);
+            cache.finish(path, &Ok(pixel()));
+        }
+        assert!(cache.entries.len() <= CAPACITY);
+        assert!(!cache.failed(&icon), "evicted");
+        assert!(!cache.request(&waiting), "pending entries are kept");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
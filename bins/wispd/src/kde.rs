@@ -0,0 +1,91 @@
+//! KDE interop hints. KDE apps name themselves with `x-kde-display-appname`, attach the
+//! files or URLs a notification is about with `x-kde-urls`, and KDE Connect marks
+//! relayed notifications with `x-kde-origin-name`. The header prefers the display name,
+//! the first URL can show as a clickable row (`ui.show_kde_urls`), and the origin shows
+//! as a "via <device>" suffix.
+
+use std::process::{Command, Stdio};
+
+use tracing::{debug, warn};
+
+/// The name in a popup's header: the sender's display name, else its `app_name`, else
+/// one derived from its desktop entry (`org.kde.dolphin` gives `dolphin`).
+pub(crate) fn header_app_name(
+    display_app_name: Option<&str>,
+    app_name: &str,
+    desktop_entry: Option<&str>,
+) -> String {
+    if let Some(display) = display_app_name.map(str::trim).filter(|d| !d.is_empty()) {
+        return display.to_string();
+    }
+    if !app_name.trim().is_empty() {
+        return app_name.to_string();
+    }
+    desktop_entry
+        .map(|entry| entry.trim().trim_end_matches(".desktop"))
+        .and_then(|entry| entry.rsplit('.').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Text of the URL row: a local file's name, or the URL without its scheme.
+pub(crate) fn url_label(url: &str) -> String {
+    if let Some(path) = url.strip_prefix("file://") {
+        let name = path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(path);
+        return if name.is_empty() { path } else { name }.to_string();
+    }
+    url.split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Hands `url` to `xdg-open` without waiting for it.
+pub(crate) fn open_url(url: &str) {
+    match Command::new("xdg-open")
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(_) => debug!(url, "opened notification url"),
+        Err(err) => warn!(url, %err, "failed to run xdg-open"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_display_name_wins_over_app_name_and_desktop_entry() {
+        let entry = Some("org.kde.dolphin");
+        assert_eq!(
+            header_app_name(Some("Dolphin"), "dolphin", entry),
+            "Dolphin"
+        );
+        assert_eq!(header_app_name(Some(" "), "dolphin5", entry), "dolphin5");
+        assert_eq!(header_app_name(None, "", entry), "dolphin");
+        assert_eq!(
+            header_app_name(None, "", Some("org.kde.kdeconnect.app.desktop")),
+            "app"
+        );
+        assert_eq!(header_app_name(None, "", None), "");
+    }
+
+    #[test]
+    fn url_labels_show_file_names_and_drop_schemes() {
+        assert_eq!(
+            url_label("file:///home/me/Downloads/report.pdf"),
+            "report.pdf"
+        );
+        assert_eq!(url_label("file:///home/me/Downloads/"), "Downloads");
+        assert_eq!(url_label("https://example.org/a/"), "example.org/a");
+        assert_eq!(url_label("mailto-less text"), "mailto-less text");
+    }
+}
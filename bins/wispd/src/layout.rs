@@ -0,0 +1,327 @@
+//! Stack slots for visible popups, clamped to the outputs they are on, and the spot of a
+//! popup placed by its `x`/`y` hint instead.
+//!
+//! Some compositors misplace or refuse a layer surface whose margin pushes it off the
+//! output, so a popup that would not fit is given no slot; the UI keeps it queued until
+//! the stack shrinks. Output sizes come from xdg-output; without one nothing is clamped.
+//!
+//! A [`LayoutSnapshot`] remembers what each window was last told, so a layout pass only
+//! sends the margins and sizes that differ from it.
+
+use std::{collections::HashMap, hash::Hash};
+
+use iced_layershell::reexport::Anchor;
+
+use super::animation::Margin;
+
+/// Where a popup window sits on its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Placement {
+    pub(crate) margin: Margin,
+    pub(crate) anchor: Anchor,
+    pub(crate) size: (u32, u32),
+}
+
+/// A layer-surface update one window needs to reach its [`Placement`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LayoutChange<K> {
+    Margin {
+        window: K,
+        margin: Margin,
+    },
+    AnchorSize {
+        window: K,
+        anchor: Anchor,
+        size: (u32, u32),
+    },
+}
+
+/// The placement last sent for each window.
+#[derive(Debug, Clone)]
+pub(crate) struct LayoutSnapshot<K> {
+    placements: HashMap<K, Placement>,
+}
+
+impl<K> Default for LayoutSnapshot<K> {
+    fn default() -> Self {
+        Self {
+            placements: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> LayoutSnapshot<K> {
+    /// Records a placement sent outside a layout pass, such as a window's initial one.
+    pub(crate) fn record(&mut self, window: K, placement: Placement) {
+        self.placements.insert(window, placement);
+    }
+
+    /// Records a margin sent on its own, e.g. by an animation frame. Windows without a
+    /// recorded placement are left alone.
+    pub(crate) fn record_margin(&mut self, window: K, margin: Margin) {
+        if let Some(placement) = self.placements.get_mut(&window) {
+            placement.margin = margin;
+        }
+    }
+
+    pub(crate) fn forget(&mut self, window: K) {
+        self.placements.remove(&window);
+    }
+
+    /// The updates that take each window in `next` from its recorded placement to the
+    /// new one, in `next` order, and records them. A window with nothing recorded gets
+    /// both updates; one already in place gets none.
+    pub(crate) fn advance(&mut self, next: &[(K, Placement)]) -> Vec<LayoutChange<K>> {
+        let mut changes = Vec::new();
+        for &(window, placement) in next {
+            let previous = self.placements.insert(window, placement);
+            if previous.is_none_or(|p| p.margin != placement.margin) {
+                changes.push(LayoutChange::Margin {
+                    window,
+                    margin: placement.margin,
+                });
+            }
+            if previous.is_none_or(|p| p.anchor != placement.anchor || p.size != placement.size) {
+                changes.push(LayoutChange::AnchorSize {
+                    window,
+                    anchor: placement.anchor,
+                    size: placement.size,
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// One popup, in stack order from the anchored edge.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StackedPopup<'a> {
+    /// Output the popup was opened on by name; `None` when the compositor chose.
+    pub(crate) output: Option<&'a str>,
+    pub(crate) height: u32,
+}
+
+/// Slot margin of each popup, or `None` for popups that would reach past the far edge of
+/// their output, and every popup behind them. `output_height` is the logical height of an
+/// output when known. The first popup of each output always gets a slot, so oversized
+/// margins cannot hide everything.
+pub(crate) fn compute_layout(
+    anchored_top: bool,
+    base: Margin,
+    gap: u16,
+    popups: &[StackedPopup<'_>],
+    output_height: impl Fn(Option<&str>) -> Option<u32>,
+) -> Vec<Option<Margin>> {
+    // Running offset per output, and whether that stack is already full.
+    let mut stacks: HashMap<Option<&str>, (i32, bool)> = HashMap::new();
+    popups
+        .iter()
+        .map(|popup| {
+            let (offset, full) = stacks.entry(popup.output).or_default();
+            if *full {
+                return None;
+            }
+            let mut margin = base;
+            let edge = if anchored_top {
+                &mut margin.0
+            } else {
+                &mut margin.2
+            };
+            *edge += *offset;
+            let far_edge = i64::from(*edge) + i64::from(popup.height);
+            if *offset > 0
+                && output_height(popup.output).is_some_and(|height| far_edge > i64::from(height))
+            {
+                *full = true;
+                return None;
+            }
+            *offset += popup.height as i32 + i32::from(gap);
+            Some(margin)
+        })
+        .collect()
+}
+
+/// Whether the margins alone leave no room for a `width` wide popup on an output of
+/// `(width, height)`.
+pub(crate) fn margins_exceed(base: Margin, width: u32, output: (u32, u32)) -> bool {
+    let (top, right, bottom, left) = base;
+    i64::from(top) + i64::from(bottom) >= i64::from(output.1)
+        || i64::from(left) + i64::from(right) + i64::from(width) > i64::from(output.0)
+}
+
+/// Anchor of a popup placed at a point; see [`positioned_margin`].
+pub(crate) fn positioned_anchor() -> Anchor {
+    Anchor::Top | Anchor::Left
+}
+
+/// Margin that puts the top-left corner of a `size` popup at `point`, moved back onto an
+/// output of `output` size where it would reach past an edge. A popup larger than the
+/// output keeps to its top or left edge; without an output size only negative coordinates
+/// are clamped.
+pub(crate) fn positioned_margin(
+    point: (i32, i32),
+    size: (u32, u32),
+    output: Option<(u32, u32)>,
+) -> Margin {
+    let clamp = |at: i32, extent: u32, room: Option<u32>| {
+        let last = room.map_or(i64::MAX, |room| {
+            (i64::from(room) - i64::from(extent)).max(0)
+        });
+        // Never above `at` once clamped, so it fits.
+        i64::from(at).clamp(0, last) as i32
+    };
+    (
+        clamp(point.1, size.1, output.map(|output| output.1)),
+        0,
+        0,
+        clamp(point.0, size.0, output.map(|output| output.0)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placed(top: i32, height: u32) -> Placement {
+        Placement {
+            margin: (top, 0, 0, 0),
+            anchor: Anchor::Top | Anchor::Right,
+            size: (300, height),
+        }
+    }
+
+    #[test]
+    fn a_snapshot_sends_only_what_changed() {
+        let mut sent = LayoutSnapshot::default();
+        sent.record(1, placed(0, 50));
+        let first = sent.advance(&[(1, placed(0, 50)), (2, placed(60, 40))]);
+        assert_eq!(
+            first,
+            [
+                LayoutChange::Margin {
+                    window: 2,
+                    margin: (60, 0, 0, 0)
+                },
+                LayoutChange::AnchorSize {
+                    window: 2,
+                    anchor: Anchor::Top | Anchor::Right,
+                    size: (300, 40)
+                },
+            ],
+            "an unrecorded window gets both, one in place nothing"
+        );
+
+        sent.record_margin(2, (70, 0, 0, 0));
+        let second = sent.advance(&[(1, placed(0, 80)), (2, placed(90, 40))]);
+        assert_eq!(
+            second,
+            [
+                LayoutChange::AnchorSize {
+                    window: 1,
+                    anchor: Anchor::Top | Anchor::Right,
+                    size: (300, 80)
+                },
+                LayoutChange::Margin {
+                    window: 2,
+                    margin: (90, 0, 0, 0)
+                },
+            ]
+        );
+        assert!(sent.advance(&[(1, placed(0, 80))]).is_empty());
+        sent.forget(1);
+        assert_eq!(sent.advance(&[(1, placed(0, 80))]).len(), 2);
+    }
+
+    fn popups(heights: &[u32], output: Option<&'static str>) -> Vec<StackedPopup<'static>> {
+        heights
+            .iter()
+            .map(|&height| StackedPopup { output, height })
+            .collect()
+    }
+
+    #[test]
+    fn a_tall_stack_stops_at_the_bottom_of_a_small_output() {
+        let stack = popups(&[100, 100, 80, 100], Some("small"));
+        let height = |output: Option<&str>| (output == Some("small")).then_some(300);
+
+        let slots = compute_layout(true, (10, 10, 10, 10), 10, &stack, height);
+        let tops: Vec<Option<i32>> = slots.iter().map(|slot| slot.map(|m| m.0)).collect();
+        assert_eq!(tops, [Some(10), Some(120), None, None]);
+
+        let slots = compute_layout(false, (10, 10, 10, 10), 10, &stack, height);
+        let bottoms: Vec<Option<i32>> = slots.iter().map(|slot| slot.map(|m| m.2)).collect();
+        assert_eq!(
+            bottoms,
+            [Some(10), Some(120), None, None],
+            "bottom anchoring grows upwards"
+        );
+    }
+
+    #[test]
+    fn unknown_outputs_are_not_clamped_and_stacks_are_per_output() {
+        let stack = popups(&[200, 200, 200], None);
+        let slots = compute_layout(true, (0, 0, 0, 0), 0, &stack, |_| None);
+        assert!(slots.iter().all(Option::is_some));
+
+        let mut mixed = popups(&[250, 250], Some("a"));
+        mixed.insert(
+            1,
+            StackedPopup {
+                output: Some("b"),
+                height: 250,
+            },
+        );
+        let slots = compute_layout(true, (0, 0, 0, 0), 0, &mixed, |_| Some(300));
+        let tops: Vec<Option<i32>> = slots.iter().map(|slot| slot.map(|m| m.0)).collect();
+        assert_eq!(tops, [Some(0), Some(0), None]);
+
+        let slots = compute_layout(true, (500, 0, 0, 0), 0, &mixed, |_| Some(300));
+        assert_eq!(
+            slots[0],
+            Some((500, 0, 0, 0)),
+            "the first popup is kept even when it cannot fit"
+        );
+    }
+
+    #[test]
+    fn positioned_popups_stay_on_their_output() {
+        let size = (400, 100);
+        let output = Some((1920, 1080));
+        assert_eq!(
+            positioned_margin((300, 200), size, output),
+            (200, 0, 0, 300)
+        );
+        assert_eq!(
+            positioned_margin((-50, -10), size, output),
+            (0, 0, 0, 0),
+            "past the top-left corner"
+        );
+        assert_eq!(
+            positioned_margin((1800, 1050), size, output),
+            (980, 0, 0, 1520),
+            "past the bottom-right corner"
+        );
+        assert_eq!(
+            positioned_margin((1520, 980), size, output),
+            (980, 0, 0, 1520),
+            "touching the edges is on screen"
+        );
+        assert_eq!(
+            positioned_margin((100, 50), (2000, 1200), output),
+            (0, 0, 0, 0),
+            "larger than the output"
+        );
+        assert_eq!(
+            positioned_margin((i32::MAX, -1), size, None),
+            (0, 0, 0, i32::MAX),
+            "unknown outputs only clamp negatives"
+        );
+    }
+
+    #[test]
+    fn margins_exceeding_the_output_are_detected() {
+        assert!(!margins_exceed((10, 10, 10, 10), 420, (1920, 1080)));
+        assert!(margins_exceed((600, 0, 500, 0), 420, (1920, 1080)));
+        assert!(margins_exceed((0, 1000, 0, 600), 420, (1920, 1080)));
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    panic::PanicHookInfo,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry,
+    fmt::{self, MakeWriter},
+    layer::{Layered, SubscriberExt},
+    reload,
+    util::SubscriberInitExt,
+};
+use wisp_types::loggable;
+
+const DEFAULT_LEVEL: &str = "info";
+const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 3;
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type BoxedLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+static FILTER_HANDLE: OnceLock<FilterHandle> = OnceLock::new();
+static LEVEL_OVERRIDE: OnceLock<Option<String>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct LogSection {
+    /// `EnvFilter` directives, e.g. `info` or `wispd=debug,wisp_source=info`.
+    pub(crate) level: String,
+    /// Optional log file; `~`, `$HOME` and `$XDG_STATE_HOME` are expanded.
+    pub(crate) file: Option<String>,
+    pub(crate) format: LogFormat,
+    /// Rotate the log file once it would grow past this many bytes (0 disables rotation).
+    pub(crate) max_file_bytes: u64,
+    /// Number of rotated files (`wispd.log.1`, `wispd.log.2`, ...) to keep.
+    pub(crate) max_files: usize,
+    /// Log notification summaries, bodies and action labels; when off they show as a
+    /// length and hash. Also hides them in debug dumps.
+    pub(crate) log_content: bool,
+}
+
+impl Default for LogSection {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL.to_string(),
+            file: None,
+            format: LogFormat::Text,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+            log_content: true,
+        }
+    }
+}
+
+/// Command-line overrides that take precedence over the `[log]` config section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LogOverrides {
+    pub(crate) level: Option<String>,
+    pub(crate) file: Option<PathBuf>,
+}
+
+pub(crate) fn validate_log_section(cfg: &LogSection) -> Result<()> {
+    EnvFilter::try_new(&cfg.level)
+        .map_err(|err| anyhow!("invalid log.level {:?}: {err}", cfg.level))?;
+    if cfg
+        .file
+        .as_deref()
+        .is_some_and(|file| file.trim().is_empty())
+    {
+        return Err(anyhow!("log.file must not be empty"));
+    }
+    Ok(())
+}
+
+/// Installs the global subscriber: stderr plus an optional rotating file, both behind a
+/// reloadable level filter.
+pub(crate) fn init(cfg: &LogSection, overrides: &LogOverrides) -> Result<()> {
+    loggable::set_log_content(cfg.log_content);
+    let _ = LEVEL_OVERRIDE.set(overrides.level.clone());
+    let directives = effective_directives(
+        &cfg.level,
+        overrides.level.as_deref(),
+        std::env::var("RUST_LOG").ok().as_deref(),
+    );
+    let filter = EnvFilter::try_new(&directives)
+        .map_err(|err| anyhow!("invalid log level {directives:?}: {err}"))?;
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let mut layers: Vec<BoxedLayer> = vec![output_layer(cfg.format, io::stderr, true)];
+    let mut file_error = None;
+    let file_path = overrides
+        .file
+        .clone()
+        .or_else(|| cfg.file.as_deref().map(expand_path));
+    if let Some(path) = file_path {
+        match LogFileWriter::open(&path, cfg.max_file_bytes, cfg.max_files) {
+            Ok(writer) => layers.push(output_layer(cfg.format, writer, false)),
+            Err(err) => file_error = Some((path, err)),
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
+        .try_init()
+        .map_err(|err| anyhow!("failed to install tracing subscriber: {err}"))?;
+    let _ = FILTER_HANDLE.set(handle);
+
+    if let Some((path, err)) = file_error {
+        warn!(path = %path.display(), %err, "failed to open log file, logging to stderr only");
+    }
+    Ok(())
+}
+
+/// Re-applies `log.level` after a config reload. CLI and `RUST_LOG` overrides still win.
+pub(crate) fn reload_level(cfg: &LogSection) {
+    loggable::set_log_content(cfg.log_content);
+    let Some(handle) = FILTER_HANDLE.get() else {
+        return;
+    };
+    let cli_level = LEVEL_OVERRIDE.get().cloned().flatten();
+    let directives = effective_directives(
+        &cfg.level,
+        cli_level.as_deref(),
+        std::env::var("RUST_LOG").ok().as_deref(),
+    );
+    match EnvFilter::try_new(&directives) {
+        Ok(filter) => {
+            if let Err(err) = handle.reload(filter) {
+                warn!(%err, "failed to reload log level");
+            }
+        }
+        Err(err) => warn!(%err, directives, "ignoring invalid log level on reload"),
+    }
+}
+
+/// Writes panics (with a backtrace when `RUST_BACKTRACE` enables one) through the
+/// subscriber so they land in the log file as well as on stderr.
+pub(crate) fn install_panic_hook() {
+    std::panic::set_hook(Box::new(log_panic));
+}
+
+fn log_panic(info: &PanicHookInfo<'_>) {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| (*s).to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<non-string panic payload>".to_string());
+    let location = info
+        .location()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "<unknown>".to_string());
+    let thread = std::thread::current()
+        .name()
+        .unwrap_or("<unnamed>")
+        .to_string();
+
+    let backtrace = Backtrace::capture();
+    if backtrace.status() == BacktraceStatus::Captured {
+        error!(%thread, %location, %backtrace, "panic: {payload}");
+    } else {
+        error!(%thread, %location, "panic: {payload}");
+    }
+}
+
+fn effective_directives(config: &str, cli: Option<&str>, env: Option<&str>) -> String {
+    cli.or(env.filter(|value| !value.trim().is_empty()))
+        .unwrap_or(config)
+        .to_string()
+}
+
+fn output_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn expand_path(raw: &str) -> PathBuf {
+    let home = std::env::var("HOME").ok();
+    let state = super::state::home().to_string_lossy().into_owned();
+    PathBuf::from(expand_path_with(raw, home.as_deref(), &state))
+}
+
+fn expand_path_with(raw: &str, home: Option<&str>, state_home: &str) -> String {
+    let mut expanded = raw
+        .replace("${XDG_STATE_HOME}", state_home)
+        .replace("$XDG_STATE_HOME", state_home);
+    if let Some(home) = home {
+        expanded = expanded.replace("${HOME}", home).replace("$HOME", home);
+        if expanded == "~" {
+            expanded = home.to_string();
+        } else if let Some(rest) = expanded.strip_prefix("~/") {
+            expanded = format!("{home}/{rest}");
+        }
+    }
+    expanded
+}
+
+fn should_rotate(current_len: u64, incoming: usize, max_bytes: u64) -> bool {
+    max_bytes > 0 && current_len > 0 && current_len.saturating_add(incoming as u64) > max_bytes
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            len,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files > 0 {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if should_rotate(self.len, buf.len(), self.max_bytes) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Shared handle to the rotating log file, usable as a `MakeWriter`.
+#[derive(Debug, Clone)]
+struct LogFileWriter {
+    inner: Arc<Mutex<RotatingFile>>,
+}
+
+impl LogFileWriter {
+    fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RotatingFile::open(path, max_bytes, max_files)?)),
+        })
+    }
+}
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner
+            .lock()
+            .map_err(|_| io::Error::other("log file lock poisoned"))?
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner
+            .lock()
+            .map_err(|_| io::Error::other("log file lock poisoned"))?
+            .flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFileWriter {
+    type Writer = LogFileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    #[test]
+    fn log_section_defaults_and_parses() {
+        let cfg: LogSection = toml::from_str("").expect("empty log section parses");
+        assert_eq!(cfg, LogSection::default());
+
+        let cfg: LogSection = toml::from_str(
+            r#"
+level = "wispd=debug,info"
+file = "$XDG_STATE_HOME/wispd/wispd.log"
+format = "json"
+max_file_bytes = 1024
+max_files = 5
+"#,
+        )
+        .expect("log section parses");
+        assert_eq!(cfg.level, "wispd=debug,info");
+        assert_eq!(cfg.file.as_deref(), Some("$XDG_STATE_HOME/wispd/wispd.log"));
+        assert_eq!(cfg.format, LogFormat::Json);
+        assert_eq!(cfg.max_file_bytes, 1024);
+        assert_eq!(cfg.max_files, 5);
+        assert!(validate_log_section(&cfg).is_ok());
+    }
+
+    #[test]
+    fn log_section_rejects_unknown_format_and_bad_level() {
+        assert!(toml::from_str::<LogSection>(r#"format = "yaml""#).is_err());
+
+        let cfg = LogSection {
+            level: "wispd=loud".to_string(),
+            ..LogSection::default()
+        };
+        assert!(validate_log_section(&cfg).is_err());
+    }
+
+    #[test]
+    fn cli_level_beats_env_which_beats_config() {
+        assert_eq!(
+            effective_directives("info", Some("trace"), Some("warn")),
+            "trace"
+        );
+        assert_eq!(effective_directives("info", None, Some("warn")), "warn");
+        assert_eq!(effective_directives("info", None, Some("  ")), "info");
+        assert_eq!(effective_directives("info", None, None), "info");
+    }
+
+    #[test]
+    fn log_paths_expand_home_and_state_dirs() {
+        assert_eq!(
+            expand_path_with(
+                "$XDG_STATE_HOME/wispd/wispd.log",
+                Some("/home/u"),
+                "/home/u/.local/state"
+            ),
+            "/home/u/.local/state/wispd/wispd.log"
+        );
+        assert_eq!(
+            expand_path_with("~/logs/wispd.log", Some("/home/u"), "/s"),
+            "/home/u/logs/wispd.log"
+        );
+        assert_eq!(
+            expand_path_with("${HOME}/wispd.log", Some("/home/u"), "/s"),
+            "/home/u/wispd.log"
+        );
+        assert_eq!(
+            expand_path_with("/var/log/wispd.log", None, "/s"),
+            "/var/log/wispd.log"
+        );
+    }
+
+    #[test]
+    fn rotation_triggers_only_when_write_would_exceed_limit() {
+        assert!(!should_rotate(0, 4096, 1024), "never rotate an empty file");
+        assert!(!should_rotate(512, 512, 1024));
+        assert!(should_rotate(512, 513, 1024));
+        assert!(!should_rotate(u64::MAX, 1, 0), "zero disables rotation");
+    }
+
+    #[test]
+    fn rotating_file_shifts_old_logs() {
+        let dir = temp_dir("log");
+        let path = dir.join("wispd.log");
+
+        let mut file = RotatingFile::open(&path, 8, 2).expect("open log file");
+        file.write_all(b"first\n").expect("write");
+        file.write_all(b"second\n").expect("write");
+        file.write_all(b"third\n").expect("write");
+        file.flush().expect("flush");
+
+        assert_eq!(fs::read_to_string(&path).expect("current"), "third\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).expect("rotated 1"),
+            "second\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).expect("rotated 2"),
+            "first\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,22 +1,34 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Reverse,
     collections::{HashMap, HashSet, VecDeque},
     fs,
     future::Future,
     hash::Hash,
     mem::ManuallyDrop,
-    panic::{AssertUnwindSafe, catch_unwind, set_hook, take_hook},
+    panic::{AssertUnwindSafe, catch_unwind},
     path::PathBuf,
     process::Command,
-    sync::{Arc, Mutex, mpsc},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
     task::Poll,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use iced::advanced::widget as adv_widget;
 use iced::futures::{SinkExt, channel::mpsc::Sender};
+use iced::keyboard;
 use iced::widget::button::Status as ButtonStatus;
-use iced::widget::{button, column, container, image, mouse_area, row, text};
+use iced::widget::image::Handle;
+use iced::widget::{
+    button, column, container, image, mouse_area, rich_text, row, scrollable, span, text,
+    text_input, tooltip,
+};
 use iced::{
     Background, Color, ContentFit, Element, Font, Length, Rectangle, Subscription, Task, border,
 };
@@ -26,9 +38,10 @@ use iced_layershell::reexport::{
 };
 use iced_layershell::settings::{LayerShellSettings, Settings};
 use iced_layershell::to_layer_message;
-use serde::Deserialize;
-use tokio::sync::mpsc as tokio_mpsc;
-use tracing::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc as tokio_mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+use unicode_segmentation::UnicodeSegmentation;
 use wayland_client::{
     Connection, Dispatch, EventQueue, Proxy, delegate_noop,
     globals::{BindError, GlobalError, GlobalListContents, registry_queue_init},
@@ -41,8 +54,89 @@ use wayland_protocols::xdg::xdg_output::zv1::client::{
     zxdg_output_manager_v1::ZxdgOutputManagerV1,
     zxdg_output_v1::{self, ZxdgOutputV1},
 };
-use wisp_source::{SourceConfig, WispSource};
-use wisp_types::{Notification, NotificationAction, NotificationEvent, Urgency};
+use wisp_source::{
+    CONTROL_PATH, CommandError, Dedup, Features, HistoryEntry, RateLimit, SourceConfig,
+    StartupError, TimeoutClamp, TimeoutRule, WispSource,
+};
+use wisp_types::{
+    Notification, NotificationAction, NotificationEvent, NotificationHints, ReplaceChain,
+    SoundHint, Urgency,
+    dnd::DndWindow,
+    icon::IconSource,
+    loggable::{self, Loggable, LoggableEvent},
+    privacy::{self, PrivacyRule},
+    systemd,
+};
+
+mod accelerators;
+mod activation;
+mod animation;
+mod category;
+mod cli;
+mod clipboard;
+mod confirm;
+mod delivered;
+mod dump;
+mod duration;
+mod escalation;
+mod explain;
+mod flood;
+mod fonts;
+mod glyphs;
+mod headless;
+mod history_log;
+mod history_panel;
+mod icons;
+mod images;
+mod kde;
+mod layout;
+mod logging;
+mod metrics;
+mod mirror;
+mod mutes;
+mod overrides;
+mod popup_model;
+mod profiling;
+mod rules;
+mod sink;
+mod sounds;
+mod startup;
+mod state;
+mod style;
+mod supervisor;
+mod takeover;
+#[cfg(test)]
+mod testing;
+mod text_metrics;
+#[cfg(feature = "x11")]
+mod x11;
+
+use accelerators::Accelerator;
+use animation::{AnimationSection, Margin, MarginTween, Presence};
+use category::{CategoryIcon, CategoryIconsSection};
+use cli::Backend;
+use confirm::{ConfirmActionsSection, Confirmations};
+use duration::HumanDuration;
+use escalation::{EscalationSection, Escalations};
+use flood::Floods;
+use fonts::{FontReport, SystemFonts};
+use glyphs::{CloseFace, Glyphs, ShapedCoverage};
+use history_log::HistoryLog;
+use history_panel::{
+    FilterEdit, HistoryFilter, HistoryPanel, HistorySection, PanelCommand, PanelKey, PanelMessage,
+};
+use images::{Decoder, FileDecoder, ImageCache};
+use layout::{LayoutChange, LayoutSnapshot, Placement, StackedPopup};
+use metrics::Metrics;
+use mirror::{MirrorHistory, MirrorSection};
+use mutes::MuteList;
+use popup_model::{ActionVisibility, Controls, Pin, PopupModel, PopupSlot, action_visibility};
+use sounds::{SoundResolver, SoundSection};
+use startup::{Stage, StartupReport};
+use style::{ResolvedStyle, StyleInputs};
+use supervisor::{Outcome, RetryPolicy, UiExit};
+use takeover::{TakeoverSurfaces, Takeovers, UrgencySection};
+use text_metrics::{TextBlock, TextMetrics, TextMetricsCache};
 
 #[derive(Debug)]
 struct BaseWaylandState;
@@ -61,8 +155,15 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for BaseWaylandState
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum OutputHotplugEvent {
-    Added { name: String, description: String },
-    Removed { name: Option<String> },
+    Added {
+        name: String,
+        description: String,
+        /// Logical `(width, height)`, when the compositor sent it before the name.
+        size: Option<(u32, u32)>,
+    },
+    Removed {
+        name: Option<String>,
+    },
     Failed(String),
 }
 
@@ -72,6 +173,7 @@ struct PendingOutputInfo {
     zxdg_output: ZxdgOutputV1,
     name: String,
     description: String,
+    size: Option<(u32, u32)>,
     is_ready: bool,
 }
 
@@ -82,6 +184,7 @@ impl PendingOutputInfo {
             zxdg_output,
             name: String::new(),
             description: String::new(),
+            size: None,
             is_ready: false,
         }
     }
@@ -192,6 +295,9 @@ impl Dispatch<zxdg_output_v1::ZxdgOutputV1, ()> for OutputWatchState {
             zxdg_output_v1::Event::Description { description } => {
                 pending.description = description;
             }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                pending.size = u32::try_from(width).ok().zip(u32::try_from(height).ok());
+            }
             _ => {}
         }
 
@@ -205,6 +311,7 @@ impl Dispatch<zxdg_output_v1::ZxdgOutputV1, ()> for OutputWatchState {
             state.events.push(OutputHotplugEvent::Added {
                 name: ready.name,
                 description: ready.description,
+                size: ready.size,
             });
         }
     }
@@ -320,38 +427,97 @@ fn listen_output_hotplug(connection: Connection) -> Subscription<OutputHotplugEv
     })
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 #[serde(default)]
 struct AppConfig {
     source: SourceSection,
+    #[serde(deserialize_with = "deserialize_ui_section")]
     ui: UiSection,
+    log: logging::LogSection,
+    sink: sink::SinkSection,
+    metrics: metrics::MetricsSection,
+    mirror: MirrorSection,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct SourceSection {
     default_timeout_ms: Option<i32>,
     capabilities: Vec<String>,
+    /// Drops new notifications past this rate per app; read at startup.
+    rate_limit: Option<RateLimit>,
+    /// Folds resent copies of a live notification into it; read at startup.
+    dedup: Option<DedupSection>,
 }
 
 impl Default for SourceSection {
     fn default() -> Self {
         Self {
             default_timeout_ms: None,
-            capabilities: vec!["body".to_string(), "actions".to_string()],
+            capabilities: vec![
+                "body".to_string(),
+                "actions".to_string(),
+                "action-icons".to_string(),
+            ],
+            rate_limit: None,
+            dedup: None,
+        }
+    }
+}
+
+impl SourceSection {
+    /// Configured capabilities, minus those the current UI settings cannot honour, with
+    /// `sound` exactly while `[ui.sound]` plays sounds so clients stay quiet then.
+    fn features(&self, ui: &UiSection) -> Features {
+        let mut features =
+            Features::from_capabilities(&self.capabilities).honoured_by_ui(ui.show_icons);
+        features.sound = ui.sound.enabled;
+        features
+    }
+}
+
+/// `[source.dedup]`: when a new notification counts as a copy of a live one.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+struct DedupSection {
+    /// How long after a notification's content last arrived a copy still folds into it.
+    window: HumanDuration,
+    /// Whether the bodies have to match too, besides the app name and summary.
+    match_body: bool,
+}
+
+impl Default for DedupSection {
+    fn default() -> Self {
+        let dedup = Dedup::default();
+        Self {
+            window: dedup.window.into(),
+            match_body: dedup.match_body,
+        }
+    }
+}
+
+impl DedupSection {
+    fn dedup(self) -> Dedup {
+        Dedup {
+            window: self.window.get(),
+            match_body: self.match_body,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 enum ClickAction {
     #[default]
     Dismiss,
     InvokeDefaultAction,
+    MuteApp,
+    CopyBody,
+    /// Toggles the popup's pin.
+    Pin,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct UiSection {
     #[allow(dead_code)]
@@ -364,20 +530,90 @@ struct UiSection {
     font_size: u16,
     #[serde(alias = "font")]
     font_family: String,
+    /// Columns between the tab stops that tabs in app names, summaries and bodies are
+    /// expanded to.
+    tab_width: usize,
     show_icons: bool,
     max_icon_size: u16,
     anchor: String,
+    /// Layer-shell namespace of popup surfaces, for compositor rules; `{urgency}` and
+    /// `{app}` (the app name as a slug) are filled in.
+    namespace_template: String,
     output: String,
+    /// Outputs `output = "round-robin"` cycles through; empty means every connected one.
+    outputs: Vec<String>,
     focused_output_command: Option<String>,
     margin: MarginConfig,
+    /// Places popups with `x`/`y` hints at that point of their output, out of the stack.
+    honor_position_hints: bool,
     colors: UrgencyColors,
     text: TextStyleConfig,
     buttons: ButtonStyleConfig,
     show_timeout_progress: bool,
-    timeout_progress_height: u16,
-    timeout_progress_position: String,
+    timeout_progress: TimeoutProgressConfig,
+    /// Old spelling of `timeout_progress.height`; folded in by `deserialize_ui_section`.
+    #[serde(skip_serializing)]
+    timeout_progress_height: Option<u16>,
+    /// Old spelling of `timeout_progress.position`.
+    #[serde(skip_serializing)]
+    timeout_progress_position: Option<String>,
     left_click_action: ClickAction,
     right_click_action: ClickAction,
+    mute_duration_secs: u64,
+    /// Lifetime of a closed notification shown again with `ShowHistoric`; 0 keeps it
+    /// until dismissed.
+    history_timeout_ms: u32,
+    /// Age past which closed notifications leave the history, e.g. `"48h"`; starred
+    /// entries stay. Unset keeps the last 50 whatever their age.
+    history_ttl: Option<HumanDuration>,
+    /// Keeps the history in the state dir across restarts; read at startup only.
+    persist_history: bool,
+    history: HistorySection,
+    /// Notifications one app may post within `flood_window_ms` before the rest collapse
+    /// into a summary popup; 0 turns this off.
+    flood_threshold: u32,
+    flood_window_ms: u64,
+    debug_dump_redact_bodies: bool,
+    sort: StackSort,
+    animation: AnimationSection,
+    /// Shows a copy button on the hovered popup.
+    show_copy_button: bool,
+    /// Command receiving copied text on stdin; unset or empty uses the toolkit clipboard.
+    clipboard_command: Option<String>,
+    /// Shows a pin toggle on the hovered popup.
+    show_pin_button: bool,
+    /// Shows the first `x-kde-urls` entry as a row that opens it.
+    show_kde_urls: bool,
+    /// Lower bound on popup lifetime, whatever the sender asked for.
+    min_timeout_ms: Option<u32>,
+    /// Upper bound on popup lifetime; also applies to never-expiring notifications.
+    max_timeout_ms: Option<u32>,
+    timeout_clamp_exempt_critical: bool,
+    /// Keeps critical popups until they are closed, whatever the sender or the clamp say.
+    critical_never_expires: bool,
+    /// Accents drawn before the app name, keyed by `category` hint prefix.
+    category_icons: CategoryIconsSection,
+    /// Apps whose content is never shown or kept, whatever `debug_dump_redact_bodies` says.
+    privacy_apps: Vec<PrivacyRule>,
+    /// Shown in place of content hidden by `privacy_apps`.
+    redaction_placeholder: String,
+    /// Re-alerting for critical popups left unacknowledged.
+    escalation: EscalationSection,
+    /// Actions that ask "Really …?" before being sent.
+    confirm_actions: ConfirmActionsSection,
+    /// Actions run as local commands and lifetime bounds, per matched notification.
+    rules: Vec<rules::Rule>,
+    dnd: DndSection,
+    urgency: UrgencySection,
+    sound: SoundSection,
+}
+
+/// `[ui.dnd]`: do-not-disturb keeps non-critical notifications from popping up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+struct DndSection {
+    /// Local-time windows that switch do-not-disturb on; the source checks them.
+    schedule: Vec<DndWindow>,
 }
 
 impl Default for UiSection {
@@ -391,25 +627,167 @@ impl Default for UiSection {
             padding: 10,
             font_size: 15,
             font_family: "sans-serif".to_string(),
+            tab_width: 4,
             show_icons: true,
             max_icon_size: 32,
             anchor: "top-right".to_string(),
+            namespace_template: "wispd".to_string(),
             output: "focused".to_string(),
+            outputs: Vec::new(),
             focused_output_command: None,
             margin: MarginConfig::default(),
+            honor_position_hints: false,
             colors: UrgencyColors::default(),
             text: TextStyleConfig::default(),
             buttons: ButtonStyleConfig::default(),
             show_timeout_progress: true,
-            timeout_progress_height: 3,
-            timeout_progress_position: "bottom".to_string(),
+            timeout_progress: TimeoutProgressConfig::default(),
+            timeout_progress_height: None,
+            timeout_progress_position: None,
             left_click_action: ClickAction::Dismiss,
             right_click_action: ClickAction::InvokeDefaultAction,
+            mute_duration_secs: 3600,
+            history_timeout_ms: 8000,
+            history_ttl: None,
+            persist_history: false,
+            history: HistorySection::default(),
+            flood_threshold: 0,
+            flood_window_ms: 10_000,
+            debug_dump_redact_bodies: true,
+            sort: StackSort::Arrival,
+            animation: AnimationSection::default(),
+            show_copy_button: false,
+            clipboard_command: Some("wl-copy".to_string()),
+            show_pin_button: true,
+            show_kde_urls: false,
+            min_timeout_ms: None,
+            max_timeout_ms: None,
+            timeout_clamp_exempt_critical: true,
+            critical_never_expires: false,
+            category_icons: CategoryIconsSection::default(),
+            privacy_apps: Vec::new(),
+            redaction_placeholder: privacy::DEFAULT_PLACEHOLDER.to_string(),
+            escalation: EscalationSection::default(),
+            confirm_actions: ConfirmActionsSection::default(),
+            rules: Vec::new(),
+            dnd: DndSection::default(),
+            urgency: UrgencySection::default(),
+            sound: SoundSection::default(),
+        }
+    }
+}
+
+impl UiSection {
+    /// Lifetime bounds shared with the source so both expire notifications together.
+    fn timeout_clamp(&self) -> TimeoutClamp {
+        TimeoutClamp {
+            min_ms: self.min_timeout_ms,
+            max_ms: self.max_timeout_ms,
+            exempt_critical: self.timeout_clamp_exempt_critical,
+        }
+    }
+
+    /// The `[[ui.rules]]` overriding [`Self::timeout_clamp`], also handed to the source.
+    fn timeout_rules(&self) -> Vec<TimeoutRule> {
+        rules::timeout_rules(&self.rules)
+    }
+
+    /// The lifetime bounds for `n` as sent, the way the source resolves them.
+    fn timeout_clamp_for(&self, n: &Notification) -> TimeoutClamp {
+        self.timeout_clamp()
+            .for_notification(&self.timeout_rules(), n)
+    }
+
+    /// Whether popups of `urgency` stay until closed under `critical_never_expires`.
+    fn never_expires(&self, urgency: &Urgency) -> bool {
+        self.critical_never_expires && *urgency == Urgency::Critical
+    }
+
+    /// Moves the pre-table `timeout_progress_*` keys into `timeout_progress`; they win
+    /// over the table when both are set.
+    fn fold_legacy_keys(&mut self) {
+        if let Some(height) = self.timeout_progress_height.take() {
+            self.timeout_progress.height = height;
+        }
+        if let Some(position) = self.timeout_progress_position.take() {
+            self.timeout_progress.position = position;
+        }
+    }
+
+    /// Category accent for `n`, sized to the app name text.
+    fn category_icon(&self, n: &UiNotification) -> Option<CategoryIcon> {
+        let size = self.text.app_name.font_size.unwrap_or(self.font_size);
+        self.category_icons
+            .icon_for(n.category.as_deref(), size.max(1) as u32)
+    }
+}
+
+fn deserialize_ui_section<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<UiSection, D::Error> {
+    let mut ui = UiSection::deserialize(deserializer)?;
+    ui.fold_legacy_keys();
+    Ok(ui)
+}
+
+/// `ui.timeout_progress`: the bar along a timed popup's top or bottom edge.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+struct TimeoutProgressConfig {
+    height: u16,
+    /// `top` or `bottom`.
+    position: String,
+    /// Space between the bar and the card content; unset uses half the card padding
+    /// (at least 2 px).
+    gap: Option<u16>,
+    /// Pulls the bar in from the card's sides and its outer edge, on top of the padding.
+    inset: u16,
+    /// Colour of the unfilled part of the bar; the fill is `colors.timeout_progress`.
+    track_color: String,
+    corner_radius: f32,
+}
+
+impl Default for TimeoutProgressConfig {
+    fn default() -> Self {
+        Self {
+            height: 3,
+            position: "bottom".to_string(),
+            gap: None,
+            inset: 0,
+            track_color: "#ffffff14".to_string(),
+            corner_radius: 0.0,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl TimeoutProgressConfig {
+    fn is_top(&self) -> bool {
+        self.position.eq_ignore_ascii_case("top")
+    }
+
+    fn gap_px(&self, padding: u16) -> u16 {
+        self.gap.unwrap_or((padding / 2).max(2))
+    }
+
+    /// Height the bar adds to a card: the bar, a gap on either side, and the inset.
+    fn block_height(&self, padding: u16) -> u32 {
+        self.height.max(1) as u32 + 2 * self.gap_px(padding) as u32 + self.inset as u32
+    }
+}
+
+/// Order of popups in the visible stack; the front is the anchor end.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum StackSort {
+    /// Newest first.
+    #[default]
+    Arrival,
+    /// Most urgent first, newest first within an urgency level; overflow evicts the
+    /// lowest-priority popup.
+    UrgencyThenArrival,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct MarginConfig {
     top: i32,
@@ -429,7 +807,7 @@ impl Default for MarginConfig {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct UrgencyColors {
     low: String,
@@ -438,6 +816,8 @@ struct UrgencyColors {
     background: String,
     text: String,
     timeout_progress: String,
+    /// Fill of the gauge a `value` hint draws under the summary.
+    value_progress: String,
 }
 
 impl Default for UrgencyColors {
@@ -449,11 +829,12 @@ impl Default for UrgencyColors {
             background: "#1e1e2ecc".to_string(),
             text: "#f8f8f2".to_string(),
             timeout_progress: "#f8f8f2".to_string(),
+            value_progress: "#8ec07c".to_string(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct TextStyleConfig {
     app_name: TextPartStyle,
@@ -480,7 +861,7 @@ impl Default for TextStyleConfig {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct TextPartStyle {
     color: String,
@@ -496,7 +877,7 @@ impl Default for TextPartStyle {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 struct ButtonStyleConfig {
     text_color: String,
@@ -508,6 +889,20 @@ struct ButtonStyleConfig {
     font_family: Option<String>,
     font_size: Option<u16>,
     close_font_size: Option<u16>,
+    /// Close button glyph, tried before "✕", "×" and "x"; the first the button font can
+    /// draw is used.
+    close_glyph: Option<String>,
+    /// Themed icon name for the close button; used instead of a glyph when it resolves.
+    close_icon: Option<String>,
+    /// Action labels longer than this many graphemes are ellipsized (0 disables).
+    max_label_chars: usize,
+    /// Largest share of the card's content width a single action button may take.
+    max_width: f32,
+    /// Action buttons drawn before the rest hide behind an affordance in the header;
+    /// unset draws them all.
+    max_visible_actions: Option<usize>,
+    /// Affordance glyph, tried before "⚡", "›" and ">".
+    actions_glyph: Option<String>,
 }
 
 impl Default for ButtonStyleConfig {
@@ -521,6 +916,12 @@ impl Default for ButtonStyleConfig {
             font_family: None,
             font_size: None,
             close_font_size: None,
+            close_glyph: None,
+            close_icon: None,
+            max_label_chars: 40,
+            max_width: 1.0,
+            max_visible_actions: None,
+            actions_glyph: None,
         }
     }
 }
@@ -528,26 +929,90 @@ impl Default for ButtonStyleConfig {
 #[derive(Debug, Clone)]
 struct UiAction {
     key: String,
+    /// Label without its mnemonic marker.
     label: String,
+    /// Character marked with `_` in the sent label; triggers the action in keyboard mode.
+    mnemonic: Option<char>,
 }
 
 #[derive(Debug, Clone)]
 struct UiNotification {
     id: u32,
     app_name: String,
+    /// Name shown in the header; see `kde::header_app_name`.
+    header_name: String,
+    /// `x-kde-origin-name`: the device a relayed notification came from.
+    origin: Option<String>,
+    /// First `x-kde-urls` entry.
+    url: Option<String>,
+    /// `desktop-entry` hint, for `[[ui.rules]]` matchers.
+    desktop_entry: Option<String>,
+    /// Flatpak app id of the sender, for `[[ui.rules]]` matchers.
+    sandbox_app_id: Option<String>,
+    /// The icon drawn, as a path or theme name; see `popup_icon`.
     app_icon: String,
     summary: String,
     body: String,
     urgency: Urgency,
+    /// `category` hint, e.g. `email.arrived`.
+    category: Option<String>,
     actions: Vec<UiAction>,
+    /// `action-icons` hint: action keys name icons to draw in place of the labels.
+    action_icons: bool,
+    /// `resident` hint: an action leaves the popup up for the sender to replace.
+    resident: bool,
+    /// `value` hint as sent; the gauge clamps it to `0..=100`.
+    progress: Option<i32>,
+    /// `x`/`y` hints, for `ui.honor_position_hints`.
+    position: Option<(i32, i32)>,
+    /// Sound hints; played when the popup opens and again when it escalates.
+    sound: SoundHint,
+    /// `expire_timeout` as sent, before defaults and clamps.
+    requested_timeout_ms: i32,
+    /// Lifetime bounds for this notification, with its `[[ui.rules]]` applied.
+    timeout_clamp: TimeoutClamp,
     timeout_ms: Option<u32>,
     created_at: Instant,
+    /// When the source expires it, as reported with the event; `None` counts `timeout_ms`
+    /// from `created_at` instead.
+    expires_at: Option<Instant>,
+}
+
+impl UiNotification {
+    /// What `[[ui.rules]]` match and fill commands in from: the popup's content as shown,
+    /// so redacted content stays hidden.
+    fn rule_target(&self) -> Notification {
+        let mut hints = NotificationHints::default();
+        hints.category = self.category.clone();
+        hints.desktop_entry = self.desktop_entry.clone();
+        hints.sandbox_app_id = self.sandbox_app_id.clone();
+        hints.urls = self.url.iter().cloned().collect();
+        Notification {
+            app_name: self.app_name.clone(),
+            summary: self.summary.clone(),
+            body: self.body.clone(),
+            urgency: self.urgency.clone(),
+            hints,
+            ..Notification::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 struct WindowBinding {
     window_id: IcedId,
     notification_id: u32,
+    /// Start of the entrance animation; cleared once it has finished.
+    entered_at: Option<Instant>,
+}
+
+/// A popup whose notification is gone but whose window stays up for the exit animation.
+#[derive(Debug, Clone)]
+struct ClosingWindow {
+    window_id: IcedId,
+    notification: UiNotification,
+    height: u32,
+    started: Instant,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -565,6 +1030,34 @@ impl StackOutputPolicy {
     }
 }
 
+/// Requests delivered from outside the UI: by the Unix signal listener thread, and by
+/// `org.wispd.Ui1` calls.
+#[derive(Debug)]
+enum SignalRequest {
+    /// `SIGHUP`: reload `config.toml`.
+    ReloadConfig,
+    /// `SIGQUIT` or `DebugDump`: write a debug state dump; `reply` gets its path.
+    DebugDump { reply: Option<DumpReply> },
+    /// `ExplainNotification`: the JSON trace of `id`'s popup, `None` for unknown ids.
+    Explain {
+        id: u32,
+        reply: oneshot::Sender<Option<String>>,
+    },
+    /// `SetKeyboardMode`: give the front popup the keyboard, or take it back.
+    KeyboardMode(bool),
+    /// `ToggleHistoryPanel`: open the history panel, or close it.
+    ToggleHistoryPanel,
+    /// `ListMutes`: muted apps with the seconds left on each.
+    ListMutes {
+        reply: oneshot::Sender<Vec<(String, u64)>>,
+    },
+    /// `Unmute`: lift `app_name`'s mute; replies whether it was muted.
+    Unmute {
+        app_name: String,
+        reply: oneshot::Sender<bool>,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum SourceCommand {
     InvokeAction {
@@ -574,61 +1067,491 @@ enum SourceCommand {
     Dismiss {
         id: u32,
     },
+    Refresh {
+        id: u32,
+    },
+    SetPinned {
+        id: u32,
+        pinned: bool,
+    },
+    /// Announces the `count`th escalation of `id`; never answered.
+    Escalate {
+        id: u32,
+        count: u32,
+    },
+    /// An action picked on a history popup; `id` is the original one. Never answered,
+    /// the popup is already gone.
+    InvokeHistoricAction {
+        id: u32,
+        key: String,
+    },
     ReloadConfig {
-        capabilities: Vec<String>,
+        features: Features,
         default_timeout_ms: Option<i32>,
+        clamp_timeout: TimeoutClamp,
+        timeout_rules: Vec<TimeoutRule>,
+        dnd_schedule: Vec<DndWindow>,
+        history_ttl: Option<Duration>,
+        critical_never_expires: bool,
+    },
+    /// Every live notification, for a UI restarted after losing its display.
+    Resync,
+    /// The history, for the history panel; answered with [`SourceReply::History`] as
+    /// are the history commands below.
+    ListHistory,
+    /// Shows a history entry again, picked in the history panel.
+    ShowHistoric {
+        id: u32,
+    },
+    RemoveHistory {
+        ids: Vec<u32>,
+    },
+    /// Stars or unstars a history entry, from the history panel.
+    SetHistoryStarred {
+        id: u32,
+        starred: bool,
+    },
+    ClearHistory,
+    /// The source's part of a debug dump; answered with [`SourceReply::DebugSnapshot`].
+    DebugSnapshot,
+}
+
+impl SourceCommand {
+    fn notification_id(&self) -> Option<u32> {
+        match self {
+            Self::InvokeAction { id, .. }
+            | Self::Dismiss { id }
+            | Self::Refresh { id }
+            | Self::SetPinned { id, .. }
+            | Self::Escalate { id, .. } => Some(*id),
+            // The popup showing it has a local id.
+            Self::InvokeHistoricAction { .. }
+            | Self::ReloadConfig { .. }
+            | Self::Resync
+            | Self::ListHistory
+            | Self::ShowHistoric { .. }
+            | Self::RemoveHistory { .. }
+            | Self::SetHistoryStarred { .. }
+            | Self::ClearHistory
+            | Self::DebugSnapshot => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SourceReply {
+    Command {
+        command: SourceCommand,
+        result: Result<(), CommandError>,
+    },
+    Snapshot {
+        id: u32,
+        notification: Option<Box<Notification>>,
+    },
+    Resync {
+        notifications: Vec<(u32, Notification)>,
+        do_not_disturb: bool,
     },
+    /// The history, newest first.
+    History {
+        entries: Vec<HistoryEntry>,
+    },
+    DebugSnapshot(dump::SourceSnapshot),
 }
 
+/// Where a requested debug dump's path, or why it failed, goes.
+type DumpReply = oneshot::Sender<Result<String, String>>;
+
+const ERROR_FLASH_DURATION: Duration = Duration::from_millis(600);
+const COPIED_FLASH_DURATION: Duration = Duration::from_millis(900);
+const UNMUTE_ACTION_KEY: &str = "wispd-unmute";
+/// Horizontal padding (both sides) inside an action button.
+const ACTION_BUTTON_H_PADDING: f32 = 16.0;
+/// Longer animations would hold exiting windows (and their output) for too long.
+const MAX_ANIMATION_DURATION_MS: u64 = 2000;
+
 #[derive(Debug)]
 struct WispdUi {
     events: Arc<Mutex<mpsc::Receiver<NotificationEvent>>>,
-    reload_rx: Arc<Mutex<mpsc::Receiver<()>>>,
+    signal_rx: Arc<Mutex<mpsc::Receiver<SignalRequest>>>,
+    replies: Arc<Mutex<mpsc::Receiver<SourceReply>>>,
     cmd_tx: tokio_mpsc::UnboundedSender<SourceCommand>,
     notifications: HashMap<u32, UiNotification>,
     windows: VecDeque<WindowBinding>,
     measured_heights: HashMap<u32, u32>,
+    /// Text measurements behind height estimates; estimates are taken from `&self`.
+    text_metrics: RefCell<TextMetricsCache>,
+    /// How the configured fonts resolved at the last startup or reload.
+    fonts: Option<FontReport>,
     pending_measure: HashSet<u32>,
+    activating: HashSet<u32>,
+    error_flash: HashMap<u32, Instant>,
+    copied_flash: HashMap<u32, Instant>,
+    /// Popups whose hidden actions were expanded with the header affordance.
+    expanded_actions: HashSet<u32>,
+    /// Popup under the pointer, for hover-only controls.
+    hovered: Option<u32>,
+    /// Popup holding the keyboard in keyboard mode; its actions show their accelerators.
+    keyboard_target: Option<u32>,
+    /// Looks up `sound-name` hints in `ui.sound.theme`; rebuilt on config reload.
+    sounds: SoundResolver,
+    /// Decoded popup icons; see [`images`].
+    images: ImageCache,
+    decoder: Arc<dyn Decoder>,
+    /// Popups kept on screen: never evicted for `max_visible`, never timed out.
+    pinned: HashSet<u32>,
+    /// Escalation timers and badges of unacknowledged critical popups.
+    escalations: Escalations,
+    /// Confirmation prompts showing in place of action buttons.
+    confirmations: Confirmations,
+    /// Do-not-disturb as last reported by the source.
+    do_not_disturb: bool,
+    /// Notifications the source received silently, under do-not-disturb: tracked, but
+    /// no popup opens for them.
+    silenced: HashSet<u32>,
+    /// Set under `--mirror`: notifications belong to another daemon, so nothing is sent
+    /// back and their popups are read-only (and only opened with `show_popups`).
+    mirror: Option<MirrorSection>,
+    /// Under `--mirror`, what the history panel lists in place of the source's history.
+    mirror_history: MirrorHistory,
+    /// Callers waiting for the dump being assembled; `Some` while the source's part is
+    /// on its way.
+    pending_dump: Option<Vec<DumpReply>>,
     stack_output_policy: Option<StackOutputPolicy>,
+    /// Output names in connection order, as reported by the hotplug listener.
+    connected_outputs: Vec<String>,
+    /// Logical output sizes by name, for clamping the stack to its output.
+    output_sizes: HashMap<String, (u32, u32)>,
+    /// Outputs already warned about for margins larger than the output.
+    oversized_margin_warned: HashSet<String>,
+    /// Notifications whose popup did not fit on its output, next to reopen first.
+    queued: VecDeque<u32>,
+    /// Position in the round-robin output cycle of the next popup.
+    next_round_robin: usize,
+    /// Output each popup was opened on by name; stacks on different outputs are laid
+    /// out independently.
+    window_outputs: HashMap<IcedId, String>,
     ui: UiSection,
     default_timeout_ms: Option<i32>,
     next_local_notification_id: u32,
+    mutes: MuteList,
+    /// Local "muted" confirmation popups, keyed by id, offering an undo for the app.
+    unmute_offers: HashMap<u32, String>,
+    /// Local popups showing a closed notification again, keyed by local id.
+    historic: HashMap<u32, HistoricPopup>,
+    /// Arrivals per app, and the notifications and summary popups of apps flooding.
+    floods: Floods,
+    /// Faces of the built-in buttons, checked against the button font.
+    glyphs: Glyphs,
+    /// The critical takeover on screen and those waiting for it.
+    takeovers: Takeovers,
+    /// Rows, selection and navigation of the history panel while it is open.
+    history_panel: HistoryPanel,
+    /// The history panel surface; `None` while the panel is closed.
+    history_window: Option<IcedId>,
+    /// Search and chips of the history panel; cleared when it closes.
+    history_filter: HistoryFilter,
+    /// `notify_batch` whose events are being applied; relayout waits for its end.
+    open_batch: Option<u64>,
+    /// A relayout was skipped inside `open_batch`.
+    batch_relayout_pending: bool,
+    /// Relayout passes run since startup.
+    relayouts: u64,
+    /// Counters served at `/metrics`; see [`metrics`].
+    metrics: Arc<Metrics>,
+    /// Margin and size updates sent to popup windows since startup.
+    layer_updates: u64,
+    /// Set while `on_tick` applies its events; relayout waits for the last one.
+    applying_tick: bool,
+    /// Placement each popup window was last sent; relayout only sends what differs.
+    sent_layout: LayoutSnapshot<IcedId>,
+    /// Last stack-slot margin assigned to each window by `relayout_task`.
+    placed_margins: HashMap<IcedId, Margin>,
+    margin_tweens: HashMap<IcedId, MarginTween>,
+    closing: Vec<ClosingWindow>,
+    /// Set on the X11 fallback, which places popup windows itself.
+    #[cfg(feature = "x11")]
+    x11: Option<x11::X11Presenter>,
 }
 
 impl WispdUi {
     fn new(
         events: Arc<Mutex<mpsc::Receiver<NotificationEvent>>>,
-        reload_rx: Arc<Mutex<mpsc::Receiver<()>>>,
+        signal_rx: Arc<Mutex<mpsc::Receiver<SignalRequest>>>,
+        replies: Arc<Mutex<mpsc::Receiver<SourceReply>>>,
         cmd_tx: tokio_mpsc::UnboundedSender<SourceCommand>,
         ui: UiSection,
         default_timeout_ms: Option<i32>,
+        mutes: MuteList,
     ) -> Self {
         Self {
             events,
-            reload_rx,
+            signal_rx,
+            replies,
             cmd_tx,
             notifications: HashMap::new(),
             windows: VecDeque::new(),
             measured_heights: HashMap::new(),
+            text_metrics: RefCell::default(),
+            fonts: None,
             pending_measure: HashSet::new(),
+            activating: HashSet::new(),
+            error_flash: HashMap::new(),
+            copied_flash: HashMap::new(),
+            expanded_actions: HashSet::new(),
+            hovered: None,
+            keyboard_target: None,
+            sounds: SoundResolver::for_session(&ui.sound.theme),
+            images: ImageCache::default(),
+            decoder: Arc::new(FileDecoder),
+            pinned: HashSet::new(),
+            escalations: Escalations::default(),
+            confirmations: Confirmations::default(),
+            do_not_disturb: false,
+            silenced: HashSet::new(),
+            mirror: None,
+            mirror_history: MirrorHistory::default(),
+            pending_dump: None,
             stack_output_policy: None,
+            connected_outputs: Vec::new(),
+            output_sizes: HashMap::new(),
+            oversized_margin_warned: HashSet::new(),
+            queued: VecDeque::new(),
+            next_round_robin: 0,
+            window_outputs: HashMap::new(),
             ui,
             default_timeout_ms,
             next_local_notification_id: u32::MAX,
+            mutes,
+            unmute_offers: HashMap::new(),
+            historic: HashMap::new(),
+            floods: Floods::default(),
+            glyphs: Glyphs::preferred(&ui.buttons),
+            takeovers: Takeovers::default(),
+            history_panel: HistoryPanel::default(),
+            history_window: None,
+            history_filter: HistoryFilter::default(),
+            open_batch: None,
+            batch_relayout_pending: false,
+            relayouts: 0,
+            metrics: Arc::default(),
+            layer_updates: 0,
+            applying_tick: false,
+            sent_layout: LayoutSnapshot::default(),
+            placed_margins: HashMap::new(),
+            margin_tweens: HashMap::new(),
+            closing: Vec::new(),
+            #[cfg(feature = "x11")]
+            x11: None,
+        }
+    }
+
+    fn mirroring(mut self, cfg: MirrorSection) -> Self {
+        self.mirror = Some(cfg);
+        self
+    }
+
+    #[cfg(feature = "x11")]
+    fn presenting_on_x11(mut self, presenter: x11::X11Presenter) -> Self {
+        self.x11 = Some(presenter);
+        self
+    }
+
+    /// Opens a popup surface: a layer surface, or a placed window on the X11 fallback.
+    fn open_popup(&mut self, settings: NewLayerShellSettings) -> (IcedId, Task<Message>) {
+        #[cfg(feature = "x11")]
+        if let Some(presenter) = self.x11.as_mut() {
+            return presenter.open(settings);
+        }
+        Message::layershell_open(settings)
+    }
+
+    /// Mirrored notifications without `show_popups` are tracked but never opened;
+    /// wispd's own popups always show. Under do-not-disturb, non-critical notifications
+    /// without a popup stay that way; they are not shown when it ends either.
+    fn hides_popup(&self, id: u32) -> bool {
+        if self.is_local(id) {
+            return false;
+        }
+        self.mirror.is_some_and(|m| !m.show_popups)
+            || self.floods.is_collapsed(id)
+            || ((self.silenced.contains(&id)
+                || (self.do_not_disturb && self.urgency_of(id) != Urgency::Critical))
+                && !self.windows.iter().any(|w| w.notification_id == id))
+    }
+
+    /// Whether the popup's buttons can do anything: a mirror cannot act on the other
+    /// daemon's notifications.
+    fn is_interactive(&self, id: u32) -> bool {
+        self.mirror.is_none() || self.is_local(id)
+    }
+
+    /// Whether `id` at `urgency` shows as a critical takeover rather than in the stack.
+    /// A mirror cannot dismiss what it shows, so it never takes over.
+    fn takes_over(&self, id: u32, urgency: &Urgency) -> bool {
+        self.ui.urgency.critical.takeover
+            && *urgency == Urgency::Critical
+            && self.mirror.is_none()
+            && !self.is_local(id)
+    }
+
+    /// Queues `id` for a takeover, showing it now unless another one is on screen. The
+    /// source keeps it pinned, so it stays until dismissed or acted on.
+    fn offer_takeover(&mut self, id: u32) -> Task<Message> {
+        self.send_source_command(SourceCommand::SetPinned { id, pinned: true });
+        self.takeovers.enqueue(id);
+        if self.takeovers.is_active() {
+            info!(
+                id,
+                showing = ?self.takeovers.showing(),
+                waiting = self.takeovers.waiting(),
+                "critical takeover waiting for the one on screen"
+            );
+        }
+        self.show_next_takeover().unwrap_or_else(Task::none)
+    }
+
+    /// Shows the next waiting takeover, moving every stacked popup to the front of
+    /// `queued` so they come back in the same order once takeovers are over.
+    fn show_next_takeover(&mut self) -> Option<Task<Message>> {
+        let id = self.takeovers.next()?;
+        let mut tasks = Vec::new();
+        while let Some(binding) = self.windows.pop_back() {
+            self.queued.push_front(binding.notification_id);
+            tasks.push(self.retire_window(binding));
+        }
+
+        let critical = &self.ui.urgency.critical;
+        let output_option = self.output_option_for_new_window();
+        let backdrop = if critical.backdrop && !self.presents_on_x11() {
+            let (window_id, open) = self.open_popup(NewLayerShellSettings {
+                size: Some((0, 0)),
+                layer: Layer::Overlay,
+                anchor: Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right,
+                output_option: output_option.clone(),
+                keyboard_interactivity: KeyboardInteractivity::None,
+                exclusive_zone: Some(-1),
+                ..Default::default()
+            });
+            tasks.push(open);
+            Some(window_id)
+        } else {
+            None
+        };
+        let size = (critical.width.max(1), self.takeover_height(id).max(1));
+        let (card, open) = self.open_popup(NewLayerShellSettings {
+            size: Some(size),
+            layer: Layer::Overlay,
+            anchor: Anchor::empty(),
+            output_option,
+            keyboard_interactivity: KeyboardInteractivity::None,
+            exclusive_zone: Some(0),
+            namespace: Some(self.popup_namespace(id)),
+            ..Default::default()
+        });
+        tasks.push(open);
+        self.takeovers.show(id, TakeoverSurfaces { card, backdrop });
+        info!(
+            id,
+            ?card,
+            backdrop = backdrop.is_some(),
+            held_back = self.queued.len(),
+            "critical takeover shown"
+        );
+        Some(Task::batch(tasks))
+    }
+
+    /// Takes down the surfaces of the takeover `id` just removed, then shows the next
+    /// one, or restores the popups held back once none is left.
+    fn close_takeover(&mut self, id: u32, surfaces: TakeoverSurfaces) -> Task<Message> {
+        let mut tasks = vec![Task::done(Message::RemoveWindow(surfaces.card))];
+        tasks.extend(
+            surfaces
+                .backdrop
+                .map(|b| Task::done(Message::RemoveWindow(b))),
+        );
+        if let Some(next) = self.show_next_takeover() {
+            info!(id, "critical takeover closed; showing the next one");
+            tasks.push(next);
+            return Task::batch(tasks);
+        }
+        info!(
+            id,
+            restoring = self.queued.len(),
+            "critical takeover closed"
+        );
+        tasks.extend(self.reopen_queued());
+        tasks.push(self.relayout_task());
+        Task::batch(tasks)
+    }
+
+    /// Fits the takeover card to its replaced content; a waiting one has no card yet.
+    fn resize_takeover(&mut self, id: u32) -> Task<Message> {
+        let Some(surfaces) = self
+            .takeovers
+            .surfaces()
+            .filter(|_| self.takeovers.showing() == Some(id))
+        else {
+            return Task::none();
+        };
+        Task::done(Message::AnchorSizeChange {
+            id: surfaces.card,
+            anchor: Anchor::empty(),
+            size: (
+                self.ui.urgency.critical.width.max(1),
+                self.takeover_height(id).max(1),
+            ),
+        })
+    }
+
+    /// Estimated height of `id` laid out at the takeover width.
+    fn takeover_height(&self, id: u32) -> u32 {
+        let ui = UiSection {
+            width: self.ui.urgency.critical.width,
+            ..self.ui.clone()
+        };
+        self.notifications
+            .get(&id)
+            .map(|n| estimate_popup_height(&ui, n, &mut self.text_metrics.borrow_mut()))
+            .unwrap_or(ui.height.max(1))
+    }
+
+    /// The X11 fallback has no overlay layer to stretch a backdrop over.
+    fn presents_on_x11(&self) -> bool {
+        #[cfg(feature = "x11")]
+        if self.x11.is_some() {
+            return true;
         }
+        false
     }
 
     fn on_tick(&mut self) -> Task<Message> {
         let mut pending = Vec::new();
         let mut reload_requested = false;
+        let mut dump_requests = Vec::new();
+        let mut explain_requests = Vec::new();
+        let mut keyboard_mode = None;
+        let mut toggle_history_panel = false;
+        let mut mute_requests = Vec::new();
 
-        if let Ok(reload_rx) = self.reload_rx.lock() {
+        if let Ok(signal_rx) = self.signal_rx.lock() {
             loop {
-                match reload_rx.try_recv() {
-                    Ok(()) => reload_requested = true,
+                match signal_rx.try_recv() {
+                    Ok(SignalRequest::ReloadConfig) => reload_requested = true,
+                    Ok(SignalRequest::DebugDump { reply }) => dump_requests.push(reply),
+                    Ok(SignalRequest::Explain { id, reply }) => explain_requests.push((id, reply)),
+                    Ok(SignalRequest::KeyboardMode(enabled)) => keyboard_mode = Some(enabled),
+                    Ok(SignalRequest::ToggleHistoryPanel) => {
+                        toggle_history_panel = !toggle_history_panel;
+                    }
+                    Ok(
+                        request @ (SignalRequest::ListMutes { .. } | SignalRequest::Unmute { .. }),
+                    ) => {
+                        mute_requests.push(request);
+                    }
                     Err(mpsc::TryRecvError::Empty) => break,
                     Err(mpsc::TryRecvError::Disconnected) => {
-                        warn!("reload channel disconnected");
+                        warn!("signal channel disconnected");
                         break;
                     }
                 }
@@ -648,15 +1571,74 @@ impl WispdUi {
             }
         }
 
+        let mut replies = Vec::new();
+        if let Ok(receiver) = self.replies.lock() {
+            loop {
+                match receiver.try_recv() {
+                    Ok(reply) => replies.push(reply),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        warn!("source reply channel disconnected");
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.error_flash
+            .retain(|_, started| started.elapsed() < ERROR_FLASH_DURATION);
+        self.copied_flash
+            .retain(|_, started| started.elapsed() < COPIED_FLASH_DURATION);
+        self.escalate_due(Instant::now());
+
         let processed = pending.len();
-        let mut tasks = Vec::new();
+        self.applying_tick = true;
+        let mut expired = self.expired_historic(Instant::now());
+        expired.extend(self.expired_flood_summaries(Instant::now()));
+        let mut tasks: Vec<_> = expired
+            .into_iter()
+            .map(|id| self.remove_notification(id))
+            .collect();
+        for id in self.confirmations.expire(Instant::now()) {
+            debug!(id, "action confirmation timed out");
+            tasks.push(self.remeasure(id));
+        }
 
         if reload_requested {
             tasks.push(self.reload_config());
         }
-        for event in pending {
+        if !dump_requests.is_empty() {
+            self.request_debug_dump(dump_requests);
+        }
+        for event in coalesce_events(pending) {
             tasks.push(self.apply_event(event));
         }
+        for reply in replies {
+            tasks.push(self.handle_source_reply(reply));
+        }
+        self.applying_tick = false;
+        if self.open_batch.is_none() && std::mem::take(&mut self.batch_relayout_pending) {
+            tasks.push(self.relayout_task());
+        }
+        if let Some(enabled) = keyboard_mode {
+            tasks.push(self.set_keyboard_mode(enabled));
+        }
+        if toggle_history_panel {
+            tasks.push(self.toggle_history_panel());
+        }
+        tasks.push(self.close_idle_history_panel(Instant::now()));
+        for request in mute_requests {
+            tasks.push(self.answer_mute_request(request));
+        }
+        // Answered after this tick's events so the trace matches what is on screen.
+        for (id, reply) in explain_requests {
+            let json = self.explain(id, Instant::now()).and_then(|explanation| {
+                serde_json::to_string_pretty(&explanation)
+                    .inspect_err(|err| warn!(%err, id, "failed to serialize explanation"))
+                    .ok()
+            });
+            let _ = reply.send(json);
+        }
 
         if processed > 0 {
             info!(processed, visible = self.windows.len(), "ui state updated");
@@ -665,60 +1647,360 @@ impl WispdUi {
         for id in self.pending_measure.iter().copied() {
             tasks.push(measure_notification_height_task(id));
         }
+        self.metrics.set_queue_depth(self.queued.len());
 
         Task::batch(tasks)
     }
 
     fn apply_event(&mut self, event: NotificationEvent) -> Task<Message> {
+        if self.mirror.is_some() {
+            self.mirror_history.observe(&event, SystemTime::now());
+        }
         match event {
-            NotificationEvent::Received { id, notification } => self.insert_new(id, *notification),
-            NotificationEvent::Replaced { id, current, .. } => {
-                self.notifications.insert(
-                    id,
-                    to_ui_notification(id, *current, self.default_timeout_ms),
-                );
+            NotificationEvent::Received {
+                id,
+                notification,
+                silent,
+                remaining_ms,
+            } => {
+                if self.is_muted(&notification.app_name) {
+                    debug!(id, app = %notification.app_name, "app muted; not showing popup");
+                    return Task::none();
+                }
+                if silent {
+                    self.silenced.insert(id);
+                }
+                if notification.urgency != Urgency::Critical
+                    && self.floods.arrive(
+                        &notification.app_name,
+                        Instant::now(),
+                        self.ui.flood_threshold,
+                        Duration::from_millis(self.ui.flood_window_ms),
+                    )
+                {
+                    let task = self.collapse_flood(id, *notification);
+                    self.adopt_source_expiry(id, remaining_ms);
+                    return task;
+                }
+                let task = self.insert_new(id, *notification);
+                self.adopt_source_expiry(id, remaining_ms);
+                task
+            }
+            NotificationEvent::Replaced {
+                id,
+                current,
+                remaining_ms,
+                ..
+            } => {
+                if !self.notifications.contains_key(&id) && self.is_muted(&current.app_name) {
+                    debug!(id, app = %current.app_name, "app muted; not showing replacement");
+                    return Task::none();
+                }
+                let urgency_changed = self
+                    .notifications
+                    .get(&id)
+                    .is_some_and(|n| n.urgency != current.urgency);
+                let current = self.to_ui(id, *current);
+                // The prompt was for the old content.
+                if self.confirmations.cancel(id) {
+                    debug!(id, "replacement cancelled action confirmation");
+                }
+                if let Some(previous) = self.notifications.insert(id, current) {
+                    self.forget_text_metrics(&previous);
+                }
+                self.adopt_source_expiry(id, remaining_ms);
+                let icon = self.load_icon(id);
+                if self.takeovers.contains(id) {
+                    return Task::batch([icon, self.resize_takeover(id)]);
+                }
+                if self.hides_popup(id) {
+                    return icon;
+                }
                 self.measured_heights.remove(&id);
                 self.pending_measure.insert(id);
-                self.relayout_task()
+                if urgency_changed {
+                    self.resort_binding(id);
+                    if self.urgency_of(id) == Urgency::Critical {
+                        self.arm_escalation(id);
+                    } else {
+                        self.escalations.acknowledge(id);
+                    }
+                }
+                Task::batch([icon, self.relayout_unless_batched()])
             }
             NotificationEvent::Closed { id, .. } => self.remove_notification(id),
             NotificationEvent::ActionInvoked { .. } => Task::none(),
+            NotificationEvent::DndChanged { enabled } => {
+                info!(enabled, "do-not-disturb changed");
+                self.do_not_disturb = enabled;
+                Task::none()
+            }
+            NotificationEvent::Historic {
+                id,
+                notification,
+                actions_enabled,
+                chain,
+            } => self.show_historic(id, *notification, actions_enabled, &chain),
+            NotificationEvent::BatchStarted { batch_id } => {
+                debug!(batch_id, "notification batch started");
+                self.open_batch = Some(batch_id);
+                Task::none()
+            }
+            NotificationEvent::BatchEnded { batch_id } => {
+                if self.open_batch != Some(batch_id) {
+                    warn!(batch_id, open = ?self.open_batch, "end of a batch that is not open");
+                }
+                self.open_batch = None;
+                if std::mem::take(&mut self.batch_relayout_pending) {
+                    debug!(batch_id, "notification batch ended; relayout");
+                    self.relayout_unless_batched()
+                } else {
+                    Task::none()
+                }
+            }
+            NotificationEvent::Overflowed { dropped } => {
+                warn!(dropped, "source events were lost; resyncing");
+                self.send_source_command(SourceCommand::Resync);
+                Task::none()
+            }
+            NotificationEvent::RateLimited {
+                app_name,
+                suppressed,
+            } => {
+                warn!(app = %app_name, suppressed, "notifications dropped by the source's rate limit");
+                Task::none()
+            }
+            _ => Task::none(),
         }
     }
 
-    fn insert_new(&mut self, id: u32, notification: Notification) -> Task<Message> {
-        let summary = notification.summary.clone();
-        let app_name = notification.app_name.clone();
-
-        self.notifications.insert(
-            id,
-            to_ui_notification(id, notification, self.default_timeout_ms),
-        );
-        self.measured_heights.remove(&id);
-        self.pending_measure.insert(id);
-        debug!(id, summary = %summary, app = %app_name, "notification entered pending measurement state");
+    /// Plays the sound of popup `id`, unless sounds are off or nothing pops up.
+    fn play_sound(&mut self, id: u32) {
+        if !self.ui.sound.enabled || self.is_local(id) || self.hides_popup(id) {
+            return;
+        }
+        let Some(hint) = self.notifications.get(&id).map(|n| n.sound.clone()) else {
+            return;
+        };
+        let urgency = self.urgency_of(id);
+        if let Some(path) = sounds::pick(&self.ui.sound, &mut self.sounds, &hint, &urgency) {
+            sounds::play(&self.ui.sound.player, &path);
+        }
+    }
 
-        if self.windows.iter().any(|w| w.notification_id == id) {
+    /// `relayout_task`, or a note to run it once the open `notify_batch` or the events
+    /// of this tick are applied.
+    fn relayout_unless_batched(&mut self) -> Task<Message> {
+        if self.open_batch.is_some() || self.applying_tick {
+            self.batch_relayout_pending = true;
             return Task::none();
         }
+        self.relayout_task()
+    }
 
-        let stack_was_empty = self.windows.is_empty();
-        info!(id, app = %app_name, summary = %summary, stack_was_empty, visible = self.windows.len(), "opening notification popup");
+    fn insert_new(&mut self, id: u32, notification: Notification) -> Task<Message> {
+        let summary = notification.summary.clone();
+        let app_name = notification.app_name.clone();
+        self.log_ellipsized_actions(id, &notification);
 
-        let mut tasks = vec![self.open_window_for_notification(id)];
+        let notification = self.to_ui(id, notification);
+        self.notifications.insert(id, notification);
+        self.play_sound(id);
+        let icon = self.load_icon(id);
+        Task::batch([icon, self.present_new(id, &summary, &app_name)])
+    }
 
+    /// Tracks `id` from a flooding app without a popup and counts it in the app's summary
+    /// popup, opening one when none is up. The summary is updated in place, restarting
+    /// its timeout.
+    fn collapse_flood(&mut self, id: u32, notification: Notification) -> Task<Message> {
+        let app_name = notification.app_name.clone();
+        let app_icon = notification.app_icon.clone();
+        let collapsed = self.floods.collapse(id, &app_name);
+        let tracked = self.insert_new(id, notification);
+        let summary = match collapsed {
+            Some((summary_id, count)) => {
+                debug!(id, summary_id, count, app = %app_name, "flooding app; summary updated");
+                if let Some(n) = self.notifications.get_mut(&summary_id) {
+                    n.summary = flood::summary_text(&app_name, count);
+                    n.created_at = Instant::now();
+                }
+                self.remeasure(summary_id)
+            }
+            None => {
+                let summary_id = self.next_local_notification_id();
+                info!(id, summary_id, app = %app_name, "flooding app; collapsing into a summary");
+                self.floods.start_summary(&app_name, summary_id);
+                self.insert_new(
+                    summary_id,
+                    Notification {
+                        summary: flood::summary_text(&app_name, 1),
+                        app_name,
+                        app_icon,
+                        body: String::new(),
+                        urgency: Urgency::Normal,
+                        timeout_ms: -1,
+                        actions: vec![NotificationAction {
+                            key: "default".to_string(),
+                            label: "Show in history".to_string(),
+                        }],
+                        hints: Default::default(),
+                    },
+                )
+            }
+        };
+        Task::batch([tracked, summary])
+    }
+
+    /// Shows a just-inserted notification: as a takeover, a popup, or held back.
+    fn present_new(&mut self, id: u32, summary: &str, app_name: &str) -> Task<Message> {
+        if self.takes_over(id, &self.urgency_of(id)) {
+            return self.offer_takeover(id);
+        }
+        if self.hides_popup(id) {
+            debug!(id, summary = %Loggable(summary), app = %app_name, dnd = self.do_not_disturb, "notification tracked without a popup");
+            return Task::none();
+        }
+        if self.takeovers.is_active() {
+            debug!(id, summary = %Loggable(summary), "popup held back behind a critical takeover");
+            self.queued.push_front(id);
+            return Task::none();
+        }
+        self.measured_heights.remove(&id);
+        self.pending_measure.insert(id);
+        debug!(id, summary = %Loggable(summary), app = %app_name, "notification entered pending measurement state");
+
+        if self.windows.iter().any(|w| w.notification_id == id) {
+            return Task::none();
+        }
+
+        let stack_was_empty = self.windows.is_empty();
+        info!(id, app = %app_name, summary = %Loggable(summary), stack_was_empty, visible = self.windows.len(), "opening notification popup");
+        self.arm_escalation(id);
+
+        let mut tasks = vec![self.open_window_for_notification(id)];
+        tasks.extend(self.evict_overflow());
+        tasks.push(self.relayout_unless_batched());
+        Task::batch(tasks)
+    }
+
+    /// `to_ui_notification` with the current settings; pinned popups show no timeout.
+    /// Content hidden by `privacy_apps` is dropped here, so the UI never holds it.
+    fn to_ui(&self, id: u32, mut notification: Notification) -> UiNotification {
+        // Resolved on the content as sent, as the source does.
+        let clamp = self.ui.timeout_clamp_for(&notification);
+        if privacy::redact(
+            &self.ui.privacy_apps,
+            &self.ui.redaction_placeholder,
+            &notification.app_name,
+            &mut notification.summary,
+            &mut notification.body,
+        ) {
+            notification.hints.urls.clear();
+        }
+        let mut n = to_ui_notification(id, notification, self.default_timeout_ms, clamp);
+        if self.pinned.contains(&id)
+            || self.takeovers.contains(id)
+            || self.takes_over(id, &n.urgency)
+            || self.ui.never_expires(&n.urgency)
+        {
+            n.timeout_ms = None;
+        }
+        for text in [&mut n.header_name, &mut n.summary, &mut n.body] {
+            let normalized = match normalize_whitespace(text, self.ui.tab_width) {
+                Cow::Borrowed(_) => continue,
+                Cow::Owned(normalized) => normalized,
+            };
+            *text = normalized;
+        }
+        if resolve_icon_path(&n.app_icon).is_some_and(|path| self.images.failed(&path)) {
+            n.app_icon.clear();
+        }
+        n
+    }
+
+    /// Starts decoding the icon of `id` unless it is cached or already on the way.
+    fn load_icon(&mut self, id: u32) -> Task<Message> {
+        let Some(path) = self
+            .notifications
+            .get(&id)
+            .and_then(|n| renderable_icon_path(self.ui.show_icons, &n.app_icon))
+        else {
+            return Task::none();
+        };
+        if !self.images.request(&path) {
+            return Task::none();
+        }
+        debug!(id, path = %path.display(), "decoding popup icon");
+        Task::perform(
+            images::decode(Arc::clone(&self.decoder), path),
+            move |(path, handle)| Message::ImageReady { id, path, handle },
+        )
+    }
+
+    /// Caches a decoded icon. An icon that cannot be decoded is dropped from every popup
+    /// showing it, and the space reserved for it collapses.
+    fn image_ready(
+        &mut self,
+        id: u32,
+        path: PathBuf,
+        handle: Result<Handle, String>,
+    ) -> Task<Message> {
+        self.images.finish(path.clone(), &handle);
+        if let Err(err) = handle {
+            warn!(id, path = %path.display(), %err, "failed to decode popup icon; showing the popup without it");
+            let showing: Vec<u32> = self
+                .notifications
+                .iter_mut()
+                .filter(|(_, n)| resolve_icon_path(&n.app_icon).as_deref() == Some(path.as_path()))
+                .map(|(id, n)| {
+                    n.app_icon.clear();
+                    *id
+                })
+                .collect();
+            return Task::batch(showing.into_iter().map(|id| self.remeasure(id)));
+        }
+        Task::none()
+    }
+
+    /// Retires popups beyond `max_visible`, lowest priority first, skipping pinned ones;
+    /// a stack of pinned popups may exceed the limit.
+    fn evict_overflow(&mut self) -> Vec<Task<Message>> {
+        let mut tasks = Vec::new();
         while self.windows.len() > self.ui.max_visible {
-            if let Some(evicted) = self.windows.pop_back() {
+            let Some(index) = self
+                .windows
+                .iter()
+                .rposition(|w| !self.pinned.contains(&w.notification_id))
+            else {
+                break;
+            };
+            if let Some(evicted) = self.windows.remove(index) {
+                tasks.push(self.retire_window(evicted));
                 self.notifications.remove(&evicted.notification_id);
-                tasks.push(Task::done(Message::RemoveWindow(evicted.window_id)));
             }
         }
+        tasks
+    }
 
-        tasks.push(self.relayout_task());
-        Task::batch(tasks)
+    fn log_ellipsized_actions(&self, id: u32, notification: &Notification) {
+        let has_icon =
+            renderable_icon_path(self.ui.show_icons, &popup_icon(notification)).is_some();
+        let budget = action_label_char_budget(&self.ui, has_icon);
+        for action in &notification.actions {
+            if matches!(ellipsize_label(&action.label, budget), Cow::Owned(_)) {
+                debug!(id, action_key = %action.key, label = %Loggable(&action.label), "action label ellipsized");
+            }
+        }
     }
 
     fn open_window_for_notification(&mut self, id: u32) -> Task<Message> {
+        self.open_window_at(id, self.stack_index_for(id))
+    }
+
+    /// Opens a popup window for `id` at position `index` of `windows`.
+    fn open_window_at(&mut self, id: u32, index: usize) -> Task<Message> {
+        profiling::milestone("first_window");
         let popup_height = self.popup_height_for_id(id);
         let had_existing_windows = !self.windows.is_empty();
         let output_option = self.output_option_for_new_window();
@@ -728,14 +2010,19 @@ impl WispdUi {
             .map(StackOutputPolicy::log_label)
             .unwrap_or_else(|| "none".to_string());
         let output_target = describe_output_option(&output_option);
+        let named_output = match &output_option {
+            OutputOption::OutputName(name) => Some(name.clone()),
+            _ => None,
+        };
 
-        let (window_id, open_task) = Message::layershell_open(NewLayerShellSettings {
+        let (window_id, open_task) = self.open_popup(NewLayerShellSettings {
             size: Some((self.ui.width.max(1), popup_height.max(1))),
             layer: Layer::Top,
             anchor: layer_anchor_from_str(&self.ui.anchor),
             output_option,
             keyboard_interactivity: KeyboardInteractivity::None,
             exclusive_zone: Some(0),
+            namespace: Some(self.popup_namespace(id)),
             margin: Some((
                 self.ui.margin.top,
                 self.ui.margin.right,
@@ -744,10 +2031,25 @@ impl WispdUi {
             )),
             ..Default::default()
         });
-        self.windows.push_front(WindowBinding {
+        if let Some(name) = named_output {
+            self.window_outputs.insert(window_id, name);
+        }
+        self.sent_layout.record(
             window_id,
-            notification_id: id,
-        });
+            Placement {
+                margin: self.base_margin(),
+                anchor: layer_anchor_from_str(&self.ui.anchor),
+                size: (self.ui.width.max(1), popup_height.max(1)),
+            },
+        );
+        self.windows.insert(
+            index,
+            WindowBinding {
+                window_id,
+                notification_id: id,
+                entered_at: self.ui.animation.enabled.then(Instant::now),
+            },
+        );
 
         info!(
             id,
@@ -758,11 +2060,89 @@ impl WispdUi {
             visible = self.windows.len(),
             "notification popup window opened"
         );
+        self.metrics.record_popup_opened();
 
         open_task
     }
 
+    /// `ui.namespace_template` for `id`'s popup surface.
+    fn popup_namespace(&self, id: u32) -> String {
+        let app_name = self
+            .notifications
+            .get(&id)
+            .map_or("", |n| n.app_name.as_str());
+        popup_namespace(&self.ui.namespace_template, app_name, self.urgency_of(id))
+    }
+
+    fn urgency_of(&self, id: u32) -> Urgency {
+        self.notifications
+            .get(&id)
+            .map(|n| n.urgency.clone())
+            .unwrap_or_default()
+    }
+
+    /// Position a binding for `id` takes in `windows`. With `urgency-then-arrival` the
+    /// deque stays sorted, so overflow eviction from the back drops the lowest priority.
+    fn stack_index_for(&self, id: u32) -> usize {
+        match self.ui.sort {
+            StackSort::Arrival => 0,
+            StackSort::UrgencyThenArrival => {
+                let urgency = self.urgency_of(id);
+                self.windows
+                    .iter()
+                    .position(|w| self.urgency_of(w.notification_id) <= urgency)
+                    .unwrap_or(self.windows.len())
+            }
+        }
+    }
+
+    /// Position a queued popup takes when it gets a window again: behind everything it
+    /// arrived after.
+    fn reopen_index_for(&self, id: u32) -> usize {
+        match self.ui.sort {
+            StackSort::Arrival => self.windows.len(),
+            StackSort::UrgencyThenArrival => {
+                let urgency = self.urgency_of(id);
+                self.windows
+                    .iter()
+                    .position(|w| self.urgency_of(w.notification_id) < urgency)
+                    .unwrap_or(self.windows.len())
+            }
+        }
+    }
+
+    fn resort_binding(&mut self, id: u32) {
+        if self.ui.sort != StackSort::UrgencyThenArrival {
+            return;
+        }
+        let Some(index) = self.windows.iter().position(|w| w.notification_id == id) else {
+            return;
+        };
+        if let Some(binding) = self.windows.remove(index) {
+            let index = self.stack_index_for(id);
+            self.windows.insert(index, binding);
+        }
+    }
+
+    fn sort_windows(&mut self) {
+        if self.ui.sort != StackSort::UrgencyThenArrival {
+            return;
+        }
+        let notifications = &self.notifications;
+        self.windows.make_contiguous().sort_by_key(|w| {
+            Reverse(
+                notifications
+                    .get(&w.notification_id)
+                    .map(|n| n.urgency.clone())
+                    .unwrap_or_default(),
+            )
+        });
+    }
+
     fn output_option_for_new_window(&mut self) -> OutputOption {
+        if is_round_robin(&self.ui.output) {
+            return self.next_round_robin_output();
+        }
         if let Some(policy) = self.stack_output_policy.as_ref() {
             let output_option = match policy {
                 StackOutputPolicy::CompositorChosen => OutputOption::LastOutput,
@@ -790,18 +2170,77 @@ impl WispdUi {
         output_option
     }
 
+    /// The next output in the round-robin cycle: `ui.outputs` less any known to be
+    /// disconnected, or every connected output. With none known, the compositor picks.
+    fn next_round_robin_output(&mut self) -> OutputOption {
+        let cycle: Vec<&String> = if self.ui.outputs.is_empty() {
+            self.connected_outputs.iter().collect()
+        } else {
+            self.ui
+                .outputs
+                .iter()
+                .filter(|name| {
+                    self.connected_outputs.is_empty() || self.connected_outputs.contains(name)
+                })
+                .collect()
+        };
+        if cycle.is_empty() {
+            info!(
+                visible = self.windows.len(),
+                "no outputs to cycle through; letting the compositor pick"
+            );
+            return OutputOption::CompositorDefault;
+        }
+
+        let name = cycle[self.next_round_robin % cycle.len()].clone();
+        self.next_round_robin = self.next_round_robin.wrapping_add(1);
+        info!(
+            output = %name,
+            cycle_len = cycle.len(),
+            visible = self.windows.len(),
+            "round-robin placed notification popup"
+        );
+        OutputOption::OutputName(name)
+    }
+
     fn remove_notification(&mut self, id: u32) -> Task<Message> {
+        let retired = self
+            .windows
+            .iter()
+            .position(|w| w.notification_id == id)
+            .and_then(|index| self.windows.remove(index))
+            .map(|binding| self.retire_window(binding));
+
         self.notifications.remove(&id);
+        self.silenced.remove(&id);
+        self.queued.retain(|queued| *queued != id);
         self.measured_heights.remove(&id);
         self.pending_measure.remove(&id);
+        self.activating.remove(&id);
+        self.error_flash.remove(&id);
+        self.copied_flash.remove(&id);
+        self.expanded_actions.remove(&id);
+        self.unmute_offers.remove(&id);
+        self.historic.remove(&id);
+        self.floods.remove(id);
+        self.pinned.remove(&id);
+        self.escalations.acknowledge(id);
+        self.confirmations.cancel(id);
+        if self.hovered == Some(id) {
+            self.hovered = None;
+        }
+        if self.keyboard_target == Some(id) {
+            debug!(id, "keyboard mode ended with its popup");
+            self.keyboard_target = None;
+        }
 
-        if let Some(index) = self.windows.iter().position(|w| w.notification_id == id)
-            && let Some(binding) = self.windows.remove(index)
-        {
-            let mut tasks = vec![
-                Task::done(Message::RemoveWindow(binding.window_id)),
-                self.relayout_task(),
-            ];
+        if let Some(surfaces) = self.takeovers.remove(id) {
+            return self.close_takeover(id, surfaces);
+        }
+        if let Some(retired) = retired {
+            let mut tasks = vec![retired];
+            tasks.extend(self.reopen_queued());
+            tasks.push(self.relayout_unless_batched());
             if self.windows.is_empty() {
                 let previous_policy = self
                     .stack_output_policy
@@ -818,7 +2257,172 @@ impl WispdUi {
         Task::none()
     }
 
+    /// Takes down the window of a binding already removed from `windows`. With animations
+    /// on, the window lingers in `closing` (rendering a snapshot of its notification) until
+    /// the exit animation ends; call before dropping the notification itself.
+    fn retire_window(&mut self, binding: WindowBinding) -> Task<Message> {
+        let notification = self
+            .notifications
+            .get(&binding.notification_id)
+            .filter(|_| self.ui.animation.enabled)
+            .cloned();
+        let Some(notification) = notification else {
+            self.forget_window(binding.window_id);
+            return Task::done(Message::RemoveWindow(binding.window_id));
+        };
+
+        let now = Instant::now();
+        if let Some(tween) = self.margin_tweens.remove(&binding.window_id) {
+            let current = tween.at(now, self.animation_duration());
+            self.placed_margins.insert(binding.window_id, current);
+        }
+        self.closing.push(ClosingWindow {
+            window_id: binding.window_id,
+            height: self.popup_height_for_id(binding.notification_id),
+            notification,
+            started: now,
+        });
+        debug!(id = binding.notification_id, window_id = ?binding.window_id, "deferring popup removal for exit animation");
+        Task::none()
+    }
+
+    fn forget_window(&mut self, window_id: IcedId) {
+        self.placed_margins.remove(&window_id);
+        self.sent_layout.forget(window_id);
+        self.window_outputs.remove(&window_id);
+        self.margin_tweens.remove(&window_id);
+    }
+
+    fn animation_duration(&self) -> Duration {
+        if self.ui.animation.enabled {
+            self.ui.animation.duration()
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    fn animations_active(&self) -> bool {
+        !self.closing.is_empty()
+            || !self.margin_tweens.is_empty()
+            || self.windows.iter().any(|w| w.entered_at.is_some())
+    }
+
+    /// Applies the slide offset for `presence` to `margin`.
+    fn slide_margin(&self, presence: Presence, margin: Margin) -> Margin {
+        let anchor = layer_anchor_from_str(&self.ui.anchor);
+        presence.slide_margin(
+            self.ui.animation.kind,
+            margin,
+            self.ui.width,
+            anchor.contains(Anchor::Right),
+            anchor.contains(Anchor::Left),
+        )
+    }
+
+    /// Margin a visible popup should have at `now`, including any reflow or entrance
+    /// motion in progress.
+    fn frame_margin(&self, binding: &WindowBinding, now: Instant) -> Margin {
+        let duration = self.animation_duration();
+        let margin = match self.margin_tweens.get(&binding.window_id) {
+            Some(tween) => tween.at(now, duration),
+            None => self
+                .placed_margins
+                .get(&binding.window_id)
+                .copied()
+                .unwrap_or_else(|| self.base_margin()),
+        };
+        match binding.entered_at {
+            Some(started) => self.slide_margin(Presence::entering(started, now, duration), margin),
+            None => margin,
+        }
+    }
+
+    /// Sends `margin` to a popup window outside a layout pass, keeping `sent_layout`
+    /// in step.
+    fn move_window(&mut self, window_id: IcedId, margin: Margin) -> Task<Message> {
+        self.sent_layout.record_margin(window_id, margin);
+        self.layer_updates += 1;
+        Task::done(Message::MarginChange {
+            id: window_id,
+            margin,
+        })
+    }
+
+    fn base_margin(&self) -> Margin {
+        (
+            self.ui.margin.top,
+            self.ui.margin.right,
+            self.ui.margin.bottom,
+            self.ui.margin.left,
+        )
+    }
+
+    /// Advances running animations: moves popups along their tweens, finishes entrances
+    /// and removes popups whose exit animation is over.
+    fn on_animation_frame(&mut self, now: Instant) -> Task<Message> {
+        let duration = self.animation_duration();
+        let slides = self.ui.animation.kind == animation::AnimationKind::Slide;
+        let mut tasks = Vec::new();
+
+        let (finished, running): (Vec<_>, Vec<_>) = std::mem::take(&mut self.closing)
+            .into_iter()
+            .partition(|c| animation::progress(c.started, now, duration) >= 1.0);
+        for closing in &running {
+            if slides && let Some(&margin) = self.placed_margins.get(&closing.window_id) {
+                let margin =
+                    self.slide_margin(Presence::leaving(closing.started, now, duration), margin);
+                tasks.push(self.move_window(closing.window_id, margin));
+            }
+        }
+        for closing in finished {
+            debug!(id = closing.notification.id, window_id = ?closing.window_id, "exit animation finished");
+            self.forget_window(closing.window_id);
+            tasks.push(Task::done(Message::RemoveWindow(closing.window_id)));
+        }
+        self.closing = running;
+
+        for index in 0..self.windows.len() {
+            let binding = self.windows[index];
+            let tween = self.margin_tweens.get(&binding.window_id).copied();
+            if tween.is_some() || (slides && binding.entered_at.is_some()) {
+                let margin = self.frame_margin(&binding, now);
+                tasks.push(self.move_window(binding.window_id, margin));
+            }
+            if tween.is_some_and(|t| t.is_done(now, duration)) {
+                self.margin_tweens.remove(&binding.window_id);
+            }
+            if binding
+                .entered_at
+                .is_some_and(|started| animation::progress(started, now, duration) >= 1.0)
+            {
+                self.windows[index].entered_at = None;
+            }
+        }
+
+        Task::batch(tasks)
+    }
+
     fn handle_window_closed(&mut self, window_id: IcedId) -> Task<Message> {
+        if self.history_window == Some(window_id) {
+            debug!(?window_id, "history panel closed by the compositor");
+            self.history_window = None;
+            self.forget_window(window_id);
+            return self.relayout_unless_batched();
+        }
+        if let Some(id) = self.takeovers.card_of(window_id) {
+            warn!(
+                id,
+                ?window_id,
+                "compositor closed the critical takeover; dismissing it"
+            );
+            return self.dismiss(id);
+        }
+        if let Some(index) = self.closing.iter().position(|c| c.window_id == window_id) {
+            self.closing.remove(index);
+            self.forget_window(window_id);
+            return Task::none();
+        }
+
         let Some(index) = self.windows.iter().position(|w| w.window_id == window_id) else {
             return Task::none();
         };
@@ -827,9 +2431,12 @@ impl WispdUi {
             return Task::none();
         };
 
+        self.forget_window(window_id);
         self.notifications.remove(&binding.notification_id);
         self.measured_heights.remove(&binding.notification_id);
         self.pending_measure.remove(&binding.notification_id);
+        self.pinned.remove(&binding.notification_id);
+        self.escalations.acknowledge(binding.notification_id);
 
         let mut tasks = vec![self.relayout_task()];
         if self.windows.is_empty() {
@@ -857,6 +2464,9 @@ impl WispdUi {
 
         self.windows.clear();
         self.stack_output_policy = None;
+        for window_id in &window_ids {
+            self.forget_window(*window_id);
+        }
 
         info!(
             notification_count = notification_ids.len(),
@@ -883,10 +2493,22 @@ impl WispdUi {
 
     fn handle_output_hotplug(&mut self, event: OutputHotplugEvent) -> Task<Message> {
         match event {
-            OutputHotplugEvent::Added { name, description } => {
+            OutputHotplugEvent::Added {
+                name,
+                description,
+                size,
+            } => {
+                if !self.connected_outputs.contains(&name) {
+                    self.connected_outputs.push(name.clone());
+                }
+                match size {
+                    Some(size) => self.output_sizes.insert(name.clone(), size),
+                    None => self.output_sizes.remove(&name),
+                };
                 info!(
                     output = %name,
                     description = %description,
+                    ?size,
                     visible = self.windows.len(),
                     stack_policy = %self.stack_output_policy.as_ref().map(StackOutputPolicy::log_label).unwrap_or_else(|| "none".to_string()),
                     "wayland output added"
@@ -894,8 +2516,18 @@ impl WispdUi {
                 Task::none()
             }
             OutputHotplugEvent::Removed { name } => {
+                if let Some(removed) = name.as_deref() {
+                    self.connected_outputs.retain(|output| output != removed);
+                    self.output_sizes.remove(removed);
+                }
                 let should_rebuild = match (&self.stack_output_policy, name.as_deref()) {
-                    (None, _) => false,
+                    // Round-robin stacks carry no policy; rebuild when one loses its output.
+                    (None, Some(removed)) => self.windows.iter().any(|w| {
+                        self.window_outputs
+                            .get(&w.window_id)
+                            .is_some_and(|output| output == removed)
+                    }),
+                    (None, None) => !self.windows.is_empty(),
                     (Some(StackOutputPolicy::CompositorChosen), _) => !self.windows.is_empty(),
                     (Some(StackOutputPolicy::Named(current)), Some(removed)) => {
                         !self.windows.is_empty() && current == removed
@@ -933,40 +2565,253 @@ impl WispdUi {
         }
     }
 
-    fn relayout_task(&self) -> Task<Message> {
+    /// Moves every visible popup to its stack slot. With animations on, popups that change
+    /// slot get a tween and are carried there by `on_animation_frame`. Popups that no longer
+    /// fit on their output lose their window and wait in `queued`. Only margins and sizes
+    /// that differ from `sent_layout` are sent.
+    fn relayout_task(&mut self) -> Task<Message> {
+        self.relayouts += 1;
+        self.metrics.record_relayout();
         let anchor = layer_anchor_from_str(&self.ui.anchor);
-        let mut offset = 0_i32;
-
-        let updates = self.windows.iter().map(|binding| {
-            let popup_height = self.popup_height_for_id(binding.notification_id);
-            let mut margin = (
-                self.ui.margin.top,
-                self.ui.margin.right,
-                self.ui.margin.bottom,
-                self.ui.margin.left,
-            );
-
-            if anchor.contains(Anchor::Top) {
-                margin.0 += offset;
-            } else {
-                margin.2 += offset;
+        let now = Instant::now();
+        let duration = self.animation_duration();
+        self.warn_oversized_margins();
+
+        let heights = self.stack_heights();
+        let layout = self.stack_layout(anchor, &heights, None);
+
+        let mut slots = Vec::new();
+        let mut hidden = Vec::new();
+        for ((binding, margin), popup_height) in self.windows.iter().zip(layout).zip(heights) {
+            match margin {
+                Some(margin) => slots.push((*binding, margin, popup_height)),
+                None => hidden.push(*binding),
             }
-            offset += popup_height as i32 + self.ui.gap as i32;
+        }
+        let mut tasks = Vec::new();
+        if !hidden.is_empty() {
+            info!(
+                hidden = hidden.len(),
+                queued = self.queued.len() + hidden.len(),
+                "popups do not fit on their output; queued"
+            );
+        }
+        for binding in hidden.into_iter().rev() {
+            self.windows.retain(|w| w.window_id != binding.window_id);
+            self.queued.push_front(binding.notification_id);
+            tasks.push(self.retire_window(binding));
+        }
 
-            Task::batch([
-                Task::done(Message::MarginChange {
-                    id: binding.window_id,
-                    margin,
-                }),
-                Task::done(Message::AnchorSizeChange {
-                    id: binding.window_id,
+        let placements: Vec<_> = slots
+            .into_iter()
+            .map(|(binding, margin, popup_height)| {
+                let previous = self.placed_margins.insert(binding.window_id, margin);
+                if let Some(previous) = previous.filter(|p| !duration.is_zero() && *p != margin) {
+                    let from = self
+                        .margin_tweens
+                        .get(&binding.window_id)
+                        .map_or(previous, |tween| tween.at(now, duration));
+                    self.margin_tweens.insert(
+                        binding.window_id,
+                        MarginTween {
+                            from,
+                            to: margin,
+                            started: now,
+                        },
+                    );
+                }
+                let anchor = if self.position_hint(binding.notification_id).is_some() {
+                    layout::positioned_anchor()
+                } else {
+                    anchor
+                };
+                let placement = Placement {
+                    margin: self.frame_margin(&binding, now),
                     anchor,
                     size: (self.ui.width.max(1), popup_height.max(1)),
-                }),
-            ])
+                };
+                (binding.window_id, placement)
+            })
+            .collect();
+
+        let changes = self.sent_layout.advance(&placements);
+        self.layer_updates += changes.len() as u64;
+        tasks.extend(changes.into_iter().map(|change| {
+            Task::done(match change {
+                LayoutChange::Margin { window, margin } => {
+                    Message::MarginChange { id: window, margin }
+                }
+                LayoutChange::AnchorSize {
+                    window,
+                    anchor,
+                    size,
+                } => Message::AnchorSizeChange {
+                    id: window,
+                    anchor,
+                    size,
+                },
+            })
+        }));
+        Task::batch(tasks)
+    }
+
+    fn stack_heights(&self) -> Vec<u32> {
+        self.windows
+            .iter()
+            .map(|binding| self.popup_height_for_id(binding.notification_id))
+            .collect()
+    }
+
+    /// [`layout::compute_layout`] for the current stack with `heights` in `windows` order,
+    /// plus a popup of `extra` height at the back when given. An open history panel
+    /// takes the first slot on its output; its own slot is left out of the result.
+    /// Popups placed by their position hint keep their spot and take no slot.
+    fn stack_layout(
+        &self,
+        anchor: Anchor,
+        heights: &[u32],
+        extra: Option<u32>,
+    ) -> Vec<Option<Margin>> {
+        let positioned: Vec<Option<Margin>> = self
+            .windows
+            .iter()
+            .zip(heights)
+            .map(|(binding, &height)| self.positioned_margin(binding, height))
+            .collect();
+        let panel = self.history_window.map(|window| StackedPopup {
+            output: self.window_outputs.get(&window).map(String::as_str),
+            height: history_panel::PANEL_HEIGHT,
         });
+        let back_output = match self.windows.back() {
+            Some(binding) => self.window_outputs.get(&binding.window_id),
+            None => self
+                .history_window
+                .and_then(|window| self.window_outputs.get(&window)),
+        }
+        .map(String::as_str);
+        let popups: Vec<StackedPopup<'_>> = panel
+            .into_iter()
+            .chain(
+                self.windows
+                    .iter()
+                    .zip(heights)
+                    .zip(&positioned)
+                    .filter(|(_, positioned)| positioned.is_none())
+                    .map(|((binding, &height), _)| StackedPopup {
+                        output: self
+                            .window_outputs
+                            .get(&binding.window_id)
+                            .map(String::as_str),
+                        height,
+                    }),
+            )
+            .chain(extra.map(|height| StackedPopup {
+                output: back_output,
+                height,
+            }))
+            .collect();
+        let mut layout = layout::compute_layout(
+            anchor.contains(Anchor::Top),
+            self.base_margin(),
+            self.ui.gap,
+            &popups,
+            |output| self.output_height(output),
+        );
+        if panel.is_some() {
+            layout.remove(0);
+        }
+        let mut stacked = layout.into_iter();
+        let mut margins: Vec<Option<Margin>> = positioned
+            .into_iter()
+            .map(|margin| margin.or_else(|| stacked.next().flatten()))
+            .collect();
+        margins.extend(stacked);
+        margins
+    }
+
+    /// The `x`/`y` hint of notification `id` while `ui.honor_position_hints` is on.
+    fn position_hint(&self, id: u32) -> Option<(i32, i32)> {
+        if !self.ui.honor_position_hints {
+            return None;
+        }
+        self.notifications.get(&id)?.position
+    }
+
+    /// Where `binding`'s popup of `height` goes for its position hint, kept on its output.
+    fn positioned_margin(&self, binding: &WindowBinding, height: u32) -> Option<Margin> {
+        let point = self.position_hint(binding.notification_id)?;
+        let output = self
+            .window_outputs
+            .get(&binding.window_id)
+            .map(String::as_str);
+        Some(layout::positioned_margin(
+            point,
+            (self.ui.width.max(1), height.max(1)),
+            self.output_size(output),
+        ))
+    }
 
-        Task::batch(updates)
+    /// Size of a named output, or of the only known output for popups the compositor
+    /// placed.
+    fn output_size(&self, output: Option<&str>) -> Option<(u32, u32)> {
+        match output {
+            Some(name) => self.output_sizes.get(name).copied(),
+            None if self.output_sizes.len() == 1 => self.output_sizes.values().next().copied(),
+            None => None,
+        }
+    }
+
+    fn output_height(&self, output: Option<&str>) -> Option<u32> {
+        self.output_size(output).map(|size| size.1)
+    }
+
+    /// Warns once per output whose size the configured margins already exceed.
+    fn warn_oversized_margins(&mut self) {
+        let base = self.base_margin();
+        for (name, &size) in &self.output_sizes {
+            if layout::margins_exceed(base, self.ui.width, size)
+                && self.oversized_margin_warned.insert(name.clone())
+            {
+                warn!(
+                    output = %name,
+                    ?size,
+                    margin = ?base,
+                    width = self.ui.width,
+                    "ui.margin and ui.width exceed the output; popups may be misplaced"
+                );
+            }
+        }
+    }
+
+    /// Opens windows for queued popups, front first, while they fit behind the stack.
+    /// Nothing reopens while a critical takeover is showing.
+    fn reopen_queued(&mut self) -> Vec<Task<Message>> {
+        if self.takeovers.is_active() {
+            return Vec::new();
+        }
+        let anchor = layer_anchor_from_str(&self.ui.anchor);
+        let mut tasks = Vec::new();
+        while self.windows.len() < self.ui.max_visible
+            && let Some(&id) = self.queued.front()
+        {
+            if !self.notifications.contains_key(&id) || self.hides_popup(id) {
+                self.queued.pop_front();
+                continue;
+            }
+            let heights = self.stack_heights();
+            let fits = self
+                .stack_layout(anchor, &heights, Some(self.popup_height_for_id(id)))
+                .last()
+                .is_some_and(Option::is_some);
+            if !fits {
+                break;
+            }
+            self.queued.pop_front();
+            debug!(id, queued = self.queued.len(), "reopening queued popup");
+            let index = self.reopen_index_for(id);
+            tasks.push(self.open_window_at(id, index));
+        }
+        tasks
     }
 
     fn popup_height_for_id(&self, id: u32) -> u32 {
@@ -976,1843 +2821,7050 @@ impl WispdUi {
 
         self.notifications
             .get(&id)
-            .map(|n| estimate_popup_height(&self.ui, n))
+            .map(|n| estimate_popup_height(&self.ui, n, &mut self.text_metrics.borrow_mut()))
             .unwrap_or(self.ui.height.max(1))
     }
 
+    /// Drops the measurements of `n`'s text once it is no longer shown.
+    fn forget_text_metrics(&self, n: &UiNotification) {
+        let mut metrics = self.text_metrics.borrow_mut();
+        metrics.forget(&header_text(n));
+        metrics.forget(&n.body);
+    }
+
     fn timeout_progress_for(&self, id: u32) -> Option<f32> {
         let n = self.notifications.get(&id)?;
-        let timeout_ms = n.timeout_ms?;
-        let elapsed = n.created_at.elapsed().as_secs_f32() * 1000.0;
-        let progress = (elapsed / timeout_ms as f32).clamp(0.0, 1.0);
-        Some(progress)
+        let timeout = Duration::from_millis(u64::from(n.timeout_ms?));
+        let remaining = match n.expires_at {
+            Some(expires_at) => expires_at.saturating_duration_since(Instant::now()),
+            None => timeout.saturating_sub(n.created_at.elapsed()),
+        };
+        let progress = 1.0 - remaining.as_secs_f32() / timeout.as_secs_f32();
+        Some(progress.clamp(0.0, 1.0))
     }
 
-    fn dispatch_click_action(&self, id: u32, action: ClickAction) {
-        let cmd = match action {
-            ClickAction::Dismiss => SourceCommand::Dismiss { id },
-            ClickAction::InvokeDefaultAction => SourceCommand::InvokeAction {
-                id,
-                key: "default".to_string(),
-            },
+    /// Times the bar of `id` by the expiry the source reported with its event, which
+    /// accounts for the source's own default and clamp. Popups without a bar keep none.
+    fn adopt_source_expiry(&mut self, id: u32, remaining_ms: Option<u64>) {
+        let Some(remaining_ms) = remaining_ms else {
+            return;
+        };
+        let Some(n) = self
+            .notifications
+            .get_mut(&id)
+            .filter(|n| n.timeout_ms.is_some())
+        else {
+            return;
         };
+        n.timeout_ms = Some(u32::try_from(remaining_ms).unwrap_or(u32::MAX));
+        n.expires_at = Some(Instant::now() + Duration::from_millis(remaining_ms));
+    }
 
-        if let Err(err) = self.cmd_tx.send(cmd) {
-            warn!(?err, "failed to send click action command to source thread");
+    fn dispatch_click_action(&mut self, id: u32, action: ClickAction) -> Task<Message> {
+        match action {
+            ClickAction::Dismiss => self.dismiss(id),
+            ClickAction::InvokeDefaultAction => self.action_clicked(id, "default".to_string()),
+            ClickAction::MuteApp => self.mute_app(id),
+            ClickAction::CopyBody => self.copy_body(id),
+            ClickAction::Pin => self.toggle_pin(id),
         }
     }
 
-    fn reload_config(&mut self) -> Task<Message> {
-        info!("runtime config reload requested");
-        self.apply_loaded_config(load_config_checked())
+    /// Copies the popup's text (see `clipboard::clipboard_text`) and flashes a
+    /// confirmation on it.
+    fn copy_body(&mut self, id: u32) -> Task<Message> {
+        let Some(text) = self
+            .notifications
+            .get(&id)
+            .and_then(|n| clipboard::clipboard_text(&n.summary, &n.body))
+        else {
+            debug!(id, "nothing to copy from popup");
+            return Task::none();
+        };
+        self.copied_flash.insert(id, Instant::now());
+        clipboard::copy(self.ui.clipboard_command.as_deref(), text)
     }
 
-    fn apply_loaded_config(&mut self, result: Result<AppConfig>) -> Task<Message> {
-        match result {
-            Ok(cfg) => self.apply_config(cfg),
-            Err(err) => {
-                warn!(%err, "runtime config reload rejected");
-                self.emit_local_notification(
-                    "Config reload failed",
-                    format!("Keeping current configuration. {err}"),
+    /// Shows every action of a popup whose buttons `max_visible_actions` hid.
+    fn expand_actions(&mut self, id: u32) -> Task<Message> {
+        if !self.action_visibility(id).affordance {
+            return Task::none();
+        }
+        self.expanded_actions.insert(id);
+        debug!(id, "hidden actions expanded");
+        self.remeasure(id)
+    }
+
+    fn action_visibility(&self, id: u32) -> ActionVisibility {
+        let actions = self
+            .notifications
+            .get(&id)
+            .map_or(&[][..], |n| n.actions.as_slice());
+        action_visibility(
+            self.ui.buttons.max_visible_actions,
+            actions,
+            self.expanded_actions.contains(&id),
+            self.actions_enabled(id),
+        )
+    }
+
+    /// Pins or unpins a popup, mirroring the change at the source so both agree on
+    /// expiry. Unpinning restarts the default timeout, as the source does.
+    fn toggle_pin(&mut self, id: u32) -> Task<Message> {
+        if !self.is_interactive(id) {
+            return Task::none();
+        }
+        let local = self.is_local(id);
+        let Some(n) = self.notifications.get_mut(&id) else {
+            return Task::none();
+        };
+        let pinned = self.pinned.insert(id);
+        n.expires_at = None;
+        if pinned {
+            n.timeout_ms = None;
+        } else {
+            self.pinned.remove(&id);
+            n.timeout_ms = if self.ui.never_expires(&n.urgency) {
+                None
+            } else {
+                n.timeout_clamp.apply(
+                    effective_timeout_ms(-1, self.default_timeout_ms),
+                    n.urgency == Urgency::Critical,
                 )
-            }
+            };
+            n.created_at = Instant::now();
+        }
+        info!(id, pinned, "popup pin toggled");
+
+        // wispd's own popups are not in the source and never expire there.
+        if !local {
+            self.send_source_command(SourceCommand::SetPinned { id, pinned });
         }
+        // The timeout bar comes and goes with the pin.
+        self.measured_heights.remove(&id);
+        self.pending_measure.insert(id);
+        self.relayout_task()
     }
 
-    fn apply_config(&mut self, cfg: AppConfig) -> Task<Message> {
-        if let Err(err) = self.cmd_tx.send(SourceCommand::ReloadConfig {
-            capabilities: cfg.source.capabilities.clone(),
-            default_timeout_ms: cfg.source.default_timeout_ms,
-        }) {
-            warn!(?err, "failed to send source reload command");
+    fn is_pinned(&self, id: u32) -> bool {
+        self.pinned.contains(&id)
+    }
+
+    fn is_resident(&self, id: u32) -> bool {
+        self.notifications.get(&id).is_some_and(|n| n.resident)
+    }
+
+    /// Starts the escalation timer of a critical popup the daemon did not raise itself.
+    fn arm_escalation(&mut self, id: u32) {
+        if self.ui.escalation.enabled
+            && !self.is_local(id)
+            && self.urgency_of(id) == Urgency::Critical
+        {
+            self.escalations
+                .arm(id, Instant::now(), &self.ui.escalation);
         }
+    }
 
-        self.ui = cfg.ui;
-        self.default_timeout_ms = cfg.source.default_timeout_ms;
+    /// Escalates the critical popups left unacknowledged until `now`: replays their
+    /// sound; the flash and badge follow from `escalations`, the rest is up to the source.
+    fn escalate_due(&mut self, now: Instant) {
+        for (id, count) in self.escalations.fire_due(now, &self.ui.escalation) {
+            info!(id, count, "escalating unacknowledged critical notification");
+            self.play_sound(id);
+            self.send_source_command(SourceCommand::Escalate { id, count });
+        }
+    }
 
-        self.measured_heights.clear();
-        self.pending_measure
-            .extend(self.notifications.keys().copied());
+    /// Any interaction with a popup acknowledges it, ending its escalation.
+    fn acknowledge(&mut self, id: u32) {
+        if self.escalations.acknowledge(id) {
+            debug!(id, "critical notification acknowledged");
+        }
+    }
 
-        let mut tasks = Vec::new();
-        while self.windows.len() > self.ui.max_visible {
-            if let Some(evicted) = self.windows.pop_back() {
-                self.notifications.remove(&evicted.notification_id);
-                tasks.push(Task::done(Message::RemoveWindow(evicted.window_id)));
-            }
+    /// Colors of `n`'s popup at `now`, with the layers that set them.
+    fn resolve_style(&self, n: &UiNotification, now: Instant) -> ResolvedStyle {
+        style::resolve_colors(
+            &self.ui,
+            &StyleInputs {
+                urgency: n.urgency.clone(),
+                error_flash: self.is_error_flashing(n.id),
+                escalation_flash: self.escalations.is_flashing(n.id, now),
+                activating: self.activating.contains(&n.id),
+                measuring: self.pending_measure.contains(&n.id),
+            },
+        )
+    }
+
+    fn is_local(&self, id: u32) -> bool {
+        id > self.next_local_notification_id
+    }
+
+    fn is_copied_flashing(&self, id: u32) -> bool {
+        self.copied_flash
+            .get(&id)
+            .is_some_and(|started| started.elapsed() < COPIED_FLASH_DURATION)
+    }
+
+    fn is_muted(&self, app_name: &str) -> bool {
+        self.mutes.is_muted(app_name, SystemTime::now())
+    }
+
+    /// Mutes the popup's app for `ui.mute_duration_secs`: dismisses the clicked popup,
+    /// hides the app's other popups, and shows a confirmation with an undo action.
+    fn mute_app(&mut self, id: u32) -> Task<Message> {
+        if self.unmute_offers.contains_key(&id) {
+            return Task::none();
         }
+        let Some(app_name) = self.notifications.get(&id).map(|n| n.app_name.clone()) else {
+            return Task::none();
+        };
 
-        tasks.push(self.relayout_task());
+        self.mutes.mute(
+            &app_name,
+            Duration::from_secs(self.ui.mute_duration_secs),
+            SystemTime::now(),
+        );
+        info!(id, app = %app_name, duration_secs = self.ui.mute_duration_secs, "muted app");
+        self.send_source_command(SourceCommand::Dismiss { id });
+
+        let siblings: Vec<u32> = self
+            .notifications
+            .values()
+            .filter(|n| {
+                n.id != id && n.app_name == app_name && !self.unmute_offers.contains_key(&n.id)
+            })
+            .map(|n| n.id)
+            .collect();
+        let mut tasks: Vec<_> = siblings
+            .into_iter()
+            .map(|sibling| self.remove_notification(sibling))
+            .collect();
+        tasks.push(self.offer_unmute(app_name));
         Task::batch(tasks)
     }
 
-    fn next_local_notification_id(&mut self) -> u32 {
-        let id = self.next_local_notification_id;
-        self.next_local_notification_id = self.next_local_notification_id.saturating_sub(1);
-        id
+    /// Answers `ListMutes` and `Unmute`; an unmuted app's undo popup goes too.
+    fn answer_mute_request(&mut self, request: SignalRequest) -> Task<Message> {
+        match request {
+            SignalRequest::ListMutes { reply } => {
+                let _ = reply.send(self.mutes.list(SystemTime::now()));
+                Task::none()
+            }
+            SignalRequest::Unmute { app_name, reply } => {
+                let unmuted = self.mutes.unmute(&app_name);
+                let _ = reply.send(unmuted);
+                if !unmuted {
+                    return Task::none();
+                }
+                info!(app = %app_name, "unmuted app over D-Bus");
+                let offers: Vec<u32> = self
+                    .unmute_offers
+                    .iter()
+                    .filter(|(_, app)| **app == app_name)
+                    .map(|(id, _)| *id)
+                    .collect();
+                Task::batch(offers.into_iter().map(|id| self.remove_notification(id)))
+            }
+            _ => Task::none(),
+        }
     }
 
-    fn emit_local_notification(&mut self, summary: &str, body: String) -> Task<Message> {
+    fn offer_unmute(&mut self, app_name: String) -> Task<Message> {
         let id = self.next_local_notification_id();
+        let minutes = self.ui.mute_duration_secs.div_ceil(60);
+        self.unmute_offers.insert(id, app_name.clone());
         self.insert_new(
             id,
             Notification {
                 app_name: "wispd".to_string(),
                 app_icon: String::new(),
-                summary: summary.to_string(),
-                body,
-                urgency: Urgency::Critical,
+                summary: format!("Muted {app_name}"),
+                body: format!("Popups from {app_name} are hidden for {minutes} min."),
+                urgency: Urgency::Low,
                 timeout_ms: 5000,
-                actions: vec![],
+                actions: vec![NotificationAction {
+                    key: UNMUTE_ACTION_KEY.to_string(),
+                    label: "Undo".to_string(),
+                }],
                 hints: Default::default(),
             },
         )
     }
-}
-
-#[to_layer_message(multi)]
-#[derive(Debug, Clone)]
-enum Message {
-    Tick,
-    ActionClicked { id: u32, key: String },
-    DismissClicked { id: u32 },
-    NotificationLeftClick { id: u32 },
-    NotificationRightClick { id: u32 },
-    MeasuredPopupHeight { id: u32, height: Option<u32> },
-    WindowClosed(IcedId),
-    OutputHotplug(OutputHotplugEvent),
-}
-
-fn namespace() -> String {
-    String::from("wispd")
-}
 
-fn subscription(_: &WispdUi, wayland_connection: Connection) -> Subscription<Message> {
-    Subscription::batch([
-        iced::time::every(Duration::from_millis(33)).map(|_| Message::Tick),
-        iced::window::close_events().map(Message::WindowClosed),
-        listen_output_hotplug(wayland_connection).map(Message::OutputHotplug),
-    ])
-}
+    /// Invokes `key` on `id`, unless `[ui.confirm_actions]` matches it; then the popup
+    /// asks first and `Message::ConfirmAction` sends it.
+    fn action_clicked(&mut self, id: u32, key: String) -> Task<Message> {
+        let Some(n) = self.notifications.get(&id) else {
+            return self.invoke_action(id, key);
+        };
+        let label = n
+            .actions
+            .iter()
+            .find(|action| action.key == key)
+            .map_or(key.as_str(), |action| action.label.as_str());
+        if !self
+            .ui
+            .confirm_actions
+            .requires_confirmation(&n.app_name, &key, label)
+        {
+            return self.invoke_action(id, key);
+        }
+        let label = label.to_string();
+        debug!(id, key = %key, "action waiting for confirmation");
+        self.confirmations
+            .request(id, key, label, Instant::now(), &self.ui.confirm_actions);
+        self.remeasure(id)
+    }
 
-fn update(state: &mut WispdUi, message: Message) -> Task<Message> {
-    match message {
-        Message::Tick => state.on_tick(),
-        Message::WindowClosed(id) => state.handle_window_closed(id),
-        Message::OutputHotplug(event) => state.handle_output_hotplug(event),
-        Message::ActionClicked { id, key } => {
-            if let Err(err) = state.cmd_tx.send(SourceCommand::InvokeAction { id, key }) {
-                warn!(?err, "failed to send action command to source thread");
-            }
-            Task::none()
+    /// Enters keyboard mode on the front popup, which takes the keyboard until an action
+    /// is triggered, Escape is pressed, or the mode is switched off.
+    fn set_keyboard_mode(&mut self, enabled: bool) -> Task<Message> {
+        let target = enabled
+            .then(|| self.windows.front().map(|w| w.notification_id))
+            .flatten();
+        if target == self.keyboard_target {
+            return Task::none();
         }
-        Message::DismissClicked { id } => {
-            if let Err(err) = state.cmd_tx.send(SourceCommand::Dismiss { id }) {
-                warn!(?err, "failed to send dismiss command to source thread");
+        let mut tasks = Vec::new();
+        for (id, interactivity) in [
+            (self.keyboard_target, KeyboardInteractivity::None),
+            (target, KeyboardInteractivity::Exclusive),
+        ] {
+            let window = id.and_then(|id| self.windows.iter().find(|w| w.notification_id == id));
+            if let Some(window_id) = window.map(|w| w.window_id) {
+                tasks.push(Task::done(Message::KeyboardInteractivityChange {
+                    id: window_id,
+                    keyboard_interactivity: interactivity,
+                }));
             }
-            Task::none()
-        }
-        Message::NotificationLeftClick { id } => {
-            state.dispatch_click_action(id, state.ui.left_click_action);
-            Task::none()
         }
-        Message::NotificationRightClick { id } => {
-            state.dispatch_click_action(id, state.ui.right_click_action);
-            Task::none()
+        info!(enabled, target = ?target, "keyboard mode");
+        self.keyboard_target = target;
+        Task::batch(tasks)
+    }
+
+    /// Triggers the action `key` picks on the keyboard-mode popup, leaving the mode.
+    fn accelerator_pressed(&mut self, key: char) -> Task<Message> {
+        let Some(id) = self.keyboard_target else {
+            return Task::none();
+        };
+        let Some(action_key) = self.notifications.get(&id).and_then(|n| {
+            accelerators::action_for_key(&n.actions, key).map(|action| action.key.clone())
+        }) else {
+            return Task::none();
+        };
+        if !self.actions_enabled(id) {
+            return Task::none();
         }
-        Message::MeasuredPopupHeight { id, height } => {
-            let Some(height) = height else {
-                warn!(
-                    id,
-                    pending = state.pending_measure.contains(&id),
-                    visible = state.windows.len(),
-                    known = state.notifications.contains_key(&id),
-                    "popup height measurement returned no bounds"
-                );
-                return Task::none();
-            };
+        debug!(id, key = %key, action = %action_key, "action triggered from the keyboard");
+        self.acknowledge(id);
+        let leave = self.set_keyboard_mode(false);
+        Task::batch([leave, self.action_clicked(id, action_key)])
+    }
 
-            let snapped = height.max(state.ui.height.max(1));
-            let changed = state.measured_heights.get(&id).copied() != Some(snapped);
+    /// Opens the history panel in the middle of the output, or closes it. Its rows come
+    /// from the source with the reply to `ListHistory`.
+    fn toggle_history_panel(&mut self) -> Task<Message> {
+        if self.history_window.is_some() {
+            return self.close_history_panel();
+        }
+        self.open_history_panel(None)
+    }
 
-            debug!(
-                id,
-                measured_height = height,
-                snapped_height = snapped,
-                changed,
-                "popup height measurement resolved"
-            );
+    /// Opens the history panel listing `app`'s entries, or all with `None`. An open
+    /// panel takes the new filter in place. The panel takes the first slot of the stack
+    /// on its output, so the popups there move along to make room.
+    fn open_history_panel(&mut self, app: Option<String>) -> Task<Message> {
+        self.history_panel = app.map_or_else(HistoryPanel::default, HistoryPanel::for_app);
+        self.history_filter = HistoryFilter::default();
+        self.history_panel.touch(Instant::now());
+        if self.history_window.is_some() {
+            self.send_source_command(SourceCommand::ListHistory);
+            return Task::none();
+        }
+        let output_option = self.output_option_for_new_window();
+        let named_output = match &output_option {
+            OutputOption::OutputName(name) => Some(name.clone()),
+            _ => None,
+        };
+        let (window_id, open) = self.open_popup(NewLayerShellSettings {
+            size: Some((history_panel::PANEL_WIDTH, history_panel::PANEL_HEIGHT)),
+            layer: Layer::Overlay,
+            anchor: layer_anchor_from_str(&self.ui.anchor),
+            output_option,
+            keyboard_interactivity: KeyboardInteractivity::OnDemand,
+            exclusive_zone: Some(0),
+            margin: Some(self.base_margin()),
+            ..Default::default()
+        });
+        info!(?window_id, "history panel opened");
+        if let Some(name) = named_output {
+            self.window_outputs.insert(window_id, name);
+        }
+        self.history_window = Some(window_id);
+        self.send_source_command(SourceCommand::ListHistory);
+        Task::batch([open, self.relayout_unless_batched()])
+    }
 
-            state.measured_heights.insert(id, snapped);
-            state.pending_measure.remove(&id);
+    /// Closes the history panel; the popups below it move back up.
+    fn close_history_panel(&mut self) -> Task<Message> {
+        let Some(window_id) = self.history_window.take() else {
+            return Task::none();
+        };
+        info!(?window_id, "history panel closed");
+        self.history_filter = HistoryFilter::default();
+        self.forget_window(window_id);
+        Task::batch([
+            Task::done(Message::RemoveWindow(window_id)),
+            self.relayout_unless_batched(),
+        ])
+    }
 
-            if changed {
-                state.relayout_task()
-            } else {
-                Task::none()
-            }
+    /// Closes the history panel once `ui.history.auto_close_ms` has passed without the
+    /// user at it.
+    fn close_idle_history_panel(&mut self, now: Instant) -> Task<Message> {
+        let Some(timeout) = self.ui.history.auto_close() else {
+            return Task::none();
+        };
+        if self.history_window.is_none() || !self.history_panel.is_idle(now, timeout) {
+            return Task::none();
         }
-        _ => Task::none(),
+        debug!(?timeout, "history panel idle");
+        self.close_history_panel()
     }
-}
 
-fn app_style(_state: &WispdUi, theme: &iced::Theme) -> iced::theme::Style {
-    iced::theme::Style {
-        background_color: Color::TRANSPARENT,
-        text_color: theme.palette().text,
+    fn history_panel_message(&mut self, message: PanelMessage) -> Task<Message> {
+        if self.history_window.is_none() {
+            return Task::none();
+        }
+        if message.is_activity() {
+            self.history_panel.touch(Instant::now());
+        }
+        let message = match message {
+            // Space ticks a row until it can be part of a search.
+            PanelMessage::Filter(FilterEdit::Type(typed))
+                if self.history_filter.query.is_empty() && typed.trim().is_empty() =>
+            {
+                PanelMessage::Key(PanelKey::Toggle)
+            }
+            PanelMessage::Filter(edit) => {
+                self.history_filter.edit(edit);
+                self.history_panel
+                    .refilter(&self.history_filter, start_of_today());
+                return Task::none();
+            }
+            message => message,
+        };
+        let command = match self.history_panel.update(message) {
+            Some(PanelCommand::Show(id)) => SourceCommand::ShowHistoric { id },
+            Some(PanelCommand::InvokeDefault(id)) => {
+                self.send_source_command(SourceCommand::InvokeHistoricAction {
+                    id,
+                    key: "default".to_string(),
+                });
+                return self.close_history_panel();
+            }
+            Some(PanelCommand::Remove(ids)) => SourceCommand::RemoveHistory { ids },
+            Some(PanelCommand::Clear) => SourceCommand::ClearHistory,
+            Some(PanelCommand::SetStarred { id, starred }) => {
+                SourceCommand::SetHistoryStarred { id, starred }
+            }
+            Some(PanelCommand::Close) => return self.close_history_panel(),
+            None => return Task::none(),
+        };
+        self.send_source_command(command);
+        Task::none()
     }
-}
 
-fn popup_content_widget_id(id: u32) -> adv_widget::Id {
-    adv_widget::Id::from(format!("popup-content-{id}"))
-}
+    fn invoke_action(&mut self, id: u32, key: String) -> Task<Message> {
+        if let Some(task) = self.handle_local_action(id, &key) {
+            return task;
+        }
+        if self.run_routed_action(id, &key) == Some(false) {
+            // The sender never hears of it, so the popup goes as a click's would.
+            if self.is_resident(id) {
+                return Task::none();
+            }
+            return self.dismiss(id);
+        }
+        self.send_source_command(SourceCommand::InvokeAction { id, key });
+        Task::none()
+    }
 
-fn measure_notification_height_task(id: u32) -> Task<Message> {
-    iced::advanced::widget::operate(FindWidgetBounds::new(popup_content_widget_id(id))).map(
-        move |bounds| Message::MeasuredPopupHeight {
-            id,
-            height: bounds.map(|rect| rect.height.ceil() as u32),
-        },
-    )
-}
+    /// Runs the local command `[[ui.rules]]` maps `key` on `id` to. Returns whether the
+    /// action still goes to the sender (`also_forward`), or `None` when no rule routes it.
+    fn run_routed_action(&self, id: u32, key: &str) -> Option<bool> {
+        let n = self.notifications.get(&id)?;
+        let target = n.rule_target();
+        let route = rules::route_for(&self.ui.rules, &target, key)?;
+        info!(id, key, "action routed to a local command");
+        rules::run(route, id, key, &target);
+        Some(route.also_forward)
+    }
 
-struct FindWidgetBounds {
-    target: adv_widget::Id,
-    found: Option<Rectangle>,
-}
+    /// Answers `id`'s confirmation prompt, sending its action on `confirmed`.
+    fn answer_confirmation(&mut self, id: u32, confirmed: bool) -> Task<Message> {
+        let key = if confirmed {
+            self.confirmations.confirm(id, Instant::now())
+        } else {
+            self.confirmations.cancel(id);
+            None
+        };
+        let remeasure = self.remeasure(id);
+        match key {
+            Some(key) => Task::batch([remeasure, self.invoke_action(id, key)]),
+            None => remeasure,
+        }
+    }
 
-impl FindWidgetBounds {
-    fn new(target: adv_widget::Id) -> Self {
-        Self {
-            target,
-            found: None,
+    /// Drops `id`'s measured height after its content changed shape.
+    fn remeasure(&mut self, id: u32) -> Task<Message> {
+        if !self.notifications.contains_key(&id) {
+            return Task::none();
         }
+        self.measured_heights.remove(&id);
+        self.pending_measure.insert(id);
+        self.relayout_task()
     }
-}
 
-impl adv_widget::Operation<Option<Rectangle>> for FindWidgetBounds {
-    fn traverse(
-        &mut self,
-        operate: &mut dyn FnMut(&mut dyn adv_widget::Operation<Option<Rectangle>>),
-    ) {
-        operate(self);
+    /// Handles actions on wispd's own popups. Returns `None` for source-owned notifications.
+    fn handle_local_action(&mut self, id: u32, key: &str) -> Option<Task<Message>> {
+        if let Some(app_name) = self.floods.summary_app(id).map(str::to_string) {
+            let remove = self.remove_notification(id);
+            return Some(Task::batch([
+                remove,
+                self.open_history_panel(Some(app_name)),
+            ]));
+        }
+        if let Some(historic) = self.historic.get(&id).copied() {
+            if !historic.actions_enabled {
+                return Some(Task::none());
+            }
+            self.send_source_command(SourceCommand::InvokeHistoricAction {
+                id: historic.original_id,
+                key: key.to_string(),
+            });
+            return Some(self.remove_notification(id));
+        }
+        if key != UNMUTE_ACTION_KEY {
+            return None;
+        }
+        let app_name = self.unmute_offers.remove(&id)?;
+        if self.mutes.unmute(&app_name) {
+            info!(app = %app_name, "unmuted app");
+        }
+        Some(self.remove_notification(id))
     }
 
-    fn container(&mut self, id: Option<&adv_widget::Id>, bounds: Rectangle) {
-        if id.is_some_and(|id| id == &self.target) {
-            self.found = Some(bounds);
+    /// Under `--mirror` no source answers for wispd's own popups, so they go directly.
+    fn dismiss(&mut self, id: u32) -> Task<Message> {
+        if self.mirror.is_some() && self.is_local(id) {
+            return self.remove_notification(id);
         }
+        self.send_source_command(SourceCommand::Dismiss { id });
+        Task::none()
     }
 
-    fn finish(&self) -> adv_widget::operation::Outcome<Option<Rectangle>> {
-        adv_widget::operation::Outcome::Some(self.found)
-    }
-}
-
-fn view(state: &WispdUi, window_id: iced::window::Id) -> Element<'_, Message> {
-    let Some(binding) = state.windows.iter().find(|w| w.window_id == window_id) else {
-        return container(text(""))
-            .width(Length::Fixed(1.0))
-            .height(Length::Fixed(1.0))
-            .style(|_| {
-                iced::widget::container::Style::default()
-                    .background(Background::Color(Color::TRANSPARENT))
-            })
-            .into();
-    };
+    /// Sends a user-initiated command and optimistically marks its popup as activating.
+    /// A resident popup's actions stay clickable: it is kept whatever the result.
+    fn send_source_command(&mut self, cmd: SourceCommand) {
+        if self.mirror.is_some() {
+            match self.mirror_history.answer(&cmd) {
+                Some(entries) => self.show_history_entries(&entries),
+                None => debug!(?cmd, "mirror mode is read-only; command not sent"),
+            }
+            return;
+        }
+        let id = cmd.notification_id();
+        if let Some(id) = id
+            && self.notifications.contains_key(&id)
+            && !matches!(
+                cmd,
+                SourceCommand::Refresh { .. }
+                    | SourceCommand::SetPinned { .. }
+                    | SourceCommand::Escalate { .. }
+            )
+            && !(matches!(cmd, SourceCommand::InvokeAction { .. }) && self.is_resident(id))
+        {
+            self.activating.insert(id);
+        }
 
-    let Some(n) = state.notifications.get(&binding.notification_id) else {
-        return container(text(""))
-            .width(Length::Fixed(1.0))
-            .height(Length::Fixed(1.0))
-            .style(|_| {
-                iced::widget::container::Style::default()
-                    .background(Background::Color(Color::TRANSPARENT))
-            })
-            .into();
-    };
+        if let Err(err) = self.cmd_tx.send(cmd) {
+            warn!(?err, "failed to send command to source thread");
+            if let Some(id) = id {
+                self.activating.remove(&id);
+            }
+        }
+    }
 
-    let is_measuring = state.pending_measure.contains(&n.id);
-
-    let mut border_color = urgency_color(&state.ui.colors, n.urgency.clone());
-    let mut bg_color = parse_hex_color(&state.ui.colors.background)
-        .unwrap_or(Color::from_rgba(0.12, 0.12, 0.18, 0.8));
-    let mut text_color = parse_hex_color(&state.ui.colors.text).unwrap_or(Color::WHITE);
-    let mut progress_color =
-        parse_hex_color(&state.ui.colors.timeout_progress).unwrap_or(text_color);
-    let mut app_name_color = parse_hex_color(&state.ui.text.app_name.color).unwrap_or(text_color);
-    let mut summary_color = parse_hex_color(&state.ui.text.summary.color).unwrap_or(text_color);
-    let mut body_color = parse_hex_color(&state.ui.text.body.color).unwrap_or(text_color);
-
-    if is_measuring {
-        border_color = Color::TRANSPARENT;
-        bg_color = Color::TRANSPARENT;
-        text_color = Color::TRANSPARENT;
-        progress_color = Color::TRANSPARENT;
-        app_name_color = Color::TRANSPARENT;
-        summary_color = Color::TRANSPARENT;
-        body_color = Color::TRANSPARENT;
-    }
-
-    let card_width = state.ui.width as f32;
-    let card_height = state.popup_height_for_id(n.id) as f32;
-    let card_padding = state.ui.padding;
-
-    let app_name_size = state
-        .ui
-        .text
-        .app_name
-        .font_size
-        .unwrap_or(state.ui.font_size) as u32;
-    let summary_size = state
-        .ui
-        .text
-        .summary
-        .font_size
-        .unwrap_or(state.ui.font_size) as u32;
-    let body_size = state.ui.text.body.font_size.unwrap_or(state.ui.font_size) as u32;
-
-    let font = resolve_font(&state.ui.font_family);
-
-    let mut button_text_color =
-        parse_hex_color(&state.ui.buttons.text_color).unwrap_or(Color::from_rgb8(0xeb, 0xdb, 0xb2));
-    let mut button_bg_color =
-        parse_hex_color(&state.ui.buttons.background).unwrap_or(Color::from_rgb8(0x3c, 0x38, 0x36));
-    let mut button_border_color = parse_hex_color(&state.ui.buttons.border_color)
-        .unwrap_or(Color::from_rgb8(0x66, 0x5c, 0x54));
-    let mut button_hover_bg_color = parse_hex_color(&state.ui.buttons.hover_background)
-        .unwrap_or(Color::from_rgb8(0x50, 0x49, 0x45));
-    let mut button_hover_text_color = parse_hex_color(&state.ui.buttons.hover_text_color)
-        .unwrap_or(Color::from_rgb8(0xfb, 0xf1, 0xc7));
-
-    if is_measuring {
-        button_text_color = Color::TRANSPARENT;
-        button_bg_color = Color::TRANSPARENT;
-        button_border_color = Color::TRANSPARENT;
-        button_hover_bg_color = Color::TRANSPARENT;
-        button_hover_text_color = Color::TRANSPARENT;
-    }
-
-    let button_font = state
-        .ui
-        .buttons
-        .font_family
-        .as_deref()
-        .map(resolve_font)
-        .unwrap_or(font);
-    let button_font_size = state.ui.buttons.font_size.unwrap_or(state.ui.font_size) as u32;
-    let close_button_font_size = state.ui.buttons.close_font_size.unwrap_or(
-        state
-            .ui
-            .buttons
-            .font_size
-            .unwrap_or(state.ui.font_size.saturating_sub(2)),
-    ) as u32;
+    fn handle_source_reply(&mut self, reply: SourceReply) -> Task<Message> {
+        match reply {
+            SourceReply::Command { command, result } => {
+                let Some(id) = command.notification_id() else {
+                    return Task::none();
+                };
+                self.activating.remove(&id);
+
+                match result {
+                    // Applied locally already; the popup stays.
+                    Ok(()) if matches!(command, SourceCommand::SetPinned { .. }) => Task::none(),
+                    // The source keeps it; a replacement with the new content follows.
+                    Ok(())
+                        if matches!(command, SourceCommand::InvokeAction { .. })
+                            && self.is_resident(id) =>
+                    {
+                        Task::none()
+                    }
+                    Ok(()) => {
+                        debug!(
+                            id,
+                            ?command,
+                            "source command succeeded; removing popup locally"
+                        );
+                        self.remove_notification(id)
+                    }
+                    // Already closed at the source, which is what the dismiss wanted.
+                    Err(CommandError::NotFound)
+                        if matches!(command, SourceCommand::Dismiss { .. }) =>
+                    {
+                        debug!(id, "dismissed notification was already closed");
+                        self.remove_notification(id)
+                    }
+                    Err(CommandError::NotFound) => {
+                        info!(
+                            id,
+                            ?command,
+                            "source command target not found; refreshing popup"
+                        );
+                        self.error_flash.insert(id, Instant::now());
+                        self.send_source_command(SourceCommand::Refresh { id });
+                        Task::none()
+                    }
+                    // The popup may show actions from before a replacement.
+                    Err(CommandError::UnknownAction) => {
+                        warn!(
+                            id,
+                            ?command,
+                            "notification has no such action; refreshing popup"
+                        );
+                        self.error_flash.insert(id, Instant::now());
+                        self.send_source_command(SourceCommand::Refresh { id });
+                        Task::none()
+                    }
+                    Err(CommandError::Backend(err)) => {
+                        error!(id, ?command, %err, "notification backend failed");
+                        self.error_flash.insert(id, Instant::now());
+                        self.emit_local_notification(
+                            "Notification backend error",
+                            format!("The notification source could not apply a command. {err}"),
+                        )
+                    }
+                }
+            }
+            SourceReply::Snapshot { id, notification } => match notification {
+                Some(notification) => self.refresh_notification(id, *notification),
+                None => self.remove_notification(id),
+            },
+            SourceReply::Resync {
+                mut notifications,
+                do_not_disturb,
+            } => {
+                info!(
+                    count = notifications.len(),
+                    do_not_disturb, "resyncing notifications from the source"
+                );
+                self.do_not_disturb = do_not_disturb;
+                // After lost events the UI is not empty: popups the source no longer
+                // holds go, and the ones it still holds are refreshed in place.
+                let held: HashSet<u32> = notifications.iter().map(|(id, _)| *id).collect();
+                let gone: Vec<u32> = self
+                    .notifications
+                    .keys()
+                    .copied()
+                    .filter(|id| !self.is_local(*id) && !held.contains(id))
+                    .collect();
+                let mut tasks: Vec<_> = gone
+                    .into_iter()
+                    .map(|id| self.remove_notification(id))
+                    .collect();
+                // Oldest first, so the newest ends up at the front as it did live.
+                notifications.sort_by_key(|(id, _)| *id);
+                tasks.extend(notifications.into_iter().map(|(id, notification)| {
+                    if self.notifications.contains_key(&id) {
+                        self.refresh_notification(id, notification)
+                    } else {
+                        self.apply_event(NotificationEvent::Received {
+                            id,
+                            notification: Box::new(notification),
+                            silent: false,
+                            remaining_ms: None,
+                        })
+                    }
+                }));
+                Task::batch(tasks)
+            }
+            SourceReply::History { entries } => {
+                self.show_history_entries(&entries);
+                Task::none()
+            }
+            SourceReply::DebugSnapshot(snapshot) => {
+                self.write_debug_dump(Some(&snapshot));
+                Task::none()
+            }
+        }
+    }
 
-    let close_button = button(
-        text("✕")
-            .size(close_button_font_size)
-            .font(button_font)
-            .color(button_text_color),
-    )
-    .padding([1, 6])
-    .style(move |_, status| {
-        style_button(
-            status,
-            button_bg_color,
-            button_text_color,
-            button_border_color,
-            button_hover_bg_color,
-            button_hover_text_color,
-        )
-    })
-    .on_press(Message::DismissClicked { id: n.id });
+    /// Lists `entries` in the history panel, if it is open.
+    fn show_history_entries(&mut self, entries: &[HistoryEntry]) {
+        if self.history_window.is_some() {
+            self.history_panel
+                .set_entries(entries, &self.history_filter, start_of_today());
+        }
+    }
 
-    let mut text_block = column![].spacing(2);
+    fn refresh_notification(&mut self, id: u32, notification: Notification) -> Task<Message> {
+        let Some(existing) = self.notifications.get(&id) else {
+            return Task::none();
+        };
 
-    let mut top_line = row![].spacing(6);
-    if !n.app_name.trim().is_empty() {
-        top_line = top_line.push(
-            text(n.app_name.clone())
-                .size(app_name_size)
-                .font(font)
-                .color(app_name_color),
-        );
-    }
-    if !n.summary.trim().is_empty() {
-        top_line = top_line.push(
-            text(n.summary.clone())
-                .size(summary_size)
-                .font(font)
-                .color(summary_color),
-        );
-    }
-    if !n.app_name.trim().is_empty() || !n.summary.trim().is_empty() {
-        text_block = text_block.push(top_line);
+        let (created_at, expires_at) = (existing.created_at, existing.expires_at);
+        let mut refreshed = self.to_ui(id, notification);
+        refreshed.created_at = created_at;
+        refreshed.expires_at = expires_at;
+        self.notifications.insert(id, refreshed);
+        self.measured_heights.remove(&id);
+        self.pending_measure.insert(id);
+        Task::batch([self.load_icon(id), self.relayout_task()])
     }
 
-    if !n.body.trim().is_empty() {
-        text_block = text_block.push(
-            text(n.body.clone())
-                .size(body_size)
-                .font(font)
-                .color(body_color),
-        );
+    fn is_error_flashing(&self, id: u32) -> bool {
+        self.error_flash
+            .get(&id)
+            .is_some_and(|started| started.elapsed() < ERROR_FLASH_DURATION)
     }
 
-    let header = row![container(text_block).width(Length::Fill), close_button].spacing(8);
-
-    let mut card_content = column![header].spacing(8);
-
-    if !n.actions.is_empty() {
-        for action_chunk in n.actions.chunks(3) {
-            let mut actions_row = row![].spacing(8);
-            for action in action_chunk {
-                let btn_bg = button_bg_color;
-                let btn_fg = button_text_color;
-                let btn_border = button_border_color;
-                let btn_hover_bg = button_hover_bg_color;
-                let btn_hover_fg = button_hover_text_color;
-
-                actions_row = actions_row.push(
-                    button(
-                        text(action.label.clone())
-                            .font(button_font)
-                            .size(button_font_size)
-                            .color(btn_fg),
-                    )
-                    .padding([2, 8])
-                    .style(move |_, status| {
-                        style_button(
-                            status,
-                            btn_bg,
-                            btn_fg,
-                            btn_border,
-                            btn_hover_bg,
-                            btn_hover_fg,
-                        )
-                    })
-                    .on_press(Message::ActionClicked {
-                        id: n.id,
-                        key: action.key.clone(),
-                    }),
-                );
-            }
-            card_content = card_content.push(actions_row);
+    /// Asks the source for its stats and history tail; the dump is written when they
+    /// arrive. Under `--mirror` no source answers, so it is written at once without them.
+    fn request_debug_dump(&mut self, replies: Vec<Option<DumpReply>>) {
+        let asked = self.pending_dump.is_some();
+        self.pending_dump
+            .get_or_insert_default()
+            .extend(replies.into_iter().flatten());
+        if self.mirror.is_some() {
+            self.write_debug_dump(None);
+        } else if !asked && self.cmd_tx.send(SourceCommand::DebugSnapshot).is_err() {
+            warn!("source thread gone; writing the debug dump without its sections");
+            self.write_debug_dump(None);
         }
     }
 
-    let mut content_row = row![].spacing(10);
-    if let Some(path) = renderable_icon_path(state.ui.show_icons, &n.app_icon) {
-        let icon_size = state.ui.max_icon_size.max(1) as f32;
-        let icon = image(iced::widget::image::Handle::from_path(path))
-            .width(Length::Fixed(icon_size))
-            .height(Length::Fixed(icon_size))
-            .content_fit(ContentFit::Contain);
-        content_row = content_row.push(
-            container(icon)
-                .width(Length::Fixed(icon_size))
-                .height(Length::Fixed(icon_size)),
+    fn write_debug_dump(&mut self, source: Option<&dump::SourceSnapshot>) {
+        let waiting = self.pending_dump.take().unwrap_or_default();
+        let dump = self.debug_dump(
+            self.ui.debug_dump_redact_bodies,
+            loggable::log_content(),
+            source,
         );
+        let result = match dump::write_debug_dump(&state::dir(), &dump) {
+            Ok(path) => {
+                info!(path = %path.display(), "wrote debug dump");
+                println!("{}", path.display());
+                Ok(path.display().to_string())
+            }
+            Err(err) => {
+                warn!(%err, "failed to write debug dump");
+                Err(err.to_string())
+            }
+        };
+        for reply in waiting {
+            let _ = reply.send(result.clone());
+        }
     }
-    content_row = content_row.push(container(card_content).width(Length::Fill));
-
-    let body = container(content_row)
-        .padding(card_padding)
-        .width(Length::Fill)
-        .height(Length::Shrink)
-        .style(move |_| iced::widget::container::Style::default().color(text_color));
-
-    let timeout_progress = state
-        .timeout_progress_for(n.id)
-        .filter(|_| state.ui.show_timeout_progress);
 
-    let progress_height = state.ui.timeout_progress_height.max(1) as f32;
+    fn reload_config(&mut self) -> Task<Message> {
+        info!("runtime config reload requested");
+        self.apply_loaded_config(load_config_checked())
+    }
 
-    let card_stack = if let Some(progress) = timeout_progress {
-        let progress_track_width = (card_width - (card_padding as f32 * 2.0)).max(1.0);
-        let fill_width = (progress_track_width * progress).clamp(0.0, progress_track_width);
-        let empty_width = (progress_track_width - fill_width).max(0.0);
-        let fill = container(text(""))
-            .width(Length::Fixed(fill_width))
-            .height(Length::Fixed(progress_height))
-            .style(move |_| {
-                iced::widget::container::Style::default()
-                    .background(Background::Color(progress_color))
-            });
-        let empty = container(text(""))
-            .width(Length::Fixed(empty_width))
-            .height(Length::Fixed(progress_height))
-            .style(|_| {
-                iced::widget::container::Style::default()
-                    .background(Background::Color(Color::from_rgba(1.0, 1.0, 1.0, 0.08)))
-            });
-        let progress_bar = row![fill, empty]
-            .width(Length::Fixed(progress_track_width))
-            .spacing(0);
-        let progress_top_gap = (card_padding / 2).max(2) as f32;
-        let progress_bottom_gap = (card_padding / 2).max(2) as f32;
-        let progress_bar_inset = container(column![
-            container(text("")).height(Length::Fixed(progress_top_gap)),
-            progress_bar,
-            container(text("")).height(Length::Fixed(progress_bottom_gap))
-        ])
-        .width(Length::Fill)
-        .padding([0, card_padding]);
+    fn apply_loaded_config(&mut self, result: Result<AppConfig>) -> Task<Message> {
+        match result {
+            Ok(cfg) => self.apply_config(cfg),
+            Err(err) => {
+                warn!(%err, "runtime config reload rejected");
+                self.emit_local_notification(
+                    "Config reload failed",
+                    format!("Keeping current configuration. {err}"),
+                )
+            }
+        }
+    }
 
-        if state
-            .ui
-            .timeout_progress_position
-            .eq_ignore_ascii_case("top")
+    fn apply_config(&mut self, cfg: AppConfig) -> Task<Message> {
+        if self.mirror.is_none()
+            && let Err(err) = self.cmd_tx.send(SourceCommand::ReloadConfig {
+                features: cfg.source.features(&cfg.ui),
+                default_timeout_ms: cfg.source.default_timeout_ms,
+                clamp_timeout: cfg.ui.timeout_clamp(),
+                timeout_rules: cfg.ui.timeout_rules(),
+                dnd_schedule: cfg.ui.dnd.schedule.clone(),
+                history_ttl: cfg.ui.history_ttl.map(HumanDuration::get),
+                critical_never_expires: cfg.ui.critical_never_expires,
+            })
         {
-            column![progress_bar_inset, body]
-        } else {
-            column![body, progress_bar_inset]
+            warn!(?err, "failed to send source reload command");
         }
-    } else {
-        column![body]
-    };
-
-    let card = container(card_stack)
-        .id(popup_content_widget_id(n.id))
-        .width(Length::Fixed(card_width))
-        .height(Length::Fixed(card_height))
-        .style(move |_| {
-            iced::widget::container::Style::default()
-                .background(Background::Color(bg_color))
-                .border(border::width(2).color(border_color))
-        });
-
-    let content: Element<'_, Message> = if is_measuring {
-        card.into()
-    } else {
-        mouse_area(card)
-            .on_press(Message::NotificationLeftClick { id: n.id })
-            .on_right_press(Message::NotificationRightClick { id: n.id })
-            .into()
-    };
 
-    container(column![content])
-        .width(Length::Shrink)
-        .style(|_| {
-            iced::widget::container::Style::default()
-                .background(Background::Color(Color::TRANSPARENT))
-        })
-        .into()
-}
+        logging::reload_level(&cfg.log);
+        self.ui = cfg.ui;
+        self.sounds = SoundResolver::for_session(&self.ui.sound.theme);
+        self.check_glyphs();
+        self.check_fonts();
+        if !self.ui.escalation.enabled {
+            self.escalations.clear();
+        }
+        self.default_timeout_ms = cfg.source.default_timeout_ms;
 
-fn to_ui_notification(
-    id: u32,
-    notification: Notification,
-    default_timeout_ms: Option<i32>,
-) -> UiNotification {
-    let timeout_ms = effective_timeout_ms(notification.timeout_ms, default_timeout_ms);
+        self.measured_heights.clear();
+        self.text_metrics.borrow_mut().clear();
+        let shown: Vec<u32> = self
+            .notifications
+            .keys()
+            .copied()
+            .filter(|&id| !self.hides_popup(id))
+            .collect();
+        self.pending_measure.extend(shown);
+        self.sort_windows();
 
-    UiNotification {
-        id,
-        app_name: notification.app_name,
-        app_icon: notification.app_icon,
-        summary: notification.summary,
-        body: notification.body,
-        urgency: notification.urgency,
-        actions: notification
-            .actions
-            .into_iter()
-            .filter_map(to_ui_action)
-            .collect(),
-        timeout_ms,
-        created_at: Instant::now(),
+        let mut tasks = self.evict_overflow();
+        tasks.push(self.relayout_task());
+        Task::batch(tasks)
     }
-}
 
-fn to_ui_action(action: NotificationAction) -> Option<UiAction> {
-    if action.label.trim().is_empty() {
-        return None;
+    /// Picks the built-in button faces the button font can draw.
+    fn check_glyphs(&mut self) {
+        let buttons = &self.ui.buttons;
+        let font_family = buttons
+            .font_family
+            .as_deref()
+            .unwrap_or(&self.ui.font_family);
+        self.glyphs = Glyphs::resolve(
+            buttons,
+            font_family,
+            close_font_size(&self.ui),
+            &ShapedCoverage,
+        );
     }
 
-    Some(UiAction {
-        key: action.key,
-        label: action.label,
-    })
-}
+    /// Resolves the configured fonts, warning when the missing families changed; the
+    /// estimator takes the fallback's metrics.
+    fn check_fonts(&mut self) {
+        let report = FontReport::check(&self.ui, &SystemFonts);
+        if self.fonts.as_ref().map(|last| &last.missing) != Some(&report.missing) {
+            report.warn_missing();
+        }
+        self.text_metrics
+            .borrow_mut()
+            .set_char_width_em(report.char_width_em);
+        self.fonts = Some(report);
+    }
 
-#[cfg(test)]
-fn render_format(format: &str, n: &UiNotification) -> String {
-    format
-        .replace("{id}", &n.id.to_string())
-        .replace("{app_name}", &n.app_name)
-        .replace("{summary}", &n.summary)
-        .replace("{body}", &n.body)
-        .replace("{urgency}", urgency_label(n.urgency.clone()))
-}
+    fn next_local_notification_id(&mut self) -> u32 {
+        let id = self.next_local_notification_id;
+        self.next_local_notification_id = self.next_local_notification_id.saturating_sub(1);
+        id
+    }
 
-fn resolve_icon_path(raw: &str) -> Option<PathBuf> {
-    let trimmed = raw.trim();
-    if trimmed.is_empty() {
-        return None;
+    fn emit_local_notification(&mut self, summary: &str, body: String) -> Task<Message> {
+        let id = self.next_local_notification_id();
+        self.insert_new(
+            id,
+            Notification {
+                app_name: "wispd".to_string(),
+                app_icon: String::new(),
+                summary: summary.to_string(),
+                body,
+                urgency: Urgency::Critical,
+                timeout_ms: 5000,
+                actions: vec![],
+                hints: Default::default(),
+            },
+        )
     }
 
-    if let Some(path) = trimmed.strip_prefix("file://") {
-        return Some(PathBuf::from(path));
+    /// Opens a local popup for closed notification `original_id`. It never reaches the
+    /// source, so it is not recorded in the history again.
+    fn show_historic(
+        &mut self,
+        original_id: u32,
+        mut notification: Notification,
+        actions_enabled: bool,
+        chain: &ReplaceChain,
+    ) -> Task<Message> {
+        let id = self.next_local_notification_id();
+        info!(
+            id,
+            original_id,
+            actions_enabled,
+            replace_count = chain.replace_count,
+            "showing notification from history"
+        );
+        if let Some(updates) = chain.describe() {
+            notification.summary = format!("{} ({updates})", notification.summary);
+        }
+        notification.timeout_ms = i32::try_from(self.ui.history_timeout_ms).unwrap_or(i32::MAX);
+        self.historic.insert(
+            id,
+            HistoricPopup {
+                original_id,
+                actions_enabled,
+            },
+        );
+        self.insert_new(id, notification)
     }
 
-    Some(PathBuf::from(trimmed))
-}
+    /// Whether the popup's action buttons can do anything.
+    fn actions_enabled(&self, id: u32) -> bool {
+        self.is_interactive(id)
+            && self
+                .historic
+                .get(&id)
+                .is_none_or(|historic| historic.actions_enabled)
+    }
 
-fn renderable_icon_path(show_icons: bool, app_icon: &str) -> Option<PathBuf> {
-    if !show_icons {
-        return None;
+    /// History popups past their own timeout; the source does not expire them.
+    fn expired_historic(&self, now: Instant) -> Vec<u32> {
+        self.historic
+            .keys()
+            .copied()
+            .filter(|id| self.timed_out(*id, now))
+            .collect()
     }
 
-    let path = resolve_icon_path(app_icon)?;
-    if !path.is_file() {
-        return None;
+    /// Flood summaries past their timeout; like history popups they are local.
+    fn expired_flood_summaries(&self, now: Instant) -> Vec<u32> {
+        self.floods
+            .summary_ids()
+            .filter(|id| self.timed_out(*id, now))
+            .collect()
     }
 
-    Some(path)
-}
-
-fn icon_height_px(ui: &UiSection, app_icon: &str) -> u32 {
-    if renderable_icon_path(ui.show_icons, app_icon).is_some() {
-        ui.max_icon_size.max(1) as u32
-    } else {
-        0
+    fn timed_out(&self, id: u32, now: Instant) -> bool {
+        self.notifications.get(&id).is_some_and(|n| {
+            n.timeout_ms.is_some_and(|ms| {
+                now.saturating_duration_since(n.created_at) >= Duration::from_millis(u64::from(ms))
+            })
+        })
     }
 }
 
-fn style_button(
-    status: ButtonStatus,
-    background: Color,
-    text: Color,
-    border_color: Color,
-    hover_background: Color,
-    hover_text: Color,
-) -> iced::widget::button::Style {
-    let (bg, fg) = match status {
-        ButtonStatus::Hovered | ButtonStatus::Pressed => (hover_background, hover_text),
-        _ => (background, text),
-    };
-
-    iced::widget::button::Style {
-        background: Some(Background::Color(bg)),
-        text_color: fg,
-        border: border::width(1).color(border_color),
-        ..Default::default()
-    }
+/// A closed notification shown again; see [`WispdUi::show_historic`].
+#[derive(Debug, Clone, Copy)]
+struct HistoricPopup {
+    original_id: u32,
+    /// The original sender is still connected to receive `ActionInvoked`.
+    actions_enabled: bool,
 }
 
-fn effective_timeout_ms(requested_timeout_ms: i32, default_timeout_ms: Option<i32>) -> Option<u32> {
-    let effective = match requested_timeout_ms {
-        0 => return None,
-        x if x < 0 => default_timeout_ms?,
-        x => x,
-    };
-
-    u32::try_from(effective).ok().filter(|value| *value > 0)
+#[to_layer_message(multi)]
+#[derive(Debug, Clone)]
+enum Message {
+    Tick,
+    AnimationFrame,
+    ActionClicked {
+        id: u32,
+        key: String,
+    },
+    ConfirmAction {
+        id: u32,
+    },
+    CancelConfirm {
+        id: u32,
+    },
+    DismissClicked {
+        id: u32,
+    },
+    CopyClicked {
+        id: u32,
+    },
+    /// The header affordance of a popup whose action buttons are hidden.
+    ExpandActionsClicked {
+        id: u32,
+    },
+    /// An icon decoded for popup `id`; other popups showing the same file use it too.
+    ImageReady {
+        id: u32,
+        path: PathBuf,
+        handle: Result<Handle, String>,
+    },
+    /// A key pressed in keyboard mode.
+    AcceleratorPressed(char),
+    KeyboardModeEnded,
+    /// Input for the history panel from its buttons.
+    HistoryPanel(PanelMessage),
+    /// Input for the history panel from a window's events; dropped unless `window` is
+    /// the panel.
+    HistoryPanelEvent {
+        window: IcedId,
+        message: PanelMessage,
+    },
+    UrlClicked {
+        id: u32,
+    },
+    PinClicked {
+        id: u32,
+    },
+    PopupHovered {
+        id: u32,
+        hovered: bool,
+    },
+    NotificationLeftClick {
+        id: u32,
+    },
+    NotificationRightClick {
+        id: u32,
+    },
+    MeasuredPopupHeight {
+        id: u32,
+        height: Option<u32>,
+    },
+    WindowClosed(IcedId),
+    OutputHotplug(OutputHotplugEvent),
 }
 
-fn estimate_popup_height(ui: &UiSection, n: &UiNotification) -> u32 {
-    let app_name_size = ui.text.app_name.font_size.unwrap_or(ui.font_size) as f32;
-    let summary_size = ui.text.summary.font_size.unwrap_or(ui.font_size) as f32;
-    let body_size = ui.text.body.font_size.unwrap_or(ui.font_size) as f32;
-
-    let icon_height = icon_height_px(ui, &n.app_icon);
-    let icon_width = if icon_height > 0 {
-        ui.max_icon_size.max(1) as f32 + 10.0 // icon + row spacing
-    } else {
-        0.0
-    };
-
-    let close_button_font_size = ui.buttons.close_font_size.unwrap_or(
-        ui.buttons
-            .font_size
-            .unwrap_or(ui.font_size.saturating_sub(2)),
-    ) as f32;
-    let close_button_width = (close_button_font_size * 0.8) + 14.0; // glyph + horizontal padding/border
-
-    let content_width_px = (ui.width as f32 - (ui.padding as f32 * 2.0) - icon_width).max(80.0);
-    let text_width_px = (content_width_px - close_button_width - 8.0).max(40.0);
+fn namespace() -> String {
+    String::from("wispd")
+}
 
-    let header_text = match (n.app_name.trim().is_empty(), n.summary.trim().is_empty()) {
-        (false, false) => format!("{} {}", n.app_name, n.summary),
-        (false, true) => n.app_name.clone(),
-        (true, false) => n.summary.clone(),
-        (true, true) => String::new(),
-    };
+/// Output hotplug is only watched with a Wayland connection.
+fn subscription(state: &WispdUi, wayland_connection: Option<Connection>) -> Subscription<Message> {
+    let mut subscriptions = vec![
+        iced::time::every(Duration::from_millis(33)).map(|_| Message::Tick),
+        iced::window::close_events().map(Message::WindowClosed),
+    ];
+    if let Some(connection) = wayland_connection {
+        subscriptions.push(listen_output_hotplug(connection).map(Message::OutputHotplug));
+    }
+    if state.keyboard_target.is_some() {
+        subscriptions.push(iced::event::listen_with(keyboard_message));
+    }
+    if state.history_window.is_some() {
+        subscriptions.push(iced::event::listen_with(history_panel_event));
+    }
+    // Frames are only requested while something is moving.
+    if state.animations_active() {
+        subscriptions
+            .push(iced::time::every(animation::FRAME_INTERVAL).map(|_| Message::AnimationFrame));
+    }
+    Subscription::batch(subscriptions)
+}
 
-    let header_font_size = app_name_size.max(summary_size).max(1.0);
-    let header_char_width = (header_font_size * 0.54).max(1.0);
-    let header_chars_per_line = (text_width_px / header_char_width).floor().max(1.0) as usize;
-    let header_wrapped_lines = if header_text.is_empty() {
-        0
-    } else {
-        wrapped_line_count(&header_text, header_chars_per_line)
+/// An action button's label; in keyboard mode its mnemonic is underlined, or its
+/// accelerator shown in front of it.
+fn action_label<'a>(
+    label: String,
+    accelerator: Option<Accelerator>,
+    font: Font,
+    size: u32,
+    color: Color,
+) -> Element<'a, Message> {
+    let mnemonic = match accelerator {
+        Some(Accelerator::Mnemonic(key)) => accelerators::mnemonic_span(&label, key),
+        _ => None,
     };
-    let header_line_height = (header_font_size * 1.30).ceil() as u32;
-    let header_height = header_wrapped_lines as u32 * header_line_height;
-
-    let body_char_width = (body_size * 0.54).max(1.0);
-    let body_chars_per_line = (text_width_px / body_char_width).floor().max(1.0) as usize;
-    let body_wrapped_lines = if n.body.trim().is_empty() {
-        0
-    } else {
-        n.body
-            .lines()
-            .map(|line| wrapped_line_count(line, body_chars_per_line))
-            .sum::<usize>()
-            .max(1)
+    if let Some((start, end)) = mnemonic {
+        let spans: [text::Span<'a, (), Font>; 3] = [
+            span(label[..start].to_string()),
+            span(label[start..end].to_string()).underline(true),
+            span(label[end..].to_string()),
+        ];
+        return rich_text(spans).font(font).size(size).color(color).into();
+    }
+    let label = text(label)
+        .font(font)
+        .size(size)
+        .color(color)
+        .wrapping(text::Wrapping::None);
+    let hint = match accelerator {
+        Some(Accelerator::Mnemonic(key)) => key.to_uppercase().to_string(),
+        Some(Accelerator::Number(position)) => position.to_string(),
+        None => return label.into(),
     };
-    let body_line_height = (body_size * 1.30).ceil() as u32;
-    let body_height = body_wrapped_lines as u32 * body_line_height;
+    row![
+        text(hint)
+            .font(font)
+            .size((size * 3 / 4).max(1))
+            .color(color),
+        label
+    ]
+    .spacing(3)
+    .into()
+}
 
-    let text_internal_spacing = if header_height > 0 && body_height > 0 {
-        2
-    } else {
-        0
+/// Keys that matter in keyboard mode: Escape leaves it, characters pick actions.
+fn keyboard_message(
+    event: iced::Event,
+    _status: iced::event::Status,
+    _window: IcedId,
+) -> Option<Message> {
+    let iced::Event::Keyboard(keyboard::Event::KeyPressed { key, .. }) = event else {
+        return None;
     };
-    let text_height = header_height
-        .saturating_add(body_height)
-        .saturating_add(text_internal_spacing);
-    let close_button_height = (close_button_font_size * 1.30).ceil() as u32 + 4;
-    let content_height = text_height.max(close_button_height).max(icon_height);
+    match key.as_ref() {
+        keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::KeyboardModeEnded),
+        keyboard::Key::Character(typed) => typed.chars().next().map(Message::AcceleratorPressed),
+        _ => None,
+    }
+}
 
-    let actions_rows = n.actions.len().div_ceil(3) as u32;
-    // Button widget chrome/padding can exceed raw text line-height.
-    let action_row_height = (ui.font_size as f32 * 2.0).ceil() as u32;
-    let actions_height = if actions_rows == 0 {
-        0
-    } else {
-        let row_gaps = 8 * actions_rows; // header->row1 plus gaps between action rows
-        actions_rows * action_row_height + row_gaps + 2
-    };
+/// Local midnight, where the history panel's "today" chip starts.
+fn start_of_today() -> SystemTime {
+    let now = chrono::Local::now();
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+        .map_or_else(|| now.into(), SystemTime::from)
+}
 
-    let progress_height = if ui.show_timeout_progress && n.timeout_ms.is_some() {
-        ui.timeout_progress_height.max(1) as u32
-    } else {
-        0
-    };
-    let (progress_top_inset, progress_bottom_inset) = if progress_height > 0 {
-        let gap = (ui.padding / 2).max(2) as u32;
-        (gap, gap)
-    } else {
-        (0, 0)
+/// Keys, typing, focus changes, scrolling and pointer movement of every window, for the
+/// history panel.
+fn history_panel_event(
+    event: iced::Event,
+    status: iced::event::Status,
+    window: IcedId,
+) -> Option<Message> {
+    let message = match event {
+        // The search field handles its own typing.
+        iced::Event::Keyboard(_) if status == iced::event::Status::Captured => return None,
+        iced::Event::Keyboard(keyboard::Event::KeyPressed {
+            key: keyboard::Key::Named(keyboard::key::Named::Backspace),
+            ..
+        }) => PanelMessage::Filter(FilterEdit::Erase),
+        iced::Event::Keyboard(keyboard::Event::KeyPressed {
+            key,
+            text,
+            modifiers,
+            ..
+        }) => match PanelKey::from_key(key.as_ref(), modifiers) {
+            Some(key) => PanelMessage::Key(key),
+            None => {
+                let text = text.filter(|text| {
+                    !(modifiers.control() || modifiers.alt() || modifiers.logo())
+                        && !text.chars().any(char::is_control)
+                })?;
+                PanelMessage::Filter(FilterEdit::Type(text.to_string()))
+            }
+        },
+        iced::Event::Window(iced::window::Event::Focused) => PanelMessage::Focused(true),
+        iced::Event::Window(iced::window::Event::Unfocused) => PanelMessage::Focused(false),
+        iced::Event::Mouse(
+            iced::mouse::Event::CursorEntered
+            | iced::mouse::Event::CursorMoved { .. }
+            | iced::mouse::Event::WheelScrolled { .. },
+        ) => PanelMessage::Pointer,
+        _ => return None,
     };
-
-    let chrome =
-        ui.padding as u32 * 2 + progress_height + progress_top_inset + progress_bottom_inset + 2;
-
-    content_height
-        .saturating_add(actions_height)
-        .saturating_add(chrome)
-        .max(ui.height.max(1))
+    Some(Message::HistoryPanelEvent { window, message })
 }
 
-fn wrapped_line_count(line: &str, max_chars: usize) -> usize {
-    if line.is_empty() {
-        return 1;
+fn update(state: &mut WispdUi, message: Message) -> Task<Message> {
+    // Layer-shell messages only get here without layer-shell.
+    #[cfg(feature = "x11")]
+    if let Some(presenter) = state.x11.as_mut()
+        && let Some(task) = presenter.apply(&message)
+    {
+        return task;
     }
 
-    let mut lines = 1usize;
-    let mut current = 0usize;
-
-    for word in line.split_whitespace() {
-        let word_len = word.chars().count();
-
-        if current == 0 {
-            if word_len <= max_chars {
-                current = word_len;
-            } else {
-                lines += word_len.div_ceil(max_chars).saturating_sub(1);
-                current = word_len % max_chars;
+    match message {
+        Message::Tick => state.on_tick(),
+        Message::AnimationFrame => {
+            state.metrics.record_frame_wakeup();
+            state.on_animation_frame(Instant::now())
+        }
+        Message::WindowClosed(id) => state.handle_window_closed(id),
+        Message::OutputHotplug(event) => state.handle_output_hotplug(event),
+        Message::ActionClicked { id, key } => {
+            state.acknowledge(id);
+            state.action_clicked(id, key)
+        }
+        Message::ConfirmAction { id } => state.answer_confirmation(id, true),
+        Message::CancelConfirm { id } => state.answer_confirmation(id, false),
+        Message::DismissClicked { id } => state.dismiss(id),
+        Message::CopyClicked { id } => {
+            state.acknowledge(id);
+            state.copy_body(id)
+        }
+        Message::ExpandActionsClicked { id } => {
+            state.acknowledge(id);
+            state.expand_actions(id)
+        }
+        Message::ImageReady { id, path, handle } => state.image_ready(id, path, handle),
+        Message::AcceleratorPressed(key) => state.accelerator_pressed(key),
+        Message::KeyboardModeEnded => state.set_keyboard_mode(false),
+        Message::HistoryPanel(message) => state.history_panel_message(message),
+        Message::HistoryPanelEvent { window, message } => {
+            if state.history_window != Some(window) {
+                return Task::none();
             }
-            continue;
+            state.history_panel_message(message)
         }
-
-        let needed = 1 + word_len;
-        if current + needed <= max_chars {
-            current += needed;
-        } else {
-            lines += 1;
-            if word_len <= max_chars {
-                current = word_len;
-            } else {
-                lines += word_len.div_ceil(max_chars).saturating_sub(1);
-                current = word_len % max_chars;
+        Message::UrlClicked { id } => {
+            state.acknowledge(id);
+            if let Some(url) = state.notifications.get(&id).and_then(|n| n.url.as_deref()) {
+                kde::open_url(url);
             }
+            Task::none()
         }
-    }
+        Message::PinClicked { id } => {
+            state.acknowledge(id);
+            state.toggle_pin(id)
+        }
+        Message::PopupHovered { id, hovered } => {
+            if hovered {
+                if state.ui.escalation.hover_acknowledges {
+                    state.acknowledge(id);
+                }
+                state.hovered = Some(id);
+            } else if state.hovered == Some(id) {
+                state.hovered = None;
+            }
+            Task::none()
+        }
+        Message::NotificationLeftClick { id } => {
+            state.acknowledge(id);
+            state.dispatch_click_action(id, state.ui.left_click_action)
+        }
+        Message::NotificationRightClick { id } => {
+            state.acknowledge(id);
+            state.dispatch_click_action(id, state.ui.right_click_action)
+        }
+        Message::MeasuredPopupHeight { id, height } => {
+            let Some(height) = height else {
+                warn!(
+                    id,
+                    pending = state.pending_measure.contains(&id),
+                    visible = state.windows.len(),
+                    known = state.notifications.contains_key(&id),
+                    "popup height measurement returned no bounds"
+                );
+                return Task::none();
+            };
 
-    lines
-}
+            let snapped = height.max(state.ui.height.max(1));
+            let changed = state.measured_heights.get(&id).copied() != Some(snapped);
 
-fn resolve_font(raw: &str) -> Font {
-    let trimmed = raw.trim();
+            debug!(
+                id,
+                measured_height = height,
+                snapped_height = snapped,
+                changed,
+                "popup height measurement resolved"
+            );
 
-    match trimmed.to_ascii_lowercase().as_str() {
-        "sans" | "sans-serif" => Font::DEFAULT,
-        "serif" => Font {
-            family: iced::font::Family::Serif,
-            ..Font::DEFAULT
-        },
-        "monospace" | "mono" => Font::MONOSPACE,
-        "cursive" => Font {
-            family: iced::font::Family::Cursive,
-            ..Font::DEFAULT
-        },
-        "fantasy" => Font {
-            family: iced::font::Family::Fantasy,
-            ..Font::DEFAULT
-        },
-        _ => {
-            let leaked: &'static str = Box::leak(trimmed.to_string().into_boxed_str());
-            Font::with_name(leaked)
+            state.measured_heights.insert(id, snapped);
+            state.pending_measure.remove(&id);
+
+            if changed {
+                state.relayout_task()
+            } else {
+                Task::none()
+            }
         }
+        _ => Task::none(),
     }
 }
 
-#[cfg(test)]
-fn urgency_label(urgency: Urgency) -> &'static str {
-    match urgency {
-        Urgency::Low => "low",
-        Urgency::Normal => "normal",
-        Urgency::Critical => "critical",
+fn app_style(_state: &WispdUi, theme: &iced::Theme) -> iced::theme::Style {
+    iced::theme::Style {
+        background_color: Color::TRANSPARENT,
+        text_color: theme.palette().text,
     }
 }
 
-fn urgency_color(colors: &UrgencyColors, urgency: Urgency) -> Color {
-    let fallback = match urgency {
-        Urgency::Low => Color::from_rgb(0.42, 0.66, 1.0),
-        Urgency::Normal => Color::from_rgb(0.49, 0.81, 0.49),
-        Urgency::Critical => Color::from_rgb(1.0, 0.42, 0.42),
-    };
+fn popup_content_widget_id(id: u32) -> adv_widget::Id {
+    adv_widget::Id::from(format!("popup-content-{id}"))
+}
 
-    let selected = match urgency {
-        Urgency::Low => &colors.low,
-        Urgency::Normal => &colors.normal,
-        Urgency::Critical => &colors.critical,
-    };
+fn measure_notification_height_task(id: u32) -> Task<Message> {
+    iced::advanced::widget::operate(FindWidgetBounds::new(popup_content_widget_id(id))).map(
+        move |bounds| Message::MeasuredPopupHeight {
+            id,
+            height: bounds.map(|rect| rect.height.ceil() as u32),
+        },
+    )
+}
 
-    parse_hex_color(selected).unwrap_or(fallback)
+struct FindWidgetBounds {
+    target: adv_widget::Id,
+    found: Option<Rectangle>,
 }
 
-fn parse_hex_color(raw: &str) -> Option<Color> {
-    let hex = raw.trim().trim_start_matches('#');
-    match hex.len() {
-        6 => {
-            let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-            let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-            let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-            Some(Color::from_rgb8(r, g, b))
-        }
-        8 => {
-            let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-            let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-            let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-            let a = u8::from_str_radix(&hex[6..8], 16).ok()?;
-            Some(Color::from_rgba8(r, g, b, a as f32 / 255.0))
+impl FindWidgetBounds {
+    fn new(target: adv_widget::Id) -> Self {
+        Self {
+            target,
+            found: None,
         }
-        _ => None,
     }
 }
 
-fn layer_anchor_from_str(anchor: &str) -> Anchor {
-    match anchor {
-        "top-left" => Anchor::Top | Anchor::Left,
-        "top-right" => Anchor::Top | Anchor::Right,
-        "bottom-left" => Anchor::Bottom | Anchor::Left,
-        "bottom-right" => Anchor::Bottom | Anchor::Right,
-        "top" => Anchor::Top,
-        "bottom" => Anchor::Bottom,
-        "left" => Anchor::Left,
-        "right" => Anchor::Right,
-        _ => Anchor::Top | Anchor::Right,
+impl adv_widget::Operation<Option<Rectangle>> for FindWidgetBounds {
+    fn traverse(
+        &mut self,
+        operate: &mut dyn FnMut(&mut dyn adv_widget::Operation<Option<Rectangle>>),
+    ) {
+        operate(self);
     }
-}
-
-fn output_option_from_config(
-    output: &str,
-    focused_output_command: Option<&str>,
-) -> (OutputOption, Option<StackOutputPolicy>) {
-    let trimmed = output.trim();
-    let lower = trimmed.to_ascii_lowercase();
 
-    match lower.as_str() {
-        // Mako-like behavior:
-        // - with an empty stack, let compositor pick (usually focused/current output)
-        // - once the first popup lands, keep the rest of the stack on that same output
-        "focused" => resolve_focused_output_name(focused_output_command)
-            .map(|name| {
-                (
-                    OutputOption::OutputName(name.clone()),
-                    Some(StackOutputPolicy::Named(name)),
-                )
-            })
-            .unwrap_or((
-                OutputOption::CompositorDefault,
-                Some(StackOutputPolicy::CompositorChosen),
-            )),
-        "last-output" | "last_output" => (
-            OutputOption::LastOutput,
-            Some(StackOutputPolicy::CompositorChosen),
-        ),
-        "any" | "none" | "default" => (
-            OutputOption::CompositorDefault,
-            Some(StackOutputPolicy::CompositorChosen),
-        ),
-        _ if trimmed.is_empty() => (
-            OutputOption::CompositorDefault,
-            Some(StackOutputPolicy::CompositorChosen),
-        ),
-        _ => (
-            OutputOption::OutputName(trimmed.to_string()),
-            Some(StackOutputPolicy::Named(trimmed.to_string())),
-        ),
+    fn container(&mut self, id: Option<&adv_widget::Id>, bounds: Rectangle) {
+        if id.is_some_and(|id| id == &self.target) {
+            self.found = Some(bounds);
+        }
     }
-}
 
-fn resolve_focused_output_name(focused_output_command: Option<&str>) -> Option<String> {
-    let cmd = focused_output_command?.trim();
-    if cmd.is_empty() {
-        return None;
+    fn finish(&self) -> adv_widget::operation::Outcome<Option<Rectangle>> {
+        adv_widget::operation::Outcome::Some(self.found)
     }
+}
 
-    info!(command = %cmd, "resolving focused output via command");
+fn empty_popup<'a>() -> Element<'a, Message> {
+    container(text(""))
+        .width(Length::Fixed(1.0))
+        .height(Length::Fixed(1.0))
+        .style(|_| {
+            iced::widget::container::Style::default()
+                .background(Background::Color(Color::TRANSPARENT))
+        })
+        .into()
+}
 
-    let out = Command::new("sh").arg("-c").arg(cmd).output().ok()?;
-    if !out.status.success() {
-        warn!(command = %cmd, status = ?out.status.code(), "focused output command failed");
-        return None;
-    }
+/// The history panel: bulk buttons and the search with its chips over a scrollable list
+/// of entries, each with a checkbox; clicking an entry shows it again.
+fn history_panel_card(state: &WispdUi) -> Element<'_, Message> {
+    let ui = &state.ui;
+    let panel = &state.history_panel;
+    let font = resolve_font(&ui.font_family);
+    let size = u32::from(ui.font_size);
+    let small = (size * 4 / 5).max(1);
+    let bg_color = parse_hex_color(&ui.colors.background).unwrap_or(Color::BLACK);
+    let text_color = parse_hex_color(&ui.colors.text).unwrap_or(Color::WHITE);
+    let accent = parse_hex_color(&ui.colors.normal).unwrap_or(text_color);
+    let buttons = &ui.buttons;
+    let button_text_color = parse_hex_color(&buttons.text_color).unwrap_or(text_color);
+    let button_bg_color = parse_hex_color(&buttons.background).unwrap_or(bg_color);
+    let button_border_color = parse_hex_color(&buttons.border_color).unwrap_or(accent);
+    let button_hover_bg_color = parse_hex_color(&buttons.hover_background).unwrap_or(accent);
+    let button_hover_text_color =
+        parse_hex_color(&buttons.hover_text_color).unwrap_or(button_text_color);
+    let styled = move |_: &iced::Theme, status: ButtonStatus| {
+        style_button(
+            status,
+            button_bg_color,
+            button_text_color,
+            button_border_color,
+            button_hover_bg_color,
+            button_hover_text_color,
+        )
+    };
+    let label = |label: String| text(label).font(font).size(small);
 
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    let name = stdout.lines().next()?.trim();
-    if name.is_empty() {
-        warn!(command = %cmd, "focused output command produced empty output");
-        return None;
+    let select_all = if panel.all_selected() {
+        "Select none"
+    } else {
+        "Select all"
+    };
+    let title = panel
+        .app()
+        .map_or_else(|| "History".to_string(), |app| format!("History: {app}"));
+    let header = row![
+        text(title)
+            .font(font)
+            .size(size)
+            .color(text_color)
+            .width(Length::Fill),
+        button(label(select_all.to_string()))
+            .padding([2, 8])
+            .style(styled)
+            .on_press_maybe(
+                (!panel.rows().is_empty())
+                    .then_some(Message::HistoryPanel(PanelMessage::ToggleAll))
+            ),
+        button(label(format!("Delete ({})", panel.selected_count())))
+            .padding([2, 8])
+            .style(styled)
+            .on_press_maybe(
+                (panel.selected_count() > 0)
+                    .then_some(Message::HistoryPanel(PanelMessage::DeleteSelected))
+            ),
+        button(label("Clear all".to_string()))
+            .padding([2, 8])
+            .style(styled)
+            .on_press_maybe(
+                (!panel.rows().is_empty()).then_some(Message::HistoryPanel(PanelMessage::ClearAll))
+            ),
+        button(label("Close".to_string()))
+            .padding([2, 8])
+            .style(styled)
+            .on_press(Message::HistoryPanel(PanelMessage::Close)),
+    ]
+    .spacing(6);
+
+    let filter = &state.history_filter;
+    let chip = |name: &str, active: bool, edit: FilterEdit| {
+        let mark = if active { "✓ " } else { "" };
+        button(label(format!("{mark}{name}")))
+            .padding([2, 8])
+            .style(styled)
+            .on_press(Message::HistoryPanel(PanelMessage::Filter(edit)))
+    };
+    let urgency_chip = |name: &str, urgency: Urgency| {
+        chip(
+            name,
+            filter.urgency.as_ref() == Some(&urgency),
+            FilterEdit::Urgency(urgency),
+        )
+    };
+    let search = row![
+        text_input("Search", &filter.query)
+            .on_input(|query| Message::HistoryPanel(PanelMessage::Filter(FilterEdit::Query(query))))
+            .font(font)
+            .size(small)
+            .padding([2, 6])
+            .width(Length::Fill),
+        urgency_chip("Low", Urgency::Low),
+        urgency_chip("Normal", Urgency::Normal),
+        urgency_chip("Critical", Urgency::Critical),
+        chip("Today", filter.today, FilterEdit::Today),
+    ]
+    .spacing(6);
+
+    let mut list = column![].spacing(2);
+    if panel.rows().is_empty() {
+        let empty = if filter.is_active() {
+            "No matches"
+        } else {
+            "No history"
+        };
+        list = list.push(label(empty.to_string()).color(text_color));
     }
-
-    info!(command = %cmd, output = %name, "resolved focused output via command");
-    Some(name.to_string())
-}
-
-fn describe_output_option(output_option: &OutputOption) -> String {
-    match output_option {
-        OutputOption::LastOutput => "last-output".to_string(),
-        OutputOption::OutputName(name) => format!("output-name:{name}"),
-        OutputOption::Output(_) => "output-object".to_string(),
-        OutputOption::CompositorDefault => "compositor-default".to_string(),
-        OutputOption::None => "library-default".to_string(),
+    for entry in panel.rows() {
+        let tick = if panel.is_selected(entry.id) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let highlighted = panel.highlighted() == Some(entry.id);
+        let star = if entry.starred { "★" } else { "☆" };
+        let details = column![
+            text(entry.app_name.clone())
+                .font(font)
+                .size(small)
+                .color(accent),
+            text(entry.summary.clone())
+                .font(font)
+                .size(size)
+                .color(text_color),
+        ];
+        list = list.push(
+            container(
+                row![
+                    button(label(tick.to_string()))
+                        .padding([2, 6])
+                        .style(styled)
+                        .on_press(Message::HistoryPanel(PanelMessage::Toggle(entry.id))),
+                    button(label(star.to_string()))
+                        .padding([2, 6])
+                        .style(styled)
+                        .on_press(Message::HistoryPanel(PanelMessage::ToggleStar(entry.id))),
+                    mouse_area(container(details).width(Length::Fill))
+                        .on_press(Message::HistoryPanel(PanelMessage::Show(entry.id))),
+                    button(label("Show again".to_string()))
+                        .padding([2, 8])
+                        .style(styled)
+                        .on_press(Message::HistoryPanel(PanelMessage::Show(entry.id))),
+                ]
+                .spacing(8),
+            )
+            .padding([4, 6])
+            .width(Length::Fill)
+            .style(move |_| {
+                let style = iced::widget::container::Style::default();
+                if highlighted {
+                    style
+                        .background(Background::Color(accent.scale_alpha(0.25)))
+                        .border(border::width(1).color(accent))
+                } else {
+                    style
+                }
+            }),
+        );
     }
-}
 
-fn config_path() -> PathBuf {
-    let base = std::env::var_os("XDG_CONFIG_HOME")
-        .map(PathBuf::from)
-        .or_else(|| {
-            std::env::var_os("HOME").map(|home| {
-                let mut p = PathBuf::from(home);
-                p.push(".config");
-                p
-            })
+    container(column![header, search, scrollable(list).height(Length::Fill)].spacing(10))
+        .padding(12)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(move |_| {
+            iced::widget::container::Style::default()
+                .background(Background::Color(bg_color))
+                .border(border::width(2).color(accent))
         })
-        .unwrap_or_else(|| PathBuf::from("."));
-
-    base.join("wispd").join("config.toml")
+        .into()
 }
 
-fn validate_app_config(cfg: &AppConfig) -> Result<()> {
-    let valid_anchor = matches!(
-        cfg.ui.anchor.as_str(),
-        "top-left"
-            | "top-right"
-            | "bottom-left"
-            | "bottom-right"
-            | "top"
-            | "bottom"
-            | "left"
-            | "right"
-    );
-    if !valid_anchor {
-        return Err(anyhow!("invalid ui.anchor: {}", cfg.ui.anchor));
-    }
+fn view(state: &WispdUi, window_id: iced::window::Id) -> Element<'_, Message> {
+    let now = Instant::now();
 
-    if !cfg.ui.timeout_progress_position.eq_ignore_ascii_case("top")
-        && !cfg
-            .ui
-            .timeout_progress_position
-            .eq_ignore_ascii_case("bottom")
-    {
-        return Err(anyhow!(
-            "invalid ui.timeout_progress_position: {}",
-            cfg.ui.timeout_progress_position
-        ));
+    if state.history_window == Some(window_id) {
+        return history_panel_card(state);
     }
-
-    for (name, color) in [
-        ("ui.colors.low", cfg.ui.colors.low.as_str()),
-        ("ui.colors.normal", cfg.ui.colors.normal.as_str()),
-        ("ui.colors.critical", cfg.ui.colors.critical.as_str()),
-        ("ui.colors.background", cfg.ui.colors.background.as_str()),
-        ("ui.colors.text", cfg.ui.colors.text.as_str()),
-        (
-            "ui.colors.timeout_progress",
-            cfg.ui.colors.timeout_progress.as_str(),
-        ),
-        (
-            "ui.text.app_name.color",
-            cfg.ui.text.app_name.color.as_str(),
-        ),
-        ("ui.text.summary.color", cfg.ui.text.summary.color.as_str()),
-        ("ui.text.body.color", cfg.ui.text.body.color.as_str()),
-        ("ui.buttons.text_color", cfg.ui.buttons.text_color.as_str()),
-        ("ui.buttons.background", cfg.ui.buttons.background.as_str()),
-        (
-            "ui.buttons.border_color",
-            cfg.ui.buttons.border_color.as_str(),
-        ),
-        (
-            "ui.buttons.hover_background",
-            cfg.ui.buttons.hover_background.as_str(),
-        ),
-        (
-            "ui.buttons.hover_text_color",
-            cfg.ui.buttons.hover_text_color.as_str(),
-        ),
-    ] {
-        if parse_hex_color(color).is_none() {
-            return Err(anyhow!("invalid color for {name}: {color}"));
-        }
+    if state.takeovers.is_backdrop(window_id) {
+        let color = parse_hex_color(&state.ui.urgency.critical.backdrop_color)
+            .unwrap_or(Color::from_rgba(0.0, 0.0, 0.0, 0.45));
+        return takeover::backdrop(color);
+    }
+    let Some(slot) = popup_slot(state, window_id, now) else {
+        return empty_popup();
+    };
+    if !slot.closing && !slot.takeover {
+        profiling::first_frame();
     }
+    popup_card(PopupModel::build(state, &slot, now), &state.images)
+}
 
-    if cfg.ui.width == 0 {
-        return Err(anyhow!("ui.width must be greater than zero"));
+/// What `window_id` draws: a takeover, then visible popups; otherwise a popup playing
+/// its exit animation.
+fn popup_slot(state: &WispdUi, window_id: iced::window::Id, now: Instant) -> Option<PopupSlot<'_>> {
+    let duration = state.animation_duration();
+    if let Some(id) = state.takeovers.card_of(window_id) {
+        return Some(PopupSlot {
+            notification: state.notifications.get(&id)?,
+            height: state.takeover_height(id) as f32,
+            presence: Presence::SHOWN,
+            closing: false,
+            takeover: true,
+        });
     }
-    if cfg.ui.height == 0 {
-        return Err(anyhow!("ui.height must be greater than zero"));
+    if let Some(binding) = state.windows.iter().find(|w| w.window_id == window_id) {
+        let notification = state.notifications.get(&binding.notification_id)?;
+        return Some(PopupSlot {
+            notification,
+            height: state.popup_height_for_id(notification.id) as f32,
+            presence: binding.entered_at.map_or(Presence::SHOWN, |started| {
+                Presence::entering(started, now, duration)
+            }),
+            closing: false,
+            takeover: false,
+        });
     }
-
-    Ok(())
+    let closing = state.closing.iter().find(|c| c.window_id == window_id)?;
+    Some(PopupSlot {
+        notification: &closing.notification,
+        height: closing.height as f32,
+        presence: Presence::leaving(closing.started, now, duration),
+        closing: true,
+        takeover: false,
+    })
 }
 
-fn load_config_checked() -> Result<AppConfig> {
-    let path = config_path();
-    let Ok(raw) = fs::read_to_string(&path) else {
-        info!(path = %path.display(), "config not found, using defaults");
-        return Ok(AppConfig::default());
+/// The widgets for `model`; everything shown was decided by [`PopupModel::build`]. The
+/// decoded icon is looked up in `images` by the model's key.
+fn popup_card<'a>(model: PopupModel, images: &ImageCache) -> Element<'a, Message> {
+    let PopupModel {
+        id,
+        width: card_width,
+        height: card_height,
+        padding: card_padding,
+        colors,
+        fonts,
+        header: top_line_parts,
+        gauge,
+        body: body_text,
+        url,
+        badges,
+        more_actions,
+        copy_button,
+        pin,
+        close,
+        controls,
+        action_max_width,
+        icon,
+        timeout_bar,
+        interactive,
+        clickable,
+    } = model;
+    let border_color = Color::from(colors.border);
+    let bg_color = Color::from(colors.background);
+    let text_color = Color::from(colors.text);
+    let progress_color = Color::from(colors.progress);
+    let value_progress_color = Color::from(colors.value_progress);
+    let app_name_color = Color::from(colors.app_name);
+    let summary_color = Color::from(colors.summary);
+    let body_color = Color::from(colors.body);
+    let button_text_color = Color::from(colors.button.text);
+    let button_bg_color = Color::from(colors.button.background);
+    let button_border_color = Color::from(colors.button.border);
+    let button_hover_bg_color = Color::from(colors.button.hover_background);
+    let button_hover_text_color = Color::from(colors.button.hover_text);
+
+    let font = resolve_font(&fonts.family);
+    let button_font = fonts
+        .button_family
+        .as_deref()
+        .map(resolve_font)
+        .unwrap_or(font);
+    let close_button_font_size = fonts.small_size;
+    let button_font_size = fonts.button_size;
+
+    let close_face: Element<'_, Message> = match close {
+        CloseFace::Glyph(glyph) => text(glyph)
+            .size(close_button_font_size)
+            .font(button_font)
+            .color(button_text_color)
+            .into(),
+        CloseFace::Icon(path) => image(Handle::from_path(path))
+            .width(close_button_font_size as f32)
+            .height(close_button_font_size as f32)
+            .into(),
     };
+    let close_button = button(close_face)
+        .padding([1, 6])
+        .style(move |_, status| {
+            style_button(
+                status,
+                button_bg_color,
+                button_text_color,
+                button_border_color,
+                button_hover_bg_color,
+                button_hover_text_color,
+            )
+        })
+        .on_press_maybe(interactive.then_some(Message::DismissClicked { id }));
 
-    let cfg = toml::from_str::<AppConfig>(&raw)
-        .map_err(|err| anyhow!("failed to parse {}: {err}", path.display()))?;
-    validate_app_config(&cfg)?;
-    info!(path = %path.display(), "loaded config");
-    Ok(cfg)
-}
+    let mut text_block = column![].spacing(2);
 
-fn load_config() -> AppConfig {
-    match load_config_checked() {
-        Ok(cfg) => cfg,
-        Err(err) => {
-            warn!(%err, "failed to load config, using defaults");
-            AppConfig::default()
+    if let Some(parts) = top_line_parts {
+        let mut top_line = row![].spacing(6);
+        if let Some(badge) = parts.escalation {
+            top_line = top_line.push(
+                text(badge.label)
+                    .size(fonts.app_name_size)
+                    .font(font)
+                    .color(Color::from(badge.color)),
+            );
+        }
+        match parts.category {
+            Some(CategoryIcon::Glyph(glyph)) => {
+                top_line =
+                    top_line.push(text(glyph).size(fonts.app_name_size).color(app_name_color));
+            }
+            Some(CategoryIcon::Image(path)) => {
+                let accent_size = fonts.app_name_size.max(1) as f32;
+                top_line = top_line.push(
+                    image(Handle::from_path(path))
+                        .width(Length::Fixed(accent_size))
+                        .height(Length::Fixed(accent_size))
+                        .content_fit(ContentFit::Contain),
+                );
+            }
+            None => {}
+        }
+        if let Some(app_name) = parts.app_name {
+            top_line = top_line.push(
+                text(app_name)
+                    .size(fonts.app_name_size)
+                    .font(font)
+                    .color(app_name_color),
+            );
+        }
+        if let Some(origin) = parts.origin {
+            top_line = top_line.push(
+                text(origin)
+                    .size(close_button_font_size)
+                    .font(font)
+                    .color(app_name_color),
+            );
+        }
+        if let Some(summary) = parts.summary {
+            top_line = top_line.push(
+                text(summary)
+                    .size(fonts.summary_size)
+                    .font(font)
+                    .color(summary_color),
+            );
         }
+        text_block = text_block.push(top_line);
     }
-}
 
-#[cfg(unix)]
-fn spawn_reload_signal_listener(reload_tx: mpsc::Sender<()>) -> Result<()> {
-    std::thread::Builder::new()
-        .name("wispd-reload-signal".to_string())
-        .spawn(move || {
-            let runtime = match tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-            {
-                Ok(runtime) => runtime,
-                Err(err) => {
-                    warn!(?err, "failed to build signal listener runtime");
-                    return;
-                }
+    if let Some(gauge) = gauge {
+        let corner_radius = gauge.corner_radius;
+        let track_color = Color::from(gauge.track_color);
+        let filled = gauge.percent;
+        let mut parts = row![];
+        if filled > 0 {
+            parts = parts.push(
+                container(text(""))
+                    .width(Length::FillPortion(filled))
+                    .height(Length::Fixed(gauge.height))
+                    .style(move |_| {
+                        iced::widget::container::Style::default()
+                            .background(Background::Color(value_progress_color))
+                            .border(border::rounded(corner_radius))
+                    }),
+            );
+        }
+        if filled < 100 {
+            parts = parts.push(
+                container(text(""))
+                    .width(Length::FillPortion(100 - filled))
+                    .height(Length::Fixed(gauge.height)),
+            );
+        }
+        // Rounded like the timeout bar: the fill sits inside one rounded track.
+        text_block = text_block.push(
+            container(parts)
+                .width(Length::Fill)
+                .height(Length::Fixed(gauge.height))
+                .style(move |_| {
+                    iced::widget::container::Style::default()
+                        .background(Background::Color(track_color))
+                        .border(border::rounded(corner_radius))
+                }),
+        );
+    }
+
+    if let Some(body_text) = body_text {
+        // Already broken where the height estimate wrapped it.
+        text_block = text_block.push(
+            text(body_text)
+                .size(fonts.body_size)
+                .font(font)
+                .color(body_color)
+                .wrapping(text::Wrapping::None),
+        );
+    }
+    if let Some(url) = url {
+        text_block = text_block.push(
+            button(
+                text(url)
+                    .size(fonts.body_size)
+                    .font(font)
+                    .color(app_name_color)
+                    .wrapping(text::Wrapping::None),
+            )
+            .padding(0)
+            .style(|_, _| button::Style::default())
+            .on_press_maybe(interactive.then_some(Message::UrlClicked { id })),
+        );
+    }
+
+    let mut header = row![container(text_block).width(Length::Fill)].spacing(8);
+    for badge in badges {
+        header = header.push(
+            text(badge)
+                .size(close_button_font_size)
+                .font(button_font)
+                .color(app_name_color),
+        );
+    }
+    if let Some(glyph) = more_actions {
+        header = header.push(
+            button(
+                text(glyph)
+                    .size(close_button_font_size)
+                    .font(button_font)
+                    .color(app_name_color),
+            )
+            .padding([1, 4])
+            .style(|_, _| button::Style::default())
+            .on_press(Message::ExpandActionsClicked { id }),
+        );
+    }
+    if let Some(glyph) = copy_button {
+        header = header.push(
+            button(
+                text(glyph)
+                    .size(close_button_font_size)
+                    .font(button_font)
+                    .color(button_text_color),
+            )
+            .padding([1, 6])
+            .style(move |_, status| {
+                style_button(
+                    status,
+                    button_bg_color,
+                    button_text_color,
+                    button_border_color,
+                    button_hover_bg_color,
+                    button_hover_text_color,
+                )
+            })
+            .on_press(Message::CopyClicked { id }),
+        );
+    }
+    match pin {
+        Pin::Button { glyph, pressed } => {
+            // A pinned popup's button keeps the hover look so it reads as pressed.
+            let pin_bg_color = if pressed {
+                button_hover_bg_color
+            } else {
+                button_bg_color
             };
+            header = header.push(
+                button(
+                    text(glyph)
+                        .size(close_button_font_size)
+                        .color(button_text_color),
+                )
+                .padding([1, 6])
+                .style(move |_, status| {
+                    style_button(
+                        status,
+                        pin_bg_color,
+                        button_text_color,
+                        button_border_color,
+                        button_hover_bg_color,
+                        button_hover_text_color,
+                    )
+                })
+                .on_press(Message::PinClicked { id }),
+            );
+        }
+        Pin::Marker(glyph) => {
+            header = header.push(text(glyph).size(close_button_font_size));
+        }
+        Pin::Hidden => {}
+    }
+    let header = header.push(close_button);
 
-            runtime.block_on(async move {
-                use tokio::signal::unix::{SignalKind, signal};
+    let mut card_content = column![header].spacing(8);
 
-                let mut hup = match signal(SignalKind::hangup()) {
-                    Ok(stream) => stream,
-                    Err(err) => {
-                        warn!(?err, "failed to subscribe to SIGHUP");
-                        return;
-                    }
-                };
+    match controls {
+        Controls::Confirm { prompt } => {
+            let mut confirm_row = row![
+                text(prompt)
+                    .font(button_font)
+                    .size(button_font_size)
+                    .color(text_color)
+                    .wrapping(text::Wrapping::None)
+            ]
+            .spacing(8);
+            for (glyph, message) in [
+                ("✓", Message::ConfirmAction { id }),
+                ("✗", Message::CancelConfirm { id }),
+            ] {
+                confirm_row = confirm_row.push(
+                    button(
+                        text(glyph)
+                            .font(button_font)
+                            .size(button_font_size)
+                            .color(button_text_color),
+                    )
+                    .padding([2, ACTION_BUTTON_H_PADDING as u16 / 2])
+                    .style(move |_, status| {
+                        style_button(
+                            status,
+                            button_bg_color,
+                            button_text_color,
+                            button_border_color,
+                            button_hover_bg_color,
+                            button_hover_text_color,
+                        )
+                    })
+                    .on_press(message),
+                );
+            }
+            card_content = card_content.push(confirm_row);
+        }
+        Controls::Actions(rows) => {
+            for action_row in rows {
+                let mut actions_row = row![].spacing(8);
+                for action in action_row {
+                    let btn_bg = button_bg_color;
+                    let btn_fg = button_text_color;
+                    let btn_border = button_border_color;
+                    let btn_hover_bg = button_hover_bg_color;
+                    let btn_hover_fg = button_hover_text_color;
+
+                    let face: Element<'_, Message> = match action.icon {
+                        Some(path) => image(Handle::from_path(path))
+                            .width(button_font_size as f32)
+                            .height(button_font_size as f32)
+                            .content_fit(ContentFit::Contain)
+                            .into(),
+                        None => action_label(
+                            action.label,
+                            action.accelerator,
+                            button_font,
+                            button_font_size,
+                            btn_fg,
+                        ),
+                    };
+                    let action_button = button(face)
+                        .padding([2, ACTION_BUTTON_H_PADDING as u16 / 2])
+                        .style(move |_, status| {
+                            style_button(
+                                status,
+                                btn_bg,
+                                btn_fg,
+                                btn_border,
+                                btn_hover_bg,
+                                btn_hover_fg,
+                            )
+                        })
+                        .on_press_maybe(action.enabled.then(|| Message::ActionClicked {
+                            id,
+                            key: action.key,
+                        }));
+
+                    let action_button: Element<'_, Message> = if let Some(full) = action.full_label
+                    {
+                        // Full label on hover, rendered as a one-line overlay.
+                        tooltip(
+                            action_button,
+                            container(
+                                text(full)
+                                    .font(button_font)
+                                    .size(button_font_size)
+                                    .color(btn_hover_fg)
+                                    .wrapping(text::Wrapping::None),
+                            )
+                            .padding([2, 6])
+                            .style(move |_| {
+                                iced::widget::container::Style::default()
+                                    .background(Background::Color(btn_hover_bg))
+                                    .border(border::width(1).color(btn_border))
+                            }),
+                            tooltip::Position::FollowCursor,
+                        )
+                        .into()
+                    } else {
+                        action_button.into()
+                    };
 
-                info!("listening for SIGHUP to reload config");
-                while hup.recv().await.is_some() {
-                    if reload_tx.send(()).is_err() {
-                        break;
-                    }
+                    actions_row =
+                        actions_row.push(container(action_button).max_width(action_max_width));
                 }
+                card_content = card_content.push(actions_row);
+            }
+        }
+        Controls::None => {}
+    }
+
+    let mut content_row = row![].spacing(10);
+    if let Some(icon) = icon {
+        // Empty but full size until the icon is decoded, so the card keeps its height.
+        let slot = match images.handle(&icon.path) {
+            Some(handle) => container(
+                image(handle)
+                    .width(Length::Fixed(icon.size))
+                    .height(Length::Fixed(icon.size))
+                    .content_fit(ContentFit::Contain),
+            ),
+            None => container(text("")),
+        };
+        content_row = content_row.push(
+            slot.width(Length::Fixed(icon.size))
+                .height(Length::Fixed(icon.size)),
+        );
+    }
+    content_row = content_row.push(container(card_content).width(Length::Fill));
+
+    let body = container(content_row)
+        .padding(card_padding)
+        .width(Length::Fill)
+        .height(Length::Shrink)
+        .style(move |_| iced::widget::container::Style::default().color(text_color));
+
+    let card_stack = if let Some(bar) = timeout_bar {
+        let fill_width = (bar.track_width * bar.fraction).clamp(0.0, bar.track_width);
+        let corner_radius = bar.corner_radius;
+        let track_color = Color::from(bar.track_color);
+        let fill = container(text(""))
+            .width(Length::Fixed(fill_width))
+            .height(Length::Fixed(bar.height))
+            .style(move |_| {
+                iced::widget::container::Style::default()
+                    .background(Background::Color(progress_color))
+                    .border(border::rounded(corner_radius))
+            });
+        // The track is one rounded container with the fill inside, so both ends round.
+        let progress_bar = container(fill)
+            .width(Length::Fixed(bar.track_width))
+            .height(Length::Fixed(bar.height))
+            .style(move |_| {
+                iced::widget::container::Style::default()
+                    .background(Background::Color(track_color))
+                    .border(border::rounded(corner_radius))
             });
+        let progress_bar_inset = container(column![
+            container(text("")).height(Length::Fixed(bar.gap_above)),
+            progress_bar,
+            container(text("")).height(Length::Fixed(bar.gap_below))
+        ])
+        .width(Length::Fill)
+        .padding([0, bar.side_padding]);
+
+        if bar.top {
+            column![progress_bar_inset, body]
+        } else {
+            column![body, progress_bar_inset]
+        }
+    } else {
+        column![body]
+    };
+
+    let card = container(card_stack)
+        .id(popup_content_widget_id(id))
+        .width(Length::Fixed(card_width))
+        .height(Length::Fixed(card_height))
+        .style(move |_| {
+            iced::widget::container::Style::default()
+                .background(Background::Color(bg_color))
+                .border(border::width(2).color(border_color))
+        });
+
+    let content: Element<'_, Message> = if clickable {
+        mouse_area(card)
+            .on_press(Message::NotificationLeftClick { id })
+            .on_right_press(Message::NotificationRightClick { id })
+            .on_enter(Message::PopupHovered { id, hovered: true })
+            .on_exit(Message::PopupHovered { id, hovered: false })
+            .into()
+    } else {
+        card.into()
+    };
+
+    container(column![content])
+        .width(Length::Shrink)
+        .style(|_| {
+            iced::widget::container::Style::default()
+                .background(Background::Color(Color::TRANSPARENT))
         })
-        .map(|_| ())
-        .map_err(|err| anyhow!("failed to spawn reload signal listener: {err}"))
+        .into()
 }
 
-#[cfg(not(unix))]
-fn spawn_reload_signal_listener(_: mpsc::Sender<()>) -> Result<()> {
-    Ok(())
+/// `events` without the arrivals and replacements of notifications that close later in
+/// the same tick, so a burst never opens a popup only to close it again.
+fn coalesce_events(events: Vec<NotificationEvent>) -> Vec<NotificationEvent> {
+    let last_close: HashMap<u32, usize> = events
+        .iter()
+        .enumerate()
+        .filter_map(|(at, event)| match event {
+            NotificationEvent::Closed { id, .. } => Some((*id, at)),
+            _ => None,
+        })
+        .collect();
+    events
+        .into_iter()
+        .enumerate()
+        .filter(|(at, event)| match event {
+            NotificationEvent::Received { id, .. } | NotificationEvent::Replaced { id, .. } => {
+                last_close.get(id).is_none_or(|closed_at| closed_at < at)
+            }
+            _ => true,
+        })
+        .map(|(_, event)| event)
+        .collect()
 }
 
-fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+fn to_ui_notification(
+    id: u32,
+    notification: Notification,
+    default_timeout_ms: Option<i32>,
+    clamp: TimeoutClamp,
+) -> UiNotification {
+    let requested_timeout_ms = notification.timeout_ms;
+    let timeout_ms = clamp.apply(
+        effective_timeout_ms(requested_timeout_ms, default_timeout_ms),
+        notification.urgency == Urgency::Critical,
+    );
 
-    let app_cfg = load_config();
+    let app_icon = popup_icon(&notification);
+    let hints = notification.hints;
+    UiNotification {
+        id,
+        header_name: kde::header_app_name(
+            hints.display_app_name.as_deref(),
+            &notification.app_name,
+            hints.desktop_entry.as_deref(),
+        ),
+        origin: hints.origin_name,
+        url: hints.urls.into_iter().next(),
+        desktop_entry: hints.desktop_entry,
+        sandbox_app_id: hints.sandbox_app_id,
+        app_name: notification.app_name,
+        app_icon,
+        summary: notification.summary,
+        body: notification.body,
+        urgency: notification.urgency,
+        category: hints.category,
+        actions: notification
+            .actions
+            .into_iter()
+            .filter_map(to_ui_action)
+            .collect(),
+        action_icons: hints.action_icons == Some(true),
+        resident: hints.resident == Some(true),
+        progress: hints.progress,
+        position: hints.position,
+        sound: hints.sound,
+        requested_timeout_ms,
+        timeout_clamp: clamp,
+        timeout_ms,
+        created_at: Instant::now(),
+        expires_at: None,
+    }
+}
 
-    let source_cfg = SourceConfig {
-        default_timeout_ms: app_cfg.source.default_timeout_ms,
-        capabilities: app_cfg.source.capabilities.clone(),
-        ..SourceConfig::default()
-    };
+fn to_ui_action(action: NotificationAction) -> Option<UiAction> {
+    if action.label.trim().is_empty() {
+        return None;
+    }
 
-    let (ui_tx, ui_rx) = mpsc::channel::<NotificationEvent>();
-    let (reload_tx, reload_rx) = mpsc::channel::<()>();
-    let (cmd_tx, mut cmd_rx) = tokio_mpsc::unbounded_channel::<SourceCommand>();
-    let (ready_tx, ready_rx) = mpsc::channel::<Result<SourceConfig, String>>();
+    let (label, mnemonic) = accelerators::parse_mnemonic(&action.label);
+    Some(UiAction {
+        key: action.key,
+        label,
+        mnemonic,
+    })
+}
 
-    std::thread::Builder::new()
-        .name("wispd-source".to_string())
-        .spawn(move || {
-            let runtime = match tokio::runtime::Builder::new_multi_thread()
-                .worker_threads(2)
-                .enable_all()
-                .build()
-            {
-                Ok(runtime) => runtime,
-                Err(err) => {
-                    let _ = ready_tx.send(Err(format!("failed to build tokio runtime: {err}")));
-                    return;
-                }
-            };
+#[cfg(test)]
+fn render_format(format: &str, n: &UiNotification) -> String {
+    wisp_types::template::render(format, |name| match name {
+        "id" => Some(n.id.to_string()),
+        "app_name" => Some(n.app_name.clone()),
+        "summary" => Some(n.summary.clone()),
+        "body" => Some(n.body.clone()),
+        "urgency" => Some(urgency_label(n.urgency.clone()).to_string()),
+        _ => None,
+    })
+}
 
-            runtime.block_on(async move {
-                info!("source thread runtime started");
-                let (source_handle, mut source_events, dbus_service) =
-                    match WispSource::start_dbus(source_cfg.clone()).await {
-                        Ok(parts) => parts,
-                        Err(err) => {
-                            let _ = ready_tx
-                                .send(Err(format!("failed to start wisp source over dbus: {err}")));
-                            return;
-                        }
-                    };
+/// The icon a popup draws: the first of `Notification::icon_sources` that is a file or
+/// an icon name. Popups do not draw inline pixels yet, so those give way to the rest.
+fn popup_icon(notification: &Notification) -> String {
+    notification
+        .icon_sources()
+        .find_map(|source| match source {
+            IconSource::Name(name) => Some(name.to_string()),
+            IconSource::Path(path) => Some(path.display().to_string()),
+            IconSource::Data(_) => None,
+        })
+        .unwrap_or_default()
+}
 
-                info!(dbus_name = %source_cfg.dbus_name, "source thread dbus initialized");
-                let _ = ready_tx.send(Ok(source_cfg.clone()));
+fn resolve_icon_path(raw: &str) -> Option<PathBuf> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
 
-                loop {
-                    tokio::select! {
-                        maybe_event = source_events.recv() => {
-                            let Some(event) = maybe_event else {
-                                info!("source events channel ended");
-                                break;
-                            };
-                            if ui_tx.send(event).is_err() {
-                                warn!("ui channel receiver dropped; stopping source forwarder");
-                                break;
-                            }
-                        }
-                        maybe_cmd = cmd_rx.recv() => {
-                            let Some(cmd) = maybe_cmd else {
-                                info!("source command channel ended");
-                                break;
-                            };
-                            match cmd {
-                                SourceCommand::InvokeAction { id, key } => {
-                                    match source_handle.invoke_action(id, &key).await {
-                                        Ok(invoked) => info!(id, action_key = %key, invoked, "action command processed"),
-                                        Err(err) => warn!(id, action_key = %key, ?err, "failed to process action command"),
-                                    }
-                                }
-                                SourceCommand::Dismiss { id } => {
-                                    match source_handle.close(id, wisp_types::CloseReason::Dismissed).await {
-                                        Ok(closed) => info!(id, closed, "dismiss command processed"),
-                                        Err(err) => warn!(id, ?err, "failed to process dismiss command"),
-                                    }
-                                }
-                                SourceCommand::ReloadConfig {
-                                    capabilities,
-                                    default_timeout_ms,
-                                } => {
-                                    source_handle.update_runtime_config(capabilities, default_timeout_ms);
-                                    info!(default_timeout_ms, "source runtime config updated");
-                                }
-                            }
-                        }
-                    }
-                }
+    if let Some(path) = trimmed.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
+    }
 
-                info!("source thread event forwarder exiting");
-                drop((source_handle, dbus_service));
-            });
-        })
-        .map_err(|err| anyhow!("failed to spawn source thread: {err}"))?;
+    Some(PathBuf::from(trimmed))
+}
+
+/// Font size of the close, pin and copy buttons.
+fn close_font_size(ui: &UiSection) -> u32 {
+    ui.buttons.close_font_size.unwrap_or(
+        ui.buttons
+            .font_size
+            .unwrap_or(ui.font_size.saturating_sub(2)),
+    ) as u32
+}
+
+fn renderable_icon_path(show_icons: bool, app_icon: &str) -> Option<PathBuf> {
+    if !show_icons {
+        return None;
+    }
+
+    let path = resolve_icon_path(app_icon)?;
+    if !path.is_file() {
+        return None;
+    }
+
+    Some(path)
+}
+
+/// The icon an `action-icons` key names: a file, like `app_icon`, else a theme icon.
+/// `None` falls back to the label.
+fn action_icon_path(show_icons: bool, key: &str, size: u32) -> Option<PathBuf> {
+    if !show_icons {
+        return None;
+    }
+    renderable_icon_path(show_icons, key).or_else(|| icons::resolve_icon_name(key.trim(), size))
+}
+
+fn icon_height_px(ui: &UiSection, app_icon: &str) -> u32 {
+    if renderable_icon_path(ui.show_icons, app_icon).is_some() {
+        ui.max_icon_size.max(1) as u32
+    } else {
+        0
+    }
+}
+
+fn style_button(
+    status: ButtonStatus,
+    background: Color,
+    text: Color,
+    border_color: Color,
+    hover_background: Color,
+    hover_text: Color,
+) -> iced::widget::button::Style {
+    let (bg, fg) = match status {
+        ButtonStatus::Hovered | ButtonStatus::Pressed => (hover_background, hover_text),
+        // Disabled buttons (on mirrored popups) stay visible, dimmed.
+        ButtonStatus::Disabled => (background, text.scale_alpha(0.5)),
+        ButtonStatus::Active => (background, text),
+    };
+
+    iced::widget::button::Style {
+        background: Some(Background::Color(bg)),
+        text_color: fg,
+        border: border::width(1).color(border_color),
+        ..Default::default()
+    }
+}
+
+fn effective_timeout_ms(requested_timeout_ms: i32, default_timeout_ms: Option<i32>) -> Option<u32> {
+    let effective = match requested_timeout_ms {
+        0 => return None,
+        x if x < 0 => default_timeout_ms?,
+        x => x,
+    };
+
+    u32::try_from(effective).ok().filter(|value| *value > 0)
+}
+
+/// Measured text comes from `metrics` when the same text was wrapped the same way before.
+fn estimate_popup_height(
+    ui: &UiSection,
+    n: &UiNotification,
+    metrics: &mut TextMetricsCache,
+) -> u32 {
+    let app_name_size = ui.text.app_name.font_size.unwrap_or(ui.font_size) as f32;
+    let summary_size = ui.text.summary.font_size.unwrap_or(ui.font_size) as f32;
+    let icon_height = icon_height_px(ui, &n.app_icon);
+
+    let close_button_font_size = close_font_size(ui) as f32;
+    let actions = action_visibility(ui.buttons.max_visible_actions, &n.actions, false, true);
+    let body = body_block(ui, n);
+    let text_width_px = body.width_px;
+
+    let header_text = header_text(n);
+
+    // The accent sits in the header row, so it narrows the first line of header text.
+    let accent_width_px = ui
+        .category_icon(n)
+        .filter(|_| !header_text.is_empty())
+        .map_or(0.0, |icon| icon.width_px(app_name_size.max(1.0)) + 6.0);
+
+    let header_font_size = app_name_size.max(summary_size).max(1.0);
+    let char_width_em = metrics.char_width_em();
+    let header = TextBlock {
+        width_px: text_width_px - accent_width_px,
+        font_family: &ui.font_family,
+        font_size: header_font_size,
+    };
+    let header_height = metrics
+        .measure(&header_text, header, || {
+            let lines = if header_text.is_empty() {
+                0
+            } else {
+                header_text
+                    .lines()
+                    .map(|line| wrapped_line_count(line, chars_per_line(header, char_width_em)))
+                    .sum::<usize>()
+                    .max(1)
+            };
+            line_metrics(lines, header)
+        })
+        .height_px;
+
+    let body_height = measure_body(metrics, &n.body, body).height_px;
+
+    let text_internal_spacing = if header_height > 0 && body_height > 0 {
+        2
+    } else {
+        0
+    };
+    let url_height = if ui.show_kde_urls && n.url.is_some() {
+        line_metrics(1, body).height_px + 2
+    } else {
+        0
+    };
+    let gauge_height = if n.progress.is_some() {
+        ui.timeout_progress.height.max(1) as u32 + 2
+    } else {
+        0
+    };
+    let text_height = header_height
+        .saturating_add(body_height)
+        .saturating_add(text_internal_spacing)
+        .saturating_add(url_height)
+        .saturating_add(gauge_height);
+    let close_button_height = (close_button_font_size * 1.30).ceil() as u32 + 4;
+    let content_height = text_height.max(close_button_height).max(icon_height);
+
+    // Expanded actions are measured rather than estimated.
+    let actions_rows = actions.shown.div_ceil(3) as u32;
+    // Labels are ellipsized to a single line, so each row is one button high.
+    // Button widget chrome/padding can exceed raw text line-height.
+    let action_row_height = (ui.font_size as f32 * 2.0).ceil() as u32;
+    let actions_height = if actions_rows == 0 {
+        0
+    } else {
+        let row_gaps = 8 * actions_rows; // header->row1 plus gaps between action rows
+        actions_rows * action_row_height + row_gaps + 2
+    };
+
+    let progress_height = if ui.show_timeout_progress && n.timeout_ms.is_some() {
+        ui.timeout_progress.block_height(ui.padding)
+    } else {
+        0
+    };
+
+    let chrome = ui.padding as u32 * 2 + progress_height + 2;
+
+    content_height
+        .saturating_add(actions_height)
+        .saturating_add(chrome)
+        .max(ui.height.max(1))
+}
+
+/// How the body of `n` is laid out: the content width less the close button and the
+/// actions affordance beside the text.
+fn body_block<'a>(ui: &'a UiSection, n: &UiNotification) -> TextBlock<'a> {
+    let close_button_font_size = close_font_size(ui) as f32;
+    let close_button_width = (close_button_font_size * 0.8) + 14.0; // glyph + horizontal padding/border
+    let content_width_px = content_width_px(ui, icon_height_px(ui, &n.app_icon) > 0);
+    let actions = action_visibility(ui.buttons.max_visible_actions, &n.actions, false, true);
+    // The affordance glyph (about 1em, wider than the close glyph) with its padding, plus
+    // the row spacing before it.
+    let affordance_width = if actions.affordance {
+        close_button_font_size + 8.0 + 8.0
+    } else {
+        0.0
+    };
+    TextBlock {
+        width_px: (content_width_px - close_button_width - 8.0 - affordance_width).max(40.0),
+        font_family: &ui.font_family,
+        font_size: ui.text.body.font_size.unwrap_or(ui.font_size) as f32,
+    }
+}
+
+/// `body` wrapped as `block`; the estimator and the popup view share the measurement.
+fn measure_body(metrics: &mut TextMetricsCache, body: &str, block: TextBlock<'_>) -> TextMetrics {
+    let char_width_em = metrics.char_width_em();
+    metrics.measure(body, block, || {
+        if body.trim().is_empty() {
+            return line_metrics(0, block);
+        }
+        let max_chars = chars_per_line(block, char_width_em);
+        let mut breaks = Vec::new();
+        let mut lines = 0;
+        let mut offset = 0;
+        for line in body.split_inclusive('\n') {
+            let line_breaks = wrap_breaks(line.strip_suffix('\n').unwrap_or(line), max_chars);
+            lines += line_breaks.len() + 1;
+            breaks.extend(line_breaks.into_iter().map(|at| offset + at));
+            offset += line.len();
+        }
+        TextMetrics {
+            breaks,
+            ..line_metrics(lines, block)
+        }
+    })
+}
+
+/// App name and summary as they share the header row.
+fn header_text(n: &UiNotification) -> String {
+    let header = match (n.header_name.trim().is_empty(), n.summary.trim().is_empty()) {
+        (false, false) => format!("{} {}", n.header_name, n.summary),
+        (false, true) => n.header_name.clone(),
+        (true, false) => n.summary.clone(),
+        (true, true) => return String::new(),
+    };
+    match &n.origin {
+        Some(origin) => format!("{header} via {origin}"),
+        None => header,
+    }
+}
+
+/// Characters of `char_width_em` average width that fit on one line of `block`.
+fn chars_per_line(block: TextBlock<'_>, char_width_em: f32) -> usize {
+    let char_width = (block.font_size * char_width_em).max(1.0);
+    (block.width_px / char_width).floor().max(1.0) as usize
+}
+
+fn line_metrics(lines: usize, block: TextBlock<'_>) -> TextMetrics {
+    let line_height = (block.font_size * 1.30).ceil() as u32;
+    TextMetrics {
+        lines,
+        height_px: lines as u32 * line_height,
+        breaks: Vec::new(),
+    }
+}
+
+/// Width available to the text/actions column: card minus padding and the icon slot.
+fn content_width_px(ui: &UiSection, has_icon: bool) -> f32 {
+    let icon_width = if has_icon {
+        ui.max_icon_size.max(1) as f32 + 10.0 // icon + row spacing
+    } else {
+        0.0
+    };
+    (ui.width as f32 - (ui.padding as f32 * 2.0) - icon_width).max(80.0)
+}
+
+/// Graphemes an action label may show: `max_label_chars`, further capped by what fits
+/// on one line within `max_width` of the content width.
+fn action_label_char_budget(ui: &UiSection, has_icon: bool) -> usize {
+    let font_size = ui.buttons.font_size.unwrap_or(ui.font_size) as f32;
+    let char_width = (font_size * 0.54).max(1.0);
+    let button_px = content_width_px(ui, has_icon) * ui.buttons.max_width;
+    let fits = ((button_px - ACTION_BUTTON_H_PADDING - 2.0) / char_width)
+        .floor()
+        .max(1.0) as usize;
+
+    match ui.buttons.max_label_chars {
+        0 => fits,
+        max => fits.min(max),
+    }
+}
+
+/// Text as it is measured and drawn: CRLF and lone CRs become line feeds, and tabs are
+/// expanded with spaces to the next multiple of `tab_width` columns.
+fn normalize_whitespace(text: &str, tab_width: usize) -> Cow<'_, str> {
+    if !text.contains(['\r', '\t']) {
+        return Cow::Borrowed(text);
+    }
+    let tab_width = tab_width.max(1);
+    let mut normalized = String::with_capacity(text.len());
+    let mut column = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                normalized.push('\n');
+                column = 0;
+            }
+            '\n' => {
+                normalized.push('\n');
+                column = 0;
+            }
+            '\t' => {
+                let spaces = tab_width - column % tab_width;
+                normalized.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            c => {
+                normalized.push(c);
+                column += 1;
+            }
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// Shortens `label` to at most `max_graphemes` grapheme clusters, ending in `…`.
+fn ellipsize_label(label: &str, max_graphemes: usize) -> Cow<'_, str> {
+    if label.graphemes(true).count() <= max_graphemes.max(1) {
+        return Cow::Borrowed(label);
+    }
+
+    let kept: String = label
+        .graphemes(true)
+        .take(max_graphemes.saturating_sub(1))
+        .collect();
+    Cow::Owned(format!("{}…", kept.trim_end()))
+}
+
+/// Lines `line` wraps to. Runs of spaces keep their width (tabs are expanded by then),
+/// including indentation; the space at a wrap is dropped.
+fn wrapped_line_count(line: &str, max_chars: usize) -> usize {
+    wrap_breaks(line, max_chars).len() + 1
+}
+
+/// Byte offsets in `line` where a wrapped line starts, filling lines of `max_chars`
+/// greedily by word; a word longer than a line is broken between characters.
+fn wrap_breaks(line: &str, max_chars: usize) -> Vec<usize> {
+    let max_chars = max_chars.max(1);
+    let mut breaks = Vec::new();
+    // Characters on the current line; zero only before its first word.
+    let mut current = 0usize;
+    let mut gap = 0usize;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            gap += 1;
+            chars.next();
+            continue;
+        }
+        let mut word_len = 0usize;
+        let mut word_end = start;
+        while let Some((at, c)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+            word_len += 1;
+            word_end = at + c.len_utf8();
+        }
+        let needed = std::mem::take(&mut gap) + word_len;
+
+        if current + needed <= max_chars {
+            current += needed;
+            continue;
+        }
+        // Indentation stays on the first line; the spaces at a wrap go. What fits of the
+        // word ahead of its first break: the rest of the first line, else nothing.
+        let mut room = if current == 0 {
+            max_chars.saturating_sub(needed - word_len)
+        } else {
+            0
+        };
+        current = 0;
+        for (at, _) in line[start..word_end].char_indices() {
+            if current == room {
+                breaks.push(start + at);
+                room = max_chars;
+                current = 0;
+            }
+            current += 1;
+        }
+    }
+
+    breaks
+}
+
+fn resolve_font(raw: &str) -> Font {
+    let trimmed = raw.trim();
+
+    match trimmed.to_ascii_lowercase().as_str() {
+        "sans" | "sans-serif" => Font::DEFAULT,
+        "serif" => Font {
+            family: iced::font::Family::Serif,
+            ..Font::DEFAULT
+        },
+        "monospace" | "mono" => Font::MONOSPACE,
+        "cursive" => Font {
+            family: iced::font::Family::Cursive,
+            ..Font::DEFAULT
+        },
+        "fantasy" => Font {
+            family: iced::font::Family::Fantasy,
+            ..Font::DEFAULT
+        },
+        _ => Font::with_name(fonts::intern(trimmed)),
+    }
+}
+
+/// Renders a layer-shell namespace template; one that renders blank falls back to the
+/// static namespace.
+fn popup_namespace(template: &str, app_name: &str, urgency: Urgency) -> String {
+    let rendered = wisp_types::template::render(template, |name| match name {
+        "urgency" => Some(urgency_label(urgency.clone()).to_string()),
+        "app" => Some(wisp_types::template::slug(app_name)),
+        _ => None,
+    });
+    if rendered.trim().is_empty() {
+        return namespace();
+    }
+    rendered
+}
+
+fn urgency_label(urgency: Urgency) -> &'static str {
+    match urgency {
+        Urgency::Low => "low",
+        Urgency::Normal => "normal",
+        Urgency::Critical => "critical",
+    }
+}
+
+fn parse_hex_color(raw: &str) -> Option<Color> {
+    let hex = raw.trim().trim_start_matches('#');
+    match hex.len() {
+        6 => {
+            let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+            let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+            let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+            Some(Color::from_rgb8(r, g, b))
+        }
+        8 => {
+            let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+            let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+            let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+            let a = u8::from_str_radix(&hex[6..8], 16).ok()?;
+            Some(Color::from_rgba8(r, g, b, a as f32 / 255.0))
+        }
+        _ => None,
+    }
+}
+
+fn layer_anchor_from_str(anchor: &str) -> Anchor {
+    match anchor {
+        "top-left" => Anchor::Top | Anchor::Left,
+        "top-right" => Anchor::Top | Anchor::Right,
+        "bottom-left" => Anchor::Bottom | Anchor::Left,
+        "bottom-right" => Anchor::Bottom | Anchor::Right,
+        "top" => Anchor::Top,
+        "bottom" => Anchor::Bottom,
+        "left" => Anchor::Left,
+        "right" => Anchor::Right,
+        _ => Anchor::Top | Anchor::Right,
+    }
+}
+
+fn output_option_from_config(
+    output: &str,
+    focused_output_command: Option<&str>,
+) -> (OutputOption, Option<StackOutputPolicy>) {
+    let trimmed = output.trim();
+    let lower = trimmed.to_ascii_lowercase();
+
+    match lower.as_str() {
+        // Mako-like behavior:
+        // - with an empty stack, let compositor pick (usually focused/current output)
+        // - once the first popup lands, keep the rest of the stack on that same output
+        "focused" => resolve_focused_output_name(focused_output_command)
+            .map(|name| {
+                (
+                    OutputOption::OutputName(name.clone()),
+                    Some(StackOutputPolicy::Named(name)),
+                )
+            })
+            .unwrap_or((
+                OutputOption::CompositorDefault,
+                Some(StackOutputPolicy::CompositorChosen),
+            )),
+        "last-output" | "last_output" => (
+            OutputOption::LastOutput,
+            Some(StackOutputPolicy::CompositorChosen),
+        ),
+        "any" | "none" | "default" => (
+            OutputOption::CompositorDefault,
+            Some(StackOutputPolicy::CompositorChosen),
+        ),
+        _ if trimmed.is_empty() => (
+            OutputOption::CompositorDefault,
+            Some(StackOutputPolicy::CompositorChosen),
+        ),
+        _ => (
+            OutputOption::OutputName(trimmed.to_string()),
+            Some(StackOutputPolicy::Named(trimmed.to_string())),
+        ),
+    }
+}
+
+fn is_round_robin(output: &str) -> bool {
+    matches!(
+        output.trim().to_ascii_lowercase().as_str(),
+        "round-robin" | "round_robin"
+    )
+}
+
+fn resolve_focused_output_name(focused_output_command: Option<&str>) -> Option<String> {
+    let cmd = focused_output_command?.trim();
+    if cmd.is_empty() {
+        return None;
+    }
+
+    info!(command = %cmd, "resolving focused output via command");
+
+    let out = Command::new("sh").arg("-c").arg(cmd).output().ok()?;
+    if !out.status.success() {
+        warn!(command = %cmd, status = ?out.status.code(), "focused output command failed");
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let name = stdout.lines().next()?.trim();
+    if name.is_empty() {
+        warn!(command = %cmd, "focused output command produced empty output");
+        return None;
+    }
+
+    info!(command = %cmd, output = %name, "resolved focused output via command");
+    Some(name.to_string())
+}
+
+fn describe_output_option(output_option: &OutputOption) -> String {
+    match output_option {
+        OutputOption::LastOutput => "last-output".to_string(),
+        OutputOption::OutputName(name) => format!("output-name:{name}"),
+        OutputOption::Output(_) => "output-object".to_string(),
+        OutputOption::CompositorDefault => "compositor-default".to_string(),
+        OutputOption::None => "library-default".to_string(),
+    }
+}
+
+fn config_path() -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| {
+                let mut p = PathBuf::from(home);
+                p.push(".config");
+                p
+            })
+        })
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    base.join("wispd").join("config.toml")
+}
+
+fn validate_app_config(cfg: &AppConfig) -> Result<()> {
+    let valid_anchor = matches!(
+        cfg.ui.anchor.as_str(),
+        "top-left"
+            | "top-right"
+            | "bottom-left"
+            | "bottom-right"
+            | "top"
+            | "bottom"
+            | "left"
+            | "right"
+    );
+    if !valid_anchor {
+        return Err(anyhow!("invalid ui.anchor: {}", cfg.ui.anchor));
+    }
+
+    let progress = &cfg.ui.timeout_progress;
+    if !progress.is_top() && !progress.position.eq_ignore_ascii_case("bottom") {
+        return Err(anyhow!(
+            "invalid ui.timeout_progress.position: {}",
+            progress.position
+        ));
+    }
+    if !progress.corner_radius.is_finite() || progress.corner_radius < 0.0 {
+        return Err(anyhow!(
+            "invalid ui.timeout_progress.corner_radius: {}",
+            progress.corner_radius
+        ));
+    }
+
+    for (name, color) in [
+        ("ui.colors.low", cfg.ui.colors.low.as_str()),
+        ("ui.colors.normal", cfg.ui.colors.normal.as_str()),
+        ("ui.colors.critical", cfg.ui.colors.critical.as_str()),
+        (
+            "ui.urgency.critical.backdrop_color",
+            cfg.ui.urgency.critical.backdrop_color.as_str(),
+        ),
+        ("ui.colors.background", cfg.ui.colors.background.as_str()),
+        ("ui.colors.text", cfg.ui.colors.text.as_str()),
+        (
+            "ui.colors.timeout_progress",
+            cfg.ui.colors.timeout_progress.as_str(),
+        ),
+        (
+            "ui.colors.value_progress",
+            cfg.ui.colors.value_progress.as_str(),
+        ),
+        (
+            "ui.timeout_progress.track_color",
+            progress.track_color.as_str(),
+        ),
+        (
+            "ui.text.app_name.color",
+            cfg.ui.text.app_name.color.as_str(),
+        ),
+        ("ui.text.summary.color", cfg.ui.text.summary.color.as_str()),
+        ("ui.text.body.color", cfg.ui.text.body.color.as_str()),
+        ("ui.buttons.text_color", cfg.ui.buttons.text_color.as_str()),
+        ("ui.buttons.background", cfg.ui.buttons.background.as_str()),
+        (
+            "ui.buttons.border_color",
+            cfg.ui.buttons.border_color.as_str(),
+        ),
+        (
+            "ui.buttons.hover_background",
+            cfg.ui.buttons.hover_background.as_str(),
+        ),
+        (
+            "ui.buttons.hover_text_color",
+            cfg.ui.buttons.hover_text_color.as_str(),
+        ),
+    ] {
+        if parse_hex_color(color).is_none() {
+            return Err(anyhow!("invalid color for {name}: {color}"));
+        }
+    }
+
+    if cfg.ui.width == 0 {
+        return Err(anyhow!("ui.width must be greater than zero"));
+    }
+    if cfg.ui.height == 0 {
+        return Err(anyhow!("ui.height must be greater than zero"));
+    }
+
+    if !(cfg.ui.buttons.max_width > 0.0 && cfg.ui.buttons.max_width <= 1.0) {
+        return Err(anyhow!(
+            "ui.buttons.max_width must be in (0, 1]: {}",
+            cfg.ui.buttons.max_width
+        ));
+    }
+
+    if let (Some(min), Some(max)) = (cfg.ui.min_timeout_ms, cfg.ui.max_timeout_ms)
+        && min > max
+    {
+        return Err(anyhow!(
+            "ui.min_timeout_ms ({min}) must not exceed ui.max_timeout_ms ({max})"
+        ));
+    }
+    rules::check_timeouts(&cfg.ui.rules, cfg.ui.timeout_clamp()).map_err(|err| anyhow!(err))?;
+
+    if cfg.ui.animation.duration_ms > MAX_ANIMATION_DURATION_MS {
+        return Err(anyhow!(
+            "ui.animation.duration_ms must be at most {MAX_ANIMATION_DURATION_MS}: {}",
+            cfg.ui.animation.duration_ms
+        ));
+    }
+
+    logging::validate_log_section(&cfg.log)?;
+    sink::validate_sink_section(&cfg.sink)?;
+    metrics::validate_metrics_section(&cfg.metrics)?;
+
+    Ok(())
+}
+
+/// The config file, then `WISPD_*` variables, then `--set` flags, validated.
+fn load_config_checked() -> Result<AppConfig> {
+    let path = config_path();
+    let raw = fs::read_to_string(&path).ok();
+    let mut cfg = match &raw {
+        Some(raw) => toml::from_str::<AppConfig>(raw)
+            .map_err(|err| anyhow!("failed to parse {}: {err}", path.display()))?,
+        None => {
+            info!(path = %path.display(), "config not found, using defaults");
+            AppConfig::default()
+        }
+    };
+
+    let schema = config_schema();
+    let mut layered = overrides::from_env(std::env::vars(), &schema);
+    layered.extend(overrides::set_flags().iter().cloned());
+    if !layered.is_empty() {
+        let mut table = match &raw {
+            Some(raw) => raw.parse::<toml::Table>()?,
+            None => toml::Table::new(),
+        };
+        overrides::apply(&mut table, &schema, &layered, |table| {
+            config_from_table(table)
+                .map(drop)
+                .map_err(|err| err.to_string())
+        })?;
+        cfg = config_from_table(&table)?;
+        info!(count = layered.len(), "applied config overrides");
+    }
+    validate_app_config(&cfg)?;
+    info!(path = %path.display(), "loaded config");
+    Ok(cfg)
+}
+
+fn config_from_table(table: &toml::Table) -> Result<AppConfig, toml::de::Error> {
+    toml::Value::Table(table.clone()).try_into()
+}
+
+/// Every config key with its default, for resolving overrides. `[sink.webhook]`,
+/// `[sink.audit]` and `metrics.listen` are filled in so their keys are known although they are off by
+/// default.
+fn config_schema() -> toml::Table {
+    let mut schema = AppConfig::default();
+    schema.sink.webhook = Some(sink::WebhookConfig::default());
+    schema.sink.audit = Some(sink::AuditConfig::default());
+    schema.metrics.listen = Some(metrics::DEFAULT_LISTEN.to_string());
+    match toml::Value::try_from(schema) {
+        Ok(toml::Value::Table(table)) => table,
+        Ok(_) => toml::Table::new(),
+        Err(err) => {
+            warn!(%err, "failed to list config keys for overrides");
+            toml::Table::new()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn spawn_signal_listener(signal_tx: mpsc::Sender<SignalRequest>) -> Result<()> {
+    std::thread::Builder::new()
+        .name("wispd-signals".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    warn!(?err, "failed to build signal listener runtime");
+                    return;
+                }
+            };
+
+            runtime.block_on(async move {
+                use tokio::signal::unix::{SignalKind, signal};
+
+                let mut hup = match signal(SignalKind::hangup()) {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!(?err, "failed to subscribe to SIGHUP");
+                        return;
+                    }
+                };
+
+                let mut quit = match signal(SignalKind::quit()) {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!(?err, "failed to subscribe to SIGQUIT");
+                        return;
+                    }
+                };
+
+                info!("listening for SIGHUP (reload config) and SIGQUIT (debug dump)");
+                loop {
+                    let request = tokio::select! {
+                        Some(()) = hup.recv() => SignalRequest::ReloadConfig,
+                        Some(()) = quit.recv() => SignalRequest::DebugDump { reply: None },
+                        else => break,
+                    };
+                    if signal_tx.send(request).is_err() {
+                        break;
+                    }
+                }
+            });
+        })
+        .map(|_| ())
+        .map_err(|err| anyhow!("failed to spawn signal listener: {err}"))
+}
+
+#[cfg(not(unix))]
+fn spawn_signal_listener(_: mpsc::Sender<SignalRequest>) -> Result<()> {
+    Ok(())
+}
+
+async fn process_source_command(source: &WispSource, cmd: SourceCommand) -> Option<SourceReply> {
+    match cmd {
+        SourceCommand::InvokeAction { id, key } => {
+            let result = source.invoke_action(id, &key).await;
+            match &result {
+                Ok(()) => info!(id, action_key = %key, "action command processed"),
+                Err(err) => warn!(id, action_key = %key, %err, "action command rejected"),
+            }
+            Some(SourceReply::Command {
+                command: SourceCommand::InvokeAction { id, key },
+                result,
+            })
+        }
+        SourceCommand::Dismiss { id } => {
+            let result = source.close(id, wisp_types::CloseReason::Dismissed).await;
+            match &result {
+                Ok(()) => info!(id, "dismiss command processed"),
+                Err(err) => warn!(id, %err, "dismiss command rejected"),
+            }
+            Some(SourceReply::Command {
+                command: SourceCommand::Dismiss { id },
+                result,
+            })
+        }
+        SourceCommand::SetPinned { id, pinned } => {
+            let found = source.set_pinned(id, pinned);
+            info!(id, pinned, found, "pin command processed");
+            Some(SourceReply::Command {
+                command: SourceCommand::SetPinned { id, pinned },
+                result: if found {
+                    Ok(())
+                } else {
+                    Err(CommandError::NotFound)
+                },
+            })
+        }
+        SourceCommand::Refresh { id } => {
+            let notification = source
+                .snapshot()
+                .await
+                .into_iter()
+                .find(|(current, _)| *current == id)
+                .map(|(_, notification)| Box::new(notification));
+            Some(SourceReply::Snapshot { id, notification })
+        }
+        SourceCommand::Escalate { id, count } => {
+            if let Err(err) = source.escalate(id, count).await {
+                debug!(id, count, %err, "escalated notification already closed");
+            }
+            None
+        }
+        SourceCommand::InvokeHistoricAction { id, key } => {
+            match source.invoke_historic_action(id, &key).await {
+                Ok(()) => info!(id, action_key = %key, "history action command processed"),
+                Err(err) => warn!(id, action_key = %key, %err, "history action command rejected"),
+            }
+            None
+        }
+        SourceCommand::Resync => Some(SourceReply::Resync {
+            notifications: source.snapshot().await,
+            do_not_disturb: source.is_do_not_disturb(),
+        }),
+        SourceCommand::ListHistory => Some(SourceReply::History {
+            entries: source.history(),
+        }),
+        SourceCommand::ShowHistoric { id } => {
+            match source.reshow_historic(id).await {
+                Ok(id) => info!(id, "history entry shown from the panel"),
+                Err(err) => warn!(id, %err, "history entry not shown"),
+            }
+            None
+        }
+        SourceCommand::RemoveHistory { ids } => {
+            let removed = source.remove_history(&ids);
+            info!(
+                requested = ids.len(),
+                removed, "history entries deleted from the panel"
+            );
+            Some(SourceReply::History {
+                entries: source.history(),
+            })
+        }
+        SourceCommand::SetHistoryStarred { id, starred } => {
+            match source.set_history_starred(id, starred) {
+                Ok(id) => info!(id, starred, "history entry star changed from the panel"),
+                Err(err) => warn!(id, starred, %err, "history entry star not changed"),
+            }
+            Some(SourceReply::History {
+                entries: source.history(),
+            })
+        }
+        SourceCommand::ClearHistory => {
+            let removed = source.clear_history();
+            info!(removed, "history cleared from the panel");
+            Some(SourceReply::History {
+                entries: source.history(),
+            })
+        }
+        SourceCommand::DebugSnapshot => Some(SourceReply::DebugSnapshot(dump::SourceSnapshot {
+            stats: source.stats(),
+            history: source
+                .history()
+                .into_iter()
+                .take(dump::HISTORY_TAIL)
+                .collect(),
+        })),
+        SourceCommand::ReloadConfig {
+            features,
+            default_timeout_ms,
+            clamp_timeout,
+            timeout_rules,
+            dnd_schedule,
+            history_ttl,
+            critical_never_expires,
+        } => {
+            source.set_capabilities(features);
+            source.set_default_timeout(default_timeout_ms);
+            source.set_timeout_clamp(clamp_timeout);
+            let timeout_rule_count = timeout_rules.len();
+            source.set_timeout_rules(timeout_rules);
+            let dnd_windows = dnd_schedule.len();
+            source.set_dnd_schedule(dnd_schedule);
+            source.set_history_ttl(history_ttl);
+            source.set_critical_never_expires(critical_never_expires);
+            info!(
+                capabilities = ?source.capabilities(),
+                default_timeout_ms,
+                ?clamp_timeout,
+                timeout_rules = timeout_rule_count,
+                dnd_windows,
+                ?history_ttl,
+                critical_never_expires,
+                "source runtime config updated"
+            );
+            None
+        }
+    }
+}
+
+/// The webhook's delivery log: kept in the state dir with `ui.persist_history`, so a
+/// restart does not post what was delivered before, else only for this run.
+fn delivered_log(ui: &UiSection, cli: &cli::CliArgs) -> delivered::DeliveredLog {
+    if ui.persist_history {
+        delivered::DeliveredLog::load(state::dir(), cli.resend_all)
+    } else {
+        delivered::DeliveredLog::default()
+    }
+}
+
+/// What the source thread exports besides the UI's events.
+struct SourceExports {
+    sink_cfg: sink::SinkSection,
+    delivered: delivered::DeliveredLog,
+    /// `metrics.listen`.
+    metrics_listen: Option<String>,
+    /// Counts every event; shared with the UI.
+    metrics: Arc<Metrics>,
+}
+
+/// Starts `wisp-source` on its own runtime thread. The returned receiver reports each
+/// startup stage, then readiness once the bus name is owned (or the stage that failed).
+fn spawn_source_thread(
+    source_cfg: SourceConfig,
+    exports: SourceExports,
+    ui_tx: mpsc::Sender<NotificationEvent>,
+    mut cmd_rx: tokio_mpsc::UnboundedReceiver<SourceCommand>,
+    reply_tx: mpsc::Sender<SourceReply>,
+    ui_requests: mpsc::Sender<SignalRequest>,
+) -> Result<mpsc::Receiver<StartupReport>> {
+    let (ready_tx, ready_rx) = mpsc::channel::<StartupReport>();
+
+    std::thread::Builder::new()
+        .name("wispd-source".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    let _ = ready_tx.send(StartupReport::Failed {
+                        stage: Stage::RuntimeBuilt,
+                        error: format!("failed to build tokio runtime: {err}"),
+                    });
+                    return;
+                }
+            };
+            let _ = ready_tx.send(StartupReport::Reached(Stage::RuntimeBuilt));
+
+            runtime.block_on(async move {
+                info!("source thread runtime started");
+                let mut last = Stage::RuntimeBuilt;
+                let started = WispSource::start_dbus_with_progress(source_cfg.clone(), |stage| {
+                    last = stage.into();
+                    let _ = ready_tx.send(StartupReport::Reached(last));
+                })
+                .await;
+                let (source_handle, mut source_events, dbus_service) = match started {
+                    Ok(parts) => parts,
+                    Err(err) => {
+                        let stage = match err {
+                            StartupError::BusUnreachable(_) => Stage::Connected,
+                            StartupError::NameTaken(_) => Stage::NameAcquired,
+                            StartupError::Dbus(_) => Stage::after(Some(last)),
+                        };
+                        let _ = ready_tx.send(StartupReport::Failed {
+                            stage,
+                            error: err.to_string(),
+                        });
+                        return;
+                    }
+                };
+
+                info!(dbus_name = %source_cfg.dbus_name, "source thread dbus initialized");
+                let ui_iface = explain::UiInterface {
+                    requests: ui_requests,
+                };
+                if let Err(err) = dbus_service
+                    .connection()
+                    .object_server()
+                    .at(CONTROL_PATH, ui_iface)
+                    .await
+                {
+                    warn!(%err, "failed to serve org.wispd.Ui1; ExplainNotification is unavailable");
+                }
+                let _ = ready_tx.send(StartupReport::Ready(source_cfg.clone()));
+
+                let SourceExports {
+                    sink_cfg,
+                    delivered,
+                    metrics_listen,
+                    metrics,
+                } = exports;
+                let mut sinks = sink::Sinks::start(sink_cfg, delivered);
+                if let Some(listen) = &metrics_listen {
+                    metrics::start(listen, Arc::clone(&metrics), source_handle.clone()).await;
+                }
+
+                loop {
+                    tokio::select! {
+                        maybe_event = source_events.recv() => {
+                            let Some(event) = maybe_event else {
+                                info!("source events channel ended");
+                                break;
+                            };
+                            metrics.observe(&event);
+                            sinks.offer(&event);
+                            if ui_tx.send(event).is_err() {
+                                warn!("ui channel receiver dropped; stopping source forwarder");
+                                break;
+                            }
+                        }
+                        maybe_cmd = cmd_rx.recv() => {
+                            let Some(cmd) = maybe_cmd else {
+                                info!("source command channel ended");
+                                break;
+                            };
+                            let reply = process_source_command(&source_handle, cmd).await;
+
+                            if let Some(reply) = reply
+                                && reply_tx.send(reply).is_err()
+                            {
+                                warn!("ui reply receiver dropped");
+                            }
+                        }
+                    }
+                }
+
+                info!("source thread event forwarder exiting");
+                drop((source_handle, dbus_service));
+            });
+        })
+        .map_err(|err| anyhow!("failed to spawn source thread: {err}"))?;
+
+    Ok(ready_rx)
+}
+
+/// The iced layer-shell UI (or its X11 fallback) as a [`supervisor::UiRunner`]. The
+/// channels outlive each run, so a restarted UI reattaches to the same source thread.
+struct LayerShellRunner {
+    events: Arc<Mutex<mpsc::Receiver<NotificationEvent>>>,
+    signals: Arc<Mutex<mpsc::Receiver<SignalRequest>>>,
+    replies: Arc<Mutex<mpsc::Receiver<SourceReply>>>,
+    cmd_tx: tokio_mpsc::UnboundedSender<SourceCommand>,
+    ui_cfg: UiSection,
+    default_timeout_ms: Option<i32>,
+    mirror_cfg: Option<MirrorSection>,
+    /// Shared with the source thread; every UI run counts into the same registry.
+    metrics: Arc<Metrics>,
+    /// Resolved from `--backend`; never `Auto`.
+    backend: Backend,
+    runs: u32,
+}
+
+impl LayerShellRunner {
+    /// The iced boot function for one run; it sets `booted` once the state exists, so a
+    /// later failure counts as a lost session.
+    fn boot(&self, booted: Arc<AtomicBool>) -> impl Fn() -> WispdUi + 'static {
+        let events = Arc::clone(&self.events);
+        let signals = Arc::clone(&self.signals);
+        let replies = Arc::clone(&self.replies);
+        let cmd_tx = self.cmd_tx.clone();
+        let ui_cfg = self.ui_cfg.clone();
+        let default_timeout_ms = self.default_timeout_ms;
+        let mirror_cfg = self.mirror_cfg;
+        let metrics = Arc::clone(&self.metrics);
+
+        move || {
+            booted.store(true, Ordering::Relaxed);
+            let mut ui = WispdUi::new(
+                Arc::clone(&events),
+                Arc::clone(&signals),
+                Arc::clone(&replies),
+                cmd_tx.clone(),
+                ui_cfg.clone(),
+                default_timeout_ms,
+                MuteList::load(state::dir()),
+            );
+            ui.metrics = Arc::clone(&metrics);
+            ui.check_glyphs();
+            ui.check_fonts();
+            match mirror_cfg {
+                Some(cfg) => ui.mirroring(cfg),
+                None => ui,
+            }
+        }
+    }
+
+    fn run_layer_shell(&self) -> UiExit {
+        let wayland_connection = match Connection::connect_to_env() {
+            Ok(connection) => connection,
+            Err(err) => return UiExit::Unavailable(format!("failed to connect to wayland: {err}")),
+        };
+
+        let settings = Settings {
+            layer_settings: LayerShellSettings {
+                // Bootstrap surface kept minimal; real notification windows are opened dynamically.
+                anchor: Anchor::Top | Anchor::Left,
+                layer: Layer::Top,
+                exclusive_zone: 0,
+                margin: (0, 0, 0, 0),
+                size: Some((1, 1)),
+                keyboard_interactivity: KeyboardInteractivity::None,
+                ..Default::default()
+            },
+            with_connection: Some(wayland_connection.clone()),
+            ..Default::default()
+        };
+
+        let subscription_connection = wayland_connection.clone();
+        let booted = Arc::new(AtomicBool::new(false));
+        let app = daemon(self.boot(Arc::clone(&booted)), namespace, update, view)
+            .style(app_style)
+            .subscription(move |state| subscription(state, Some(subscription_connection.clone())))
+            .settings(settings);
+
+        let run_result = catch_unwind(AssertUnwindSafe(|| app.run()));
+        ui_exit(run_result, &booted, self.backend)
+    }
+
+    /// A restarted UI starts empty, so the events it missed are dropped in favour of the
+    /// source's current notifications. A mirror has no source to ask and replays them.
+    fn resync(&self) {
+        if self.mirror_cfg.is_some() {
+            return;
+        }
+        if let Ok(events) = self.events.lock() {
+            let dropped = events.try_iter().count();
+            debug!(dropped, "discarding events buffered while the ui was down");
+        }
+        if let Err(err) = self.cmd_tx.send(SourceCommand::Resync) {
+            warn!(?err, "failed to request a source resync");
+        }
+    }
+
+    /// Without a UI the source still serves D-Bus; its events are drained so the
+    /// forwarder keeps running.
+    fn run_headless(&self) {
+        let Ok(events) = self.events.lock() else {
+            return;
+        };
+        while let Ok(event) = events.recv() {
+            debug!(event = ?LoggableEvent(&event), "headless: no ui to show event");
+        }
+    }
+}
+
+impl supervisor::UiRunner for LayerShellRunner {
+    fn run(&mut self) -> UiExit {
+        if self.runs > 0 {
+            self.resync();
+        }
+        self.runs += 1;
+
+        #[cfg(feature = "x11")]
+        if self.backend == Backend::X11 {
+            return x11::run(self);
+        }
+        self.run_layer_shell()
+    }
+}
+
+/// Maps how an iced run ended to a [`UiExit`]; failing after boot lost a working session.
+fn ui_exit<E: std::fmt::Display>(
+    run_result: std::thread::Result<Result<(), E>>,
+    booted: &AtomicBool,
+    backend: Backend,
+) -> UiExit {
+    let failed = |reason: String| {
+        if booted.load(Ordering::Relaxed) {
+            UiExit::Lost(reason)
+        } else {
+            UiExit::Unavailable(reason)
+        }
+    };
+    match (run_result, backend) {
+        (Ok(Ok(())), _) => UiExit::Quit,
+        (Ok(Err(err)), Backend::X11) => failed(format!("iced x11 app failed: {err}")),
+        (Ok(Err(err)), _) => failed(format!("iced layer-shell app failed: {err}")),
+        (Err(_), Backend::X11) => failed(
+            "wispd ui panicked. Make sure DISPLAY points at a running X server.".to_string(),
+        ),
+        (Err(_), _) => failed(
+            "wispd ui panicked. Make sure you are running inside a Wayland session and have Wayland runtime libraries available (e.g. `wayland`, `libxkbcommon`).".to_string(),
+        ),
+    }
+}
+
+/// `wispd --repair-state`: reports on every state file, failing while any is broken.
+fn repair_state(mode: cli::RepairMode) -> Result<()> {
+    let dir = state::dir();
+    let quarantine = mode == cli::RepairMode::Quarantine;
+    let mut reports = state::repair(&dir, state::FILES, quarantine);
+    reports.extend(state::repair_logs(&dir, state::LOGS, quarantine));
+    for report in &reports {
+        println!("{report}");
+    }
+    let broken = reports
+        .iter()
+        .filter(|report| report.needs_repair())
+        .count();
+    match (broken, mode) {
+        (0, _) => Ok(()),
+        (_, cli::RepairMode::Check) => bail!(
+            "{broken} state file(s) in {} are broken; `wispd --repair-state quarantine` moves them aside",
+            dir.display()
+        ),
+        (_, cli::RepairMode::Quarantine) => bail!(
+            "{broken} state file(s) in {} were left in place",
+            dir.display()
+        ),
+    }
+}
+
+/// `wispd --check-config`: validates the config and prints how its fonts resolve.
+fn check_config() -> Result<()> {
+    let cfg = load_config_checked()?;
+    println!("config {}: ok", config_path().display());
+    println!("{}", FontReport::check(&cfg.ui, &SystemFonts));
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    profiling::start();
+    let cli = cli::parse_args(std::env::args().skip(1))?;
+    overrides::install(cli.set.clone());
+    if let Some(kind) = cli.print_service_file {
+        let exe = std::env::current_exe()
+            .map_err(|err| anyhow!("failed to resolve the wispd binary path: {err}"))?;
+        print!("{}", kind.render(&exe));
+        return Ok(());
+    }
+    if let Some(mode) = cli.repair_state {
+        return repair_state(mode);
+    }
+    if cli.check_config {
+        return check_config();
+    }
+
+    // Config is read before the subscriber exists so `[log]` can shape it; report the
+    // outcome once logging is up.
+    let loaded_cfg = {
+        let _stage = profiling::stage("config_load");
+        load_config_checked()
+    };
+    let log_cfg = loaded_cfg
+        .as_ref()
+        .map(|cfg| cfg.log.clone())
+        .unwrap_or_default();
+    logging::init(&log_cfg, &cli.log)?;
+    logging::install_panic_hook();
+
+    let app_cfg = match loaded_cfg {
+        Ok(cfg) => {
+            info!(path = %config_path().display(), "config ready");
+            cfg
+        }
+        Err(err) => {
+            warn!(%err, "failed to load config, using defaults");
+            AppConfig::default()
+        }
+    };
+
+    let mut source_cfg = SourceConfig::builder()
+        .log_content(app_cfg.log.log_content)
+        .default_timeout_ms(app_cfg.source.default_timeout_ms)
+        .rate_limit(app_cfg.source.rate_limit)
+        .dedup(app_cfg.source.dedup.map(DedupSection::dedup))
+        .capabilities(app_cfg.source.features(&app_cfg.ui).capabilities())
+        .clamp_timeout(app_cfg.ui.timeout_clamp())
+        .timeout_rules(app_cfg.ui.timeout_rules())
+        .critical_never_expires(app_cfg.ui.critical_never_expires)
+        .dnd_schedule(app_cfg.ui.dnd.schedule.clone())
+        .history_ttl(app_cfg.ui.history_ttl.map(HumanDuration::get));
+    if app_cfg.ui.persist_history {
+        source_cfg = source_cfg.history_store(HistoryLog::new(state::dir()));
+    }
+    let source_cfg = source_cfg.build();
+
+    if cli.headless {
+        if let Ok(raw) = fs::read_to_string(config_path()) {
+            let ignored = headless::ignored_keys(&raw);
+            if !ignored.is_empty() {
+                warn!(keys = ?ignored, "UI-only config keys are ignored in headless mode");
+            }
+        }
+        return headless::run(
+            source_cfg,
+            app_cfg.sink.clone(),
+            delivered_log(&app_cfg.ui, &cli),
+            cli.startup_timeout(),
+        );
+    }
+
+    let backend = cli.backend.resolve(
+        std::env::var_os("WAYLAND_DISPLAY").is_some(),
+        std::env::var_os("DISPLAY").is_some(),
+    )?;
+    info!(?backend, "popup backend selected");
+
+    let (ui_tx, ui_rx) = mpsc::channel::<NotificationEvent>();
+    let (signal_tx, signal_rx) = mpsc::channel::<SignalRequest>();
+    let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel::<SourceCommand>();
+    let (reply_tx, reply_rx) = mpsc::channel::<SourceReply>();
+    let metrics = Arc::new(Metrics::default());
+
+    if cli.mirror {
+        // Another daemon keeps the bus name; its traffic is observed instead.
+        let ready_rx =
+            mirror::spawn_mirror_thread(source_cfg.dbus_name.clone(), app_cfg.sink.clone(), ui_tx)?;
+        spawn_signal_listener(signal_tx)?;
+        let owner = match ready_rx.recv_timeout(cli.startup_timeout()) {
+            Ok(Ok(owner)) => owner,
+            Ok(Err(err)) => return Err(anyhow!(err)),
+            Err(err) => return Err(anyhow!("mirror did not start in time: {err}")),
+        };
+        info!(dbus_name = %source_cfg.dbus_name, %owner, "wispd ui started in mirror mode");
+    } else {
+        // The bus name is requested before any Wayland/iced setup; events that arrive in
+        // the meantime wait in `ui_tx` until the first UI tick drains them.
+        let ready_rx = {
+            let _stage = profiling::stage("source_spawn");
+            spawn_source_thread(
+                source_cfg,
+                SourceExports {
+                    sink_cfg: app_cfg.sink.clone(),
+                    delivered: delivered_log(&app_cfg.ui, &cli),
+                    metrics_listen: app_cfg.metrics.listen.clone(),
+                    metrics: Arc::clone(&metrics),
+                },
+                ui_tx,
+                cmd_rx,
+                reply_tx,
+                signal_tx.clone(),
+            )?
+        };
+
+        spawn_signal_listener(signal_tx)?;
+
+        let source_runtime_cfg = {
+            let _stage = profiling::stage("name_acquisition");
+            startup::wait_for_startup(&ready_rx, cli.startup_timeout())?
+        };
+
+        info!(
+            dbus_name = %source_runtime_cfg.dbus_name,
+            dbus_path = %source_runtime_cfg.dbus_path,
+            "wispd ui started"
+        );
+    }
+    // systemd only listens under `Type=notify`; the generated unit uses `Type=dbus`.
+    if let Err(err) = systemd::Notifier::from_env().ready() {
+        warn!(%err, "failed to notify systemd of readiness");
+    }
+
+    let mut runner = LayerShellRunner {
+        events: Arc::new(Mutex::new(ui_rx)),
+        signals: Arc::new(Mutex::new(signal_rx)),
+        replies: Arc::new(Mutex::new(reply_rx)),
+        cmd_tx,
+        ui_cfg: app_cfg.ui,
+        default_timeout_ms: app_cfg.source.default_timeout_ms,
+        mirror_cfg: cli.mirror.then_some(app_cfg.mirror),
+        metrics,
+        backend,
+        runs: 0,
+    };
+
+    match supervisor::supervise(&mut runner, RetryPolicy::default(), std::thread::sleep) {
+        Outcome::Quit => Ok(()),
+        Outcome::Headless => {
+            runner.run_headless();
+            Ok(())
+        }
+    }
+}
+
+/// Hot paths for `benches/hot_paths.rs`, which compiles this file as a module.
+#[cfg(feature = "profiling")]
+pub(crate) mod bench {
+    use super::*;
+
+    /// Popup height estimation over a fixed set of notifications with default UI settings.
+    pub(crate) struct MeasureBench {
+        ui: UiSection,
+        notifications: Vec<UiNotification>,
+        metrics: TextMetricsCache,
+    }
+
+    impl MeasureBench {
+        pub(crate) fn new(notifications: Vec<Notification>) -> Self {
+            let ui = UiSection::default();
+            let notifications = notifications
+                .into_iter()
+                .enumerate()
+                .map(|(id, n)| to_ui_notification(id as u32, n, None, ui.timeout_clamp()))
+                .collect();
+            Self {
+                ui,
+                notifications,
+                metrics: TextMetricsCache::default(),
+            }
+        }
+
+        /// A relayout with nothing measured yet.
+        pub(crate) fn run_cold(&self) -> u32 {
+            let mut metrics = TextMetricsCache::default();
+            self.notifications
+                .iter()
+                .map(|n| estimate_popup_height(&self.ui, n, &mut metrics))
+                .sum()
+        }
+
+        /// A relayout after an earlier one measured the same popups.
+        pub(crate) fn run_warm(&mut self) -> u32 {
+            self.notifications
+                .iter()
+                .map(|n| estimate_popup_height(&self.ui, n, &mut self.metrics))
+                .sum()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::temp_dir, *};
+    use wisp_types::{CloseReason, NotificationHints};
+
+    fn sample(id: u32, summary: &str) -> NotificationEvent {
+        NotificationEvent::Received {
+            id,
+            notification: Box::new(Notification {
+                app_name: String::from("app"),
+                app_icon: String::new(),
+                summary: summary.to_string(),
+                body: String::new(),
+                urgency: Urgency::Normal,
+                timeout_ms: 1000,
+                actions: vec![],
+                hints: Default::default(),
+            }),
+            silent: false,
+            remaining_ms: None,
+        }
+    }
+
+    fn test_ui(
+        ui: UiSection,
+    ) -> (
+        WispdUi,
+        tokio_mpsc::UnboundedReceiver<SourceCommand>,
+        mpsc::Sender<SignalRequest>,
+    ) {
+        let (_event_tx, event_rx) = mpsc::channel();
+        test_ui_with_events(ui, event_rx)
+    }
+
+    /// Like `test_ui`, but keeps the reply sender so replies arrive through `Message::Tick`.
+    fn test_ui_with_replies(
+        ui: UiSection,
+    ) -> (
+        WispdUi,
+        tokio_mpsc::UnboundedReceiver<SourceCommand>,
+        mpsc::Sender<SourceReply>,
+    ) {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+        (
+            WispdUi::new(
+                Arc::new(Mutex::new(event_rx)),
+                Arc::new(Mutex::new(signal_rx)),
+                Arc::new(Mutex::new(reply_rx)),
+                cmd_tx,
+                ui,
+                None,
+                MuteList::in_memory(),
+            ),
+            cmd_rx,
+            reply_tx,
+        )
+    }
+
+    fn test_ui_with_events(
+        ui: UiSection,
+        event_rx: mpsc::Receiver<NotificationEvent>,
+    ) -> (
+        WispdUi,
+        tokio_mpsc::UnboundedReceiver<SourceCommand>,
+        mpsc::Sender<SignalRequest>,
+    ) {
+        let (signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+        (
+            WispdUi::new(
+                Arc::new(Mutex::new(event_rx)),
+                Arc::new(Mutex::new(signal_rx)),
+                Arc::new(Mutex::new(reply_rx)),
+                cmd_tx,
+                ui,
+                None,
+                MuteList::in_memory(),
+            ),
+            cmd_rx,
+            signal_tx,
+        )
+    }
+
+    #[tokio::test]
+    async fn notify_before_ui_start_is_buffered_for_the_first_tick() {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let cfg = SourceConfig::builder()
+            .dbus_name(format!("org.wispd.Activated.{unique}"))
+            .build();
+        let (ui_tx, ui_rx) = mpsc::channel();
+        let (_cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+        let (reply_tx, _reply_rx) = mpsc::channel();
+        let (signal_tx, _signal_rx) = mpsc::channel();
+        let ready_rx = spawn_source_thread(
+            cfg,
+            None,
+            delivered::DeliveredLog::default(),
+            ui_tx,
+            cmd_rx,
+            reply_tx,
+            signal_tx,
+        )
+        .unwrap();
+        let Ok(cfg) = startup::wait_for_startup(&ready_rx, startup::ACTIVATED_STARTUP_TIMEOUT)
+        else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+        let Ok(client) = zbus::Connection::session().await else {
+            eprintln!("skipping dbus integration test: session bus unavailable");
+            return;
+        };
+
+        // Stands in for the call that triggered activation, answered before any UI exists.
+        for summary in ["early-1", "early-2"] {
+            client
+                .call_method(
+                    Some(cfg.dbus_name.as_str()),
+                    cfg.dbus_path.as_str(),
+                    Some(wisp_source::DBUS_INTERFACE),
+                    "Notify",
+                    &(
+                        "test",
+                        0u32,
+                        "",
+                        summary,
+                        "",
+                        Vec::<String>::new(),
+                        HashMap::<&str, zbus::zvariant::Value>::new(),
+                        -1i32,
+                    ),
+                )
+                .await
+                .unwrap();
+        }
+
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui_with_events(UiSection::default(), ui_rx);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while ui.notifications.len() < 2 && Instant::now() < deadline {
+            let _ = ui.on_tick();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let summaries: Vec<&str> = ui
+            .windows
+            .iter()
+            .map(|w| ui.notifications[&w.notification_id].summary.as_str())
+            .collect();
+        assert_eq!(summaries, vec!["early-2", "early-1"]);
+    }
+
+    #[test]
+    fn newest_goes_to_front() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 2);
+        assert_eq!(ui.windows[1].notification_id, 1);
+    }
+
+    #[test]
+    fn replacement_keeps_slot() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: Box::new(Notification::default()),
+            current: Box::new(Notification {
+                summary: String::from("one-new"),
+                ..Notification::default()
+            }),
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+
+        assert_eq!(ui.windows[1].notification_id, 1);
+        assert_eq!(ui.notifications.get(&1).unwrap().summary, "one-new");
+    }
+
+    #[test]
+    fn close_removes_notification() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::ClosedByCall,
+        });
+
+        assert!(ui.notifications.is_empty());
+    }
+
+    #[test]
+    fn closing_last_notification_resets_stack_output_policy() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::CompositorChosen);
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::ClosedByCall,
+        });
+
+        assert!(ui.windows.is_empty());
+        assert_eq!(ui.stack_output_policy, None);
+    }
+
+    #[test]
+    fn format_string_substitutes_placeholders() {
+        let n = UiNotification {
+            id: 9,
+            app_name: "mail".to_string(),
+            header_name: "mail".to_string(),
+            origin: None,
+            url: None,
+            desktop_entry: None,
+            sandbox_app_id: None,
+            app_icon: String::new(),
+            summary: "new message".to_string(),
+            body: "hello".to_string(),
+            urgency: Urgency::Critical,
+            category: None,
+            actions: vec![],
+            action_icons: false,
+            resident: false,
+            progress: None,
+            position: None,
+            sound: SoundHint::default(),
+            timeout_clamp: TimeoutClamp::default(),
+            requested_timeout_ms: -1,
+            timeout_ms: None,
+            created_at: Instant::now(),
+            expires_at: None,
+        };
+
+        let rendered = render_format("{id} {app_name} {summary} {body} {urgency}", &n);
+        assert_eq!(rendered, "9 mail new message hello critical");
+    }
+
+    #[test]
+    fn popup_namespaces_follow_the_template() {
+        assert_eq!(
+            popup_namespace(
+                &UiSection::default().namespace_template,
+                "Signal",
+                Urgency::Low
+            ),
+            "wispd",
+            "the default keeps the static namespace"
+        );
+        for (urgency, expected) in [
+            (Urgency::Low, "wispd-low"),
+            (Urgency::Normal, "wispd-normal"),
+            (Urgency::Critical, "wispd-critical"),
+        ] {
+            assert_eq!(
+                popup_namespace("wispd-{urgency}", "mail", urgency),
+                expected
+            );
+        }
+        assert_eq!(
+            popup_namespace("wispd-{app}-{urgency}", "Signal Desktop", Urgency::Critical),
+            "wispd-signal-desktop-critical"
+        );
+        assert_eq!(popup_namespace("{app}", "", Urgency::Normal), "unknown");
+        assert_eq!(popup_namespace("  ", "mail", Urgency::Normal), "wispd");
+    }
+
+    #[test]
+    fn kde_hints_name_the_header_and_add_a_url_row() {
+        let mut hints = NotificationHints::default();
+        hints.desktop_entry = Some("org.kde.kdeconnect.daemon".to_string());
+        hints.display_app_name = Some("KDE Connect".to_string());
+        hints.origin_name = Some("Pixel 8".to_string());
+        hints.urls = vec!["file:///tmp/voicemail.ogg".to_string()];
+        let notification = Notification {
+            app_name: "kdeconnectd".to_string(),
+            summary: "Missed call".to_string(),
+            hints,
+            ..Notification::default()
+        };
+        let n = to_ui_notification(1, notification, None, TimeoutClamp::default());
+        assert_eq!(n.header_name, "KDE Connect");
+        assert_eq!(n.app_name, "kdeconnectd", "mutes still key on app_name");
+        assert_eq!(header_text(&n), "KDE Connect Missed call via Pixel 8");
+        assert_eq!(n.url.as_deref(), Some("file:///tmp/voicemail.ogg"));
+
+        let mut metrics = TextMetricsCache::default();
+        let shown = UiSection {
+            show_kde_urls: true,
+            height: 1,
+            ..UiSection::default()
+        };
+        let without_row = UiSection {
+            height: 1,
+            ..UiSection::default()
+        };
+        assert!(
+            estimate_popup_height(&shown, &n, &mut metrics)
+                > estimate_popup_height(&without_row, &n, &mut metrics)
+        );
+
+        let mut hints = NotificationHints::default();
+        hints.desktop_entry = Some("org.gnome.Nautilus".to_string());
+        let plain = to_ui_notification(
+            2,
+            Notification {
+                hints,
+                ..Notification::default()
+            },
+            None,
+            TimeoutClamp::default(),
+        );
+        assert_eq!(plain.header_name, "Nautilus");
+    }
+
+    #[test]
+    fn ellipsize_label_is_grapheme_aware() {
+        assert_eq!(ellipsize_label("Open", 10), "Open");
+        assert_eq!(ellipsize_label("Open", 4), "Open");
+        assert_eq!(ellipsize_label("Open in browser", 6), "Open…");
+        assert_eq!(ellipsize_label("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}…");
+        assert_eq!(ellipsize_label("👍🏽👍🏽👍🏽", 2), "👍🏽…");
+    }
+
+    #[test]
+    fn long_action_label_is_capped_and_keeps_single_row_height() {
+        let ui = UiSection::default();
+        let mut n = UiNotification {
+            id: 1,
+            app_name: "app".to_string(),
+            header_name: "app".to_string(),
+            origin: None,
+            url: None,
+            desktop_entry: None,
+            sandbox_app_id: None,
+            app_icon: String::new(),
+            summary: "summary".to_string(),
+            body: String::new(),
+            urgency: Urgency::Normal,
+            category: None,
+            actions: vec![UiAction {
+                key: "open".to_string(),
+                label: "Open".to_string(),
+                mnemonic: None,
+            }],
+            action_icons: false,
+            resident: false,
+            progress: None,
+            position: None,
+            sound: SoundHint::default(),
+            timeout_clamp: TimeoutClamp::default(),
+            requested_timeout_ms: -1,
+            timeout_ms: None,
+            created_at: Instant::now(),
+            expires_at: None,
+        };
+        let short_height = estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0));
+
+        let long_label = "x".repeat(200);
+        n.actions[0].label = long_label.clone();
+        assert_eq!(
+            estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0)),
+            short_height
+        );
+
+        let budget = action_label_char_budget(&ui, false);
+        assert_eq!(budget, ui.buttons.max_label_chars);
+        let shown = ellipsize_label(&long_label, budget);
+        assert_eq!(shown.chars().count(), budget);
+
+        let narrow = UiSection {
+            buttons: ButtonStyleConfig {
+                max_width: 0.25,
+                ..ButtonStyleConfig::default()
+            },
+            ..UiSection::default()
+        };
+        let narrow_budget = action_label_char_budget(&narrow, false);
+        assert!(narrow_budget < budget);
+        let char_width = ui.font_size as f32 * 0.54;
+        let label_px = narrow_budget as f32 * char_width + ACTION_BUTTON_H_PADDING;
+        assert!(label_px <= content_width_px(&narrow, false) * 0.25);
+    }
+
+    #[test]
+    fn timeout_progress_height_estimate_counts_bar_gaps_and_inset() {
+        let mut ui = UiSection {
+            height: 1,
+            ..UiSection::default()
+        };
+        let mut n = UiNotification {
+            id: 1,
+            app_name: "mail".to_string(),
+            header_name: "mail".to_string(),
+            origin: None,
+            url: None,
+            desktop_entry: None,
+            sandbox_app_id: None,
+            app_icon: String::new(),
+            summary: "New message".to_string(),
+            body: String::new(),
+            urgency: Urgency::Normal,
+            category: None,
+            actions: vec![],
+            action_icons: false,
+            resident: false,
+            progress: None,
+            position: None,
+            sound: SoundHint::default(),
+            timeout_clamp: TimeoutClamp::default(),
+            requested_timeout_ms: -1,
+            timeout_ms: None,
+            created_at: Instant::now(),
+            expires_at: None,
+        };
+        let untimed = estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0));
+
+        // Default: 3 px bar plus a 5 px gap (half the 10 px padding) on each side.
+        n.timeout_ms = Some(5000);
+        assert_eq!(
+            estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0)),
+            untimed + 3 + 2 * 5
+        );
+
+        ui.timeout_progress.inset = 4;
+        ui.timeout_progress.gap = Some(2);
+        ui.timeout_progress.height = 6;
+        assert_eq!(
+            estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0)),
+            untimed + 6 + 2 * 2 + 4
+        );
+
+        ui.show_timeout_progress = false;
+        assert_eq!(
+            estimate_popup_height(&ui, &n, &mut TextMetricsCache::new(0)),
+            untimed
+        );
+    }
+
+    #[test]
+    fn category_accent_narrows_the_header_line() {
+        let ui = UiSection::default();
+        let mut n = UiNotification {
+            id: 1,
+            app_name: "mail".to_string(),
+            header_name: "mail".to_string(),
+            origin: None,
+            url: None,
+            desktop_entry: None,
+            sandbox_app_id: None,
+            app_icon: String::new(),
+            summary: String::new(),
+            body: String::new(),
+            urgency: Urgency::Normal,
+            category: None,
+            actions: vec![],
+            action_icons: false,
+            resident: false,
+            progress: None,
+            position: None,
+            sound: SoundHint::default(),
+            timeout_clamp: TimeoutClamp::default(),
+            requested_timeout_ms: -1,
+            timeout_ms: None,
+            created_at: Instant::now(),
+            expires_at: None,
+        };
+        let height = |n: &UiNotification, ui: &UiSection| {
+            estimate_popup_height(ui, n, &mut TextMetricsCache::new(0))
+        };
+
+        // Grow the summary until the accent alone pushes it onto a second line.
+        let wraps_with_accent = (1..200).find(|len| {
+            n.summary = "x".repeat(*len);
+            n.category = None;
+            let plain = height(&n, &ui);
+            n.category = Some("email.arrived".to_string());
+            let accented = height(&n, &ui);
+            assert!(accented >= plain);
+            accented > plain
+        });
+        assert!(wraps_with_accent.is_some());
+
+        let mut disabled = UiSection::default();
+        disabled.category_icons.enabled = false;
+        n.category = None;
+        let plain = height(&n, &disabled);
+        n.category = Some("email.arrived".to_string());
+        assert_eq!(height(&n, &disabled), plain);
+
+        n.category = Some("presence.online".to_string());
+        assert_eq!(height(&n, &ui), plain, "unmapped categories add nothing");
+    }
+
+    #[test]
+    fn cached_height_estimates_match_uncached_ones() {
+        let ui = UiSection::default();
+        let long_body = "Lorem ipsum dolor sit amet ".repeat(20);
+        let stack: Vec<UiNotification> = ["", "short", &long_body, "multi\nline\n\nbody"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, body)| UiNotification {
+                id: i as u32,
+                app_name: "mail".to_string(),
+                header_name: "mail".to_string(),
+                origin: None,
+                url: None,
+                desktop_entry: None,
+                sandbox_app_id: None,
+                app_icon: String::new(),
+                summary: format!("message {i}"),
+                body: body.to_string(),
+                urgency: Urgency::Normal,
+                category: None,
+                actions: vec![],
+                action_icons: false,
+                resident: false,
+                progress: None,
+                position: None,
+                sound: SoundHint::default(),
+                timeout_clamp: TimeoutClamp::default(),
+                requested_timeout_ms: -1,
+                timeout_ms: None,
+                created_at: Instant::now(),
+                expires_at: None,
+            })
+            .collect();
+        let uncached: Vec<u32> = stack
+            .iter()
+            .map(|n| estimate_popup_height(&ui, n, &mut TextMetricsCache::new(0)))
+            .collect();
+
+        let mut cache = TextMetricsCache::default();
+        for _relayout in 0..2 {
+            let cached: Vec<u32> = stack
+                .iter()
+                .map(|n| estimate_popup_height(&ui, n, &mut cache))
+                .collect();
+            assert_eq!(cached, uncached);
+        }
+        // Header and body of each popup: measured once, then served from the cache.
+        let measured = 2 * stack.len() as u64;
+        assert_eq!(
+            (cache.stats().misses, cache.stats().hits),
+            (measured, measured)
+        );
+    }
+
+    #[test]
+    fn a_wider_fallback_font_estimates_taller_popups() {
+        let ui = UiSection::default();
+        let n = UiNotification {
+            id: 1,
+            app_name: "mail".to_string(),
+            header_name: "mail".to_string(),
+            origin: None,
+            url: None,
+            desktop_entry: None,
+            sandbox_app_id: None,
+            app_icon: String::new(),
+            summary: "wide".to_string(),
+            body: "Lorem ipsum dolor sit amet ".repeat(20),
+            urgency: Urgency::Normal,
+            category: None,
+            actions: vec![],
+            action_icons: false,
+            resident: false,
+            progress: None,
+            position: None,
+            sound: SoundHint::default(),
+            timeout_clamp: TimeoutClamp::default(),
+            requested_timeout_ms: -1,
+            timeout_ms: None,
+            created_at: Instant::now(),
+            expires_at: None,
+        };
+        let default = estimate_popup_height(&ui, &n, &mut TextMetricsCache::default());
+        let mut fallback = TextMetricsCache::default();
+        fallback.set_char_width_em(Some(0.7));
+        assert!(estimate_popup_height(&ui, &n, &mut fallback) > default);
+    }
+
+    #[test]
+    fn replacement_drops_the_old_text_metrics() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "downloading 10%"));
+        ui.popup_height_for_id(1);
+        assert_eq!(ui.text_metrics.borrow().stats().entries, 2);
+
+        let NotificationEvent::Received { notification, .. } = sample(1, "downloading 20%") else {
+            unreachable!()
+        };
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: notification.clone(),
+            current: notification,
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+        ui.popup_height_for_id(1);
+        assert_eq!(
+            ui.text_metrics.borrow().stats().entries,
+            2,
+            "only the current header and body stay cached"
+        );
+    }
+
+    #[test]
+    fn wrapped_line_count_wraps_long_words() {
+        assert_eq!(wrapped_line_count("abcdefghij", 4), 3);
+        assert_eq!(wrap_breaks("abcdefghij", 4), [4, 8]);
+        assert_eq!(wrap_breaks("ééé", 2), [4], "between characters");
+    }
+
+    #[test]
+    fn wrapped_line_count_wraps_words_with_spaces() {
+        assert_eq!(wrapped_line_count("one two three four", 7), 3);
+        assert_eq!(wrap_breaks("one two three four", 7), [8, 14]);
+    }
+
+    #[test]
+    fn newlines_tabs_and_crlf_are_measured_as_drawn() {
+        assert_eq!(
+            normalize_whitespace("a\tbc\td\r\ne\rf", 4),
+            "a   bc  d\ne\nf"
+        );
+        assert!(matches!(
+            normalize_whitespace("plain\ntext", 4),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            wrapped_line_count("  ab  cd", 8),
+            1,
+            "spaces keep their width"
+        );
+        assert_eq!(wrapped_line_count("  ab  cd", 7), 2);
+
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let line_height = (ui.ui.font_size as f32 * 1.30).ceil() as u32;
+        let cases = [
+            (
+                1,
+                "CI",
+                "Build failed\nin 3 jobs\nsee log\nretried twice\nowner: ada",
+                "",
+                5,
+            ),
+            (
+                2,
+                "",
+                "",
+                "name\tstatus\ncargo\tok\nclippy\tfailed\nfmt\tok\ndoc\tok",
+                5,
+            ),
+            (3, "", "Line one\r\nline two", "first\r\nsecond\r\nthird", 5),
+        ];
+        for (id, app_name, summary, body, lines) in cases {
+            let _ = ui.apply_event(NotificationEvent::Received {
+                id,
+                notification: Box::new(Notification {
+                    app_name: app_name.to_string(),
+                    summary: summary.to_string(),
+                    body: body.to_string(),
+                    ..Notification::default()
+                }),
+                silent: false,
+                remaining_ms: None,
+            });
+            let n = &ui.notifications[&id];
+            assert!(!n.summary.contains(['\r', '\t']) && !n.body.contains(['\r', '\t']));
+            let estimate = estimate_popup_height(&ui.ui, n, &mut TextMetricsCache::new(0));
+            assert!(
+                estimate >= lines * line_height,
+                "popup {id}: {estimate}px for {lines} lines"
+            );
+        }
+        assert_eq!(
+            ui.notifications[&2].body,
+            "name    status\ncargo   ok\nclippy  failed\nfmt     ok\ndoc     ok"
+        );
+    }
+
+    #[test]
+    fn resolve_icon_path_supports_file_uri() {
+        assert_eq!(
+            resolve_icon_path("file:///tmp/icon.png"),
+            Some(PathBuf::from("/tmp/icon.png"))
+        );
+    }
+
+    #[test]
+    fn popups_draw_the_image_path_before_app_icon_and_skip_inline_pixels() {
+        let mut notification = Notification {
+            app_icon: "mail-unread".to_string(),
+            ..Notification::default()
+        };
+        notification.hints.image_path = Some("file:///tmp/art.png".to_string());
+        notification.hints.image = Some(wisp_types::ImageData {
+            width: 1,
+            height: 1,
+            rowstride: 3,
+            has_alpha: false,
+            channels: 3,
+            data: vec![0; 3],
+        });
+        assert_eq!(popup_icon(&notification), "/tmp/art.png");
+
+        notification.hints.image_path = None;
+        let ui_notification = to_ui_notification(1, notification, None, TimeoutClamp::default());
+        assert_eq!(ui_notification.app_icon, "mail-unread");
+    }
+
+    #[test]
+    fn renderable_icon_path_requires_existing_file() {
+        assert!(renderable_icon_path(true, "kitty").is_none());
+    }
+
+    #[test]
+    fn icon_height_is_zero_when_icon_is_not_renderable() {
+        let ui = UiSection {
+            show_icons: true,
+            max_icon_size: 32,
+            ..UiSection::default()
+        };
+
+        assert_eq!(icon_height_px(&ui, "kitty"), 0);
+    }
+
+    #[test]
+    fn empty_action_labels_are_filtered_from_ui() {
+        let ui_notification = to_ui_notification(
+            1,
+            Notification {
+                actions: vec![
+                    NotificationAction {
+                        key: "default".to_string(),
+                        label: " ".to_string(),
+                    },
+                    NotificationAction {
+                        key: "open".to_string(),
+                        label: "Open".to_string(),
+                    },
+                ],
+                ..Notification::default()
+            },
+            None,
+            TimeoutClamp::default(),
+        );
+
+        assert_eq!(ui_notification.actions.len(), 1);
+        assert_eq!(ui_notification.actions[0].key, "open");
+        assert_eq!(ui_notification.actions[0].label, "Open");
+    }
+
+    #[test]
+    fn ui_font_can_be_configured_via_font_alias() {
+        let cfg: AppConfig = toml::from_str("[ui]\nfont = \"JetBrains Mono\"\n").unwrap();
+        assert_eq!(cfg.ui.font_family, "JetBrains Mono");
+    }
+
+    #[test]
+    fn source_dedup_defaults_what_the_table_leaves_out() {
+        let cfg: AppConfig = toml::from_str("[source.dedup]\nwindow = \"2m\"\n").unwrap();
+        let dedup = cfg.source.dedup.map(DedupSection::dedup);
+        assert_eq!(
+            dedup,
+            Some(Dedup {
+                window: Duration::from_secs(120),
+                match_body: true,
+            })
+        );
+        assert_eq!(
+            AppConfig::default().source.dedup.map(DedupSection::dedup),
+            None
+        );
+    }
+
+    #[test]
+    fn timeout_progress_accepts_the_table_and_the_old_keys() {
+        let cfg: AppConfig = toml::from_str(
+            "[ui.timeout_progress]\nheight = 4\ninset = 6\ngap = 3\ntrack_color = \"#00000040\"\ncorner_radius = 2.0\n",
+        )
+        .unwrap();
+        let progress = &cfg.ui.timeout_progress;
+        assert_eq!(progress.height, 4);
+        assert_eq!(progress.inset, 6);
+        assert_eq!(progress.gap, Some(3));
+        assert_eq!(progress.track_color, "#00000040");
+        assert_eq!(progress.corner_radius, 2.0);
+        assert!(!progress.is_top());
+        assert!(validate_app_config(&cfg).is_ok());
+
+        let cfg: AppConfig = toml::from_str(
+            "[ui]\ntimeout_progress_height = 6\ntimeout_progress_position = \"top\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.ui.timeout_progress.height, 6);
+        assert!(cfg.ui.timeout_progress.is_top());
+        assert_eq!(cfg.ui.timeout_progress_height, None);
+        assert_eq!(cfg.ui.timeout_progress_position, None);
+
+        let cfg: AppConfig =
+            toml::from_str("[ui]\ntimeout_progress_position = \"middle\"\n").unwrap();
+        assert!(validate_app_config(&cfg).is_err());
+        let cfg: AppConfig =
+            toml::from_str("[ui.timeout_progress]\ntrack_color = \"white\"\n").unwrap();
+        assert!(validate_app_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn privacy_apps_accept_globs_and_tables() {
+        let cfg: AppConfig = toml::from_str(
+            "[ui]\nprivacy_apps = [\"Signal\", { app = \"KeePass*\", redact = \"all\" }]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.ui.privacy_apps,
+            [
+                PrivacyRule {
+                    app: "Signal".to_string(),
+                    redact: privacy::Redaction::Body,
+                },
+                PrivacyRule {
+                    app: "KeePass*".to_string(),
+                    redact: privacy::Redaction::All,
+                },
+            ]
+        );
+        assert_eq!(cfg.ui.redaction_placeholder, "New message");
+    }
+
+    #[test]
+    fn button_font_can_be_configured_via_font_alias() {
+        let cfg: AppConfig =
+            toml::from_str("[ui.buttons]\nfont = \"Recursive Mono Casual Static\"\n").unwrap();
+        assert_eq!(
+            cfg.ui.buttons.font_family.as_deref(),
+            Some("Recursive Mono Casual Static")
+        );
+    }
+
+    #[test]
+    fn ui_output_defaults_to_focused() {
+        assert_eq!(AppConfig::default().ui.output, "focused");
+    }
+
+    #[test]
+    fn validate_app_config_rejects_invalid_anchor() {
+        let mut cfg = AppConfig::default();
+        cfg.ui.anchor = "middle-right".to_string();
+        assert!(validate_app_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn validate_app_config_rejects_invalid_color() {
+        let mut cfg = AppConfig::default();
+        cfg.ui.colors.background = "nope".to_string();
+        assert!(validate_app_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn validate_app_config_rejects_overlong_animation() {
+        let mut cfg = AppConfig::default();
+        cfg.ui.animation.duration_ms = MAX_ANIMATION_DURATION_MS + 1;
+        assert!(validate_app_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn output_option_parses_focused_with_empty_stack() {
+        assert_eq!(
+            output_option_from_config("focused", None),
+            (
+                OutputOption::CompositorDefault,
+                Some(StackOutputPolicy::CompositorChosen)
+            )
+        );
+    }
+
+    #[test]
+    fn output_option_parses_last_output() {
+        assert_eq!(
+            output_option_from_config("last-output", None),
+            (
+                OutputOption::LastOutput,
+                Some(StackOutputPolicy::CompositorChosen)
+            )
+        );
+    }
+
+    #[test]
+    fn output_option_parses_output_name() {
+        assert_eq!(
+            output_option_from_config("DP-1", None),
+            (
+                OutputOption::OutputName("DP-1".to_string()),
+                Some(StackOutputPolicy::Named("DP-1".to_string()))
+            )
+        );
+    }
+
+    #[test]
+    fn output_option_uses_focused_command_when_provided() {
+        assert_eq!(
+            output_option_from_config("focused", Some("printf 'DP-3\\n'")),
+            (
+                OutputOption::OutputName("DP-3".to_string()),
+                Some(StackOutputPolicy::Named("DP-3".to_string()))
+            )
+        );
+    }
+
+    #[test]
+    fn effective_timeout_uses_default_for_negative() {
+        assert_eq!(effective_timeout_ms(-1, Some(5_000)), Some(5_000));
+    }
+
+    #[test]
+    fn effective_timeout_disables_for_zero() {
+        assert_eq!(effective_timeout_ms(0, Some(5_000)), None);
+    }
+
+    #[test]
+    fn ui_timeouts_are_clamped_like_the_source() {
+        let ui_cfg = UiSection {
+            min_timeout_ms: Some(3_000),
+            max_timeout_ms: Some(10_000),
+            ..UiSection::default()
+        };
+        let clamped = |timeout_ms: i32, urgency: Urgency| {
+            to_ui_notification(
+                1,
+                Notification {
+                    timeout_ms,
+                    urgency,
+                    ..Notification::default()
+                },
+                None,
+                ui_cfg.timeout_clamp(),
+            )
+            .timeout_ms
+        };
+
+        assert_eq!(clamped(1_800_000, Urgency::Normal), Some(10_000));
+        assert_eq!(clamped(0, Urgency::Low), Some(10_000));
+        assert_eq!(clamped(500, Urgency::Normal), Some(3_000));
+        assert_eq!(clamped(0, Urgency::Critical), None);
+    }
+
+    #[test]
+    fn rule_timeouts_bound_matching_popups_and_reach_the_source() {
+        let rules: toml::Table = toml::from_str(
+            r#"
+            [[rules]]
+            match.app_name = "Slack"
+            match.body = "glob:*standup*"
+            max_timeout_ms = 4000
+            timeout_clamp_exempt_critical = false
+            "#,
+        )
+        .unwrap();
+        let ui_cfg = UiSection {
+            max_timeout_ms: Some(10_000),
+            rules: rules["rules"].clone().try_into().unwrap(),
+            ..UiSection::default()
+        };
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(ui_cfg.clone());
+        for (id, app_name, body, urgency) in [
+            (1, "Slack", "standup in 5", Urgency::Normal),
+            (2, "Slack", "standup now", Urgency::Critical),
+            (3, "Slack", "lunch?", Urgency::Normal),
+        ] {
+            let _ = ui.apply_event(NotificationEvent::Received {
+                id,
+                notification: Box::new(Notification {
+                    app_name: app_name.to_string(),
+                    body: body.to_string(),
+                    urgency,
+                    timeout_ms: 0,
+                    ..Notification::default()
+                }),
+                silent: false,
+                remaining_ms: None,
+            });
+        }
+        assert_eq!(ui.notifications[&1].timeout_ms, Some(4_000));
+        assert_eq!(
+            ui.notifications[&2].timeout_ms,
+            Some(4_000),
+            "the rule drops the critical exemption"
+        );
+        assert_eq!(ui.notifications[&3].timeout_ms, Some(10_000));
+
+        while cmd_rx.try_recv().is_ok() {}
+        let _ = ui.apply_config(AppConfig {
+            ui: ui_cfg.clone(),
+            ..AppConfig::default()
+        });
+        let SourceCommand::ReloadConfig { timeout_rules, .. } = cmd_rx.try_recv().unwrap() else {
+            panic!("expected a reload command");
+        };
+        assert_eq!(timeout_rules, ui_cfg.timeout_rules());
+        assert_eq!(timeout_rules[0].max_ms, Some(4_000));
+    }
+
+    #[test]
+    fn critical_popups_keep_no_timeout_when_they_never_expire() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            critical_never_expires: true,
+            max_timeout_ms: Some(5_000),
+            timeout_clamp_exempt_critical: false,
+            ..UiSection::default()
+        });
+        let critical = NotificationEvent::Received {
+            id: 1,
+            notification: Box::new(Notification {
+                summary: "disk full".to_string(),
+                urgency: Urgency::Critical,
+                timeout_ms: 1_000,
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        };
+        let _ = ui.apply_event(critical);
+        let _ = ui.apply_event(sample(2, "build done"));
+        assert_eq!(ui.notifications[&1].timeout_ms, None);
+        assert_eq!(ui.notifications[&2].timeout_ms, Some(1_000));
+
+        let _ = update(&mut ui, Message::PinClicked { id: 1 });
+        let _ = update(&mut ui, Message::PinClicked { id: 1 });
+        assert!(!ui.is_pinned(1));
+        assert_eq!(ui.notifications[&1].timeout_ms, None, "unpinning keeps it");
+
+        let mut cfg = AppConfig::default();
+        cfg.ui.critical_never_expires = true;
+        while cmd_rx.try_recv().is_ok() {}
+        let _ = ui.apply_config(cfg);
+        let SourceCommand::ReloadConfig {
+            critical_never_expires,
+            ..
+        } = cmd_rx.try_recv().unwrap()
+        else {
+            panic!("expected a reload command");
+        };
+        assert!(critical_never_expires);
+    }
+
+    #[test]
+    fn left_click_can_invoke_default_action() {
+        let ui_cfg = UiSection {
+            left_click_action: ClickAction::InvokeDefaultAction,
+            ..UiSection::default()
+        };
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(ui_cfg);
+
+        let _ = update(&mut ui, Message::NotificationLeftClick { id: 42 });
+
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeAction {
+                id: 42,
+                key: "default".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn privacy_apps_never_reach_ui_state_but_keep_their_click_action() {
+        let ui_cfg = UiSection {
+            privacy_apps: vec!["signal*".parse().unwrap(), "KeePassXC:all".parse().unwrap()],
+            redaction_placeholder: "Hidden".to_string(),
+            right_click_action: ClickAction::InvokeDefaultAction,
+            ..UiSection::default()
+        };
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(ui_cfg);
+        let secret = |id, app_name: &str| NotificationEvent::Received {
+            id,
+            notification: Box::new(Notification {
+                app_name: app_name.to_string(),
+                summary: "Ada".to_string(),
+                body: "the door code is 4711".to_string(),
+                ..Default::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        };
+        let _ = ui.apply_event(secret(1, "Signal"));
+        let _ = ui.apply_event(secret(2, "KeePassXC"));
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: Box::new(Notification::default()),
+            current: Box::new(Notification {
+                app_name: "Signal".to_string(),
+                summary: "Ada".to_string(),
+                body: "actually it is 0815".to_string(),
+                ..Default::default()
+            }),
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+
+        assert_eq!(
+            (
+                ui.notifications[&1].summary.as_str(),
+                ui.notifications[&1].body.as_str()
+            ),
+            ("Ada", "Hidden")
+        );
+        assert_eq!(
+            (
+                ui.notifications[&2].summary.as_str(),
+                ui.notifications[&2].body.as_str()
+            ),
+            ("Hidden", "")
+        );
+        let dump =
+            serde_json::to_string(&ui.debug_dump(false, true, None)).expect("dump serializes");
+        assert!(!dump.contains("4711") && !dump.contains("0815"));
+
+        let _ = update(&mut ui, Message::NotificationRightClick { id: 1 });
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeAction {
+                id: 1,
+                key: "default".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn copy_click_copies_stripped_body_and_flashes_confirmation() {
+        let path = temp_dir("copy-click").join("copied");
+        let ui_cfg = UiSection {
+            right_click_action: ClickAction::CopyBody,
+            clipboard_command: Some(format!("cat > '{}'", path.display())),
+            ..UiSection::default()
+        };
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(ui_cfg);
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id: 1,
+            notification: Box::new(Notification {
+                app_name: "auth".to_string(),
+                summary: "Sign-in code".to_string(),
+                body: "Your code is <b>123456</b>".to_string(),
+                ..Default::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        });
+
+        let _ = update(&mut ui, Message::NotificationRightClick { id: 1 });
+
+        assert_eq!(
+            fs::read_to_string(&path).expect("copied"),
+            "Your code is 123456"
+        );
+        assert!(ui.is_copied_flashing(1));
+        assert!(cmd_rx.try_recv().is_err(), "copy keeps the popup open");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn apply_config_updates_ui_and_source_runtime_values() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let mut cfg = AppConfig::default();
+        cfg.source.capabilities = vec!["body".to_string(), "actions".to_string()];
+        cfg.source.default_timeout_ms = Some(4_200);
+        cfg.ui.max_visible = 2;
+
+        let _ = ui.apply_config(cfg);
+
+        assert_eq!(ui.default_timeout_ms, Some(4_200));
+        assert_eq!(ui.ui.max_visible, 2);
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::ReloadConfig {
+                features: Features::from_capabilities(["body", "actions"]),
+                default_timeout_ms: Some(4_200),
+                clamp_timeout: TimeoutClamp::default(),
+                timeout_rules: Vec::new(),
+                dnd_schedule: Vec::new(),
+                history_ttl: None,
+                critical_never_expires: false,
+            }
+        );
+    }
+
+    #[test]
+    fn apply_config_pushes_features_the_ui_can_honour() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let mut cfg = AppConfig::default();
+        cfg.source.capabilities = vec!["body".to_string(), "icon-static".to_string()];
+        cfg.ui.show_icons = false;
+        let _ = ui.apply_config(cfg.clone());
+        let SourceCommand::ReloadConfig { features, .. } = cmd_rx.try_recv().unwrap() else {
+            panic!("expected reload command");
+        };
+        assert_eq!(features.capabilities(), vec!["body".to_string()]);
+
+        cfg.ui.show_icons = true;
+        cfg.ui.sound.enabled = true;
+        let _ = ui.apply_config(cfg);
+        let SourceCommand::ReloadConfig { features, .. } = cmd_rx.try_recv().unwrap() else {
+            panic!("expected reload command");
+        };
+        assert!(features.icon_static);
+        assert!(features.sound, "advertised while wispd plays sounds");
+    }
+
+    #[test]
+    fn apply_config_with_visible_notifications_preserves_sane_popup_order() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+
+        let mut cfg = AppConfig::default();
+        cfg.source.capabilities = vec!["body".to_string()];
+        cfg.ui.max_visible = 2;
+
+        let _ = ui.apply_config(cfg);
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 3);
+        assert_eq!(ui.windows[1].notification_id, 2);
+        assert!(ui.notifications.contains_key(&2));
+        assert!(ui.notifications.contains_key(&3));
+        assert!(!ui.notifications.contains_key(&1));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::ReloadConfig {
+                features: Features::from_capabilities(["body"]),
+                default_timeout_ms: None,
+                clamp_timeout: TimeoutClamp::default(),
+                timeout_rules: Vec::new(),
+                dnd_schedule: Vec::new(),
+                history_ttl: None,
+                critical_never_expires: false,
+            }
+        );
+    }
+
+    #[test]
+    fn apply_config_does_not_strand_windows_on_stale_outputs() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+
+        let mut cfg = AppConfig::default();
+        cfg.source.capabilities = vec!["body".to_string(), "actions".to_string()];
+        cfg.ui.output = "HDMI-A-1".to_string();
+
+        let _ = ui.apply_config(cfg);
+
+        let new_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+        assert_eq!(
+            ui.stack_output_policy,
+            Some(StackOutputPolicy::Named("DP-1".to_string()))
+        );
+        assert_eq!(new_window_ids, old_window_ids);
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::ReloadConfig {
+                features: Features::from_capabilities(["body", "actions"]),
+                default_timeout_ms: None,
+                clamp_timeout: TimeoutClamp::default(),
+                timeout_rules: Vec::new(),
+                dnd_schedule: Vec::new(),
+                history_ttl: None,
+                critical_never_expires: false,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_reload_keeps_current_state_and_emits_local_notification() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+        let old_next_local = ui.next_local_notification_id;
+
+        let _ = ui.apply_loaded_config(Err(anyhow!("invalid ui.anchor: nope")));
+
+        assert_eq!(
+            ui.stack_output_policy,
+            Some(StackOutputPolicy::Named("DP-1".to_string()))
+        );
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[1].notification_id, 1);
+        assert_eq!(ui.windows[1].window_id, old_window_ids[0]);
+        assert!(
+            ui.notifications.values().any(
+                |n| n.summary == "Config reload failed" && n.body.contains("invalid ui.anchor")
+            )
+        );
+        assert!(ui.next_local_notification_id < old_next_local);
+        assert!(
+            cmd_rx.try_recv().is_err(),
+            "invalid reload should not notify source thread"
+        );
+    }
+
+    #[test]
+    fn right_click_can_dismiss() {
+        let ui_cfg = UiSection {
+            right_click_action: ClickAction::Dismiss,
+            ..UiSection::default()
+        };
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(ui_cfg);
+
+        let _ = update(&mut ui, Message::NotificationRightClick { id: 11 });
+
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::Dismiss { id: 11 }
+        );
+    }
+
+    #[test]
+    fn output_removal_rebuilds_visible_windows_without_losing_order() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::CompositorChosen);
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+
+        let _ = update(
+            &mut ui,
+            Message::OutputHotplug(OutputHotplugEvent::Removed {
+                name: Some("DP-1".to_string()),
+            }),
+        );
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 2);
+        assert_eq!(ui.windows[1].notification_id, 1);
+        assert_ne!(ui.windows[0].window_id, old_window_ids[0]);
+        assert_ne!(ui.windows[1].window_id, old_window_ids[1]);
+    }
+
+    #[test]
+    fn unrelated_named_output_removal_keeps_existing_stack() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+
+        let _ = update(
+            &mut ui,
+            Message::OutputHotplug(OutputHotplugEvent::Removed {
+                name: Some("HDMI-A-1".to_string()),
+            }),
+        );
+
+        let new_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+        assert_eq!(new_window_ids, old_window_ids);
+        assert_eq!(
+            ui.stack_output_policy,
+            Some(StackOutputPolicy::Named("DP-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn window_closed_removes_notification_binding() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let window_id = ui.windows[0].window_id;
+
+        let _ = update(&mut ui, Message::WindowClosed(window_id));
+
+        assert!(ui.notifications.is_empty());
+        assert!(ui.windows.is_empty());
+    }
+
+    #[test]
+    fn closing_last_window_resets_stack_output_policy() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let window_id = ui.windows[0].window_id;
+
+        let _ = update(&mut ui, Message::WindowClosed(window_id));
+
+        assert!(ui.windows.is_empty());
+        assert_eq!(ui.stack_output_policy, None);
+    }
+
+    #[test]
+    fn max_visible_truncates_only_visible_popup_set() {
+        let ui_cfg = UiSection {
+            max_visible: 2,
+            ..UiSection::default()
+        };
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(ui_cfg);
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 3);
+        assert_eq!(ui.windows[1].notification_id, 2);
+        assert_eq!(ui.notifications.len(), 2);
+        assert!(!ui.notifications.contains_key(&1));
+    }
+
+    #[test]
+    fn closing_middle_notification_compacts_visible_stack() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 2,
+            reason: CloseReason::ClosedByCall,
+        });
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 3);
+        assert_eq!(ui.windows[1].notification_id, 1);
+        assert!(ui.notifications.contains_key(&1));
+        assert!(!ui.notifications.contains_key(&2));
+        assert!(ui.notifications.contains_key(&3));
+    }
+
+    #[test]
+    fn popup_order_remains_stable_across_burst() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        for (id, summary) in [(1, "one"), (2, "two"), (3, "three"), (4, "four")] {
+            let _ = ui.apply_event(sample(id, summary));
+        }
+
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn replacing_hidden_notification_does_not_corrupt_visible_ordering() {
+        let ui_cfg = UiSection {
+            max_visible: 2,
+            ..UiSection::default()
+        };
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(ui_cfg);
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 2,
+            previous: Box::new(Notification {
+                summary: String::from("two"),
+                ..Notification::default()
+            }),
+            current: Box::new(Notification {
+                summary: String::from("two-new"),
+                ..Notification::default()
+            }),
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+
+        assert_eq!(ui.windows.len(), 2);
+        assert_eq!(ui.windows[0].notification_id, 3);
+        assert_eq!(ui.windows[1].notification_id, 2);
+        assert_eq!(ui.notifications.get(&2).unwrap().summary, "two-new");
+    }
+
+    #[test]
+    fn later_notifications_stick_to_existing_stack_output_policy() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let initial_policy = ui.stack_output_policy.clone();
+        assert_eq!(initial_policy, Some(StackOutputPolicy::CompositorChosen));
+
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+
+        assert_eq!(ui.stack_output_policy, initial_policy);
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![3, 2, 1]);
+    }
+
+    fn round_robin_ui(outputs: &[&str]) -> UiSection {
+        UiSection {
+            output: "round-robin".to_string(),
+            outputs: outputs.iter().map(|name| name.to_string()).collect(),
+            ..UiSection::default()
+        }
+    }
+
+    fn output_of(ui: &WispdUi, id: u32) -> Option<&str> {
+        let binding = ui.windows.iter().find(|w| w.notification_id == id)?;
+        ui.window_outputs
+            .get(&binding.window_id)
+            .map(String::as_str)
+    }
+
+    #[test]
+    fn round_robin_cycles_notifications_through_configured_outputs() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(round_robin_ui(&["DP-1", "DP-2"]));
+
+        for id in 1..=3 {
+            let _ = ui.apply_event(sample(id, "hello"));
+        }
+
+        assert_eq!(output_of(&ui, 1), Some("DP-1"));
+        assert_eq!(output_of(&ui, 2), Some("DP-2"));
+        assert_eq!(output_of(&ui, 3), Some("DP-1"));
+        assert_eq!(ui.stack_output_policy, None);
+    }
+
+    #[test]
+    fn round_robin_follows_connected_outputs_without_a_list() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(round_robin_ui(&[]));
+        let _ = ui.apply_event(sample(1, "before any output"));
+        assert_eq!(output_of(&ui, 1), None);
+
+        for name in ["DP-1", "HDMI-A-1"] {
+            let _ = ui.handle_output_hotplug(OutputHotplugEvent::Added {
+                name: name.to_string(),
+                description: String::new(),
+                size: None,
+            });
+        }
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.apply_event(sample(3, "three"));
+        assert_ne!(output_of(&ui, 2), output_of(&ui, 3));
+
+        let _ = ui.handle_output_hotplug(OutputHotplugEvent::Removed {
+            name: Some("HDMI-A-1".to_string()),
+        });
+        assert!(
+            ui.window_outputs.values().all(|output| output == "DP-1"),
+            "popups on the removed output are reopened elsewhere"
+        );
+        assert_eq!(ui.windows.len(), 3);
+    }
+
+    #[test]
+    fn popups_that_do_not_fit_a_small_output_stay_queued() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "popup"));
+        let height = ui.popup_height_for_id(1);
+        let output_height = ui.base_margin().0 as u32 + 2 * height + u32::from(ui.ui.gap);
+        let _ = ui.handle_output_hotplug(OutputHotplugEvent::Added {
+            name: "eDP-1".to_string(),
+            description: String::new(),
+            size: Some((1280, output_height)),
+        });
+
+        let _ = ui.apply_event(sample(2, "popup"));
+        assert_eq!(visible_ids(&ui), vec![2, 1]);
+        let _ = ui.apply_event(sample(3, "popup"));
+        assert_eq!(visible_ids(&ui), vec![3, 2]);
+        assert_eq!(ui.queued, [1]);
+        assert!(ui.notifications.contains_key(&1), "queued, not dropped");
+
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 3,
+            reason: wisp_types::CloseReason::Expired,
+        });
+        assert_eq!(visible_ids(&ui), vec![2, 1]);
+        assert!(ui.queued.is_empty());
+
+        let _ = ui.handle_output_hotplug(OutputHotplugEvent::Removed {
+            name: Some("eDP-1".to_string()),
+        });
+        let _ = ui.apply_event(sample(5, "popup"));
+        let _ = ui.apply_event(sample(6, "popup"));
+        assert_eq!(
+            visible_ids(&ui),
+            vec![6, 5, 2, 1],
+            "unknown sizes are not clamped"
+        );
+    }
+
+    #[test]
+    fn round_robin_skips_listed_outputs_known_to_be_disconnected() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(round_robin_ui(&["DP-1", "DP-2"]));
+        let _ = ui.handle_output_hotplug(OutputHotplugEvent::Added {
+            name: "DP-2".to_string(),
+            description: String::new(),
+            size: None,
+        });
+
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+
+        assert_eq!(output_of(&ui, 1), Some("DP-2"));
+        assert_eq!(output_of(&ui, 2), Some("DP-2"));
+    }
+
+    #[test]
+    fn round_robin_stacks_keep_separate_offsets_per_output() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(round_robin_ui(&["DP-1", "DP-2"]));
+        for id in 1..=4 {
+            let _ = ui.apply_event(sample(id, "hello"));
+        }
+        let margin_of = |ui: &WispdUi, id: u32| {
+            let binding = ui.windows.iter().find(|w| w.notification_id == id).unwrap();
+            ui.placed_margins[&binding.window_id]
+        };
+        let slot = |ui: &WispdUi, above: u32| {
+            ui.base_margin().0 + ui.popup_height_for_id(above) as i32 + ui.ui.gap as i32
+        };
+
+        // Newest first: 3 and 4 top their outputs, 1 and 2 sit one slot below.
+        assert_eq!(margin_of(&ui, 4), ui.base_margin());
+        assert_eq!(margin_of(&ui, 3), ui.base_margin());
+        assert_eq!(margin_of(&ui, 2).0, slot(&ui, 4));
+        assert_eq!(margin_of(&ui, 1).0, slot(&ui, 3));
+
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 4,
+            reason: CloseReason::Dismissed,
+        });
+
+        assert_eq!(margin_of(&ui, 2), ui.base_margin());
+        assert_eq!(margin_of(&ui, 1).0, slot(&ui, 3), "DP-1 stack is untouched");
+    }
+
+    #[test]
+    fn pinned_popups_are_excluded_from_overflow_eviction() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            max_visible: 2,
+            ..UiSection::default()
+        });
+        let _ = ui.apply_event(sample(1, "meeting link"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = update(&mut ui, Message::PinClicked { id: 1 });
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::SetPinned {
+                id: 1,
+                pinned: true
+            }
+        );
+        assert!(!ui.activating.contains(&1), "pinning is not an activation");
+
+        let _ = ui.apply_event(sample(3, "three"));
+        let _ = ui.apply_event(sample(4, "four"));
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![4, 1]);
+
+        // The pin survives a reload that shrinks the stack.
+        let mut cfg = AppConfig::default();
+        cfg.ui.max_visible = 1;
+        let _ = ui.apply_config(cfg);
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![1]);
+        assert!(ui.is_pinned(1));
+        assert_eq!(ui.notifications[&1].timeout_ms, None);
+    }
+
+    /// Runs the next queued UI command against `source` and hands the reply back.
+    async fn relay_one_command(
+        ui: &mut WispdUi,
+        cmd_rx: &mut tokio_mpsc::UnboundedReceiver<SourceCommand>,
+        source: &WispSource,
+    ) {
+        let command = cmd_rx.try_recv().unwrap();
+        let reply = process_source_command(source, command).await.unwrap();
+        let _ = ui.handle_source_reply(reply);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pin_pauses_and_unpin_resumes_expiry_at_the_source() {
+        let (source, mut source_rx) =
+            WispSource::new(SourceConfig::builder().default_timeout_ms(50).build());
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        ui.default_timeout_ms = Some(50);
+        let notification = Notification {
+            app_name: "calendar".to_string(),
+            summary: "Standup".to_string(),
+            timeout_ms: 10,
+            ..Default::default()
+        };
+        let id = source.notify(notification, 0).await.unwrap();
+        let _ = ui.apply_event(source_rx.recv().await.unwrap());
+
+        let _ = update(&mut ui, Message::PinClicked { id });
+        relay_one_command(&mut ui, &mut cmd_rx, &source).await;
+        assert!(
+            ui.notifications.contains_key(&id),
+            "a pin reply keeps the popup"
+        );
+        assert_eq!(ui.timeout_progress_for(id), None);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(source.state().pinned, 1);
+        assert_eq!(source.state().count, 1);
+
+        let _ = update(&mut ui, Message::PinClicked { id });
+        relay_one_command(&mut ui, &mut cmd_rx, &source).await;
+        assert_eq!(ui.notifications[&id].timeout_ms, Some(50));
+        assert_eq!(source.state().pinned, 0);
+        tokio::time::sleep(Duration::from_millis(51)).await;
+        assert_eq!(source.state().count, 0);
+
+        let closed = source_rx.recv().await.unwrap();
+        assert_eq!(
+            closed,
+            NotificationEvent::Closed {
+                id,
+                reason: CloseReason::Expired
+            }
+        );
+        let _ = ui.apply_event(closed);
+        assert!(ui.windows.is_empty());
+        assert!(!ui.is_pinned(id));
+    }
+
+    #[tokio::test]
+    async fn stars_toggled_in_the_history_panel_reach_the_source() {
+        let (source, _source_rx) = WispSource::new(SourceConfig::default());
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let id = source.notify(Notification::default(), 0).await.unwrap();
+        source.close(id, CloseReason::Dismissed).await.unwrap();
+        let _ = ui.open_history_panel(None);
+        relay_one_command(&mut ui, &mut cmd_rx, &source).await;
+        assert!(!ui.history_panel.rows()[0].starred);
+
+        let _ = update(&mut ui, Message::HistoryPanel(PanelMessage::ToggleStar(id)));
+        relay_one_command(&mut ui, &mut cmd_rx, &source).await;
+        assert!(source.history()[0].starred);
+        assert!(
+            ui.history_panel.rows()[0].starred,
+            "the reply redraws the row"
+        );
+
+        let _ = update(&mut ui, Message::HistoryPanel(PanelMessage::ToggleStar(id)));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::SetHistoryStarred { id, starred: false }
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout_bar_follows_the_expiry_the_source_reports() {
+        let (source, mut source_rx) = WispSource::new(
+            SourceConfig::builder()
+                .clamp_timeout(TimeoutClamp {
+                    min_ms: Some(60_000),
+                    ..TimeoutClamp::default()
+                })
+                .build(),
+        );
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let brief = |summary: &str| Notification {
+            app_name: "build".to_string(),
+            summary: summary.to_string(),
+            timeout_ms: 10,
+            ..Notification::default()
+        };
+        let id = source.notify(brief("compiling"), 0).await.unwrap();
+        let _ = ui.apply_event(source_rx.recv().await.unwrap());
+
+        // The UI alone would run the bar out in 10ms; the source keeps it for a minute.
+        assert_eq!(ui.notifications[&id].timeout_ms, Some(60_000));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(ui.timeout_progress_for(id).unwrap() < 0.5);
+
+        source.notify(brief("linking"), id).await.unwrap();
+        let replaced = source_rx.recv().await.unwrap();
+        assert!(matches!(
+            replaced,
+            NotificationEvent::Replaced {
+                remaining_ms: Some(60_000),
+                ..
+            }
+        ));
+        let _ = ui.apply_event(replaced);
+        assert_eq!(ui.notifications[&id].timeout_ms, Some(60_000));
+        assert!(ui.notifications[&id].expires_at.is_some());
+        assert!(ui.timeout_progress_for(id).unwrap() < 0.5);
+    }
+
+    #[tokio::test]
+    async fn resident_popups_update_in_place_through_action_replace_action() {
+        let (source, mut source_rx) = WispSource::new(SourceConfig::default());
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let track = |title: &str| {
+            let mut notification = Notification {
+                app_name: "player".to_string(),
+                summary: title.to_string(),
+                actions: ["previous", "next"]
+                    .map(|key| NotificationAction {
+                        key: key.to_string(),
+                        label: key.to_string(),
+                    })
+                    .to_vec(),
+                ..Notification::default()
+            };
+            notification.hints.resident = Some(true);
+            notification
+        };
+        let id = source.notify(track("Track 1"), 0).await.unwrap();
+        let _ = ui.apply_event(source_rx.recv().await.unwrap());
+        let slot = (ui.windows[0].window_id, ui.windows[0].entered_at);
+
+        for (key, title) in [("next", "Track 2"), ("previous", "Track 1")] {
+            let _ = update(
+                &mut ui,
+                Message::ActionClicked {
+                    id,
+                    key: key.to_string(),
+                },
+            );
+            assert!(ui.activating.is_empty(), "resident actions stay clickable");
+            relay_one_command(&mut ui, &mut cmd_rx, &source).await;
+            assert!(ui.notifications.contains_key(&id), "the reply keeps it");
+            let invoked = source_rx.recv().await.unwrap();
+            assert!(matches!(invoked, NotificationEvent::ActionInvoked { .. }));
+            let _ = ui.apply_event(invoked);
+
+            // The player answers with the new track.
+            assert_eq!(source.notify(track(title), id).await.unwrap(), id);
+            let replaced = source_rx.recv().await.unwrap();
+            assert!(matches!(replaced, NotificationEvent::Replaced { .. }));
+            let _ = ui.apply_event(replaced);
+
+            assert_eq!(ui.notifications[&id].summary, title);
+            assert_eq!(ui.windows.len(), 1);
+            assert_eq!(
+                (ui.windows[0].window_id, ui.windows[0].entered_at),
+                slot,
+                "same window, no new entrance"
+            );
+            assert!(ui.activating.is_empty());
+        }
+        assert_eq!(source.state().count, 1);
+    }
+
+    #[test]
+    fn action_click_is_optimistic_and_removes_popup_on_success() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
+
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: 1,
+                key: "open".to_string(),
+            },
+        );
+
+        let command = SourceCommand::InvokeAction {
+            id: 1,
+            key: "open".to_string(),
+        };
+        assert!(ui.activating.contains(&1));
+        assert_eq!(ui.windows.len(), 1);
+        assert_eq!(cmd_rx.try_recv().unwrap(), command);
+
+        let _ = ui.handle_source_reply(SourceReply::Command {
+            command,
+            result: Ok(()),
+        });
+
+        assert!(ui.activating.is_empty());
+        assert!(ui.notifications.is_empty());
+        assert!(ui.windows.is_empty());
+
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::Dismissed,
+        });
+        assert!(ui.windows.is_empty());
+    }
+
+    #[test]
+    fn stale_action_flashes_error_and_refreshes_popup_from_snapshot() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
+
+        let command = SourceCommand::InvokeAction {
+            id: 1,
+            key: "stale".to_string(),
+        };
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: 1,
+                key: "stale".to_string(),
+            },
+        );
+        assert_eq!(cmd_rx.try_recv().unwrap(), command);
+
+        let _ = ui.handle_source_reply(SourceReply::Command {
+            command,
+            result: Err(CommandError::NotFound),
+        });
+
+        assert!(ui.activating.is_empty());
+        assert!(ui.is_error_flashing(1));
+        assert_eq!(ui.windows.len(), 1);
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Refresh { id: 1 });
+
+        let _ = ui.handle_source_reply(SourceReply::Snapshot {
+            id: 1,
+            notification: Some(Box::new(Notification {
+                summary: "one-replaced".to_string(),
+                ..Notification::default()
+            })),
+        });
+
+        assert_eq!(ui.windows.len(), 1);
+        assert_eq!(ui.notifications.get(&1).unwrap().summary, "one-replaced");
+    }
 
-    spawn_reload_signal_listener(reload_tx)?;
+    #[test]
+    fn dismiss_of_already_closed_notification_drops_popup_silently() {
+        let (mut ui, mut cmd_rx, reply_tx) = test_ui_with_replies(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
 
-    let source_runtime_cfg = match ready_rx.recv_timeout(Duration::from_secs(3)) {
-        Ok(Ok(cfg)) => cfg,
-        Ok(Err(err)) => return Err(anyhow!(err)),
-        Err(err) => return Err(anyhow!("source thread did not initialize in time: {err}")),
-    };
+        let _ = update(&mut ui, Message::DismissClicked { id: 1 });
+        assert!(ui.activating.contains(&1));
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Dismiss { id: 1 });
 
-    info!(
-        dbus_name = %source_runtime_cfg.dbus_name,
-        dbus_path = %source_runtime_cfg.dbus_path,
-        "wispd ui started"
-    );
+        reply_tx
+            .send(SourceReply::Command {
+                command: SourceCommand::Dismiss { id: 1 },
+                result: Err(CommandError::NotFound),
+            })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
 
-    let events = Arc::new(Mutex::new(ui_rx));
-    let reloads = Arc::new(Mutex::new(reload_rx));
-    let boot_events = Arc::clone(&events);
-    let boot_reloads = Arc::clone(&reloads);
-    let ui_cfg = app_cfg.ui.clone();
-    let ui_default_timeout_ms = app_cfg.source.default_timeout_ms;
-    let boot_cmd_tx = cmd_tx.clone();
-
-    let wayland_connection = Connection::connect_to_env()
-        .map_err(|err| anyhow!("failed to connect to wayland: {err}"))?;
-
-    let settings = Settings {
-        layer_settings: LayerShellSettings {
-            // Bootstrap surface kept minimal; real notification windows are opened dynamically.
-            anchor: Anchor::Top | Anchor::Left,
-            layer: Layer::Top,
-            exclusive_zone: 0,
-            margin: (0, 0, 0, 0),
-            size: Some((1, 1)),
-            keyboard_interactivity: KeyboardInteractivity::None,
-            ..Default::default()
-        },
-        with_connection: Some(wayland_connection.clone()),
-        ..Default::default()
-    };
+        assert!(ui.notifications.is_empty());
+        assert!(ui.windows.is_empty());
+        assert!(ui.error_flash.is_empty());
+        assert!(cmd_rx.try_recv().is_err(), "no refresh needed");
+    }
 
-    let subscription_connection = wayland_connection.clone();
+    #[test]
+    fn unknown_action_flashes_and_refreshes_popup() {
+        let (mut ui, mut cmd_rx, reply_tx) = test_ui_with_replies(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
 
-    let app = daemon(
-        move || {
-            WispdUi::new(
-                Arc::clone(&boot_events),
-                Arc::clone(&boot_reloads),
-                boot_cmd_tx.clone(),
-                ui_cfg.clone(),
-                ui_default_timeout_ms,
-            )
-        },
-        namespace,
-        update,
-        view,
-    )
-    .style(app_style)
-    .subscription(move |state| subscription(state, subscription_connection.clone()))
-    .settings(settings);
+        let command = SourceCommand::InvokeAction {
+            id: 1,
+            key: "stale".to_string(),
+        };
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: 1,
+                key: "stale".to_string(),
+            },
+        );
+        assert_eq!(cmd_rx.try_recv().unwrap(), command);
 
-    let default_hook = take_hook();
-    set_hook(Box::new(|_| {}));
-    let run_result = catch_unwind(AssertUnwindSafe(|| app.run()));
-    set_hook(default_hook);
+        reply_tx
+            .send(SourceReply::Command {
+                command,
+                result: Err(CommandError::UnknownAction),
+            })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
 
-    match run_result {
-        Ok(Ok(())) => Ok(()),
-        Ok(Err(err)) => Err(anyhow!("failed to run iced layer-shell app: {err}")),
-        Err(_) => Err(anyhow!(
-            "wispd ui panicked while creating layer-shell window. Make sure you are running inside a Wayland session and have Wayland runtime libraries available (e.g. `wayland`, `libxkbcommon`)."
-        )),
+        assert!(ui.activating.is_empty());
+        assert!(ui.is_error_flashing(1));
+        assert_eq!(ui.windows.len(), 1);
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Refresh { id: 1 });
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wisp_types::CloseReason;
+    #[test]
+    fn backend_failure_raises_a_critical_self_notification() {
+        let (mut ui, mut cmd_rx, reply_tx) = test_ui_with_replies(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
 
-    fn sample(id: u32, summary: &str) -> NotificationEvent {
-        NotificationEvent::Received {
-            id,
-            notification: Box::new(Notification {
-                app_name: String::from("app"),
-                app_icon: String::new(),
-                summary: summary.to_string(),
-                body: String::new(),
-                urgency: Urgency::Normal,
-                timeout_ms: 1000,
-                actions: vec![],
-                hints: Default::default(),
-            }),
-        }
-    }
+        let _ = update(&mut ui, Message::DismissClicked { id: 1 });
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Dismiss { id: 1 });
 
-    fn test_ui(
-        ui: UiSection,
-    ) -> (
-        WispdUi,
-        tokio_mpsc::UnboundedReceiver<SourceCommand>,
-        mpsc::Sender<()>,
-    ) {
-        let (_event_tx, event_rx) = mpsc::channel();
-        let (reload_tx, reload_rx) = mpsc::channel();
-        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
-        (
-            WispdUi::new(
-                Arc::new(Mutex::new(event_rx)),
-                Arc::new(Mutex::new(reload_rx)),
-                cmd_tx,
-                ui,
-                None,
-            ),
-            cmd_rx,
-            reload_tx,
-        )
+        reply_tx
+            .send(SourceReply::Command {
+                command: SourceCommand::Dismiss { id: 1 },
+                result: Err(CommandError::Backend("event channel closed".to_string())),
+            })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+
+        assert!(ui.activating.is_empty());
+        assert!(ui.is_error_flashing(1));
+        assert!(ui.notifications.contains_key(&1), "popup stays");
+        let (_, report) = ui
+            .notifications
+            .iter()
+            .find(|(id, _)| **id != 1)
+            .expect("self-notification");
+        assert_eq!(report.urgency, Urgency::Critical);
+        assert!(report.body.contains("event channel closed"));
+        assert!(cmd_rx.try_recv().is_err());
     }
 
     #[test]
-    fn newest_goes_to_front() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn resync_after_a_ui_restart_restores_live_notifications_newest_first() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let notifications = [3, 1, 2]
+            .into_iter()
+            .map(|id| {
+                let NotificationEvent::Received { notification, .. } = sample(id, "n") else {
+                    unreachable!()
+                };
+                (id, *notification)
+            })
+            .collect();
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.handle_source_reply(SourceReply::Resync {
+            notifications,
+            do_not_disturb: false,
+        });
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 2);
-        assert_eq!(ui.windows[1].notification_id, 1);
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![3, 2, 1]);
+        assert_eq!(ui.notifications.len(), 3);
     }
 
     #[test]
-    fn replacement_keeps_slot() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn lost_source_events_resync_the_popups_in_place() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        for (id, summary) in [(1, "closed meanwhile"), (2, "stale")] {
+            let _ = ui.apply_event(sample(id, summary));
+        }
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(NotificationEvent::Replaced {
-            id: 1,
-            previous: Box::new(Notification::default()),
-            current: Box::new(Notification {
-                summary: String::from("one-new"),
-                ..Notification::default()
-            }),
+        let _ = ui.apply_event(NotificationEvent::Overflowed { dropped: 3 });
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Resync);
+
+        let notifications = [(2, "current"), (4, "missed")]
+            .into_iter()
+            .map(|(id, summary)| {
+                let NotificationEvent::Received { notification, .. } = sample(id, summary) else {
+                    unreachable!()
+                };
+                (id, *notification)
+            })
+            .collect();
+        let _ = ui.handle_source_reply(SourceReply::Resync {
+            notifications,
+            do_not_disturb: false,
         });
 
-        assert_eq!(ui.windows[1].notification_id, 1);
-        assert_eq!(ui.notifications.get(&1).unwrap().summary, "one-new");
+        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
+        assert_eq!(visible, vec![4, 2]);
+        assert_eq!(ui.notifications[&2].summary, "current");
+        assert!(!ui.notifications.contains_key(&1));
     }
 
     #[test]
-    fn close_removes_notification() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
-
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(NotificationEvent::Closed {
-            id: 1,
-            reason: CloseReason::ClosedByCall,
+    fn mute_click_hides_app_until_undo() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            right_click_action: ClickAction::MuteApp,
+            ..UiSection::default()
         });
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
 
-        assert!(ui.notifications.is_empty());
+        let _ = update(&mut ui, Message::NotificationRightClick { id: 1 });
+
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::Dismiss { id: 1 });
+        assert!(ui.is_muted("app"));
+        assert!(!ui.notifications.contains_key(&2));
+        let (&offer_id, app) = ui.unmute_offers.iter().next().expect("undo offer shown");
+        assert_eq!(app, "app");
+        assert_eq!(ui.windows[0].notification_id, offer_id);
+
+        let _ = ui.apply_event(sample(3, "three"));
+        assert!(!ui.notifications.contains_key(&3));
+
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: offer_id,
+                key: UNMUTE_ACTION_KEY.to_string(),
+            },
+        );
+        assert!(!ui.is_muted("app"));
+        assert!(!ui.notifications.contains_key(&offer_id));
+        assert!(cmd_rx.try_recv().is_err(), "undo stays local");
+
+        let _ = ui.apply_event(sample(4, "four"));
+        assert!(ui.notifications.contains_key(&4));
     }
 
     #[test]
-    fn closing_last_notification_resets_stack_output_policy() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::CompositorChosen);
+    fn debug_dump_requests_share_one_source_snapshot() {
+        let (mut ui, mut cmd_rx, signal_tx) = test_ui(UiSection::default());
+
+        let (reply, mut first) = oneshot::channel();
+        signal_tx
+            .send(SignalRequest::DebugDump { reply: Some(reply) })
+            .unwrap();
+        signal_tx
+            .send(SignalRequest::DebugDump { reply: None })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        let (reply, mut second) = oneshot::channel();
+        signal_tx
+            .send(SignalRequest::DebugDump { reply: Some(reply) })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(SourceCommand::DebugSnapshot)
+        ));
+        assert!(cmd_rx.try_recv().is_err(), "asked the source once");
+        assert!(first.try_recv().is_err() && second.try_recv().is_err());
+        assert_eq!(ui.pending_dump.as_ref().map(Vec::len), Some(2));
+    }
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(NotificationEvent::Closed {
-            id: 1,
-            reason: CloseReason::ClosedByCall,
+    #[test]
+    fn mutes_are_listed_and_lifted_over_the_ui_interface() {
+        let (mut ui, _cmd_rx, signal_tx) = test_ui(UiSection {
+            right_click_action: ClickAction::MuteApp,
+            mute_duration_secs: 600,
+            ..UiSection::default()
         });
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = update(&mut ui, Message::NotificationRightClick { id: 1 });
+        let (&offer_id, _) = ui.unmute_offers.iter().next().expect("undo offer shown");
+
+        let (reply, mut listed) = oneshot::channel();
+        signal_tx.send(SignalRequest::ListMutes { reply }).unwrap();
+        let (reply, mut unknown) = oneshot::channel();
+        signal_tx
+            .send(SignalRequest::Unmute {
+                app_name: "other".to_string(),
+                reply,
+            })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        let listed = listed.try_recv().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].0, "app");
+        assert!((599..=600).contains(&listed[0].1), "{listed:?}");
+        assert!(!unknown.try_recv().unwrap());
+
+        let (reply, mut unmuted) = oneshot::channel();
+        signal_tx
+            .send(SignalRequest::Unmute {
+                app_name: "app".to_string(),
+                reply,
+            })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        assert!(unmuted.try_recv().unwrap());
+        assert!(!ui.is_muted("app"));
+        assert!(
+            !ui.notifications.contains_key(&offer_id),
+            "undo offer closed"
+        );
+        let (reply, mut listed) = oneshot::channel();
+        signal_tx.send(SignalRequest::ListMutes { reply }).unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        assert!(listed.try_recv().unwrap().is_empty());
+    }
 
-        assert!(ui.windows.is_empty());
-        assert_eq!(ui.stack_output_policy, None);
+    fn historic(id: u32, actions_enabled: bool) -> NotificationEvent {
+        let NotificationEvent::Received {
+            id, notification, ..
+        } = sample(id, "from history")
+        else {
+            unreachable!("sample builds a received event");
+        };
+        NotificationEvent::Historic {
+            id,
+            notification: Box::new(Notification {
+                actions: vec![NotificationAction {
+                    key: "open".to_string(),
+                    label: "Open".to_string(),
+                }],
+                ..*notification
+            }),
+            actions_enabled,
+            chain: ReplaceChain::default(),
+        }
     }
 
     #[test]
-    fn format_string_substitutes_placeholders() {
-        let n = UiNotification {
-            id: 9,
-            app_name: "mail".to_string(),
-            app_icon: String::new(),
-            summary: "new message".to_string(),
-            body: "hello".to_string(),
-            urgency: Urgency::Critical,
-            actions: vec![],
-            timeout_ms: None,
-            created_at: Instant::now(),
+    fn history_popups_of_replace_chains_name_their_updates() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let NotificationEvent::Historic {
+            id,
+            notification,
+            actions_enabled,
+            ..
+        } = historic(7, false)
+        else {
+            unreachable!("historic builds a historic event");
         };
+        let _ = ui.apply_event(NotificationEvent::Historic {
+            id,
+            notification: Box::new(Notification {
+                summary: "Download finished".to_string(),
+                ..*notification
+            }),
+            actions_enabled,
+            chain: ReplaceChain {
+                replace_count: 200,
+                intermediate_summaries: vec!["Downloading 99%".to_string()],
+                lifetime_ms: 185_000,
+            },
+        });
+        let _ = ui.apply_event(historic(8, false));
 
-        let rendered = render_format("{id} {app_name} {summary} {body} {urgency}", &n);
-        assert_eq!(rendered, "9 mail new message hello critical");
+        let mut summaries: Vec<&str> = ui
+            .notifications
+            .values()
+            .map(|n| n.summary.as_str())
+            .collect();
+        summaries.sort_unstable();
+        assert_eq!(
+            summaries,
+            ["Download finished (200 updates, 3m)", "from history"]
+        );
     }
 
     #[test]
-    fn wrapped_line_count_wraps_long_words() {
-        assert_eq!(wrapped_line_count("abcdefghij", 4), 3);
+    fn ui_counters_reach_the_metrics_registry() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "a"));
+        let _ = ui.apply_event(sample(2, "b"));
+        let _ = update(&mut ui, Message::AnimationFrame);
+
+        let snapshot = ui
+            .metrics
+            .snapshot(&wisp_source::SourceStats::default(), false);
+        assert_eq!(snapshot.popups_opened, 2);
+        assert_eq!(snapshot.relayouts, ui.relayouts);
+        assert_eq!(snapshot.frame_wakeups, 1);
+        assert_eq!(
+            snapshot.received, 0,
+            "events are counted on the source thread"
+        );
     }
 
     #[test]
-    fn wrapped_line_count_wraps_words_with_spaces() {
-        assert_eq!(wrapped_line_count("one two three four", 7), 3);
+    fn a_flooding_app_collapses_into_one_summary_that_opens_its_history() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            flood_threshold: 2,
+            ..UiSection::default()
+        });
+        for id in 1..=5 {
+            let _ = ui.apply_event(sample(id, "ping"));
+        }
+        let visible = visible_ids(&ui);
+        let summary_id = ui.floods.summary_ids().next().expect("summary popup");
+        assert!(ui.is_local(summary_id));
+        assert_eq!(visible.len(), 3);
+        assert!(
+            [1, 2, summary_id].iter().all(|id| visible.contains(id)),
+            "the first two keep their popups"
+        );
+        assert!(ui.notifications.contains_key(&5), "the rest are tracked");
+        assert_eq!(
+            ui.notifications[&summary_id].summary, "app: 3 new notifications",
+            "updated in place"
+        );
+        assert!(
+            cmd_rx.try_recv().is_err(),
+            "the summary is not sent to the source"
+        );
+
+        let _ = ui.apply_event(sample_with_urgency(6, Urgency::Critical));
+        assert!(
+            !ui.floods.is_collapsed(6),
+            "critical ones are never collapsed"
+        );
+        while cmd_rx.try_recv().is_ok() {}
+
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: summary_id,
+                key: "default".to_string(),
+            },
+        );
+        assert!(!ui.notifications.contains_key(&summary_id));
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::ListHistory);
+        assert!(ui.history_window.is_some());
+        assert_eq!(ui.history_panel.app(), Some("app"));
+
+        let _ = ui.apply_event(sample(7, "ping"));
+        let next = ui.floods.summary_ids().next().expect("a new summary");
+        assert_ne!(next, summary_id);
+        assert_eq!(ui.notifications[&next].summary, "app: 1 new notification");
     }
 
     #[test]
-    fn resolve_icon_path_supports_file_uri() {
+    fn the_history_panel_lists_the_history_and_sends_its_deletions() {
+        let (mut ui, mut cmd_rx, signal_tx) = test_ui(UiSection::default());
+        signal_tx.send(SignalRequest::ToggleHistoryPanel).unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        let window = ui.history_window.expect("panel opened");
+        assert_eq!(cmd_rx.try_recv().unwrap(), SourceCommand::ListHistory);
+
+        let entry = |id| HistoryEntry {
+            id,
+            notification: Notification::default(),
+            sender: None,
+            reason: CloseReason::Expired,
+            received_at: SystemTime::UNIX_EPOCH,
+            chain: ReplaceChain::default(),
+            starred: false,
+        };
+        let _ = ui.handle_source_reply(SourceReply::History {
+            entries: vec![entry(3), entry(2)],
+        });
+        let key = |key| Message::HistoryPanelEvent {
+            window,
+            message: PanelMessage::Key(key),
+        };
+        let _ = update(&mut ui, key(PanelKey::Next));
+        let _ = update(&mut ui, key(PanelKey::Delete));
         assert_eq!(
-            resolve_icon_path("file:///tmp/icon.png"),
-            Some(PathBuf::from("/tmp/icon.png"))
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::RemoveHistory { ids: vec![2] }
+        );
+        let _ = update(
+            &mut ui,
+            Message::HistoryPanelEvent {
+                window: IcedId::unique(),
+                message: PanelMessage::Key(PanelKey::Open),
+            },
+        );
+        assert!(cmd_rx.try_recv().is_err(), "keys of other windows");
+        let _ = update(&mut ui, key(PanelKey::Open));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::ShowHistoric { id: 2 }
         );
+
+        let _ = update(&mut ui, key(PanelKey::Close));
+        assert_eq!(ui.history_window, None);
+        let _ = ui.handle_source_reply(SourceReply::History {
+            entries: vec![entry(4)],
+        });
+        assert_eq!(ui.history_panel.rows().len(), 2, "ignored while closed");
     }
 
     #[test]
-    fn renderable_icon_path_requires_existing_file() {
-        assert!(renderable_icon_path(true, "kitty").is_none());
+    fn typing_in_the_history_panel_filters_it_until_it_closes() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.open_history_panel(None);
+        let window = ui.history_window.expect("panel opened");
+        let _ = cmd_rx.try_recv();
+        let entry = |id, summary: &str, default_action| HistoryEntry {
+            id,
+            notification: Notification {
+                summary: summary.to_string(),
+                actions: if default_action {
+                    vec![NotificationAction {
+                        key: "default".to_string(),
+                        label: "Open".to_string(),
+                    }]
+                } else {
+                    Vec::new()
+                },
+                ..Notification::default()
+            },
+            sender: None,
+            reason: CloseReason::Dismissed,
+            received_at: SystemTime::UNIX_EPOCH,
+            chain: ReplaceChain::default(),
+            starred: false,
+        };
+        let _ = ui.handle_source_reply(SourceReply::History {
+            entries: vec![
+                entry(3, "Build failed", false),
+                entry(2, "Café order ready", true),
+            ],
+        });
+        let send = |ui: &mut WispdUi, message| {
+            let _ = update(ui, Message::HistoryPanelEvent { window, message });
+        };
+        let typed = |text: &str| PanelMessage::Filter(FilterEdit::Type(text.to_string()));
+
+        send(&mut ui, PanelMessage::Focused(true));
+        send(&mut ui, typed(" "));
+        assert!(
+            ui.history_panel.is_selected(3),
+            "space ticks while not searching"
+        );
+        assert!(ui.history_filter.query.is_empty());
+        for text in ["c", "a", "F", "É"] {
+            send(&mut ui, typed(text));
+        }
+        assert_eq!(ui.history_filter.query, "caFÉ");
+        let ids: Vec<u32> = ui.history_panel.rows().iter().map(|row| row.id).collect();
+        assert_eq!(ids, [2]);
+
+        send(&mut ui, PanelMessage::Key(PanelKey::Open));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeHistoricAction {
+                id: 2,
+                key: "default".to_string(),
+            }
+        );
+        assert_eq!(ui.history_window, None, "invoking closes the panel");
+        assert_eq!(ui.history_filter, HistoryFilter::default());
+
+        let _ = ui.open_history_panel(None);
+        let window = ui.history_window.expect("panel reopened");
+        let _ = update(
+            &mut ui,
+            Message::HistoryPanelEvent {
+                window,
+                message: PanelMessage::Filter(FilterEdit::Today),
+            },
+        );
+        assert!(ui.history_filter.today);
+        let _ = update(&mut ui, Message::HistoryPanel(PanelMessage::Close));
+        assert!(!ui.history_filter.is_active(), "cleared on close");
+    }
+
+    #[test]
+    fn position_hints_place_popups_out_of_the_stack_when_honoured() {
+        let mut ui_cfg = UiSection::default();
+        ui_cfg.honor_position_hints = true;
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(ui_cfg);
+        ui.output_sizes.insert("DP-1".to_string(), (1920, 1080));
+        let at = |id, summary: &str, position| {
+            let NotificationEvent::Received {
+                id,
+                mut notification,
+                silent,
+                remaining_ms,
+            } = sample(id, summary)
+            else {
+                unreachable!()
+            };
+            notification.hints.position = position;
+            NotificationEvent::Received {
+                id,
+                notification,
+                silent,
+                remaining_ms,
+            }
+        };
+        let margin_of = |ui: &WispdUi, id: u32| {
+            let binding = ui.windows.iter().find(|w| w.notification_id == id).unwrap();
+            ui.placed_margins[&binding.window_id]
+        };
+
+        let _ = ui.apply_event(at(1, "one", None));
+        let _ = ui.apply_event(at(2, "shot", Some((1800, 1050))));
+        let _ = ui.apply_event(at(3, "three", None));
+        let below_three =
+            ui.base_margin().0 + ui.popup_height_for_id(3) as i32 + i32::from(ui.ui.gap);
+        assert_eq!(
+            margin_of(&ui, 2),
+            layout::positioned_margin(
+                (1800, 1050),
+                (ui.ui.width, ui.popup_height_for_id(2)),
+                Some((1920, 1080)),
+            ),
+            "clamped onto the output"
+        );
+        assert_eq!(margin_of(&ui, 3), ui.base_margin());
+        assert_eq!(
+            margin_of(&ui, 1).0,
+            below_three,
+            "no slot for the placed popup"
+        );
+
+        ui.ui.honor_position_hints = false;
+        let _ = ui.relayout_task();
+        assert_eq!(
+            margin_of(&ui, 2).0,
+            below_three,
+            "stacked when not honoured"
+        );
     }
 
     #[test]
-    fn icon_height_is_zero_when_icon_is_not_renderable() {
-        let ui = UiSection {
-            show_icons: true,
-            max_icon_size: 32,
-            ..UiSection::default()
+    fn the_history_panel_takes_the_first_stack_slot_until_it_closes() {
+        let (mut ui, _cmd_rx, signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let margin_of = |ui: &WispdUi, id: u32| {
+            let binding = ui.windows.iter().find(|w| w.notification_id == id).unwrap();
+            ui.placed_margins[&binding.window_id]
         };
+        let below_panel =
+            ui.base_margin().0 + history_panel::PANEL_HEIGHT as i32 + i32::from(ui.ui.gap);
+        assert_eq!(margin_of(&ui, 2), ui.base_margin());
+
+        // Do-not-disturb holds back popups, not the panel.
+        ui.do_not_disturb = true;
+        signal_tx.send(SignalRequest::ToggleHistoryPanel).unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        assert!(ui.history_window.is_some());
+        assert_eq!(margin_of(&ui, 2).0, below_panel);
+        assert_eq!(
+            margin_of(&ui, 1).0,
+            below_panel + ui.popup_height_for_id(2) as i32 + i32::from(ui.ui.gap)
+        );
 
-        assert_eq!(icon_height_px(&ui, "kitty"), 0);
+        ui.do_not_disturb = false;
+        let _ = ui.apply_event(sample(3, "three"));
+        assert_eq!(margin_of(&ui, 3).0, below_panel, "arrivals go below it");
+
+        let _ = update(&mut ui, Message::HistoryPanel(PanelMessage::Close));
+        assert_eq!(ui.history_window, None);
+        assert_eq!(
+            margin_of(&ui, 3),
+            ui.base_margin(),
+            "back up once it closes"
+        );
+        assert_eq!(
+            margin_of(&ui, 2).0,
+            ui.base_margin().0 + ui.popup_height_for_id(3) as i32 + i32::from(ui.ui.gap)
+        );
     }
 
     #[test]
-    fn empty_action_labels_are_filtered_from_ui() {
-        let ui_notification = to_ui_notification(
-            1,
-            Notification {
-                actions: vec![
-                    NotificationAction {
-                        key: "default".to_string(),
-                        label: " ".to_string(),
-                    },
-                    NotificationAction {
-                        key: "open".to_string(),
-                        label: "Open".to_string(),
-                    },
-                ],
-                ..Notification::default()
+    fn an_idle_history_panel_closes_itself() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection {
+            history: HistorySection {
+                auto_close_ms: 1_000,
             },
-            None,
+            ..UiSection::default()
+        });
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.open_history_panel(None);
+        let window = ui.history_window.expect("panel opened");
+        let later = |ms| Instant::now() + Duration::from_millis(ms);
+
+        let _ = ui.close_idle_history_panel(later(500));
+        assert_eq!(ui.history_window, Some(window));
+        let _ = update(
+            &mut ui,
+            Message::HistoryPanelEvent {
+                window,
+                message: PanelMessage::Focused(false),
+            },
+        );
+        assert!(
+            ui.history_panel
+                .is_idle(later(1_000), Duration::from_millis(1_000)),
+            "losing the keyboard does not count as use"
+        );
+        let _ = update(
+            &mut ui,
+            Message::HistoryPanelEvent {
+                window,
+                message: PanelMessage::Pointer,
+            },
+        );
+        assert!(
+            !ui.history_panel
+                .is_idle(Instant::now(), Duration::from_millis(1_000))
         );
 
-        assert_eq!(ui_notification.actions.len(), 1);
-        assert_eq!(ui_notification.actions[0].key, "open");
-        assert_eq!(ui_notification.actions[0].label, "Open");
-    }
+        let _ = ui.close_idle_history_panel(later(1_000));
+        assert_eq!(ui.history_window, None);
+        let binding = ui.windows[0];
+        assert_eq!(ui.placed_margins[&binding.window_id], ui.base_margin());
 
-    #[test]
-    fn ui_font_can_be_configured_via_font_alias() {
-        let cfg: AppConfig = toml::from_str("[ui]\nfont = \"JetBrains Mono\"\n").unwrap();
-        assert_eq!(cfg.ui.font_family, "JetBrains Mono");
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.open_history_panel(None);
+        let _ = ui.close_idle_history_panel(later(3_600_000));
+        assert!(
+            ui.history_window.is_some(),
+            "stays open without auto_close_ms"
+        );
     }
 
     #[test]
-    fn button_font_can_be_configured_via_font_alias() {
-        let cfg: AppConfig =
-            toml::from_str("[ui.buttons]\nfont = \"Recursive Mono Casual Static\"\n").unwrap();
+    fn history_popups_are_local_and_expire_on_their_own() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            history_timeout_ms: 1_000,
+            ..UiSection::default()
+        });
+        let _ = ui.apply_event(historic(7, true));
+
+        let (&local, popup) = ui.historic.iter().next().expect("history popup shown");
+        assert_eq!(popup.original_id, 7);
+        assert!(ui.is_local(local));
+        assert!(!ui.notifications.contains_key(&7));
+        assert_eq!(visible_ids(&ui), vec![local]);
+        let created = ui.notifications[&local].created_at;
+        assert!(
+            ui.expired_historic(created + Duration::from_millis(999))
+                .is_empty()
+        );
         assert_eq!(
-            cfg.ui.buttons.font_family.as_deref(),
-            Some("Recursive Mono Casual Static")
+            ui.expired_historic(created + Duration::from_millis(1_000)),
+            vec![local]
         );
-    }
-
-    #[test]
-    fn ui_output_defaults_to_focused() {
-        assert_eq!(AppConfig::default().ui.output, "focused");
-    }
 
-    #[test]
-    fn validate_app_config_rejects_invalid_anchor() {
-        let mut cfg = AppConfig::default();
-        cfg.ui.anchor = "middle-right".to_string();
-        assert!(validate_app_config(&cfg).is_err());
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: local,
+                key: "open".to_string(),
+            },
+        );
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeHistoricAction {
+                id: 7,
+                key: "open".to_string(),
+            }
+        );
+        assert!(!ui.notifications.contains_key(&local));
+        assert!(ui.historic.is_empty());
     }
 
     #[test]
-    fn validate_app_config_rejects_invalid_color() {
-        let mut cfg = AppConfig::default();
-        cfg.ui.colors.background = "nope".to_string();
-        assert!(validate_app_config(&cfg).is_err());
+    fn history_popups_of_departed_senders_keep_actions_disabled() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(historic(7, false));
+        let _ = ui.apply_event(sample(1, "live"));
+        let local = *ui.historic.keys().next().expect("history popup shown");
+
+        assert!(!ui.actions_enabled(local));
+        assert!(ui.actions_enabled(1));
+        let _ = update(
+            &mut ui,
+            Message::ActionClicked {
+                id: local,
+                key: "open".to_string(),
+            },
+        );
+        assert!(
+            cmd_rx.try_recv().is_err(),
+            "nobody to deliver the action to"
+        );
+        assert!(ui.notifications.contains_key(&local));
     }
 
     #[test]
-    fn output_option_parses_focused_with_empty_stack() {
+    fn a_batch_relays_out_once_after_its_last_event() {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui_with_events(UiSection::default(), event_rx);
+
+        let _ = ui.apply_event(sample(1, "alone"));
+        assert_eq!(ui.relayouts, 1);
+
+        let before = ui.relayouts;
+        for event in [
+            NotificationEvent::BatchStarted { batch_id: 7 },
+            sample(2, "a"),
+            sample(3, "b"),
+            NotificationEvent::Replaced {
+                id: 1,
+                previous: Box::new(Notification::default()),
+                current: Box::new(Notification {
+                    summary: "alone, again".to_string(),
+                    ..Notification::default()
+                }),
+                remaining_ms: None,
+                repeat_count: 0,
+            },
+            sample(4, "c"),
+        ] {
+            event_tx.send(event).unwrap();
+        }
+        let _ = update(&mut ui, Message::Tick);
         assert_eq!(
-            output_option_from_config("focused", None),
-            (
-                OutputOption::CompositorDefault,
-                Some(StackOutputPolicy::CompositorChosen)
-            )
+            ui.relayouts, before,
+            "nothing laid out before the batch ends"
         );
-    }
-
-    #[test]
-    fn output_option_parses_last_output() {
         assert_eq!(
-            output_option_from_config("last-output", None),
-            (
-                OutputOption::LastOutput,
-                Some(StackOutputPolicy::CompositorChosen)
-            )
+            visible_ids(&ui),
+            vec![4, 3, 2, 1],
+            "events applied in order"
         );
+
+        event_tx
+            .send(NotificationEvent::BatchEnded { batch_id: 7 })
+            .unwrap();
+        let _ = update(&mut ui, Message::Tick);
+        assert_eq!(ui.relayouts, before + 1);
+        assert!(ui.open_batch.is_none());
+
+        let _ = ui.apply_event(NotificationEvent::BatchStarted { batch_id: 8 });
+        let _ = ui.apply_event(NotificationEvent::BatchEnded { batch_id: 8 });
+        assert_eq!(ui.relayouts, before + 1, "an empty batch lays nothing out");
     }
 
     #[test]
-    fn output_option_parses_output_name() {
+    fn a_burst_relays_out_once_and_sends_only_changed_placements() {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui_with_events(
+            UiSection {
+                max_visible: 30,
+                ..UiSection::default()
+            },
+            event_rx,
+        );
+
+        for id in 1..=30 {
+            event_tx.send(sample(id, "burst")).unwrap();
+        }
+        let _ = update(&mut ui, Message::Tick);
+        assert_eq!(ui.windows.len(), 30);
+        assert_eq!(ui.relayouts, 1, "one pass for the whole tick");
         assert_eq!(
-            output_option_from_config("DP-1", None),
-            (
-                OutputOption::OutputName("DP-1".to_string()),
-                Some(StackOutputPolicy::Named("DP-1".to_string()))
-            )
+            ui.layer_updates, 29,
+            "every popup but the front one moves down; sizes are already right"
         );
-    }
 
-    #[test]
-    fn output_option_uses_focused_command_when_provided() {
+        let _ = update(&mut ui, Message::Tick);
+        let _ = ui.relayout_task();
         assert_eq!(
-            output_option_from_config("focused", Some("printf 'DP-3\\n'")),
-            (
-                OutputOption::OutputName("DP-3".to_string()),
-                Some(StackOutputPolicy::Named("DP-3".to_string()))
-            )
+            ui.layer_updates, 29,
+            "what was sent matches the layout, so a fresh pass sends nothing"
         );
-    }
 
-    #[test]
-    fn effective_timeout_uses_default_for_negative() {
-        assert_eq!(effective_timeout_ms(-1, Some(5_000)), Some(5_000));
+        for id in (2..=30).step_by(2) {
+            event_tx
+                .send(NotificationEvent::Closed {
+                    id,
+                    reason: CloseReason::Expired,
+                })
+                .unwrap();
+        }
+        let _ = update(&mut ui, Message::Tick);
+        assert_eq!(ui.windows.len(), 15);
+        assert_eq!(ui.relayouts, 3);
+        assert_eq!(
+            ui.layer_updates,
+            29 + 15,
+            "each survivor moves up once; sizes stay"
+        );
     }
 
     #[test]
-    fn effective_timeout_disables_for_zero() {
-        assert_eq!(effective_timeout_ms(0, Some(5_000)), None);
+    fn a_notification_closed_in_the_same_tick_never_opens() {
+        let replaced = NotificationEvent::Replaced {
+            id: 1,
+            previous: Box::new(Notification::default()),
+            current: Box::new(Notification::default()),
+            remaining_ms: None,
+            repeat_count: 0,
+        };
+        let closed = |id| NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Dismissed,
+        };
+        let kept = coalesce_events(vec![
+            sample(1, "gone"),
+            sample(2, "stays"),
+            replaced,
+            closed(1),
+            sample(1, "back"),
+            closed(3),
+        ]);
+        let kept: Vec<_> = kept
+            .iter()
+            .map(|event| match event {
+                NotificationEvent::Received { id, .. } => format!("received {id}"),
+                NotificationEvent::Closed { id, .. } => format!("closed {id}"),
+                other => format!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(kept, ["received 2", "closed 1", "received 1", "closed 3"]);
     }
 
     #[test]
-    fn left_click_can_invoke_default_action() {
-        let ui_cfg = UiSection {
-            left_click_action: ClickAction::InvokeDefaultAction,
+    fn confirmed_actions_ask_first_and_unmatched_ones_do_not() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            confirm_actions: ConfirmActionsSection {
+                actions: vec![confirm::ConfirmRule {
+                    action: "delete".parse().unwrap(),
+                    app: None,
+                }],
+                ..ConfirmActionsSection::default()
+            },
             ..UiSection::default()
+        });
+        let _ = ui.apply_event(sample(1, "one"));
+        let click = |key: &str| Message::ActionClicked {
+            id: 1,
+            key: key.to_string(),
         };
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(ui_cfg);
 
-        let _ = update(&mut ui, Message::NotificationLeftClick { id: 42 });
+        let _ = update(&mut ui, click("open"));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeAction {
+                id: 1,
+                key: "open".to_string(),
+            }
+        );
 
+        let _ = update(&mut ui, click("delete"));
+        assert!(cmd_rx.try_recv().is_err(), "waits for confirmation");
+        assert!(ui.confirmations.get(1).is_some());
+        let _ = update(&mut ui, Message::CancelConfirm { id: 1 });
+        assert!(ui.confirmations.get(1).is_none());
+        assert!(cmd_rx.try_recv().is_err());
+
+        let _ = update(&mut ui, click("delete"));
+        let _ = update(&mut ui, Message::ConfirmAction { id: 1 });
         assert_eq!(
             cmd_rx.try_recv().unwrap(),
             SourceCommand::InvokeAction {
-                id: 42,
-                key: "default".to_string(),
+                id: 1,
+                key: "delete".to_string(),
             }
         );
+
+        let _ = update(&mut ui, click("delete"));
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: Box::new(Notification::default()),
+            current: Box::new(Notification::default()),
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+        assert!(ui.confirmations.get(1).is_none(), "replacement cancels");
+        let _ = update(&mut ui, Message::ConfirmAction { id: 1 });
+        assert!(cmd_rx.try_recv().is_err());
     }
 
     #[test]
-    fn apply_config_updates_ui_and_source_runtime_values() {
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(UiSection::default());
-
-        let mut cfg = AppConfig::default();
-        cfg.source.capabilities = vec!["body".to_string(), "actions".to_string()];
-        cfg.source.default_timeout_ms = Some(4_200);
-        cfg.ui.max_visible = 2;
-
-        let _ = ui.apply_config(cfg);
+    fn routed_actions_run_locally_and_reach_the_sender_only_when_forwarded() {
+        let rules: toml::Table = toml::from_str(
+            r#"
+            [[rules]]
+            match.app_name = "app"
+            actions.open = { run = ["true", "{body_url}"] }
+            actions.later = { run = "true {id}", also_forward = true }
+
+            [[rules]]
+            match.app_name = "other"
+            actions.archive = { run = "true" }
+            "#,
+        )
+        .unwrap();
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            rules: rules["rules"].clone().try_into().unwrap(),
+            ..UiSection::default()
+        });
+        let _ = ui.apply_event(sample(1, "one"));
+        let click = |key: &str| Message::ActionClicked {
+            id: 1,
+            key: key.to_string(),
+        };
 
-        assert_eq!(ui.default_timeout_ms, Some(4_200));
-        assert_eq!(ui.ui.max_visible, 2);
+        let _ = update(&mut ui, click("open"));
         assert_eq!(
             cmd_rx.try_recv().unwrap(),
-            SourceCommand::ReloadConfig {
-                capabilities: vec!["body".to_string(), "actions".to_string()],
-                default_timeout_ms: Some(4_200),
-            }
+            SourceCommand::Dismiss { id: 1 },
+            "the sender never hears of it"
         );
+        for key in ["later", "archive"] {
+            let _ = update(&mut ui, click(key));
+            assert_eq!(
+                cmd_rx.try_recv().unwrap(),
+                SourceCommand::InvokeAction {
+                    id: 1,
+                    key: key.to_string(),
+                }
+            );
+        }
     }
 
     #[test]
-    fn apply_config_with_visible_notifications_preserves_sane_popup_order() {
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(UiSection::default());
-
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(sample(3, "three"));
+    fn keyboard_mode_triggers_actions_by_mnemonic_or_position() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let with_actions = |id| NotificationEvent::Received {
+            id,
+            notification: Box::new(Notification {
+                summary: "build finished".to_string(),
+                actions: vec![
+                    NotificationAction {
+                        key: "open".to_string(),
+                        label: "_Open".to_string(),
+                    },
+                    NotificationAction {
+                        key: "archive".to_string(),
+                        label: "Archive".to_string(),
+                    },
+                ],
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        };
+        let _ = ui.apply_event(with_actions(1));
+        assert_eq!(ui.notifications[&1].actions[0].label, "Open");
+        assert_eq!(ui.notifications[&1].actions[0].mnemonic, Some('O'));
 
-        let mut cfg = AppConfig::default();
-        cfg.source.capabilities = vec!["body".to_string()];
-        cfg.ui.max_visible = 2;
+        let _ = update(&mut ui, Message::AcceleratorPressed('2'));
+        assert!(
+            cmd_rx.try_recv().is_err(),
+            "keys do nothing outside keyboard mode"
+        );
 
-        let _ = ui.apply_config(cfg);
+        let _ = ui.set_keyboard_mode(true);
+        assert_eq!(ui.keyboard_target, Some(1));
+        let _ = update(&mut ui, Message::AcceleratorPressed('x'));
+        assert!(cmd_rx.try_recv().is_err());
+        assert_eq!(ui.keyboard_target, Some(1), "unbound keys keep the mode");
+        let _ = update(&mut ui, Message::AcceleratorPressed('2'));
+        assert_eq!(
+            cmd_rx.try_recv().unwrap(),
+            SourceCommand::InvokeAction {
+                id: 1,
+                key: "archive".to_string(),
+            }
+        );
+        assert_eq!(
+            ui.keyboard_target, None,
+            "triggering an action leaves the mode"
+        );
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 3);
-        assert_eq!(ui.windows[1].notification_id, 2);
-        assert!(ui.notifications.contains_key(&2));
-        assert!(ui.notifications.contains_key(&3));
-        assert!(!ui.notifications.contains_key(&1));
+        let _ = ui.set_keyboard_mode(true);
+        let _ = update(&mut ui, Message::AcceleratorPressed('o'));
         assert_eq!(
             cmd_rx.try_recv().unwrap(),
-            SourceCommand::ReloadConfig {
-                capabilities: vec!["body".to_string()],
-                default_timeout_ms: None,
+            SourceCommand::InvokeAction {
+                id: 1,
+                key: "open".to_string(),
             }
         );
+
+        let _ = ui.set_keyboard_mode(true);
+        let _ = update(&mut ui, Message::KeyboardModeEnded);
+        assert_eq!(ui.keyboard_target, None, "Escape leaves the mode");
+        let _ = ui.set_keyboard_mode(true);
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::Dismissed,
+        });
+        assert_eq!(
+            ui.keyboard_target, None,
+            "closing the popup leaves the mode"
+        );
+    }
+
+    fn sample_with_urgency(id: u32, urgency: Urgency) -> NotificationEvent {
+        let NotificationEvent::Received {
+            id, notification, ..
+        } = sample(id, "n")
+        else {
+            unreachable!("sample builds a received event");
+        };
+        NotificationEvent::Received {
+            id,
+            notification: Box::new(Notification {
+                urgency,
+                ..*notification
+            }),
+            silent: false,
+            remaining_ms: None,
+        }
+    }
+
+    fn visible_ids(ui: &WispdUi) -> Vec<u32> {
+        ui.windows.iter().map(|w| w.notification_id).collect()
+    }
+
+    fn escalating_ui(hover_acknowledges: bool) -> UiSection {
+        UiSection {
+            escalation: EscalationSection {
+                enabled: true,
+                after_ms: 1_000,
+                repeat: true,
+                max_repeats: 1,
+                hover_acknowledges,
+            },
+            ..UiSection::default()
+        }
+    }
+
+    fn escalation_commands(
+        cmd_rx: &mut tokio_mpsc::UnboundedReceiver<SourceCommand>,
+    ) -> Vec<(u32, u32)> {
+        std::iter::from_fn(|| cmd_rx.try_recv().ok())
+            .filter_map(|cmd| match cmd {
+                SourceCommand::Escalate { id, count } => Some((id, count)),
+                _ => None,
+            })
+            .collect()
     }
 
     #[test]
-    fn apply_config_does_not_strand_windows_on_stale_outputs() {
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+    fn unacknowledged_critical_popups_escalate_through_the_source_up_to_the_cap() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(escalating_ui(true));
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Normal));
+        let start = Instant::now();
+
+        ui.escalate_due(start + Duration::from_millis(1_500));
+        assert_eq!(escalation_commands(&mut cmd_rx), vec![(1, 1)]);
+        assert_eq!(ui.escalations.count(1), 1);
+        assert!(
+            ui.activating.is_empty(),
+            "escalation keeps the popup usable"
+        );
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
-        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+        ui.escalate_due(start + Duration::from_millis(2_600));
+        ui.escalate_due(start + Duration::from_secs(60));
+        assert_eq!(escalation_commands(&mut cmd_rx), vec![(1, 2)]);
 
-        let mut cfg = AppConfig::default();
-        cfg.source.capabilities = vec!["body".to_string(), "actions".to_string()];
-        cfg.ui.output = "HDMI-A-1".to_string();
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::Expired,
+        });
+        assert_eq!(ui.escalations.count(1), 0);
+    }
 
-        let _ = ui.apply_config(cfg);
+    #[test]
+    fn interaction_acknowledges_and_cancels_escalation() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(escalating_ui(false));
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Critical));
+        let later = Instant::now() + Duration::from_secs(60);
 
-        let new_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
-        assert_eq!(
-            ui.stack_output_policy,
-            Some(StackOutputPolicy::Named("DP-1".to_string()))
+        let _ = update(
+            &mut ui,
+            Message::PopupHovered {
+                id: 1,
+                hovered: true,
+            },
         );
-        assert_eq!(new_window_ids, old_window_ids);
+        let _ = update(&mut ui, Message::PinClicked { id: 2 });
+        ui.escalate_due(later);
         assert_eq!(
-            cmd_rx.try_recv().unwrap(),
-            SourceCommand::ReloadConfig {
-                capabilities: vec!["body".to_string(), "actions".to_string()],
-                default_timeout_ms: None,
-            }
+            escalation_commands(&mut cmd_rx),
+            vec![(1, 1)],
+            "hover does not acknowledge when disabled"
+        );
+
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(escalating_ui(true));
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        let _ = update(
+            &mut ui,
+            Message::PopupHovered {
+                id: 1,
+                hovered: true,
+            },
         );
+        ui.escalate_due(later);
+        assert!(escalation_commands(&mut cmd_rx).is_empty());
     }
 
     #[test]
-    fn invalid_reload_keeps_current_state_and_emits_local_notification() {
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
-
-        let _ = ui.apply_event(sample(1, "one"));
-        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
-        let old_next_local = ui.next_local_notification_id;
-
-        let _ = ui.apply_loaded_config(Err(anyhow!("invalid ui.anchor: nope")));
+    fn escalation_replays_the_notifications_sound() {
+        let dir = temp_dir("escalation-sound");
+        let chime = dir.join("chime.oga");
+        fs::write(&chime, b"ogg").unwrap();
+        let played = dir.join("played");
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection {
+            sound: SoundSection {
+                enabled: true,
+                player: format!("echo >> '{}'", played.display()),
+                ..SoundSection::default()
+            },
+            ..escalating_ui(true)
+        });
+        let NotificationEvent::Received {
+            id, notification, ..
+        } = sample_with_urgency(1, Urgency::Critical)
+        else {
+            unreachable!("sample builds a received event");
+        };
+        let mut notification = *notification;
+        notification.hints.sound.file = Some(chime.clone());
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id,
+            notification: Box::new(notification),
+            silent: false,
+            remaining_ms: None,
+        });
 
+        ui.escalate_due(Instant::now() + Duration::from_secs(60));
+        assert_eq!(escalation_commands(&mut cmd_rx), vec![(1, 1)]);
+        let expected = format!("{0}\n{0}\n", chime.display());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fs::read_to_string(&played).unwrap_or_default() != expected
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(20));
+        }
         assert_eq!(
-            ui.stack_output_policy,
-            Some(StackOutputPolicy::Named("DP-1".to_string()))
-        );
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[1].notification_id, 1);
-        assert_eq!(ui.windows[1].window_id, old_window_ids[0]);
-        assert!(
-            ui.notifications.values().any(
-                |n| n.summary == "Config reload failed" && n.body.contains("invalid ui.anchor")
-            )
-        );
-        assert!(ui.next_local_notification_id < old_next_local);
-        assert!(
-            cmd_rx.try_recv().is_err(),
-            "invalid reload should not notify source thread"
+            fs::read_to_string(&played).unwrap(),
+            expected,
+            "played on arrival and again on escalation"
         );
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn right_click_can_dismiss() {
-        let ui_cfg = UiSection {
-            right_click_action: ClickAction::Dismiss,
-            ..UiSection::default()
-        };
-        let (mut ui, mut cmd_rx, _reload_tx) = test_ui(ui_cfg);
+    fn escalation_follows_urgency_changes_and_skips_local_popups() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(escalating_ui(true));
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Normal));
+        let _ = ui.emit_local_notification("Notification backend error", String::new());
 
-        let _ = update(&mut ui, Message::NotificationRightClick { id: 11 });
+        let NotificationEvent::Received { notification, .. } =
+            sample_with_urgency(1, Urgency::Critical)
+        else {
+            unreachable!("sample builds a received event");
+        };
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: notification.clone(),
+            current: notification,
+            remaining_ms: None,
+            repeat_count: 0,
+        });
 
-        assert_eq!(
-            cmd_rx.try_recv().unwrap(),
-            SourceCommand::Dismiss { id: 11 }
-        );
+        ui.escalate_due(Instant::now() + Duration::from_secs(60));
+        assert_eq!(escalation_commands(&mut cmd_rx), vec![(1, 1)]);
     }
 
     #[test]
-    fn output_removal_rebuilds_visible_windows_without_losing_order() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::CompositorChosen);
+    fn do_not_disturb_holds_back_non_critical_popups_without_a_burst_afterwards() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample(1, "before"));
+        let _ = ui.apply_event(NotificationEvent::DndChanged { enabled: true });
+
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Critical));
+        let _ = ui.emit_local_notification("Notification backend error", String::new());
+        assert_eq!(visible_ids(&ui).len(), 3, "1, 3 and the local popup");
+        assert!(!visible_ids(&ui).contains(&2));
+        assert!(ui.notifications.contains_key(&2), "still tracked");
+
+        let _ = ui.apply_event(NotificationEvent::DndChanged { enabled: false });
+        assert!(!visible_ids(&ui).contains(&2));
+        let _ = ui.apply_event(sample(4, "after"));
+        assert!(visible_ids(&ui).contains(&4));
+    }
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
-        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+    #[test]
+    fn silently_received_notifications_never_pop_up() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let NotificationEvent::Received {
+            id, notification, ..
+        } = sample_with_urgency(1, Urgency::Critical)
+        else {
+            unreachable!();
+        };
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id,
+            notification: notification.clone(),
+            silent: true,
+            remaining_ms: None,
+        });
+        assert!(ui.notifications.contains_key(&1), "still tracked");
+        assert!(visible_ids(&ui).is_empty(), "even when critical");
 
-        let _ = update(
-            &mut ui,
-            Message::OutputHotplug(OutputHotplugEvent::Removed {
-                name: Some("DP-1".to_string()),
-            }),
-        );
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id,
+            previous: notification.clone(),
+            current: notification,
+            remaining_ms: None,
+            repeat_count: 0,
+        });
+        assert!(visible_ids(&ui).is_empty(), "nor when replaced");
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 2);
-        assert_eq!(ui.windows[1].notification_id, 1);
-        assert_ne!(ui.windows[0].window_id, old_window_ids[0]);
-        assert_ne!(ui.windows[1].window_id, old_window_ids[1]);
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Expired,
+        });
+        assert!(!ui.silenced.contains(&1));
     }
 
     #[test]
-    fn unrelated_named_output_removal_keeps_existing_stack() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
-
-        let _ = ui.apply_event(sample(1, "one"));
-        let old_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
+    fn dnd_schedule_from_config_reaches_the_source() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let cfg: AppConfig = toml::from_str(
+            "[[ui.dnd.schedule]]\ndays = [\"mon-fri\"]\nfrom = \"22:30\"\nuntil = \"07:00\"\n",
+        )
+        .unwrap();
+        let schedule = cfg.ui.dnd.schedule.clone();
+        assert_eq!(schedule.len(), 1);
 
-        let _ = update(
-            &mut ui,
-            Message::OutputHotplug(OutputHotplugEvent::Removed {
-                name: Some("HDMI-A-1".to_string()),
-            }),
-        );
+        let _ = ui.apply_config(cfg);
+        let SourceCommand::ReloadConfig { dnd_schedule, .. } = cmd_rx.try_recv().unwrap() else {
+            panic!("expected reload command");
+        };
+        assert_eq!(dnd_schedule, schedule);
 
-        let new_window_ids: Vec<IcedId> = ui.windows.iter().map(|w| w.window_id).collect();
-        assert_eq!(new_window_ids, old_window_ids);
-        assert_eq!(
-            ui.stack_output_policy,
-            Some(StackOutputPolicy::Named("DP-1".to_string()))
+        let invalid = toml::from_str::<AppConfig>(
+            "[[ui.dnd.schedule]]\nfrom = \"22:30\"\nuntil = \"22:30\"\n",
         );
+        assert!(invalid.is_err());
     }
 
     #[test]
-    fn window_closed_removes_notification_binding() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn history_ttl_from_config_reaches_the_source() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let cfg: AppConfig = toml::from_str("[ui]\nhistory_ttl = \"1h30m\"\n").unwrap();
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let window_id = ui.windows[0].window_id;
+        let _ = ui.apply_config(cfg);
+        let SourceCommand::ReloadConfig { history_ttl, .. } = cmd_rx.try_recv().unwrap() else {
+            panic!("expected reload command");
+        };
+        assert_eq!(history_ttl, Some(Duration::from_secs(5_400)));
 
-        let _ = update(&mut ui, Message::WindowClosed(window_id));
+        let invalid = toml::from_str::<AppConfig>("[ui]\nhistory_ttl = \"2 weeks\"\n");
+        assert!(invalid.is_err());
+    }
 
-        assert!(ui.notifications.is_empty());
-        assert!(ui.windows.is_empty());
+    #[test]
+    fn urgency_sort_inserts_by_urgency_then_arrival() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection {
+            sort: StackSort::UrgencyThenArrival,
+            ..UiSection::default()
+        });
+
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Low));
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Critical));
+        let _ = ui.apply_event(sample_with_urgency(4, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(5, Urgency::Critical));
+
+        assert_eq!(visible_ids(&ui), vec![5, 3, 4, 1, 2]);
     }
 
     #[test]
-    fn closing_last_window_resets_stack_output_policy() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
-        ui.stack_output_policy = Some(StackOutputPolicy::Named("DP-1".to_string()));
+    fn urgency_sort_evicts_lowest_priority_binding() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection {
+            sort: StackSort::UrgencyThenArrival,
+            max_visible: 3,
+            ..UiSection::default()
+        });
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let window_id = ui.windows[0].window_id;
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Low));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(4, Urgency::Critical));
 
-        let _ = update(&mut ui, Message::WindowClosed(window_id));
+        assert_eq!(visible_ids(&ui), vec![4, 3, 2]);
+        assert!(!ui.notifications.contains_key(&1));
 
-        assert!(ui.windows.is_empty());
-        assert_eq!(ui.stack_output_policy, None);
+        let _ = ui.apply_event(sample_with_urgency(5, Urgency::Critical));
+        assert_eq!(visible_ids(&ui), vec![5, 4, 3]);
+        assert!(!ui.notifications.contains_key(&2));
     }
 
     #[test]
-    fn max_visible_truncates_only_visible_popup_set() {
-        let ui_cfg = UiSection {
+    fn arrival_sort_keeps_newest_first_and_evicts_oldest() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection {
             max_visible: 2,
             ..UiSection::default()
+        });
+
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Low));
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Normal));
+
+        assert_eq!(visible_ids(&ui), vec![3, 2]);
+    }
+
+    #[test]
+    fn urgency_change_on_replacement_resorts_binding() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection {
+            sort: StackSort::UrgencyThenArrival,
+            ..UiSection::default()
+        });
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Normal));
+        let _ = ui.apply_event(sample_with_urgency(2, Urgency::Critical));
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Normal));
+        assert_eq!(visible_ids(&ui), vec![2, 3, 1]);
+        let window_of_1 = ui.windows[2].window_id;
+
+        let NotificationEvent::Received { notification, .. } =
+            sample_with_urgency(1, Urgency::Critical)
+        else {
+            unreachable!();
         };
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(ui_cfg);
+        let _ = ui.apply_event(NotificationEvent::Replaced {
+            id: 1,
+            previous: Box::new(Notification {
+                urgency: Urgency::Normal,
+                ..(*notification).clone()
+            }),
+            current: notification,
+        });
 
-        let _ = ui.apply_event(sample(1, "one"));
-        let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(sample(3, "three"));
+        assert_eq!(visible_ids(&ui), vec![1, 2, 3]);
+        assert_eq!(ui.windows[0].window_id, window_of_1);
+    }
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 3);
-        assert_eq!(ui.windows[1].notification_id, 2);
-        assert_eq!(ui.notifications.len(), 2);
-        assert!(!ui.notifications.contains_key(&1));
+    fn animated_ui() -> UiSection {
+        UiSection {
+            animation: AnimationSection {
+                enabled: true,
+                duration_ms: 200,
+                kind: animation::AnimationKind::Slide,
+            },
+            ..UiSection::default()
+        }
     }
 
     #[test]
-    fn closing_middle_notification_compacts_visible_stack() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn closing_with_animation_defers_window_removal_until_exit_finishes() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(animated_ui());
 
         let _ = ui.apply_event(sample(1, "one"));
         let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(sample(3, "three"));
+        let closed_window = ui.windows[1].window_id;
+        assert!(ui.windows.iter().all(|w| w.entered_at.is_some()));
+
         let _ = ui.apply_event(NotificationEvent::Closed {
-            id: 2,
+            id: 1,
             reason: CloseReason::ClosedByCall,
         });
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 3);
-        assert_eq!(ui.windows[1].notification_id, 1);
-        assert!(ui.notifications.contains_key(&1));
-        assert!(!ui.notifications.contains_key(&2));
-        assert!(ui.notifications.contains_key(&3));
+        assert_eq!(visible_ids(&ui), vec![2]);
+        assert!(!ui.notifications.contains_key(&1));
+        assert_eq!(ui.closing.len(), 1);
+        assert_eq!(ui.closing[0].window_id, closed_window);
+        assert_eq!(ui.closing[0].notification.summary, "one");
+        assert!(ui.animations_active());
+
+        let _ = ui.on_animation_frame(Instant::now() + Duration::from_secs(1));
+
+        assert!(ui.closing.is_empty());
+        assert!(!ui.placed_margins.contains_key(&closed_window));
+        assert!(ui.windows.iter().all(|w| w.entered_at.is_none()));
+        assert!(ui.margin_tweens.is_empty());
+        assert!(!ui.animations_active());
     }
 
     #[test]
-    fn popup_order_remains_stable_across_burst() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn reflow_with_animation_tweens_margins_between_slots() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(animated_ui());
 
-        for (id, summary) in [(1, "one"), (2, "two"), (3, "three"), (4, "four")] {
-            let _ = ui.apply_event(sample(id, summary));
-        }
+        let _ = ui.apply_event(sample(1, "one"));
+        let _ = ui.apply_event(sample(2, "two"));
+        let _ = ui.on_animation_frame(Instant::now() + Duration::from_secs(1));
+        let lower = ui.windows[1].window_id;
+        let lower_slot = ui.placed_margins[&lower];
 
-        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
-        assert_eq!(visible, vec![4, 3, 2, 1]);
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 2,
+            reason: CloseReason::ClosedByCall,
+        });
+
+        let tween = ui.margin_tweens[&lower];
+        assert_eq!(tween.from, lower_slot);
+        assert_eq!(tween.to, ui.base_margin());
+        assert_eq!(ui.placed_margins[&lower], ui.base_margin());
+
+        let _ = ui.on_animation_frame(Instant::now() + Duration::from_secs(1));
+        assert!(ui.margin_tweens.is_empty());
     }
 
     #[test]
-    fn replacing_hidden_notification_does_not_corrupt_visible_ordering() {
-        let ui_cfg = UiSection {
-            max_visible: 2,
-            ..UiSection::default()
-        };
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(ui_cfg);
+    fn closing_without_animation_removes_window_immediately() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
 
         let _ = ui.apply_event(sample(1, "one"));
         let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(sample(3, "three"));
+        assert!(ui.windows.iter().all(|w| w.entered_at.is_none()));
 
-        let _ = ui.apply_event(NotificationEvent::Replaced {
-            id: 2,
-            previous: Box::new(Notification {
-                summary: String::from("two"),
-                ..Notification::default()
-            }),
-            current: Box::new(Notification {
-                summary: String::from("two-new"),
-                ..Notification::default()
-            }),
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 1,
+            reason: CloseReason::ClosedByCall,
         });
 
-        assert_eq!(ui.windows.len(), 2);
-        assert_eq!(ui.windows[0].notification_id, 3);
-        assert_eq!(ui.windows[1].notification_id, 2);
-        assert_eq!(ui.notifications.get(&2).unwrap().summary, "two-new");
+        assert!(ui.closing.is_empty());
+        assert!(ui.margin_tweens.is_empty());
+        assert!(!ui.animations_active());
     }
 
-    #[test]
-    fn later_notifications_stick_to_existing_stack_output_policy() {
-        let (mut ui, _cmd_rx, _reload_tx) = test_ui(UiSection::default());
+    fn takeover_ui() -> UiSection {
+        let mut ui = UiSection::default();
+        ui.urgency.critical.takeover = true;
+        ui
+    }
 
+    #[test]
+    fn a_critical_takeover_holds_popups_back_and_restores_them_when_dismissed() {
+        let (mut ui, mut cmd_rx, _signal_tx) = test_ui(takeover_ui());
         let _ = ui.apply_event(sample(1, "one"));
-        let initial_policy = ui.stack_output_policy.clone();
-        assert_eq!(initial_policy, Some(StackOutputPolicy::CompositorChosen));
-
         let _ = ui.apply_event(sample(2, "two"));
-        let _ = ui.apply_event(sample(3, "three"));
+        assert_eq!(visible_ids(&ui), vec![2, 1]);
+
+        let _ = ui.apply_event(sample_with_urgency(3, Urgency::Critical));
+        assert_eq!(ui.takeovers.showing(), Some(3));
+        assert!(visible_ids(&ui).is_empty(), "the stack makes way");
+        assert_eq!(ui.notifications[&3].timeout_ms, None);
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(SourceCommand::SetPinned {
+                id: 3,
+                pinned: true
+            })
+        ));
 
-        assert_eq!(ui.stack_output_policy, initial_policy);
-        let visible: Vec<u32> = ui.windows.iter().map(|w| w.notification_id).collect();
-        assert_eq!(visible, vec![3, 2, 1]);
+        let _ = ui.apply_event(sample(4, "four"));
+        let _ = ui.apply_event(sample_with_urgency(5, Urgency::Critical));
+        assert!(visible_ids(&ui).is_empty());
+        assert_eq!(ui.queued, [4, 2, 1]);
+        assert_eq!(ui.takeovers.showing(), Some(3));
+        assert_eq!(ui.takeovers.waiting(), 1);
+
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 3,
+            reason: CloseReason::Dismissed,
+        });
+        assert_eq!(
+            ui.takeovers.showing(),
+            Some(5),
+            "the next critical takes over"
+        );
+        assert!(visible_ids(&ui).is_empty());
+
+        let _ = ui.apply_event(NotificationEvent::Closed {
+            id: 5,
+            reason: CloseReason::Dismissed,
+        });
+        assert!(!ui.takeovers.is_active());
+        assert_eq!(visible_ids(&ui), vec![4, 2, 1]);
+        assert!(ui.queued.is_empty());
+    }
+
+    #[test]
+    fn critical_popups_stay_in_the_stack_without_takeover_or_in_a_mirror() {
+        let (mut ui, _cmd_rx, _signal_tx) = test_ui(UiSection::default());
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        assert_eq!(visible_ids(&ui), vec![1]);
+        assert!(!ui.takeovers.is_active());
+
+        let (ui, _cmd_rx, _signal_tx) = test_ui(takeover_ui());
+        let mut ui = ui.mirroring(MirrorSection { show_popups: true });
+        let _ = ui.apply_event(sample_with_urgency(1, Urgency::Critical));
+        assert_eq!(visible_ids(&ui), vec![1]);
+        assert!(!ui.takeovers.is_active());
     }
 }
@@ -0,0 +1,430 @@
+//! Daemon metrics in Prometheus text format. `[metrics] listen = "127.0.0.1:9814"`
+//! serves them at `/metrics` from the source thread. [`Metrics`] is the one registry:
+//! the source thread counts every notification event before the UI sees it, the UI
+//! counts popups opened, relayouts and animation frames, and the store's
+//! [`SourceStats`] and do-not-disturb state are read from the source per request.
+
+use std::{
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, warn};
+use wisp_source::{SourceStats, WispSource};
+use wisp_types::{CloseReason, NotificationEvent};
+
+/// `[metrics]`: the optional Prometheus listener.
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct MetricsSection {
+    /// Address to serve `/metrics` on; unset serves nothing.
+    pub(crate) listen: Option<String>,
+}
+
+/// Address shown for `listen` in the config schema.
+pub(crate) const DEFAULT_LISTEN: &str = "127.0.0.1:9814";
+
+pub(crate) fn validate_metrics_section(cfg: &MetricsSection) -> Result<()> {
+    if let Some(listen) = &cfg.listen {
+        listen.parse::<SocketAddr>().map_err(|err| {
+            anyhow!("metrics.listen must be an address like {DEFAULT_LISTEN:?}: {listen:?} ({err})")
+        })?;
+    }
+    Ok(())
+}
+
+/// Close reasons in exposition order.
+const CLOSE_REASONS: [CloseReason; 4] = [
+    CloseReason::Expired,
+    CloseReason::Dismissed,
+    CloseReason::ClosedByCall,
+    CloseReason::Undefined,
+];
+
+/// Counters shared by the source thread, the UI and the listener.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    received: AtomicU64,
+    replaced: AtomicU64,
+    /// Indexed like [`CLOSE_REASONS`].
+    closed: [AtomicU64; 4],
+    popups_opened: AtomicU64,
+    queue_depth: AtomicU64,
+    relayouts: AtomicU64,
+    frame_wakeups: AtomicU64,
+}
+
+/// Point-in-time copy of [`Metrics`] plus what the source reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct MetricsSnapshot {
+    pub(crate) received: u64,
+    pub(crate) replaced: u64,
+    /// Like [`CLOSE_REASONS`].
+    pub(crate) closed: [u64; 4],
+    pub(crate) popups_opened: u64,
+    pub(crate) queue_depth: u64,
+    pub(crate) relayouts: u64,
+    pub(crate) frame_wakeups: u64,
+    pub(crate) do_not_disturb: bool,
+    pub(crate) dropped_events: u64,
+    pub(crate) failed_signal_emissions: u64,
+    pub(crate) oversized_hints: u64,
+}
+
+impl Metrics {
+    /// Counts a source event on its way to the UI.
+    pub(crate) fn observe(&self, event: &NotificationEvent) {
+        match event {
+            NotificationEvent::Received { .. } => {
+                self.received.fetch_add(1, Ordering::Relaxed);
+            }
+            NotificationEvent::Replaced { .. } => {
+                self.replaced.fetch_add(1, Ordering::Relaxed);
+            }
+            NotificationEvent::Closed { reason, .. } => {
+                if let Some(index) = CLOSE_REASONS.iter().position(|known| known == reason) {
+                    self.closed[index].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn record_popup_opened(&self) {
+        self.popups_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_relayout(&self) {
+        self.relayouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An animation frame woke the UI.
+    pub(crate) fn record_frame_wakeup(&self) {
+        self.frame_wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Popups waiting for room in the stack.
+    pub(crate) fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth
+            .store(u64::try_from(depth).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, source: &SourceStats, do_not_disturb: bool) -> MetricsSnapshot {
+        MetricsSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            replaced: self.replaced.load(Ordering::Relaxed),
+            closed: self
+                .closed
+                .each_ref()
+                .map(|count| count.load(Ordering::Relaxed)),
+            popups_opened: self.popups_opened.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            relayouts: self.relayouts.load(Ordering::Relaxed),
+            frame_wakeups: self.frame_wakeups.load(Ordering::Relaxed),
+            do_not_disturb,
+            dropped_events: source.dropped_events,
+            failed_signal_emissions: source.failed_signal_emissions,
+            oversized_hints: source.oversized_hints,
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Prometheus text exposition.
+    pub(crate) fn prometheus(&self) -> String {
+        let mut out = String::new();
+        for (name, kind, help, value) in [
+            (
+                "received",
+                "counter",
+                "Notifications received.",
+                self.received,
+            ),
+            (
+                "replaced",
+                "counter",
+                "Notifications replaced in place.",
+                self.replaced,
+            ),
+            (
+                "popups_opened",
+                "counter",
+                "Popup windows opened.",
+                self.popups_opened,
+            ),
+            (
+                "queue_depth",
+                "gauge",
+                "Popups waiting for room in the stack.",
+                self.queue_depth,
+            ),
+            (
+                "do_not_disturb",
+                "gauge",
+                "Whether do-not-disturb is on.",
+                u64::from(self.do_not_disturb),
+            ),
+            (
+                "dropped_events",
+                "counter",
+                "Source events dropped because the queue was full or its receiver gone.",
+                self.dropped_events,
+            ),
+            (
+                "failed_signal_emissions",
+                "counter",
+                "D-Bus signals the bus refused.",
+                self.failed_signal_emissions,
+            ),
+            (
+                "oversized_hints",
+                "counter",
+                "Hint values truncated or dropped for their size.",
+                self.oversized_hints,
+            ),
+            (
+                "relayouts",
+                "counter",
+                "Stack layout passes.",
+                self.relayouts,
+            ),
+            (
+                "frame_wakeups",
+                "counter",
+                "Animation frames that woke the UI.",
+                self.frame_wakeups,
+            ),
+        ] {
+            let metric = if kind == "counter" {
+                format!("wispd_{name}_total")
+            } else {
+                format!("wispd_{name}")
+            };
+            let _ = writeln!(out, "# HELP {metric} {help}");
+            let _ = writeln!(out, "# TYPE {metric} {kind}");
+            let _ = writeln!(out, "{metric} {value}");
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wispd_closed_total Notifications closed, by reason."
+        );
+        let _ = writeln!(out, "# TYPE wispd_closed_total counter");
+        for (reason, count) in CLOSE_REASONS.iter().zip(self.closed) {
+            let _ = writeln!(
+                out,
+                "wispd_closed_total{{reason=\"{}\"}} {count}",
+                reason_label(reason)
+            );
+        }
+        out
+    }
+}
+
+fn reason_label(reason: &CloseReason) -> &'static str {
+    match reason {
+        CloseReason::Expired => "expired",
+        CloseReason::Dismissed => "dismissed",
+        CloseReason::ClosedByCall => "closed_by_call",
+        _ => "undefined",
+    }
+}
+
+/// Binds `listen` and serves `/metrics` on the current runtime; a failure to bind is
+/// logged and leaves metrics off.
+pub(crate) async fn start(listen: &str, metrics: Arc<Metrics>, source: WispSource) {
+    let listener = match TcpListener::bind(listen).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!(listen, %err, "failed to bind the metrics listener; metrics are off");
+            return;
+        }
+    };
+    info!(listen, "serving Prometheus metrics at /metrics");
+    tokio::spawn(serve(listener, metrics, source));
+}
+
+/// Serves `/metrics` until the listener fails.
+async fn serve(listener: TcpListener, metrics: Arc<Metrics>, source: WispSource) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!(%peer, "metrics request");
+                let snapshot = metrics.snapshot(&source.stats(), source.is_do_not_disturb());
+                tokio::spawn(async move {
+                    if let Err(err) = handle(stream, &snapshot).await {
+                        debug!(%err, "metrics request failed");
+                    }
+                });
+            }
+            Err(err) => {
+                warn!(%err, "metrics listener stopped");
+                return;
+            }
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, snapshot: &MetricsSnapshot) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..read]);
+    let path = request.lines().next().and_then(|line| {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["GET", path, ..] => Some(path),
+            _ => None,
+        }
+    });
+
+    let response = respond(path, snapshot);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Full HTTP/1.1 response for a `GET` of `path` (`None` for anything else).
+fn respond(path: Option<&str>, snapshot: &MetricsSnapshot) -> String {
+    let (status, content_type, body) = match path {
+        Some("/metrics") => ("200 OK", "text/plain; version=0.0.4", snapshot.prometheus()),
+        Some(_) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        None => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wisp_types::Notification;
+
+    #[test]
+    fn a_scripted_event_sequence_is_counted() {
+        let metrics = Metrics::default();
+        let received = |id| NotificationEvent::Received {
+            id,
+            notification: Box::default(),
+            silent: false,
+            remaining_ms: None,
+        };
+        for event in [
+            received(1),
+            received(2),
+            NotificationEvent::Replaced {
+                id: 1,
+                previous: Box::<Notification>::default(),
+                current: Box::default(),
+                remaining_ms: None,
+                repeat_count: 0,
+            },
+            NotificationEvent::Closed {
+                id: 1,
+                reason: CloseReason::Expired,
+            },
+            NotificationEvent::Closed {
+                id: 2,
+                reason: CloseReason::Dismissed,
+            },
+            NotificationEvent::DndChanged { enabled: true },
+        ] {
+            metrics.observe(&event);
+        }
+        metrics.record_popup_opened();
+        metrics.record_relayout();
+        metrics.record_relayout();
+        metrics.record_frame_wakeup();
+        metrics.set_queue_depth(3);
+
+        let stats = SourceStats {
+            dropped_events: 4,
+            ..SourceStats::default()
+        };
+        let snapshot = metrics.snapshot(&stats, true);
+        assert_eq!(
+            snapshot,
+            MetricsSnapshot {
+                received: 2,
+                replaced: 1,
+                closed: [1, 1, 0, 0],
+                popups_opened: 1,
+                queue_depth: 3,
+                relayouts: 2,
+                frame_wakeups: 1,
+                do_not_disturb: true,
+                dropped_events: 4,
+                failed_signal_emissions: 0,
+                oversized_hints: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn exposition_follows_the_text_format() {
+        let snapshot = MetricsSnapshot {
+            received: 5,
+            closed: [2, 1, 0, 0],
+            queue_depth: 1,
+            ..MetricsSnapshot::default()
+        };
+        let text = snapshot.prometheus();
+        assert!(text.contains(
+            "# HELP wispd_received_total Notifications received.\n\
+             # TYPE wispd_received_total counter\n\
+             wispd_received_total 5\n"
+        ));
+        assert!(text.contains("# TYPE wispd_queue_depth gauge\nwispd_queue_depth 1\n"));
+        assert!(text.contains("wispd_do_not_disturb 0\n"));
+        assert!(text.contains(
+            "# TYPE wispd_closed_total counter\n\
+             wispd_closed_total{reason=\"expired\"} 2\n\
+             wispd_closed_total{reason=\"dismissed\"} 1\n\
+             wispd_closed_total{reason=\"closed_by_call\"} 0\n\
+             wispd_closed_total{reason=\"undefined\"} 0\n"
+        ));
+        for line in text.lines() {
+            assert!(
+                line.starts_with("# HELP ")
+                    || line.starts_with("# TYPE ")
+                    || line.starts_with("wispd_"),
+                "{line}"
+            );
+        }
+
+        let response = respond(Some("/metrics"), &snapshot);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(&format!("Content-Length: {}\r\n", text.len())));
+        assert!(respond(Some("/"), &snapshot).starts_with("HTTP/1.1 404"));
+        assert!(respond(None, &snapshot).starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn listen_must_be_a_socket_address() {
+        assert!(validate_metrics_section(&MetricsSection::default()).is_ok());
+        assert!(
+            validate_metrics_section(&MetricsSection {
+                listen: Some(DEFAULT_LISTEN.to_string()),
+            })
+            .is_ok()
+        );
+        assert!(
+            validate_metrics_section(&MetricsSection {
+                listen: Some("localhost".to_string()),
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! `--mirror`: follows whichever daemon owns the notifications name from a D-Bus monitor
+//! connection instead of owning the name. Observed traffic is rebuilt into the same
+//! `NotificationEvent` stream the source produces, so the UI and sinks need no special
+//! path; a monitor cannot emit signals, so the UI treats mirrored popups as read-only.
+//! Nor can it ask the mirrored daemon for its history, so [`MirrorHistory`] rebuilds one
+//! for the history panel from the mirrored events.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result, anyhow};
+use iced::futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use wisp_monitor::{
+    NotificationTracker, NotifyCall, TrackerEvent, become_monitor, method_return_rule,
+    rules_all_notifications,
+};
+use wisp_source::{DEFAULT_HISTORY_INTERMEDIATE_SUMMARIES, DEFAULT_HISTORY_LEN, HistoryEntry};
+use wisp_types::{CloseReason, Notification, NotificationAction, NotificationEvent, ReplaceChain};
+use zbus::{Message, MessageStream, fdo::DBusProxy, names::BusName};
+
+use super::{SourceCommand, delivered::DeliveredLog, sink};
+
+/// `[mirror]`: how `--mirror` presents the other daemon's notifications. Read at startup.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct MirrorSection {
+    /// Open popups for mirrored notifications; off leaves them to the mirrored daemon.
+    pub(crate) show_popups: bool,
+}
+
+/// Turns monitored messages into `NotificationEvent`s, remembering live payloads so an
+/// in-place update can be reported as `Replaced`.
+#[derive(Debug, Default)]
+pub(crate) struct MirrorAdapter {
+    tracker: NotificationTracker,
+    live: HashMap<u32, Notification>,
+}
+
+impl MirrorAdapter {
+    pub(crate) fn observe(&mut self, msg: &Message) -> Result<Option<NotificationEvent>> {
+        Ok(self.tracker.observe(msg)?.map(|event| self.adapt(event)))
+    }
+
+    fn adapt(&mut self, event: TrackerEvent) -> NotificationEvent {
+        match event {
+            TrackerEvent::Notified { id, call, .. } => {
+                let current = notification_from_call(call);
+                match self.live.insert(id, current.clone()) {
+                    Some(previous) => NotificationEvent::Replaced {
+                        id,
+                        previous: Box::new(previous),
+                        current: Box::new(current),
+                        remaining_ms: None,
+                        repeat_count: 0,
+                    },
+                    None => NotificationEvent::Received {
+                        id,
+                        notification: Box::new(current),
+                        silent: false,
+                        remaining_ms: None,
+                    },
+                }
+            }
+            TrackerEvent::Closed { id, reason } => {
+                self.live.remove(&id);
+                NotificationEvent::Closed {
+                    id,
+                    reason: close_reason(reason),
+                }
+            }
+            TrackerEvent::ActionInvoked { id, action_key } => {
+                NotificationEvent::ActionInvoked { id, action_key }
+            }
+            TrackerEvent::Replied { id, text } => NotificationEvent::Replied { id, text },
+        }
+    }
+}
+
+/// The mirrored daemon's history as far as the mirror saw it: notifications that closed
+/// since it attached, newest first, at most [`DEFAULT_HISTORY_LEN`]. Answers the history
+/// panel's commands in place of a source.
+#[derive(Debug, Default)]
+pub(crate) struct MirrorHistory {
+    /// Open notifications with when their replace chain began and how it went.
+    live: HashMap<u32, (Notification, SystemTime, ReplaceChain)>,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl MirrorHistory {
+    /// Follows `event`; a closed notification becomes the newest entry.
+    pub(crate) fn observe(&mut self, event: &NotificationEvent, now: SystemTime) {
+        match event {
+            NotificationEvent::Received {
+                id, notification, ..
+            } => {
+                self.live.insert(
+                    *id,
+                    ((**notification).clone(), now, ReplaceChain::default()),
+                );
+            }
+            NotificationEvent::Replaced {
+                id,
+                previous,
+                current,
+                ..
+            } => {
+                let (notification, _, chain) = self
+                    .live
+                    .entry(*id)
+                    .or_insert_with(|| ((**previous).clone(), now, ReplaceChain::default()));
+                *notification = (**current).clone();
+                chain.replace_count = chain.replace_count.saturating_add(1);
+                if chain.intermediate_summaries.last() != Some(&previous.summary) {
+                    chain.intermediate_summaries.push(previous.summary.clone());
+                    let excess = chain
+                        .intermediate_summaries
+                        .len()
+                        .saturating_sub(DEFAULT_HISTORY_INTERMEDIATE_SUMMARIES);
+                    chain.intermediate_summaries.drain(..excess);
+                }
+            }
+            NotificationEvent::Closed { id, reason } => {
+                let Some((notification, received_at, mut chain)) = self.live.remove(id) else {
+                    return;
+                };
+                chain.lifetime_ms = now
+                    .duration_since(received_at)
+                    .map_or(0, |lifetime| lifetime.as_millis() as u64);
+                self.entries.push_front(HistoryEntry {
+                    id: *id,
+                    notification,
+                    sender: None,
+                    reason: reason.clone(),
+                    received_at,
+                    chain,
+                    starred: false,
+                });
+                self.entries.truncate(DEFAULT_HISTORY_LEN);
+            }
+            _ => {}
+        }
+    }
+
+    /// Carries out a history command; returns the history the panel shows after it, or
+    /// `None` for commands a mirror cannot carry out.
+    pub(crate) fn answer(&mut self, cmd: &SourceCommand) -> Option<Vec<HistoryEntry>> {
+        match cmd {
+            SourceCommand::ListHistory => {}
+            SourceCommand::RemoveHistory { ids } => {
+                self.entries.retain(|entry| !ids.contains(&entry.id));
+            }
+            SourceCommand::SetHistoryStarred { id, starred } => {
+                for entry in self.entries.iter_mut().filter(|entry| entry.id == *id) {
+                    entry.starred = *starred;
+                }
+            }
+            SourceCommand::ClearHistory => self.entries.clear(),
+            _ => return None,
+        }
+        Some(self.entries.iter().cloned().collect())
+    }
+}
+
+fn notification_from_call(call: NotifyCall) -> Notification {
+    let (urgency, hints) = wisp_source::parse_raw_hints(&call.hints);
+    Notification {
+        app_name: call.app_name,
+        app_icon: call.app_icon,
+        summary: call.summary,
+        body: call.body,
+        urgency,
+        timeout_ms: call.expire_timeout,
+        actions: call
+            .actions
+            .chunks_exact(2)
+            .map(|pair| NotificationAction {
+                key: pair[0].clone(),
+                label: pair[1].clone(),
+            })
+            .collect(),
+        hints,
+    }
+}
+
+fn close_reason(code: u32) -> CloseReason {
+    match code {
+        1 => CloseReason::Expired,
+        2 => CloseReason::Dismissed,
+        3 => CloseReason::ClosedByCall,
+        _ => CloseReason::Undefined,
+    }
+}
+
+/// Starts the monitor on its own runtime thread, feeding `ui_tx` (and the `[sink]` exports)
+/// like the source thread does. The returned receiver yields the mirrored daemon's
+/// unique name once monitoring has begun (or startup failed).
+pub(crate) fn spawn_mirror_thread(
+    dbus_name: String,
+    sink_cfg: sink::SinkSection,
+    ui_tx: mpsc::Sender<NotificationEvent>,
+) -> Result<mpsc::Receiver<Result<String, String>>> {
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<String, String>>();
+
+    std::thread::Builder::new()
+        .name("wispd-mirror".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    let _ = ready_tx.send(Err(format!("failed to build tokio runtime: {err}")));
+                    return;
+                }
+            };
+
+            runtime.block_on(async move {
+                let (conn, owner) = match attach_monitor(&dbus_name).await {
+                    Ok(parts) => parts,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(format!("{err:#}")));
+                        return;
+                    }
+                };
+                info!(dbus_name = %dbus_name, %owner, "mirroring notification daemon");
+                let _ = ready_tx.send(Ok(owner));
+
+                // The mirrored daemon keeps its own delivery log; a mirror only sees
+                // traffic from after it attached, so it has nothing to replay.
+                let mut sinks = sink::Sinks::start(sink_cfg, DeliveredLog::default());
+                let mut adapter = MirrorAdapter::default();
+                let mut stream = MessageStream::from(&conn);
+                while let Some(msg) = stream.next().await {
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(err) => {
+                            warn!(%err, "failed to decode monitored message");
+                            continue;
+                        }
+                    };
+                    let event = match adapter.observe(&msg) {
+                        Ok(Some(event)) => event,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            debug!(?err, "skipping unreadable notification message");
+                            continue;
+                        }
+                    };
+                    sinks.offer(&event);
+                    if ui_tx.send(event).is_err() {
+                        warn!("ui channel receiver dropped; stopping mirror");
+                        break;
+                    }
+                }
+                info!("mirror monitor stream ended");
+            });
+        })
+        .map_err(|err| anyhow!("failed to spawn mirror thread: {err}"))?;
+
+    Ok(ready_rx)
+}
+
+/// Resolves the daemon behind `dbus_name` and turns a fresh connection into a monitor
+/// of its traffic. The owner is looked up first because a monitor cannot call methods.
+async fn attach_monitor(dbus_name: &str) -> Result<(zbus::Connection, String)> {
+    let control = zbus::Connection::session()
+        .await
+        .context("failed to connect to the session bus")?;
+    let owner = DBusProxy::new(&control)
+        .await?
+        .get_name_owner(BusName::try_from(dbus_name)?)
+        .await
+        .with_context(|| format!("no notification daemon owns {dbus_name} to mirror"))?
+        .to_string();
+
+    let mut rules = rules_all_notifications();
+    rules.push(method_return_rule(&owner)?);
+    let conn = zbus::Connection::session()
+        .await
+        .context("failed to connect to the session bus")?;
+    become_monitor(&conn, rules).await?;
+    Ok((conn, owner))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::mpsc as tokio_mpsc;
+    use wisp_monitor::NOTIFY_IFACE;
+    use wisp_types::Urgency;
+    use zbus::zvariant::Value;
+
+    use super::super::{
+        Message as UiMessage, SourceCommand, UiSection, WispdUi, mutes::MuteList, update,
+    };
+    use super::*;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const DAEMON: &str = ":1.7";
+
+    fn notify(replaces_id: u32, summary: &str, urgency: u8) -> Message {
+        let hints: HashMap<&str, Value<'_>> = HashMap::from([("urgency", Value::from(urgency))]);
+        Message::method_call(PATH, "Notify")
+            .unwrap()
+            .interface(NOTIFY_IFACE)
+            .unwrap()
+            .sender(":1.42")
+            .unwrap()
+            .destination(DAEMON)
+            .unwrap()
+            .build(&(
+                "mail",
+                replaces_id,
+                "mail-unread",
+                summary,
+                "body",
+                vec!["open", "Open"],
+                hints,
+                -1_i32,
+            ))
+            .unwrap()
+    }
+
+    fn reply(call: &Message, id: u32) -> Message {
+        Message::method_return(&call.header())
+            .unwrap()
+            .sender(DAEMON)
+            .unwrap()
+            .build(&(id,))
+            .unwrap()
+    }
+
+    fn signal<B>(member: &str, body: &B) -> Message
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        Message::signal(PATH, NOTIFY_IFACE, member)
+            .unwrap()
+            .sender(DAEMON)
+            .unwrap()
+            .build(body)
+            .unwrap()
+    }
+
+    /// Two notifications, an in-place update of the first, an action on it and an
+    /// expiry of the second, as a monitor sees them.
+    fn captured_session() -> Vec<Message> {
+        let first = notify(0, "first", 1);
+        let second = notify(0, "second", 2);
+        let update = notify(1, "first, updated", 1);
+        vec![
+            first.clone(),
+            reply(&first, 1),
+            second.clone(),
+            reply(&second, 2),
+            update.clone(),
+            reply(&update, 1),
+            signal("ActionInvoked", &(1_u32, "open")),
+            signal("NotificationClosed", &(2_u32, 1_u32)),
+        ]
+    }
+
+    fn replay(messages: &[Message]) -> Vec<NotificationEvent> {
+        let mut adapter = MirrorAdapter::default();
+        messages
+            .iter()
+            .filter_map(|msg| adapter.observe(msg).expect("readable message"))
+            .collect()
+    }
+
+    fn mirror_ui(show_popups: bool) -> (WispdUi, tokio_mpsc::UnboundedReceiver<SourceCommand>) {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, cmd_rx) = tokio_mpsc::unbounded_channel();
+        let ui = WispdUi::new(
+            Arc::new(Mutex::new(event_rx)),
+            Arc::new(Mutex::new(signal_rx)),
+            Arc::new(Mutex::new(reply_rx)),
+            cmd_tx,
+            UiSection::default(),
+            Some(5000),
+            MuteList::in_memory(),
+        )
+        .mirroring(MirrorSection { show_popups });
+        (ui, cmd_rx)
+    }
+
+    #[test]
+    fn captured_traffic_becomes_the_source_event_stream() {
+        let events = replay(&captured_session());
+
+        let NotificationEvent::Received {
+            id: 1,
+            notification,
+            ..
+        } = &events[0]
+        else {
+            panic!("expected the first notification, got {:?}", events[0]);
+        };
+        assert_eq!(notification.summary, "first");
+        assert_eq!(notification.app_icon, "mail-unread");
+        assert_eq!(notification.actions[0].label, "Open");
+
+        let NotificationEvent::Received {
+            id: 2,
+            notification,
+            ..
+        } = &events[1]
+        else {
+            panic!("expected the second notification, got {:?}", events[1]);
+        };
+        assert_eq!(notification.urgency, Urgency::Critical);
+
+        let NotificationEvent::Replaced {
+            id: 1,
+            previous,
+            current,
+            remaining_ms: None,
+            repeat_count: 0,
+        } = &events[2]
+        else {
+            panic!("expected a replacement, got {:?}", events[2]);
+        };
+        assert_eq!(previous.summary, "first");
+        assert_eq!(current.summary, "first, updated");
+
+        assert_eq!(
+            events[3..],
+            [
+                NotificationEvent::ActionInvoked {
+                    id: 1,
+                    action_key: "open".to_string(),
+                },
+                NotificationEvent::Closed {
+                    id: 2,
+                    reason: CloseReason::Expired,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn replayed_session_fills_ui_state_without_popups_or_commands() {
+        let (mut ui, mut cmd_rx) = mirror_ui(false);
+        for event in replay(&captured_session()) {
+            let _ = ui.apply_event(event);
+        }
+
+        let ids: Vec<u32> = ui.notifications.keys().copied().collect();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(ui.notifications[&1].summary, "first, updated");
+        assert!(ui.windows.is_empty());
+        assert!(ui.pending_measure.is_empty());
+
+        let _ = update(&mut ui, UiMessage::DismissClicked { id: 1 });
+        let _ = update(
+            &mut ui,
+            UiMessage::ActionClicked {
+                id: 1,
+                key: "open".to_string(),
+            },
+        );
+        assert!(cmd_rx.try_recv().is_err());
+        assert!(ui.activating.is_empty());
+    }
+
+    #[test]
+    fn the_history_panel_lists_what_closed_while_mirroring() {
+        let (mut ui, mut cmd_rx) = mirror_ui(false);
+        let mut session = captured_session();
+        session.push(signal("NotificationClosed", &(1_u32, 2_u32)));
+        for event in replay(&session) {
+            let _ = ui.apply_event(event);
+        }
+
+        let _ = ui.toggle_history_panel();
+        let rows: Vec<(u32, &str)> = ui
+            .history_panel
+            .rows()
+            .iter()
+            .map(|row| (row.id, row.summary.as_str()))
+            .collect();
+        assert_eq!(rows, [(1, "first, updated"), (2, "second")]);
+        let updated = &ui.mirror_history.entries[0];
+        assert_eq!(updated.reason, CloseReason::Dismissed);
+        assert_eq!(updated.chain.intermediate_summaries, ["first"]);
+
+        ui.send_source_command(SourceCommand::RemoveHistory { ids: vec![2] });
+        assert_eq!(ui.history_panel.rows().len(), 1);
+        ui.send_source_command(SourceCommand::ClearHistory);
+        assert!(ui.history_panel.rows().is_empty());
+        assert!(cmd_rx.try_recv().is_err(), "nothing reaches a source");
+    }
+
+    #[test]
+    fn show_popups_opens_mirrored_popups() {
+        let (mut ui, _cmd_rx) = mirror_ui(true);
+        for event in replay(&captured_session()[..2]) {
+            let _ = ui.apply_event(event);
+        }
+        assert_eq!(ui.windows.len(), 1);
+    }
+}
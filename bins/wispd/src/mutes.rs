@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::state::{self, StateFile};
+
+/// `mutes.toml` in the state dir.
+pub(crate) const STATE_FILE: StateFile = StateFile {
+    name: "mutes.toml",
+    migrations: &[add_version],
+    validate: state::validate_as::<MuteFile>,
+};
+
+/// On-disk shape of `mutes.toml`: app name -> unix expiry in seconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MuteFile {
+    #[serde(default)]
+    muted: BTreeMap<String, u64>,
+}
+
+/// Temporarily muted apps, persisted in the state dir so mutes survive restarts.
+#[derive(Debug, Default)]
+pub(crate) struct MuteList {
+    dir: Option<PathBuf>,
+    until: BTreeMap<String, u64>,
+}
+
+impl MuteList {
+    /// A list that is never written to disk (tests, or no usable state dir).
+    pub(crate) fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the list from the state dir `dir`; a missing or unreadable file yields an
+    /// empty list.
+    pub(crate) fn load(dir: PathBuf) -> Self {
+        let until = match state::load::<MuteFile>(&dir, &STATE_FILE) {
+            Ok(file) => file.map(|file| file.muted).unwrap_or_default(),
+            Err(err) => {
+                warn!(
+                    file = STATE_FILE.name,
+                    %err,
+                    "ignoring unreadable mute list; `wispd --repair-state` checks state files"
+                );
+                BTreeMap::new()
+            }
+        };
+
+        let mut list = Self {
+            dir: Some(dir),
+            until,
+        };
+        list.prune(SystemTime::now());
+        list
+    }
+
+    pub(crate) fn is_muted(&self, app_name: &str, now: SystemTime) -> bool {
+        self.until
+            .get(app_name)
+            .is_some_and(|until| *until > unix_secs(now))
+    }
+
+    pub(crate) fn mute(&mut self, app_name: &str, duration: Duration, now: SystemTime) {
+        let until = unix_secs(now).saturating_add(duration.as_secs());
+        self.until.insert(app_name.to_string(), until);
+        self.persist();
+    }
+
+    /// Apps muted at `now` with the seconds left on each, by app name.
+    pub(crate) fn list(&self, now: SystemTime) -> Vec<(String, u64)> {
+        let now = unix_secs(now);
+        self.until
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(app_name, until)| (app_name.clone(), until - now))
+            .collect()
+    }
+
+    /// Lifts a mute early. Returns whether the app was muted.
+    pub(crate) fn unmute(&mut self, app_name: &str) -> bool {
+        let removed = self.until.remove(app_name).is_some();
+        if removed {
+            self.persist();
+        }
+        removed
+    }
+
+    /// Drops lapsed entries. Returns whether anything changed.
+    pub(crate) fn prune(&mut self, now: SystemTime) -> bool {
+        let now = unix_secs(now);
+        let before = self.until.len();
+        self.until.retain(|_, until| *until > now);
+        self.until.len() != before
+    }
+
+    fn persist(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let file = MuteFile {
+            muted: self.until.clone(),
+        };
+        if let Err(err) = state::save(dir, &STATE_FILE, &file) {
+            warn!(dir = %dir.display(), %err, "failed to persist mute list");
+        }
+    }
+}
+
+/// Version 0 is the file from before state files were versioned, with the same shape.
+fn add_version(_: &mut toml::Table) -> Result<(), String> {
+    Ok(())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn mute_expires_after_duration() {
+        let now = SystemTime::now();
+        let mut mutes = MuteList::in_memory();
+        mutes.mute("chat", HOUR, now);
+
+        assert!(mutes.is_muted("chat", now));
+        assert!(!mutes.is_muted("mail", now));
+        assert!(mutes.is_muted("chat", now + HOUR - Duration::from_secs(1)));
+        assert!(!mutes.is_muted("chat", now + HOUR));
+
+        assert!(mutes.prune(now + HOUR));
+        assert!(!mutes.prune(now + HOUR));
+    }
+
+    #[test]
+    fn unmute_lifts_mute_early() {
+        let now = SystemTime::now();
+        let mut mutes = MuteList::in_memory();
+        mutes.mute("chat", HOUR, now);
+
+        assert!(mutes.unmute("chat"));
+        assert!(!mutes.is_muted("chat", now));
+        assert!(!mutes.unmute("chat"));
+    }
+
+    #[test]
+    fn mute_list_round_trips_through_state_file() {
+        let dir = temp_dir("mutes");
+        let now = SystemTime::now();
+
+        let mut mutes = MuteList::load(dir.clone());
+        mutes.mute("chat", HOUR, now);
+        mutes.mute("mail", HOUR, now);
+        assert!(mutes.unmute("mail"));
+
+        let reloaded = MuteList::load(dir.clone());
+        assert!(reloaded.is_muted("chat", now));
+        assert!(!reloaded.is_muted("mail", now));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_drops_lapsed_entries() {
+        let dir = temp_dir("mutes-lapsed");
+        let path = dir.join("mutes.toml");
+        fs::write(&path, "[muted]\nold = 1\n").expect("write mutes");
+
+        let mutes = MuteList::load(dir.clone());
+        assert!(mutes.until.is_empty());
+        assert!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .starts_with("version = 1\n"),
+            "the unversioned file is migrated on load"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,350 @@
+//! Config overrides layered over `config.toml`: `WISPD_<SECTION>_<KEY>` environment
+//! variables, then `--set dotted.path=value` flags. Paths are resolved against a schema
+//! table (the serialized defaults), so every key is overridable without code of its own.
+//! Values are coerced to the type of the key they replace: strings stay as given, lists
+//! take TOML arrays or comma-separated items, anything else is parsed as a TOML value.
+
+use std::{fmt, sync::OnceLock};
+
+use anyhow::{Result, anyhow, bail};
+use toml::{Table, Value};
+
+const ENV_PREFIX: &str = "WISPD_";
+
+static SET_FLAGS: OnceLock<Vec<Override>> = OnceLock::new();
+
+/// Where an override came from, for error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Origin {
+    Env(String),
+    Set,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env(name) => write!(f, "${name}"),
+            Self::Set => f.write_str("--set"),
+        }
+    }
+}
+
+/// One `path = value` replacement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Override {
+    pub(crate) origin: Origin,
+    pub(crate) path: Vec<String>,
+    pub(crate) raw: String,
+}
+
+impl Override {
+    fn dotted(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+/// Parses a `--set` argument, `ui.font_size=18`.
+pub(crate) fn parse_set(arg: &str) -> Result<Override> {
+    let (path, raw) = arg
+        .split_once('=')
+        .ok_or_else(|| anyhow!("invalid --set {arg:?}: expected dotted.path=value"))?;
+    let path: Vec<String> = path.trim().split('.').map(str::to_string).collect();
+    if path.iter().any(|segment| segment.is_empty()) {
+        bail!("invalid --set {arg:?}: empty key in path");
+    }
+    Ok(Override {
+        origin: Origin::Set,
+        path,
+        raw: raw.to_string(),
+    })
+}
+
+/// Keeps the `--set` flags for every later config load, reloads included.
+pub(crate) fn install(flags: Vec<Override>) {
+    let _ = SET_FLAGS.set(flags);
+}
+
+/// The installed `--set` flags.
+pub(crate) fn set_flags() -> &'static [Override] {
+    SET_FLAGS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Overrides from `WISPD_<SECTION>_<KEY>` variables, sorted by name. Variables whose
+/// first part is not a config section belong to other tools (`WISPD_MONITOR_*`) and are
+/// skipped. Underscores separate tables and also occur inside keys, so the longest known
+/// key wins: `WISPD_UI_TIMEOUT_PROGRESS_HEIGHT` is `ui.timeout_progress.height`.
+pub(crate) fn from_env(
+    vars: impl IntoIterator<Item = (String, String)>,
+    schema: &Table,
+) -> Vec<Override> {
+    let mut found: Vec<Override> = vars
+        .into_iter()
+        .filter_map(|(name, raw)| {
+            let rest = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+            let (section, rest) = longest_key(schema, &rest)?;
+            let Some(Value::Table(table)) = schema.get(section) else {
+                return None;
+            };
+            let mut path = vec![section.to_string()];
+            path.extend(env_path(table, rest));
+            Some(Override {
+                origin: Origin::Env(name),
+                path,
+                raw,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| a.origin.to_string().cmp(&b.origin.to_string()));
+    found
+}
+
+/// The key of `table` that `name` starts with followed by `_`, preferring the longest,
+/// and what follows it.
+fn longest_key<'a>(table: &'a Table, name: &'a str) -> Option<(&'a str, &'a str)> {
+    table
+        .keys()
+        .filter_map(|key| {
+            let rest = name.strip_prefix(key.as_str())?.strip_prefix('_')?;
+            (!rest.is_empty()).then_some((key.as_str(), rest))
+        })
+        .max_by_key(|(key, _)| key.len())
+}
+
+fn env_path(table: &Table, name: &str) -> Vec<String> {
+    if table.contains_key(name) {
+        return vec![name.to_string()];
+    }
+    if let Some((key, rest)) = longest_key(table, name)
+        && let Some(Value::Table(child)) = table.get(key)
+    {
+        let mut path = vec![key.to_string()];
+        path.extend(env_path(child, rest));
+        return path;
+    }
+    vec![name.to_string()]
+}
+
+/// Applies `overrides` to `config` in order. `check` deserializes the result after each
+/// one, so a bad value is reported with the path that introduced it.
+pub(crate) fn apply(
+    config: &mut Table,
+    schema: &Table,
+    overrides: &[Override],
+    check: impl Fn(&Table) -> Result<(), String>,
+) -> Result<()> {
+    for item in overrides {
+        apply_one(config, schema, item)
+            .and_then(|()| check(config))
+            .map_err(|err| anyhow!("{} {}: {err}", item.origin, item.dotted()))?;
+    }
+    Ok(())
+}
+
+fn apply_one(config: &mut Table, schema: &Table, item: &Override) -> Result<(), String> {
+    let (key, parents) = item.path.split_last().ok_or("empty path")?;
+    let section = &item.path[0];
+    if !schema.contains_key(section) {
+        return Err(format!("unknown section `{section}`"));
+    }
+
+    let mut expected = Some(schema);
+    let mut table = config;
+    for (depth, segment) in parents.iter().enumerate() {
+        let at = || item.path[..=depth].join(".");
+        expected = match expected.and_then(|schema| schema.get(segment)) {
+            Some(Value::Table(child)) => Some(child),
+            Some(_) => return Err(format!("`{}` is not a table", at())),
+            None => None,
+        };
+        table = match table
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(child) => child,
+            _ => return Err(format!("`{}` is not a table", at())),
+        };
+    }
+
+    let value = coerce(&item.raw, expected.and_then(|schema| schema.get(key)))?;
+    table.insert(key.clone(), value);
+    Ok(())
+}
+
+/// `raw` as a value of the type `expected` has; unknown keys take strings when the text
+/// is not a TOML value.
+fn coerce(raw: &str, expected: Option<&Value>) -> Result<Value, String> {
+    match expected {
+        Some(Value::String(_)) => Ok(Value::String(raw.to_string())),
+        Some(Value::Array(items)) if !raw.trim_start().starts_with('[') => raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| match items.first() {
+                Some(first) => coerce(item, Some(first)),
+                None => Ok(Value::String(item.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Some(expected) => {
+            parse_value(raw).ok_or_else(|| format!("expected {}, got {raw:?}", expected.type_str()))
+        }
+        None => Ok(parse_value(raw).unwrap_or_else(|| Value::String(raw.to_string()))),
+    }
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    format!("value = {}", raw.trim())
+        .parse::<Table>()
+        .ok()?
+        .remove("value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Table {
+        r#"
+            [source]
+            capabilities = ["body", "actions"]
+            [ui]
+            width = 420
+            anchor = "top-right"
+            outputs = []
+            [ui.timeout_progress]
+            height = 3
+            [ui.dnd]
+            schedule = []
+            [log]
+            level = "info"
+        "#
+        .parse()
+        .unwrap()
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<Override> {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        from_env(vars, &schema())
+    }
+
+    fn applied(config: &str, overrides: &[Override]) -> Result<Table> {
+        let mut config: Table = config.parse().unwrap();
+        apply(&mut config, &schema(), overrides, |table| {
+            match table.get("ui").and_then(|ui| ui.get("width")) {
+                Some(Value::Integer(_)) | None => Ok(()),
+                Some(other) => Err(format!("invalid type: {}", other.type_str())),
+            }
+        })?;
+        Ok(config)
+    }
+
+    #[test]
+    fn env_names_resolve_to_nested_keys() {
+        let paths: Vec<String> = env(&[
+            ("WISPD_UI_TIMEOUT_PROGRESS_HEIGHT", "5"),
+            ("WISPD_UI_WIDTH", "300"),
+            ("WISPD_UI_MIN_TIMEOUT_MS", "1000"),
+            ("WISPD_MONITOR_FORMAT", "json"),
+            ("WISPD_UI", "x"),
+            ("HOME", "/home/me"),
+        ])
+        .iter()
+        .map(Override::dotted)
+        .collect();
+        assert_eq!(
+            paths,
+            [
+                "ui.min_timeout_ms",
+                "ui.timeout_progress.height",
+                "ui.width"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_take_the_type_of_the_key_they_replace() {
+        let config = applied(
+            "[ui]\nwidth = 400\n[ui.timeout_progress]\nposition = \"top\"",
+            &[
+                parse_set("ui.width=300").unwrap(),
+                parse_set("ui.anchor=300").unwrap(),
+                parse_set("ui.timeout_progress.height=5").unwrap(),
+                parse_set("ui.new_key=true").unwrap(),
+                parse_set("log.file=~/wispd.log").unwrap(),
+            ],
+        )
+        .unwrap();
+        let ui = config["ui"].as_table().unwrap();
+        assert_eq!(ui["width"], Value::Integer(300));
+        assert_eq!(ui["anchor"], Value::String("300".to_string()));
+        assert_eq!(ui["new_key"], Value::Boolean(true));
+        let progress = ui["timeout_progress"].as_table().unwrap();
+        assert_eq!(progress["height"], Value::Integer(5));
+        assert_eq!(progress["position"].as_str(), Some("top"), "siblings kept");
+        assert_eq!(config["log"]["file"].as_str(), Some("~/wispd.log"));
+    }
+
+    #[test]
+    fn lists_take_toml_arrays_or_comma_separated_items() {
+        let config = applied(
+            "",
+            &[
+                env(&[("WISPD_SOURCE_CAPABILITIES", "body, actions,icon-static")]).remove(0),
+                parse_set(r#"ui.outputs=["DP-1", "HDMI-A-1"]"#).unwrap(),
+                parse_set(r#"ui.dnd.schedule=[{ from = "22:00", until = "07:00" }]"#).unwrap(),
+            ],
+        )
+        .unwrap();
+        let strings = |value: &Value| -> Vec<String> {
+            let items = value.as_array().unwrap();
+            items
+                .iter()
+                .map(|v| v.as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            strings(&config["source"]["capabilities"]),
+            ["body", "actions", "icon-static"]
+        );
+        assert_eq!(strings(&config["ui"]["outputs"]), ["DP-1", "HDMI-A-1"]);
+        let schedule = config["ui"]["dnd"]["schedule"].as_array().unwrap();
+        assert_eq!(schedule[0]["from"].as_str(), Some("22:00"));
+    }
+
+    #[test]
+    fn later_overrides_win_and_errors_name_their_path() {
+        let mut overrides = env(&[("WISPD_UI_WIDTH", "300")]);
+        overrides.push(parse_set("ui.width=500").unwrap());
+        let config = applied("[ui]\nwidth = 400", &overrides).unwrap();
+        assert_eq!(config["ui"]["width"], Value::Integer(500));
+
+        let err = |set: &str| {
+            applied("", &[parse_set(set).unwrap()])
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err("ui.width=wide"),
+            "--set ui.width: expected integer, got \"wide\""
+        );
+        assert_eq!(
+            err("theme.width=1"),
+            "--set theme.width: unknown section `theme`"
+        );
+        assert_eq!(
+            err("ui.width.px=1"),
+            "--set ui.width.px: `ui.width` is not a table"
+        );
+        let unknown = env(&[("WISPD_UI_NEW_WIDTH", "x")]);
+        assert_eq!(unknown[0].dotted(), "ui.new_width", "unknown keys are kept");
+
+        let mut overrides = env(&[("WISPD_LOG_LEVEL", "debug")]);
+        overrides.push(parse_set("ui.width=1.5").unwrap());
+        let err = applied("", &overrides).unwrap_err().to_string();
+        assert_eq!(err, "--set ui.width: invalid type: float");
+
+        assert!(parse_set("ui.width").is_err());
+        assert!(parse_set("ui..width=1").is_err());
+        assert!(parse_set("=1").is_err());
+    }
+}
@@ -0,0 +1,1174 @@
+//! What a popup card shows, resolved from [`WispdUi`] before any widget exists: colors
+//! after the style layers and fades, the header parts, action buttons with their
+//! ellipsized labels and accelerators, the icon, the `value` gauge, the timeout bar, and
+//! which controls answer clicks. `view` only turns a [`PopupModel`] into widgets, so everything it
+//! decides can be checked here without a renderer.
+
+use std::{fmt, path::PathBuf, time::Instant};
+
+use wisp_types::Urgency;
+
+use super::{
+    TimeoutProgressConfig, UiAction, UiNotification, WispdUi,
+    accelerators::{self, Accelerator},
+    action_icon_path, action_label_char_budget,
+    animation::Presence,
+    body_block,
+    category::CategoryIcon,
+    close_font_size, content_width_px, ellipsize_label,
+    glyphs::CloseFace,
+    kde, measure_body, parse_hex_color, renderable_icon_path, style,
+};
+
+/// Actions per button row.
+const ACTIONS_PER_ROW: usize = 3;
+/// Invoked by clicking the card, so it needs no affordance of its own.
+const DEFAULT_ACTION: &str = "default";
+
+/// A color with straight alpha, every channel in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Rgba {
+    pub(crate) r: f32,
+    pub(crate) g: f32,
+    pub(crate) b: f32,
+    pub(crate) a: f32,
+}
+
+impl fmt::Display for Rgba {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let byte = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+        write!(
+            f,
+            "#{:02x}{:02x}{:02x}{:02x}",
+            byte(self.r),
+            byte(self.g),
+            byte(self.b),
+            byte(self.a)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ButtonColors {
+    pub(crate) text: Rgba,
+    pub(crate) background: Rgba,
+    pub(crate) border: Rgba,
+    pub(crate) hover_background: Rgba,
+    pub(crate) hover_text: Rgba,
+}
+
+/// Every color of a card, as drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CardColors {
+    pub(crate) border: Rgba,
+    pub(crate) background: Rgba,
+    pub(crate) text: Rgba,
+    pub(crate) progress: Rgba,
+    pub(crate) value_progress: Rgba,
+    pub(crate) app_name: Rgba,
+    pub(crate) summary: Rgba,
+    pub(crate) body: Rgba,
+    pub(crate) button: ButtonColors,
+}
+
+/// Font families by config name, resolved by `view`, and sizes in pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Fonts {
+    pub(crate) family: String,
+    /// `ui.buttons.font_family`; unset uses `family`.
+    pub(crate) button_family: Option<String>,
+    pub(crate) app_name_size: u32,
+    pub(crate) summary_size: u32,
+    pub(crate) body_size: u32,
+    pub(crate) button_size: u32,
+    /// Close, pin and copy buttons, badges and the origin.
+    pub(crate) small_size: u32,
+}
+
+/// The escalation badge before the app name: "!" then "!2", "!3", ...
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EscalationBadge {
+    pub(crate) label: String,
+    pub(crate) color: Rgba,
+}
+
+/// The top line; parts that are unset are not drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Header {
+    pub(crate) escalation: Option<EscalationBadge>,
+    pub(crate) category: Option<CategoryIcon>,
+    pub(crate) app_name: Option<String>,
+    /// "via <device>" for KDE Connect relays.
+    pub(crate) origin: Option<String>,
+    pub(crate) summary: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Pin {
+    Hidden,
+    /// The toggle on a hovered popup; `pressed` keeps the hover look while pinned.
+    Button {
+        glyph: String,
+        pressed: bool,
+    },
+    /// A pinned popup that is not hovered.
+    Marker(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ActionButton {
+    pub(crate) key: String,
+    pub(crate) label: String,
+    /// The whole label, shown as a tooltip, when `label` is ellipsized or `icon` stands in
+    /// for it.
+    pub(crate) full_label: Option<String>,
+    /// Drawn in place of the label for `action-icons` notifications whose key resolves.
+    pub(crate) icon: Option<PathBuf>,
+    /// Shown in keyboard mode only.
+    pub(crate) accelerator: Option<Accelerator>,
+    pub(crate) enabled: bool,
+}
+
+/// What sits under the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Controls {
+    None,
+    /// "Really …?" with confirm and cancel buttons.
+    Confirm {
+        prompt: String,
+    },
+    /// Rows of up to [`ACTIONS_PER_ROW`] buttons.
+    Actions(Vec<Vec<ActionButton>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Icon {
+    /// Key into the image cache; the view looks the decoded image up by it.
+    pub(crate) path: PathBuf,
+    /// `false` until the decode finishes; the space is reserved meanwhile.
+    pub(crate) loaded: bool,
+    pub(crate) size: f32,
+}
+
+/// The bar along a timed popup's top or bottom edge; a rounded track holding the fill.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TimeoutBar {
+    /// Share of the timeout that has passed.
+    pub(crate) fraction: f32,
+    pub(crate) top: bool,
+    pub(crate) height: f32,
+    pub(crate) track_width: f32,
+    pub(crate) corner_radius: f32,
+    pub(crate) track_color: Rgba,
+    /// Space above and below the track.
+    pub(crate) gap_above: f32,
+    pub(crate) gap_below: f32,
+    /// Horizontal padding on both sides of the track.
+    pub(crate) side_padding: u16,
+}
+
+/// The gauge a `value` hint draws under the summary, styled like the timeout bar's
+/// track with its own fill.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Gauge {
+    /// The hint clamped to `0..=100`.
+    pub(crate) percent: u16,
+    pub(crate) height: f32,
+    pub(crate) corner_radius: f32,
+    pub(crate) track_color: Rgba,
+}
+
+/// Which of a popup's actions get buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ActionVisibility {
+    /// Buttons drawn, counting from the first action.
+    pub(crate) shown: usize,
+    /// The header shows the affordance that expands the hidden actions.
+    pub(crate) affordance: bool,
+}
+
+/// Up to `max_visible` actions get buttons until the popup is `expanded`. The affordance
+/// shows while actions that can be invoked are hidden, unless the only hidden one is
+/// `default`, which a card click invokes anyway.
+pub(crate) fn action_visibility(
+    max_visible: Option<usize>,
+    actions: &[UiAction],
+    expanded: bool,
+    invocable: bool,
+) -> ActionVisibility {
+    let shown = match max_visible {
+        Some(max) if !expanded => max.min(actions.len()),
+        _ => actions.len(),
+    };
+    ActionVisibility {
+        shown,
+        affordance: invocable
+            && actions[shown..]
+                .iter()
+                .any(|action| action.key != DEFAULT_ACTION),
+    }
+}
+
+/// The notification a popup window draws and how it is on screen.
+pub(crate) struct PopupSlot<'a> {
+    pub(crate) notification: &'a UiNotification,
+    pub(crate) height: f32,
+    pub(crate) presence: Presence,
+    /// Playing its exit animation.
+    pub(crate) closing: bool,
+    /// Shown as a critical takeover.
+    pub(crate) takeover: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PopupModel {
+    pub(crate) id: u32,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    pub(crate) padding: u16,
+    pub(crate) colors: CardColors,
+    pub(crate) fonts: Fonts,
+    pub(crate) header: Option<Header>,
+    pub(crate) gauge: Option<Gauge>,
+    /// Broken into lines where the height estimate wrapped it.
+    pub(crate) body: Option<String>,
+    /// The label of the first `x-kde-urls` entry, with `ui.show_kde_urls`.
+    pub(crate) url: Option<String>,
+    /// Small labels between the text and the buttons: queue counts, "from history",
+    /// "copied".
+    pub(crate) badges: Vec<String>,
+    /// The affordance's glyph while `ui.buttons.max_visible_actions` hides actions.
+    pub(crate) more_actions: Option<String>,
+    /// The copy button's glyph, on a hovered popup with `ui.show_copy_button`.
+    pub(crate) copy_button: Option<String>,
+    pub(crate) pin: Pin,
+    pub(crate) close: CloseFace,
+    pub(crate) controls: Controls,
+    /// Widest an action button may grow.
+    pub(crate) action_max_width: f32,
+    pub(crate) icon: Option<Icon>,
+    pub(crate) timeout_bar: Option<TimeoutBar>,
+    /// The close button and URL row work; off for mirrored notifications.
+    pub(crate) interactive: bool,
+    /// The card takes clicks and hover; off while measuring, activating, closing or
+    /// taking over, which only closes through its buttons.
+    pub(crate) clickable: bool,
+}
+
+impl PopupModel {
+    pub(crate) fn build(state: &WispdUi, slot: &PopupSlot<'_>, now: Instant) -> Self {
+        let n = slot.notification;
+        let ui = &state.ui;
+        let is_measuring = state.pending_measure.contains(&n.id);
+        let is_activating = state.activating.contains(&n.id);
+        let interactive = state.is_interactive(n.id);
+        let hovered = state.hovered == Some(n.id) && !slot.closing;
+
+        let mut colors = state.resolve_style(n, now).colors;
+        if slot.presence != Presence::SHOWN {
+            colors = colors.faded(slot.presence.opacity());
+        }
+        let colors = CardColors::from(colors);
+
+        let fonts = Fonts {
+            family: ui.font_family.clone(),
+            button_family: ui.buttons.font_family.clone(),
+            app_name_size: ui.text.app_name.font_size.unwrap_or(ui.font_size) as u32,
+            summary_size: ui.text.summary.font_size.unwrap_or(ui.font_size) as u32,
+            body_size: ui.text.body.font_size.unwrap_or(ui.font_size) as u32,
+            button_size: ui.buttons.font_size.unwrap_or(ui.font_size) as u32,
+            small_size: close_font_size(ui),
+        };
+
+        let outermost = state
+            .windows
+            .back()
+            .is_some_and(|w| w.notification_id == n.id);
+        let mut badges = Vec::new();
+        if outermost && !state.queued.is_empty() && !slot.closing {
+            badges.push(format!("+{}", state.queued.len()));
+        }
+        if slot.takeover && state.takeovers.waiting() > 0 {
+            badges.push(format!("+{}", state.takeovers.waiting()));
+        }
+        if state.historic.contains_key(&n.id) {
+            badges.push("from history".to_string());
+        }
+        if state.is_copied_flashing(n.id) {
+            badges.push("copied".to_string());
+        }
+
+        let pinned = state.is_pinned(n.id);
+        let pin = if ui.show_pin_button && interactive && hovered {
+            Pin::Button {
+                glyph: state.glyphs.pin.clone(),
+                pressed: pinned,
+            }
+        } else if pinned {
+            Pin::Marker(state.glyphs.pin.clone())
+        } else {
+            Pin::Hidden
+        };
+
+        let width = if slot.takeover {
+            ui.urgency.critical.width
+        } else {
+            ui.width
+        } as f32;
+        let icon_path = renderable_icon_path(ui.show_icons, &n.app_icon);
+        let has_icon = icon_path.is_some();
+        let actions = state.action_visibility(n.id);
+
+        Self {
+            id: n.id,
+            width,
+            height: slot.height,
+            padding: ui.padding,
+            colors,
+            fonts,
+            header: header(state, n),
+            gauge: n
+                .progress
+                .map(|value| gauge(&state.ui.timeout_progress, value)),
+            body: visible(&n.body).map(|body| {
+                let metrics = measure_body(
+                    &mut state.text_metrics.borrow_mut(),
+                    &n.body,
+                    body_block(ui, n),
+                );
+                metrics.wrapped(&body).into_owned()
+            }),
+            url: n
+                .url
+                .as_deref()
+                .filter(|_| ui.show_kde_urls)
+                .map(kde::url_label),
+            badges,
+            more_actions: actions.affordance.then(|| state.glyphs.actions.clone()),
+            copy_button: (ui.show_copy_button && hovered).then(|| state.glyphs.copy.clone()),
+            pin,
+            close: state.glyphs.close.clone(),
+            controls: controls(state, n, actions.shown, has_icon),
+            action_max_width: content_width_px(ui, has_icon) * ui.buttons.max_width,
+            icon: icon_path.map(|path| Icon {
+                loaded: state.images.handle(&path).is_some(),
+                path,
+                size: ui.max_icon_size.max(1) as f32,
+            }),
+            timeout_bar: timeout_bar(state, n.id, width),
+            interactive,
+            clickable: !(is_measuring || is_activating || slot.closing || slot.takeover),
+        }
+    }
+}
+
+fn visible(text: &str) -> Option<String> {
+    (!text.trim().is_empty()).then(|| text.to_string())
+}
+
+fn header(state: &WispdUi, n: &UiNotification) -> Option<Header> {
+    let app_name = visible(&n.header_name);
+    let summary = visible(&n.summary);
+    if app_name.is_none() && summary.is_none() {
+        return None;
+    }
+    let escalations = state.escalations.count(n.id);
+    Some(Header {
+        escalation: (escalations > 0).then(|| EscalationBadge {
+            label: match escalations {
+                1 => "!".to_string(),
+                count => format!("!{count}"),
+            },
+            color: style::urgency_color(&state.ui.colors, Urgency::Critical).into(),
+        }),
+        category: state.ui.category_icon(n),
+        app_name,
+        origin: n.origin.as_ref().map(|origin| format!("via {origin}")),
+        summary,
+    })
+}
+
+fn controls(state: &WispdUi, n: &UiNotification, shown: usize, has_icon: bool) -> Controls {
+    let budget = action_label_char_budget(&state.ui, has_icon);
+    if let Some(pending) = state.confirmations.get(n.id) {
+        let prompt = format!("Really {}?", pending.label.to_lowercase());
+        return Controls::Confirm {
+            prompt: ellipsize_label(&prompt, budget).into_owned(),
+        };
+    }
+    if shown == 0 {
+        return Controls::None;
+    }
+
+    let keyboard_mode = state.keyboard_target == Some(n.id);
+    let enabled = state.actions_enabled(n.id);
+    let icon_size = state.ui.buttons.font_size.unwrap_or(state.ui.font_size) as u32;
+    let buttons: Vec<ActionButton> = n.actions[..shown]
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let label = ellipsize_label(&action.label, budget).into_owned();
+            let icon = n
+                .action_icons
+                .then(|| action_icon_path(state.ui.show_icons, &action.key, icon_size))
+                .flatten();
+            ActionButton {
+                key: action.key.clone(),
+                full_label: (label != action.label || icon.is_some()).then(|| action.label.clone()),
+                icon,
+                label,
+                accelerator: keyboard_mode
+                    .then(|| accelerators::accelerator(action, index))
+                    .flatten(),
+                enabled,
+            }
+        })
+        .collect();
+    Controls::Actions(
+        buttons
+            .chunks(ACTIONS_PER_ROW)
+            .map(<[ActionButton]>::to_vec)
+            .collect(),
+    )
+}
+
+fn gauge(cfg: &TimeoutProgressConfig, value: i32) -> Gauge {
+    Gauge {
+        percent: value.clamp(0, 100) as u16,
+        height: cfg.height.max(1) as f32,
+        corner_radius: cfg.corner_radius.max(0.0),
+        track_color: track_color(cfg),
+    }
+}
+
+fn track_color(cfg: &TimeoutProgressConfig) -> Rgba {
+    parse_hex_color(&cfg.track_color).map_or(
+        Rgba {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 0.08,
+        },
+        Rgba::from,
+    )
+}
+
+fn timeout_bar(state: &WispdUi, id: u32, card_width: f32) -> Option<TimeoutBar> {
+    if !state.ui.show_timeout_progress {
+        return None;
+    }
+    let fraction = state.timeout_progress_for(id)?;
+    let cfg = &state.ui.timeout_progress;
+    let padding = state.ui.padding;
+    let inset = cfg.inset as f32;
+    let track_width = (card_width - (padding as f32 + inset) * 2.0).max(1.0);
+    let gap = cfg.gap_px(padding) as f32;
+    let top = cfg.is_top();
+    Some(TimeoutBar {
+        fraction,
+        top,
+        height: cfg.height.max(1) as f32,
+        track_width,
+        corner_radius: cfg.corner_radius.max(0.0),
+        track_color: track_color(cfg),
+        gap_above: if top { gap + inset } else { gap },
+        gap_below: if top { gap } else { gap + inset },
+        side_padding: padding.saturating_add(cfg.inset),
+    })
+}
+
+/// A line-per-part text form for snapshot tests. Sizes and the timeout fraction are
+/// left out; they follow from config and the clock rather than from the notification.
+impl fmt::Display for PopupModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "popup {} width {} border {} background {}",
+            self.id, self.width, self.colors.border, self.colors.background
+        )?;
+        if let Some(header) = &self.header {
+            writeln!(f, "header: {header}")?;
+        }
+        if let Some(gauge) = &self.gauge {
+            writeln!(f, "gauge: {}%", gauge.percent)?;
+        }
+        if let Some(body) = &self.body {
+            writeln!(f, "body: {body:?}")?;
+        }
+        if let Some(url) = &self.url {
+            writeln!(f, "url: {url:?}")?;
+        }
+        if !self.badges.is_empty() {
+            writeln!(f, "badges: {}", self.badges.join(", "))?;
+        }
+        write!(f, "buttons:")?;
+        if let Some(more) = &self.more_actions {
+            write!(f, " more {more}")?;
+        }
+        if let Some(copy) = &self.copy_button {
+            write!(f, " copy {copy}")?;
+        }
+        match &self.pin {
+            Pin::Hidden => {}
+            Pin::Button { glyph, pressed } => {
+                write!(
+                    f,
+                    " pin {glyph}{}",
+                    if *pressed { " (pressed)" } else { "" }
+                )?;
+            }
+            Pin::Marker(glyph) => write!(f, " pinned {glyph}")?,
+        }
+        match &self.close {
+            CloseFace::Glyph(glyph) => writeln!(f, " close {glyph}")?,
+            CloseFace::Icon(path) => writeln!(f, " close {}", path.display())?,
+        }
+        match &self.controls {
+            Controls::None => {}
+            Controls::Confirm { prompt } => writeln!(f, "confirm: {prompt:?}")?,
+            Controls::Actions(rows) => {
+                for row in rows {
+                    let buttons: Vec<String> = row.iter().map(ToString::to_string).collect();
+                    writeln!(f, "actions: {}", buttons.join("; "))?;
+                }
+            }
+        }
+        if let Some(icon) = &self.icon {
+            let name = icon.path.file_name().unwrap_or_default();
+            let loading = if icon.loaded { "" } else { " (loading)" };
+            writeln!(f, "icon: {}{loading}", name.to_string_lossy())?;
+        }
+        if let Some(bar) = &self.timeout_bar {
+            writeln!(f, "timeout: {}", if bar.top { "top" } else { "bottom" })?;
+        }
+        write!(f, "flags:")?;
+        for (set, flag) in [
+            (self.interactive, "interactive"),
+            (self.clickable, "clickable"),
+        ] {
+            if set {
+                write!(f, " {flag}")?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(badge) = &self.escalation {
+            parts.push(format!("escalated {} {}", badge.label, badge.color));
+        }
+        match &self.category {
+            Some(CategoryIcon::Glyph(glyph)) => parts.push(format!("category {glyph}")),
+            Some(CategoryIcon::Image(path)) => parts.push(format!("category {}", path.display())),
+            None => {}
+        }
+        if let Some(app_name) = &self.app_name {
+            parts.push(format!("app {app_name:?}"));
+        }
+        if let Some(origin) = &self.origin {
+            parts.push(format!("{origin:?}"));
+        }
+        if let Some(summary) = &self.summary {
+            parts.push(format!("summary {summary:?}"));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+impl fmt::Display for ActionButton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} -> {}", self.label, self.key)?;
+        if let Some(icon) = &self.icon {
+            write!(f, " (icon {})", icon.display())?;
+        }
+        match self.accelerator {
+            Some(Accelerator::Mnemonic(key)) => write!(f, " [{key}]")?,
+            Some(Accelerator::Number(position)) => write!(f, " [{position}]")?,
+            None => {}
+        }
+        if let Some(full) = &self.full_label {
+            write!(f, " (full {full:?})")?;
+        }
+        if !self.enabled {
+            f.write_str(" (disabled)")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        sync::{Arc, Mutex, mpsc},
+    };
+
+    use iced::widget::image::Handle;
+    use tokio::sync::mpsc as tokio_mpsc;
+    use wisp_types::{Notification, NotificationAction, NotificationEvent, NotificationHints};
+
+    use super::super::{
+        Message, UiSection, estimate_popup_height,
+        images::{self, Decoder},
+        mutes::MuteList,
+        testing::temp_dir,
+        text_metrics::TextMetricsCache,
+        update,
+    };
+    use super::*;
+
+    fn ui_with(ui_cfg: UiSection) -> WispdUi {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, _cmd_rx) = tokio_mpsc::unbounded_channel();
+        WispdUi::new(
+            Arc::new(Mutex::new(event_rx)),
+            Arc::new(Mutex::new(signal_rx)),
+            Arc::new(Mutex::new(reply_rx)),
+            cmd_tx,
+            ui_cfg,
+            None,
+            MuteList::in_memory(),
+        )
+    }
+
+    fn receive(ui: &mut WispdUi, id: u32, notification: Notification) {
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id,
+            notification: Box::new(notification),
+            silent: false,
+            remaining_ms: None,
+        });
+    }
+
+    fn model(ui: &WispdUi, id: u32) -> PopupModel {
+        let slot = PopupSlot {
+            notification: &ui.notifications[&id],
+            height: 80.0,
+            presence: Presence::SHOWN,
+            closing: false,
+            takeover: ui.takeovers.showing() == Some(id),
+        };
+        PopupModel::build(ui, &slot, Instant::now())
+    }
+
+    fn snapshot(ui: &WispdUi, id: u32) -> String {
+        model(ui, id).to_string()
+    }
+
+    fn actions(pairs: &[(&str, &str)]) -> Vec<NotificationAction> {
+        pairs
+            .iter()
+            .map(|(key, label)| NotificationAction {
+                key: key.to_string(),
+                label: label.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn icons_headers_and_bodies_show_as_sent() {
+        let dir = temp_dir("popup-model");
+        let icon = dir.join("mail.png");
+        fs::write(&icon, b"png").unwrap();
+
+        let mut ui = ui_with(UiSection::default());
+        let mut mail_hints = NotificationHints::default();
+        mail_hints.category = Some("email.arrived".to_string());
+        receive(
+            &mut ui,
+            1,
+            Notification {
+                app_name: "Mail".to_string(),
+                app_icon: icon.display().to_string(),
+                summary: "Ada".to_string(),
+                body: "Lunch?".to_string(),
+                timeout_ms: 60_000,
+                hints: mail_hints,
+                ..Notification::default()
+            },
+        );
+        receive(
+            &mut ui,
+            2,
+            Notification {
+                app_name: "Chat".to_string(),
+                body: "<b>Bold</b> &amp; plain".to_string(),
+                ..Notification::default()
+            },
+        );
+        assert!(
+            snapshot(&ui, 1)
+                .starts_with("popup 1 width 420 border #00000000 background #00000000\n"),
+            "invisible while measured"
+        );
+        assert!(!model(&ui, 1).clickable);
+        ui.pending_measure.clear();
+
+        assert_eq!(
+            snapshot(&ui, 1),
+            "popup 1 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: category ✉, app \"Mail\", summary \"Ada\"\n\
+             body: \"Lunch?\"\n\
+             buttons: close ✕\n\
+             icon: mail.png (loading)\n\
+             timeout: bottom\n\
+             flags: interactive clickable\n"
+        );
+        // Without `body-markup` markup is text.
+        assert_eq!(
+            snapshot(&ui, 2),
+            "popup 2 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Chat\"\n\
+             body: \"<b>Bold</b> &amp; plain\"\n\
+             buttons: close ✕\n\
+             flags: interactive clickable\n"
+        );
+
+        let bar = model(&ui, 1).timeout_bar.expect("timed popup has a bar");
+        assert!((0.0..0.5).contains(&bar.fraction));
+        assert_eq!(bar.track_width, 400.0);
+        assert!(model(&ui, 2).timeout_bar.is_none(), "never expires");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn many_actions_wrap_ellipsize_and_show_accelerators() {
+        let mut ui_cfg = UiSection::default();
+        ui_cfg.buttons.max_label_chars = 12;
+        let mut ui = ui_with(ui_cfg);
+        receive(
+            &mut ui,
+            3,
+            Notification {
+                app_name: "Thunderbird".to_string(),
+                summary: "3 new messages".to_string(),
+                actions: actions(&[
+                    ("open", "Open"),
+                    ("reply", "_Reply to everyone"),
+                    ("archive", "Archive"),
+                    ("snooze", "Snooze"),
+                    ("delete", "Delete"),
+                ]),
+                ..Notification::default()
+            },
+        );
+        ui.pending_measure.clear();
+        let without_keyboard = snapshot(&ui, 3);
+        assert!(without_keyboard.contains("\"Open\" -> open; \"Reply to ev…\""));
+
+        ui.keyboard_target = Some(3);
+        ui.hovered = Some(3);
+        assert_eq!(
+            snapshot(&ui, 3),
+            "popup 3 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Thunderbird\", summary \"3 new messages\"\n\
+             buttons: pin 📌 close ✕\n\
+             actions: \"Open\" -> open [1]; \"Reply to ev…\" -> reply [R] (full \"Reply to everyone\"); \"Archive\" -> archive [3]\n\
+             actions: \"Snooze\" -> snooze [4]; \"Delete\" -> delete [5]\n\
+             flags: interactive clickable\n"
+        );
+    }
+
+    #[test]
+    fn bodies_are_drawn_wrapped_as_their_height_was_estimated() {
+        let mut ui = ui_with(UiSection::default());
+        receive(
+            &mut ui,
+            1,
+            Notification {
+                summary: "Build".to_string(),
+                body: format!("{}\nlog: {}", ["step passed"; 12].join(" "), "x".repeat(90)),
+                ..Notification::default()
+            },
+        );
+        estimate_popup_height(
+            &ui.ui,
+            &ui.notifications[&1],
+            &mut ui.text_metrics.borrow_mut(),
+        );
+        let before = ui.text_metrics.borrow().stats();
+        let body = model(&ui, 1).body.unwrap();
+        let after = ui.text_metrics.borrow().stats();
+        assert_eq!(
+            after.misses, before.misses,
+            "drawn from the estimate's measurement"
+        );
+        assert_eq!(after.hits, before.hits + 1);
+
+        let n = &ui.notifications[&1];
+        let block = body_block(&ui.ui, n);
+        let measured = measure_body(&mut ui.text_metrics.borrow_mut(), &n.body, block);
+        assert!(measured.lines > 2, "{measured:?}");
+        assert_eq!(body.lines().count(), measured.lines);
+        assert!(body.lines().all(|line| !line.ends_with(' ')));
+        let letters = |text: &str| text.split_whitespace().collect::<String>();
+        assert_eq!(letters(&body), letters(&n.body), "only whitespace moves");
+    }
+
+    #[test]
+    fn action_icons_replace_labels_that_resolve() {
+        let dir = temp_dir("action-icons");
+        let play = dir.join("play.png");
+        fs::write(&play, b"png").unwrap();
+        let player = |action_icons| {
+            let mut hints = NotificationHints::default();
+            hints.action_icons = action_icons;
+            Notification {
+                app_name: "Player".to_string(),
+                actions: actions(&[
+                    (&play.display().to_string(), "Play"),
+                    ("x-wisp-no-such-icon", "Next"),
+                ]),
+                hints,
+                ..Notification::default()
+            }
+        };
+        let buttons = |ui: &WispdUi, id| match model(ui, id).controls {
+            Controls::Actions(rows) => rows.concat(),
+            other => panic!("expected actions, got {other:?}"),
+        };
+
+        let mut ui = ui_with(UiSection::default());
+        receive(&mut ui, 1, player(Some(true)));
+        receive(&mut ui, 2, player(None));
+        let icons = buttons(&ui, 1);
+        assert_eq!(icons[0].icon.as_deref(), Some(play.as_path()));
+        assert_eq!(
+            icons[0].full_label.as_deref(),
+            Some("Play"),
+            "annotates the icon"
+        );
+        assert_eq!(icons[1].icon, None, "falls back to the label");
+        assert_eq!(icons[1].label, "Next");
+        assert!(buttons(&ui, 2).iter().all(|b| b.icon.is_none()), "no hint");
+
+        let mut ui_cfg = UiSection::default();
+        ui_cfg.show_icons = false;
+        let mut ui = ui_with(ui_cfg);
+        receive(&mut ui, 1, player(Some(true)));
+        assert!(
+            buttons(&ui, 1).iter().all(|b| b.icon.is_none()),
+            "icons off"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn value_gauges_clamp_and_follow_replacements() {
+        let volume = |value| {
+            let mut hints = NotificationHints::default();
+            hints.progress = value;
+            Notification {
+                app_name: "Volume".to_string(),
+                summary: "Volume".to_string(),
+                body: "Speakers".to_string(),
+                hints,
+                ..Notification::default()
+            }
+        };
+        let percent = |ui: &WispdUi| model(ui, 1).gauge.map(|gauge| gauge.percent);
+
+        let mut ui = ui_with(UiSection::default());
+        receive(&mut ui, 1, volume(Some(40)));
+        ui.pending_measure.clear();
+        assert_eq!(
+            snapshot(&ui, 1),
+            "popup 1 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Volume\", summary \"Volume\"\n\
+             gauge: 40%\n\
+             body: \"Speakers\"\n\
+             buttons: close ✕\n\
+             flags: interactive clickable\n"
+        );
+
+        for (sent, shown) in [(65, 65), (150, 100), (-5, 0)] {
+            let _ = ui.apply_event(NotificationEvent::Replaced {
+                id: 1,
+                previous: Box::new(volume(None)),
+                current: Box::new(volume(Some(sent))),
+                remaining_ms: None,
+                repeat_count: 0,
+            });
+            assert_eq!(ui.notifications[&1].progress, Some(sent), "kept as sent");
+            assert_eq!(percent(&ui), Some(shown));
+        }
+
+        let mut metrics = TextMetricsCache::new(0);
+        let with_gauge = estimate_popup_height(&ui.ui, &ui.notifications[&1], &mut metrics);
+        receive(&mut ui, 2, volume(None));
+        ui.pending_measure.clear();
+        assert!(model(&ui, 2).gauge.is_none(), "no hint, no gauge");
+        assert!(
+            estimate_popup_height(&ui.ui, &ui.notifications[&2], &mut metrics) < with_gauge,
+            "the gauge takes height"
+        );
+    }
+
+    #[test]
+    fn hidden_actions_get_an_affordance_that_expands_them() {
+        let action = |key: &str| UiAction {
+            key: key.to_string(),
+            label: key.to_string(),
+            mnemonic: None,
+        };
+        let three = [action("default"), action("reply"), action("archive")];
+        let visibility = |max, actions: &[UiAction], expanded, invocable| {
+            let v = action_visibility(max, actions, expanded, invocable);
+            (v.shown, v.affordance)
+        };
+        assert_eq!(
+            visibility(None, &three, false, true),
+            (3, false),
+            "no limit"
+        );
+        assert_eq!(visibility(Some(0), &three, false, true), (0, true));
+        assert_eq!(visibility(Some(1), &three, false, true), (1, true));
+        assert_eq!(visibility(Some(5), &three, false, true), (3, false));
+        assert_eq!(
+            visibility(Some(0), &three, true, true),
+            (3, false),
+            "expanded"
+        );
+        assert_eq!(
+            visibility(Some(0), &three, false, false),
+            (0, false),
+            "mirrored and stale history popups cannot invoke anything"
+        );
+        assert_eq!(
+            visibility(Some(0), &[action("default")], false, true),
+            (0, false),
+            "a card click invokes default"
+        );
+        assert_eq!(
+            visibility(
+                Some(2),
+                &[action("reply"), action("archive"), action("default")],
+                false,
+                true
+            ),
+            (2, false)
+        );
+        assert_eq!(visibility(Some(0), &[], false, true), (0, false));
+
+        let mut ui_cfg = UiSection::default();
+        ui_cfg.buttons.max_visible_actions = Some(0);
+        let mut ui = ui_with(ui_cfg.clone());
+        receive(
+            &mut ui,
+            7,
+            Notification {
+                app_name: "Mail".to_string(),
+                summary: "Ada".to_string(),
+                actions: actions(&[("reply", "Reply"), ("archive", "Archive")]),
+                ..Notification::default()
+            },
+        );
+        ui.pending_measure.clear();
+        assert_eq!(
+            snapshot(&ui, 7),
+            "popup 7 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Mail\", summary \"Ada\"\n\
+             buttons: more ⚡ close ✕\n\
+             flags: interactive clickable\n"
+        );
+        let n = &ui.notifications[&7];
+        let mut metrics = TextMetricsCache::new(0);
+        assert!(
+            estimate_popup_height(&ui_cfg, n, &mut metrics)
+                < estimate_popup_height(&UiSection::default(), n, &mut metrics),
+            "hidden rows take no height"
+        );
+
+        let _ = update(&mut ui, Message::ExpandActionsClicked { id: 7 });
+        assert!(ui.pending_measure.remove(&7), "measured again");
+        assert_eq!(
+            snapshot(&ui, 7),
+            "popup 7 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Mail\", summary \"Ada\"\n\
+             buttons: close ✕\n\
+             actions: \"Reply\" -> reply; \"Archive\" -> archive\n\
+             flags: interactive clickable\n"
+        );
+    }
+
+    /// Decodes every file to one pixel, except `broken.png`.
+    #[derive(Debug)]
+    struct FakeDecoder;
+
+    impl Decoder for FakeDecoder {
+        fn decode(&self, path: &Path) -> Result<Handle, String> {
+            if path.ends_with("broken.png") {
+                return Err("unsupported image format".to_string());
+            }
+            Ok(Handle::from_rgba(1, 1, vec![0, 0, 0, 255]))
+        }
+    }
+
+    #[tokio::test]
+    async fn icons_fill_their_reserved_space_and_broken_ones_collapse() {
+        let dir = temp_dir("popup-icons");
+        let good = dir.join("good.png");
+        let broken = dir.join("broken.png");
+        fs::write(&good, b"png").unwrap();
+        fs::write(&broken, b"png").unwrap();
+
+        let ui_cfg = UiSection {
+            max_icon_size: 96,
+            ..UiSection::default()
+        };
+        let mut ui = ui_with(ui_cfg.clone());
+        ui.decoder = Arc::new(FakeDecoder);
+        for (id, icon) in [(1, &good), (2, &broken), (3, &good)] {
+            receive(
+                &mut ui,
+                id,
+                Notification {
+                    app_name: "Shots".to_string(),
+                    app_icon: icon.display().to_string(),
+                    summary: "Saved".to_string(),
+                    ..Notification::default()
+                },
+            );
+        }
+        ui.pending_measure.clear();
+        let mut metrics = TextMetricsCache::new(0);
+        let reserved = estimate_popup_height(&ui_cfg, &ui.notifications[&2], &mut metrics);
+        assert!(snapshot(&ui, 1).contains("icon: good.png (loading)\n"));
+        assert!(snapshot(&ui, 2).contains("icon: broken.png (loading)\n"));
+
+        for path in [good.clone(), broken.clone()] {
+            let (path, handle) = images::decode(Arc::clone(&ui.decoder), path).await;
+            let _ = update(
+                &mut ui,
+                Message::ImageReady {
+                    id: 1,
+                    path,
+                    handle,
+                },
+            );
+        }
+        assert!(snapshot(&ui, 1).contains("icon: good.png\n"));
+        assert!(
+            snapshot(&ui, 3).contains("icon: good.png\n"),
+            "one decode serves every popup with the file"
+        );
+        assert_eq!(
+            estimate_popup_height(&ui_cfg, &ui.notifications[&1], &mut metrics),
+            reserved,
+            "the decoded icon fits the reserved space"
+        );
+
+        assert!(model(&ui, 2).icon.is_none());
+        assert!(ui.pending_measure.contains(&2), "measured again");
+        assert!(!ui.pending_measure.contains(&1));
+        assert!(estimate_popup_height(&ui_cfg, &ui.notifications[&2], &mut metrics) < reserved);
+        receive(
+            &mut ui,
+            4,
+            Notification {
+                app_icon: broken.display().to_string(),
+                ..Notification::default()
+            },
+        );
+        assert!(
+            model(&ui, 4).icon.is_none(),
+            "known broken files reserve nothing"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn redacted_and_relayed_popups() {
+        let ui_cfg = UiSection {
+            privacy_apps: vec!["Signal".parse().unwrap()],
+            show_kde_urls: true,
+            ..UiSection::default()
+        };
+        let mut ui = ui_with(ui_cfg);
+        let mut relayed = NotificationHints::default();
+        relayed.urls = vec!["file:///home/me/code.txt".to_string()];
+        relayed.origin_name = Some("Pixel".to_string());
+        let mut named = NotificationHints::default();
+        named.display_app_name = Some("Dolphin".to_string());
+        named.urls = vec!["file:///home/me/Downloads/report.pdf".to_string()];
+        receive(
+            &mut ui,
+            5,
+            Notification {
+                app_name: "Signal".to_string(),
+                summary: "Ada".to_string(),
+                body: "the door code is 4711".to_string(),
+                hints: relayed,
+                ..Notification::default()
+            },
+        );
+        receive(
+            &mut ui,
+            6,
+            Notification {
+                app_name: "dolphin".to_string(),
+                summary: "Download finished".to_string(),
+                urgency: Urgency::Low,
+                hints: named,
+                ..Notification::default()
+            },
+        );
+        ui.pending_measure.clear();
+
+        assert_eq!(
+            snapshot(&ui, 5),
+            "popup 5 width 420 border #7dcf7dff background #1e1e2ecc\n\
+             header: app \"Signal\", \"via Pixel\", summary \"Ada\"\n\
+             body: \"New message\"\n\
+             buttons: close ✕\n\
+             flags: interactive clickable\n"
+        );
+        assert_eq!(
+            snapshot(&ui, 6),
+            "popup 6 width 420 border #6aa9ffff background #1e1e2ecc\n\
+             header: app \"Dolphin\", summary \"Download finished\"\n\
+             url: \"report.pdf\"\n\
+             buttons: close ✕\n\
+             flags: interactive clickable\n"
+        );
+    }
+
+    #[test]
+    fn a_critical_takeover_is_wide_counts_waiting_criticals_and_ignores_card_clicks() {
+        let mut ui_cfg = UiSection::default();
+        ui_cfg.urgency.critical.takeover = true;
+        let mut ui = ui_with(ui_cfg);
+        let critical = |summary: &str| Notification {
+            app_name: "Backup".to_string(),
+            summary: summary.to_string(),
+            body: "SMART errors on /dev/sda".to_string(),
+            urgency: Urgency::Critical,
+            actions: actions(&[("details", "Details")]),
+            ..Notification::default()
+        };
+        receive(&mut ui, 8, critical("Disk failing"));
+        receive(&mut ui, 9, critical("Disk failed"));
+        ui.pending_measure.clear();
+        assert_eq!(ui.takeovers.showing(), Some(8));
+
+        assert_eq!(
+            snapshot(&ui, 8),
+            "popup 8 width 640 border #ff6b6bff background #1e1e2ecc\n\
+             header: app \"Backup\", summary \"Disk failing\"\n\
+             body: \"SMART errors on /dev/sda\"\n\
+             badges: +1\n\
+             buttons: close ✕\n\
+             actions: \"Details\" -> details\n\
+             flags: interactive\n"
+        );
+    }
+}
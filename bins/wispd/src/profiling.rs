@@ -0,0 +1,162 @@
+//! Startup timings for the `profiling` feature: each stage runs inside a `startup` span
+//! and the first rendered popup logs one summary line. Without the feature every call
+//! here compiles to nothing.
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::{
+        sync::{
+            Mutex, OnceLock,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use tracing::{info, info_span, span::EnteredSpan};
+
+    static TIMINGS: OnceLock<Mutex<Timings>> = OnceLock::new();
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Debug)]
+    struct Timings {
+        started: Instant,
+        stages: Vec<(&'static str, Duration)>,
+        milestones: Vec<(&'static str, Duration)>,
+    }
+
+    fn timings() -> &'static Mutex<Timings> {
+        TIMINGS.get_or_init(|| {
+            Mutex::new(Timings {
+                started: Instant::now(),
+                stages: Vec::new(),
+                milestones: Vec::new(),
+            })
+        })
+    }
+
+    /// Pins the origin milestones are measured from; call first thing in `main`.
+    pub(crate) fn start() {
+        timings();
+    }
+
+    /// Times one startup stage until dropped.
+    pub(crate) struct Stage {
+        name: &'static str,
+        started: Instant,
+        _span: EnteredSpan,
+    }
+
+    pub(crate) fn stage(name: &'static str) -> Stage {
+        Stage {
+            name,
+            started: Instant::now(),
+            _span: info_span!("startup", stage = name).entered(),
+        }
+    }
+
+    impl Drop for Stage {
+        fn drop(&mut self) {
+            let elapsed = self.started.elapsed();
+            timings()
+                .lock()
+                .expect("startup timings poisoned")
+                .stages
+                .push((self.name, elapsed));
+        }
+    }
+
+    /// Records the first time `name` happens, relative to [`start`].
+    pub(crate) fn milestone(name: &'static str) {
+        if REPORTED.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut timings = timings().lock().expect("startup timings poisoned");
+        if !timings.milestones.iter().any(|(seen, _)| *seen == name) {
+            let at = timings.started.elapsed();
+            timings.milestones.push((name, at));
+        }
+    }
+
+    /// Marks the first popup frame and logs the summary, once.
+    pub(crate) fn first_frame() {
+        if REPORTED.load(Ordering::Relaxed) {
+            return;
+        }
+        milestone("first_frame");
+        if !REPORTED.swap(true, Ordering::Relaxed) {
+            let timings = timings().lock().expect("startup timings poisoned");
+            info!(
+                "startup timings: {}",
+                summarize(&timings.stages, &timings.milestones)
+            );
+        }
+    }
+
+    /// `config_load=1.2ms ...; first_window at 40.0ms, ...`: stages as durations,
+    /// milestones as offsets from process start.
+    fn summarize(
+        stages: &[(&'static str, Duration)],
+        milestones: &[(&'static str, Duration)],
+    ) -> String {
+        let ms = |d: &Duration| d.as_secs_f64() * 1000.0;
+        let stages = stages
+            .iter()
+            .map(|(name, d)| format!("{name}={:.1}ms", ms(d)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let milestones = milestones
+            .iter()
+            .map(|(name, d)| format!("{name} at {:.1}ms", ms(d)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{stages}; {milestones}")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn summary_lists_stage_durations_then_milestone_offsets() {
+            let summary = summarize(
+                &[
+                    ("config_load", Duration::from_micros(1_240)),
+                    ("name_acquisition", Duration::from_millis(12)),
+                ],
+                &[
+                    ("first_window", Duration::from_millis(40)),
+                    ("first_frame", Duration::from_micros(55_040)),
+                ],
+            );
+            assert_eq!(
+                summary,
+                "config_load=1.2ms name_acquisition=12.0ms; first_window at 40.0ms, first_frame at 55.0ms"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+pub(crate) use enabled::*;
+
+#[cfg(not(feature = "profiling"))]
+mod disabled {
+    pub(crate) struct Stage;
+
+    #[inline]
+    pub(crate) fn start() {}
+
+    #[inline]
+    pub(crate) fn stage(_name: &'static str) -> Stage {
+        Stage
+    }
+
+    #[inline]
+    pub(crate) fn milestone(_name: &'static str) {}
+
+    #[inline]
+    pub(crate) fn first_frame() {}
+}
+
+#[cfg(not(feature = "profiling"))]
+pub(crate) use disabled::*;
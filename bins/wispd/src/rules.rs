@@ -0,0 +1,507 @@
+//! Per-notification rules. A `[[ui.rules]]` entry picks notifications with a [`Matcher`]
+//! and maps action keys to local commands, or bounds their lifetime:
+//!
+//! ```toml
+//! [[ui.rules]]
+//! match.app_name = "Firefox"
+//! actions.open = { run = "xdg-open {body_url}" }
+//!
+//! [[ui.rules]]
+//! match.app_name = "Slack"
+//! max_timeout_ms = 10000
+//! ```
+//!
+//! Lifetime bounds override `ui.min_timeout_ms`, `ui.max_timeout_ms` and
+//! `ui.timeout_clamp_exempt_critical` field by field. The first rule matching the
+//! notification and setting any of them applies, in the popup and in the source alike.
+//!
+//! Clicking a mapped action runs its command instead of sending `ActionInvoked`, or as
+//! well with `also_forward = true`. The first rule matching the notification and mapping
+//! the key wins.
+//!
+//! A command is an argv, never handed to a shell. A string `run` is split into words
+//! first (quotes group words), then each word is filled in on its own, so a placeholder
+//! value is always exactly one argument whatever it contains. `$VAR` and `${VAR}` in the
+//! template take the environment's value; placeholder values are inserted as they are.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use wisp_source::{TimeoutClamp, TimeoutRule};
+use wisp_types::{Notification, matcher::Matcher};
+
+/// Placeholders a command may use.
+const PLACEHOLDERS: &[&str] = &["id", "action", "app_name", "summary", "body", "body_url"];
+
+/// One `[[ui.rules]]` entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Rule {
+    /// Notifications the rule applies to; empty matches all.
+    #[serde(rename = "match")]
+    pub(crate) matcher: Matcher,
+    /// Local commands keyed by action key.
+    pub(crate) actions: BTreeMap<String, ActionRoute>,
+    /// Override `ui.min_timeout_ms` and the rest for the matched notifications.
+    pub(crate) min_timeout_ms: Option<u32>,
+    pub(crate) max_timeout_ms: Option<u32>,
+    pub(crate) timeout_clamp_exempt_critical: Option<bool>,
+}
+
+impl Rule {
+    /// The lifetime bounds this rule sets, as the source takes them.
+    fn timeout_rule(&self) -> Option<TimeoutRule> {
+        let rule = TimeoutRule {
+            matcher: self.matcher.clone(),
+            min_ms: self.min_timeout_ms,
+            max_ms: self.max_timeout_ms,
+            exempt_critical: self.timeout_clamp_exempt_critical,
+        };
+        (rule.min_ms.is_some() || rule.max_ms.is_some() || rule.exempt_critical.is_some())
+            .then_some(rule)
+    }
+}
+
+/// The rules that bound notification lifetime, in order.
+pub(crate) fn timeout_rules(rules: &[Rule]) -> Vec<TimeoutRule> {
+    rules.iter().filter_map(Rule::timeout_rule).collect()
+}
+
+/// The first rule whose bounds, applied to `clamp`, let a minimum exceed the maximum.
+pub(crate) fn check_timeouts(rules: &[Rule], clamp: TimeoutClamp) -> Result<(), String> {
+    for (index, rule) in rules.iter().enumerate() {
+        let Some(rule) = rule.timeout_rule() else {
+            continue;
+        };
+        let min = rule.min_ms.or(clamp.min_ms);
+        let max = rule.max_ms.or(clamp.max_ms);
+        if let (Some(min), Some(max)) = (min, max)
+            && min > max
+        {
+            return Err(format!(
+                "ui.rules[{index}]: min_timeout_ms ({min}) must not exceed max_timeout_ms ({max})"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// What clicking a routed action does.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ActionRoute {
+    pub(crate) run: CommandTemplate,
+    /// Also sends `ActionInvoked` to the sender; otherwise the popup is dismissed.
+    #[serde(default)]
+    pub(crate) also_forward: bool,
+}
+
+/// The route for `key` on `n` from the first rule matching both.
+pub(crate) fn route_for<'a>(
+    rules: &'a [Rule],
+    n: &Notification,
+    key: &str,
+) -> Option<&'a ActionRoute> {
+    rules
+        .iter()
+        .filter(|rule| rule.matcher.matches(n))
+        .find_map(|rule| rule.actions.get(key))
+}
+
+/// A command line as words with placeholders. In config a string (`"xdg-open {body_url}"`)
+/// or a list of words (`["notify-send", "{summary}"]`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "TemplateSpec", into = "Vec<String>")]
+pub(crate) struct CommandTemplate {
+    words: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TemplateSpec {
+    Line(String),
+    Words(Vec<String>),
+}
+
+impl TryFrom<TemplateSpec> for CommandTemplate {
+    type Error = String;
+
+    fn try_from(spec: TemplateSpec) -> Result<Self, Self::Error> {
+        let words = match spec {
+            TemplateSpec::Line(line) => split_words(&line)?,
+            TemplateSpec::Words(words) => words,
+        };
+        if words
+            .first()
+            .is_none_or(|program| program.trim().is_empty())
+        {
+            return Err("run: empty command".to_string());
+        }
+        for word in &words {
+            for name in placeholders(word) {
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!(
+                        "run: unknown placeholder {{{name}}}; use one of {}",
+                        PLACEHOLDERS.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(Self { words })
+    }
+}
+
+impl From<CommandTemplate> for Vec<String> {
+    fn from(template: CommandTemplate) -> Self {
+        template.words
+    }
+}
+
+impl CommandTemplate {
+    /// The argv for clicking `action` on notification `id`, with `env` read for `$VAR`s.
+    pub(crate) fn argv(
+        &self,
+        id: u32,
+        action: &str,
+        n: &Notification,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Vec<String> {
+        let id = id.to_string();
+        let body_url = first_url(&n.body)
+            .or_else(|| n.hints.urls.first().map(String::as_str))
+            .unwrap_or_default();
+        let value = |name: &str| match name {
+            "id" => id.as_str(),
+            "action" => action,
+            "app_name" => n.app_name.as_str(),
+            "summary" => n.summary.as_str(),
+            "body" => n.body.as_str(),
+            "body_url" => body_url,
+            _ => "",
+        };
+        self.words
+            .iter()
+            .map(|word| fill_word(word, &value, &env))
+            .collect()
+    }
+}
+
+/// Runs `route` for clicking `action` on notification `id`, without waiting for it.
+pub(crate) fn run(route: &ActionRoute, id: u32, action: &str, n: &Notification) {
+    let argv = route.run.argv(id, action, n, |name| env::var(name).ok());
+    let Some((program, args)) = argv.split_first() else {
+        return;
+    };
+    match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(_) => debug!(id, action, %program, "ran routed action"),
+        Err(err) => warn!(id, action, %program, %err, "failed to run routed action"),
+    }
+}
+
+/// Splits `line` at whitespace; single or double quotes keep a word together and are
+/// dropped. There are no escapes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("run: unclosed quote in {line:?}"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Names of the `{name}` placeholders in `word`; `${VAR}` is not one.
+fn placeholders(word: &str) -> impl Iterator<Item = &str> {
+    word.match_indices('{')
+        .filter(|(at, _)| !word[..*at].ends_with('$'))
+        .filter_map(|(at, _)| word[at + 1..].split_once('}').map(|(name, _)| name))
+}
+
+/// `word` with its placeholders and `$VAR`s filled in. Inserted text is never scanned
+/// again.
+fn fill_word<'a>(
+    word: &str,
+    value: &impl Fn(&str) -> &'a str,
+    env: &impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some(start) = rest.find(['{', '$']) {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let (filled, used) = if let Some(inner) = tail.strip_prefix('{') {
+            match inner.split_once('}') {
+                Some((name, _)) => (Some(value(name).to_string()), name.len() + 2),
+                None => (None, 1),
+            }
+        } else if let Some(inner) = tail.strip_prefix("${") {
+            match inner.split_once('}') {
+                Some((name, _)) => (Some(env(name).unwrap_or_default()), name.len() + 3),
+                None => (None, 1),
+            }
+        } else {
+            let name_len = tail[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(tail.len() - 1);
+            match name_len {
+                0 => (None, 1),
+                len => (Some(env(&tail[1..=len]).unwrap_or_default()), len + 1),
+            }
+        };
+        out.push_str(filled.as_deref().unwrap_or(&tail[..used]));
+        rest = &tail[used..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The first `scheme://…` run in `text`, up to whitespace or a closing bracket or quote.
+fn first_url(text: &str) -> Option<&str> {
+    let end_of_url = |c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | ')');
+    text.match_indices("://").find_map(|(at, _)| {
+        let start = text[..at]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+            .map_or(0, |i| i + 1);
+        let scheme = &text[start..at];
+        if scheme.is_empty() || !scheme.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let len = text[at..].find(end_of_url).unwrap_or(text.len() - at);
+        (len > 3).then(|| &text[start..at + len])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(raw: &str) -> Result<CommandTemplate, String> {
+        CommandTemplate::try_from(TemplateSpec::Line(raw.to_string()))
+    }
+
+    fn download() -> Notification {
+        Notification {
+            app_name: "Firefox".to_string(),
+            summary: "Download complete".to_string(),
+            body: "report.pdf from https://example.org/report.pdf finished".to_string(),
+            ..Notification::default()
+        }
+    }
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn placeholders_and_env_fill_whole_words() {
+        let env = |name: &str| (name == "HOME").then(|| "/home/me".to_string());
+        let argv = template("xdg-open {body_url}")
+            .unwrap()
+            .argv(7, "open", &download(), env);
+        assert_eq!(argv, ["xdg-open", "https://example.org/report.pdf"]);
+
+        let argv = template("'my tool' --id={id} \"{summary}\" $HOME/x ${HOME}y {action} $")
+            .unwrap()
+            .argv(7, "open", &download(), env);
+        assert_eq!(
+            argv,
+            [
+                "my tool",
+                "--id=7",
+                "Download complete",
+                "/home/me/x",
+                "/home/mey",
+                "open",
+                "$"
+            ]
+        );
+    }
+
+    #[test]
+    fn body_url_falls_back_to_the_kde_url() {
+        let mut n = download();
+        n.body = "no link here".to_string();
+        n.hints.urls = vec!["file:///home/me/report.pdf".to_string()];
+        let argv = template("open {body_url}")
+            .unwrap()
+            .argv(1, "open", &n, no_env);
+        assert_eq!(argv, ["open", "file:///home/me/report.pdf"]);
+
+        n.hints.urls.clear();
+        let argv = template("open {body_url}")
+            .unwrap()
+            .argv(1, "open", &n, no_env);
+        assert_eq!(argv, ["open", ""]);
+        assert_eq!(
+            first_url("see <https://a.example/x>."),
+            Some("https://a.example/x")
+        );
+        assert_eq!(first_url("(mailto-less ://nothing)"), None);
+    }
+
+    #[test]
+    fn notification_content_is_never_split_expanded_or_interpreted() {
+        let mut n = download();
+        n.summary = "x; rm -rf ~ && echo $(id) `id` $HOME {body} ${HOME}".to_string();
+        let env = |_: &str| Some("EXPANDED".to_string());
+        let argv = template("notify {summary}")
+            .unwrap()
+            .argv(1, "open", &n, env);
+        assert_eq!(
+            argv,
+            ["notify", n.summary.as_str()],
+            "one argument, verbatim"
+        );
+
+        let list = CommandTemplate::try_from(TemplateSpec::Words(vec![
+            "printf".to_string(),
+            "%s {app_name}".to_string(),
+        ]))
+        .unwrap();
+        n.app_name = "a b".to_string();
+        assert_eq!(list.argv(1, "open", &n, no_env), ["printf", "%s a b"]);
+    }
+
+    #[test]
+    fn bad_templates_are_rejected_at_load() {
+        assert!(template("").is_err());
+        assert!(template("open 'unclosed").is_err());
+        assert!(template("open {nope}").unwrap_err().contains("{nope}"));
+        assert!(template("open ${HOME}/{body}").is_ok(), "not a placeholder");
+        assert_eq!(template("a  'b c'\"\" d").unwrap().words, ["a", "b c", "d"]);
+    }
+
+    #[test]
+    fn the_first_rule_mapping_the_key_wins() {
+        let rules: Vec<Rule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rules]]
+            match.app_name = "Firefox"
+            actions.open = { run = "xdg-open {body_url}" }
+
+            [[rules]]
+            actions.open = { run = "fallback", also_forward = true }
+            actions.default = { run = "other" }
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let firefox = route_for(&rules, &download(), "open").unwrap();
+        assert!(!firefox.also_forward);
+        assert_eq!(firefox.run.words, ["xdg-open", "{body_url}"]);
+
+        let mail = Notification {
+            app_name: "mail".to_string(),
+            ..Notification::default()
+        };
+        assert!(route_for(&rules, &mail, "open").unwrap().also_forward);
+        assert!(route_for(&rules, &download(), "default").is_some());
+        assert!(route_for(&rules, &download(), "dismiss").is_none());
+    }
+
+    #[test]
+    fn rules_match_sandboxed_apps_by_their_flatpak_id() {
+        let rules: Vec<Rule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rules]]
+            match.sandbox_app_id = "org.mozilla.firefox"
+            actions.open = { run = "flatpak run org.mozilla.firefox {body_url}" }
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let mut sandboxed = download();
+        sandboxed.hints.sandbox_app_id = Some("org.mozilla.firefox".to_string());
+        assert!(route_for(&rules, &sandboxed, "open").is_some());
+        assert!(
+            route_for(&rules, &download(), "open").is_none(),
+            "the same app name from outside the sandbox"
+        );
+    }
+
+    #[test]
+    fn lifetime_bounds_become_source_timeout_rules() {
+        let rules: Vec<Rule> = toml::from_str::<toml::Table>(
+            r#"
+            [[rules]]
+            match.app_name = "Firefox"
+            actions.open = { run = "xdg-open {body_url}" }
+
+            [[rules]]
+            match.app_name = "Slack"
+            max_timeout_ms = 10000
+            timeout_clamp_exempt_critical = false
+
+            [[rules]]
+            min_timeout_ms = 3000
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let timeouts = timeout_rules(&rules);
+        assert_eq!(timeouts.len(), 2, "the action route sets no bounds");
+        assert_eq!(timeouts[0].matcher, rules[1].matcher);
+        assert_eq!(timeouts[0].max_ms, Some(10_000));
+        assert_eq!(timeouts[0].exempt_critical, Some(false));
+        assert_eq!(timeouts[1].min_ms, Some(3_000));
+
+        let slack = Notification {
+            app_name: "Slack".to_string(),
+            ..Notification::default()
+        };
+        let clamp = TimeoutClamp::default().for_notification(&timeouts, &slack);
+        assert_eq!(clamp.max_ms, Some(10_000));
+        assert_eq!(clamp.min_ms, None, "only the first matching rule applies");
+
+        assert!(check_timeouts(&rules, TimeoutClamp::default()).is_ok());
+        let strict = TimeoutClamp {
+            max_ms: Some(2_000),
+            ..TimeoutClamp::default()
+        };
+        assert_eq!(
+            check_timeouts(&rules, strict).unwrap_err(),
+            "ui.rules[2]: min_timeout_ms (3000) must not exceed max_timeout_ms (2000)"
+        );
+    }
+}
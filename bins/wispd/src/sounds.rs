@@ -0,0 +1,387 @@
+//! Notification sounds. With `[ui.sound] enabled`, a new popup plays the sender's
+//! `sound-file` hint, else its `sound-name` looked up in the XDG sound theme the way
+//! [`super::icons`] looks up icons, else the sound configured for its urgency; nothing
+//! with `suppress-sound`. Files are handed to an external `player` command.
+
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use wisp_types::{SoundHint, Urgency};
+
+use super::icons;
+
+/// The theme every other theme falls back to.
+const FALLBACK_THEME: &str = "freedesktop";
+/// Output profile directory probed inside a theme.
+const PROFILE: &str = "stereo";
+/// Extensions tried in order; `.disabled` lets a theme silence a sound its parents have.
+const EXTENSIONS: &[&str] = &["disabled", "oga", "ogg", "wav"];
+
+/// `[ui.sound]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct SoundSection {
+    pub(crate) enabled: bool,
+    /// Sound theme searched first; `freedesktop` is searched after it.
+    pub(crate) theme: String,
+    /// Shell command that plays a file, given its path as the last argument.
+    pub(crate) player: String,
+    /// Played for notifications without a resolvable `sound-name`: a sound name from
+    /// the theme, or a path to a file.
+    pub(crate) low: Option<String>,
+    pub(crate) normal: Option<String>,
+    pub(crate) critical: Option<String>,
+}
+
+impl Default for SoundSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            theme: FALLBACK_THEME.to_string(),
+            player: "paplay".to_string(),
+            low: None,
+            normal: None,
+            critical: None,
+        }
+    }
+}
+
+impl SoundSection {
+    fn for_urgency(&self, urgency: &Urgency) -> Option<&str> {
+        match urgency {
+            Urgency::Low => self.low.as_deref(),
+            Urgency::Normal => self.normal.as_deref(),
+            Urgency::Critical => self.critical.as_deref(),
+        }
+        .map(str::trim)
+        .filter(|sound| !sound.is_empty())
+    }
+}
+
+/// What a theme has for a sound name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SoundLookup {
+    Found(PathBuf),
+    /// The theme ships `<name>.disabled`: play nothing.
+    Disabled,
+    Missing,
+}
+
+/// Lookup per the XDG sound theme spec: `<dir>/sounds/<theme>/stereo/<locale>/<name>.<ext>`
+/// for the configured theme then `freedesktop`, across the data dirs, for the session
+/// locale (`de_DE@euro`, `de_DE`, `de`) then none. A name that is not found is retried
+/// without its last dash-separated part (`message-new-instant`, then `message-new`).
+#[derive(Debug)]
+pub(crate) struct SoundResolver {
+    data_dirs: Vec<PathBuf>,
+    themes: Vec<String>,
+    locales: Vec<String>,
+    cache: HashMap<String, SoundLookup>,
+}
+
+impl SoundResolver {
+    pub(crate) fn new(data_dirs: Vec<PathBuf>, theme: &str, locale: Option<&str>) -> Self {
+        let mut themes = vec![theme.trim().to_string(), FALLBACK_THEME.to_string()];
+        themes.retain(|theme| !theme.is_empty());
+        themes.dedup();
+        Self {
+            data_dirs,
+            themes,
+            locales: locale.map(locale_variants).unwrap_or_default(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// A resolver for `theme` over the session's data dirs and locale.
+    pub(crate) fn for_session(theme: &str) -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty());
+        Self::new(icons::data_dirs(), theme, locale.as_deref())
+    }
+
+    pub(crate) fn resolve(&mut self, name: &str) -> SoundLookup {
+        if name.is_empty() || name.contains('/') {
+            return SoundLookup::Missing;
+        }
+        if let Some(hit) = self.cache.get(name) {
+            return hit.clone();
+        }
+        let found = self.lookup(name);
+        self.cache.insert(name.to_string(), found.clone());
+        found
+    }
+
+    fn lookup(&self, name: &str) -> SoundLookup {
+        let mut candidate = name;
+        loop {
+            if let Some(found) = self.lookup_exact(candidate) {
+                return found;
+            }
+            match candidate.rsplit_once('-') {
+                Some((shorter, _)) if !shorter.is_empty() => candidate = shorter,
+                _ => return SoundLookup::Missing,
+            }
+        }
+    }
+
+    fn lookup_exact(&self, name: &str) -> Option<SoundLookup> {
+        let locales = self.locales.iter().map(Some).chain([None]);
+        for theme in &self.themes {
+            for dir in &self.data_dirs {
+                let profile_dir = dir.join("sounds").join(theme).join(PROFILE);
+                for locale in locales.clone() {
+                    let dir = locale.map_or_else(|| profile_dir.clone(), |l| profile_dir.join(l));
+                    if let Some(found) = find_with_extensions(&dir, name) {
+                        return Some(found);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+fn find_with_extensions(dir: &Path, name: &str) -> Option<SoundLookup> {
+    EXTENSIONS.iter().find_map(|ext| {
+        let path = dir.join(format!("{name}.{ext}"));
+        path.is_file().then(|| match *ext {
+            "disabled" => SoundLookup::Disabled,
+            _ => SoundLookup::Found(path),
+        })
+    })
+}
+
+/// `de_DE.UTF-8@euro` gives `de_DE@euro`, `de_DE` and `de`; `C` and `POSIX` give none.
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (base, modifier) = match locale.split_once('@') {
+        Some((base, modifier)) => (base, Some(modifier)),
+        None => (locale, None),
+    };
+    let base = base.split('.').next().unwrap_or_default();
+    if base.is_empty() || base == "C" || base == "POSIX" {
+        return Vec::new();
+    }
+    let mut variants: Vec<String> = modifier
+        .map(|modifier| format!("{base}@{modifier}"))
+        .into_iter()
+        .chain([base.to_string()])
+        .collect();
+    if let Some((language, _)) = base.split_once('_') {
+        variants.push(language.to_string());
+    }
+    variants
+}
+
+/// The file a new popup plays: its `sound-file` when that exists, else its `sound-name`
+/// when the theme has it, else the sound configured for `urgency`. `None` when there is
+/// nothing to play, the sender suppressed sounds, or the theme disables the requested one.
+pub(crate) fn pick(
+    section: &SoundSection,
+    resolver: &mut SoundResolver,
+    hint: &SoundHint,
+    urgency: &Urgency,
+) -> Option<PathBuf> {
+    if hint.suppress {
+        debug!("sound suppressed by the sender");
+        return None;
+    }
+    if let Some(file) = &hint.file {
+        if file.is_file() {
+            return Some(file.clone());
+        }
+        debug!(file = %file.display(), "sound file not found; trying the name");
+    }
+    if let Some(name) = hint.name.as_deref() {
+        match resolver.resolve(name) {
+            SoundLookup::Found(path) => return Some(path),
+            SoundLookup::Disabled => {
+                debug!(name, "sound disabled by the theme");
+                return None;
+            }
+            SoundLookup::Missing => debug!(name, "sound not in the theme; using the urgency sound"),
+        }
+    }
+    let configured = section.for_urgency(urgency)?;
+    if configured.contains('/') {
+        return Some(PathBuf::from(configured));
+    }
+    match resolver.resolve(configured) {
+        SoundLookup::Found(path) => Some(path),
+        SoundLookup::Disabled | SoundLookup::Missing => None,
+    }
+}
+
+/// Plays `path` with `player` without waiting for it.
+pub(crate) fn play(player: &str, path: &Path) {
+    match Command::new("sh")
+        .arg("-c")
+        .arg(format!("{player} \"$1\""))
+        .arg("wispd-sound")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(_) => debug!(path = %path.display(), "playing notification sound"),
+        Err(err) => warn!(player, %err, "failed to run the sound player"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let root = temp_dir(&format!("sounds-{name}"));
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"ogg").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn names_resolve_through_themes_locales_formats_and_shorter_names() {
+        let root = fixture(
+            "theme",
+            &[
+                "sounds/freedesktop/stereo/message-new-instant.oga",
+                "sounds/freedesktop/stereo/message.oga",
+                "sounds/freedesktop/stereo/bell.wav",
+                "sounds/freedesktop/stereo/de/bell.ogg",
+                "sounds/ocean/stereo/bell.oga",
+                "sounds/ocean/stereo/message-new-instant.disabled",
+            ],
+        );
+        let sound = |file: &str| SoundLookup::Found(root.join("sounds").join(file));
+
+        let mut plain = SoundResolver::new(vec![root.clone()], "freedesktop", None);
+        assert_eq!(
+            plain.resolve("message-new-instant"),
+            sound("freedesktop/stereo/message-new-instant.oga")
+        );
+        assert_eq!(
+            plain.resolve("message-new-email"),
+            sound("freedesktop/stereo/message.oga"),
+            "falls back to shorter names"
+        );
+        assert_eq!(plain.resolve("bell"), sound("freedesktop/stereo/bell.wav"));
+        assert_eq!(plain.resolve("phone-incoming-call"), SoundLookup::Missing);
+        assert_eq!(plain.resolve("../stereo/bell"), SoundLookup::Missing);
+
+        let mut german = SoundResolver::new(vec![root.clone()], "freedesktop", Some("de_AT.UTF-8"));
+        assert_eq!(
+            german.resolve("bell"),
+            sound("freedesktop/stereo/de/bell.ogg")
+        );
+
+        let mut ocean = SoundResolver::new(vec![root.clone()], "ocean", None);
+        assert_eq!(ocean.resolve("bell"), sound("ocean/stereo/bell.oga"));
+        assert_eq!(
+            ocean.resolve("message-new-instant"),
+            SoundLookup::Disabled,
+            "a .disabled file silences the sound"
+        );
+        assert_eq!(
+            ocean.resolve("message"),
+            sound("freedesktop/stereo/message.oga"),
+            "missing sounds come from freedesktop"
+        );
+
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(
+            ocean.resolve("bell"),
+            sound("ocean/stereo/bell.oga"),
+            "cached"
+        );
+    }
+
+    #[test]
+    fn unresolved_names_fall_back_to_the_urgency_sound() {
+        let root = fixture(
+            "pick",
+            &[
+                "sounds/freedesktop/stereo/dialog-warning.oga",
+                "sounds/freedesktop/stereo/muted.disabled",
+            ],
+        );
+        let mut resolver = SoundResolver::new(vec![root.clone()], "freedesktop", None);
+        let section = SoundSection {
+            enabled: true,
+            normal: Some("/opt/sounds/ding.wav".to_string()),
+            critical: Some("dialog-warning".to_string()),
+            ..SoundSection::default()
+        };
+        let mut sound_for = |name: Option<&str>, urgency| {
+            let hint = SoundHint {
+                name: name.map(ToOwned::to_owned),
+                ..SoundHint::default()
+            };
+            pick(&section, &mut resolver, &hint, &urgency)
+        };
+
+        assert_eq!(
+            sound_for(Some("dialog-warning"), Urgency::Low),
+            Some(root.join("sounds/freedesktop/stereo/dialog-warning.oga"))
+        );
+        assert_eq!(
+            sound_for(Some("trumpet"), Urgency::Normal),
+            Some(PathBuf::from("/opt/sounds/ding.wav"))
+        );
+        assert_eq!(
+            sound_for(None, Urgency::Critical),
+            Some(root.join("sounds/freedesktop/stereo/dialog-warning.oga"))
+        );
+        assert_eq!(sound_for(None, Urgency::Low), None);
+        assert_eq!(
+            sound_for(Some("muted"), Urgency::Normal),
+            None,
+            "disabled by the theme"
+        );
+
+        let sent = root.join("sent.wav");
+        fs::write(&sent, b"wav").unwrap();
+        let mut hinted = SoundHint {
+            file: Some(sent.clone()),
+            name: Some("dialog-warning".to_string()),
+            suppress: false,
+        };
+        assert_eq!(
+            pick(&section, &mut resolver, &hinted, &Urgency::Normal),
+            Some(sent),
+            "a sent file comes first"
+        );
+        hinted.file = Some(root.join("missing.wav"));
+        assert_eq!(
+            pick(&section, &mut resolver, &hinted, &Urgency::Normal),
+            Some(root.join("sounds/freedesktop/stereo/dialog-warning.oga"))
+        );
+        hinted.suppress = true;
+        assert_eq!(
+            pick(&section, &mut resolver, &hinted, &Urgency::Critical),
+            None,
+            "suppressed, urgency sound included"
+        );
+
+        assert_eq!(
+            locale_variants("de_DE.UTF-8@euro"),
+            ["de_DE@euro", "de_DE", "de"]
+        );
+        assert!(locale_variants("C.UTF-8").is_empty());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
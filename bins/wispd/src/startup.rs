@@ -0,0 +1,279 @@
+//! Startup handshake with the source thread. The thread reports each stage as it reaches
+//! it, so startup logs its progress, fails as soon as a stage fails (naming that stage),
+//! and only runs into the deadline when a stage hangs.
+
+use std::{
+    fmt,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+use wisp_source::{SourceConfig, StartupStage};
+
+/// Overall deadline without `--startup-timeout`. Failures are reported as they happen,
+/// so this only bounds a bus that stops answering.
+pub(crate) const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Under D-Bus activation the bus holds the first `Notify` until the name is owned, so a
+/// slow cold start gets the bus's own activation budget (25 s), minus a margin.
+pub(crate) const ACTIVATED_STARTUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Source startup stages, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Stage {
+    RuntimeBuilt,
+    Connected,
+    NameRequested,
+    NameAcquired,
+}
+
+impl Stage {
+    fn label(self) -> &'static str {
+        match self {
+            Self::RuntimeBuilt => "runtime built",
+            Self::Connected => "connection established",
+            Self::NameRequested => "name requested",
+            Self::NameAcquired => "name acquired",
+        }
+    }
+
+    /// What the source thread does until it reaches this stage.
+    fn attempt(self) -> &'static str {
+        match self {
+            Self::RuntimeBuilt => "building the runtime",
+            Self::Connected => "connecting to the session bus",
+            Self::NameRequested => "requesting the bus name",
+            Self::NameAcquired => "acquiring the bus name",
+        }
+    }
+
+    /// The stage after `last`, or the first one.
+    pub(crate) fn after(last: Option<Self>) -> Self {
+        match last {
+            None => Self::RuntimeBuilt,
+            Some(Self::RuntimeBuilt) => Self::Connected,
+            Some(Self::Connected) => Self::NameRequested,
+            Some(Self::NameRequested | Self::NameAcquired) => Self::NameAcquired,
+        }
+    }
+}
+
+impl From<StartupStage> for Stage {
+    fn from(stage: StartupStage) -> Self {
+        match stage {
+            StartupStage::Connected => Self::Connected,
+            StartupStage::NameRequested => Self::NameRequested,
+            StartupStage::NameAcquired => Self::NameAcquired,
+        }
+    }
+}
+
+/// What the source thread sends while it starts.
+#[derive(Debug)]
+pub(crate) enum StartupReport {
+    Reached(Stage),
+    /// Started; the thread now serves the bus.
+    Ready(SourceConfig),
+    /// `stage` could not be reached.
+    Failed {
+        stage: Stage,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StartupFailure {
+    Failed {
+        stage: Stage,
+        error: String,
+    },
+    /// Nothing came before the deadline.
+    TimedOut {
+        last: Option<Stage>,
+        after: Duration,
+    },
+    /// The source thread ended without a verdict.
+    Disconnected {
+        last: Option<Stage>,
+    },
+}
+
+impl fmt::Display for StartupFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed { stage, error } => {
+                write!(f, "source startup failed {}: {error}", stage.attempt())
+            }
+            Self::TimedOut { last, after } => {
+                write!(
+                    f,
+                    "source startup timed out after {after:?} {}",
+                    Stage::after(*last).attempt()
+                )?;
+                if let Some(last) = last {
+                    write!(f, " (last stage: {})", last.label())?;
+                }
+                Ok(())
+            }
+            Self::Disconnected { last } => write!(
+                f,
+                "source thread exited while {}",
+                Stage::after(*last).attempt()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StartupFailure {}
+
+/// Follows `reports` until the source is ready, a stage fails, or `timeout` passes
+/// overall.
+pub(crate) fn wait_for_startup(
+    reports: &mpsc::Receiver<StartupReport>,
+    timeout: Duration,
+) -> Result<SourceConfig, StartupFailure> {
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let mut last = None;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match reports.recv_timeout(remaining) {
+            Ok(StartupReport::Reached(stage)) => {
+                info!(
+                    stage = stage.label(),
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    "source startup progress"
+                );
+                last = Some(stage);
+            }
+            Ok(StartupReport::Ready(cfg)) => return Ok(cfg),
+            Ok(StartupReport::Failed { stage, error }) => {
+                return Err(StartupFailure::Failed { stage, error });
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Err(StartupFailure::TimedOut {
+                    last,
+                    after: timeout,
+                });
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(StartupFailure::Disconnected { last });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the source thread: sends `script` with the given delays.
+    fn mock_starter(script: Vec<(u64, StartupReport)>) -> mpsc::Receiver<StartupReport> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (delay_ms, report) in script {
+                std::thread::sleep(Duration::from_millis(delay_ms));
+                if tx.send(report).is_err() {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    #[test]
+    fn stages_in_order_end_in_ready() {
+        let reports = mock_starter(vec![
+            (0, StartupReport::Reached(Stage::RuntimeBuilt)),
+            (5, StartupReport::Reached(Stage::Connected)),
+            (5, StartupReport::Reached(Stage::NameRequested)),
+            (5, StartupReport::Reached(Stage::NameAcquired)),
+            (0, StartupReport::Ready(SourceConfig::default())),
+        ]);
+        let cfg = wait_for_startup(&reports, Duration::from_secs(5)).expect("ready");
+        assert_eq!(cfg.dbus_name, SourceConfig::default().dbus_name);
+
+        assert_eq!(Stage::after(None), Stage::RuntimeBuilt);
+        assert_eq!(Stage::after(Some(Stage::Connected)), Stage::NameRequested);
+        assert!(Stage::RuntimeBuilt < Stage::NameAcquired);
+    }
+
+    #[test]
+    fn failures_name_their_stage_without_waiting_for_the_deadline() {
+        let started = Instant::now();
+        let unreachable = mock_starter(vec![
+            (0, StartupReport::Reached(Stage::RuntimeBuilt)),
+            (
+                0,
+                StartupReport::Failed {
+                    stage: Stage::Connected,
+                    error: "cannot connect to the bus: no such file".to_string(),
+                },
+            ),
+        ]);
+        let err = wait_for_startup(&unreachable, Duration::from_secs(30)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "source startup failed connecting to the session bus: cannot connect to the bus: no such file"
+        );
+
+        let taken = mock_starter(vec![
+            (0, StartupReport::Reached(Stage::RuntimeBuilt)),
+            (0, StartupReport::Reached(Stage::Connected)),
+            (0, StartupReport::Reached(Stage::NameRequested)),
+            (
+                0,
+                StartupReport::Failed {
+                    stage: Stage::NameAcquired,
+                    error: "org.freedesktop.Notifications is owned by another connection"
+                        .to_string(),
+                },
+            ),
+        ]);
+        let err = wait_for_startup(&taken, Duration::from_secs(30)).unwrap_err();
+        assert!(matches!(
+            err,
+            StartupFailure::Failed {
+                stage: Stage::NameAcquired,
+                ..
+            }
+        ));
+        assert!(
+            err.to_string()
+                .starts_with("source startup failed acquiring the bus name")
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_hanging_stage_times_out_with_the_last_stage_reached() {
+        let (tx, hanging) = mpsc::channel();
+        tx.send(StartupReport::Reached(Stage::RuntimeBuilt))
+            .unwrap();
+        tx.send(StartupReport::Reached(Stage::Connected)).unwrap();
+        tx.send(StartupReport::Reached(Stage::NameRequested))
+            .unwrap();
+
+        let err = wait_for_startup(&hanging, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(
+            err,
+            StartupFailure::TimedOut {
+                last: Some(Stage::NameRequested),
+                after: Duration::from_millis(50),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "source startup timed out after 50ms acquiring the bus name (last stage: name requested)"
+        );
+
+        drop(tx);
+        let gone = mock_starter(vec![(0, StartupReport::Reached(Stage::RuntimeBuilt))]);
+        assert_eq!(
+            wait_for_startup(&gone, Duration::from_secs(5)).unwrap_err(),
+            StartupFailure::Disconnected {
+                last: Some(Stage::RuntimeBuilt)
+            }
+        );
+    }
+}
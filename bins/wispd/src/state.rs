@@ -0,0 +1,653 @@
+//! Files wispd keeps in its state dir (`$XDG_STATE_HOME/wispd`). Every state file is
+//! TOML that starts with a `version` key; files written before versioning have none and
+//! count as version 0. [`load`] upgrades an older file one version at a time through
+//! its [`StateFile::migrations`] and writes the result back, [`save`] replaces a file
+//! atomically, and `wispd --repair-state` runs [`repair`] over [`FILES`]. Features that
+//! persist go through here rather than touching the state dir themselves.
+//!
+//! Files appended to record by record are [`LogFile`]s instead: NDJSON behind a
+//! `{"version": n}` header line, with migrations that upgrade each record. They go
+//! through [`load_log`], [`append_log`] and [`save_log`], and [`repair_logs`] checks
+//! [`LOGS`].
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{info, warn};
+
+use super::{delivered, history_log, mutes};
+
+/// Every state file wispd owns, checked by `--repair-state`.
+pub(crate) const FILES: &[StateFile] = &[mutes::STATE_FILE, delivered::STATE_FILE];
+
+/// Every log file wispd owns, checked by `--repair-state` too.
+pub(crate) const LOGS: &[LogFile] = &[history_log::LOG_FILE];
+
+/// Key holding a state file's schema version.
+const VERSION_KEY: &str = "version";
+
+/// Upgrades a table of one version to the next, in place.
+pub(crate) type Migration = fn(&mut toml::Table) -> Result<(), String>;
+
+/// A versioned file in the state dir.
+#[derive(Debug)]
+pub(crate) struct StateFile {
+    /// File name inside the state dir.
+    pub(crate) name: &'static str,
+    /// `migrations[n]` turns a version `n` table into version `n + 1`, so the current
+    /// version is their count.
+    pub(crate) migrations: &'static [Migration],
+    /// Checks that a current-version table reads as the file's type.
+    pub(crate) validate: fn(toml::Table) -> Result<(), String>,
+}
+
+impl StateFile {
+    pub(crate) fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+}
+
+/// Upgrades one record of a [`LogFile`] from one version to the next, in place.
+pub(crate) type RecordMigration = fn(&mut serde_json::Value) -> Result<(), String>;
+
+/// A versioned NDJSON file in the state dir: a `{"version": n}` line, then one record
+/// per line, oldest first. Files written before versioning have no header and count
+/// as version 0.
+#[derive(Debug)]
+pub(crate) struct LogFile {
+    /// File name inside the state dir.
+    pub(crate) name: &'static str,
+    /// `migrations[n]` turns a version `n` record into version `n + 1`, so the current
+    /// version is their count.
+    pub(crate) migrations: &'static [RecordMigration],
+    /// Checks that a current-version record reads as the file's record type.
+    pub(crate) validate: fn(serde_json::Value) -> Result<(), String>,
+}
+
+impl LogFile {
+    pub(crate) fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+}
+
+/// A [`LogFile::validate`] that deserializes into `T`.
+pub(crate) fn validate_record_as<T: DeserializeOwned>(
+    record: serde_json::Value,
+) -> Result<(), String> {
+    serde_json::from_value::<T>(record)
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+/// A [`StateFile::validate`] that deserializes into `T`.
+pub(crate) fn validate_as<T: DeserializeOwned>(table: toml::Table) -> Result<(), String> {
+    toml::Value::Table(table)
+        .try_into::<T>()
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+/// Why a state file could not be read.
+#[derive(Debug)]
+pub(crate) enum StateError {
+    Io(io::Error),
+    Parse(String),
+    BadVersion,
+    /// Written by a newer wispd; left alone rather than guessed at.
+    TooNew {
+        version: u32,
+        supported: u32,
+    },
+    Migration {
+        from: u32,
+        message: String,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "unreadable: {err}"),
+            Self::Parse(message) => write!(f, "not valid TOML: {message}"),
+            Self::BadVersion => write!(f, "`{VERSION_KEY}` is not a non-negative integer"),
+            Self::TooNew { version, supported } => write!(
+                f,
+                "version {version} is newer than this wispd supports ({supported})"
+            ),
+            Self::Migration { from, message } => {
+                write!(f, "migrating from version {from} failed: {message}")
+            }
+            Self::Invalid(message) => write!(f, "unexpected contents: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// `$XDG_STATE_HOME`, else `~/.local/state`.
+pub(crate) fn home() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| {
+                let mut p = PathBuf::from(home);
+                p.push(".local");
+                p.push("state");
+                p
+            })
+        })
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// wispd's state dir.
+pub(crate) fn dir() -> PathBuf {
+    home().join("wispd")
+}
+
+/// Reads `file` from `dir`, or `None` when it does not exist. An older version is
+/// migrated and written back in the current version.
+pub(crate) fn load<T>(dir: &Path, file: &StateFile) -> Result<Option<T>, StateError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let path = dir.join(file.name);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(StateError::Io(err)),
+    };
+    let (table, from) = upgrade(file, &raw)?;
+    let value = toml::Value::Table(table)
+        .try_into::<T>()
+        .map_err(|err| StateError::Invalid(err.to_string()))?;
+    if from != file.version() {
+        info!(
+            file = file.name,
+            from,
+            to = file.version(),
+            "migrating state file"
+        );
+        save(dir, file, &value).map_err(StateError::Io)?;
+    }
+    Ok(Some(value))
+}
+
+/// Writes `value` as `file` in the current version, replacing any old file atomically.
+pub(crate) fn save<T: Serialize>(dir: &Path, file: &StateFile, value: &T) -> io::Result<()> {
+    let body = toml::to_string(value).map_err(io::Error::other)?;
+    let raw = format!("{VERSION_KEY} = {}\n{body}", file.version());
+    write_atomic(&dir.join(file.name), raw.as_bytes())
+}
+
+/// Writes `contents` to a temporary file next to `path`, then renames it over `path`,
+/// so readers see the old file or the new one and never a partial write.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = sibling(path, ".tmp");
+    let written = File::create(&tmp).and_then(|mut tmp_file| {
+        tmp_file.write_all(contents)?;
+        tmp_file.sync_all()
+    });
+    let result = written.and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Parses `raw` and migrates it to the current version, returning the table without
+/// its version key and the version it was read at.
+fn upgrade(file: &StateFile, raw: &str) -> Result<(toml::Table, u32), StateError> {
+    let mut table: toml::Table = raw
+        .parse()
+        .map_err(|err: toml::de::Error| StateError::Parse(err.message().to_string()))?;
+    let from = match table.remove(VERSION_KEY) {
+        None => 0,
+        Some(version) => version
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or(StateError::BadVersion)?,
+    };
+    if from > file.version() {
+        return Err(StateError::TooNew {
+            version: from,
+            supported: file.version(),
+        });
+    }
+    for (version, migrate) in file.migrations.iter().enumerate().skip(from as usize) {
+        migrate(&mut table).map_err(|message| StateError::Migration {
+            from: version as u32,
+            message,
+        })?;
+    }
+    Ok((table, from))
+}
+
+/// Reads the records of `file` from `dir`, oldest first; a missing file has none.
+/// Records that do not parse, migrate or read as `T` are skipped with a warning. An
+/// older version is migrated and written back in the current version.
+pub(crate) fn load_log<T>(dir: &Path, file: &LogFile) -> Result<Vec<T>, StateError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let raw = match fs::read_to_string(dir.join(file.name)) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(StateError::Io(err)),
+    };
+    let (records, from) = upgrade_log(file, &raw)?;
+    let records: Vec<T> = records
+        .into_iter()
+        .filter_map(|(line, record)| {
+            let read = record
+                .and_then(|record| serde_json::from_value(record).map_err(|err| err.to_string()));
+            match read {
+                Ok(record) => Some(record),
+                Err(err) => {
+                    warn!(file = file.name, line, %err, "skipping unreadable record");
+                    None
+                }
+            }
+        })
+        .collect();
+    if from != file.version() {
+        info!(
+            file = file.name,
+            from,
+            to = file.version(),
+            "migrating log file"
+        );
+        save_log(dir, file, &records).map_err(StateError::Io)?;
+    }
+    Ok(records)
+}
+
+/// Appends `record` to `file`, starting the file with its header when it is new.
+pub(crate) fn append_log<T: Serialize>(dir: &Path, file: &LogFile, record: &T) -> io::Result<()> {
+    let path = dir.join(file.name);
+    let mut out = Vec::new();
+    if !fs::metadata(&path).is_ok_and(|meta| meta.len() > 0) {
+        write_log_header(&mut out, file)?;
+    }
+    write_record(&mut out, record)?;
+    fs::create_dir_all(dir)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&out)
+}
+
+/// Writes `records` as `file` in the current version, oldest first, replacing any old
+/// file atomically.
+pub(crate) fn save_log<T: Serialize>(dir: &Path, file: &LogFile, records: &[T]) -> io::Result<()> {
+    let mut out = Vec::new();
+    write_log_header(&mut out, file)?;
+    for record in records {
+        write_record(&mut out, record)?;
+    }
+    write_atomic(&dir.join(file.name), &out)
+}
+
+/// Size of `file` on disk; 0 when it does not exist.
+pub(crate) fn log_len(dir: &Path, file: &LogFile) -> u64 {
+    fs::metadata(dir.join(file.name)).map_or(0, |meta| meta.len())
+}
+
+fn write_log_header(out: &mut Vec<u8>, file: &LogFile) -> io::Result<()> {
+    let header = serde_json::Map::from_iter([(VERSION_KEY.to_string(), file.version().into())]);
+    write_record(out, &header)
+}
+
+fn write_record<T: Serialize>(out: &mut Vec<u8>, record: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.push(b'\n');
+    Ok(())
+}
+
+/// Record lines of a log after its header, with their 1-based line numbers, each
+/// migrated to the current version or the reason it could not be; and the version
+/// the log was read at.
+type UpgradedLog = (Vec<(usize, Result<serde_json::Value, String>)>, u32);
+
+/// Reads the header of `raw` and migrates every record to the current version.
+fn upgrade_log(file: &LogFile, raw: &str) -> Result<UpgradedLog, StateError> {
+    let mut lines = raw
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+    let header = lines
+        .peek()
+        .and_then(|(_, line)| serde_json::from_str::<serde_json::Value>(line).ok())
+        .and_then(|value| match value {
+            serde_json::Value::Object(map) if map.len() == 1 => map.get(VERSION_KEY).cloned(),
+            _ => None,
+        });
+    let from = match header {
+        None => 0,
+        Some(version) => {
+            lines.next();
+            version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or(StateError::BadVersion)?
+        }
+    };
+    if from > file.version() {
+        return Err(StateError::TooNew {
+            version: from,
+            supported: file.version(),
+        });
+    }
+    let records = lines
+        .map(|(line, raw)| {
+            let record = serde_json::from_str(raw)
+                .map_err(|err| format!("not valid JSON: {err}"))
+                .and_then(|mut record| {
+                    for (version, migrate) in file.migrations.iter().enumerate().skip(from as usize)
+                    {
+                        migrate(&mut record).map_err(|message| {
+                            format!("migrating from version {version} failed: {message}")
+                        })?;
+                    }
+                    Ok(record)
+                });
+            (line, record)
+        })
+        .collect();
+    Ok((records, from))
+}
+
+/// What `--repair-state` found for one state file.
+#[derive(Debug)]
+pub(crate) struct FileReport {
+    pub(crate) path: PathBuf,
+    pub(crate) status: FileStatus,
+}
+
+#[derive(Debug)]
+pub(crate) enum FileStatus {
+    Missing,
+    /// Readable; older versions are migrated on the next start.
+    Ok {
+        version: u32,
+    },
+    Broken {
+        error: StateError,
+        /// Where the file was moved, when it was quarantined.
+        quarantined: Option<PathBuf>,
+    },
+}
+
+impl FileReport {
+    /// Whether the file still needs attention.
+    pub(crate) fn needs_repair(&self) -> bool {
+        matches!(
+            self.status,
+            FileStatus::Broken {
+                quarantined: None,
+                ..
+            }
+        )
+    }
+}
+
+impl fmt::Display for FileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.status {
+            FileStatus::Missing => write!(f, "{path}: not present"),
+            FileStatus::Ok { version } => write!(f, "{path}: ok (version {version})"),
+            FileStatus::Broken {
+                error,
+                quarantined: Some(to),
+            } => write!(f, "{path}: {error}; moved to {}", to.display()),
+            FileStatus::Broken {
+                error,
+                quarantined: None,
+            } => write!(f, "{path}: {error}"),
+        }
+    }
+}
+
+/// Checks every file in `files`, moving broken ones to `<name>.broken` with
+/// `quarantine`. Files from a newer wispd are reported but never moved.
+pub(crate) fn repair(dir: &Path, files: &[StateFile], quarantine: bool) -> Vec<FileReport> {
+    files
+        .iter()
+        .map(|file| {
+            let path = dir.join(file.name);
+            let checked = check(&path, file);
+            report(path, checked, quarantine)
+        })
+        .collect()
+}
+
+/// [`repair`] for log files: a log is broken when any of its records is.
+pub(crate) fn repair_logs(dir: &Path, logs: &[LogFile], quarantine: bool) -> Vec<FileReport> {
+    logs.iter()
+        .map(|file| {
+            let path = dir.join(file.name);
+            let checked = check_log(&path, file);
+            report(path, checked, quarantine)
+        })
+        .collect()
+}
+
+fn report(path: PathBuf, checked: Result<Option<u32>, StateError>, quarantine: bool) -> FileReport {
+    let status = match checked {
+        Ok(None) => FileStatus::Missing,
+        Ok(Some(version)) => FileStatus::Ok { version },
+        Err(error) => {
+            let movable = quarantine && !matches!(error, StateError::TooNew { .. });
+            let quarantined = movable.then(|| quarantine_file(&path)).flatten();
+            FileStatus::Broken { error, quarantined }
+        }
+    };
+    FileReport { path, status }
+}
+
+/// The version `path` was read at, or `None` when it does not exist.
+fn check(path: &Path, file: &StateFile) -> Result<Option<u32>, StateError> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(StateError::Io(err)),
+    };
+    let (table, from) = upgrade(file, &raw)?;
+    (file.validate)(table).map_err(StateError::Invalid)?;
+    Ok(Some(from))
+}
+
+/// The version the log at `path` was read at, or `None` when it does not exist.
+fn check_log(path: &Path, file: &LogFile) -> Result<Option<u32>, StateError> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(StateError::Io(err)),
+    };
+    let (records, from) = upgrade_log(file, &raw)?;
+    for (line, record) in records {
+        record
+            .and_then(file.validate)
+            .map_err(|message| StateError::Invalid(format!("line {line}: {message}")))?;
+    }
+    Ok(Some(from))
+}
+
+/// Moves `path` to `<name>.broken`, replacing an older quarantined copy.
+fn quarantine_file(path: &Path) -> Option<PathBuf> {
+    let to = sibling(path, ".broken");
+    match fs::rename(path, &to) {
+        Ok(()) => {
+            info!(from = %path.display(), to = %to.display(), "quarantined state file");
+            Some(to)
+        }
+        Err(err) => {
+            warn!(path = %path.display(), %err, "failed to quarantine state file");
+            None
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::super::testing::temp_dir;
+    use super::*;
+
+    /// v0 kept one `app` string; v1 made it a list `apps`; v2 renamed it `sources`.
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Watched {
+        sources: Vec<String>,
+        #[serde(default)]
+        counts: BTreeMap<String, u32>,
+    }
+
+    fn to_list(table: &mut toml::Table) -> Result<(), String> {
+        let app = table.remove("app").ok_or("missing `app`")?;
+        table.insert("apps".to_string(), toml::Value::Array(vec![app]));
+        Ok(())
+    }
+
+    fn rename_to_sources(table: &mut toml::Table) -> Result<(), String> {
+        let apps = table.remove("apps").ok_or("missing `apps`")?;
+        table.insert("sources".to_string(), apps);
+        Ok(())
+    }
+
+    const WATCHED: StateFile = StateFile {
+        name: "watched.toml",
+        migrations: &[to_list, rename_to_sources],
+        validate: validate_as::<Watched>,
+    };
+
+    #[test]
+    fn old_files_migrate_through_every_version_and_are_rewritten() {
+        let dir = temp_dir("state-migrate");
+        let path = dir.join(WATCHED.name);
+        let expected = Watched {
+            sources: vec!["mail".to_string()],
+            counts: BTreeMap::new(),
+        };
+
+        fs::write(&path, "app = \"mail\"\n").unwrap();
+        let loaded: Option<Watched> = load(&dir, &WATCHED).expect("v0 migrates");
+        assert_eq!(loaded.as_ref(), Some(&expected));
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(rewritten.starts_with("version = 2\n"), "{rewritten}");
+
+        fs::write(&path, "version = 1\napps = [\"mail\"]\n").unwrap();
+        let loaded: Option<Watched> = load(&dir, &WATCHED).expect("v1 migrates");
+        assert_eq!(loaded, Some(expected));
+
+        let current = fs::read_to_string(&path).unwrap();
+        let _: Option<Watched> = load(&dir, &WATCHED).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), current, "no rewrite");
+
+        fs::write(&path, "version = 1\nsources = []\n").unwrap();
+        assert!(matches!(
+            load::<Watched>(&dir, &WATCHED),
+            Err(StateError::Migration { from: 1, .. })
+        ));
+        fs::write(&path, "version = 3\nsources = []\n").unwrap();
+        assert!(matches!(
+            load::<Watched>(&dir, &WATCHED),
+            Err(StateError::TooNew {
+                version: 3,
+                supported: 2
+            })
+        ));
+        assert!(
+            load::<Watched>(&dir.join("absent"), &WATCHED)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            fs::read_dir(&dir).unwrap().all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".tmp")),
+            "no temporary files left behind"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repair_reports_and_quarantines_corrupt_files() {
+        const TRUNCATED: StateFile = StateFile {
+            name: "truncated.toml",
+            ..WATCHED
+        };
+        const WRONG_SHAPE: StateFile = StateFile {
+            name: "wrong-shape.toml",
+            ..WATCHED
+        };
+        const FUTURE: StateFile = StateFile {
+            name: "future.toml",
+            ..WATCHED
+        };
+        const ABSENT: StateFile = StateFile {
+            name: "absent.toml",
+            ..WATCHED
+        };
+        let files = [WATCHED, TRUNCATED, WRONG_SHAPE, FUTURE, ABSENT];
+        let dir = temp_dir("state-repair");
+        fs::write(dir.join(WATCHED.name), "app = \"mail\"\n").unwrap();
+        fs::write(dir.join(TRUNCATED.name), "version = 2\nsources = [\"ma").unwrap();
+        fs::write(dir.join(WRONG_SHAPE.name), "version = 2\nsources = 7\n").unwrap();
+        fs::write(dir.join(FUTURE.name), "version = 9\n").unwrap();
+
+        let checked = repair(&dir, &files, false);
+        let lines: Vec<String> = checked.iter().map(ToString::to_string).collect();
+        assert!(
+            lines[0].ends_with("watched.toml: ok (version 0)"),
+            "{lines:?}"
+        );
+        assert!(lines[1].contains("not valid TOML"), "{lines:?}");
+        assert!(lines[2].contains("unexpected contents"), "{lines:?}");
+        assert!(lines[3].contains("newer than this wispd"), "{lines:?}");
+        assert!(lines[4].ends_with("absent.toml: not present"), "{lines:?}");
+        let needing: Vec<bool> = checked.iter().map(FileReport::needs_repair).collect();
+        assert_eq!(needing, [false, true, true, true, false]);
+        assert!(
+            dir.join(TRUNCATED.name).exists(),
+            "a check alone moves nothing"
+        );
+
+        let repaired = repair(&dir, &files, true);
+        let needing: Vec<bool> = repaired.iter().map(FileReport::needs_repair).collect();
+        assert_eq!(needing, [false, false, false, true, false]);
+        for broken in [TRUNCATED.name, WRONG_SHAPE.name] {
+            assert!(!dir.join(broken).exists());
+            assert!(dir.join(format!("{broken}.broken")).exists());
+        }
+        assert!(dir.join(FUTURE.name).exists(), "newer files are left alone");
+        assert!(dir.join(WATCHED.name).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,433 @@
+//! Popup colors, resolved in layers from config and popup state. Each layer records the
+//! fields it set, so `ExplainNotification` can say why a popup looks the way it does;
+//! `view` only uses the final colors.
+
+use std::collections::BTreeMap;
+
+use iced::Color;
+use serde::Serialize;
+use wisp_types::Urgency;
+
+use super::{
+    UiSection, UrgencyColors, parse_hex_color,
+    popup_model::{ButtonColors, CardColors, Rgba},
+    urgency_label,
+};
+
+/// Every color of a popup card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PopupColors {
+    pub(crate) border: Color,
+    pub(crate) background: Color,
+    pub(crate) text: Color,
+    pub(crate) progress: Color,
+    pub(crate) value_progress: Color,
+    pub(crate) app_name: Color,
+    pub(crate) summary: Color,
+    pub(crate) body: Color,
+    pub(crate) button_text: Color,
+    pub(crate) button_background: Color,
+    pub(crate) button_border: Color,
+    pub(crate) button_hover_background: Color,
+    pub(crate) button_hover_text: Color,
+}
+
+impl From<Color> for Rgba {
+    fn from(color: Color) -> Self {
+        Self {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: color.a,
+        }
+    }
+}
+
+impl From<Rgba> for Color {
+    fn from(color: Rgba) -> Self {
+        Color::from_rgba(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl From<PopupColors> for CardColors {
+    fn from(colors: PopupColors) -> Self {
+        Self {
+            border: colors.border.into(),
+            background: colors.background.into(),
+            text: colors.text.into(),
+            progress: colors.progress.into(),
+            value_progress: colors.value_progress.into(),
+            app_name: colors.app_name.into(),
+            summary: colors.summary.into(),
+            body: colors.body.into(),
+            button: ButtonColors {
+                text: colors.button_text.into(),
+                background: colors.button_background.into(),
+                border: colors.button_border.into(),
+                hover_background: colors.button_hover_background.into(),
+                hover_text: colors.button_hover_text.into(),
+            },
+        }
+    }
+}
+
+/// The popup state colors depend on, besides config.
+#[derive(Debug, Clone)]
+pub(crate) struct StyleInputs {
+    pub(crate) urgency: Urgency,
+    pub(crate) error_flash: bool,
+    pub(crate) escalation_flash: bool,
+    pub(crate) activating: bool,
+    /// Drawn invisibly to measure its height.
+    pub(crate) measuring: bool,
+}
+
+/// One layer that set colors, in the order applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct StyleLayer {
+    pub(crate) layer: &'static str,
+    /// Config key or popup state the layer comes from.
+    pub(crate) source: String,
+    /// Fields set, as `#rrggbbaa`.
+    pub(crate) set: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedStyle {
+    pub(crate) colors: PopupColors,
+    pub(crate) layers: Vec<StyleLayer>,
+}
+
+impl PopupColors {
+    const FIELDS: [&'static str; 13] = [
+        "border",
+        "background",
+        "text",
+        "progress",
+        "value_progress",
+        "app_name",
+        "summary",
+        "body",
+        "button_text",
+        "button_background",
+        "button_border",
+        "button_hover_background",
+        "button_hover_text",
+    ];
+
+    fn slot(&mut self, field: &str) -> &mut Color {
+        match field {
+            "border" => &mut self.border,
+            "background" => &mut self.background,
+            "text" => &mut self.text,
+            "progress" => &mut self.progress,
+            "value_progress" => &mut self.value_progress,
+            "app_name" => &mut self.app_name,
+            "summary" => &mut self.summary,
+            "body" => &mut self.body,
+            "button_text" => &mut self.button_text,
+            "button_background" => &mut self.button_background,
+            "button_border" => &mut self.button_border,
+            "button_hover_background" => &mut self.button_hover_background,
+            "button_hover_text" => &mut self.button_hover_text,
+            _ => unreachable!("unknown popup color {field}"),
+        }
+    }
+
+    /// Every color by field name, as `#rrggbbaa`.
+    pub(crate) fn to_hex_map(mut self) -> BTreeMap<&'static str, String> {
+        Self::FIELDS
+            .iter()
+            .map(|field| (*field, color_hex(*self.slot(field))))
+            .collect()
+    }
+
+    /// Fades every color for entrance and exit animations.
+    pub(crate) fn faded(mut self, opacity: f32) -> Self {
+        for field in Self::FIELDS {
+            let color = self.slot(field);
+            *color = super::animation::fade_color(*color, opacity);
+        }
+        self
+    }
+}
+
+/// Applies the layers in order: config colors, urgency border, then the popup states.
+pub(crate) fn resolve_colors(ui: &UiSection, inputs: &StyleInputs) -> ResolvedStyle {
+    let text = parse_hex_color(&ui.colors.text).unwrap_or(Color::WHITE);
+    let mut pipeline = Pipeline {
+        colors: PopupColors {
+            border: Color::TRANSPARENT,
+            background: Color::TRANSPARENT,
+            text: Color::TRANSPARENT,
+            progress: Color::TRANSPARENT,
+            value_progress: Color::TRANSPARENT,
+            app_name: Color::TRANSPARENT,
+            summary: Color::TRANSPARENT,
+            body: Color::TRANSPARENT,
+            button_text: Color::TRANSPARENT,
+            button_background: Color::TRANSPARENT,
+            button_border: Color::TRANSPARENT,
+            button_hover_background: Color::TRANSPARENT,
+            button_hover_text: Color::TRANSPARENT,
+        },
+        layers: Vec::new(),
+    };
+
+    pipeline.set(
+        "colors",
+        "ui.colors",
+        [
+            (
+                "background",
+                parse_hex_color(&ui.colors.background)
+                    .unwrap_or(Color::from_rgba(0.12, 0.12, 0.18, 0.8)),
+            ),
+            ("text", text),
+            (
+                "progress",
+                parse_hex_color(&ui.colors.timeout_progress).unwrap_or(text),
+            ),
+            (
+                "value_progress",
+                parse_hex_color(&ui.colors.value_progress).unwrap_or(text),
+            ),
+        ],
+    );
+    pipeline.set(
+        "text",
+        "ui.text",
+        [
+            (
+                "app_name",
+                parse_hex_color(&ui.text.app_name.color).unwrap_or(text),
+            ),
+            (
+                "summary",
+                parse_hex_color(&ui.text.summary.color).unwrap_or(text),
+            ),
+            ("body", parse_hex_color(&ui.text.body.color).unwrap_or(text)),
+        ],
+    );
+    pipeline.set(
+        "buttons",
+        "ui.buttons",
+        [
+            (
+                "button_text",
+                parse_hex_color(&ui.buttons.text_color)
+                    .unwrap_or(Color::from_rgb8(0xeb, 0xdb, 0xb2)),
+            ),
+            (
+                "button_background",
+                parse_hex_color(&ui.buttons.background)
+                    .unwrap_or(Color::from_rgb8(0x3c, 0x38, 0x36)),
+            ),
+            (
+                "button_border",
+                parse_hex_color(&ui.buttons.border_color)
+                    .unwrap_or(Color::from_rgb8(0x66, 0x5c, 0x54)),
+            ),
+            (
+                "button_hover_background",
+                parse_hex_color(&ui.buttons.hover_background)
+                    .unwrap_or(Color::from_rgb8(0x50, 0x49, 0x45)),
+            ),
+            (
+                "button_hover_text",
+                parse_hex_color(&ui.buttons.hover_text_color)
+                    .unwrap_or(Color::from_rgb8(0xfb, 0xf1, 0xc7)),
+            ),
+        ],
+    );
+    pipeline.set(
+        "urgency",
+        format!("ui.colors.{}", urgency_label(inputs.urgency.clone())),
+        [("border", urgency_color(&ui.colors, inputs.urgency.clone()))],
+    );
+
+    if inputs.error_flash {
+        pipeline.set(
+            "error_flash",
+            "failed command",
+            [("border", urgency_color(&ui.colors, Urgency::Critical))],
+        );
+    }
+    // An escalation flashes the whole card in the border color.
+    if inputs.escalation_flash {
+        let border = pipeline.colors.border;
+        pipeline.set(
+            "escalation_flash",
+            "ui.escalation",
+            [("background", border)],
+        );
+    }
+    if inputs.activating {
+        let colors = pipeline.colors;
+        pipeline.set(
+            "activating",
+            "command in flight",
+            [
+                ("background", dim_color(colors.background)),
+                ("text", dim_color(colors.text)),
+                ("app_name", dim_color(colors.app_name)),
+                ("summary", dim_color(colors.summary)),
+                ("body", dim_color(colors.body)),
+            ],
+        );
+    }
+    if inputs.measuring {
+        pipeline.set(
+            "measuring",
+            "height measurement",
+            PopupColors::FIELDS.map(|field| (field, Color::TRANSPARENT)),
+        );
+    }
+
+    ResolvedStyle {
+        colors: pipeline.colors,
+        layers: pipeline.layers,
+    }
+}
+
+struct Pipeline {
+    colors: PopupColors,
+    layers: Vec<StyleLayer>,
+}
+
+impl Pipeline {
+    fn set<const N: usize>(
+        &mut self,
+        layer: &'static str,
+        source: impl Into<String>,
+        fields: [(&'static str, Color); N],
+    ) {
+        let mut set = BTreeMap::new();
+        for (field, color) in fields {
+            *self.colors.slot(field) = color;
+            set.insert(field, color_hex(color));
+        }
+        self.layers.push(StyleLayer {
+            layer,
+            source: source.into(),
+            set,
+        });
+    }
+}
+
+pub(crate) fn urgency_color(colors: &UrgencyColors, urgency: Urgency) -> Color {
+    let fallback = match urgency {
+        Urgency::Low => Color::from_rgb(0.42, 0.66, 1.0),
+        Urgency::Normal => Color::from_rgb(0.49, 0.81, 0.49),
+        Urgency::Critical => Color::from_rgb(1.0, 0.42, 0.42),
+    };
+
+    let selected = match urgency {
+        Urgency::Low => &colors.low,
+        Urgency::Normal => &colors.normal,
+        Urgency::Critical => &colors.critical,
+    };
+
+    parse_hex_color(selected).unwrap_or(fallback)
+}
+
+fn dim_color(color: Color) -> Color {
+    Color {
+        a: color.a * 0.5,
+        ..color
+    }
+}
+
+fn color_hex(color: Color) -> String {
+    let [r, g, b, a] = color.into_rgba8();
+    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(urgency: Urgency) -> StyleInputs {
+        StyleInputs {
+            urgency,
+            error_flash: false,
+            escalation_flash: false,
+            activating: false,
+            measuring: false,
+        }
+    }
+
+    fn layer_names(style: &ResolvedStyle) -> Vec<&'static str> {
+        style.layers.iter().map(|layer| layer.layer).collect()
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones_and_are_recorded() {
+        let mut ui = UiSection::default();
+        ui.colors.critical = "#ff0000".to_string();
+        ui.colors.background = "#102030".to_string();
+
+        let plain = resolve_colors(&ui, &inputs(Urgency::Normal));
+        assert_eq!(
+            layer_names(&plain),
+            ["colors", "text", "buttons", "urgency"]
+        );
+        assert_eq!(plain.layers[3].source, "ui.colors.normal");
+        assert_eq!(plain.colors.background, Color::from_rgb8(0x10, 0x20, 0x30));
+
+        let style = resolve_colors(
+            &ui,
+            &StyleInputs {
+                escalation_flash: true,
+                activating: true,
+                ..inputs(Urgency::Critical)
+            },
+        );
+        assert_eq!(
+            layer_names(&style),
+            [
+                "colors",
+                "text",
+                "buttons",
+                "urgency",
+                "escalation_flash",
+                "activating"
+            ]
+        );
+        assert_eq!(style.layers[3].set["border"], "#ff0000ff");
+        assert_eq!(style.layers[4].set["background"], "#ff0000ff");
+        assert_eq!(
+            style.colors.background,
+            dim_color(Color::from_rgb8(0xff, 0, 0)),
+            "activating dims the flashed background"
+        );
+        assert!(style.colors.to_hex_map()["background"].starts_with("#ff0000"));
+    }
+
+    #[test]
+    fn invalid_config_colors_fall_back_and_measuring_hides_everything() {
+        let mut ui = UiSection::default();
+        ui.colors.text = "not a color".to_string();
+        ui.text.body.color = "nope".to_string();
+
+        let style = resolve_colors(&ui, &inputs(Urgency::Low));
+        assert_eq!(style.colors.text, Color::WHITE);
+        assert_eq!(style.colors.body, Color::WHITE, "parts fall back to text");
+
+        let measuring = resolve_colors(
+            &ui,
+            &StyleInputs {
+                measuring: true,
+                ..inputs(Urgency::Low)
+            },
+        );
+        assert_eq!(measuring.layers.last().map(|l| l.set.len()), Some(13));
+        assert!(
+            measuring
+                .colors
+                .to_hex_map()
+                .values()
+                .all(|hex| hex.ends_with("00"))
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Keeps the daemon alive across Wayland losses. The UI is the only part that needs a
+//! display: when it dies, the source thread keeps serving D-Bus while the UI is retried
+//! with backoff, and a UI that never comes back leaves the daemon running headless.
+
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+
+/// How one UI run ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UiExit {
+    /// The event loop returned normally.
+    Quit,
+    /// The UI was up and its display went away.
+    Lost(String),
+    /// The UI could not start, e.g. no compositor to connect to.
+    Unavailable(String),
+}
+
+/// One UI lifetime; called again for every retry.
+pub(crate) trait UiRunner {
+    fn run(&mut self) -> UiExit;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    /// Consecutive failed starts before giving up on the UI.
+    pub(crate) max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_attempts: 10,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next start: `initial_backoff` after a lost session or the first
+    /// failed start, doubling with each further failure.
+    fn backoff(&self, failures: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Quit,
+    /// The retry budget ran out; only the source is left.
+    Headless,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    /// Waiting before the next start; `failures` counts consecutive failed starts.
+    Backoff {
+        failures: u32,
+    },
+    Done(Outcome),
+}
+
+/// Runs the UI until it quits, retrying lost or unavailable displays. A UI that was up
+/// resets the budget, so only a display that stays away ends in [`Outcome::Headless`].
+pub(crate) fn supervise<R: UiRunner>(
+    runner: &mut R,
+    policy: RetryPolicy,
+    mut sleep: impl FnMut(Duration),
+) -> Outcome {
+    let mut failures = 0;
+    let mut state = State::Running;
+    loop {
+        state = match state {
+            State::Running => match runner.run() {
+                UiExit::Quit => State::Done(Outcome::Quit),
+                UiExit::Lost(reason) => {
+                    warn!(%reason, "wayland connection lost; source keeps running while the ui restarts");
+                    failures = 0;
+                    State::Backoff { failures }
+                }
+                UiExit::Unavailable(reason) => {
+                    failures += 1;
+                    if failures >= policy.max_attempts {
+                        error!(
+                            %reason,
+                            attempts = failures,
+                            "no wayland display after repeated attempts; running headless (D-Bus only, no popups)"
+                        );
+                        State::Done(Outcome::Headless)
+                    } else {
+                        warn!(%reason, failures, "ui could not start; retrying");
+                        State::Backoff { failures }
+                    }
+                }
+            },
+            State::Backoff { failures } => {
+                let delay = policy.backoff(failures);
+                info!(delay_ms = delay.as_millis() as u64, "retrying ui");
+                sleep(delay);
+                State::Running
+            }
+            State::Done(outcome) => return outcome,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Plays back scripted exits, then quits.
+    struct ScriptedRunner {
+        exits: VecDeque<UiExit>,
+        runs: u32,
+    }
+
+    impl ScriptedRunner {
+        fn new(exits: impl IntoIterator<Item = UiExit>) -> Self {
+            Self {
+                exits: exits.into_iter().collect(),
+                runs: 0,
+            }
+        }
+    }
+
+    impl UiRunner for ScriptedRunner {
+        fn run(&mut self) -> UiExit {
+            self.runs += 1;
+            self.exits.pop_front().unwrap_or(UiExit::Quit)
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            max_attempts,
+        }
+    }
+
+    fn unavailable() -> UiExit {
+        UiExit::Unavailable("no display".to_string())
+    }
+
+    #[test]
+    fn failed_starts_back_off_until_the_ui_comes_up() {
+        let mut runner = ScriptedRunner::new([unavailable(), unavailable(), unavailable()]);
+        let mut sleeps = Vec::new();
+
+        let outcome = supervise(&mut runner, policy(5), |d| sleeps.push(d));
+
+        assert_eq!(outcome, Outcome::Quit);
+        assert_eq!(runner.runs, 4);
+        assert_eq!(sleeps, [100, 200, 350].map(Duration::from_millis).to_vec());
+    }
+
+    #[test]
+    fn exhausting_the_budget_goes_headless() {
+        let mut runner = ScriptedRunner::new(std::iter::repeat_n(unavailable(), 10));
+        let mut sleeps = Vec::new();
+
+        let outcome = supervise(&mut runner, policy(3), |d| sleeps.push(d));
+
+        assert_eq!(outcome, Outcome::Headless);
+        assert_eq!(runner.runs, 3);
+        assert_eq!(sleeps.len(), 2);
+    }
+
+    #[test]
+    fn a_lost_session_resets_the_budget() {
+        let mut runner = ScriptedRunner::new([
+            unavailable(),
+            UiExit::Lost("compositor restarted".to_string()),
+            unavailable(),
+        ]);
+        let mut sleeps = Vec::new();
+
+        let outcome = supervise(&mut runner, policy(2), |d| sleeps.push(d));
+
+        assert_eq!(outcome, Outcome::Quit);
+        assert_eq!(runner.runs, 4);
+        assert_eq!(sleeps, [100, 100, 100].map(Duration::from_millis).to_vec());
+    }
+}
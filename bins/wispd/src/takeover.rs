@@ -0,0 +1,173 @@
+//! Critical takeover. With `ui.urgency.critical.takeover`, a critical notification skips
+//! the stack and shows as one wider popup centred on the overlay layer, optionally over
+//! a dimmed backdrop. It ignores its timeout and closes only when dismissed or one of its
+//! actions is invoked. Ordinary popups wait behind it in the overflow queue; further
+//! criticals wait their turn here, one takeover at a time.
+
+use std::collections::VecDeque;
+
+use iced::{
+    Background, Color, Element, Length,
+    widget::{container, text},
+};
+use iced_layershell::reexport::IcedId;
+use serde::{Deserialize, Serialize};
+
+/// `[ui.urgency]`: settings that depend on a notification's urgency.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct UrgencySection {
+    pub(crate) critical: CriticalSection,
+}
+
+/// `[ui.urgency.critical]`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct CriticalSection {
+    /// Shows critical notifications as a centred takeover instead of in the stack.
+    pub(crate) takeover: bool,
+    /// Dims the whole output behind a takeover.
+    pub(crate) backdrop: bool,
+    /// Colour of the backdrop, alpha included.
+    pub(crate) backdrop_color: String,
+    /// Width of a takeover popup; its height follows the content.
+    pub(crate) width: u32,
+}
+
+impl Default for CriticalSection {
+    fn default() -> Self {
+        Self {
+            takeover: false,
+            backdrop: false,
+            backdrop_color: "#00000073".to_string(),
+            width: 640,
+        }
+    }
+}
+
+/// Surfaces of the takeover on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TakeoverSurfaces {
+    pub(crate) card: IcedId,
+    pub(crate) backdrop: Option<IcedId>,
+}
+
+/// The takeover on screen and the critical notifications waiting for it to close.
+#[derive(Debug, Default)]
+pub(crate) struct Takeovers {
+    showing: Option<(u32, TakeoverSurfaces)>,
+    waiting: VecDeque<u32>,
+}
+
+impl Takeovers {
+    /// Queues `id` for a takeover; [`Takeovers::next`] hands it out in arrival order.
+    pub(crate) fn enqueue(&mut self, id: u32) {
+        if !self.contains(id) {
+            self.waiting.push_back(id);
+        }
+    }
+
+    /// The next notification to take over, once nothing is showing.
+    pub(crate) fn next(&mut self) -> Option<u32> {
+        if self.showing.is_some() {
+            return None;
+        }
+        self.waiting.pop_front()
+    }
+
+    /// Records that `id` now shows on `surfaces`.
+    pub(crate) fn show(&mut self, id: u32, surfaces: TakeoverSurfaces) {
+        self.showing = Some((id, surfaces));
+    }
+
+    /// Forgets `id`, returning its surfaces to close if it was the one showing.
+    pub(crate) fn remove(&mut self, id: u32) -> Option<TakeoverSurfaces> {
+        self.waiting.retain(|waiting| *waiting != id);
+        if self.showing()? != id {
+            return None;
+        }
+        self.showing.take().map(|(_, surfaces)| surfaces)
+    }
+
+    pub(crate) fn showing(&self) -> Option<u32> {
+        self.showing.map(|(id, _)| id)
+    }
+
+    /// Whether a takeover is on screen, holding ordinary popups back.
+    pub(crate) fn is_active(&self) -> bool {
+        self.showing.is_some()
+    }
+
+    pub(crate) fn contains(&self, id: u32) -> bool {
+        self.showing() == Some(id) || self.waiting.contains(&id)
+    }
+
+    pub(crate) fn waiting(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub(crate) fn surfaces(&self) -> Option<TakeoverSurfaces> {
+        self.showing.map(|(_, surfaces)| surfaces)
+    }
+
+    /// The notification whose card `window_id` is.
+    pub(crate) fn card_of(&self, window_id: IcedId) -> Option<u32> {
+        self.showing
+            .filter(|(_, surfaces)| surfaces.card == window_id)
+            .map(|(id, _)| id)
+    }
+
+    pub(crate) fn is_backdrop(&self, window_id: IcedId) -> bool {
+        self.surfaces()
+            .is_some_and(|surfaces| surfaces.backdrop == Some(window_id))
+    }
+}
+
+/// The dimmed fullscreen surface behind a takeover.
+pub(crate) fn backdrop<'a, M: 'a>(color: Color) -> Element<'a, M> {
+    container(text(""))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(move |_| {
+            iced::widget::container::Style::default().background(Background::Color(color))
+        })
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surfaces() -> TakeoverSurfaces {
+        TakeoverSurfaces {
+            card: IcedId::unique(),
+            backdrop: Some(IcedId::unique()),
+        }
+    }
+
+    #[test]
+    fn takeovers_show_one_at_a_time_in_arrival_order() {
+        let mut takeovers = Takeovers::default();
+        takeovers.enqueue(1);
+        takeovers.enqueue(2);
+        takeovers.enqueue(3);
+        assert_eq!(takeovers.next(), Some(1));
+        let first = surfaces();
+        takeovers.show(1, first);
+        assert_eq!(takeovers.next(), None, "one takeover at a time");
+        assert!(takeovers.is_active());
+        assert_eq!(takeovers.card_of(first.card), Some(1));
+        assert!(first.backdrop.is_some_and(|b| takeovers.is_backdrop(b)));
+
+        assert_eq!(
+            takeovers.remove(3),
+            None,
+            "a waiting one just leaves the queue"
+        );
+        assert_eq!(takeovers.waiting(), 1);
+        assert_eq!(takeovers.remove(1), Some(first));
+        assert!(!takeovers.is_active());
+        assert_eq!(takeovers.next(), Some(2));
+        assert_eq!(takeovers.next(), None);
+    }
+}
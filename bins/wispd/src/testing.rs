@@ -0,0 +1,11 @@
+//! Helpers shared by the daemon's unit tests.
+
+use std::{fs, path::PathBuf};
+
+/// A fresh, empty `wispd-<name>-<pid>` directory under the system temp dir.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wispd-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
@@ -0,0 +1,308 @@
+//! Wrapped-text measurements kept across relayouts.
+//!
+//! Every relayout estimates the height of every popup, and most popups have not changed
+//! since the last one. The popup view draws the body from the same measurement, broken
+//! where it was measured to wrap, so what is drawn is as high as what was estimated.
+//! Measurements are keyed by the text's content hash and the
+//! width, font and size it was wrapped with, so a hit is only possible for identical
+//! input. The least recently used entry goes when the cache is full; config reloads
+//! clear it and replacements drop the old text's entries.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use serde::Serialize;
+
+/// Entries kept before the least recently used is evicted; a few per popup.
+pub(crate) const DEFAULT_CAPACITY: usize = 512;
+
+/// Average character advance in ems when the font's own is not known.
+pub(crate) const DEFAULT_CHAR_WIDTH_EM: f32 = 0.54;
+
+/// How a block of text is laid out.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TextBlock<'a> {
+    pub(crate) width_px: f32,
+    pub(crate) font_family: &'a str,
+    pub(crate) font_size: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TextMetrics {
+    /// Wrapped lines.
+    pub(crate) lines: usize,
+    pub(crate) height_px: u32,
+    /// Byte offsets where a line wrapped, not counting line feeds in the text.
+    pub(crate) breaks: Vec<usize>,
+}
+
+impl TextMetrics {
+    /// `text` with a line feed at each break, for drawing without wrapping again. The
+    /// spaces a line wrapped at are dropped.
+    pub(crate) fn wrapped<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.breaks.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        let mut wrapped = String::with_capacity(text.len() + self.breaks.len());
+        let mut start = 0;
+        for &at in &self.breaks {
+            let line = &text[start..at];
+            wrapped.push_str(line.trim_end_matches(|c: char| c.is_whitespace() && c != '\n'));
+            wrapped.push('\n');
+            start = at;
+        }
+        wrapped.push_str(&text[start..]);
+        Cow::Owned(wrapped)
+    }
+}
+
+/// Counters for the debug dump.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct CacheStats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+    pub(crate) entries: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TextKey {
+    content: u64,
+    width_bits: u32,
+    font: u64,
+    size_bits: u32,
+}
+
+impl TextKey {
+    fn new(text: &str, block: TextBlock<'_>) -> Self {
+        Self {
+            content: hash_of(text),
+            width_bits: block.width_px.to_bits(),
+            font: hash_of(block.font_family),
+            size_bits: block.font_size.to_bits(),
+        }
+    }
+}
+
+fn hash_of(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug)]
+pub(crate) struct TextMetricsCache {
+    capacity: usize,
+    /// Average advance the measurements assume; see [`Self::set_char_width_em`].
+    char_width_em: f32,
+    /// Metrics and the use stamp for LRU eviction.
+    entries: HashMap<TextKey, (TextMetrics, u64)>,
+    next_stamp: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl Default for TextMetricsCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl TextMetricsCache {
+    /// A capacity of zero measures every time.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            char_width_em: DEFAULT_CHAR_WIDTH_EM,
+            entries: HashMap::new(),
+            next_stamp: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Metrics of `text` laid out as `block`, from the cache or from `measure`.
+    pub(crate) fn measure(
+        &mut self,
+        text: &str,
+        block: TextBlock<'_>,
+        measure: impl FnOnce() -> TextMetrics,
+    ) -> TextMetrics {
+        let key = TextKey::new(text, block);
+        self.next_stamp += 1;
+        if let Some((metrics, stamp)) = self.entries.get_mut(&key) {
+            *stamp = self.next_stamp;
+            self.hits += 1;
+            return metrics.clone();
+        }
+        self.misses += 1;
+        let metrics = measure();
+        if self.capacity == 0 {
+            return metrics;
+        }
+        if self.entries.len() >= self.capacity
+            && let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, stamp))| *stamp)
+                .map(|(key, _)| *key)
+        {
+            self.entries.remove(&oldest);
+            self.evictions += 1;
+        }
+        self.entries.insert(key, (metrics.clone(), self.next_stamp));
+        metrics
+    }
+
+    /// Drops every measurement of `text`, e.g. the content a replacement superseded.
+    pub(crate) fn forget(&mut self, text: &str) {
+        let content = hash_of(text);
+        self.entries.retain(|key, _| key.content != content);
+    }
+
+    pub(crate) fn char_width_em(&self) -> f32 {
+        self.char_width_em
+    }
+
+    /// Measures with the advance of the font text is drawn in, e.g. the fallback for a
+    /// missing family; `None` restores the default. A change drops every measurement.
+    pub(crate) fn set_char_width_em(&mut self, char_width_em: Option<f32>) {
+        let char_width_em = char_width_em.unwrap_or(DEFAULT_CHAR_WIDTH_EM);
+        if char_width_em != self.char_width_em {
+            self.char_width_em = char_width_em;
+            self.clear();
+        }
+    }
+
+    /// Drops everything; fonts or sizes may have changed.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            entries: self.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: TextBlock<'static> = TextBlock {
+        width_px: 300.0,
+        font_family: "sans-serif",
+        font_size: 15.0,
+    };
+
+    fn metrics(lines: usize) -> TextMetrics {
+        TextMetrics {
+            lines,
+            height_px: lines as u32 * 20,
+            breaks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hits_need_the_same_text_width_font_and_size() {
+        let mut cache = TextMetricsCache::default();
+        assert_eq!(cache.measure("hello", BLOCK, || metrics(1)), metrics(1));
+        assert_eq!(
+            cache.measure("hello", BLOCK, || unreachable!("cached")),
+            metrics(1)
+        );
+
+        let narrower = TextBlock {
+            width_px: 100.0,
+            ..BLOCK
+        };
+        let mono = TextBlock {
+            font_family: "monospace",
+            ..BLOCK
+        };
+        let larger = TextBlock {
+            font_size: 18.0,
+            ..BLOCK
+        };
+        for block in [narrower, mono, larger] {
+            assert_eq!(cache.measure("hello", block, || metrics(2)), metrics(2));
+        }
+        assert_eq!(cache.measure("hello!", BLOCK, || metrics(3)), metrics(3));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 5,
+                evictions: 0,
+                entries: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = TextMetricsCache::new(2);
+        cache.measure("a", BLOCK, || metrics(1));
+        cache.measure("b", BLOCK, || metrics(1));
+        cache.measure("a", BLOCK, || metrics(1));
+        cache.measure("c", BLOCK, || metrics(1));
+
+        assert_eq!(cache.measure("a", BLOCK, || metrics(9)), metrics(1));
+        assert_eq!(cache.measure("b", BLOCK, || metrics(9)), metrics(9));
+        assert_eq!(cache.stats().evictions, 2);
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn forget_and_clear_drop_entries() {
+        let mut cache = TextMetricsCache::default();
+        cache.measure("old", BLOCK, || metrics(1));
+        cache.measure(
+            "old",
+            TextBlock {
+                width_px: 10.0,
+                ..BLOCK
+            },
+            || metrics(4),
+        );
+        cache.measure("kept", BLOCK, || metrics(1));
+        cache.forget("old");
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.measure("old", BLOCK, || metrics(2)), metrics(2));
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+
+        cache.measure("kept", BLOCK, || metrics(1));
+        cache.set_char_width_em(None);
+        assert_eq!(cache.stats().entries, 1, "unchanged width keeps entries");
+        cache.set_char_width_em(Some(0.6));
+        assert_eq!(cache.char_width_em(), 0.6);
+        assert_eq!(cache.stats().entries, 0);
+
+        let mut uncached = TextMetricsCache::new(0);
+        uncached.measure("x", BLOCK, || metrics(1));
+        assert_eq!(uncached.measure("x", BLOCK, || metrics(2)), metrics(2));
+        assert_eq!(uncached.stats().entries, 0);
+    }
+
+    #[test]
+    fn wrapped_text_breaks_where_it_was_measured() {
+        let text = "one two\nthree  four";
+        assert!(matches!(metrics(2).wrapped(text), Cow::Borrowed(_)));
+        let measured = TextMetrics {
+            breaks: vec![4, 15],
+            ..metrics(4)
+        };
+        assert_eq!(measured.wrapped(text), "one\ntwo\nthree\nfour");
+    }
+}
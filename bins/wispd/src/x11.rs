@@ -0,0 +1,418 @@
+//! X11 fallback presentation (`x11` feature). Without layer-shell, each popup is a
+//! borderless always-on-top window placed by hand on the primary monitor. The UI keeps
+//! emitting its layer-shell messages; [`X11Presenter`] turns the ones that place
+//! surfaces into window moves, resizes and closes, and swallows the rest.
+
+use std::{
+    collections::HashMap,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use anyhow::{Context, Result};
+use iced::{Point, Size, Task, window};
+use iced_layershell::reexport::{Anchor, IcedId, KeyboardInteractivity, NewLayerShellSettings};
+use tracing::{debug, info};
+use x11rb::{connection::Connection as _, protocol::randr::ConnectionExt as _};
+
+use super::{
+    LayerShellRunner, Message, WispdUi, animation::Margin, app_style, cli::Backend, namespace,
+    subscription, supervisor::UiExit, ui_exit, update, view,
+};
+
+/// Area popups are anchored in, in X root-window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Screen {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// Queries the primary monitor through RandR; without one, the first monitor, and
+/// without RandR the whole root window.
+fn primary_screen() -> Result<Screen> {
+    let (conn, screen_num) = x11rb::connect(None).context("failed to connect to the X server")?;
+    let root = &conn.setup().roots[screen_num];
+    let whole = Screen {
+        x: 0,
+        y: 0,
+        width: u32::from(root.width_in_pixels),
+        height: u32::from(root.height_in_pixels),
+    };
+
+    let monitors = match conn
+        .randr_get_monitors(root.root, true)
+        .map_err(anyhow::Error::from)
+        .and_then(|cookie| cookie.reply().map_err(anyhow::Error::from))
+    {
+        Ok(reply) => reply.monitors,
+        Err(err) => {
+            debug!(?err, "randr monitors unavailable; using the root window");
+            return Ok(whole);
+        }
+    };
+    let monitor = monitors
+        .iter()
+        .find(|monitor| monitor.primary)
+        .or_else(|| monitors.first());
+    Ok(monitor.map_or(whole, |monitor| Screen {
+        x: i32::from(monitor.x),
+        y: i32::from(monitor.y),
+        width: u32::from(monitor.width),
+        height: u32::from(monitor.height),
+    }))
+}
+
+/// What a layer-shell surface would have been asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Placement {
+    anchor: Anchor,
+    /// `(top, right, bottom, left)`, as in layer-shell.
+    margin: Margin,
+    size: (u32, u32),
+}
+
+impl Placement {
+    /// Top-left corner on `screen` following layer-shell rules: margins count from the
+    /// anchored edges, and an axis anchored on both or neither side is centred.
+    fn origin(&self, screen: Screen) -> (i32, i32) {
+        let (top, right, bottom, left) = self.margin;
+        let x = axis_start(
+            (screen.x, screen.width),
+            self.size.0,
+            (
+                self.anchor.contains(Anchor::Left),
+                self.anchor.contains(Anchor::Right),
+            ),
+            (left, right),
+        );
+        let y = axis_start(
+            (screen.y, screen.height),
+            self.size.1,
+            (
+                self.anchor.contains(Anchor::Top),
+                self.anchor.contains(Anchor::Bottom),
+            ),
+            (top, bottom),
+        );
+        (x, y)
+    }
+}
+
+/// Start of a `size`-long surface on one axis of the `(start, span)` screen range.
+fn axis_start(
+    (start, span): (i32, u32),
+    size: u32,
+    anchored: (bool, bool),
+    (near_margin, far_margin): (i32, i32),
+) -> i32 {
+    let free = span as i32 - size as i32;
+    match anchored {
+        (true, false) => start + near_margin,
+        (false, true) => start + free - far_margin,
+        _ => start + free / 2,
+    }
+}
+
+/// Opens popups as X11 windows and replays layer-shell placement messages onto them.
+#[derive(Debug)]
+pub(crate) struct X11Presenter {
+    screen: Screen,
+    popups: HashMap<IcedId, Placement>,
+}
+
+impl X11Presenter {
+    pub(crate) fn new(screen: Screen) -> Self {
+        Self {
+            screen,
+            popups: HashMap::new(),
+        }
+    }
+
+    /// Opens a window where the layer surface described by `settings` would appear.
+    pub(crate) fn open(&mut self, settings: NewLayerShellSettings) -> (IcedId, Task<Message>) {
+        let placement = Placement {
+            anchor: settings.anchor,
+            margin: settings.margin.unwrap_or_default(),
+            size: settings.size.unwrap_or((1, 1)),
+        };
+        let (x, y) = placement.origin(self.screen);
+        let (id, open) = window::open(window::Settings {
+            size: Size::new(placement.size.0 as f32, placement.size.1 as f32),
+            position: window::Position::Specific(Point::new(x as f32, y as f32)),
+            visible: true,
+            resizable: false,
+            decorations: false,
+            transparent: true,
+            level: window::Level::AlwaysOnTop,
+            exit_on_close_request: false,
+            platform_specific: window::settings::PlatformSpecific {
+                application_id: settings.namespace.clone().unwrap_or_else(namespace),
+                // Keeps the window manager from focusing, framing or moving popups.
+                override_redirect: true,
+            },
+            ..window::Settings::default()
+        });
+        self.popups.insert(id, placement);
+        (id, open.discard())
+    }
+
+    /// Applies a layer-shell message the UI sends to the popup windows. Returns `None`
+    /// for everything else, which `update` handles (other layer-shell messages are never
+    /// sent, and would be no-ops there).
+    pub(crate) fn apply(&mut self, message: &Message) -> Option<Task<Message>> {
+        let task = match message {
+            Message::MarginChange { id, margin } => self.place(*id, |p| p.margin = *margin),
+            Message::AnchorSizeChange { id, anchor, size } => self.place(*id, |p| {
+                p.anchor = *anchor;
+                p.size = *size;
+            }),
+            Message::RemoveWindow(id) => {
+                self.popups.remove(id);
+                window::close(*id)
+            }
+            // Windows always go to the primary monitor, so there is no output to forget.
+            Message::ForgetLastOutput => Task::none(),
+            Message::KeyboardInteractivityChange {
+                id,
+                keyboard_interactivity,
+            } if !matches!(keyboard_interactivity, KeyboardInteractivity::None) => {
+                window::gain_focus(*id)
+            }
+            Message::KeyboardInteractivityChange { .. } => Task::none(),
+            _ => return None,
+        };
+        Some(task)
+    }
+
+    /// Updates a popup's placement and moves or resizes its window to match.
+    fn place(&mut self, id: IcedId, change: impl FnOnce(&mut Placement)) -> Task<Message> {
+        let Some(placement) = self.popups.get_mut(&id) else {
+            return Task::none();
+        };
+        let before = *placement;
+        change(placement);
+        let after = *placement;
+
+        let mut tasks = Vec::new();
+        if after.size != before.size {
+            tasks.push(window::resize(
+                id,
+                Size::new(after.size.0 as f32, after.size.1 as f32),
+            ));
+        }
+        let origin = after.origin(self.screen);
+        if origin != before.origin(self.screen) {
+            tasks.push(window::move_to(
+                id,
+                Point::new(origin.0 as f32, origin.1 as f32),
+            ));
+        }
+        Task::batch(tasks)
+    }
+}
+
+fn title(_state: &WispdUi, _window: IcedId) -> String {
+    namespace()
+}
+
+/// One UI lifetime on X11; the X11 counterpart of the layer-shell run.
+pub(super) fn run(runner: &LayerShellRunner) -> UiExit {
+    let screen = match primary_screen() {
+        Ok(screen) => screen,
+        Err(err) => return UiExit::Unavailable(format!("{err:#}")),
+    };
+    info!(
+        ?screen,
+        "presenting popups as x11 windows on the primary monitor"
+    );
+
+    let booted = Arc::new(AtomicBool::new(false));
+    let boot = runner.boot(Arc::clone(&booted));
+    let app = iced::daemon(
+        move || boot().presenting_on_x11(X11Presenter::new(screen)),
+        update,
+        view,
+    )
+    .title(title)
+    .style(app_style)
+    .subscription(|state| subscription(state, None));
+
+    let run_result = catch_unwind(AssertUnwindSafe(|| app.run()));
+    ui_exit(run_result, &booted, Backend::X11)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, mpsc};
+
+    use tokio::sync::mpsc as tokio_mpsc;
+    use wisp_types::{Notification, NotificationEvent};
+
+    use super::super::{UiSection, mutes::MuteList};
+    use super::*;
+
+    const SCREEN: Screen = Screen {
+        x: 1920,
+        y: 0,
+        width: 1000,
+        height: 800,
+    };
+
+    fn placement(anchor: Anchor) -> Placement {
+        Placement {
+            anchor,
+            margin: (10, 20, 30, 40),
+            size: (300, 100),
+        }
+    }
+
+    #[test]
+    fn anchored_corners_keep_their_margins() {
+        assert_eq!(
+            placement(Anchor::Top | Anchor::Right).origin(SCREEN),
+            (1920 + 1000 - 300 - 20, 10)
+        );
+        assert_eq!(
+            placement(Anchor::Bottom | Anchor::Left).origin(SCREEN),
+            (1920 + 40, 800 - 100 - 30)
+        );
+    }
+
+    #[test]
+    fn unanchored_axes_are_centred() {
+        assert_eq!(placement(Anchor::Top).origin(SCREEN), (1920 + 350, 10));
+        assert_eq!(placement(Anchor::Left).origin(SCREEN), (1960, 350));
+    }
+
+    #[test]
+    fn layer_shell_messages_drive_the_window_and_ui_messages_pass_through() {
+        let mut presenter = X11Presenter::new(SCREEN);
+        let (id, _) = presenter.open(NewLayerShellSettings {
+            size: Some((300, 100)),
+            anchor: Anchor::Top | Anchor::Right,
+            margin: Some((10, 20, 30, 40)),
+            ..Default::default()
+        });
+        assert_eq!(presenter.popups[&id].origin(SCREEN), (2600, 10));
+
+        assert!(
+            presenter
+                .apply(&Message::MarginChange {
+                    id,
+                    margin: (120, 20, 30, 40),
+                })
+                .is_some()
+        );
+        assert_eq!(presenter.popups[&id].origin(SCREEN), (2600, 120));
+
+        assert!(presenter.apply(&Message::ForgetLastOutput).is_some());
+        assert!(presenter.apply(&Message::Tick).is_none());
+
+        assert!(presenter.apply(&Message::RemoveWindow(id)).is_some());
+        assert!(presenter.popups.is_empty());
+    }
+
+    #[test]
+    fn anchor_and_size_changes_resize_and_move_the_window() {
+        let mut presenter = X11Presenter::new(SCREEN);
+        let (id, _) = presenter.open(NewLayerShellSettings {
+            size: Some((300, 100)),
+            anchor: Anchor::Top | Anchor::Right,
+            margin: Some((10, 20, 30, 40)),
+            ..Default::default()
+        });
+
+        assert!(
+            presenter
+                .apply(&Message::AnchorSizeChange {
+                    id,
+                    anchor: Anchor::Bottom | Anchor::Left,
+                    size: (400, 200),
+                })
+                .is_some()
+        );
+        assert_eq!(presenter.popups[&id].size, (400, 200));
+        assert_eq!(presenter.popups[&id].origin(SCREEN), (1960, 570));
+
+        // A window closed in between is not brought back by a late placement.
+        assert!(presenter.apply(&Message::RemoveWindow(id)).is_some());
+        assert!(
+            presenter
+                .apply(&Message::MarginChange {
+                    id,
+                    margin: (0, 0, 0, 0),
+                })
+                .is_some()
+        );
+        assert!(presenter.popups.is_empty());
+    }
+
+    #[test]
+    fn keyboard_requests_never_reach_the_layer_shell_handler() {
+        let mut presenter = X11Presenter::new(SCREEN);
+        let (id, _) = presenter.open(NewLayerShellSettings::default());
+        for keyboard_interactivity in [
+            KeyboardInteractivity::Exclusive,
+            KeyboardInteractivity::None,
+        ] {
+            assert!(
+                presenter
+                    .apply(&Message::KeyboardInteractivityChange {
+                        id,
+                        keyboard_interactivity,
+                    })
+                    .is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn popups_open_as_placed_windows_and_their_layer_messages_move_them() {
+        let (_event_tx, event_rx) = mpsc::channel();
+        let (_signal_tx, signal_rx) = mpsc::channel();
+        let (_reply_tx, reply_rx) = mpsc::channel();
+        let (cmd_tx, _cmd_rx) = tokio_mpsc::unbounded_channel();
+        let mut ui = WispdUi::new(
+            Arc::new(Mutex::new(event_rx)),
+            Arc::new(Mutex::new(signal_rx)),
+            Arc::new(Mutex::new(reply_rx)),
+            cmd_tx,
+            UiSection::default(),
+            None,
+            MuteList::in_memory(),
+        )
+        .presenting_on_x11(X11Presenter::new(SCREEN));
+
+        let _ = ui.apply_event(NotificationEvent::Received {
+            id: 1,
+            notification: Box::new(Notification {
+                summary: String::from("hello"),
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        });
+        let window = ui.windows[0].window_id;
+        let presenter = ui.x11.as_ref().expect("x11 presenter");
+        assert_eq!(presenter.popups.len(), 1);
+        let placed = presenter.popups[&window];
+
+        let _ = update(
+            &mut ui,
+            Message::MarginChange {
+                id: window,
+                margin: (
+                    placed.margin.0 + 50,
+                    placed.margin.1,
+                    placed.margin.2,
+                    placed.margin.3,
+                ),
+            },
+        );
+        let moved = ui.x11.as_ref().expect("x11 presenter").popups[&window];
+        assert_eq!(moved.margin.0, placed.margin.0 + 50);
+        assert_ne!(moved.origin(SCREEN), placed.origin(SCREEN));
+    }
+}
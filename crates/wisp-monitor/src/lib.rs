@@ -1,7 +1,11 @@
 use std::collections::HashMap;
 
 use anyhow::{Context, Result};
-use zbus::{Message, message::Type as MessageType, zvariant};
+use zbus::{MatchRule, Message, message::Type as MessageType, zvariant};
+
+pub mod tracker;
+
+pub use tracker::{NotificationTracker, TrackerEvent};
 
 pub const DBUS_NAME: &str = "org.freedesktop.DBus";
 pub const DBUS_PATH: &str = "/org/freedesktop/DBus";
@@ -12,6 +16,7 @@ pub const NOTIFY_IFACE: &str = "org.freedesktop.Notifications";
 pub struct NotifyCall {
     pub app_name: String,
     pub replaces_id: u32,
+    pub app_icon: String,
     pub summary: String,
     pub body: String,
     pub actions: Vec<String>,
@@ -25,6 +30,7 @@ pub enum NotificationMessage {
     CloseNotification { id: u32 },
     NotificationClosed { id: u32, reason: u32 },
     ActionInvoked { id: u32, action_key: String },
+    NotificationReplied { id: u32, text: String },
 }
 
 pub async fn become_monitor(conn: &zbus::Connection, rules: Vec<String>) -> Result<()> {
@@ -54,6 +60,53 @@ pub fn rules_notify_only() -> Vec<String> {
     )]
 }
 
+/// Builds a match rule on the notifications interface, optionally narrowed by message type,
+/// member, and sender (a unique name such as `:1.234`).
+pub fn notification_rule(
+    msg_type: Option<MessageType>,
+    member: Option<&str>,
+    sender: Option<&str>,
+) -> Result<String> {
+    let mut builder = MatchRule::builder().interface(NOTIFY_IFACE)?;
+    if let Some(msg_type) = msg_type {
+        builder = builder.msg_type(msg_type);
+    }
+    if let Some(member) = member {
+        builder = builder
+            .member(member)
+            .with_context(|| format!("invalid member name {member:?}"))?;
+    }
+    if let Some(sender) = sender {
+        builder = builder
+            .sender(sender)
+            .with_context(|| format!("invalid sender {sender:?}"))?;
+    }
+    Ok(builder.build().to_string())
+}
+
+/// Matches method returns sent by `sender`, e.g. the ids a daemon hands back from `Notify`.
+pub fn method_return_rule(sender: &str) -> Result<String> {
+    Ok(MatchRule::builder()
+        .msg_type(MessageType::MethodReturn)
+        .sender(sender)
+        .with_context(|| format!("invalid sender {sender:?}"))?
+        .build()
+        .to_string())
+}
+
+/// Message type of a known notifications-interface member.
+pub fn member_type(member: &str) -> Option<MessageType> {
+    match member {
+        "Notify" | "CloseNotification" | "GetCapabilities" | "GetServerInformation" => {
+            Some(MessageType::MethodCall)
+        }
+        "NotificationClosed" | "ActionInvoked" | "ActivationToken" | "NotificationReplied" => {
+            Some(MessageType::Signal)
+        }
+        _ => None,
+    }
+}
+
 pub fn parse_notification_message(msg: &Message) -> Result<Option<NotificationMessage>> {
     let header = msg.header();
 
@@ -69,7 +122,7 @@ pub fn parse_notification_message(msg: &Message) -> Result<Option<NotificationMe
 
     match (msg.message_type(), member) {
         (MessageType::MethodCall, Some("Notify")) => {
-            let (app_name, replaces_id, _app_icon, summary, body, actions, hints, expire_timeout) =
+            let (app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout) =
                 msg.body().deserialize::<(
                     String,
                     u32,
@@ -84,6 +137,7 @@ pub fn parse_notification_message(msg: &Message) -> Result<Option<NotificationMe
             Ok(Some(NotificationMessage::Notify(NotifyCall {
                 app_name,
                 replaces_id,
+                app_icon,
                 summary,
                 body,
                 actions,
@@ -103,6 +157,10 @@ pub fn parse_notification_message(msg: &Message) -> Result<Option<NotificationMe
             let (id, action_key) = msg.body().deserialize::<(u32, String)>()?;
             Ok(Some(NotificationMessage::ActionInvoked { id, action_key }))
         }
+        (MessageType::Signal, Some("NotificationReplied")) => {
+            let (id, text) = msg.body().deserialize::<(u32, String)>()?;
+            Ok(Some(NotificationMessage::NotificationReplied { id, text }))
+        }
         _ => Ok(None),
     }
 }
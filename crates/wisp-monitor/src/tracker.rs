@@ -0,0 +1,240 @@
+//! Rebuilds notification lifecycles from monitored bus traffic. A `Notify` call only
+//! becomes a notification once the daemon's method return reveals its id, so calls are
+//! held until their reply is seen.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{Context, Result};
+use zbus::{Message, message::Type as MessageType};
+
+use crate::{NotificationMessage, NotifyCall, parse_notification_message};
+
+/// Unanswered `Notify` calls kept before the oldest are forgotten.
+const MAX_PENDING: usize = 512;
+
+/// A lifecycle step of a notification owned by another daemon.
+#[derive(Debug, Clone)]
+pub enum TrackerEvent {
+    /// The daemon answered `call` with `id`; `replaced` when `id` was already live.
+    Notified {
+        id: u32,
+        replaced: bool,
+        call: NotifyCall,
+    },
+    /// The daemon emitted `NotificationClosed`.
+    Closed { id: u32, reason: u32 },
+    /// The daemon emitted `ActionInvoked`.
+    ActionInvoked { id: u32, action_key: String },
+    /// The daemon emitted `NotificationReplied`.
+    Replied { id: u32, text: String },
+}
+
+/// Joins `Notify` calls with the replies that carry their ids and tracks which ids are live.
+///
+/// Feed it every message from a monitor connection matching [`crate::rules_all_notifications`]
+/// plus [`crate::method_return_rule`] for the daemon.
+#[derive(Debug, Default)]
+pub struct NotificationTracker {
+    pending: HashMap<(String, u32), NotifyCall>,
+    order: VecDeque<(String, u32)>,
+    live: HashSet<u32>,
+}
+
+impl NotificationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the tracker with one monitored message, returning the step it completes.
+    pub fn observe(&mut self, msg: &Message) -> Result<Option<TrackerEvent>> {
+        let header = msg.header();
+        if msg.message_type() == MessageType::MethodReturn {
+            let Some((destination, reply_serial)) = header.destination().zip(header.reply_serial())
+            else {
+                return Ok(None);
+            };
+            let Some(call) = self.take_pending(&destination.to_string(), reply_serial.get()) else {
+                return Ok(None);
+            };
+            let id = msg
+                .body()
+                .deserialize::<u32>()
+                .context("Notify reply did not carry an id")?;
+            return Ok(Some(self.notified(id, call)));
+        }
+
+        match parse_notification_message(msg)? {
+            Some(NotificationMessage::Notify(call)) => {
+                if let Some(sender) = header.sender() {
+                    let serial = msg.primary_header().serial_num().get();
+                    self.insert_pending(sender.to_string(), serial, call);
+                }
+                Ok(None)
+            }
+            Some(NotificationMessage::NotificationClosed { id, reason }) => {
+                self.live.remove(&id);
+                Ok(Some(TrackerEvent::Closed { id, reason }))
+            }
+            Some(NotificationMessage::ActionInvoked { id, action_key }) => {
+                Ok(Some(TrackerEvent::ActionInvoked { id, action_key }))
+            }
+            Some(NotificationMessage::NotificationReplied { id, text }) => {
+                Ok(Some(TrackerEvent::Replied { id, text }))
+            }
+            // The daemon answers a close with `NotificationClosed`, which is what counts.
+            Some(NotificationMessage::CloseNotification { .. }) | None => Ok(None),
+        }
+    }
+
+    /// Ids the daemon has handed out and not yet closed.
+    pub fn live(&self) -> &HashSet<u32> {
+        &self.live
+    }
+
+    fn notified(&mut self, id: u32, call: NotifyCall) -> TrackerEvent {
+        let replaced = !self.live.insert(id);
+        TrackerEvent::Notified { id, replaced, call }
+    }
+
+    fn insert_pending(&mut self, sender: String, serial: u32, call: NotifyCall) {
+        if self.order.len() == MAX_PENDING
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.pending.remove(&oldest);
+        }
+        self.order.push_back((sender.clone(), serial));
+        self.pending.insert((sender, serial), call);
+    }
+
+    fn take_pending(&mut self, destination: &str, reply_serial: u32) -> Option<NotifyCall> {
+        let key = (destination.to_string(), reply_serial);
+        let call = self.pending.remove(&key)?;
+        self.order.retain(|k| *k != key);
+        Some(call)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use zbus::zvariant::Value;
+
+    use super::*;
+    use crate::NOTIFY_IFACE;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+
+    fn notify(sender: &str, replaces_id: u32, summary: &str) -> Message {
+        let hints: HashMap<&str, Value<'_>> = HashMap::from([("urgency", Value::from(2_u8))]);
+        Message::method_call(PATH, "Notify")
+            .unwrap()
+            .interface(NOTIFY_IFACE)
+            .unwrap()
+            .sender(sender)
+            .unwrap()
+            .build(&(
+                "mail",
+                replaces_id,
+                "mail-unread",
+                summary,
+                "body",
+                vec!["default", "Open"],
+                hints,
+                -1_i32,
+            ))
+            .unwrap()
+    }
+
+    fn reply(call: &Message, id: u32) -> Message {
+        Message::method_return(&call.header())
+            .unwrap()
+            .sender(":1.7")
+            .unwrap()
+            .build(&(id,))
+            .unwrap()
+    }
+
+    fn closed(id: u32, reason: u32) -> Message {
+        Message::signal(PATH, NOTIFY_IFACE, "NotificationClosed")
+            .unwrap()
+            .sender(":1.7")
+            .unwrap()
+            .build(&(id, reason))
+            .unwrap()
+    }
+
+    #[test]
+    fn notify_becomes_an_event_only_once_its_reply_names_the_id() {
+        let mut tracker = NotificationTracker::new();
+        let call = notify(":1.42", 0, "hello");
+        assert!(tracker.observe(&call).unwrap().is_none());
+
+        let Some(TrackerEvent::Notified { id, replaced, call }) =
+            tracker.observe(&reply(&call, 9)).unwrap()
+        else {
+            panic!("expected a notification");
+        };
+        assert_eq!((id, replaced), (9, false));
+        assert_eq!(call.summary, "hello");
+        assert_eq!(call.app_icon, "mail-unread");
+        assert!(tracker.live().contains(&9));
+    }
+
+    #[test]
+    fn replacement_and_close_update_the_live_set() {
+        let mut tracker = NotificationTracker::new();
+        let first = notify(":1.42", 0, "v1");
+        tracker.observe(&first).unwrap();
+        tracker.observe(&reply(&first, 3)).unwrap();
+
+        let second = notify(":1.42", 3, "v2");
+        tracker.observe(&second).unwrap();
+        let event = tracker.observe(&reply(&second, 3)).unwrap();
+        assert!(matches!(
+            event,
+            Some(TrackerEvent::Notified {
+                id: 3,
+                replaced: true,
+                ..
+            })
+        ));
+
+        let event = tracker.observe(&closed(3, 2)).unwrap();
+        assert!(matches!(
+            event,
+            Some(TrackerEvent::Closed { id: 3, reason: 2 })
+        ));
+        assert!(tracker.live().is_empty());
+    }
+
+    #[test]
+    fn inline_replies_are_reported_without_closing() {
+        let mut tracker = NotificationTracker::new();
+        let call = notify(":1.42", 0, "chat");
+        tracker.observe(&call).unwrap();
+        tracker.observe(&reply(&call, 4)).unwrap();
+
+        let replied = Message::signal(PATH, NOTIFY_IFACE, "NotificationReplied")
+            .unwrap()
+            .sender(":1.7")
+            .unwrap()
+            .build(&(4_u32, "on my way"))
+            .unwrap();
+        let Some(TrackerEvent::Replied { id, text }) = tracker.observe(&replied).unwrap() else {
+            panic!("expected a reply");
+        };
+        assert_eq!((id, text.as_str()), (4, "on my way"));
+        assert!(tracker.live().contains(&4));
+    }
+
+    #[test]
+    fn unrelated_replies_are_ignored() {
+        let mut tracker = NotificationTracker::new();
+        let call = notify(":1.42", 0, "hello");
+        let other = notify(":1.43", 0, "elsewhere");
+        tracker.observe(&call).unwrap();
+        assert!(tracker.observe(&reply(&other, 5)).unwrap().is_none());
+        assert!(tracker.live().is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+//! Hint parsing for a chat message: the 20 hints a typical chat client sends, parsed with
+//! `extra` kept and skipped. Run with `cargo bench -p wisp-source --bench parse_hints`.
+
+use std::{collections::HashMap, hint::black_box};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use zbus::zvariant::{OwnedValue, Str, Value};
+
+fn chat_hint_map() -> HashMap<String, OwnedValue> {
+    let text = |value: &str| OwnedValue::from(Str::from(value.to_string()));
+    let mut hints = HashMap::from([
+        ("urgency".to_string(), OwnedValue::from(1_u8)),
+        ("category".to_string(), text("im.received")),
+        ("desktop-entry".to_string(), text("org.example.Chat")),
+        ("transient".to_string(), OwnedValue::from(false)),
+        ("sound-name".to_string(), text("message-new-instant")),
+        ("x-kde-display-appname".to_string(), text("Chat")),
+        ("x-kde-origin-name".to_string(), text("Work")),
+        (
+            "x-kde-urls".to_string(),
+            OwnedValue::try_from(Value::from(vec!["https://chat.example.org/c/42"]))
+                .expect("string array hint"),
+        ),
+        ("sender-pid".to_string(), OwnedValue::from(4_242_u32)),
+        ("image-path".to_string(), text("/tmp/chat/avatar-alice.png")),
+        ("suppress-sound".to_string(), OwnedValue::from(false)),
+        ("resident".to_string(), OwnedValue::from(false)),
+        ("action-icons".to_string(), OwnedValue::from(false)),
+        ("x-example-conversation-id".to_string(), text("c-42")),
+        ("x-example-message-id".to_string(), text("m-9f2c1e")),
+        ("x-example-thread".to_string(), text("release planning")),
+        ("x-example-unread".to_string(), OwnedValue::from(7_u32)),
+    ]);
+    for i in 0..3 {
+        hints.insert(format!("x-example-extra-{i}"), text("padding value"));
+    }
+    assert_eq!(hints.len(), 20);
+    hints
+}
+
+fn parse_hints(c: &mut Criterion) {
+    let hints = chat_hint_map();
+    let mut group = c.benchmark_group("parse_hints/chat_20");
+    group.bench_function("keep_extra", |b| {
+        b.iter(|| wisp_source::parse_raw_hints_with(black_box(&hints), true))
+    });
+    group.bench_function("skip_extra", |b| {
+        b.iter(|| wisp_source::parse_raw_hints_with(black_box(&hints), false))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse_hints);
+criterion_main!(benches);
@@ -0,0 +1,214 @@
+//! Per-app rate limit on new notifications. Each `app_name` gets a token bucket; a new
+//! notification takes a token or is dropped, and the drops are counted for coalesced
+//! [`NotificationEvent::RateLimited`](wisp_types::NotificationEvent::RateLimited) reports.
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Minimum spacing between two drop reports for the same app.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the buckets of apps that went quiet are forgotten.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many new notifications one app may send; replacements are not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Sustained rate, in new notifications per minute.
+    pub per_app_per_minute: u32,
+    /// New notifications an app that was quiet may send at once; at least one.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+
+    /// Tokens `bucket` holds at `now`, refilled since it was last touched.
+    fn tokens_at(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        let refill = elapsed.as_secs_f64() * f64::from(self.per_app_per_minute) / 60.0;
+        (bucket.tokens + refill).min(self.capacity())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Drops since the last report.
+    suppressed: u64,
+    reported_at: Option<Instant>,
+}
+
+impl Bucket {
+    /// The drops to report now; counting starts over.
+    fn take_report(&mut self, now: Instant) -> u64 {
+        self.reported_at = Some(now);
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
+/// What happens to one new notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Verdict {
+    /// Whether it is stored; otherwise it is dropped.
+    pub(crate) pass: bool,
+    /// Drops of the same app to report before it, when a report is due.
+    pub(crate) report: Option<u64>,
+}
+
+/// Token buckets by `app_name`.
+#[derive(Debug, Default)]
+pub(crate) struct AppLimiter {
+    buckets: HashMap<String, Bucket>,
+    pruned_at: Option<Instant>,
+}
+
+impl AppLimiter {
+    /// Takes a token for a new notification from `app_name` at `now`. Drops are reported
+    /// when the app gets through again, or at most once per [`REPORT_INTERVAL`] while it
+    /// keeps being dropped.
+    pub(crate) fn check(&mut self, limit: RateLimit, app_name: &str, now: Instant) -> Verdict {
+        if !self.buckets.contains_key(app_name) {
+            self.buckets.insert(
+                app_name.to_string(),
+                Bucket {
+                    tokens: limit.capacity(),
+                    refilled_at: now,
+                    suppressed: 0,
+                    reported_at: None,
+                },
+            );
+        }
+        let bucket = self
+            .buckets
+            .get_mut(app_name)
+            .expect("bucket inserted above");
+        bucket.tokens = limit.tokens_at(bucket, now);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            let report = (bucket.suppressed > 0).then(|| bucket.take_report(now));
+            return Verdict { pass: true, report };
+        }
+        bucket.suppressed += 1;
+        let due = bucket
+            .reported_at
+            .is_none_or(|at| now.saturating_duration_since(at) >= REPORT_INTERVAL);
+        Verdict {
+            pass: false,
+            report: due.then(|| bucket.take_report(now)),
+        }
+    }
+
+    /// Forgets the apps whose bucket has refilled, at most once per [`PRUNE_INTERVAL`].
+    /// Returns the drops they had left unreported, by app.
+    pub(crate) fn prune(&mut self, limit: RateLimit, now: Instant) -> Vec<(String, u64)> {
+        if self
+            .pruned_at
+            .is_some_and(|at| now.saturating_duration_since(at) < PRUNE_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.pruned_at = Some(now);
+        let mut unreported = Vec::new();
+        self.buckets.retain(|app_name, bucket| {
+            if limit.tokens_at(bucket, now) < limit.capacity() {
+                return true;
+            }
+            if bucket.suppressed > 0 {
+                unreported.push((app_name.clone(), bucket.suppressed));
+            }
+            false
+        });
+        unreported.sort_unstable();
+        unreported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        per_app_per_minute: 60,
+        burst: 3,
+    };
+
+    fn pass(report: Option<u64>) -> Verdict {
+        Verdict { pass: true, report }
+    }
+
+    fn dropped(report: Option<u64>) -> Verdict {
+        Verdict {
+            pass: false,
+            report,
+        }
+    }
+
+    #[test]
+    fn a_burst_passes_then_one_per_refill_interval() {
+        let mut limiter = AppLimiter::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(LIMIT, "spam", start), pass(None));
+        }
+        assert_eq!(limiter.check(LIMIT, "spam", at(0)), dropped(Some(1)));
+        assert_eq!(limiter.check(LIMIT, "spam", at(500)), dropped(None));
+        assert_eq!(
+            limiter.check(LIMIT, "mail", at(500)),
+            pass(None),
+            "apps have their own bucket"
+        );
+        assert_eq!(
+            limiter.check(LIMIT, "spam", at(1_000)),
+            pass(Some(1)),
+            "a refilled token passes and reports what was held back"
+        );
+        assert_eq!(limiter.check(LIMIT, "spam", at(1_100)), dropped(None));
+    }
+
+    #[test]
+    fn drops_are_reported_at_most_once_per_interval() {
+        let mut limiter = AppLimiter::default();
+        let limit = RateLimit {
+            per_app_per_minute: 0,
+            burst: 1,
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert!(limiter.check(limit, "spam", start).pass);
+        assert_eq!(limiter.check(limit, "spam", at(1)), dropped(Some(1)));
+        for ms in 2..50 {
+            assert_eq!(limiter.check(limit, "spam", at(ms)), dropped(None));
+        }
+        assert_eq!(limiter.check(limit, "spam", at(10_001)), dropped(Some(49)));
+    }
+
+    #[test]
+    fn quiet_apps_are_pruned_with_their_unreported_drops() {
+        let mut limiter = AppLimiter::default();
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.check(LIMIT, "spam", start);
+        }
+        limiter.check(LIMIT, "mail", start);
+        assert_eq!(limiter.buckets.len(), 2);
+
+        assert!(
+            limiter.prune(LIMIT, start).is_empty(),
+            "nothing has refilled yet"
+        );
+        assert_eq!(limiter.buckets.len(), 2);
+        let later = start + PRUNE_INTERVAL;
+        assert_eq!(limiter.prune(LIMIT, later), [("spam".to_string(), 1)]);
+        assert_eq!(limiter.buckets.len(), 0);
+    }
+}
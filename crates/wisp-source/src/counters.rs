@@ -0,0 +1,202 @@
+//! Per-app notification counts for bar modules, kept in step with the event stream.
+
+use std::collections::{BTreeMap, HashMap};
+
+use wisp_types::NotificationEvent;
+
+/// Counts for one app, as returned by `GetAppCounters` (`(uuu)` in field order).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AppCounters {
+    /// Notifications received since the app was last marked seen.
+    pub unseen: u32,
+    /// Notifications currently in the store, i.e. not yet closed.
+    pub visible: u32,
+    /// Notifications closed since the source started.
+    pub history: u32,
+}
+
+impl AppCounters {
+    pub(crate) fn as_tuple(self) -> (u32, u32, u32) {
+        (self.unseen, self.visible, self.history)
+    }
+}
+
+/// [`AppCounters`] per `app_name`, updated from every [`NotificationEvent`].
+#[derive(Debug, Default)]
+pub(crate) struct CounterMap {
+    apps: BTreeMap<String, AppCounters>,
+    /// App of every live id, since `Closed` only carries the id.
+    owners: HashMap<u32, String>,
+}
+
+impl CounterMap {
+    /// Applies `event`; returns whether any count changed.
+    pub(crate) fn observe(&mut self, event: &NotificationEvent) -> bool {
+        match event {
+            NotificationEvent::Received {
+                id, notification, ..
+            } => {
+                let counters = self.apps.entry(notification.app_name.clone()).or_default();
+                counters.unseen = counters.unseen.saturating_add(1);
+                self.track(*id, &notification.app_name);
+                true
+            }
+            NotificationEvent::Replaced { id, current, .. } => {
+                if self.owners.get(id) == Some(&current.app_name) {
+                    return false;
+                }
+                self.release(*id);
+                self.track(*id, &current.app_name);
+                true
+            }
+            NotificationEvent::Closed { id, .. } => match self.release(*id) {
+                Some(counters) => {
+                    counters.history = counters.history.saturating_add(1);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Zeroes `app`'s unseen count; returns whether it was non-zero.
+    pub(crate) fn mark_seen(&mut self, app: &str) -> bool {
+        match self.apps.get_mut(app) {
+            Some(counters) if counters.unseen > 0 => {
+                counters.unseen = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> BTreeMap<String, AppCounters> {
+        self.apps.clone()
+    }
+
+    fn track(&mut self, id: u32, app: &str) {
+        let counters = self.apps.entry(app.to_string()).or_default();
+        counters.visible = counters.visible.saturating_add(1);
+        self.owners.insert(id, app.to_string());
+    }
+
+    /// Forgets `id` and returns its app's counters with `visible` already lowered.
+    fn release(&mut self, id: u32) -> Option<&mut AppCounters> {
+        let app = self.owners.remove(&id)?;
+        let counters = self.apps.entry(app).or_default();
+        counters.visible = counters.visible.saturating_sub(1);
+        Some(counters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wisp_types::{CloseReason, Notification};
+
+    use super::*;
+
+    fn from(app_name: &str) -> Box<Notification> {
+        Box::new(Notification {
+            app_name: app_name.to_string(),
+            ..Notification::default()
+        })
+    }
+
+    fn received(id: u32, app_name: &str) -> NotificationEvent {
+        NotificationEvent::Received {
+            id,
+            notification: from(app_name),
+            silent: false,
+            remaining_ms: None,
+        }
+    }
+
+    fn closed(id: u32) -> NotificationEvent {
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Dismissed,
+        }
+    }
+
+    fn counts(map: &CounterMap, app: &str) -> (u32, u32, u32) {
+        map.snapshot()
+            .get(app)
+            .copied()
+            .unwrap_or_default()
+            .as_tuple()
+    }
+
+    #[test]
+    fn scripted_session_moves_counts_through_each_event() {
+        let mut map = CounterMap::default();
+        let script = [
+            (received(1, "mail"), (1, 1, 0)),
+            (received(2, "mail"), (2, 2, 0)),
+            (
+                NotificationEvent::ActionInvoked {
+                    id: 1,
+                    action_key: "open".to_string(),
+                },
+                (2, 2, 0),
+            ),
+            (closed(1), (2, 1, 1)),
+            (
+                NotificationEvent::Replaced {
+                    id: 2,
+                    previous: from("mail"),
+                    current: from("mail"),
+                    remaining_ms: None,
+                    repeat_count: 0,
+                },
+                (2, 1, 1),
+            ),
+            (closed(2), (2, 0, 2)),
+            (closed(2), (2, 0, 2)),
+        ];
+        for (step, (event, expected)) in script.into_iter().enumerate() {
+            map.observe(&event);
+            assert_eq!(
+                counts(&map, "mail"),
+                expected,
+                "after step {step}: {event:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn mark_seen_resets_only_the_unseen_count_of_that_app() {
+        let mut map = CounterMap::default();
+        map.observe(&received(1, "mail"));
+        map.observe(&received(2, "mail"));
+        map.observe(&received(3, "chat"));
+        map.observe(&closed(1));
+
+        assert!(map.mark_seen("mail"));
+        assert_eq!(counts(&map, "mail"), (0, 1, 1));
+        assert_eq!(counts(&map, "chat"), (1, 1, 0));
+        assert!(!map.mark_seen("mail"), "nothing left to reset");
+        assert!(!map.mark_seen("unknown"));
+
+        map.observe(&received(4, "mail"));
+        assert_eq!(counts(&map, "mail"), (1, 2, 1));
+    }
+
+    #[test]
+    fn replacement_from_another_app_moves_the_visible_count() {
+        let mut map = CounterMap::default();
+        map.observe(&received(1, "mail"));
+        assert!(map.observe(&NotificationEvent::Replaced {
+            id: 1,
+            previous: from("mail"),
+            current: from("chat"),
+            remaining_ms: None,
+            repeat_count: 0,
+        }));
+        assert_eq!(counts(&map, "mail"), (1, 0, 0));
+        assert_eq!(counts(&map, "chat"), (0, 1, 0));
+
+        map.observe(&closed(1));
+        assert_eq!(counts(&map, "chat"), (0, 0, 1));
+    }
+}
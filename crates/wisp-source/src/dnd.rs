@@ -0,0 +1,91 @@
+//! Do-not-disturb state: the schedule's verdict plus a manual override that lasts until
+//! the schedule next starts or ends a window.
+
+/// Do-not-disturb state, as reported by `GetState`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DndStatus {
+    /// Whether DND is on: the manual override if there is one, else the schedule.
+    pub enabled: bool,
+    /// Whether a schedule window covers the current local time.
+    pub scheduled: bool,
+    /// Whether a manual toggle currently overrides the schedule.
+    pub overridden: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DndState {
+    scheduled: bool,
+    manual: Option<bool>,
+}
+
+impl DndState {
+    pub(crate) fn enabled(&self) -> bool {
+        self.manual.unwrap_or(self.scheduled)
+    }
+
+    pub(crate) fn status(&self) -> DndStatus {
+        DndStatus {
+            enabled: self.enabled(),
+            scheduled: self.scheduled,
+            overridden: self.manual.is_some(),
+        }
+    }
+
+    /// Records the schedule's current verdict. A change is a boundary and ends any
+    /// override. Returns whether DND switched.
+    pub(crate) fn observe_schedule(&mut self, scheduled: bool) -> bool {
+        if scheduled == self.scheduled {
+            return false;
+        }
+        let before = self.enabled();
+        self.scheduled = scheduled;
+        self.manual = None;
+        before != self.enabled()
+    }
+
+    /// The manual switch; agreeing with the schedule drops the override. Returns whether
+    /// DND switched.
+    pub(crate) fn set_manual(&mut self, enabled: bool) -> bool {
+        let before = self.enabled();
+        self.manual = (enabled != self.scheduled).then_some(enabled);
+        before != enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_toggle_overrides_the_schedule_until_its_next_boundary() {
+        let mut dnd = DndState::default();
+        assert!(dnd.observe_schedule(true), "window opens");
+
+        assert!(dnd.set_manual(false));
+        assert_eq!(
+            dnd.status(),
+            DndStatus {
+                enabled: false,
+                scheduled: true,
+                overridden: true,
+            }
+        );
+        assert!(!dnd.observe_schedule(true), "still inside the window");
+        assert!(!dnd.enabled());
+
+        assert!(!dnd.observe_schedule(false), "window closes; already off");
+        assert!(!dnd.status().overridden);
+        assert!(dnd.observe_schedule(true), "the next window applies again");
+    }
+
+    #[test]
+    fn agreeing_with_the_schedule_drops_the_override() {
+        let mut dnd = DndState::default();
+        assert!(dnd.set_manual(true));
+        assert!(dnd.status().overridden);
+        assert!(!dnd.set_manual(true));
+
+        assert!(dnd.set_manual(false));
+        assert_eq!(dnd.status(), DndStatus::default());
+    }
+}
@@ -0,0 +1,174 @@
+//! Expiry deadlines of stored notifications. One queue holds every armed deadline and a
+//! single driver task sleeps until the soonest, so a flood of notifications costs queue
+//! entries rather than sleeping tasks.
+
+use std::collections::{BTreeSet, HashMap};
+
+use tokio::time::Instant;
+
+/// The deadline armed for one notification generation.
+#[derive(Debug, Clone, Copy)]
+struct Armed {
+    generation: u64,
+    deadline: Instant,
+    /// Held back until its gate opens, e.g. until the `Notify` reply is sent.
+    gated: bool,
+}
+
+/// Armed deadlines by id, plus the ungated ones in deadline order.
+#[derive(Debug, Default)]
+pub(crate) struct ExpiryQueue {
+    armed: HashMap<u32, Armed>,
+    /// `(deadline, id)` of every ungated entry, soonest first.
+    order: BTreeSet<(Instant, u32)>,
+}
+
+impl ExpiryQueue {
+    /// Arms `generation` of `id` to expire at `deadline`, replacing whatever was armed for
+    /// `id`. A gated entry waits for [`Self::open_gate`] even past its deadline. Returns
+    /// whether the soonest deadline moved earlier, i.e. the driver must wake.
+    pub(crate) fn insert(
+        &mut self,
+        id: u32,
+        generation: u64,
+        deadline: Instant,
+        gated: bool,
+    ) -> bool {
+        self.remove(id);
+        self.armed.insert(
+            id,
+            Armed {
+                generation,
+                deadline,
+                gated,
+            },
+        );
+        !gated && self.enqueue(deadline, id)
+    }
+
+    /// Lets `generation` of `id` expire once its deadline has passed. Returns whether the
+    /// driver must wake.
+    pub(crate) fn open_gate(&mut self, id: u32, generation: u64) -> bool {
+        let Some(armed) = self
+            .armed
+            .get_mut(&id)
+            .filter(|armed| armed.generation == generation && armed.gated)
+        else {
+            return false;
+        };
+        armed.gated = false;
+        let deadline = armed.deadline;
+        self.enqueue(deadline, id)
+    }
+
+    /// Disarms `id`.
+    pub(crate) fn remove(&mut self, id: u32) {
+        if let Some(armed) = self.armed.remove(&id)
+            && !armed.gated
+        {
+            self.order.remove(&(armed.deadline, id));
+        }
+    }
+
+    /// The deadline armed for `generation` of `id`, if that generation is armed.
+    pub(crate) fn deadline(&self, id: u32, generation: u64) -> Option<Instant> {
+        self.armed
+            .get(&id)
+            .filter(|armed| armed.generation == generation)
+            .map(|armed| armed.deadline)
+    }
+
+    /// The soonest deadline that can fire.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.order.first().map(|(deadline, _)| *deadline)
+    }
+
+    /// Disarms and returns the `(id, generation)` of every ungated entry due by `now`,
+    /// soonest first.
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Vec<(u32, u64)> {
+        let mut due = Vec::new();
+        while let Some(&(deadline, id)) = self.order.first()
+            && deadline <= now
+        {
+            self.order.pop_first();
+            if let Some(armed) = self.armed.remove(&id) {
+                due.push((id, armed.generation));
+            }
+        }
+        due
+    }
+
+    /// Entries armed, gated or not.
+    pub(crate) fn len(&self) -> usize {
+        self.armed.len()
+    }
+
+    /// Queues `deadline`; returns whether it is now the soonest.
+    fn enqueue(&mut self, deadline: Instant, id: u32) -> bool {
+        let sooner = self.next_deadline().is_none_or(|next| deadline < next);
+        self.order.insert((deadline, id));
+        sooner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn entries_pop_in_deadline_order_once_due() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut queue = ExpiryQueue::default();
+
+        assert!(queue.insert(1, 0, at(30), false), "first deadline");
+        assert!(queue.insert(2, 0, at(10), false), "sooner");
+        assert!(!queue.insert(3, 0, at(20), false));
+        assert_eq!(queue.next_deadline(), Some(at(10)));
+
+        assert!(queue.pop_due(at(9)).is_empty());
+        assert_eq!(queue.pop_due(at(20)), [(2, 0), (3, 0)]);
+        assert_eq!(queue.next_deadline(), Some(at(30)));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn rearming_replaces_and_removal_disarms() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut queue = ExpiryQueue::default();
+
+        queue.insert(1, 0, at(10), false);
+        queue.insert(1, 1, at(50), false);
+        assert_eq!(queue.deadline(1, 0), None, "old generation gone");
+        assert_eq!(queue.deadline(1, 1), Some(at(50)));
+        assert!(queue.pop_due(at(10)).is_empty());
+
+        queue.remove(1);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.next_deadline(), None);
+        assert!(queue.pop_due(at(100)).is_empty());
+    }
+
+    #[test]
+    fn gated_entries_wait_for_their_gate() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut queue = ExpiryQueue::default();
+
+        assert!(!queue.insert(1, 0, at(10), true));
+        assert_eq!(queue.next_deadline(), None);
+        assert!(
+            queue.pop_due(at(20)).is_empty(),
+            "past its deadline, still gated"
+        );
+        assert_eq!(queue.deadline(1, 0), Some(at(10)));
+
+        assert!(!queue.open_gate(1, 1), "other generation");
+        assert!(queue.open_gate(1, 0));
+        assert_eq!(queue.pop_due(at(20)), [(1, 0)]);
+        assert!(!queue.open_gate(1, 0), "already gone");
+    }
+}
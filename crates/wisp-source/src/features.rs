@@ -0,0 +1,194 @@
+//! Server features advertised through `GetCapabilities`.
+
+/// Feature set behind the capability list, with the spec's capabilities as flags.
+///
+/// Capabilities wisp does not know about are kept in `extra` and advertised verbatim.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    pub actions: bool,
+    pub action_icons: bool,
+    pub body: bool,
+    pub body_hyperlinks: bool,
+    pub body_images: bool,
+    pub body_markup: bool,
+    pub icon_static: bool,
+    pub persistence: bool,
+    pub sound: bool,
+    /// KDE-style quick replies (`inline-reply`).
+    pub inline_reply: bool,
+    pub extra: Vec<String>,
+}
+
+impl Features {
+    /// Parses a capability list such as `["body", "actions"]`; duplicates collapse.
+    pub fn from_capabilities<I, S>(capabilities: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut features = Self::default();
+        for capability in capabilities {
+            let capability = capability.as_ref().trim();
+            match features.flag_mut(capability) {
+                Some(flag) => *flag = true,
+                None if capability.is_empty() => {}
+                None => {
+                    if !features.extra.iter().any(|e| e == capability) {
+                        features.extra.push(capability.to_string());
+                    }
+                }
+            }
+        }
+        features
+    }
+
+    /// Capability strings in a stable order: known flags first, then `extra`.
+    pub fn capabilities(&self) -> Vec<String> {
+        let known = [
+            ("body", self.body),
+            ("body-markup", self.body_markup),
+            ("body-hyperlinks", self.body_hyperlinks),
+            ("body-images", self.body_images),
+            ("actions", self.actions),
+            ("action-icons", self.action_icons),
+            ("icon-static", self.icon_static),
+            ("persistence", self.persistence),
+            ("sound", self.sound),
+            ("inline-reply", self.inline_reply),
+        ];
+        known
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .chain(self.extra.iter().cloned())
+            .collect()
+    }
+
+    /// Drops what a popup UI cannot draw when `show_icons` is off.
+    pub fn honoured_by_ui(mut self, show_icons: bool) -> Self {
+        self.icon_static &= show_icons;
+        self.action_icons &= show_icons;
+        self
+    }
+
+    /// Compares these expected features with what a daemon `advertised`.
+    pub fn diff(&self, advertised: &Self) -> CapabilityDiff {
+        let (expected, advertised) = (self.capabilities(), advertised.capabilities());
+        let mut diff = CapabilityDiff::default();
+        for capability in &expected {
+            if advertised.contains(capability) {
+                diff.matching.push(capability.clone());
+            } else {
+                diff.not_advertised.push(capability.clone());
+            }
+        }
+        diff.unexpected = advertised
+            .into_iter()
+            .filter(|capability| !expected.contains(capability))
+            .collect();
+        diff
+    }
+
+    fn flag_mut(&mut self, capability: &str) -> Option<&mut bool> {
+        Some(match capability {
+            "actions" => &mut self.actions,
+            "action-icons" => &mut self.action_icons,
+            "body" => &mut self.body,
+            "body-hyperlinks" => &mut self.body_hyperlinks,
+            "body-images" => &mut self.body_images,
+            "body-markup" => &mut self.body_markup,
+            "icon-static" => &mut self.icon_static,
+            "persistence" => &mut self.persistence,
+            "sound" => &mut self.sound,
+            "inline-reply" => &mut self.inline_reply,
+            _ => return None,
+        })
+    }
+}
+
+/// Result of [`Features::diff`], each list in [`Features::capabilities`] order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapabilityDiff {
+    /// Expected and advertised.
+    pub matching: Vec<String>,
+    /// Expected but missing from the advertised list.
+    pub not_advertised: Vec<String>,
+    /// Advertised but not expected.
+    pub unexpected: Vec<String>,
+}
+
+impl CapabilityDiff {
+    /// Whether both sides list the same capabilities.
+    pub fn is_empty(&self) -> bool {
+        self.not_advertised.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_round_trip_with_extras_kept_verbatim() {
+        let features =
+            Features::from_capabilities(["actions", "body", "x-wisp-pin", "body", " ", "sound"]);
+        assert!(features.actions && features.body && features.sound);
+        assert!(!features.body_markup);
+        assert_eq!(features.extra, vec!["x-wisp-pin".to_string()]);
+        assert_eq!(
+            features.capabilities(),
+            vec!["body", "actions", "sound", "x-wisp-pin"]
+        );
+        assert_eq!(
+            Features::from_capabilities(features.capabilities()),
+            features
+        );
+    }
+
+    fn diff(expected: &[&str], advertised: &[&str]) -> CapabilityDiff {
+        Features::from_capabilities(expected).diff(&Features::from_capabilities(advertised))
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn diff_splits_capabilities_by_side_in_a_stable_order() {
+        let same = diff(&["actions", "body"], &["body", "actions"]);
+        assert!(same.is_empty());
+        assert_eq!(same.matching, strings(&["body", "actions"]));
+
+        let mixed = diff(
+            &["body", "actions", "icon-static", "x-wisp-pin"],
+            &["body", "body-markup", "actions", "x-kde-urls"],
+        );
+        assert_eq!(mixed.matching, strings(&["body", "actions"]));
+        assert_eq!(
+            mixed.not_advertised,
+            strings(&["icon-static", "x-wisp-pin"])
+        );
+        assert_eq!(mixed.unexpected, strings(&["body-markup", "x-kde-urls"]));
+
+        let nothing_live = diff(&["body"], &[]);
+        assert_eq!(nothing_live.not_advertised, strings(&["body"]));
+        assert!(nothing_live.matching.is_empty() && nothing_live.unexpected.is_empty());
+    }
+
+    #[test]
+    fn icons_only_count_when_the_ui_shows_them() {
+        let configured = Features::from_capabilities(["body", "icon-static", "action-icons"]);
+        let with_icons = configured.clone().honoured_by_ui(true);
+        assert!(with_icons.icon_static && with_icons.action_icons);
+
+        let without_icons = configured.honoured_by_ui(false);
+        assert_eq!(without_icons.capabilities(), strings(&["body"]));
+        // A daemon still advertising icons is then ahead of what the UI draws.
+        assert_eq!(
+            without_icons
+                .diff(&Features::from_capabilities(["body", "icon-static"]))
+                .unexpected,
+            strings(&["icon-static"])
+        );
+    }
+}
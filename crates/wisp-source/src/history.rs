@@ -0,0 +1,490 @@
+//! Recently closed notifications, kept so the UI can show one again on request. A
+//! notification replaced many times, such as a download's progress, leaves one entry:
+//! its final content with the [`ReplaceChain`] that led there.
+//!
+//! Entries older than the history TTL are pruned when a new one is recorded and on a
+//! coarse sweep; starred entries are exempt. With a [`HistoryStore`] the entries are
+//! also kept across restarts: each new entry is appended to the store, and the store is
+//! rewritten with only the live entries when it is loaded, when it asks to be
+//! compacted after an append or a prune, when a star changes or when entries are
+//! deleted. Where and how the store keeps them is up to the consumer.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use wisp_types::{CloseReason, Notification, ReplaceChain};
+
+/// Keeps the history across restarts; see [`SourceConfig::history_store`].
+///
+/// The source calls it with the history locked and handles no errors: a store reports
+/// its own failures and carries on.
+///
+/// [`SourceConfig::history_store`]: crate::SourceConfig::history_store
+pub trait HistoryStore: fmt::Debug + Send + Sync {
+    /// The stored entries, newest first. Entries that cannot be read are left out.
+    fn load(&self) -> Vec<HistoryEntry>;
+    /// Stores `entry` as the newest.
+    fn append(&self, entry: &HistoryEntry);
+    /// Replaces everything stored with `entries`, newest first.
+    fn rewrite(&self, entries: &[HistoryEntry]);
+    /// Whether entries dropped since the last rewrite take up enough room that the
+    /// store should be rewritten.
+    fn wants_compaction(&self) -> bool;
+}
+
+/// A notification after it left the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Id the notification had while it was stored.
+    pub id: u32,
+    /// Last content, after any replacements.
+    pub notification: Notification,
+    /// Unique bus name of the connection that sent it; `None` for in-process and
+    /// peer-to-peer notifications.
+    pub sender: Option<String>,
+    /// Why it closed.
+    pub reason: CloseReason,
+    /// When the first notification of its replace chain was received.
+    pub received_at: SystemTime,
+    /// Replacements that led to `notification`.
+    pub chain: ReplaceChain,
+    /// Starred entries are never pruned by the history TTL.
+    #[serde(default)]
+    pub starred: bool,
+}
+
+impl HistoryEntry {
+    /// Whether the TTL has run out for this entry at `now`.
+    fn is_stale(&self, ttl: Option<Duration>, now: SystemTime) -> bool {
+        let Some(ttl) = ttl else {
+            return false;
+        };
+        !self.starred
+            && now
+                .duration_since(self.received_at)
+                .is_ok_and(|age| age >= ttl)
+    }
+}
+
+/// Counts a replacement of a notification that had `replaced_summary` into `chain`,
+/// keeping at most `keep` intermediate summaries.
+pub(crate) fn extend_chain(chain: &mut ReplaceChain, replaced_summary: &str, keep: usize) {
+    chain.replace_count = chain.replace_count.saturating_add(1);
+    if keep == 0
+        || chain
+            .intermediate_summaries
+            .last()
+            .is_some_and(|last| last == replaced_summary)
+    {
+        return;
+    }
+    chain
+        .intermediate_summaries
+        .push(replaced_summary.to_string());
+    let excess = chain.intermediate_summaries.len().saturating_sub(keep);
+    chain.intermediate_summaries.drain(..excess);
+}
+
+#[derive(Debug)]
+pub(crate) struct History {
+    capacity: usize,
+    /// Entries are pruned once they are this old, unless starred.
+    ttl: Option<Duration>,
+    /// Mirrors the entries across restarts; `None` keeps them in memory only.
+    store: Option<Arc<dyn HistoryStore>>,
+    /// Newest first.
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            store: None,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// A history backed by `store`: its entries are loaded, pruned as of `now` and the
+    /// store rewritten with what is left.
+    pub(crate) fn with_store(
+        capacity: usize,
+        ttl: Option<Duration>,
+        store: Arc<dyn HistoryStore>,
+        now: SystemTime,
+    ) -> Self {
+        let mut history = Self::new(capacity, ttl);
+        if capacity > 0 {
+            history.entries = store.load().into();
+        }
+        history.store = Some(store);
+        history.prune(now);
+        history.entries.truncate(capacity);
+        history.compact();
+        history
+    }
+
+    /// Adds `entry` as the newest, dropping the oldest past the capacity and any past
+    /// the TTL at `now`.
+    pub(crate) fn record(&mut self, entry: HistoryEntry, now: SystemTime) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.prune(now);
+        if let Some(store) = &self.store {
+            store.append(&entry);
+        }
+        self.entries.push_front(entry);
+        self.entries.truncate(self.capacity);
+        self.compact_if_large();
+    }
+
+    /// Drops unstarred entries older than the TTL at `now`; returns how many went.
+    pub(crate) fn prune(&mut self, now: SystemTime) -> usize {
+        let before = self.entries.len();
+        let ttl = self.ttl;
+        self.entries.retain(|entry| !entry.is_stale(ttl, now));
+        let pruned = before - self.entries.len();
+        if pruned > 0 {
+            debug!(pruned, "history entries past their TTL pruned");
+            self.compact_if_large();
+        }
+        pruned
+    }
+
+    pub(crate) fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Changes the TTL and prunes with it right away.
+    pub(crate) fn set_ttl(&mut self, ttl: Option<Duration>, now: SystemTime) {
+        self.ttl = ttl;
+        self.prune(now);
+    }
+
+    /// Stars or unstars the entry [`History::find`] picks for `index_or_id`; returns its
+    /// id, or `None` when there is no such entry.
+    pub(crate) fn set_starred(&mut self, index_or_id: u32, starred: bool) -> Option<u32> {
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| entry.id == index_or_id)
+            .or_else(|| {
+                usize::try_from(index_or_id)
+                    .ok()
+                    .filter(|&index| index < self.entries.len())
+            })?;
+        let entry = &mut self.entries[position];
+        let id = entry.id;
+        if entry.starred != starred {
+            entry.starred = starred;
+            self.compact();
+        }
+        Some(id)
+    }
+
+    /// Deletes the entries with these ids, starred or not; returns how many went.
+    pub(crate) fn remove(&mut self, ids: &[u32]) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| !ids.contains(&entry.id));
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            debug!(removed, "history entries deleted");
+            self.compact();
+        }
+        removed
+    }
+
+    /// Deletes every entry; returns how many there were.
+    pub(crate) fn clear(&mut self) -> usize {
+        let ids: Vec<u32> = self.entries.iter().map(|entry| entry.id).collect();
+        self.remove(&ids)
+    }
+
+    /// The entry for notification id `index_or_id`, or else the one at that position
+    /// counting from 0 = newest.
+    pub(crate) fn find(&self, index_or_id: u32) -> Option<&HistoryEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == index_or_id)
+            .or_else(|| self.entries.get(usize::try_from(index_or_id).ok()?))
+    }
+
+    pub(crate) fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    fn compact_if_large(&self) {
+        if self
+            .store
+            .as_ref()
+            .is_some_and(|store| store.wants_compaction())
+        {
+            self.compact();
+        }
+    }
+
+    /// Rewrites the store with exactly the live entries.
+    fn compact(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let entries: Vec<HistoryEntry> = self.entries.iter().cloned().collect();
+        store.rewrite(&entries);
+        debug!(entries = entries.len(), "history store compacted");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    fn entry(id: u32) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            notification: Notification {
+                summary: format!("n{id}"),
+                ..Notification::default()
+            },
+            sender: None,
+            reason: CloseReason::Expired,
+            received_at: SystemTime::UNIX_EPOCH,
+            chain: ReplaceChain::default(),
+            starred: false,
+        }
+    }
+
+    fn received(id: u32, hours_after_epoch: u64) -> HistoryEntry {
+        HistoryEntry {
+            received_at: at(hours_after_epoch),
+            ..entry(id)
+        }
+    }
+
+    fn at(hours_after_epoch: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(hours_after_epoch * 3_600)
+    }
+
+    fn ids(history: &History) -> Vec<u32> {
+        history.entries().iter().map(|e| e.id).collect()
+    }
+
+    /// Newest first, as [`History`] hands them over; compaction is asked for once
+    /// `compact_after` appends piled up since the last rewrite.
+    #[derive(Debug, Default)]
+    struct MemoryStore {
+        entries: Mutex<Vec<HistoryEntry>>,
+        appends: AtomicUsize,
+        rewrites: AtomicUsize,
+        compact_after: usize,
+    }
+
+    impl MemoryStore {
+        fn with(entries: Vec<HistoryEntry>, compact_after: usize) -> Arc<Self> {
+            Arc::new(Self {
+                entries: Mutex::new(entries),
+                compact_after,
+                ..Self::default()
+            })
+        }
+
+        fn ids(&self) -> Vec<u32> {
+            self.entries.lock().unwrap().iter().map(|e| e.id).collect()
+        }
+    }
+
+    impl HistoryStore for MemoryStore {
+        fn load(&self) -> Vec<HistoryEntry> {
+            self.entries.lock().unwrap().clone()
+        }
+
+        fn append(&self, entry: &HistoryEntry) {
+            self.entries.lock().unwrap().insert(0, entry.clone());
+            self.appends.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn rewrite(&self, entries: &[HistoryEntry]) {
+            *self.entries.lock().unwrap() = entries.to_vec();
+            self.appends.store(0, Ordering::Relaxed);
+            self.rewrites.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn wants_compaction(&self) -> bool {
+            self.compact_after > 0 && self.appends.load(Ordering::Relaxed) >= self.compact_after
+        }
+    }
+
+    #[test]
+    fn ids_win_over_positions_and_the_oldest_entry_goes_first() {
+        let mut history = History::new(3, None);
+        for id in [1, 2, 7, 9] {
+            history.record(entry(id), SystemTime::UNIX_EPOCH);
+        }
+        let ids: Vec<u32> = history.entries().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [9, 7, 2]);
+
+        assert_eq!(history.find(7).map(|e| e.id), Some(7));
+        assert_eq!(history.find(2).map(|e| e.id), Some(2));
+        assert_eq!(history.find(0).map(|e| e.id), Some(9), "0 is the newest");
+        assert_eq!(history.find(1).map(|e| e.id), Some(7));
+        assert!(history.find(5).is_none());
+
+        let mut disabled = History::new(0, None);
+        disabled.record(entry(1), SystemTime::UNIX_EPOCH);
+        assert!(disabled.find(1).is_none());
+    }
+
+    #[test]
+    fn entries_past_the_ttl_are_pruned_unless_starred() {
+        let mut history = History::new(10, Some(Duration::from_secs(48 * 3_600)));
+        history.record(received(1, 0), at(0));
+        history.record(received(2, 10), at(10));
+        history.record(received(3, 20), at(20));
+        assert_eq!(history.set_starred(1, true), Some(1));
+        assert_eq!(
+            history.set_starred(1, true),
+            Some(1),
+            "starring twice is fine"
+        );
+        assert_eq!(history.set_starred(42, true), None);
+
+        history.record(received(4, 60), at(60));
+        assert_eq!(
+            ids(&history),
+            [4, 3, 1],
+            "2 expired on insertion, 1 is starred"
+        );
+
+        assert_eq!(history.prune(at(67)), 0, "3 is exactly 47h old");
+        assert_eq!(history.prune(at(68)), 1);
+        assert_eq!(ids(&history), [4, 1]);
+
+        assert_eq!(history.set_starred(1, false), Some(1));
+        assert_eq!(history.prune(at(68)), 1);
+        assert_eq!(ids(&history), [4]);
+
+        history.set_ttl(Some(Duration::from_secs(3_600)), at(68));
+        assert!(history.entries().is_empty());
+        history.set_ttl(None, at(1_000));
+        history.record(received(5, 0), at(1_000));
+        assert_eq!(ids(&history), [5], "no TTL keeps everything");
+    }
+
+    #[test]
+    fn the_store_is_reloaded_pruned_and_compacted() {
+        let ttl = Some(Duration::from_secs(24 * 3_600));
+        let mut starred = received(1, 1);
+        starred.starred = true;
+        let stored = vec![received(4, 4), received(3, 3), received(2, 2), starred];
+        let store = MemoryStore::with(stored, 0);
+
+        let mut history = History::with_store(3, ttl, store.clone(), at(26));
+        assert_eq!(ids(&history), [4, 3, 1], "2 expired, 1 is starred");
+        assert_eq!(store.ids(), [4, 3, 1], "rewritten with only live entries");
+        assert_eq!(store.rewrites.load(Ordering::Relaxed), 1);
+
+        history.record(received(5, 26), at(26));
+        assert_eq!(store.ids(), [5, 4, 3, 1], "appended without a rewrite");
+        assert_eq!(store.rewrites.load(Ordering::Relaxed), 1);
+        history.set_starred(5, true);
+        assert_eq!(store.ids(), [5, 4, 3], "a star change rewrites it");
+        assert!(store.load()[0].starred);
+
+        let store = MemoryStore::with(Vec::new(), 3);
+        let mut small = History::with_store(2, None, store.clone(), at(0));
+        for id in 10..13 {
+            small.record(entry(id), at(0));
+        }
+        assert_eq!(
+            store.ids(),
+            [12, 11],
+            "compacted once the store asked, keeping only live entries"
+        );
+        small.record(entry(13), at(0));
+        assert_eq!(store.ids(), [13, 12, 11]);
+        assert_eq!(ids(&small), [13, 12]);
+
+        let empty = History::with_store(0, ttl, MemoryStore::with(vec![entry(1)], 0), at(0));
+        assert!(empty.entries().is_empty(), "no capacity loads nothing");
+    }
+
+    #[test]
+    fn deleted_entries_are_gone_from_the_store_too() {
+        let store = MemoryStore::with(Vec::new(), 0);
+        let mut history = History::with_store(10, None, store.clone(), at(0));
+        for id in 1..=5 {
+            history.record(entry(id), at(0));
+        }
+        history.set_starred(2, true);
+
+        assert_eq!(history.remove(&[2, 4, 42]), 2, "starred entries go too");
+        assert_eq!(history.remove(&[42]), 0);
+        assert_eq!(ids(&history), [5, 3, 1]);
+        assert_eq!(store.ids(), [5, 3, 1]);
+
+        assert_eq!(history.clear(), 3);
+        assert!(history.entries().is_empty());
+        assert!(store.ids().is_empty());
+    }
+
+    #[test]
+    fn replacements_accumulate_a_capped_chain() {
+        let mut chain = ReplaceChain::default();
+        for summary in ["Downloading 10%", "Downloading 40%", "Downloading 40%"] {
+            extend_chain(&mut chain, summary, 2);
+        }
+        assert_eq!(chain.replace_count, 3);
+        assert_eq!(
+            chain.intermediate_summaries,
+            ["Downloading 10%", "Downloading 40%"],
+            "a repeated summary is kept once"
+        );
+
+        extend_chain(&mut chain, "Downloading 90%", 2);
+        assert_eq!(chain.replace_count, 4);
+        assert_eq!(
+            chain.intermediate_summaries,
+            ["Downloading 40%", "Downloading 90%"],
+            "only the latest are kept"
+        );
+
+        let mut counted_only = ReplaceChain::default();
+        extend_chain(&mut counted_only, "step 1", 0);
+        assert_eq!(counted_only.replace_count, 1);
+        assert!(counted_only.intermediate_summaries.is_empty());
+    }
+
+    #[test]
+    fn chains_describe_their_updates_and_lifetime() {
+        let chain = |replace_count, lifetime_ms| ReplaceChain {
+            replace_count,
+            lifetime_ms,
+            ..ReplaceChain::default()
+        };
+        assert_eq!(chain(0, 5_000).describe(), None);
+        assert_eq!(
+            chain(1, 42_000).describe().as_deref(),
+            Some("1 update, 42s")
+        );
+        assert_eq!(
+            chain(200, 185_000).describe().as_deref(),
+            Some("200 updates, 3m")
+        );
+        assert_eq!(
+            chain(9, 2 * 3_600_000 + 5 * 60_000).describe().as_deref(),
+            Some("9 updates, 2h 5m")
+        );
+    }
+}
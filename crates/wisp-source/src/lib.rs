@@ -1,21 +1,64 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    path::PathBuf,
     sync::{
-        Arc, Mutex, RwLock,
-        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, RwLock, Weak,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
+use futures_util::Stream;
 use thiserror::Error;
 use tokio::runtime::Handle;
-use tokio::sync::mpsc::error::TrySendError;
-use tokio::sync::{RwLock as AsyncRwLock, mpsc};
+use tokio::sync::{
+    RwLock as AsyncRwLock,
+    broadcast::{self, error::RecvError},
+    mpsc, watch,
+};
 use tracing::{debug, info, warn};
 use wisp_types::{
-    CloseReason, Notification, NotificationAction, NotificationEvent, NotificationHints, Urgency,
+    CloseReason, ImageData, Notification, NotificationAction, NotificationEvent, NotificationHints,
+    ReplaceChain, SoundHint, Urgency,
+    dnd::{DndWindow, is_dnd_scheduled},
+    hint::HintValue,
+    loggable::{self, Loggable, LoggableEvent},
+    matcher::Matcher,
+};
+use zbus::{
+    connection::Builder as ConnectionBuilder,
+    names::BusName,
+    object_server::{ResponseDispatchNotifier, SignalEmitter},
+    zvariant,
 };
-use zbus::{connection::Builder as ConnectionBuilder, object_server::SignalEmitter, zvariant};
+
+mod applimit;
+mod counters;
+mod dnd;
+mod expiry;
+mod features;
+mod history;
+mod overflow;
+pub mod prelude;
+mod ratelimit;
+mod sandbox;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+use applimit::AppLimiter;
+pub use applimit::RateLimit;
+pub use counters::AppCounters;
+use counters::CounterMap;
+use dnd::DndState;
+pub use dnd::DndStatus;
+use expiry::ExpiryQueue;
+pub use features::{CapabilityDiff, Features};
+use history::History;
+pub use history::{HistoryEntry, HistoryStore};
+use overflow::Backlog;
+pub use overflow::OverflowPolicy;
+use ratelimit::WarnLimiter;
 
 /// Default freedesktop notification bus name.
 pub const DEFAULT_DBUS_NAME: &str = "org.freedesktop.Notifications";
@@ -23,14 +66,38 @@ pub const DEFAULT_DBUS_NAME: &str = "org.freedesktop.Notifications";
 pub const DEFAULT_DBUS_PATH: &str = "/org/freedesktop/Notifications";
 /// Freedesktop notifications D-Bus interface name.
 pub const DBUS_INTERFACE: &str = "org.freedesktop.Notifications";
+/// Object path of the wispd extension interface, served next to the freedesktop one.
+pub const CONTROL_PATH: &str = "/org/wispd/Control";
+/// wispd extension interface name.
+pub const CONTROL_INTERFACE: &str = "org.wispd.Control1";
+/// Quiet period that coalesces counter changes into one `CountersChanged` signal.
+pub(crate) const COUNTERS_DEBOUNCE: Duration = Duration::from_millis(250);
+/// How often the do-not-disturb schedule is re-checked against the local clock.
+pub(crate) const DND_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How often history entries are checked against [`SourceConfig::history_ttl`].
+pub(crate) const HISTORY_SWEEP_INTERVAL: Duration = Duration::from_secs(3_600);
+/// Default for [`SourceConfig::max_hint_value_len`].
+pub const DEFAULT_MAX_HINT_VALUE_LEN: usize = 1024;
+/// Default for [`SourceConfig::max_total_hints_bytes`].
+pub const DEFAULT_MAX_TOTAL_HINTS_BYTES: usize = 16 * 1024;
+/// Default for [`SourceConfig::history_len`].
+pub const DEFAULT_HISTORY_LEN: usize = 50;
+/// Default for [`SourceConfig::history_intermediate_summaries`].
+pub const DEFAULT_HISTORY_INTERMEDIATE_SUMMARIES: usize = 5;
 
 /// Configuration for [`WispSource`].
+///
+/// More fields may be added; outside this crate, build it with
+/// [`SourceConfig::builder`] or set fields on [`SourceConfig::default`].
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct SourceConfig {
     /// Capabilities returned by `GetCapabilities`.
     pub capabilities: Vec<String>,
     /// Capacity of the internal notification event channel.
     pub channel_capacity: usize,
+    /// What happens to events that do not fit the channel.
+    pub overflow_policy: OverflowPolicy,
     /// D-Bus name to own.
     pub dbus_name: String,
     /// D-Bus object path to serve.
@@ -47,6 +114,55 @@ pub struct SourceConfig {
     ///
     /// If `None`, negative incoming timeout values are treated as persistent.
     pub default_timeout_ms: Option<i32>,
+    /// Longest string, byte array or text kept for an unparsed hint in
+    /// [`NotificationHints::extra`]; longer values become [`HintValue::Omitted`].
+    pub max_hint_value_len: usize,
+    /// Budget for all unparsed hints of one notification (keys plus values); hints past
+    /// the budget become [`HintValue::Omitted`].
+    pub max_total_hints_bytes: usize,
+    /// Converts unparsed hints into [`NotificationHints::extra`]; off leaves it empty and
+    /// saves the work for consumers that never read it.
+    pub keep_extra_hints: bool,
+    /// procfs mount used to identify sandboxed senders; overridable for tests.
+    pub proc_root: PathBuf,
+    /// Bounds applied to the effective expiry timeout, whatever the sender asked for.
+    pub clamp_timeout: TimeoutClamp,
+    /// Overrides of [`Self::clamp_timeout`] for the notifications they match; the first
+    /// matching rule applies.
+    pub timeout_rules: Vec<TimeoutRule>,
+    /// Critical notifications stay until closed or acted on, whatever timeout was asked
+    /// for, defaulted or clamped to.
+    pub critical_never_expires: bool,
+    /// Local-time windows during which do-not-disturb turns on by itself.
+    pub dnd_schedule: Vec<DndWindow>,
+    /// Critical notifications still pop up under do-not-disturb; off silences them too.
+    pub critical_bypasses_dnd: bool,
+    /// New notifications beyond this rate are dropped per `app_name`; their senders still
+    /// get ids, and consumers see [`NotificationEvent::RateLimited`]. `None` lets all in.
+    pub rate_limit: Option<RateLimit>,
+    /// Folds a new notification identical to a live one into it, as a replacement that
+    /// counts the repeats; `None` stores every copy.
+    pub dedup: Option<Dedup>,
+    /// Closes a notification as dismissed once [`WispSource::reply`] answered it; off
+    /// leaves it stored, for the sender to update with the conversation.
+    pub close_on_reply: bool,
+    /// Advertises `action-icons` whenever `actions` is advertised, so senders may mark
+    /// their action keys as icon names.
+    pub action_icons: bool,
+    /// Closed notifications kept for `ShowHistoric`; 0 keeps none.
+    pub history_len: usize,
+    /// Summaries a replaced notification's history entry keeps from before its last
+    /// content; 0 only counts the replacements.
+    pub history_intermediate_summaries: usize,
+    /// Age past which unstarred history entries are pruned; `None` keeps them until the
+    /// history is full.
+    pub history_ttl: Option<Duration>,
+    /// Keeps the history across restarts; `None` keeps it in memory only.
+    pub history_store: Option<Arc<dyn HistoryStore>>,
+    /// Log summaries, bodies and action labels. [`WispSource::new`] applies it process-wide
+    /// with [`wisp_types::loggable::set_log_content`]; when off they show as a length and
+    /// hash.
+    pub log_content: bool,
 }
 
 impl Default for SourceConfig {
@@ -54,6 +170,7 @@ impl Default for SourceConfig {
         Self {
             capabilities: vec!["body".to_string()],
             channel_capacity: 256,
+            overflow_policy: OverflowPolicy::default(),
             dbus_name: DEFAULT_DBUS_NAME.to_string(),
             dbus_path: DEFAULT_DBUS_PATH.to_string(),
             server_name: "wispd".to_string(),
@@ -61,10 +178,216 @@ impl Default for SourceConfig {
             server_version: env!("CARGO_PKG_VERSION").to_string(),
             spec_version: "1.2".to_string(),
             default_timeout_ms: None,
+            max_hint_value_len: DEFAULT_MAX_HINT_VALUE_LEN,
+            max_total_hints_bytes: DEFAULT_MAX_TOTAL_HINTS_BYTES,
+            keep_extra_hints: true,
+            proc_root: PathBuf::from("/proc"),
+            clamp_timeout: TimeoutClamp::default(),
+            timeout_rules: Vec::new(),
+            critical_never_expires: false,
+            dnd_schedule: Vec::new(),
+            critical_bypasses_dnd: true,
+            rate_limit: None,
+            dedup: None,
+            close_on_reply: true,
+            action_icons: false,
+            history_len: DEFAULT_HISTORY_LEN,
+            history_intermediate_summaries: DEFAULT_HISTORY_INTERMEDIATE_SUMMARIES,
+            history_ttl: None,
+            history_store: None,
+            log_content: true,
+        }
+    }
+}
+
+impl SourceConfig {
+    /// A [`SourceConfigBuilder`] starting from the defaults.
+    pub fn builder() -> SourceConfigBuilder {
+        SourceConfigBuilder::default()
+    }
+}
+
+/// Builds a [`SourceConfig`] one field at a time; fields left alone keep their default.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct SourceConfigBuilder {
+    cfg: SourceConfig,
+}
+
+/// One setter per [`SourceConfig`] field, taking anything that converts into it.
+macro_rules! config_setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets [`SourceConfig::", stringify!($field), "`].")]
+            pub fn $field(mut self, value: impl Into<$ty>) -> Self {
+                self.cfg.$field = value.into();
+                self
+            }
+        )*
+    };
+}
+
+impl SourceConfigBuilder {
+    config_setters! {
+        capabilities: Vec<String>,
+        channel_capacity: usize,
+        overflow_policy: OverflowPolicy,
+        dbus_name: String,
+        dbus_path: String,
+        server_name: String,
+        server_vendor: String,
+        server_version: String,
+        spec_version: String,
+        default_timeout_ms: Option<i32>,
+        max_hint_value_len: usize,
+        max_total_hints_bytes: usize,
+        keep_extra_hints: bool,
+        proc_root: PathBuf,
+        clamp_timeout: TimeoutClamp,
+        timeout_rules: Vec<TimeoutRule>,
+        critical_never_expires: bool,
+        dnd_schedule: Vec<DndWindow>,
+        critical_bypasses_dnd: bool,
+        rate_limit: Option<RateLimit>,
+        dedup: Option<Dedup>,
+        close_on_reply: bool,
+        action_icons: bool,
+        history_len: usize,
+        history_intermediate_summaries: usize,
+        history_ttl: Option<Duration>,
+        log_content: bool,
+    }
+
+    /// Sets [`SourceConfig::history_store`].
+    pub fn history_store(mut self, store: impl HistoryStore + 'static) -> Self {
+        self.cfg.history_store = Some(Arc::new(store));
+        self
+    }
+
+    pub fn build(self) -> SourceConfig {
+        self.cfg
+    }
+}
+
+/// Minimum and maximum lifetime for notifications, independent of sender timeouts.
+///
+/// A `max_ms` also bounds notifications that would otherwise never expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutClamp {
+    /// Shortest allowed timeout.
+    pub min_ms: Option<u32>,
+    /// Longest allowed timeout; persistent notifications expire after this too.
+    pub max_ms: Option<u32>,
+    /// Leaves critical notifications unclamped.
+    pub exempt_critical: bool,
+}
+
+impl Default for TimeoutClamp {
+    fn default() -> Self {
+        Self {
+            min_ms: None,
+            max_ms: None,
+            exempt_critical: true,
+        }
+    }
+}
+
+impl TimeoutClamp {
+    /// Clamps an effective timeout (`None` = never expires); `critical` marks critical
+    /// urgency.
+    pub fn apply(&self, timeout_ms: Option<u32>, critical: bool) -> Option<u32> {
+        if self.exempt_critical && critical {
+            return timeout_ms;
+        }
+        match timeout_ms {
+            None => self.max_ms,
+            Some(ms) => {
+                let ms = self.min_ms.map_or(ms, |min| ms.max(min));
+                Some(self.max_ms.map_or(ms, |max| ms.min(max)))
+            }
+        }
+    }
+
+    /// The clamp for `notification`: this one with the fields set by the first of `rules`
+    /// that matches it.
+    pub fn for_notification(&self, rules: &[TimeoutRule], notification: &Notification) -> Self {
+        let Some(rule) = rules.iter().find(|rule| rule.matcher.matches(notification)) else {
+            return *self;
+        };
+        Self {
+            min_ms: rule.min_ms.or(self.min_ms),
+            max_ms: rule.max_ms.or(self.max_ms),
+            exempt_critical: rule.exempt_critical.unwrap_or(self.exempt_critical),
+        }
+    }
+}
+
+/// A [`TimeoutClamp`] override for the notifications `matcher` picks; fields left unset
+/// keep the clamp's own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeoutRule {
+    pub matcher: Matcher,
+    pub min_ms: Option<u32>,
+    pub max_ms: Option<u32>,
+    pub exempt_critical: Option<bool>,
+}
+
+/// When a new notification counts as a resent copy of a live one, for
+/// [`SourceConfig::dedup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dedup {
+    /// How long after the live one's content last arrived a copy still folds into it.
+    pub window: Duration,
+    /// Whether the bodies have to match too; off compares `app_name` and `summary` only.
+    pub match_body: bool,
+}
+
+impl Default for Dedup {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            match_body: true,
         }
     }
 }
 
+/// Counters describing source activity since startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    /// Unparsed hint values truncated or dropped for exceeding the hint size limits.
+    pub oversized_hints: u64,
+    /// [`SourceStats::oversized_hints`] broken down by sending `app_name`.
+    pub oversized_hints_by_app: HashMap<String, u64>,
+    /// Events dropped because the queue was full or the receiver was gone.
+    pub dropped_events: u64,
+    /// `NotificationClosed`/`ActionInvoked` signals the bus refused.
+    pub failed_signal_emissions: u64,
+    /// New notifications dropped by [`SourceConfig::rate_limit`].
+    pub rate_limited: u64,
+}
+
+/// Point-in-time summary of the store, as reported by `GetState`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceState {
+    /// Notifications currently stored.
+    pub count: u32,
+    /// Stored notifications that are pinned and therefore never expire.
+    pub pinned: u32,
+}
+
+/// One stored notification with its lifecycle state, as returned by
+/// [`WispSource::snapshot_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub id: u32,
+    pub notification: Notification,
+    /// Bumped by every replacement and pin change; starts at 0.
+    pub generation: u64,
+    /// Time left until the expiry deadline, zero once it has passed; `None` when the
+    /// notification never expires (no timeout, or pinned).
+    pub remaining: Option<Duration>,
+}
+
 /// Errors produced by source runtime operations.
 #[derive(Debug, Error)]
 pub enum SourceError {
@@ -73,12 +396,78 @@ pub enum SourceError {
     EventChannelClosed,
 }
 
+/// Why a command on a stored notification did not apply.
+///
+/// Reported on `org.wispd.Control1` under the error names from [`CommandError::dbus_name`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CommandError {
+    /// No notification with this id is stored; it already closed or never existed.
+    #[error("notification not found")]
+    NotFound,
+    /// The notification is stored but carries no action with this key.
+    #[error("notification has no such action")]
+    UnknownAction,
+    /// The source could not carry the command out.
+    #[error("backend error: {0}")]
+    Backend(String),
+}
+
+impl CommandError {
+    /// D-Bus error name for this error on the control interface.
+    pub fn dbus_name(&self) -> &'static str {
+        match self {
+            Self::NotFound => "org.wispd.Control1.Error.NotFound",
+            Self::UnknownAction => "org.wispd.Control1.Error.UnknownAction",
+            Self::Backend(_) => "org.wispd.Control1.Error.Backend",
+        }
+    }
+
+    /// Recovers the taxonomy from a failed control interface call, or `None` for errors
+    /// outside it (transport failures, missing interface).
+    pub fn from_dbus_error(err: &zbus::Error) -> Option<Self> {
+        let zbus::Error::MethodError(name, description, _) = err else {
+            return None;
+        };
+        match name.as_str() {
+            "org.wispd.Control1.Error.NotFound" => Some(Self::NotFound),
+            "org.wispd.Control1.Error.UnknownAction" => Some(Self::UnknownAction),
+            "org.wispd.Control1.Error.Backend" => {
+                Some(Self::Backend(description.clone().unwrap_or_default()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<SourceError> for CommandError {
+    fn from(err: SourceError) -> Self {
+        Self::Backend(err.to_string())
+    }
+}
+
 /// Errors produced while starting the D-Bus server.
 #[derive(Debug, Error)]
 pub enum StartupError {
     /// A D-Bus error occurred.
     #[error("dbus error: {0}")]
     Dbus(#[from] zbus::Error),
+    /// No connection to the bus (or peer) could be set up.
+    #[error("cannot connect to the bus: {0}")]
+    BusUnreachable(zbus::Error),
+    /// Another connection owns the bus name and does not give it up.
+    #[error("{0} is owned by another connection")]
+    NameTaken(String),
+}
+
+/// Steps of [`WispSource::start_dbus_with_progress`], in the order they are reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupStage {
+    /// Connected to the session bus, with the interfaces served.
+    Connected,
+    /// The bus name was requested.
+    NameRequested,
+    /// The bus name is owned; calls to it reach the source from now on.
+    NameAcquired,
 }
 
 /// In-memory notification source plus lifecycle logic.
@@ -92,17 +481,83 @@ struct Inner {
     cfg: SourceConfig,
     capabilities: RwLock<Vec<String>>,
     default_timeout_ms: RwLock<Option<i32>>,
+    clamp_timeout: RwLock<TimeoutClamp>,
+    timeout_rules: RwLock<Vec<TimeoutRule>>,
+    critical_never_expires: AtomicBool,
     sender: mpsc::Sender<NotificationEvent>,
+    /// Events held back from a full channel. Taken under the `notifications` lock, never
+    /// the other way round.
+    backlog: Mutex<Backlog>,
+    /// Every event again, for [`WispSource::subscribe`].
+    broadcast: broadcast::Sender<NotificationEvent>,
     notifications: Mutex<HashMap<u32, StoredNotification>>,
+    /// Taken under the `notifications` lock, never the other way round.
+    history: Mutex<History>,
+    /// Taken under the `notifications` lock, never the other way round.
+    rate_limiter: Mutex<AppLimiter>,
     next_id: AtomicU32,
+    next_batch_id: AtomicU64,
     dbus_connection: AsyncRwLock<Option<zbus::Connection>>,
     runtime_handle: Option<Handle>,
+    stats: Mutex<SourceStats>,
+    counters: Mutex<CounterMap>,
+    /// Set while a `CountersChanged` emission is scheduled.
+    counters_flush_pending: AtomicBool,
+    dnd: Mutex<DndState>,
+    dnd_schedule: RwLock<Vec<DndWindow>>,
+    /// Set once the task re-checking `dnd_schedule` runs; it lives as long as the source.
+    dnd_ticker_running: AtomicBool,
+    /// Set once the task pruning the history by age runs; it lives as long as the source.
+    history_sweeper_running: AtomicBool,
+    /// Armed expiry deadlines. Taken under the `notifications` lock, never the other way
+    /// round.
+    expiries: Mutex<ExpiryQueue>,
+    /// Signalled when the soonest deadline moves earlier, waking the expiry driver.
+    expiry_wake: watch::Sender<()>,
+    /// Set once the expiry driver runs; it lives as long as the source.
+    expiry_driver_running: AtomicBool,
+    /// Signalled after each event that can change the store count or do-not-disturb,
+    /// for the `Count` and `DoNotDisturb` properties of the control interface.
+    state_changed: watch::Sender<()>,
+    warn_limits: WarnLimits,
 }
 
-#[derive(Debug, Clone)]
+/// One limiter per warning call site that can fire once per event during a storm.
+#[derive(Debug, Default)]
+struct WarnLimits {
+    event_queue_full: WarnLimiter,
+    event_receiver_dropped: WarnLimiter,
+    closed_signal: WarnLimiter,
+    action_signal: WarnLimiter,
+}
+
+#[derive(Debug)]
 struct StoredNotification {
     notification: Notification,
     generation: u64,
+    /// Pinned notifications survive their expiry deadline; replacements keep the pin.
+    pinned: bool,
+    /// Unique bus name of the sender, kept for the history.
+    sender: Option<String>,
+    /// When the first notification with this id arrived; replacements keep it.
+    received_at: SystemTime,
+    /// Replacements so far, for the history entry.
+    chain: ReplaceChain,
+    /// When the current content arrived, for the [`SourceConfig::dedup`] window.
+    arrived_at: tokio::time::Instant,
+    /// Copies folded in by dedup since the content last changed.
+    repeat_count: u32,
+}
+
+/// Expiry computed for a freshly stored notification generation but not yet armed.
+#[derive(Debug, Clone, Copy)]
+struct PendingExpiry {
+    id: u32,
+    generation: u64,
+    requested_timeout_ms: i32,
+    critical: bool,
+    /// The clamp for the notification, resolved when it was stored.
+    clamp: TimeoutClamp,
 }
 
 /// Handle that keeps the D-Bus service connection alive.
@@ -121,19 +576,55 @@ impl DbusService {
 impl WispSource {
     /// Creates a new source and returns it with its event receiver.
     pub fn new(cfg: SourceConfig) -> (Self, mpsc::Receiver<NotificationEvent>) {
+        loggable::set_log_content(cfg.log_content);
         let (sender, receiver) = mpsc::channel(cfg.channel_capacity);
+        let broadcast = broadcast::Sender::new(cfg.channel_capacity);
         let source = Self {
             inner: Arc::new(Inner {
-                capabilities: RwLock::new(cfg.capabilities.clone()),
+                capabilities: RwLock::new(with_action_icons(
+                    cfg.capabilities.clone(),
+                    cfg.action_icons,
+                )),
                 default_timeout_ms: RwLock::new(cfg.default_timeout_ms),
+                clamp_timeout: RwLock::new(cfg.clamp_timeout),
+                timeout_rules: RwLock::new(cfg.timeout_rules.clone()),
+                critical_never_expires: AtomicBool::new(cfg.critical_never_expires),
+                dnd_schedule: RwLock::new(cfg.dnd_schedule.clone()),
+                history: Mutex::new(match &cfg.history_store {
+                    Some(store) => History::with_store(
+                        cfg.history_len,
+                        cfg.history_ttl,
+                        Arc::clone(store),
+                        SystemTime::now(),
+                    ),
+                    None => History::new(cfg.history_len, cfg.history_ttl),
+                }),
                 cfg,
                 sender,
+                backlog: Mutex::new(Backlog::default()),
+                broadcast,
                 notifications: Mutex::new(HashMap::new()),
+                rate_limiter: Mutex::new(AppLimiter::default()),
                 next_id: AtomicU32::new(1),
+                next_batch_id: AtomicU64::new(1),
                 dbus_connection: AsyncRwLock::new(None),
                 runtime_handle: Handle::try_current().ok(),
+                stats: Mutex::new(SourceStats::default()),
+                counters: Mutex::new(CounterMap::default()),
+                counters_flush_pending: AtomicBool::new(false),
+                dnd: Mutex::new(DndState::default()),
+                dnd_ticker_running: AtomicBool::new(false),
+                history_sweeper_running: AtomicBool::new(false),
+                expiries: Mutex::new(ExpiryQueue::default()),
+                expiry_wake: watch::Sender::new(()),
+                expiry_driver_running: AtomicBool::new(false),
+                state_changed: watch::Sender::new(()),
+                warn_limits: WarnLimits::default(),
             }),
         };
+        source.check_dnd_schedule();
+        source.ensure_dnd_ticker();
+        source.ensure_history_sweeper();
 
         (source, receiver)
     }
@@ -145,25 +636,73 @@ impl WispSource {
     pub async fn start_dbus(
         cfg: SourceConfig,
     ) -> Result<(Self, mpsc::Receiver<NotificationEvent>, DbusService), StartupError> {
-        let (source, receiver) = Self::new(cfg.clone());
+        Self::start_dbus_with_progress(cfg, |_| {}).await
+    }
+
+    /// Like [`start_dbus`](Self::start_dbus), calling `on_stage` as each
+    /// [`StartupStage`] is reached. Failing to connect is a
+    /// [`StartupError::BusUnreachable`]; a name kept by another daemon is a
+    /// [`StartupError::NameTaken`].
+    pub async fn start_dbus_with_progress(
+        cfg: SourceConfig,
+        mut on_stage: impl FnMut(StartupStage),
+    ) -> Result<(Self, mpsc::Receiver<NotificationEvent>, DbusService), StartupError> {
+        let dbus_name = cfg.dbus_name.clone();
+        info!(dbus_name = %dbus_name, dbus_path = %cfg.dbus_path, "starting dbus notification service");
+        let builder = ConnectionBuilder::session().map_err(StartupError::BusUnreachable)?;
+        let (source, receiver, service) =
+            Self::serve(cfg, builder).await.map_err(|err| match err {
+                StartupError::Dbus(err) => StartupError::BusUnreachable(err),
+                err => err,
+            })?;
+        on_stage(StartupStage::Connected);
+
+        on_stage(StartupStage::NameRequested);
+        service
+            .connection
+            .request_name(dbus_name.as_str())
+            .await
+            .map_err(|err| match err {
+                zbus::Error::NameTaken => StartupError::NameTaken(dbus_name.clone()),
+                err => StartupError::Dbus(err),
+            })?;
+        on_stage(StartupStage::NameAcquired);
+
+        info!(dbus_name = %dbus_name, "dbus notification service ready");
+        Ok((source, receiver, service))
+    }
+
+    /// Serves the notification and control interfaces on a connection the caller set
+    /// up, such as one end of a peer-to-peer socket pair. No bus name is requested; add
+    /// one to `builder` when it targets a bus.
+    pub async fn serve(
+        cfg: SourceConfig,
+        builder: ConnectionBuilder<'_>,
+    ) -> Result<(Self, mpsc::Receiver<NotificationEvent>, DbusService), StartupError> {
+        let dbus_path = cfg.dbus_path.clone();
+        let (source, receiver) = Self::new(cfg);
         let iface = NotificationsInterface {
             source: source.clone(),
         };
+        let control = ControlInterface {
+            source: source.clone(),
+        };
 
-        info!(dbus_name = %cfg.dbus_name, dbus_path = %cfg.dbus_path, "starting dbus notification service");
-        let connection = ConnectionBuilder::session()?
-            .name(cfg.dbus_name.as_str())?
-            .serve_at(cfg.dbus_path.as_str(), iface)?
+        let connection = builder
+            .serve_at(dbus_path, iface)?
+            .serve_at(CONTROL_PATH, control)?
             .build()
             .await?;
-
-        info!(dbus_name = %cfg.dbus_name, "dbus notification service ready");
         source.set_dbus_connection(connection.clone()).await;
+        source.start_property_notifier();
 
         Ok((source, receiver, DbusService { connection }))
     }
 
     /// Returns currently advertised freedesktop capabilities.
+    ///
+    /// Clients may cache `GetCapabilities` per connection, so changes only reach clients
+    /// that ask again.
     pub fn capabilities(&self) -> Vec<String> {
         self.inner
             .capabilities
@@ -172,17 +711,38 @@ impl WispSource {
             .clone()
     }
 
+    /// Returns a copy of the source counters.
+    pub fn stats(&self) -> SourceStats {
+        self.inner
+            .stats
+            .lock()
+            .expect("stats mutex poisoned")
+            .clone()
+    }
+
     /// Updates runtime-configurable source values.
     pub fn update_runtime_config(
         &self,
         capabilities: Vec<String>,
         default_timeout_ms: Option<i32>,
     ) {
+        self.set_capabilities(Features::from_capabilities(capabilities));
+        self.set_default_timeout(default_timeout_ms);
+    }
+
+    /// Replaces the feature set advertised by `GetCapabilities` from now on, with
+    /// `action-icons` added as [`SourceConfig::action_icons`] asks.
+    pub fn set_capabilities(&self, features: Features) {
         *self
             .inner
             .capabilities
             .write()
-            .expect("capabilities lock poisoned") = capabilities;
+            .expect("capabilities lock poisoned") =
+            with_action_icons(features.capabilities(), self.inner.cfg.action_icons);
+    }
+
+    /// Replaces the timeout used for notifications that ask for the server default.
+    pub fn set_default_timeout(&self, default_timeout_ms: Option<i32>) {
         *self
             .inner
             .default_timeout_ms
@@ -190,6 +750,47 @@ impl WispSource {
             .expect("default timeout lock poisoned") = default_timeout_ms;
     }
 
+    /// Replaces the timeout clamp for notifications stored from now on.
+    pub fn set_timeout_clamp(&self, clamp: TimeoutClamp) {
+        *self
+            .inner
+            .clamp_timeout
+            .write()
+            .expect("timeout clamp lock poisoned") = clamp;
+    }
+
+    /// Replaces [`SourceConfig::timeout_rules`] for notifications stored from now on.
+    pub fn set_timeout_rules(&self, rules: Vec<TimeoutRule>) {
+        *self
+            .inner
+            .timeout_rules
+            .write()
+            .expect("timeout rules lock poisoned") = rules;
+    }
+
+    /// The clamp for `notification`, with the first matching timeout rule applied.
+    fn timeout_clamp_for(&self, notification: &Notification) -> TimeoutClamp {
+        let clamp = *self
+            .inner
+            .clamp_timeout
+            .read()
+            .expect("timeout clamp lock poisoned");
+        let rules = self
+            .inner
+            .timeout_rules
+            .read()
+            .expect("timeout rules lock poisoned");
+        clamp.for_notification(&rules, notification)
+    }
+
+    /// Switches [`SourceConfig::critical_never_expires`] for notifications stored from
+    /// now on.
+    pub fn set_critical_never_expires(&self, enabled: bool) {
+        self.inner
+            .critical_never_expires
+            .store(enabled, Ordering::Relaxed);
+    }
+
     /// Inserts or replaces a notification and emits the corresponding event.
     ///
     /// If `replaces_id` points to an existing notification, replacement happens in-place
@@ -199,127 +800,783 @@ impl WispSource {
         notification: Notification,
         replaces_id: u32,
     ) -> Result<u32, SourceError> {
-        let timeout_ms = notification.timeout_ms;
-        debug!(app = %notification.app_name, summary = %notification.summary, replaces_id, timeout_ms, "processing notification");
+        let (id, expiry) = self.store_notification(notification, replaces_id, None)?;
+        self.arm_expiry(expiry);
+        Ok(id)
+    }
+
+    /// Stores `notifications` under one lock, as if each `(notification, replaces_id)`
+    /// went to [`notify`](Self::notify) in order, and returns their ids in that order.
+    ///
+    /// New notifications get contiguous ids. Their events are bracketed by
+    /// [`NotificationEvent::BatchStarted`] and [`NotificationEvent::BatchEnded`], so a
+    /// consumer can apply the whole batch before laying anything out.
+    pub async fn notify_batch(
+        &self,
+        notifications: Vec<(Notification, u32)>,
+    ) -> Result<Vec<u32>, SourceError> {
+        if notifications.is_empty() {
+            return Ok(Vec::new());
+        }
+        let batch_id = self.inner.next_batch_id.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            batch_id,
+            len = notifications.len(),
+            "acquiring notifications lock for notify_batch"
+        );
+        let stored = {
+            let mut store = self
+                .inner
+                .notifications
+                .lock()
+                .expect("notifications mutex poisoned");
+
+            let fresh = notifications
+                .iter()
+                .filter(|(_, replaces_id)| *replaces_id == 0 || !store.contains_key(replaces_id))
+                .count();
+            let mut next_id = self
+                .inner
+                .next_id
+                .fetch_add(fresh as u32, Ordering::Relaxed);
+            debug!(batch_id, first_id = next_id, fresh, "batch ids allocated");
+
+            self.send_event(NotificationEvent::BatchStarted { batch_id })?;
+            let mut stored = Vec::with_capacity(notifications.len());
+            for (notification, replaces_id) in notifications {
+                stored.push(self.store_locked(
+                    &mut store,
+                    notification,
+                    replaces_id,
+                    None,
+                    || {
+                        let id = next_id;
+                        next_id = next_id.wrapping_add(1);
+                        id
+                    },
+                )?);
+            }
+            self.send_event(NotificationEvent::BatchEnded { batch_id })?;
+            stored
+        };
+
+        Ok(stored
+            .into_iter()
+            .map(|(id, expiry)| {
+                self.arm_expiry(expiry);
+                id
+            })
+            .collect())
+    }
+
+    /// Stores the notification and emits its event without arming the expiry timer.
+    ///
+    /// The D-Bus handler arms the timer only once the `Notify` reply has been sent, so a
+    /// client never sees `NotificationClosed` for an id it has not been told about yet.
+    fn store_notification(
+        &self,
+        notification: Notification,
+        replaces_id: u32,
+        sender: Option<String>,
+    ) -> Result<(u32, PendingExpiry), SourceError> {
         debug!("acquiring notifications lock for notify");
         let mut store = self
             .inner
             .notifications
             .lock()
             .expect("notifications mutex poisoned");
+        self.store_locked(&mut store, notification, replaces_id, sender, || {
+            self.alloc_id()
+        })
+    }
+
+    /// [`store_notification`](Self::store_notification) on a store the caller has
+    /// locked; `new_id` is only called when nothing is replaced.
+    fn store_locked(
+        &self,
+        store: &mut HashMap<u32, StoredNotification>,
+        notification: Notification,
+        replaces_id: u32,
+        sender: Option<String>,
+        new_id: impl FnOnce() -> u32,
+    ) -> Result<(u32, PendingExpiry), SourceError> {
+        let timeout_ms = notification.timeout_ms;
+        let critical = notification.urgency == Urgency::Critical;
+        let clamp = self.timeout_clamp_for(&notification);
+        debug!(app = %notification.app_name, summary = %Loggable(&notification.summary), replaces_id, timeout_ms, "processing notification");
 
+        let repeat_of = if replaces_id != 0 && store.contains_key(&replaces_id) {
+            None
+        } else {
+            self.repeat_of(store, &notification)
+        };
+        let replaces_id = repeat_of.unwrap_or(replaces_id);
         if replaces_id != 0
             && let Some(entry) = store.get_mut(&replaces_id)
         {
             let previous = entry.notification.clone();
+            history::extend_chain(
+                &mut entry.chain,
+                &previous.summary,
+                self.inner.cfg.history_intermediate_summaries,
+            );
             entry.notification = notification.clone();
             entry.generation = entry.generation.saturating_add(1);
+            entry.arrived_at = tokio::time::Instant::now();
+            entry.repeat_count = match repeat_of {
+                Some(_) => entry.repeat_count.saturating_add(1),
+                None => 0,
+            };
+            self.disarm_expiry(replaces_id);
+            if sender.is_some() {
+                entry.sender = sender;
+            }
             let generation = entry.generation;
-            drop(store);
+            let remaining_ms = if entry.pinned {
+                None
+            } else {
+                self.timeout_millis(timeout_ms, critical, clamp)
+            };
 
-            self.schedule_timeout(replaces_id, generation, timeout_ms);
+            // Emitted under the store lock so the event stream orders exactly like the
+            // store mutations; otherwise a racing close could be reported before this.
             self.send_event(NotificationEvent::Replaced {
                 id: replaces_id,
                 previous: Box::new(previous),
                 current: Box::new(notification),
+                remaining_ms,
+                repeat_count: entry.repeat_count,
             })?;
-            debug!(id = replaces_id, "notification replaced");
-            return Ok(replaces_id);
+            debug!(
+                id = replaces_id,
+                repeat_count = entry.repeat_count,
+                "notification replaced"
+            );
+            return Ok((
+                replaces_id,
+                PendingExpiry {
+                    id: replaces_id,
+                    generation,
+                    requested_timeout_ms: timeout_ms,
+                    critical,
+                    clamp,
+                },
+            ));
         }
 
-        let id = self.alloc_id();
+        let id = new_id();
         debug!(id, "allocated notification id");
+        let expiry = PendingExpiry {
+            id,
+            generation: 0,
+            requested_timeout_ms: timeout_ms,
+            critical,
+            clamp,
+        };
+        if !self.within_rate_limit(&notification.app_name)? {
+            // Never stored, so the expiry has nothing to arm.
+            debug!(id, app = %notification.app_name, "notification dropped by the rate limit");
+            return Ok((id, expiry));
+        }
 
         let generation = 0;
+        let silent =
+            self.is_do_not_disturb() && !(critical && self.inner.cfg.critical_bypasses_dnd);
         store.insert(
             id,
             StoredNotification {
                 notification: notification.clone(),
                 generation,
+                pinned: false,
+                sender,
+                received_at: SystemTime::now(),
+                chain: ReplaceChain::default(),
+                arrived_at: tokio::time::Instant::now(),
+                repeat_count: 0,
             },
         );
-        drop(store);
-
-        self.schedule_timeout(id, generation, timeout_ms);
         self.send_event(NotificationEvent::Received {
             id,
             notification: Box::new(notification),
+            silent,
+            remaining_ms: self.timeout_millis(timeout_ms, critical, clamp),
         })?;
-        debug!(id, "notification stored");
-        Ok(id)
+        debug!(id, silent, "notification stored");
+        Ok((id, expiry))
+    }
+
+    /// The live notification `notification` is a resent copy of under
+    /// [`SourceConfig::dedup`]: the newest one with the same fingerprint whose content
+    /// arrived within the window.
+    fn repeat_of(
+        &self,
+        store: &HashMap<u32, StoredNotification>,
+        notification: &Notification,
+    ) -> Option<u32> {
+        let dedup = self.inner.cfg.dedup?;
+        let fingerprint = notification.fingerprint(dedup.match_body);
+        let now = tokio::time::Instant::now();
+        store
+            .iter()
+            .filter(|(_, entry)| {
+                now.saturating_duration_since(entry.arrived_at) <= dedup.window
+                    && entry.notification.fingerprint(dedup.match_body) == fingerprint
+            })
+            .map(|(id, _)| *id)
+            .max()
+    }
+
+    /// Runs a new notification from `app_name` past [`SourceConfig::rate_limit`] and sends
+    /// the `RateLimited` reports that are due. Returns whether to store it.
+    fn within_rate_limit(&self, app_name: &str) -> Result<bool, SourceError> {
+        let Some(limit) = self.inner.cfg.rate_limit else {
+            return Ok(true);
+        };
+        let now = tokio::time::Instant::now();
+        let (quiet, verdict) = {
+            let mut limiter = self
+                .inner
+                .rate_limiter
+                .lock()
+                .expect("rate limiter poisoned");
+            (
+                limiter.prune(limit, now),
+                limiter.check(limit, app_name, now),
+            )
+        };
+        let reports = quiet.into_iter().chain(
+            verdict
+                .report
+                .map(|suppressed| (app_name.to_string(), suppressed)),
+        );
+        for (app_name, suppressed) in reports {
+            info!(app = %app_name, suppressed, "notifications dropped by the rate limit");
+            self.send_event(NotificationEvent::RateLimited {
+                app_name,
+                suppressed,
+            })?;
+        }
+        if !verdict.pass {
+            self.inner
+                .stats
+                .lock()
+                .expect("stats mutex poisoned")
+                .rate_limited += 1;
+        }
+        Ok(verdict.pass)
     }
 
     /// Closes a notification by id.
     ///
-    /// Returns `Ok(true)` if a notification was closed, `Ok(false)` if it was not found.
-    pub async fn close(&self, id: u32, reason: CloseReason) -> Result<bool, SourceError> {
-        let removed = self
+    /// Fails with [`CommandError::NotFound`] if no notification has this id.
+    pub async fn close(&self, id: u32, reason: CloseReason) -> Result<(), CommandError> {
+        {
+            let mut store = self
+                .inner
+                .notifications
+                .lock()
+                .expect("notifications mutex poisoned");
+            let stored = store.remove(&id).ok_or(CommandError::NotFound)?;
+            self.send_closed_event(id, stored, &reason)?;
+        }
+
+        self.emit_notification_closed_signal(id, reason).await;
+        Ok(())
+    }
+
+    /// Closes every stored notification with `reason`, sending a `Closed` event and the
+    /// `NotificationClosed` signal for each. Returns the closed ids in ascending order.
+    ///
+    /// The store is drained and the events queued under one lock, so nothing arriving
+    /// meanwhile is closed by accident; the signals are emitted after it is released.
+    /// Fails with [`SourceError::EventChannelClosed`] once the event receiver is gone;
+    /// the store is drained and the signals sent regardless.
+    pub async fn close_all(&self, reason: CloseReason) -> Result<Vec<u32>, SourceError> {
+        let (closed, result) = self.drain(&reason);
+        for id in &closed {
+            self.emit_notification_closed_signal(*id, reason.clone())
+                .await;
+        }
+        info!(closed = closed.len(), ?reason, "closed all notifications");
+        result.map(|()| closed)
+    }
+
+    /// Removes every stored notification and queues its `Closed` event. Returns the ids
+    /// in ascending order and the first delivery failure.
+    fn drain(&self, reason: &CloseReason) -> (Vec<u32>, Result<(), SourceError>) {
+        let mut store = self
             .inner
             .notifications
             .lock()
-            .expect("notifications mutex poisoned")
-            .remove(&id);
-        if removed.is_none() {
-            return Ok(false);
+            .expect("notifications mutex poisoned");
+        let mut ids: Vec<u32> = store.keys().copied().collect();
+        ids.sort_unstable();
+        let mut result = Ok(());
+        for id in &ids {
+            if let Some(stored) = store.remove(id)
+                && let Err(err) = self.send_closed_event(*id, stored, reason)
+            {
+                debug!(id, %err, "closed event not delivered");
+                result = result.and(Err(err));
+            }
         }
+        (ids, result)
+    }
 
-        self.send_closed(id, reason).await?;
-        Ok(true)
+    /// Closes every notification as [`CloseReason::Undefined`], emitting
+    /// `NotificationClosed` for each so senders stop waiting, then releases the bus name
+    /// when the source owns one. Returns the closed ids in ascending order.
+    ///
+    /// Meant for a clean exit; calls that still arrive are served as usual.
+    pub async fn shutdown(&self) -> Vec<u32> {
+        let (closed, _) = self.drain(&CloseReason::Undefined);
+        for id in &closed {
+            self.emit_notification_closed_signal(*id, CloseReason::Undefined)
+                .await;
+        }
+
+        let connection = self.inner.dbus_connection.read().await.clone();
+        if let Some(connection) = connection {
+            let dbus_name = self.inner.cfg.dbus_name.as_str();
+            match connection.release_name(dbus_name).await {
+                Ok(released) => debug!(dbus_name, released, "bus name released"),
+                // Peer connections have no bus to own names on.
+                Err(err) => debug!(dbus_name, %err, "bus name not released"),
+            }
+        }
+        info!(closed = closed.len(), "source shut down");
+        closed
     }
 
     /// Invokes an action for a notification.
     ///
-    /// On success, emits `ActionInvoked` and then closes the notification as dismissed.
-    /// Returns `Ok(false)` if notification or action key is not found.
-    pub async fn invoke_action(&self, id: u32, action_key: &str) -> Result<bool, SourceError> {
-        let action_exists = {
+    /// On success, emits `ActionInvoked` and then closes the notification as dismissed,
+    /// unless it has the `resident` hint: that one stays stored, its timeout running, for
+    /// the sender to replace or close. Fails with [`CommandError::NotFound`] for an unknown id and
+    /// [`CommandError::UnknownAction`] when the notification has no such action.
+    pub async fn invoke_action(&self, id: u32, action_key: &str) -> Result<(), CommandError> {
+        let resident = {
             let mut store = self
                 .inner
                 .notifications
                 .lock()
                 .expect("notifications mutex poisoned");
-            let Some(stored) = store.remove(&id) else {
-                return Ok(false);
-            };
-
+            let stored = store.get(&id).ok_or(CommandError::NotFound)?;
             if !stored
                 .notification
                 .actions
                 .iter()
                 .any(|a| a.key == action_key)
             {
-                store.insert(id, stored);
-                false
-            } else {
-                true
+                return Err(CommandError::UnknownAction);
+            }
+            let resident = stored.notification.hints.resident == Some(true);
+
+            self.send_event(NotificationEvent::ActionInvoked {
+                id,
+                action_key: action_key.to_string(),
+            })?;
+            if !resident {
+                let stored = store.remove(&id).ok_or(CommandError::NotFound)?;
+                self.send_closed_event(id, stored, &CloseReason::Dismissed)?;
             }
+            resident
         };
 
-        if !action_exists {
-            return Ok(false);
+        self.emit_action_invoked_signal(id, action_key).await;
+        if !resident {
+            self.emit_notification_closed_signal(id, CloseReason::Dismissed)
+                .await;
         }
 
-        self.send_event(NotificationEvent::ActionInvoked {
-            id,
-            action_key: action_key.to_string(),
-        })?;
-        self.emit_action_invoked_signal(id, action_key).await;
-        self.send_closed(id, CloseReason::Dismissed).await?;
+        Ok(())
+    }
 
-        Ok(true)
+    /// Sends a quick reply (`inline-reply`) to a notification.
+    ///
+    /// Emits `Replied` and the `NotificationReplied` signal, then closes the notification
+    /// as dismissed when [`SourceConfig::close_on_reply`] is on and it has no `resident`
+    /// hint. Clients only offer a reply field when `inline-reply` is advertised. Fails with
+    /// [`CommandError::NotFound`] for an unknown id.
+    pub async fn reply(&self, id: u32, text: &str) -> Result<(), CommandError> {
+        let close = {
+            let mut store = self
+                .inner
+                .notifications
+                .lock()
+                .expect("notifications mutex poisoned");
+            let stored = store.get(&id).ok_or(CommandError::NotFound)?;
+            let close =
+                self.inner.cfg.close_on_reply && stored.notification.hints.resident != Some(true);
+
+            self.send_event(NotificationEvent::Replied {
+                id,
+                text: text.to_string(),
+            })?;
+            if close {
+                let stored = store.remove(&id).ok_or(CommandError::NotFound)?;
+                self.send_closed_event(id, stored, &CloseReason::Dismissed)?;
+            }
+            close
+        };
+
+        self.emit_notification_replied_signal(id, text).await;
+        if close {
+            self.emit_notification_closed_signal(id, CloseReason::Dismissed)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Streams every event from now on, next to the receiver [`new`](Self::new) returned;
+    /// any number of subscribers can watch the same source.
+    ///
+    /// A subscriber starts with an empty view: [`snapshot`](Self::snapshot) catches it up.
+    /// One that falls more than `channel_capacity` events behind gets
+    /// [`NotificationEvent::Overflowed`] in place of what it missed. The stream ends when
+    /// the source is dropped.
+    pub fn subscribe(&self) -> impl Stream<Item = NotificationEvent> + Send + 'static {
+        futures_util::stream::unfold(self.inner.broadcast.subscribe(), |mut rx| async move {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(dropped)) => NotificationEvent::Overflowed { dropped },
+                Err(RecvError::Closed) => return None,
+            };
+            Some((event, rx))
+        })
     }
 
-    /// Returns a snapshot of current notifications keyed by id.
+    /// Returns a snapshot of current notifications, sorted by id.
     pub async fn snapshot(&self) -> Vec<(u32, Notification)> {
-        let store = self
-            .inner
+        self.snapshot_detailed()
+            .into_iter()
+            .map(|entry| (entry.id, entry.notification))
+            .collect()
+    }
+
+    /// Returns every stored notification with its generation and remaining timeout,
+    /// sorted by id.
+    pub fn snapshot_detailed(&self) -> Vec<SnapshotEntry> {
+        let now = tokio::time::Instant::now();
+        let store = self
+            .inner
             .notifications
             .lock()
             .expect("notifications mutex poisoned");
-        store
+        let expiries = self.inner.expiries.lock().expect("expiry queue poisoned");
+        let mut entries: Vec<_> = store
             .iter()
-            .map(|(id, stored)| (*id, stored.notification.clone()))
-            .collect()
+            .map(|(id, stored)| SnapshotEntry {
+                id: *id,
+                notification: stored.notification.clone(),
+                generation: stored.generation,
+                remaining: expiries
+                    .deadline(*id, stored.generation)
+                    .filter(|_| !stored.pinned)
+                    .map(|deadline| deadline.saturating_duration_since(now)),
+            })
+            .collect();
+        entries.sort_unstable_by_key(|entry| entry.id);
+        entries
+    }
+
+    /// Time left until `id` expires; `None` when it is not stored, never expires or is
+    /// pinned.
+    pub fn remaining_timeout(&self, id: u32) -> Option<Duration> {
+        let now = tokio::time::Instant::now();
+        let store = self
+            .inner
+            .notifications
+            .lock()
+            .expect("notifications mutex poisoned");
+        let stored = store.get(&id).filter(|stored| !stored.pinned)?;
+        self.inner
+            .expiries
+            .lock()
+            .expect("expiry queue poisoned")
+            .deadline(id, stored.generation)
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Returns the stored notification `id`, if any.
+    pub fn get(&self, id: u32) -> Option<Notification> {
+        self.inner
+            .notifications
+            .lock()
+            .expect("notifications mutex poisoned")
+            .get(&id)
+            .map(|stored| stored.notification.clone())
+    }
+
+    /// Pins or unpins `id`. A pinned notification never expires; unpinning starts a fresh
+    /// server-default timeout. Returns `false` when `id` is not stored.
+    pub fn set_pinned(&self, id: u32, pinned: bool) -> bool {
+        let expiry = {
+            let mut store = self
+                .inner
+                .notifications
+                .lock()
+                .expect("notifications mutex poisoned");
+            let Some(entry) = store.get_mut(&id) else {
+                return false;
+            };
+            if entry.pinned == pinned {
+                return true;
+            }
+            entry.pinned = pinned;
+            // A timer armed before the pin may still be pending; a new generation keeps it
+            // from cutting the fresh timeout short.
+            entry.generation = entry.generation.saturating_add(1);
+            self.disarm_expiry(id);
+            PendingExpiry {
+                id,
+                generation: entry.generation,
+                requested_timeout_ms: -1,
+                critical: entry.notification.urgency == Urgency::Critical,
+                clamp: self.timeout_clamp_for(&entry.notification),
+            }
+        };
+
+        debug!(id, pinned, "notification pin changed");
+        if !pinned {
+            self.arm_expiry(expiry);
+        }
+        true
+    }
+
+    /// Returns the current store counts.
+    pub fn state(&self) -> SourceState {
+        let store = self
+            .inner
+            .notifications
+            .lock()
+            .expect("notifications mutex poisoned");
+        SourceState {
+            count: u32::try_from(store.len()).unwrap_or(u32::MAX),
+            pinned: u32::try_from(store.values().filter(|entry| entry.pinned).count())
+                .unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Per-app counts, sorted by app name. Kept across config reloads; they start empty
+    /// with the source.
+    pub fn app_counters(&self) -> BTreeMap<String, AppCounters> {
+        self.inner
+            .counters
+            .lock()
+            .expect("counters mutex poisoned")
+            .snapshot()
+    }
+
+    /// Zeroes `app`'s unseen count. Returns whether there was anything to reset.
+    pub fn mark_seen(&self, app: &str) -> bool {
+        let changed = self
+            .inner
+            .counters
+            .lock()
+            .expect("counters mutex poisoned")
+            .mark_seen(app);
+        if changed {
+            debug!(app, "app counters marked seen");
+            self.schedule_counters_changed();
+        }
+        changed
+    }
+
+    /// Announces that `id`, left unacknowledged, was escalated for the `count`th time:
+    /// emits `Escalated` on the control interface with the stored app name, summary and
+    /// body. The UI decides when; the source only relays.
+    ///
+    /// Fails with [`CommandError::NotFound`] if no notification has this id.
+    pub async fn escalate(&self, id: u32, count: u32) -> Result<(), CommandError> {
+        let notification = self
+            .inner
+            .notifications
+            .lock()
+            .expect("notifications mutex poisoned")
+            .get(&id)
+            .map(|stored| stored.notification.clone())
+            .ok_or(CommandError::NotFound)?;
+
+        debug!(id, count, "notification escalated");
+        self.emit_escalated_signal(id, &notification, count).await;
+        Ok(())
+    }
+
+    /// Recently closed notifications, newest first, up to [`SourceConfig::history_len`].
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .entries()
+    }
+
+    /// The history entry of notification `index_or_id`, or else the one at that position
+    /// counting from 0 = newest.
+    pub fn historic(&self, index_or_id: u32) -> Option<HistoryEntry> {
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .find(index_or_id)
+            .cloned()
+    }
+
+    /// Stars or unstars history entry `index_or_id` (its id, or else its position, 0 =
+    /// newest); starred entries are exempt from [`SourceConfig::history_ttl`]. Returns
+    /// the entry's id.
+    ///
+    /// Fails with [`CommandError::NotFound`] if there is no such entry.
+    pub fn set_history_starred(
+        &self,
+        index_or_id: u32,
+        starred: bool,
+    ) -> Result<u32, CommandError> {
+        let id = self
+            .inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .set_starred(index_or_id, starred)
+            .ok_or(CommandError::NotFound)?;
+        debug!(id, starred, "history entry star changed");
+        Ok(id)
+    }
+
+    /// Deletes the history entries with these ids, starred ones too, from memory and the
+    /// history file. Returns how many there were.
+    pub fn remove_history(&self, ids: &[u32]) -> usize {
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .remove(ids)
+    }
+
+    /// Deletes every history entry. Returns how many there were.
+    pub fn clear_history(&self) -> usize {
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .clear()
+    }
+
+    /// Replaces the history TTL and prunes with it right away.
+    pub fn set_history_ttl(&self, ttl: Option<Duration>) {
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .set_ttl(ttl, SystemTime::now());
+        self.ensure_history_sweeper();
+    }
+
+    /// Asks the UI to show a history entry again (see [`WispSource::historic`]) with a
+    /// `Historic` event. Nothing is stored, no id is allocated and no signal is sent.
+    /// Its actions stay disabled unless `sender_connected` says the original sender can
+    /// still receive `ActionInvoked`. Returns the entry's original id.
+    ///
+    /// Fails with [`CommandError::NotFound`] if there is no such entry.
+    pub fn show_historic(
+        &self,
+        index_or_id: u32,
+        sender_connected: bool,
+    ) -> Result<u32, CommandError> {
+        let entry = self.historic(index_or_id).ok_or(CommandError::NotFound)?;
+        let actions_enabled = sender_connected && !entry.notification.actions.is_empty();
+        debug!(id = entry.id, actions_enabled, "history entry shown again");
+        self.send_event(NotificationEvent::Historic {
+            id: entry.id,
+            notification: Box::new(entry.notification),
+            actions_enabled,
+            chain: entry.chain,
+        })?;
+        Ok(entry.id)
+    }
+
+    /// [`WispSource::show_historic`] for callers off the bus, such as the UI's history
+    /// panel: the actions are enabled when the entry's sender is still connected to the
+    /// bus the source serves.
+    pub async fn reshow_historic(&self, index_or_id: u32) -> Result<u32, CommandError> {
+        let entry = self.historic(index_or_id).ok_or(CommandError::NotFound)?;
+        let connection = self.inner.dbus_connection.read().await.clone();
+        let connected = match &connection {
+            Some(connection) => sender_connected(connection, entry.sender.as_deref()).await,
+            None => false,
+        };
+        self.show_historic(entry.id, connected)
+    }
+
+    /// Emits `ActionInvoked` for an action picked on a history entry shown again. The
+    /// entry's id is only meaningful to its original sender; nothing is closed.
+    ///
+    /// Fails with [`CommandError::NotFound`] for an id no longer in the history and
+    /// [`CommandError::UnknownAction`] when the entry has no such action.
+    pub async fn invoke_historic_action(
+        &self,
+        id: u32,
+        action_key: &str,
+    ) -> Result<(), CommandError> {
+        let entry = self
+            .inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .find(id)
+            .filter(|entry| entry.id == id)
+            .cloned()
+            .ok_or(CommandError::NotFound)?;
+        if !entry
+            .notification
+            .actions
+            .iter()
+            .any(|a| a.key == action_key)
+        {
+            return Err(CommandError::UnknownAction);
+        }
+
+        self.emit_action_invoked_signal(id, action_key).await;
+        Ok(())
+    }
+
+    /// The manual do-not-disturb switch. It overrides the schedule until the schedule next
+    /// starts or ends a window; switching to what the schedule says drops the override.
+    /// Sends `DndChanged` when the effective state flips.
+    pub fn set_do_not_disturb(&self, enabled: bool) {
+        let changed = self
+            .inner
+            .dnd
+            .lock()
+            .expect("dnd mutex poisoned")
+            .set_manual(enabled);
+        info!(enabled, "do-not-disturb switched manually");
+        if changed {
+            self.send_dnd_changed(enabled);
+        }
+    }
+
+    /// Whether do-not-disturb is on, manually or by schedule.
+    pub fn is_do_not_disturb(&self) -> bool {
+        self.do_not_disturb().enabled
+    }
+
+    /// Returns the do-not-disturb state with its schedule and override parts.
+    pub fn do_not_disturb(&self) -> DndStatus {
+        self.inner.dnd.lock().expect("dnd mutex poisoned").status()
+    }
+
+    /// Replaces the do-not-disturb schedule and checks it against the clock right away.
+    pub fn set_dnd_schedule(&self, schedule: Vec<DndWindow>) {
+        *self
+            .inner
+            .dnd_schedule
+            .write()
+            .expect("dnd schedule lock poisoned") = schedule;
+        self.check_dnd_schedule();
+        self.ensure_dnd_ticker();
     }
 
     /// Returns `(name, vendor, version, spec_version)` for `GetServerInformation`.
@@ -332,15 +1589,78 @@ impl WispSource {
         )
     }
 
+    /// Parses raw D-Bus hints within the configured size limits, counting oversized
+    /// values against `app_name`.
+    fn parse_incoming_hints(
+        &self,
+        app_name: &str,
+        hints: &HashMap<String, zvariant::OwnedValue>,
+    ) -> (Urgency, NotificationHints) {
+        let limits = HintLimits {
+            max_value_len: self.inner.cfg.max_hint_value_len,
+            max_total_bytes: self.inner.cfg.max_total_hints_bytes,
+            keep_extra: self.inner.cfg.keep_extra_hints,
+        };
+        let (urgency, parsed, oversized) = parse_hints(hints, limits);
+        if oversized > 0 {
+            warn!(app = %app_name, oversized, "truncated oversized notification hints");
+            let mut stats = self.inner.stats.lock().expect("stats mutex poisoned");
+            stats.oversized_hints += oversized;
+            *stats
+                .oversized_hints_by_app
+                .entry(app_name.to_string())
+                .or_default() += oversized;
+        }
+        (urgency, parsed)
+    }
+
+    /// Best-effort Flatpak app id for the process `pid`; `None` when unsandboxed or
+    /// unreadable.
+    fn sandbox_app_id(&self, pid: u32) -> Option<String> {
+        let app_id = sandbox::flatpak_app_id(&self.inner.cfg.proc_root, pid);
+        if let Some(app_id) = &app_id {
+            debug!(pid, app_id = %app_id, "identified sandboxed sender");
+        }
+        app_id
+    }
+
     async fn set_dbus_connection(&self, connection: zbus::Connection) {
         *self.inner.dbus_connection.write().await = Some(connection);
     }
 
-    fn schedule_timeout(&self, id: u32, generation: u64, requested_timeout_ms: i32) {
-        let Some(duration) = self.effective_timeout_duration(requested_timeout_ms) else {
+    /// Arms the expiry of a freshly stored generation; the deadline counts from now.
+    fn arm_expiry(&self, expiry: PendingExpiry) {
+        self.schedule_expiry(expiry, false);
+    }
+
+    /// [`arm_expiry`](Self::arm_expiry), but the notification is not expired before
+    /// `after` resolves, even once its deadline has passed. Only a task waiting for `after`
+    /// is spawned, not one sleeping out the timeout.
+    fn arm_expiry_after<F>(&self, expiry: PendingExpiry, after: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let Some(handle) = self.schedule_expiry(expiry, true) else {
             return;
         };
+        let source = self.clone();
+        handle.spawn(async move {
+            after.await;
+            source.wake_expiry_driver_if(|queue| queue.open_gate(expiry.id, expiry.generation));
+        });
+    }
 
+    /// Puts the deadline of `expiry` in the queue, unless it never expires or its
+    /// generation is no longer stored. Returns the runtime the driver runs on when armed.
+    fn schedule_expiry(&self, expiry: PendingExpiry, gated: bool) -> Option<Handle> {
+        let PendingExpiry {
+            id,
+            generation,
+            requested_timeout_ms,
+            critical,
+            clamp,
+        } = expiry;
+        let duration = self.effective_timeout_duration(requested_timeout_ms, critical, clamp)?;
         let handle = self
             .inner
             .runtime_handle
@@ -351,19 +1671,118 @@ impl WispSource {
                 id,
                 "no tokio runtime handle available; skipping timeout scheduling"
             );
-            return;
+            return None;
         };
+        self.ensure_expiry_driver(&handle);
 
-        let source = self.clone();
+        let deadline = tokio::time::Instant::now() + duration;
+        let store = self
+            .inner
+            .notifications
+            .lock()
+            .expect("notifications mutex poisoned");
+        // Closed or replaced since it was stored: nothing to expire.
+        store
+            .get(&id)
+            .filter(|entry| entry.generation == generation)?;
+        self.wake_expiry_driver_if(|queue| queue.insert(id, generation, deadline, gated));
+        Some(handle)
+    }
+
+    /// Drops the deadline armed for `id`, if any.
+    fn disarm_expiry(&self, id: u32) {
+        self.inner
+            .expiries
+            .lock()
+            .expect("expiry queue poisoned")
+            .remove(id);
+    }
+
+    /// Applies `change` to the expiry queue and wakes the driver when it says the soonest
+    /// deadline moved earlier.
+    fn wake_expiry_driver_if(&self, change: impl FnOnce(&mut ExpiryQueue) -> bool) {
+        let sooner = change(&mut self.inner.expiries.lock().expect("expiry queue poisoned"));
+        if sooner {
+            self.inner.expiry_wake.send_replace(());
+        }
+    }
+
+    /// Starts the one task expiring notifications: it sleeps until the soonest deadline
+    /// in the queue, or until woken by a sooner one, and expires whatever is due. The task
+    /// stops when the source is dropped.
+    fn ensure_expiry_driver(&self, handle: &Handle) {
+        if self
+            .inner
+            .expiry_driver_running
+            .swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+        let mut wake = self.inner.expiry_wake.subscribe();
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
         handle.spawn(async move {
-            tokio::time::sleep(duration).await;
-            if let Err(err) = source.expire_if_current(id, generation).await {
-                warn!(id, ?err, "failed to process timeout expiration");
+            while let Some(next) = inner.upgrade().map(|inner| {
+                inner
+                    .expiries
+                    .lock()
+                    .expect("expiry queue poisoned")
+                    .next_deadline()
+            }) {
+                let woken = match next {
+                    Some(deadline) => tokio::select! {
+                        changed = wake.changed() => changed.is_ok(),
+                        () = tokio::time::sleep_until(deadline) => true,
+                    },
+                    None => wake.changed().await.is_ok(),
+                };
+                let Some(inner) = inner.upgrade().filter(|_| woken) else {
+                    break;
+                };
+                let source = WispSource { inner };
+                let due = {
+                    let mut queue = source.inner.expiries.lock().expect("expiry queue poisoned");
+                    let due = queue.pop_due(tokio::time::Instant::now());
+                    if !due.is_empty() {
+                        debug!(
+                            due = due.len(),
+                            armed = queue.len(),
+                            "expiring notifications"
+                        );
+                    }
+                    due
+                };
+                for (id, generation) in due {
+                    if let Err(err) = source.expire_if_current(id, generation).await {
+                        warn!(id, ?err, "failed to process timeout expiration");
+                    }
+                }
             }
         });
     }
 
-    fn effective_timeout_duration(&self, requested_timeout_ms: i32) -> Option<Duration> {
+    /// [`effective_timeout_duration`](Self::effective_timeout_duration) in milliseconds,
+    /// as carried by `Received` and `Replaced`.
+    fn timeout_millis(
+        &self,
+        requested_timeout_ms: i32,
+        critical: bool,
+        clamp: TimeoutClamp,
+    ) -> Option<u64> {
+        self.effective_timeout_duration(requested_timeout_ms, critical, clamp)
+            .map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    /// `clamp` is the one resolved for the notification by
+    /// [`timeout_clamp_for`](Self::timeout_clamp_for).
+    fn effective_timeout_duration(
+        &self,
+        requested_timeout_ms: i32,
+        critical: bool,
+        clamp: TimeoutClamp,
+    ) -> Option<Duration> {
+        if critical && self.inner.critical_never_expires.load(Ordering::Relaxed) {
+            return None;
+        }
         let default_timeout_ms = *self
             .inner
             .default_timeout_ms
@@ -371,21 +1790,23 @@ impl WispSource {
             .expect("default timeout lock poisoned");
 
         let effective_ms = match requested_timeout_ms {
-            0 => return None,
-            x if x < 0 => default_timeout_ms?,
-            x => x,
-        };
-
-        let millis = u64::try_from(effective_ms).ok()?;
-        if millis == 0 {
-            None
-        } else {
-            Some(Duration::from_millis(millis))
+            0 => None,
+            x if x < 0 => default_timeout_ms,
+            x => Some(x),
         }
+        .and_then(|ms| u32::try_from(ms).ok())
+        .filter(|ms| *ms > 0);
+
+        clamp
+            .apply(effective_ms, critical)
+            .filter(|ms| *ms > 0)
+            .map(|ms| Duration::from_millis(u64::from(ms)))
     }
 
+    /// Expires `id` only if it still holds `generation`; the check, removal and `Closed`
+    /// event happen in one store critical section so a replacement cannot slip between.
     async fn expire_if_current(&self, id: u32, generation: u64) -> Result<(), SourceError> {
-        let removed = {
+        {
             let mut store = self
                 .inner
                 .notifications
@@ -394,29 +1815,52 @@ impl WispSource {
 
             let should_expire = store
                 .get(&id)
-                .is_some_and(|entry| entry.generation == generation);
-
-            if !should_expire {
+                .is_some_and(|entry| entry.generation == generation && !entry.pinned);
+            let Some(stored) = should_expire.then(|| store.remove(&id)).flatten() else {
                 return Ok(());
-            }
-
-            store.remove(&id)
-        };
-
-        if removed.is_none() {
-            return Ok(());
+            };
+            self.send_closed_event(id, stored, &CloseReason::Expired)?;
         }
 
-        self.send_closed(id, CloseReason::Expired).await
+        self.emit_notification_closed_signal(id, CloseReason::Expired)
+            .await;
+        Ok(())
     }
 
-    async fn send_closed(&self, id: u32, reason: CloseReason) -> Result<(), SourceError> {
+    /// Disarms the removed notification's expiry, records it in the history and queues the
+    /// `Closed` event; callers hold the store lock for the matching removal.
+    fn send_closed_event(
+        &self,
+        id: u32,
+        stored: StoredNotification,
+        reason: &CloseReason,
+    ) -> Result<(), SourceError> {
+        self.disarm_expiry(id);
+        let lifetime = stored.received_at.elapsed().unwrap_or_default();
+        let chain = ReplaceChain {
+            lifetime_ms: u64::try_from(lifetime.as_millis()).unwrap_or(u64::MAX),
+            ..stored.chain
+        };
+        self.inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .record(
+                HistoryEntry {
+                    id,
+                    notification: stored.notification,
+                    sender: stored.sender,
+                    reason: reason.clone(),
+                    received_at: stored.received_at,
+                    chain,
+                    starred: false,
+                },
+                SystemTime::now(),
+            );
         self.send_event(NotificationEvent::Closed {
             id,
             reason: reason.clone(),
-        })?;
-        self.emit_notification_closed_signal(id, reason).await;
-        Ok(())
+        })
     }
 
     async fn emit_notification_closed_signal(&self, id: u32, reason: CloseReason) {
@@ -434,7 +1878,14 @@ impl WispSource {
             )
             .await
         {
-            warn!(id, ?err, "failed to emit NotificationClosed signal");
+            self.record_failed_signal();
+            if let Some(suppressed) = self.inner.warn_limits.closed_signal.check(Instant::now()) {
+                warn!(
+                    id,
+                    ?err,
+                    "failed to emit NotificationClosed signal{suppressed}"
+                );
+            }
         }
     }
 
@@ -453,81 +1904,540 @@ impl WispSource {
             )
             .await
         {
-            warn!(id, ?err, "failed to emit ActionInvoked signal");
-        }
-    }
-
-    fn alloc_id(&self) -> u32 {
-        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
-        debug!(id, "next_id advanced");
-        id
-    }
-
-    fn send_event(&self, event: NotificationEvent) -> Result<(), SourceError> {
-        debug!(?event, "sending notification event");
-        match self.inner.sender.try_send(event) {
-            Ok(()) => Ok(()),
-            Err(TrySendError::Full(_)) => {
-                warn!("event queue full; dropping notification event");
-                Ok(())
-            }
-            Err(TrySendError::Closed(_)) => {
-                warn!("event receiver dropped");
-                Err(SourceError::EventChannelClosed)
+            self.record_failed_signal();
+            if let Some(suppressed) = self.inner.warn_limits.action_signal.check(Instant::now()) {
+                warn!(id, ?err, "failed to emit ActionInvoked signal{suppressed}");
             }
         }
     }
-}
-
-#[derive(Debug, Clone)]
-struct NotificationsInterface {
-    source: WispSource,
-}
 
-#[zbus::interface(name = "org.freedesktop.Notifications")]
-impl NotificationsInterface {
-    #[allow(clippy::too_many_arguments)]
-    async fn notify(
-        &self,
-        app_name: String,
-        replaces_id: u32,
-        app_icon: String,
-        summary: String,
-        body: String,
-        actions: Vec<String>,
-        hints: HashMap<String, zvariant::OwnedValue>,
-        expire_timeout: i32,
-    ) -> zbus::fdo::Result<u32> {
-        info!(app = %app_name, summary = %summary, replaces_id, expire_timeout, action_pairs = actions.len() / 2, "dbus Notify called");
-        let (urgency, parsed_hints) = parse_hints(&hints);
-        let notification = Notification {
-            app_name,
-            app_icon,
-            summary,
-            body,
-            urgency,
-            timeout_ms: expire_timeout,
-            actions: parse_actions(actions),
-            hints: parsed_hints,
+    async fn emit_notification_replied_signal(&self, id: u32, text: &str) {
+        let Some(connection) = self.inner.dbus_connection.read().await.clone() else {
+            return;
         };
 
-        let id = self
-            .source
-            .notify(notification, replaces_id)
+        if let Err(err) = connection
+            .emit_signal(
+                None::<&str>,
+                self.inner.cfg.dbus_path.as_str(),
+                DBUS_INTERFACE,
+                "NotificationReplied",
+                &(id, text),
+            )
             .await
-            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))?;
-
-        info!(id, "dbus Notify handled");
-        Ok(id)
+        {
+            self.record_failed_signal();
+            warn!(id, ?err, "failed to emit NotificationReplied signal");
+        }
     }
 
-    async fn close_notification(&self, id: u32) -> zbus::fdo::Result<()> {
-        info!(id, "dbus CloseNotification called");
-        let closed = self
-            .source
-            .close(id, CloseReason::ClosedByCall)
+    async fn emit_escalated_signal(&self, id: u32, notification: &Notification, count: u32) {
+        let Some(connection) = self.inner.dbus_connection.read().await.clone() else {
+            return;
+        };
+
+        if let Err(err) = connection
+            .emit_signal(
+                None::<&str>,
+                CONTROL_PATH,
+                CONTROL_INTERFACE,
+                "Escalated",
+                &(
+                    id,
+                    notification.app_name.as_str(),
+                    notification.summary.as_str(),
+                    notification.body.as_str(),
+                    count,
+                ),
+            )
+            .await
+        {
+            self.record_failed_signal();
+            warn!(id, ?err, "failed to emit Escalated signal");
+        }
+    }
+
+    fn check_dnd_schedule(&self) {
+        let scheduled = is_dnd_scheduled(
+            &chrono::Local::now(),
+            &self
+                .inner
+                .dnd_schedule
+                .read()
+                .expect("dnd schedule lock poisoned"),
+        );
+        self.observe_dnd_schedule(scheduled);
+    }
+
+    /// Feeds the schedule's verdict to the DND state; a change of verdict is a boundary
+    /// and ends any manual override.
+    fn observe_dnd_schedule(&self, scheduled: bool) {
+        let (changed, enabled) = {
+            let mut dnd = self.inner.dnd.lock().expect("dnd mutex poisoned");
+            (dnd.observe_schedule(scheduled), dnd.enabled())
+        };
+        if changed {
+            info!(enabled, "do-not-disturb switched by schedule");
+            self.send_dnd_changed(enabled);
+        }
+    }
+
+    fn send_dnd_changed(&self, enabled: bool) {
+        if let Err(err) = self.send_event(NotificationEvent::DndChanged { enabled }) {
+            debug!(%err, "dnd change not delivered");
+        }
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        if let Some(handle) = handle {
+            let source = self.clone();
+            handle.spawn(async move { source.emit_dnd_changed_signal().await });
+        }
+    }
+
+    /// Signals the state current when it runs, so quick toggles cannot leave the last
+    /// signal stale.
+    async fn emit_dnd_changed_signal(&self) {
+        let Some(connection) = self.inner.dbus_connection.read().await.clone() else {
+            return;
+        };
+
+        if let Err(err) = connection
+            .emit_signal(
+                None::<&str>,
+                CONTROL_PATH,
+                CONTROL_INTERFACE,
+                "DndChanged",
+                &(self.is_do_not_disturb(),),
+            )
+            .await
+        {
+            self.record_failed_signal();
+            debug!(?err, "failed to emit DndChanged signal");
+        }
+    }
+
+    /// Starts re-checking the schedule every [`DND_CHECK_INTERVAL`] once it has windows.
+    /// Checking the wall clock on a coarse timer, rather than sleeping until precomputed
+    /// instants, keeps midnight wraps, DST changes and suspend correct. The task stops
+    /// when the source is dropped.
+    fn ensure_dnd_ticker(&self) {
+        let empty = self
+            .inner
+            .dnd_schedule
+            .read()
+            .expect("dnd schedule lock poisoned")
+            .is_empty();
+        if empty || self.inner.dnd_ticker_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        let Some(handle) = handle else {
+            self.inner
+                .dnd_ticker_running
+                .store(false, Ordering::Release);
+            return;
+        };
+
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        handle.spawn(async move {
+            let mut interval = tokio::time::interval(DND_CHECK_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                WispSource { inner }.check_dnd_schedule();
+            }
+        });
+    }
+
+    /// Starts pruning the history every [`HISTORY_SWEEP_INTERVAL`] once it has a TTL, so
+    /// entries expire even while nothing new is recorded. The task stops when the source
+    /// is dropped.
+    fn ensure_history_sweeper(&self) {
+        let no_ttl = self
+            .inner
+            .history
+            .lock()
+            .expect("history mutex poisoned")
+            .ttl()
+            .is_none();
+        if no_ttl
+            || self
+                .inner
+                .history_sweeper_running
+                .swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        let Some(handle) = handle else {
+            self.inner
+                .history_sweeper_running
+                .store(false, Ordering::Release);
+            return;
+        };
+
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        handle.spawn(async move {
+            let mut interval = tokio::time::interval(HISTORY_SWEEP_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                inner
+                    .history
+                    .lock()
+                    .expect("history mutex poisoned")
+                    .prune(SystemTime::now());
+            }
+        });
+    }
+
+    /// Emits `CountersChanged` once [`COUNTERS_DEBOUNCE`] has passed, folding in every
+    /// change made meanwhile.
+    fn schedule_counters_changed(&self) {
+        if self
+            .inner
+            .counters_flush_pending
+            .swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        let Some(handle) = handle else {
+            self.inner
+                .counters_flush_pending
+                .store(false, Ordering::Release);
+            return;
+        };
+
+        let source = self.clone();
+        handle.spawn(async move {
+            tokio::time::sleep(COUNTERS_DEBOUNCE).await;
+            source
+                .inner
+                .counters_flush_pending
+                .store(false, Ordering::Release);
+            source.emit_counters_changed_signal().await;
+        });
+    }
+
+    async fn emit_counters_changed_signal(&self) {
+        let Some(connection) = self.inner.dbus_connection.read().await.clone() else {
+            return;
+        };
+
+        if let Err(err) = connection
+            .emit_signal(
+                None::<&str>,
+                CONTROL_PATH,
+                CONTROL_INTERFACE,
+                "CountersChanged",
+                &(wire_counters(self.app_counters()),),
+            )
             .await
+        {
+            self.record_failed_signal();
+            debug!(?err, "failed to emit CountersChanged signal");
+        }
+    }
+
+    /// Starts the task that sends `PropertiesChanged` for `Count` and `DoNotDisturb` on
+    /// the source's connection whenever either moved since the last one. Changes in quick
+    /// succession may be reported together. The task stops when the source is dropped or
+    /// no longer serves its interfaces.
+    fn start_property_notifier(&self) {
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        let Some(handle) = handle else {
+            warn!("no tokio runtime handle available; control properties never change");
+            return;
+        };
+        let mut changed = self.inner.state_changed.subscribe();
+        let mut last = (self.state().count, self.is_do_not_disturb());
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        handle.spawn(async move {
+            while changed.changed().await.is_ok() {
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                let Some(connection) = inner.dbus_connection.read().await.clone() else {
+                    break;
+                };
+                drop(inner);
+                let Ok(control) = connection
+                    .object_server()
+                    .interface::<_, ControlInterface>(CONTROL_PATH)
+                    .await
+                else {
+                    break;
+                };
+                let iface = control.get().await;
+                let now = (iface.source.state().count, iface.source.is_do_not_disturb());
+                let emitter = control.signal_emitter();
+                let mut emitted = Ok(());
+                if now.0 != last.0 {
+                    emitted = emitted.and(iface.count_changed(emitter).await);
+                }
+                if now.1 != last.1 {
+                    emitted = emitted.and(iface.do_not_disturb_changed(emitter).await);
+                }
+                if let Err(err) = emitted {
+                    iface.source.record_failed_signal();
+                    debug!(?err, "failed to emit PropertiesChanged");
+                }
+                last = now;
+            }
+        });
+    }
+
+    fn record_failed_signal(&self) {
+        self.inner
+            .stats
+            .lock()
+            .expect("stats mutex poisoned")
+            .failed_signal_emissions += 1;
+    }
+
+    fn record_dropped_events(&self, dropped: u64) {
+        self.inner
+            .stats
+            .lock()
+            .expect("stats mutex poisoned")
+            .dropped_events += dropped;
+    }
+
+    /// Counts events lost to a full channel and warns, at most once per limiter window.
+    fn report_overflow(&self, dropped: u64) {
+        self.record_dropped_events(dropped);
+        if let Some(suppressed) = self
+            .inner
+            .warn_limits
+            .event_queue_full
+            .check(Instant::now())
+        {
+            warn!(
+                policy = ?self.inner.cfg.overflow_policy,
+                "event queue full; dropping notification event{suppressed}"
+            );
+        }
+    }
+
+    /// Starts the task that moves held events into the channel as the consumer makes
+    /// room, waiting out [`OverflowPolicy::Block`] timeouts. It stops once nothing is held
+    /// and no drop is left to report.
+    fn start_event_flusher(&self) {
+        let handle = self
+            .inner
+            .runtime_handle
+            .clone()
+            .or_else(|| Handle::try_current().ok());
+        let Some(handle) = handle else {
+            // Held events go out with the next event instead.
+            self.inner
+                .backlog
+                .lock()
+                .expect("event backlog poisoned")
+                .settle_flusher();
+            warn!("no tokio runtime handle available; held events wait for the next event");
+            return;
+        };
+        let sender = self.inner.sender.clone();
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        let timeout = match self.inner.cfg.overflow_policy {
+            OverflowPolicy::Block { timeout } => Some(timeout),
+            _ => None,
+        };
+        handle.spawn(async move {
+            while let Some(only_notice_left) = inner.upgrade().map(|inner| {
+                inner
+                    .backlog
+                    .lock()
+                    .expect("event backlog poisoned")
+                    .only_notice_left()
+            }) {
+                let reserved = match timeout.filter(|_| !only_notice_left) {
+                    Some(timeout) => tokio::time::timeout(timeout, sender.reserve()).await,
+                    None => Ok(sender.reserve().await),
+                };
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                let source = WispSource { inner };
+                let mut backlog = source.inner.backlog.lock().expect("event backlog poisoned");
+                match reserved {
+                    Ok(Ok(permit)) => {
+                        if !backlog.send_next(permit) {
+                            break;
+                        }
+                    }
+                    // The consumer is gone; the next `send_event` reports it.
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        if backlog.drop_oldest() {
+                            drop(backlog);
+                            source.report_overflow(1);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn alloc_id(&self) -> u32 {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        debug!(id, "next_id advanced");
+        id
+    }
+
+    fn send_event(&self, event: NotificationEvent) -> Result<(), SourceError> {
+        debug!(event = ?LoggableEvent(&event), "sending notification event");
+        // Counted before queueing so the counts follow the store even when the UI lags.
+        let counters_changed = self
+            .inner
+            .counters
+            .lock()
+            .expect("counters mutex poisoned")
+            .observe(&event);
+        if counters_changed {
+            self.schedule_counters_changed();
+        }
+        if matches!(
+            event,
+            NotificationEvent::Received { .. }
+                | NotificationEvent::Closed { .. }
+                | NotificationEvent::DndChanged { .. }
+        ) {
+            self.inner.state_changed.send_replace(());
+        }
+        if self.inner.broadcast.receiver_count() > 0 {
+            // Fails only when the last subscriber left since the check.
+            let _ = self.inner.broadcast.send(event.clone());
+        }
+        let pushed = self
+            .inner
+            .backlog
+            .lock()
+            .expect("event backlog poisoned")
+            .push(
+                &self.inner.sender,
+                self.inner.cfg.overflow_policy,
+                self.inner.cfg.channel_capacity,
+                event,
+            );
+        match pushed {
+            Ok(pushed) => {
+                if pushed.dropped > 0 {
+                    self.report_overflow(pushed.dropped);
+                }
+                if pushed.start_flusher {
+                    self.start_event_flusher();
+                }
+                Ok(())
+            }
+            Err(overflow::Closed) => {
+                self.record_dropped_events(1);
+                if let Some(suppressed) = self
+                    .inner
+                    .warn_limits
+                    .event_receiver_dropped
+                    .check(Instant::now())
+                {
+                    warn!("event receiver dropped{suppressed}");
+                }
+                Err(SourceError::EventChannelClosed)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NotificationsInterface {
+    source: WispSource,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl NotificationsInterface {
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, zvariant::OwnedValue>,
+        expire_timeout: i32,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<ResponseDispatchNotifier<u32>> {
+        info!(app = %app_name, summary = %Loggable(&summary), replaces_id, expire_timeout, action_pairs = actions.len() / 2, "dbus Notify called");
+        let (urgency, mut parsed_hints) = self.source.parse_incoming_hints(&app_name, &hints);
+        let pid = match sender_pid_hint(&hints) {
+            Some(pid) => Some(pid),
+            None => sender_credentials_pid(connection, &header).await,
+        };
+        parsed_hints.sandbox_app_id = pid.and_then(|pid| self.source.sandbox_app_id(pid));
+        let notification = Notification {
+            app_name,
+            app_icon,
+            summary,
+            body,
+            urgency,
+            timeout_ms: expire_timeout,
+            actions: parse_actions(actions),
+            hints: parsed_hints,
+        };
+
+        let (id, expiry) = self
+            .source
+            .store_notification(
+                notification,
+                replaces_id,
+                header.sender().map(|sender| sender.to_string()),
+            )
             .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))?;
+
+        // Hold expiry until the reply carrying `id` is on the wire so the client always
+        // learns the id before any `NotificationClosed` for it.
+        let (reply, reply_sent) = ResponseDispatchNotifier::new(id);
+        self.source.arm_expiry_after(expiry, reply_sent);
+
+        info!(id, "dbus Notify handled");
+        Ok(reply)
+    }
+
+    async fn close_notification(&self, id: u32) -> zbus::fdo::Result<()> {
+        info!(id, "dbus CloseNotification called");
+        // The spec makes closing an unknown id a no-op, so only backend failures surface.
+        let closed = match self.source.close(id, CloseReason::ClosedByCall).await {
+            Ok(()) => true,
+            Err(CommandError::NotFound) => false,
+            Err(err) => return Err(zbus::fdo::Error::Failed(err.to_string())),
+        };
         info!(id, closed, "dbus CloseNotification handled");
         Ok(())
     }
@@ -553,795 +2463,3100 @@ impl NotificationsInterface {
         id: u32,
         action_key: &str,
     ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_replied(
+        emitter: SignalEmitter<'_>,
+        id: u32,
+        text: &str,
+    ) -> zbus::Result<()>;
 }
 
-fn parse_actions(flat_actions: Vec<String>) -> Vec<NotificationAction> {
-    flat_actions
-        .chunks_exact(2)
-        .map(|chunk| NotificationAction {
-            key: chunk[0].clone(),
-            label: chunk[1].clone(),
-        })
-        .collect()
+/// [`CommandError`] as sent over the bus; names must match [`CommandError::dbus_name`].
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.wispd.Control1.Error")]
+enum ControlError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    NotFound(String),
+    UnknownAction(String),
+    Backend(String),
 }
 
-fn parse_hints(hints: &HashMap<String, zvariant::OwnedValue>) -> (Urgency, NotificationHints) {
-    let urgency = hints
-        .get("urgency")
-        .and_then(|raw| u8::try_from(raw).ok())
-        .map(|value| match value {
-            0 => Urgency::Low,
-            2 => Urgency::Critical,
-            _ => Urgency::Normal,
-        })
-        .unwrap_or(Urgency::Normal);
+impl From<CommandError> for ControlError {
+    fn from(err: CommandError) -> Self {
+        match err {
+            CommandError::NotFound => Self::NotFound(err.to_string()),
+            CommandError::UnknownAction => Self::UnknownAction(err.to_string()),
+            CommandError::Backend(message) => Self::Backend(message),
+        }
+    }
+}
 
-    let category = hints
-        .get("category")
-        .and_then(|raw| <&str>::try_from(raw).ok())
-        .map(ToOwned::to_owned);
-    let desktop_entry = hints
-        .get("desktop-entry")
-        .and_then(|raw| <&str>::try_from(raw).ok())
-        .map(ToOwned::to_owned);
-    let transient = hints
-        .get("transient")
-        .and_then(|raw| bool::try_from(raw).ok());
+/// wispd-specific methods that have no freedesktop equivalent.
+#[derive(Debug, Clone)]
+struct ControlInterface {
+    source: WispSource,
+}
 
-    let extra = hints
-        .iter()
-        .filter(|(key, _)| {
-            key.as_str() != "urgency"
-                && key.as_str() != "category"
-                && key.as_str() != "desktop-entry"
-                && key.as_str() != "transient"
-        })
-        .map(|(key, value)| (key.clone(), format_hint_value(key, value)))
-        .collect();
+#[zbus::interface(name = "org.wispd.Control1")]
+impl ControlInterface {
+    /// Invokes `action_key` on notification `id` as if the user had picked it. Returns
+    /// `true` (kept for older callers); failures are `NotFound`, `UnknownAction` or
+    /// `Backend` under `org.wispd.Control1.Error`.
+    async fn invoke_action(&self, id: u32, action_key: &str) -> Result<bool, ControlError> {
+        info!(id, action_key, "dbus InvokeAction called");
+        self.source.invoke_action(id, action_key).await?;
+        Ok(true)
+    }
+
+    /// Store summary as `a{sv}`: `count` and `pinned` (both `u`), plus do-not-disturb as
+    /// `dnd`, `dnd_scheduled` (a schedule window is active) and `dnd_override` (a manual
+    /// toggle beats the schedule), all `b`.
+    async fn get_state(&self) -> HashMap<String, zvariant::OwnedValue> {
+        let state = self.source.state();
+        let dnd = self.source.do_not_disturb();
+        HashMap::from([
+            ("count".to_string(), zvariant::OwnedValue::from(state.count)),
+            (
+                "pinned".to_string(),
+                zvariant::OwnedValue::from(state.pinned),
+            ),
+            ("dnd".to_string(), zvariant::OwnedValue::from(dnd.enabled)),
+            (
+                "dnd_scheduled".to_string(),
+                zvariant::OwnedValue::from(dnd.scheduled),
+            ),
+            (
+                "dnd_override".to_string(),
+                zvariant::OwnedValue::from(dnd.overridden),
+            ),
+        ])
+    }
+
+    /// The manual do-not-disturb switch; it holds until the schedule's next boundary.
+    async fn set_do_not_disturb(&self, enabled: bool) {
+        info!(enabled, "dbus SetDoNotDisturb called");
+        self.source.set_do_not_disturb(enabled);
+    }
+
+    /// Whether do-not-disturb is on, manually or by schedule.
+    async fn get_do_not_disturb(&self) -> bool {
+        self.source.is_do_not_disturb()
+    }
+
+    /// Stored notifications, as `GetState`'s `count`; `PropertiesChanged` follows changes.
+    #[zbus(property)]
+    async fn count(&self) -> u32 {
+        self.source.state().count
+    }
+
+    /// `GetDoNotDisturb` as a property; `PropertiesChanged` follows changes.
+    #[zbus(property)]
+    async fn do_not_disturb(&self) -> bool {
+        self.source.is_do_not_disturb()
+    }
+
+    /// Closes every stored notification with the freedesktop reason code `reason` (1
+    /// expired, 2 dismissed, 3 closed by call, anything else undefined) and signals
+    /// `NotificationClosed` for each. Returns the closed ids in ascending order.
+    async fn close_all(&self, reason: u32) -> Result<Vec<u32>, ControlError> {
+        info!(reason, "dbus CloseAll called");
+        self.source
+            .close_all(close_reason_from_code(reason))
+            .await
+            .map_err(|err| CommandError::Backend(err.to_string()).into())
+    }
+
+    /// Stored notifications as `a(ua{sv})`, sorted by id: `app_name`, `app_icon`,
+    /// `summary`, `body` (`s`), `urgency` (`y`, 0 low to 2 critical), `expire_timeout`
+    /// (`i`, as sent), `actions` (`as`, alternating key and label), plus `category` (`s`)
+    /// when set and `remaining_ms` (`t`) when it expires.
+    async fn list_notifications(&self) -> Vec<(u32, HashMap<String, zvariant::OwnedValue>)> {
+        self.source
+            .snapshot_detailed()
+            .into_iter()
+            .map(wire_notification)
+            .collect()
+    }
+
+    /// Per-app counts as `a{s(uuu)}`: unseen since `MarkSeen`, currently stored, and
+    /// closed since startup.
+    async fn get_app_counters(&self) -> HashMap<String, (u32, u32, u32)> {
+        wire_counters(self.source.app_counters())
+    }
+
+    /// Source counters since startup as `a{sv}`: `oversized_hints`, `dropped_events`,
+    /// `failed_signal_emissions` and `rate_limited` (all `t`), plus
+    /// `oversized_hints_by_app` (`a{st}`) to find the app sending oversized hints.
+    async fn get_stats(&self) -> HashMap<String, zvariant::OwnedValue> {
+        wire_stats(self.source.stats())
+    }
+
+    /// Zeroes `app`'s unseen count; unknown apps are a no-op.
+    async fn mark_seen(&self, app: &str) {
+        info!(app, "dbus MarkSeen called");
+        self.source.mark_seen(app);
+    }
+
+    /// Shows closed notification `index_or_id` again (its id, or else its position in
+    /// the history, 0 = newest) as a local popup that is not stored. Its actions work
+    /// only while the original sender is still on the bus. Returns the original id;
+    /// `NotFound` when there is no such entry.
+    async fn show_historic(
+        &self,
+        index_or_id: u32,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> Result<u32, ControlError> {
+        info!(index_or_id, "dbus ShowHistoric called");
+        let entry = self
+            .source
+            .historic(index_or_id)
+            .ok_or(CommandError::NotFound)?;
+        let connected = sender_connected(connection, entry.sender.as_deref()).await;
+        Ok(self.source.show_historic(entry.id, connected)?)
+    }
+
+    /// Stars (`true`) or unstars history entry `index_or_id`, chosen as for
+    /// `ShowHistoric`; starred entries outlive the history TTL. Returns the entry's id;
+    /// `NotFound` when there is no such entry.
+    async fn star_historic(&self, index_or_id: u32, starred: bool) -> Result<u32, ControlError> {
+        info!(index_or_id, starred, "dbus StarHistoric called");
+        Ok(self.source.set_history_starred(index_or_id, starred)?)
+    }
+
+    /// Deletes the history entries with these ids, starred ones too, from memory and
+    /// the history file. Unknown ids are skipped; returns how many were deleted.
+    async fn remove_historic(&self, ids: Vec<u32>) -> u32 {
+        info!(?ids, "dbus RemoveHistoric called");
+        u32::try_from(self.source.remove_history(&ids)).unwrap_or(u32::MAX)
+    }
+
+    /// Deletes the whole history; returns how many entries there were.
+    async fn clear_history(&self) -> u32 {
+        info!("dbus ClearHistory called");
+        u32::try_from(self.source.clear_history()).unwrap_or(u32::MAX)
+    }
+
+    /// Sent at most once per [`COUNTERS_DEBOUNCE`] with the full `GetAppCounters` map.
+    #[zbus(signal)]
+    async fn counters_changed(
+        emitter: SignalEmitter<'_>,
+        counters: HashMap<String, (u32, u32, u32)>,
+    ) -> zbus::Result<()>;
+
+    /// Sent when do-not-disturb turns on or off, manually or by schedule.
+    #[zbus(signal)]
+    async fn dnd_changed(emitter: SignalEmitter<'_>, enabled: bool) -> zbus::Result<()>;
+
+    /// Sent each time the UI escalates a critical notification nobody acknowledged;
+    /// `count` starts at 1.
+    #[zbus(signal)]
+    async fn escalated(
+        emitter: SignalEmitter<'_>,
+        id: u32,
+        app_name: &str,
+        summary: &str,
+        body: &str,
+        count: u32,
+    ) -> zbus::Result<()>;
+}
+
+fn wire_counters(counters: BTreeMap<String, AppCounters>) -> HashMap<String, (u32, u32, u32)> {
+    counters
+        .into_iter()
+        .map(|(app, counters)| (app, counters.as_tuple()))
+        .collect()
+}
+
+fn wire_stats(stats: SourceStats) -> HashMap<String, zvariant::OwnedValue> {
+    HashMap::from([
+        (
+            "oversized_hints".to_string(),
+            zvariant::OwnedValue::from(stats.oversized_hints),
+        ),
+        (
+            "oversized_hints_by_app".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(stats.oversized_hints_by_app))
+                .expect("a string to integer map carries no fds"),
+        ),
+        (
+            "dropped_events".to_string(),
+            zvariant::OwnedValue::from(stats.dropped_events),
+        ),
+        (
+            "failed_signal_emissions".to_string(),
+            zvariant::OwnedValue::from(stats.failed_signal_emissions),
+        ),
+        (
+            "rate_limited".to_string(),
+            zvariant::OwnedValue::from(stats.rate_limited),
+        ),
+    ])
+}
+
+/// One `ListNotifications` entry.
+fn wire_notification(entry: SnapshotEntry) -> (u32, HashMap<String, zvariant::OwnedValue>) {
+    let text = |text: String| zvariant::OwnedValue::from(zvariant::Str::from(text));
+    let notification = entry.notification;
+    let urgency: u8 = match notification.urgency {
+        Urgency::Low => 0,
+        Urgency::Normal => 1,
+        Urgency::Critical => 2,
+    };
+    let actions: Vec<String> = notification
+        .actions
+        .into_iter()
+        .flat_map(|action| [action.key, action.label])
+        .collect();
+    let mut fields = HashMap::from([
+        ("app_name".to_string(), text(notification.app_name)),
+        ("app_icon".to_string(), text(notification.app_icon)),
+        ("summary".to_string(), text(notification.summary)),
+        ("body".to_string(), text(notification.body)),
+        ("urgency".to_string(), zvariant::OwnedValue::from(urgency)),
+        (
+            "expire_timeout".to_string(),
+            zvariant::OwnedValue::from(notification.timeout_ms),
+        ),
+        (
+            "actions".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(actions))
+                .expect("a string array carries no fds"),
+        ),
+    ]);
+    if let Some(category) = notification.hints.category {
+        fields.insert("category".to_string(), text(category));
+    }
+    if let Some(remaining) = entry.remaining {
+        let remaining_ms = u64::try_from(remaining.as_millis()).unwrap_or(u64::MAX);
+        fields.insert(
+            "remaining_ms".to_string(),
+            zvariant::OwnedValue::from(remaining_ms),
+        );
+    }
+    (entry.id, fields)
+}
+
+fn parse_actions(flat_actions: Vec<String>) -> Vec<NotificationAction> {
+    flat_actions
+        .chunks_exact(2)
+        .map(|chunk| NotificationAction {
+            key: chunk[0].clone(),
+            label: chunk[1].clone(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HintLimits {
+    max_value_len: usize,
+    max_total_bytes: usize,
+    /// Whether unparsed hints are converted into `extra` at all.
+    keep_extra: bool,
+}
+
+/// Reads the optional `sender-pid` hint some clients (e.g. libnotify) attach.
+fn sender_pid_hint(hints: &HashMap<String, zvariant::OwnedValue>) -> Option<u32> {
+    let raw = hints.get("sender-pid")?;
+    let pid = i64::try_from(raw)
+        .ok()
+        .or_else(|| u32::try_from(raw).ok().map(i64::from))
+        .or_else(|| i32::try_from(raw).ok().map(i64::from))
+        .or_else(|| u64::try_from(raw).ok().and_then(|v| i64::try_from(v).ok()))?;
+    u32::try_from(pid).ok().filter(|pid| *pid > 0)
+}
+
+/// Asks the bus daemon for the pid behind the message sender. Failures are logged at
+/// debug and yield `None`; identification is best effort.
+async fn sender_credentials_pid(
+    connection: &zbus::Connection,
+    header: &zbus::message::Header<'_>,
+) -> Option<u32> {
+    let sender = header.sender()?;
+    let proxy = zbus::fdo::DBusProxy::new(connection).await.ok()?;
+    match proxy
+        .get_connection_unix_process_id(BusName::from(sender.clone()))
+        .await
+    {
+        Ok(pid) => Some(pid),
+        Err(err) => {
+            debug!(sender = %sender, %err, "could not resolve sender pid");
+            None
+        }
+    }
+}
+
+/// Whether `sender` still owns its unique name, so `ActionInvoked` would reach it.
+/// Peer-to-peer senders have no name and count as gone, as do failed lookups.
+async fn sender_connected(connection: &zbus::Connection, sender: Option<&str>) -> bool {
+    let Some(name) = sender.and_then(|sender| BusName::try_from(sender).ok()) else {
+        return false;
+    };
+    let Ok(proxy) = zbus::fdo::DBusProxy::new(connection).await else {
+        return false;
+    };
+    match proxy.name_has_owner(name).await {
+        Ok(owned) => owned,
+        Err(err) => {
+            debug!(sender, %err, "could not check whether the sender is connected");
+            false
+        }
+    }
+}
+
+/// Parses raw `Notify` hints with the default size limits, for callers that see hints
+/// outside the server (`wispd --mirror`, benchmarks).
+pub fn parse_raw_hints(
+    hints: &HashMap<String, zvariant::OwnedValue>,
+) -> (Urgency, NotificationHints) {
+    parse_raw_hints_with(hints, true)
+}
+
+/// [`parse_raw_hints`], leaving [`NotificationHints::extra`] empty unless
+/// `keep_extra_hints` (see [`SourceConfig::keep_extra_hints`]).
+pub fn parse_raw_hints_with(
+    hints: &HashMap<String, zvariant::OwnedValue>,
+    keep_extra_hints: bool,
+) -> (Urgency, NotificationHints) {
+    let limits = HintLimits {
+        max_value_len: DEFAULT_MAX_HINT_VALUE_LEN,
+        max_total_bytes: DEFAULT_MAX_TOTAL_HINTS_BYTES,
+        keep_extra: keep_extra_hints,
+    };
+    let (urgency, hints, _) = parse_hints(hints, limits);
+    (urgency, hints)
+}
+
+/// Parses known hints and converts the rest into `extra` (when `limits.keep_extra`),
+/// returning how many extra values were truncated or dropped to stay within `limits`.
+/// The map is walked once; recognized values are borrowed until they are stored.
+fn parse_hints(
+    hints: &HashMap<String, zvariant::OwnedValue>,
+    limits: HintLimits,
+) -> (Urgency, NotificationHints, u64) {
+    let mut urgency = Urgency::Normal;
+    let mut category = None;
+    let mut desktop_entry = None;
+    let mut transient = None;
+    let mut resident = None;
+    let mut action_icons = None;
+    let mut progress = None;
+    let mut x = None;
+    let mut y = None;
+    let mut display_app_name = None;
+    let mut urls = Vec::new();
+    let mut origin_name = None;
+    let mut sound_file = None;
+    let mut sound_name = None;
+    let mut suppress_sound = false;
+    // By spelling, oldest first: `image_data` (spec 1.1), `image-data` (1.2).
+    let mut images = [None; 2];
+    let mut image_paths = [None; 2];
+    let mut icon_data = None;
+    let mut unparsed = Vec::new();
+    for (key, raw) in hints {
+        match key.as_str() {
+            "urgency" => {
+                urgency = match u8::try_from(raw) {
+                    Ok(0) => Urgency::Low,
+                    Ok(2) => Urgency::Critical,
+                    _ => Urgency::Normal,
+                };
+            }
+            "category" => category = <&str>::try_from(raw).ok(),
+            "desktop-entry" => desktop_entry = <&str>::try_from(raw).ok(),
+            "transient" => transient = bool::try_from(raw).ok(),
+            "resident" => resident = bool::try_from(raw).ok(),
+            "action-icons" => action_icons = bool::try_from(raw).ok(),
+            "value" => progress = int_hint(raw).map(saturating_i32),
+            "x" => x = int_hint(raw).and_then(|value| i32::try_from(value).ok()),
+            "y" => y = int_hint(raw).and_then(|value| i32::try_from(value).ok()),
+            "x-kde-display-appname" => display_app_name = non_empty_str(raw),
+            "x-kde-urls" => urls = str_array(raw),
+            "x-kde-origin-name" => origin_name = non_empty_str(raw),
+            "sound-file" => sound_file = non_empty_str(raw),
+            "sound-name" => sound_name = non_empty_str(raw),
+            "suppress-sound" => suppress_sound = bool::try_from(raw).unwrap_or(false),
+            "image_data" => images[0] = Some((key.as_str(), raw)),
+            "image-data" => images[1] = Some((key.as_str(), raw)),
+            "image_path" => image_paths[0] = non_empty_str(raw),
+            "image-path" => image_paths[1] = non_empty_str(raw),
+            "icon_data" => icon_data = Some((key.as_str(), raw)),
+            _ if limits.keep_extra => unparsed.push((key.as_str(), raw)),
+            _ => {}
+        }
+    }
+    // The newest spelling that decodes wins; the others are noted in `extra`.
+    let mut image = None;
+    let mut legacy_icon = None;
+    let candidates = images
+        .into_iter()
+        .rev()
+        .flatten()
+        .map(|candidate| (false, candidate))
+        .chain(icon_data.map(|candidate| (true, candidate)));
+    for (legacy, (key, raw)) in candidates {
+        let slot = if legacy { &mut legacy_icon } else { &mut image };
+        if slot.is_none() {
+            match image_data(raw) {
+                Ok(decoded) => {
+                    *slot = Some(decoded);
+                    continue;
+                }
+                Err(reason) => debug!(key, reason, "ignoring malformed image hint"),
+            }
+        }
+        if limits.keep_extra {
+            unparsed.push((key, raw));
+        }
+    }
+    // Sorted so the budget cut-off is deterministic.
+    unparsed.sort_unstable_by_key(|(key, _)| *key);
+
+    let mut oversized = 0;
+    let mut budget = limits.max_total_bytes;
+    let mut extra = HashMap::with_capacity(unparsed.len());
+    for (key, raw) in unparsed {
+        let (mut value, too_long) = extra_hint(key, raw, limits.max_value_len);
+        if too_long {
+            oversized += 1;
+        }
+
+        let cost = key.len() + hint_len(&value);
+        if cost > budget {
+            if !too_long {
+                oversized += 1;
+            }
+            value = HintValue::Omitted {
+                signature: signature_of(raw),
+                reason: format!("{} bytes over hint budget", cost - budget),
+            };
+            budget = 0;
+        } else {
+            budget -= cost;
+        }
+        extra.insert(key.to_owned(), value);
+    }
+
+    let mut hints = NotificationHints::default();
+    hints.category = category.map(ToOwned::to_owned);
+    hints.desktop_entry = desktop_entry.map(ToOwned::to_owned);
+    hints.display_app_name = display_app_name.map(ToOwned::to_owned);
+    hints.urls = urls;
+    hints.origin_name = origin_name.map(ToOwned::to_owned);
+    hints.sound = SoundHint {
+        file: sound_file.map(PathBuf::from),
+        name: sound_name.map(ToOwned::to_owned),
+        suppress: suppress_sound,
+    };
+    hints.transient = transient;
+    hints.resident = resident;
+    hints.action_icons = action_icons;
+    hints.progress = progress;
+    hints.position = x.zip(y);
+    hints.image = image;
+    hints.image_path = image_paths
+        .into_iter()
+        .rev()
+        .flatten()
+        .next()
+        .map(ToOwned::to_owned);
+    hints.icon_data = legacy_icon;
+    hints.extra = extra;
+    (urgency, hints, oversized)
+}
+
+/// A string hint, unless it is of another type or blank.
+fn non_empty_str(raw: &zvariant::OwnedValue) -> Option<&str> {
+    <&str>::try_from(raw)
+        .ok()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// An integer hint of any integer type, saturated to `i64`.
+fn int_hint(raw: &zvariant::OwnedValue) -> Option<i64> {
+    match hint_value(raw) {
+        HintValue::Int64(value) => Some(value),
+        HintValue::UInt64(value) => Some(i64::try_from(value).unwrap_or(i64::MAX)),
+        _ => None,
+    }
+}
+
+fn saturating_i32(value: i64) -> i32 {
+    value.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+/// The non-blank strings of an `as` hint; empty for any other type.
+fn str_array(raw: &zvariant::OwnedValue) -> Vec<String> {
+    let zvariant::Value::Array(array) = &**raw else {
+        return Vec::new();
+    };
+    array
+        .iter()
+        .filter_map(|item| <&str>::try_from(item).ok())
+        .filter(|url| !url.trim().is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Decodes an `(iiibiiay)` image hint, rejecting payloads whose pixels do not fit their
+/// declared size.
+fn image_data(raw: &zvariant::OwnedValue) -> Result<ImageData, &'static str> {
+    let zvariant::Value::Structure(structure) = &**raw else {
+        return Err("not a structure");
+    };
+    let [
+        width,
+        height,
+        rowstride,
+        has_alpha,
+        bits_per_sample,
+        channels,
+        data,
+    ] = structure.fields()
+    else {
+        return Err("wrong number of fields");
+    };
+    let dimension = |value: &zvariant::Value<'_>| {
+        i32::try_from(value)
+            .ok()
+            .and_then(|value| u32::try_from(value).ok())
+            .filter(|value| *value > 0)
+            .ok_or("dimensions must be positive integers")
+    };
+    let (width, height, rowstride) = (dimension(width)?, dimension(height)?, dimension(rowstride)?);
+    let has_alpha = bool::try_from(has_alpha).map_err(|_| "has_alpha is not a boolean")?;
+    if i32::try_from(bits_per_sample).ok() != Some(8) {
+        return Err("only 8 bits per sample are supported");
+    }
+    let channels = match (i32::try_from(channels), has_alpha) {
+        (Ok(3), false) => 3,
+        (Ok(4), true) => 4,
+        _ => return Err("channels do not match has_alpha"),
+    };
+    let zvariant::Value::Array(data) = data else {
+        return Err("data is not a byte array");
+    };
+    let data = data
+        .iter()
+        .map(u8::try_from)
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| "data is not a byte array")?;
+
+    // Every row but the last is padded to `rowstride`.
+    let row = u64::from(width) * u64::from(channels);
+    let stride = u64::from(rowstride);
+    let len = data.len() as u64;
+    if stride < row {
+        return Err("rowstride is shorter than a row");
+    }
+    if len < stride * u64::from(height - 1) + row || len > stride * u64::from(height) {
+        return Err("data length does not match rowstride and height");
+    }
+    Ok(ImageData {
+        width,
+        height,
+        rowstride,
+        has_alpha,
+        channels,
+        data,
+    })
+}
+
+/// Converts an unparsed hint for `extra`, returning whether it was left out for being
+/// longer than `max_len`. Image payloads are never kept.
+fn extra_hint(key: &str, raw: &zvariant::OwnedValue, max_len: usize) -> (HintValue, bool) {
+    if matches!(key, "image-data" | "image_data" | "icon_data") {
+        let omitted = HintValue::Omitted {
+            signature: signature_of(raw),
+            reason: "image payload".to_string(),
+        };
+        return (omitted, false);
+    }
+
+    let value = hint_value(raw);
+    let len = hint_len(&value);
+    if len <= max_len {
+        return (value, false);
+    }
+    let omitted = HintValue::Omitted {
+        signature: signature_of(raw),
+        reason: format!("{len} bytes over the {max_len} byte limit"),
+    };
+    (omitted, true)
+}
+
+/// The typed form of a hint value. Senders such as `notify-send` wrap values in
+/// another variant; those are unwrapped first.
+fn hint_value(value: &zvariant::Value<'_>) -> HintValue {
+    use zvariant::Value;
+
+    match value {
+        Value::Value(inner) => hint_value(inner),
+        Value::Bool(value) => HintValue::Bool(*value),
+        Value::I16(value) => HintValue::Int64(i64::from(*value)),
+        Value::I32(value) => HintValue::Int64(i64::from(*value)),
+        Value::I64(value) => HintValue::Int64(*value),
+        Value::U8(value) => HintValue::UInt64(u64::from(*value)),
+        Value::U16(value) => HintValue::UInt64(u64::from(*value)),
+        Value::U32(value) => HintValue::UInt64(u64::from(*value)),
+        Value::U64(value) => HintValue::UInt64(*value),
+        // JSON has no NaN or infinity, so those stay text.
+        Value::F64(value) if value.is_finite() => HintValue::Double(*value),
+        Value::Str(value) => HintValue::String(value.to_string()),
+        Value::ObjectPath(value) => HintValue::String(value.to_string()),
+        Value::Signature(value) => HintValue::String(value.to_string()),
+        Value::Array(array) if value.value_signature().to_string() == "ay" => HintValue::Bytes(
+            array
+                .iter()
+                .filter_map(|byte| u8::try_from(byte).ok())
+                .collect(),
+        ),
+        other => HintValue::Other {
+            signature: other.value_signature().to_string(),
+            text: other.to_string(),
+        },
+    }
+}
+
+/// The signature of the value inside any variant wrapping.
+fn signature_of(value: &zvariant::Value<'_>) -> String {
+    match value {
+        zvariant::Value::Value(inner) => signature_of(inner),
+        other => other.value_signature().to_string(),
+    }
+}
+
+/// Bytes a hint value counts against the size limits.
+fn hint_len(value: &HintValue) -> usize {
+    match value {
+        HintValue::Bool(_) => 1,
+        HintValue::Int64(_) | HintValue::UInt64(_) | HintValue::Double(_) => 8,
+        HintValue::String(text)
+        | HintValue::Other { text, .. }
+        | HintValue::Omitted { reason: text, .. } => text.len(),
+        HintValue::Bytes(bytes) => bytes.len(),
+    }
+}
+
+/// `capabilities` plus `action-icons` when `action_icons` is on and actions are advertised.
+fn with_action_icons(mut capabilities: Vec<String>, action_icons: bool) -> Vec<String> {
+    let has = |capabilities: &[String], name: &str| capabilities.iter().any(|c| c == name);
+    if action_icons && has(&capabilities, "actions") && !has(&capabilities, "action-icons") {
+        capabilities.push("action-icons".to_string());
+    }
+    capabilities
+}
+
+fn close_reason_code(reason: CloseReason) -> u32 {
+    match reason {
+        CloseReason::Expired => 1,
+        CloseReason::Dismissed => 2,
+        CloseReason::ClosedByCall => 3,
+        _ => 4,
+    }
+}
+
+fn close_reason_from_code(code: u32) -> CloseReason {
+    match code {
+        1 => CloseReason::Expired,
+        2 => CloseReason::Dismissed,
+        3 => CloseReason::ClosedByCall,
+        _ => CloseReason::Undefined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{PeerBus, TestSource};
+    use futures_util::StreamExt;
+
+    fn test_hint_limits() -> HintLimits {
+        HintLimits {
+            max_value_len: DEFAULT_MAX_HINT_VALUE_LEN,
+            max_total_bytes: DEFAULT_MAX_TOTAL_HINTS_BYTES,
+            keep_extra: true,
+        }
+    }
+
+    fn test_notification(summary: &str) -> Notification {
+        Notification {
+            app_name: "test".into(),
+            app_icon: String::new(),
+            summary: summary.into(),
+            body: String::new(),
+            urgency: Default::default(),
+            timeout_ms: -1,
+            actions: vec![],
+            hints: NotificationHints::default(),
+        }
+    }
+
+    fn test_notification_with_action(summary: &str, action_key: &str) -> Notification {
+        Notification {
+            app_name: "test".into(),
+            app_icon: String::new(),
+            summary: summary.into(),
+            body: String::new(),
+            urgency: Default::default(),
+            timeout_ms: -1,
+            actions: vec![NotificationAction {
+                key: action_key.to_string(),
+                label: "Test Action".to_string(),
+            }],
+            hints: NotificationHints::default(),
+        }
+    }
+
+    #[test]
+    fn image_hints_are_omitted_from_extra() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert("image-data".to_string(), true.into());
+        raw_hints.insert("x-quiet".to_string(), false.into());
+        raw_hints.insert(
+            "blob".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(vec![1_u8, 2, 3])).unwrap(),
+        );
+
+        let (_urgency, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+
+        assert_eq!(
+            hints.extra["image-data"],
+            HintValue::Omitted {
+                signature: "b".to_string(),
+                reason: "image payload".to_string(),
+            }
+        );
+        assert_eq!(hints.extra["blob"], HintValue::Bytes(vec![1, 2, 3]));
+        assert_eq!(hints.extra["x-quiet"], HintValue::Bool(false));
+    }
+
+    #[tokio::test]
+    async fn replacement_uses_same_id() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        let id = source.notify(test_notification("first"), 0).await.unwrap();
+        let _ = rx.recv().await;
+
+        let replaced_id = source
+            .notify(test_notification("second"), id)
+            .await
+            .unwrap();
+        assert_eq!(id, replaced_id);
+
+        match rx.recv().await.unwrap() {
+            NotificationEvent::Replaced { id: event_id, .. } => assert_eq!(event_id, id),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_replaces_id_allocates_fresh_id() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        let first_id = source.notify(test_notification("first"), 0).await.unwrap();
+        let _ = rx.recv().await;
+
+        let second_id = source
+            .notify(test_notification("second"), 999_999)
+            .await
+            .unwrap();
+        assert_ne!(first_id, second_id);
+
+        match rx.recv().await.unwrap() {
+            NotificationEvent::Received {
+                id, notification, ..
+            } => {
+                assert_eq!(id, second_id);
+                assert_eq!(notification.summary, "second");
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn notify_batch_stores_in_order_with_contiguous_ids() {
+        let (source, mut rx) = WispSource::new(SourceConfig {
+            default_timeout_ms: Some(20),
+            ..SourceConfig::default()
+        });
+        let existing = source.notify(test_notification("old"), 0).await.unwrap();
+        let _ = rx.recv().await;
+
+        let ids = source
+            .notify_batch(vec![
+                (test_notification("a"), 0),
+                (test_notification("replacement"), existing),
+                (test_notification("b"), 999_999),
+                (test_notification("c"), 0),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(ids, [existing + 1, existing, existing + 2, existing + 3]);
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        let batch_id = match events.first() {
+            Some(NotificationEvent::BatchStarted { batch_id }) => *batch_id,
+            other => panic!("batch not opened first: {other:?}"),
+        };
+        assert_eq!(
+            events.last(),
+            Some(&NotificationEvent::BatchEnded { batch_id })
+        );
+        let inner: Vec<(u32, &str)> = events[1..events.len() - 1]
+            .iter()
+            .map(|event| match event {
+                NotificationEvent::Received {
+                    id, notification, ..
+                } => (*id, notification.summary.as_str()),
+                NotificationEvent::Replaced { id, current, .. } => (*id, current.summary.as_str()),
+                other => panic!("unexpected event in batch: {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            inner,
+            [
+                (ids[0], "a"),
+                (existing, "replacement"),
+                (ids[2], "b"),
+                (ids[3], "c")
+            ]
+        );
+
+        assert!(source.notify_batch(Vec::new()).await.unwrap().is_empty());
+        assert!(rx.try_recv().is_err(), "an empty batch emits nothing");
+
+        // Under the paused clock, waiting runs time forward to each expiry.
+        let mut expired = Vec::new();
+        for _ in 0..4 {
+            match rx.recv().await {
+                Some(NotificationEvent::Closed { id, .. }) => expired.push(id),
+                other => panic!("expected an expiry: {other:?}"),
+            }
+        }
+        expired.sort_unstable();
+        assert_eq!(expired, [existing, ids[0], ids[2], ids[3]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_emits_closed_expired_event() {
+        let mut test = TestSource::with_config(SourceConfig {
+            default_timeout_ms: Some(20),
+            ..SourceConfig::default()
+        });
+
+        let id = test.notify(test_notification("expires")).await;
+        assert_eq!(test.expect_received().await.0, id);
+
+        test.advance(19).await;
+        test.expect_no_event().await;
+        test.advance(1).await;
+        test.expect_closed(id, CloseReason::Expired).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn negative_timeout_without_default_is_persistent() {
+        let mut test = TestSource::new();
+
+        let id = test.notify(test_notification("persistent")).await;
+        assert_eq!(test.expect_received().await.0, id);
+
+        test.advance(3_600_000).await;
+        test.expect_no_event().await;
+
+        let snapshot = test.source().snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].0, id);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_timeout_never_schedules_expiry() {
+        let mut test = TestSource::with_config(SourceConfig {
+            default_timeout_ms: Some(10),
+            ..SourceConfig::default()
+        });
+
+        let id = test
+            .notify(Notification {
+                timeout_ms: 0,
+                ..test_notification("persistent-zero")
+            })
+            .await;
+        assert_eq!(test.expect_received().await.0, id);
+
+        test.advance(3_600_000).await;
+        test.expect_no_event().await;
+
+        let snapshot = test.source().snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].0, id);
+    }
+
+    #[test]
+    fn timeout_clamp_bounds_timeouts_and_exempts_critical() {
+        let clamp = TimeoutClamp {
+            min_ms: Some(2_000),
+            max_ms: Some(60_000),
+            exempt_critical: true,
+        };
+
+        assert_eq!(clamp.apply(Some(500), false), Some(2_000));
+        assert_eq!(clamp.apply(Some(5_000), false), Some(5_000));
+        assert_eq!(clamp.apply(Some(1_800_000), false), Some(60_000));
+        assert_eq!(clamp.apply(None, false), Some(60_000));
+        assert_eq!(clamp.apply(None, true), None);
+        assert_eq!(clamp.apply(Some(1_800_000), true), Some(1_800_000));
+
+        let strict = TimeoutClamp {
+            exempt_critical: false,
+            ..clamp
+        };
+        assert_eq!(strict.apply(None, true), Some(60_000));
+        assert_eq!(TimeoutClamp::default().apply(None, false), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_timeout_clamp_expires_persistent_notifications_but_not_critical() {
+        let mut test = TestSource::with_config(SourceConfig {
+            clamp_timeout: TimeoutClamp {
+                min_ms: None,
+                max_ms: Some(20),
+                exempt_critical: true,
+            },
+            ..SourceConfig::default()
+        });
+
+        let trivial = test
+            .notify(Notification {
+                timeout_ms: 0,
+                ..test_notification("trivial")
+            })
+            .await;
+        let critical = test
+            .notify(Notification {
+                timeout_ms: 0,
+                urgency: Urgency::Critical,
+                ..test_notification("critical")
+            })
+            .await;
+        test.expect_received().await;
+        test.expect_received().await;
+
+        test.advance(20).await;
+        test.expect_closed(trivial, CloseReason::Expired).await;
+        test.advance(3_600_000).await;
+        test.expect_no_event().await;
+
+        let snapshot = test.source().snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].0, critical);
+    }
+
+    #[test]
+    fn timeout_rules_override_the_clamp_field_by_field() {
+        let clamp = TimeoutClamp {
+            min_ms: Some(2_000),
+            max_ms: Some(60_000),
+            exempt_critical: true,
+        };
+        let rules = [
+            TimeoutRule {
+                matcher: Matcher {
+                    app: Some("chatty".parse().unwrap()),
+                    ..Matcher::default()
+                },
+                max_ms: Some(5_000),
+                exempt_critical: Some(false),
+                ..TimeoutRule::default()
+            },
+            TimeoutRule {
+                min_ms: Some(1),
+                ..TimeoutRule::default()
+            },
+        ];
+        let chatty = Notification {
+            app_name: "Chatty".into(),
+            ..test_notification("hi")
+        };
+        assert_eq!(
+            clamp.for_notification(&rules, &chatty),
+            TimeoutClamp {
+                min_ms: Some(2_000),
+                max_ms: Some(5_000),
+                exempt_critical: false,
+            }
+        );
+        assert_eq!(
+            clamp.for_notification(&rules, &test_notification("other")),
+            TimeoutClamp {
+                min_ms: Some(1),
+                ..clamp
+            },
+            "the first matching rule applies"
+        );
+        assert_eq!(clamp.for_notification(&[], &chatty), clamp);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_rules_bound_the_source_expiry_of_matching_notifications() {
+        let chatty = TimeoutRule {
+            matcher: Matcher {
+                app: Some("chatty".parse().unwrap()),
+                ..Matcher::default()
+            },
+            max_ms: Some(20),
+            ..TimeoutRule::default()
+        };
+        let mut test = TestSource::with_config(SourceConfig {
+            timeout_rules: vec![chatty.clone()],
+            ..SourceConfig::default()
+        });
+        let persistent = |app_name: &str, summary: &str| Notification {
+            app_name: app_name.into(),
+            timeout_ms: 0,
+            ..test_notification(summary)
+        };
+
+        let trivial = test.notify(persistent("chatty", "lol")).await;
+        let kept = test.notify(persistent("test", "build done")).await;
+        match test.next_event().await {
+            NotificationEvent::Received { remaining_ms, .. } => {
+                assert_eq!(remaining_ms, Some(20), "reported with the rule's bound");
+            }
+            other => panic!("expected Received, got {other:?}"),
+        }
+        test.expect_received().await;
+
+        test.advance(20).await;
+        test.expect_closed(trivial, CloseReason::Expired).await;
+        test.advance(3_600_000).await;
+        test.expect_no_event().await;
+
+        test.source().set_timeout_rules(vec![TimeoutRule {
+            matcher: Matcher {
+                app: Some("test".parse().unwrap()),
+                ..Matcher::default()
+            },
+            ..chatty
+        }]);
+        let replaced = test
+            .replace(persistent("test", "build done again"), kept)
+            .await;
+        assert_eq!(replaced, kept);
+        test.expect_replaced(kept).await;
+        test.advance(20).await;
+        test.expect_closed(kept, CloseReason::Expired).await;
+    }
+
+    /// The repeat count of the next event, which must replace `id`.
+    async fn expect_repeat(test: &mut TestSource, id: u32) -> u32 {
+        match test.next_event().await {
+            NotificationEvent::Replaced {
+                id: event_id,
+                repeat_count,
+                ..
+            } if event_id == id => repeat_count,
+            other => panic!("expected Replaced for {id}, got {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dedup_folds_identical_copies_and_counts_the_repeats() {
+        let mut test = TestSource::with_config(SourceConfig {
+            dedup: Some(Dedup {
+                window: Duration::from_secs(30),
+                match_body: true,
+            }),
+            ..SourceConfig::default()
+        });
+        let with_body = |body: &str| Notification {
+            body: body.into(),
+            ..test_notification("Now playing")
+        };
+
+        let first = test.notify(with_body("Song A")).await;
+        test.expect_received().await;
+        for expected in 1..=2 {
+            assert_eq!(test.notify(with_body("Song A")).await, first);
+            assert_eq!(expect_repeat(&mut test, first).await, expected);
+        }
+        assert_eq!(test.source().snapshot().await.len(), 1);
+
+        let other = test.notify(with_body("Song B")).await;
+        assert_ne!(other, first, "a different body is a new notification");
+        test.expect_received().await;
+        test.replace(with_body("Song C"), other).await;
+        assert_eq!(
+            expect_repeat(&mut test, other).await,
+            0,
+            "an explicit replacement is no repeat"
+        );
+
+        test.source()
+            .close(first, CloseReason::Dismissed)
+            .await
+            .unwrap();
+        test.expect_closed(first, CloseReason::Dismissed).await;
+        let again = test.notify(with_body("Song A")).await;
+        assert!(again > other);
+        test.expect_received().await;
+        test.notify(with_body("Song A")).await;
+        assert_eq!(
+            expect_repeat(&mut test, again).await,
+            1,
+            "the count starts over after a close"
+        );
+
+        test.advance(30_001).await;
+        let late = test.notify(with_body("Song A")).await;
+        assert_ne!(late, again, "copies past the window are stored again");
+        test.expect_received().await;
+    }
+
+    #[tokio::test]
+    async fn dedup_without_bodies_folds_copies_whatever_their_body() {
+        let mut test = TestSource::with_config(SourceConfig {
+            dedup: Some(Dedup {
+                match_body: false,
+                ..Dedup::default()
+            }),
+            ..SourceConfig::default()
+        });
+        let first = test.notify(test_notification("Download done")).await;
+        test.expect_received().await;
+        let copy = test
+            .notify(Notification {
+                body: "report.pdf".into(),
+                ..test_notification("Download done")
+            })
+            .await;
+        assert_eq!(copy, first);
+        assert_eq!(test.expect_replaced(first).await.body, "report.pdf");
+
+        let other_app = test
+            .notify(Notification {
+                app_name: "browser".into(),
+                ..test_notification("Download done")
+            })
+            .await;
+        assert_ne!(other_app, first, "the app name always takes part");
+        test.expect_received().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limited_floods_keep_ids_and_a_bounded_event_stream() {
+        let mut test = TestSource::with_config(SourceConfig {
+            rate_limit: Some(RateLimit {
+                per_app_per_minute: 60,
+                burst: 5,
+            }),
+            ..SourceConfig::default()
+        });
+        let spam = |n: u32| Notification {
+            app_name: "spammer".into(),
+            ..test_notification(&format!("spam {n}"))
+        };
+
+        let mut ids = Vec::new();
+        for n in 0..100 {
+            ids.push(test.notify(spam(n)).await);
+        }
+        let mail = test.notify(test_notification("mail")).await;
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids[99] < mail);
+
+        let seen: Vec<String> = test
+            .drain()
+            .into_iter()
+            .map(|event| match event {
+                NotificationEvent::Received { id, .. } => format!("received {id}"),
+                NotificationEvent::RateLimited {
+                    app_name,
+                    suppressed,
+                } => format!("{app_name} limited {suppressed}"),
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect();
+        let mut expected: Vec<String> =
+            ids[..5].iter().map(|id| format!("received {id}")).collect();
+        expected.push("spammer limited 1".to_string());
+        expected.push(format!("received {mail}"));
+        assert_eq!(seen, expected, "other apps are not limited");
+        assert_eq!(test.source().snapshot().await.len(), 6);
+        assert_eq!(test.source().stats().rate_limited, 95);
+        let closed = test
+            .source()
+            .close(ids[50], CloseReason::ClosedByCall)
+            .await;
+        assert!(closed.is_err(), "dropped ids were never stored");
+
+        test.replace(spam(100), ids[0]).await;
+        test.expect_replaced(ids[0]).await;
+
+        test.advance(1_000).await;
+        let refilled = test.notify(spam(101)).await;
+        assert_eq!(
+            test.next_event().await,
+            NotificationEvent::RateLimited {
+                app_name: "spammer".into(),
+                suppressed: 94,
+            }
+        );
+        assert_eq!(test.expect_received().await.0, refilled);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn critical_notifications_never_expire_when_configured() {
+        let mut test = TestSource::with_config(SourceConfig {
+            default_timeout_ms: Some(10),
+            clamp_timeout: TimeoutClamp {
+                min_ms: None,
+                max_ms: Some(20),
+                exempt_critical: false,
+            },
+            critical_never_expires: true,
+            ..SourceConfig::default()
+        });
+        let critical = |summary: &str| Notification {
+            urgency: Urgency::Critical,
+            timeout_ms: 10,
+            ..test_notification_with_action(summary, "default")
+        };
+
+        let first = test.notify(critical("disk full")).await;
+        match test.next_event().await {
+            NotificationEvent::Received {
+                id, remaining_ms, ..
+            } => {
+                assert_eq!(id, first);
+                assert_eq!(remaining_ms, None, "no expiry to count down");
+            }
+            other => panic!("expected Received, got {other:?}"),
+        }
+        let second = test.notify(critical("battery low")).await;
+        test.expect_received().await;
+        let normal = test
+            .notify(Notification {
+                timeout_ms: 10,
+                ..test_notification("normal")
+            })
+            .await;
+        test.expect_received().await;
+
+        test.advance(3_600_000).await;
+        test.expect_closed(normal, CloseReason::Expired).await;
+        test.expect_no_event().await;
+
+        test.source()
+            .close(first, CloseReason::ClosedByCall)
+            .await
+            .unwrap();
+        test.expect_closed(first, CloseReason::ClosedByCall).await;
+        test.source()
+            .invoke_action(second, "default")
+            .await
+            .unwrap();
+        test.expect_action_invoked(second, "default").await;
+        test.expect_closed(second, CloseReason::Dismissed).await;
+
+        test.source().set_critical_never_expires(false);
+        let later = test.notify(critical("disk full again")).await;
+        test.expect_received().await;
+        test.advance(10).await;
+        test.expect_closed(later, CloseReason::Expired).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replacement_resets_timeout_generation() {
+        let mut test = TestSource::new();
+
+        let id = test
+            .notify(Notification {
+                timeout_ms: 20,
+                ..test_notification("first")
+            })
+            .await;
+        test.expect_received().await;
+
+        test.advance(10).await;
+        let replaced_id = test
+            .replace(
+                Notification {
+                    timeout_ms: 80,
+                    ..test_notification("second")
+                },
+                id,
+            )
+            .await;
+        assert_eq!(replaced_id, id);
+        test.expect_replaced(id).await;
+
+        // Well past the first generation's deadline, one short of the replacement's.
+        test.advance(79).await;
+        test.expect_no_event().await;
+        test.advance(1).await;
+        test.expect_closed(id, CloseReason::Expired).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn detailed_snapshot_is_sorted_and_counts_down_the_current_generation() {
+        let test = TestSource::new();
+        let ids = test
+            .source()
+            .notify_batch(vec![
+                (
+                    Notification {
+                        timeout_ms: 100,
+                        ..test_notification("timed")
+                    },
+                    0,
+                ),
+                (
+                    Notification {
+                        timeout_ms: 0,
+                        ..test_notification("persistent")
+                    },
+                    0,
+                ),
+            ])
+            .await
+            .unwrap();
+        let (timed, persistent) = (ids[0], ids[1]);
+        test.advance(30).await;
+
+        let entries = test.source().snapshot_detailed();
+        assert_eq!(
+            entries.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            [timed, persistent]
+        );
+        assert_eq!(entries[0].remaining, Some(Duration::from_millis(70)));
+        assert_eq!(entries[0].generation, 0);
+        assert_eq!(entries[1].remaining, None, "never expires");
+
+        test.replace(
+            Notification {
+                timeout_ms: 200,
+                ..test_notification("timed again")
+            },
+            timed,
+        )
+        .await;
+        let entry = &test.source().snapshot_detailed()[0];
+        assert_eq!(
+            (entry.generation, entry.remaining),
+            (1, Some(Duration::from_millis(200)))
+        );
+        assert_eq!(
+            test.source().get(timed).map(|n| n.summary),
+            Some("timed again".to_string())
+        );
+
+        assert!(test.source().set_pinned(timed, true));
+        assert_eq!(
+            test.source().snapshot_detailed()[0].remaining,
+            None,
+            "pinned"
+        );
+        assert_eq!(test.source().get(u32::MAX), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_and_remaining_timeout_report_the_source_deadline() {
+        let mut test = TestSource::with_config(SourceConfig {
+            default_timeout_ms: Some(50),
+            ..SourceConfig::default()
+        });
+        let id = test
+            .notify(Notification {
+                timeout_ms: 100,
+                ..test_notification("timed")
+            })
+            .await;
+        let persistent = test
+            .notify(Notification {
+                timeout_ms: 0,
+                ..test_notification("persistent")
+            })
+            .await;
+        test.advance(30).await;
+        assert_eq!(
+            test.source().remaining_timeout(id),
+            Some(Duration::from_millis(70))
+        );
+        assert_eq!(test.source().remaining_timeout(persistent), None);
+        assert_eq!(test.source().remaining_timeout(u32::MAX), None);
+
+        // The replacement restarts the deadline with its own timeout, here the default.
+        test.replace(test_notification("timed again"), id).await;
+        assert_eq!(
+            test.source().remaining_timeout(id),
+            Some(Duration::from_millis(50))
+        );
+        assert!(test.source().set_pinned(id, true));
+        assert_eq!(test.source().remaining_timeout(id), None, "pinned");
+        test.replace(test_notification("pinned again"), id).await;
+
+        let remaining: Vec<_> = test
+            .drain()
+            .into_iter()
+            .map(|event| match event {
+                NotificationEvent::Received { remaining_ms, .. }
+                | NotificationEvent::Replaced { remaining_ms, .. } => remaining_ms,
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect();
+        assert_eq!(remaining, [Some(100), None, Some(50), None]);
+    }
+
+    #[tokio::test]
+    async fn invoke_action_emits_action_and_closed_events() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        let id = source
+            .notify(test_notification_with_action("action", "open"), 0)
+            .await
+            .unwrap();
+
+        let first = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match first {
+            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        source.invoke_action(id, "open").await.unwrap();
+
+        let second = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match second {
+            NotificationEvent::ActionInvoked { id: event_id, .. } => assert_eq!(event_id, id),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        let third = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match third {
+            NotificationEvent::Closed {
+                id: event_id,
+                reason,
+            } => {
+                assert_eq!(event_id, id);
+                assert_eq!(reason, CloseReason::Dismissed);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn invoke_action_reports_unknown_action() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        let id = source
+            .notify(test_notification("no action"), 0)
+            .await
+            .unwrap();
+        let _ = rx.recv().await;
+
+        assert_eq!(
+            source.invoke_action(id, "open").await,
+            Err(CommandError::UnknownAction)
+        );
+
+        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(maybe_event.is_err(), "unexpected event was emitted");
+    }
+
+    #[tokio::test]
+    async fn resident_notifications_stay_after_an_action() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+        let mut player = test_notification_with_action("Track 1", "next");
+        player.hints.resident = Some(true);
+        let id = source.notify(player, 0).await.unwrap();
+        let _ = rx.recv().await;
+
+        source.invoke_action(id, "next").await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            NotificationEvent::ActionInvoked { id: event_id, .. } if event_id == id
+        ));
+        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(
+            maybe_event.is_err(),
+            "no Closed for a resident notification"
+        );
+        assert_eq!(source.snapshot().await.len(), 1);
+
+        source.invoke_action(id, "next").await.unwrap();
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            NotificationEvent::ActionInvoked { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn invoke_action_reports_unknown_id_as_not_found() {
+        let (source, _rx) = WispSource::new(SourceConfig::default());
+
+        assert_eq!(
+            source.invoke_action(42, "open").await,
+            Err(CommandError::NotFound)
+        );
+    }
+
+    #[test]
+    fn command_errors_round_trip_through_control_error_replies() {
+        use zbus::DBusError;
+
+        let call = zbus::Message::method_call(CONTROL_PATH, "InvokeAction")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        for err in [
+            CommandError::NotFound,
+            CommandError::UnknownAction,
+            CommandError::Backend("event channel closed".to_string()),
+        ] {
+            let name = err.dbus_name();
+            let reply = ControlError::from(err.clone())
+                .create_reply(&call.header())
+                .unwrap();
+            let dbus_err = zbus::Error::from(reply);
+            let zbus::Error::MethodError(sent, _, _) = &dbus_err else {
+                panic!("expected a method error, got {dbus_err:?}");
+            };
+            assert_eq!(sent.as_str(), name);
+            assert_eq!(CommandError::from_dbus_error(&dbus_err), Some(err));
+        }
+        assert_eq!(
+            CommandError::from_dbus_error(&zbus::Error::InvalidReply),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot_reflects_replace_and_close_state() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        let id = source.notify(test_notification("first"), 0).await.unwrap();
+        let _ = rx.recv().await;
+
+        source
+            .notify(test_notification("second"), id)
+            .await
+            .unwrap();
+        let _ = rx.recv().await;
+
+        let snapshot = source.snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].0, id);
+        assert_eq!(snapshot[0].1.summary, "second");
+
+        source.close(id, CloseReason::ClosedByCall).await.unwrap();
+        let _ = rx.recv().await;
+
+        assert!(source.snapshot().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn close_unknown_id_is_safe_noop() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+
+        assert_eq!(
+            source.close(42, CloseReason::ClosedByCall).await,
+            Err(CommandError::NotFound)
+        );
+
+        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(maybe_event.is_err(), "unexpected event was emitted");
+    }
+
+    #[test]
+    fn parse_hints_extracts_known_fields_exactly() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert("urgency".to_string(), 0_u8.into());
+        raw_hints.insert(
+            "category".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("email.arrived")),
+        );
+        raw_hints.insert(
+            "desktop-entry".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("org.example.Mail")),
+        );
+        raw_hints.insert("transient".to_string(), zvariant::OwnedValue::from(true));
+        raw_hints.insert("resident".to_string(), zvariant::OwnedValue::from(true));
+        raw_hints.insert("action-icons".to_string(), zvariant::OwnedValue::from(true));
+
+        let (urgency, hints, oversized) = parse_hints(&raw_hints, test_hint_limits());
+
+        assert_eq!(urgency, Urgency::Low);
+        assert_eq!(hints.category.as_deref(), Some("email.arrived"));
+        assert_eq!(hints.desktop_entry.as_deref(), Some("org.example.Mail"));
+        assert_eq!(hints.transient, Some(true));
+        assert_eq!(hints.resident, Some(true));
+        assert_eq!(hints.action_icons, Some(true));
+        assert!(hints.extra.is_empty());
+        assert_eq!(oversized, 0);
+    }
+
+    #[test]
+    fn parse_hints_reads_kde_interop_hints() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "x-kde-display-appname".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("Dolphin")),
+        );
+        raw_hints.insert(
+            "x-kde-urls".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(vec![
+                "file:///home/me/Downloads/report.pdf",
+                "",
+                "https://example.org/report",
+            ]))
+            .unwrap(),
+        );
+        raw_hints.insert(
+            "x-kde-origin-name".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("  ")),
+        );
+
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+
+        assert_eq!(hints.display_app_name.as_deref(), Some("Dolphin"));
+        assert_eq!(
+            hints.urls,
+            [
+                "file:///home/me/Downloads/report.pdf",
+                "https://example.org/report"
+            ]
+        );
+        assert_eq!(hints.origin_name, None, "a blank origin is no origin");
+        assert!(hints.extra.is_empty(), "parsed hints stay out of extra");
+
+        raw_hints.insert(
+            "x-kde-origin-name".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("Pixel 8")),
+        );
+        raw_hints.insert("x-kde-urls".to_string(), 3_u32.into());
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.origin_name.as_deref(), Some("Pixel 8"));
+        assert!(hints.urls.is_empty(), "a mistyped url list is ignored");
+    }
+
+    #[test]
+    fn parse_hints_can_leave_extra_empty() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "category".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("im.received")),
+        );
+        for key in ["x-a", "x-b", "x-huge"] {
+            raw_hints.insert(
+                key.to_string(),
+                zvariant::OwnedValue::from(zvariant::Str::from("v".repeat(5_000))),
+            );
+        }
+
+        let (_, kept, oversized) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(kept.extra.len(), 3);
+        assert!(oversized > 0);
+
+        let (_, hints, oversized) = parse_hints(
+            &raw_hints,
+            HintLimits {
+                keep_extra: false,
+                ..test_hint_limits()
+            },
+        );
+        assert_eq!(hints.category.as_deref(), Some("im.received"));
+        assert!(hints.extra.is_empty());
+        assert_eq!(oversized, 0, "nothing formatted, nothing cut");
+    }
+
+    /// An `(iiibiiay)` image hint: width, height, rowstride, has_alpha, bits_per_sample,
+    /// channels, data.
+    fn image_hint(
+        width: i32,
+        height: i32,
+        rowstride: i32,
+        has_alpha: bool,
+        bits_per_sample: i32,
+        data: Vec<u8>,
+    ) -> zvariant::OwnedValue {
+        let channels: i32 = if has_alpha { 4 } else { 3 };
+        zvariant::OwnedValue::try_from(zvariant::Value::from(zvariant::Structure::from((
+            width,
+            height,
+            rowstride,
+            has_alpha,
+            bits_per_sample,
+            channels,
+            data,
+        ))))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_hints_decodes_the_newest_image_spelling() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "icon_data".to_string(),
+            image_hint(1, 1, 4, true, 8, vec![1, 2, 3, 4]),
+        );
+        raw_hints.insert(
+            "image_data".to_string(),
+            image_hint(1, 1, 3, false, 8, vec![5, 6, 7]),
+        );
+        // Two RGB rows padded to 8 bytes; the last one without its padding.
+        let pixels = vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        raw_hints.insert(
+            "image-data".to_string(),
+            image_hint(2, 2, 8, false, 8, pixels.clone()),
+        );
 
-    (
-        urgency,
-        NotificationHints {
-            category,
-            desktop_entry,
-            transient,
-            extra,
-        },
-    )
-}
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(
+            hints.image,
+            Some(ImageData {
+                width: 2,
+                height: 2,
+                rowstride: 8,
+                has_alpha: false,
+                channels: 3,
+                data: pixels,
+            })
+        );
+        assert!(matches!(
+            hints.extra["image_data"],
+            HintValue::Omitted { ref reason, .. } if reason == "image payload"
+        ));
+        assert!(!hints.extra.contains_key("image-data"));
+        assert_eq!(
+            hints.icon_data.map(|icon| icon.data),
+            Some(vec![1, 2, 3, 4]),
+            "the legacy key ranks below app_icon, so it is kept apart"
+        );
+        assert!(!hints.extra.contains_key("icon_data"));
 
-fn format_hint_value(key: &str, value: &zvariant::OwnedValue) -> String {
-    if matches!(key, "image-data" | "image_data" | "icon_data") {
-        return "<omitted image payload>".to_string();
+        raw_hints.remove("image-data");
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.image.map(|image| image.data), Some(vec![5, 6, 7]));
     }
 
-    let signature = value.value_signature().to_string();
-    if signature.contains("ay") {
-        return format!("<omitted binary payload signature={signature}>");
+    #[test]
+    fn parse_hints_reads_the_newest_image_path_spelling() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "image_path".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("/tmp/old.png")),
+        );
+        raw_hints.insert(
+            "image-path".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from(" file:///tmp/art.png ")),
+        );
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.image_path.as_deref(), Some("file:///tmp/art.png"));
+        assert!(hints.extra.is_empty());
+
+        raw_hints.insert(
+            "image-path".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("")),
+        );
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(
+            hints.image_path.as_deref(),
+            Some("/tmp/old.png"),
+            "blank falls back"
+        );
+
+        raw_hints.clear();
+        raw_hints.insert(
+            "image-path".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("firefox")),
+        );
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.image_path.as_deref(), Some("firefox"));
     }
 
-    format!("{value:?}")
-}
+    #[test]
+    fn parse_hints_rejects_bogus_images() {
+        let bogus = [
+            (
+                "rows shorter than the pixels",
+                image_hint(2, 1, 4, false, 8, vec![0; 6]),
+            ),
+            (
+                "data short of the last row",
+                image_hint(2, 2, 6, false, 8, vec![0; 11]),
+            ),
+            (
+                "data past the last row",
+                image_hint(1, 2, 4, true, 8, vec![0; 12]),
+            ),
+            ("negative width", image_hint(-1, 1, 4, true, 8, vec![0; 4])),
+            ("zero height", image_hint(1, 0, 4, true, 8, Vec::new())),
+            (
+                "16 bits per sample",
+                image_hint(1, 1, 6, false, 16, vec![0; 6]),
+            ),
+            (
+                "channels without alpha",
+                zvariant::OwnedValue::try_from(zvariant::Value::from(zvariant::Structure::from((
+                    1_i32,
+                    1_i32,
+                    4_i32,
+                    false,
+                    8_i32,
+                    4_i32,
+                    vec![0_u8; 4],
+                ))))
+                .unwrap(),
+            ),
+            ("not a structure", zvariant::OwnedValue::from(true)),
+        ];
+        let fallback = image_hint(1, 1, 3, false, 8, vec![5, 6, 7]);
+        for (case, value) in bogus {
+            let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+            raw_hints.insert("image-data".to_string(), value);
+            let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+            assert_eq!(hints.image, None, "{case}");
+            assert!(
+                matches!(
+                    hints.extra["image-data"],
+                    HintValue::Omitted { ref reason, .. } if reason == "image payload"
+                ),
+                "{case}"
+            );
 
-fn close_reason_code(reason: CloseReason) -> u32 {
-    match reason {
-        CloseReason::Expired => 1,
-        CloseReason::Dismissed => 2,
-        CloseReason::ClosedByCall => 3,
-        CloseReason::Undefined => 4,
+            raw_hints.insert("image_data".to_string(), fallback.try_clone().unwrap());
+            let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+            assert_eq!(
+                hints.image.map(|image| image.width),
+                Some(1),
+                "{case}: an older spelling that decodes is used"
+            );
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::{SystemTime, UNIX_EPOCH};
+    #[test]
+    fn parse_hints_reads_sound_name() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "sound-name".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("message-new-instant")),
+        );
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.sound.name.as_deref(), Some("message-new-instant"));
+        assert!(hints.extra.is_empty());
 
-    use futures_util::StreamExt;
+        raw_hints.insert("sound-name".to_string(), 7_u32.into());
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.sound.name, None, "a mistyped name is ignored");
+    }
 
-    use super::*;
+    #[test]
+    fn parse_hints_keeps_the_value_hint_as_sent() {
+        let cases = [
+            (zvariant::OwnedValue::from(40_i32), Some(40)),
+            (zvariant::OwnedValue::from(140_u32), Some(140)),
+            (zvariant::OwnedValue::from(-5_i64), Some(-5)),
+            (zvariant::OwnedValue::from(u64::MAX), Some(i32::MAX)),
+            (zvariant::OwnedValue::from(7_u8), Some(7)),
+            (
+                zvariant::OwnedValue::try_from(zvariant::Value::Value(Box::new(60_i32.into())))
+                    .unwrap(),
+                Some(60),
+            ),
+            (zvariant::OwnedValue::from(zvariant::Str::from("50")), None),
+            (zvariant::OwnedValue::from(0.5_f64), None),
+        ];
+        for (raw, expected) in cases {
+            let raw_hints = HashMap::from([("value".to_string(), raw)]);
+            let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+            assert_eq!(hints.progress, expected, "{raw_hints:?}");
+            assert!(hints.extra.is_empty(), "{raw_hints:?}");
+        }
+    }
 
-    fn test_notification(summary: &str) -> Notification {
-        Notification {
-            app_name: "test".into(),
-            app_icon: String::new(),
-            summary: summary.into(),
-            body: String::new(),
-            urgency: Default::default(),
-            timeout_ms: -1,
-            actions: vec![],
-            hints: NotificationHints::default(),
+    #[test]
+    fn parse_hints_reads_a_position_only_from_both_coordinates() {
+        let wrapped = |value: i32| {
+            zvariant::OwnedValue::try_from(zvariant::Value::Value(Box::new(value.into()))).unwrap()
+        };
+        let cases = [
+            (
+                vec![("x", wrapped(120)), ("y", wrapped(-40))],
+                Some((120, -40)),
+            ),
+            (
+                vec![("x", 30_u32.into()), ("y", 0_i64.into())],
+                Some((30, 0)),
+            ),
+            (vec![("x", wrapped(120))], None),
+            (vec![("y", wrapped(40))], None),
+            (
+                vec![("x", wrapped(120)), ("y", (i64::from(i32::MAX) + 1).into())],
+                None,
+            ),
+            (vec![("x", u64::MAX.into()), ("y", wrapped(40))], None),
+            (
+                vec![
+                    ("x", zvariant::OwnedValue::from(zvariant::Str::from("120"))),
+                    ("y", wrapped(40)),
+                ],
+                None,
+            ),
+        ];
+        for (raw, expected) in cases {
+            let raw_hints: HashMap<String, zvariant::OwnedValue> = raw
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect();
+            let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+            assert_eq!(hints.position, expected, "{raw_hints:?}");
+            assert!(hints.extra.is_empty(), "{raw_hints:?}");
         }
     }
 
-    fn test_notification_with_action(summary: &str, action_key: &str) -> Notification {
-        Notification {
-            app_name: "test".into(),
-            app_icon: String::new(),
-            summary: summary.into(),
-            body: String::new(),
-            urgency: Default::default(),
-            timeout_ms: -1,
-            actions: vec![NotificationAction {
-                key: action_key.to_string(),
-                label: "Test Action".to_string(),
-            }],
-            hints: NotificationHints::default(),
+    #[tokio::test]
+    async fn sound_hints_survive_into_the_received_event() {
+        let text = |value: &str| zvariant::OwnedValue::from(zvariant::Str::from(value));
+        let cases = [
+            (
+                vec![("sound-file", text("/usr/share/sounds/ding.oga"))],
+                SoundHint {
+                    file: Some(PathBuf::from("/usr/share/sounds/ding.oga")),
+                    ..SoundHint::default()
+                },
+            ),
+            (
+                vec![("sound-name", text("message-new-email"))],
+                SoundHint {
+                    name: Some("message-new-email".to_string()),
+                    ..SoundHint::default()
+                },
+            ),
+            (
+                vec![("suppress-sound", true.into())],
+                SoundHint {
+                    suppress: true,
+                    ..SoundHint::default()
+                },
+            ),
+            (
+                vec![
+                    ("suppress-sound", true.into()),
+                    ("sound-name", text("message-new-email")),
+                ],
+                SoundHint {
+                    name: Some("message-new-email".to_string()),
+                    suppress: true,
+                    ..SoundHint::default()
+                },
+            ),
+            (
+                vec![("sound-file", text("  ")), ("suppress-sound", text("yes"))],
+                SoundHint::default(),
+            ),
+        ];
+
+        let mut test = TestSource::new();
+        for (raw, expected) in cases {
+            let raw_hints: HashMap<String, zvariant::OwnedValue> = raw
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect();
+            let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+            assert_eq!(hints.sound, expected, "{raw_hints:?}");
+            assert!(hints.extra.is_empty(), "{raw_hints:?}");
+
+            test.notify(Notification {
+                hints,
+                ..test_notification("sound")
+            })
+            .await;
+            let (_, received) = test.expect_received().await;
+            assert_eq!(received.hints.sound, expected);
         }
     }
 
     #[test]
-    fn image_hints_are_omitted_from_extra_debug_dump() {
+    fn sender_pid_hint_accepts_integer_variants() {
         let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
-        raw_hints.insert("image-data".to_string(), true.into());
-        raw_hints.insert("suppress-sound".to_string(), false.into());
+        assert_eq!(sender_pid_hint(&raw_hints), None);
+
+        raw_hints.insert("sender-pid".to_string(), 4242_i64.into());
+        assert_eq!(sender_pid_hint(&raw_hints), Some(4242));
+        raw_hints.insert("sender-pid".to_string(), 17_u32.into());
+        assert_eq!(sender_pid_hint(&raw_hints), Some(17));
+        raw_hints.insert("sender-pid".to_string(), (-1_i64).into());
+        assert_eq!(sender_pid_hint(&raw_hints), None);
         raw_hints.insert(
-            "blob".to_string(),
-            zvariant::OwnedValue::try_from(zvariant::Value::from(vec![1_u8, 2, 3])).unwrap(),
+            "sender-pid".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("12")),
         );
+        assert_eq!(sender_pid_hint(&raw_hints), None);
+    }
 
-        let (_urgency, hints) = parse_hints(&raw_hints);
+    #[test]
+    fn sandbox_app_id_reads_configured_proc_root() {
+        let proc_root =
+            std::env::temp_dir().join(format!("wisp-source-sandbox-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&proc_root);
+        std::fs::create_dir_all(proc_root.join("31").join("root")).unwrap();
+        std::fs::write(
+            proc_root.join("31").join("root").join(".flatpak-info"),
+            "[Application]\nname=org.example.Chat\n",
+        )
+        .unwrap();
 
+        let (source, _rx) = WispSource::new(SourceConfig {
+            proc_root: proc_root.clone(),
+            ..SourceConfig::default()
+        });
         assert_eq!(
-            hints.extra.get("image-data").map(String::as_str),
-            Some("<omitted image payload>")
+            source.sandbox_app_id(31).as_deref(),
+            Some("org.example.Chat")
         );
-        assert!(
-            hints
-                .extra
-                .get("blob")
-                .is_some_and(|v| v.contains("<omitted binary payload signature=ay>"))
+        assert_eq!(source.sandbox_app_id(32), None);
+
+        let _ = std::fs::remove_dir_all(&proc_root);
+    }
+
+    #[test]
+    fn oversized_hint_values_are_truncated_and_counted() {
+        let (source, _rx) = WispSource::new(SourceConfig {
+            max_hint_value_len: 64,
+            ..SourceConfig::default()
+        });
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        raw_hints.insert(
+            "x-electron-blob".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("A".repeat(10_000))),
         );
-        assert!(
-            hints
-                .extra
-                .get("suppress-sound")
-                .is_some_and(|v| v.contains("Bool(false)"))
+        raw_hints.insert(
+            "x-bytes".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(vec![7_u8; 500])).unwrap(),
         );
+        raw_hints.insert(
+            "image-data".to_string(),
+            zvariant::OwnedValue::try_from(zvariant::Value::from(vec![0_u8; 500])).unwrap(),
+        );
+        raw_hints.insert("x-small".to_string(), 42_i32.into());
+
+        let (_urgency, hints) = source.parse_incoming_hints("electron", &raw_hints);
+
+        assert_eq!(
+            hints.extra["x-electron-blob"],
+            HintValue::Omitted {
+                signature: "s".to_string(),
+                reason: "10000 bytes over the 64 byte limit".to_string(),
+            }
+        );
+        assert_eq!(
+            hints.extra["x-bytes"],
+            HintValue::Omitted {
+                signature: "ay".to_string(),
+                reason: "500 bytes over the 64 byte limit".to_string(),
+            }
+        );
+        assert!(matches!(
+            hints.extra["image-data"],
+            HintValue::Omitted { ref reason, .. } if reason == "image payload"
+        ));
+        assert_eq!(hints.extra["x-small"], HintValue::Int64(42));
+
+        let stats = source.stats();
+        assert_eq!(stats.oversized_hints, 2);
+        assert_eq!(stats.oversized_hints_by_app.get("electron"), Some(&2));
+
+        let _ = source.parse_incoming_hints("quiet", &HashMap::new());
+        assert_eq!(source.stats().oversized_hints_by_app.len(), 1);
     }
 
     #[tokio::test]
-    async fn replacement_uses_same_id() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn dropped_events_are_counted_while_warnings_are_limited() {
+        let (source, rx) = WispSource::new(SourceConfig {
+            channel_capacity: 1,
+            ..SourceConfig::default()
+        });
+        for n in 0..5 {
+            source
+                .notify(test_notification(&format!("storm {n}")), 0)
+                .await
+                .unwrap();
+        }
+        assert_eq!(source.stats().dropped_events, 4);
+        assert_eq!(
+            source
+                .inner
+                .warn_limits
+                .event_queue_full
+                .check(Instant::now()),
+            None,
+            "only the first drop in the window was logged"
+        );
 
-        let id = source.notify(test_notification("first"), 0).await.unwrap();
-        let _ = rx.recv().await;
+        drop(rx);
+        assert!(source.notify(test_notification("gone"), 0).await.is_err());
+        assert_eq!(source.stats().dropped_events, 5);
+    }
 
-        let replaced_id = source
-            .notify(test_notification("second"), id)
-            .await
-            .unwrap();
-        assert_eq!(id, replaced_id);
+    /// Reads `count` events, naming each by its summary or the drops it reports.
+    async fn next_seen(rx: &mut mpsc::Receiver<NotificationEvent>, count: usize) -> Vec<String> {
+        let mut seen = Vec::with_capacity(count);
+        for _ in 0..count {
+            seen.push(match rx.recv().await.expect("event channel open") {
+                NotificationEvent::Received { notification, .. } => notification.summary,
+                NotificationEvent::Overflowed { dropped } => format!("overflowed {dropped}"),
+                other => panic!("unexpected event {other:?}"),
+            });
+        }
+        seen
+    }
 
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Replaced { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
+    async fn overflowing_source(
+        policy: OverflowPolicy,
+        summaries: &[&str],
+    ) -> (WispSource, mpsc::Receiver<NotificationEvent>) {
+        let (source, rx) = WispSource::new(SourceConfig {
+            channel_capacity: 1,
+            overflow_policy: policy,
+            ..SourceConfig::default()
+        });
+        for summary in summaries {
+            source.notify(test_notification(summary), 0).await.unwrap();
         }
+        (source, rx)
     }
 
     #[tokio::test]
-    async fn missing_replaces_id_allocates_fresh_id() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn drop_newest_reports_the_lost_events_when_room_comes() {
+        let (source, mut rx) =
+            overflowing_source(OverflowPolicy::DropNewest, &["a", "b", "c"]).await;
+        assert_eq!(next_seen(&mut rx, 2).await, ["a", "overflowed 2"]);
+
+        source.notify(test_notification("d"), 0).await.unwrap();
+        assert_eq!(next_seen(&mut rx, 1).await, ["d"]);
+        assert_eq!(source.stats().dropped_events, 2);
+    }
 
-        let first_id = source.notify(test_notification("first"), 0).await.unwrap();
-        let _ = rx.recv().await;
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_newest_events_for_a_slow_consumer() {
+        let (source, mut rx) =
+            overflowing_source(OverflowPolicy::DropOldest, &["a", "b", "c", "d"]).await;
+        assert_eq!(next_seen(&mut rx, 3).await, ["a", "overflowed 2", "d"]);
+        assert_eq!(source.stats().dropped_events, 2);
+        assert_eq!(source.state().count, 4, "dropped events are still stored");
+    }
 
-        let second_id = source
-            .notify(test_notification("second"), 999_999)
-            .await
-            .unwrap();
-        assert_ne!(first_id, second_id);
+    #[tokio::test(start_paused = true)]
+    async fn block_waits_for_room_until_its_timeout() {
+        let policy = OverflowPolicy::Block {
+            timeout: Duration::from_millis(100),
+        };
+        let (source, mut rx) = overflowing_source(policy, &["a", "b"]).await;
+        // Room comes in time: nothing is lost.
+        assert_eq!(next_seen(&mut rx, 2).await, ["a", "b"]);
 
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Received { id, notification } => {
-                assert_eq!(id, second_id);
-                assert_eq!(notification.summary, "second");
-            }
-            other => panic!("unexpected event: {other:?}"),
+        for summary in ["c", "d"] {
+            source.notify(test_notification(summary), 0).await.unwrap();
         }
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(next_seen(&mut rx, 2).await, ["c", "overflowed 1"]);
+        assert_eq!(source.stats().dropped_events, 1);
+    }
+
+    /// Names the next `count` events of `events` by kind and id.
+    async fn kinds(events: impl Stream<Item = NotificationEvent>, count: usize) -> Vec<String> {
+        events
+            .take(count)
+            .map(|event| match event {
+                NotificationEvent::Received { id, .. } => format!("received {id}"),
+                NotificationEvent::Closed { id, reason } => format!("closed {id} {reason:?}"),
+                NotificationEvent::Overflowed { dropped } => format!("overflowed {dropped}"),
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect()
+            .await
     }
 
     #[tokio::test]
-    async fn timeout_emits_closed_expired_event() {
-        let cfg = SourceConfig {
-            default_timeout_ms: Some(20),
+    async fn subscribers_each_see_the_same_events() {
+        let (source, _rx) = WispSource::new(SourceConfig::default());
+        let (first, second) = (source.subscribe(), source.subscribe());
+
+        let id = source.notify(test_notification("a"), 0).await.unwrap();
+        source.close(id, CloseReason::Dismissed).await.unwrap();
+        let late = source.subscribe();
+        let next = source.notify(test_notification("b"), 0).await.unwrap();
+
+        let expected = [
+            format!("received {id}"),
+            format!("closed {id} Dismissed"),
+            format!("received {next}"),
+        ];
+        assert_eq!(kinds(first, 3).await, expected);
+        assert_eq!(kinds(second, 3).await, expected);
+        // A late subscriber catches up from the snapshot, not the stream.
+        assert_eq!(kinds(late, 1).await, [format!("received {next}")]);
+        assert_eq!(source.snapshot().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_lagging_subscriber_is_told_what_it_missed() {
+        let (source, _rx) = WispSource::new(SourceConfig {
+            channel_capacity: 2,
             ..SourceConfig::default()
-        };
-        let (source, mut rx) = WispSource::new(cfg);
+        });
+        let events = source.subscribe();
+        let mut ids = Vec::new();
+        for summary in ["a", "b", "c", "d"] {
+            ids.push(source.notify(test_notification(summary), 0).await.unwrap());
+        }
 
-        let id = source
-            .notify(test_notification("expires"), 0)
-            .await
-            .unwrap();
+        assert_eq!(
+            kinds(events, 3).await,
+            [
+                "overflowed 2".to_string(),
+                format!("received {}", ids[2]),
+                format!("received {}", ids[3]),
+            ]
+        );
+    }
 
-        let first = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match first {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
+    #[test]
+    fn hints_past_total_budget_are_dropped_and_counted() {
+        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
+        for key in ["x-a", "x-b", "x-c"] {
+            raw_hints.insert(
+                key.to_string(),
+                zvariant::OwnedValue::from(zvariant::Str::from("v".repeat(40))),
+            );
         }
 
-        let second = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match second {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::Expired);
-            }
-            other => panic!("unexpected event: {other:?}"),
+        let one_hint = "x-a".len() + hint_len(&extra_hint("x-a", &raw_hints["x-a"], 1024).0);
+
+        let (_urgency, hints, oversized) = parse_hints(
+            &raw_hints,
+            HintLimits {
+                max_value_len: 1024,
+                max_total_bytes: 2 * one_hint + 1,
+                keep_extra: true,
+            },
+        );
+
+        assert_eq!(oversized, 1);
+        assert_eq!(hints.extra["x-a"], HintValue::String("v".repeat(40)));
+        assert_eq!(hints.extra["x-b"], HintValue::String("v".repeat(40)));
+        assert!(matches!(
+            &hints.extra["x-c"],
+            HintValue::Omitted { signature, reason }
+                if signature == "s" && reason.ends_with("bytes over hint budget")
+        ));
+    }
+
+    #[test]
+    fn unknown_hints_keep_their_types_through_nested_variants() {
+        fn wrapped(value: zvariant::Value<'static>) -> zvariant::OwnedValue {
+            zvariant::OwnedValue::try_from(zvariant::Value::Value(Box::new(value))).unwrap()
         }
+        let doubly_wrapped = zvariant::Value::Value(Box::new(zvariant::Value::from(3_u32)));
+        let raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::from([
+            ("x-quiet".to_string(), wrapped(true.into())),
+            ("x-level".to_string(), wrapped(42_i32.into())),
+            ("x-count".to_string(), wrapped(doubly_wrapped)),
+            ("x-ratio".to_string(), wrapped(0.25_f64.into())),
+            (
+                "x-canonical-private-synchronous".to_string(),
+                wrapped(zvariant::Value::from("volume")),
+            ),
+            ("x-blob".to_string(), wrapped(vec![1_u8, 2].into())),
+            (
+                "x-list".to_string(),
+                zvariant::OwnedValue::try_from(zvariant::Value::from(vec!["a", "b"])).unwrap(),
+            ),
+        ]);
+
+        let (_, hints, _) = parse_hints(&raw_hints, test_hint_limits());
+        assert_eq!(hints.extra["x-quiet"], HintValue::Bool(true));
+        assert_eq!(hints.extra["x-level"], HintValue::Int64(42));
+        assert_eq!(hints.extra["x-count"], HintValue::UInt64(3));
+        assert_eq!(hints.extra["x-ratio"], HintValue::Double(0.25));
+        assert_eq!(
+            hints.extra["x-canonical-private-synchronous"],
+            HintValue::String("volume".to_string())
+        );
+        assert_eq!(hints.extra["x-blob"], HintValue::Bytes(vec![1, 2]));
+        assert!(matches!(
+            &hints.extra["x-list"],
+            HintValue::Other { signature, text } if signature == "as" && text.contains('b')
+        ));
+    }
+
+    #[test]
+    fn parse_actions_handles_empty_and_odd_action_lists_safely() {
+        assert!(parse_actions(Vec::new()).is_empty());
+
+        let parsed = parse_actions(vec!["only-key".to_string()]);
+        assert!(parsed.is_empty());
+
+        let parsed = parse_actions(vec![
+            "open".to_string(),
+            "Open".to_string(),
+            "dangling".to_string(),
+        ]);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].key, "open");
+        assert_eq!(parsed[0].label, "Open");
     }
 
     #[tokio::test]
-    async fn negative_timeout_without_default_is_persistent() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn dbus_notify_emits_received_event() {
+        let cfg = SourceConfig::default();
+        let (mut test, bus) = PeerBus::start(cfg.clone()).await;
 
-        let id = source
-            .notify(test_notification("persistent"), 0)
+        let mut hints = HashMap::<String, zvariant::OwnedValue>::new();
+        hints.insert("urgency".to_string(), zvariant::OwnedValue::from(2_u8));
+        hints.insert(
+            "category".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("mail.arrived")),
+        );
+        hints.insert(
+            "desktop-entry".to_string(),
+            zvariant::OwnedValue::from(zvariant::Str::from("org.example.Mail")),
+        );
+        hints.insert("transient".to_string(), zvariant::OwnedValue::from(true));
+        hints.insert("x-foo".to_string(), zvariant::OwnedValue::from(42_i32));
+
+        let msg = bus
+            .client()
+            .call_method(
+                None::<&str>,
+                cfg.dbus_path.as_str(),
+                Some(DBUS_INTERFACE),
+                "Notify",
+                &(
+                    String::from("test-client"),
+                    0_u32,
+                    String::from("test-icon"),
+                    String::from("hello"),
+                    String::from("world"),
+                    Vec::<String>::new(),
+                    hints,
+                    2_500_i32,
+                ),
+            )
             .await
             .unwrap();
 
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
-        }
-
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(maybe_event.is_err(), "unexpected timeout event was emitted");
+        let id: u32 = msg.body().deserialize().unwrap();
 
-        let snapshot = source.snapshot().await;
-        assert_eq!(snapshot.len(), 1);
-        assert_eq!(snapshot[0].0, id);
+        let (event_id, notification) = test.expect_received().await;
+        assert_eq!(event_id, id);
+        assert_eq!(notification.app_icon, "test-icon");
+        assert_eq!(notification.urgency, Urgency::Critical);
+        assert_eq!(notification.hints.category.as_deref(), Some("mail.arrived"));
+        assert_eq!(
+            notification.hints.desktop_entry.as_deref(),
+            Some("org.example.Mail")
+        );
+        assert_eq!(notification.hints.transient, Some(true));
+        assert!(notification.hints.extra.contains_key("x-foo"));
     }
 
     #[tokio::test]
-    async fn zero_timeout_never_schedules_expiry() {
-        let (source, mut rx) = WispSource::new(SourceConfig {
-            default_timeout_ms: Some(10),
-            ..SourceConfig::default()
-        });
-
-        let id = source
-            .notify(
-                Notification {
-                    timeout_ms: 0,
-                    ..test_notification("persistent-zero")
-                },
-                0,
-            )
-            .await
-            .unwrap();
+    async fn dbus_image_data_arrives_decoded() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
+        let art: Vec<u8> = (0..16).collect();
+        let hints = HashMap::from([(
+            "image-data",
+            zvariant::Value::from(zvariant::Structure::from((
+                2_i32,
+                2_i32,
+                8_i32,
+                true,
+                8_i32,
+                4_i32,
+                art.clone(),
+            ))),
+        )]);
+
+        bus.notify_call(0, "Now playing", &[], hints, 5_000).await;
+
+        let (_, notification) = test.expect_received().await;
+        let image = notification.hints.image.expect("decoded album art");
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 4));
+        assert!(image.has_alpha);
+        assert_eq!(image.data, art);
+        assert!(notification.hints.extra.is_empty());
+    }
 
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
+    #[tokio::test]
+    async fn dbus_value_replacements_carry_each_new_level() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
+        let volume = |level: i32| HashMap::from([("value", zvariant::Value::from(level))]);
+
+        let reply = bus.notify_call(0, "Volume", &[], volume(0), 5_000).await;
+        let id: u32 = reply.body().deserialize().unwrap();
+        let (_, notification) = test.expect_received().await;
+        assert_eq!(notification.hints.progress, Some(0));
+
+        for level in [25, 50, 75, 100, 120] {
+            let reply = bus
+                .notify_call(id, "Volume", &[], volume(level), 5_000)
+                .await;
+            assert_eq!(reply.body().deserialize::<u32>().unwrap(), id);
+            let current = test.expect_replaced(id).await;
+            assert_eq!(current.hints.progress, Some(level));
         }
+    }
 
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(maybe_event.is_err(), "unexpected timeout event was emitted");
+    #[tokio::test]
+    async fn dbus_close_notification_emits_closed_event() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
 
-        let snapshot = source.snapshot().await;
-        assert_eq!(snapshot.len(), 1);
-        assert_eq!(snapshot[0].0, id);
+        let id = bus.notify(0, "hello", &[], 10_000).await;
+        test.expect_received().await;
+
+        bus.close_notification(id).await;
+        test.expect_closed(id, CloseReason::ClosedByCall).await;
     }
 
     #[tokio::test]
-    async fn replacement_resets_timeout_generation() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
-
-        let id = source
-            .notify(
-                Notification {
-                    timeout_ms: 20,
-                    ..test_notification("first")
-                },
-                0,
-            )
-            .await
-            .unwrap();
-        let _ = rx.recv().await;
+    async fn dbus_close_notification_emits_notification_closed_signal() {
+        let (_test, mut bus) = PeerBus::start(SourceConfig::default()).await;
 
-        tokio::time::sleep(Duration::from_millis(10)).await;
+        let id = bus.notify(0, "hello", &[], 10_000).await;
+        bus.close_notification(id).await;
 
-        let replaced_id = source
-            .notify(
-                Notification {
-                    timeout_ms: 80,
-                    ..test_notification("second")
-                },
-                id,
-            )
-            .await
-            .unwrap();
-        assert_eq!(replaced_id, id);
+        let signal = bus.expect_signal("NotificationClosed").await;
+        let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
+        assert_eq!(signal_id, id);
+        assert_eq!(reason_code, 3);
+    }
 
-        let replaced = rx.recv().await.unwrap();
-        match replaced {
-            NotificationEvent::Replaced { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
+    #[tokio::test]
+    async fn shutdown_closes_everything_and_tells_the_senders() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+        let first = bus.notify(0, "one", &[], 10_000).await;
+        let second = bus.notify(0, "two", &[], 0).await;
+        test.drain();
+
+        assert_eq!(test.source().shutdown().await, [first, second]);
+        test.expect_closed(first, CloseReason::Undefined).await;
+        test.expect_closed(second, CloseReason::Undefined).await;
+        for id in [first, second] {
+            let signal = bus.expect_signal("NotificationClosed").await;
+            let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
+            assert_eq!((signal_id, reason_code), (id, 4));
         }
+        assert_eq!(test.source().state().count, 0);
+        assert!(test.source().shutdown().await.is_empty());
+    }
 
-        let maybe_event = tokio::time::timeout(Duration::from_millis(30), rx.recv()).await;
+    #[tokio::test]
+    async fn close_all_closes_everything_and_tells_the_senders() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+        let first = bus.notify(0, "one", &[], 10_000).await;
+        let second = bus.notify(0, "two", &[], 0).await;
+        test.drain();
+
+        assert_eq!(
+            test.source()
+                .close_all(CloseReason::Dismissed)
+                .await
+                .unwrap(),
+            [first, second]
+        );
+        test.expect_closed(first, CloseReason::Dismissed).await;
+        test.expect_closed(second, CloseReason::Dismissed).await;
+        for id in [first, second] {
+            let signal = bus.expect_signal("NotificationClosed").await;
+            let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
+            assert_eq!((signal_id, reason_code), (id, 2));
+        }
+        assert_eq!(test.source().state().count, 0);
         assert!(
-            maybe_event.is_err(),
-            "replacement should have canceled the old timeout generation"
+            test.source()
+                .close_all(CloseReason::Dismissed)
+                .await
+                .unwrap()
+                .is_empty()
         );
 
-        let closed = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match closed {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::Expired);
-            }
-            other => panic!("unexpected event: {other:?}"),
-        }
+        let third = bus.notify(0, "three", &[], 0).await;
+        assert!(third > second, "ids keep counting");
+        assert_eq!(test.source().state().count, 1);
     }
 
     #[tokio::test]
-    async fn invoke_action_emits_action_and_closed_events() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
-
-        let id = source
-            .notify(test_notification_with_action("action", "open"), 0)
-            .await
-            .unwrap();
-
-        let first = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match first {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
+    async fn close_all_does_not_wait_for_a_slow_consumer() {
+        let (source, rx) = WispSource::new(SourceConfig {
+            channel_capacity: 2,
+            ..SourceConfig::default()
+        });
+        for summary in ["a", "b", "c", "d"] {
+            source.notify(test_notification(summary), 0).await.unwrap();
         }
+        // Nothing is read from `rx`: the queue is full before closing starts.
+        let closed = tokio::time::timeout(
+            Duration::from_secs(1),
+            source.close_all(CloseReason::Dismissed),
+        )
+        .await
+        .expect("close_all returns with a full event queue");
+        assert_eq!(closed.unwrap().len(), 4);
+        assert_eq!(source.state().count, 0);
+
+        drop(rx);
+        source.notify(test_notification("e"), 0).await.ok();
+        assert!(matches!(
+            source.close_all(CloseReason::Dismissed).await,
+            Err(SourceError::EventChannelClosed)
+        ));
+        assert_eq!(source.state().count, 0, "drained regardless");
+    }
 
-        let invoked = source.invoke_action(id, "open").await.unwrap();
-        assert!(invoked);
+    #[tokio::test]
+    async fn show_historic_resurrects_without_storing_anything() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
 
-        let second = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match second {
-            NotificationEvent::ActionInvoked { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
-        }
+        let id = bus.notify(0, "hello", &["open", "Open"], 10_000).await;
+        test.expect_received().await;
+        bus.close_notification(id).await;
+        test.expect_closed(id, CloseReason::ClosedByCall).await;
 
-        let third = tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
-        match third {
-            NotificationEvent::Closed {
+        assert_eq!(bus.show_historic(0).await, Ok(id));
+        match test.next_event().await {
+            NotificationEvent::Historic {
                 id: event_id,
-                reason,
+                notification,
+                actions_enabled,
+                ..
             } => {
                 assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::Dismissed);
+                assert_eq!(notification.summary, "hello");
+                assert!(
+                    !actions_enabled,
+                    "a peer-to-peer sender has no name to reach"
+                );
             }
             other => panic!("unexpected event: {other:?}"),
         }
+        assert_eq!(test.source().state().count, 0);
+        assert_eq!(bus.show_historic(99).await, Err(CommandError::NotFound));
+        test.expect_no_event().await;
     }
 
     #[tokio::test]
-    async fn invoke_action_returns_false_for_unknown_action() {
+    async fn historic_actions_need_a_connected_sender() {
         let (source, mut rx) = WispSource::new(SourceConfig::default());
+        let (id, _) = source
+            .store_notification(
+                test_notification_with_action("v1", "open"),
+                0,
+                Some(":1.7".to_string()),
+            )
+            .unwrap();
+        source
+            .store_notification(test_notification_with_action("v2", "open"), id, None)
+            .unwrap();
+        source.close(id, CloseReason::Dismissed).await.unwrap();
+        let plain = source.notify(test_notification("plain"), 0).await.unwrap();
+        source.close(plain, CloseReason::Expired).await.unwrap();
+        while rx.try_recv().is_ok() {}
+
+        let history = source.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].id, id);
+        assert_eq!(history[1].notification.summary, "v2");
+        assert_eq!(history[1].sender.as_deref(), Some(":1.7"));
+        assert_eq!(history[1].reason, CloseReason::Dismissed);
+
+        let shown = |rx: &mut mpsc::Receiver<NotificationEvent>| match rx.try_recv() {
+            Ok(NotificationEvent::Historic {
+                actions_enabled, ..
+            }) => actions_enabled,
+            other => panic!("unexpected event: {other:?}"),
+        };
+        assert_eq!(source.show_historic(id, true), Ok(id));
+        assert!(shown(&mut rx));
+        source.show_historic(id, false).unwrap();
+        assert!(!shown(&mut rx));
+        source.show_historic(plain, true).unwrap();
+        assert!(!shown(&mut rx), "nothing to enable without actions");
+
+        assert_eq!(source.invoke_historic_action(id, "open").await, Ok(()));
+        assert_eq!(
+            source.invoke_historic_action(id, "close").await,
+            Err(CommandError::UnknownAction)
+        );
+        assert_eq!(
+            source.invoke_historic_action(0, "open").await,
+            Err(CommandError::NotFound),
+            "positions only select entries to show"
+        );
+        assert!(rx.try_recv().is_err());
+        assert_eq!(source.state().count, 0);
+    }
 
+    #[tokio::test]
+    async fn replacements_collapse_into_one_history_entry_until_closed() {
+        let (source, mut rx) = WispSource::new(SourceConfig {
+            history_intermediate_summaries: 2,
+            ..SourceConfig::default()
+        });
+        let before = SystemTime::now();
         let id = source
-            .notify(test_notification("no action"), 0)
+            .notify(test_notification("Downloading 0%"), 0)
             .await
             .unwrap();
-        let _ = rx.recv().await;
-
-        let invoked = source.invoke_action(id, "open").await.unwrap();
-        assert!(!invoked);
+        for summary in ["Downloading 30%", "Downloading 60%", "Download finished"] {
+            let replaced = source.notify(test_notification(summary), id).await.unwrap();
+            assert_eq!(replaced, id);
+        }
+        assert!(
+            source.history().is_empty(),
+            "the chain is open until closed"
+        );
 
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(maybe_event.is_err(), "unexpected event was emitted");
+        source.close(id, CloseReason::Dismissed).await.unwrap();
+        let history = source.history();
+        assert_eq!(history.len(), 1, "one entry for the whole chain");
+        let entry = &history[0];
+        assert_eq!(entry.notification.summary, "Download finished");
+        assert!(entry.received_at >= before);
+        assert_eq!(entry.chain.replace_count, 3);
+        assert_eq!(
+            entry.chain.intermediate_summaries,
+            ["Downloading 30%", "Downloading 60%"]
+        );
+        assert_eq!(entry.chain.describe().as_deref(), Some("3 updates, 0s"));
+
+        let next = source.notify(test_notification("fresh"), id).await.unwrap();
+        assert_ne!(next, id, "a closed id starts a new chain");
+        source.close(next, CloseReason::Expired).await.unwrap();
+        assert_eq!(source.history()[0].chain.replace_count, 0);
+        assert!(source.history()[0].chain.intermediate_summaries.is_empty());
+
+        while rx.try_recv().is_ok() {}
+        source.show_historic(id, false).unwrap();
+        match rx.try_recv() {
+            Ok(NotificationEvent::Historic { chain, .. }) => assert_eq!(chain.replace_count, 3),
+            other => panic!("unexpected event: {other:?}"),
+        }
     }
 
     #[tokio::test]
-    async fn snapshot_reflects_replace_and_close_state() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn starred_history_entries_outlive_the_ttl() {
+        let (source, _rx) = WispSource::new(SourceConfig::default());
+        for summary in ["keep", "drop"] {
+            let id = source.notify(test_notification(summary), 0).await.unwrap();
+            source.close(id, CloseReason::Dismissed).await.unwrap();
+        }
+        let keep = source.history()[1].id;
+        assert_eq!(source.set_history_starred(1, true), Ok(keep), "by position");
+        assert_eq!(
+            source.set_history_starred(99, true),
+            Err(CommandError::NotFound)
+        );
 
-        let id = source.notify(test_notification("first"), 0).await.unwrap();
-        let _ = rx.recv().await;
+        source.set_history_ttl(Some(Duration::ZERO));
+        let history = source.history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, keep);
+        assert!(history[0].starred);
+    }
 
-        source
-            .notify(test_notification("second"), id)
-            .await
-            .unwrap();
-        let _ = rx.recv().await;
+    #[tokio::test]
+    async fn invoke_action_emits_action_invoked_signal() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
 
-        let snapshot = source.snapshot().await;
-        assert_eq!(snapshot.len(), 1);
-        assert_eq!(snapshot[0].0, id);
-        assert_eq!(snapshot[0].1.summary, "second");
+        let id = bus.notify(0, "hello", &["open", "Open"], 10_000).await;
+        test.expect_received().await;
 
-        let closed = source.close(id, CloseReason::ClosedByCall).await.unwrap();
-        assert!(closed);
-        let _ = rx.recv().await;
+        test.source().invoke_action(id, "open").await.unwrap();
 
-        assert!(source.snapshot().await.is_empty());
+        let signal = bus.expect_signal("ActionInvoked").await;
+        let (signal_id, action_key): (u32, String) = signal.body().deserialize().unwrap();
+        assert_eq!(signal_id, id);
+        assert_eq!(action_key, "open");
     }
 
     #[tokio::test]
-    async fn close_unknown_id_is_safe_noop() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
-
-        let closed = source.close(42, CloseReason::ClosedByCall).await.unwrap();
-        assert!(!closed);
+    async fn reply_sends_the_text_and_closes_unless_configured_otherwise() {
+        let mut test = TestSource::new();
+        let id = test.notify(test_notification("chat")).await;
+        test.expect_received().await;
+        test.source().reply(id, "on my way").await.unwrap();
+        test.expect_replied(id, "on my way").await;
+        test.expect_closed(id, CloseReason::Dismissed).await;
+        assert_eq!(
+            test.source().reply(id, "again").await,
+            Err(CommandError::NotFound)
+        );
 
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(maybe_event.is_err(), "unexpected event was emitted");
+        let mut test = TestSource::with_config(SourceConfig {
+            close_on_reply: false,
+            ..SourceConfig::default()
+        });
+        let id = test.notify(test_notification("chat")).await;
+        test.expect_received().await;
+        test.source().reply(id, "first").await.unwrap();
+        test.source().reply(id, "second").await.unwrap();
+        test.expect_replied(id, "first").await;
+        test.expect_replied(id, "second").await;
+        test.expect_no_event().await;
+        assert!(test.source().get(id).is_some());
     }
 
-    #[test]
-    fn parse_hints_extracts_known_fields_exactly() {
-        let mut raw_hints: HashMap<String, zvariant::OwnedValue> = HashMap::new();
-        raw_hints.insert("urgency".to_string(), 0_u8.into());
-        raw_hints.insert(
-            "category".to_string(),
-            zvariant::OwnedValue::from(zvariant::Str::from("email.arrived")),
-        );
-        raw_hints.insert(
-            "desktop-entry".to_string(),
-            zvariant::OwnedValue::from(zvariant::Str::from("org.example.Mail")),
+    #[tokio::test]
+    async fn reply_emits_notification_replied_before_closing() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig {
+            capabilities: Features {
+                inline_reply: true,
+                ..Features::from_capabilities(["body"])
+            }
+            .capabilities(),
+            ..SourceConfig::default()
+        })
+        .await;
+        assert!(
+            bus.get_capabilities()
+                .await
+                .contains(&"inline-reply".to_string())
         );
-        raw_hints.insert("transient".to_string(), zvariant::OwnedValue::from(true));
 
-        let (urgency, hints) = parse_hints(&raw_hints);
+        let id = bus.notify(0, "chat", &[], 10_000).await;
+        test.expect_received().await;
+        test.source().reply(id, "héllo ✓").await.unwrap();
 
-        assert_eq!(urgency, Urgency::Low);
-        assert_eq!(hints.category.as_deref(), Some("email.arrived"));
-        assert_eq!(hints.desktop_entry.as_deref(), Some("org.example.Mail"));
-        assert_eq!(hints.transient, Some(true));
-        assert!(hints.extra.is_empty());
+        let signal = bus.expect_signal("NotificationReplied").await;
+        let (signal_id, text): (u32, String) = signal.body().deserialize().unwrap();
+        assert_eq!((signal_id, text.as_str()), (id, "héllo ✓"));
+        let signal = bus.expect_signal("NotificationClosed").await;
+        let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
+        assert_eq!((signal_id, reason_code), (id, 2));
     }
 
-    #[test]
-    fn parse_actions_handles_empty_and_odd_action_lists_safely() {
-        assert!(parse_actions(Vec::new()).is_empty());
-
-        let parsed = parse_actions(vec!["only-key".to_string()]);
-        assert!(parsed.is_empty());
+    #[tokio::test]
+    async fn get_stats_reports_the_source_counters_over_the_bus() {
+        let (mut test, bus) = PeerBus::start(SourceConfig {
+            rate_limit: Some(RateLimit {
+                per_app_per_minute: 0,
+                burst: 1,
+            }),
+            ..SourceConfig::default()
+        })
+        .await;
 
-        let parsed = parse_actions(vec![
-            "open".to_string(),
-            "Open".to_string(),
-            "dangling".to_string(),
-        ]);
-        assert_eq!(parsed.len(), 1);
-        assert_eq!(parsed[0].key, "open");
-        assert_eq!(parsed[0].label, "Open");
+        for n in 0..3 {
+            bus.notify(0, &format!("spam {n}"), &[], 10_000).await;
+        }
+        test.drain();
+        let stats = bus.get_stats().await;
+        let count = |key: &str| u64::try_from(&stats[key]).unwrap();
+        assert_eq!(count("rate_limited"), 2);
+        assert_eq!(count("oversized_hints"), 0);
+        assert_eq!(count("dropped_events"), 0);
+        assert_eq!(count("failed_signal_emissions"), 0);
+        let by_app: HashMap<String, u64> = stats["oversized_hints_by_app"]
+            .try_clone()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(by_app.is_empty());
     }
 
-    async fn setup_dbus_source_for_test(
-        suffix: &str,
-    ) -> Option<(
-        SourceConfig,
-        WispSource,
-        mpsc::Receiver<NotificationEvent>,
-        DbusService,
-        zbus::Connection,
-    )> {
-        let unique = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    #[tokio::test]
+    async fn app_counters_track_the_bus_and_mark_seen_resets_unseen() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+
+        let first = bus.notify(0, "one", &[], 10_000).await;
+        bus.notify(0, "two", &[], 10_000).await;
+        bus.close_notification(first).await;
+        test.drain();
+        assert_eq!(
+            bus.get_app_counters().await,
+            HashMap::from([("test-client".to_string(), (2, 1, 1))])
+        );
+
+        // Normally one coalesced signal; a slow runner may split the burst.
+        loop {
+            let signal = bus.expect_signal("CountersChanged").await;
+            let counters: HashMap<String, (u32, u32, u32)> = signal.body().deserialize().unwrap();
+            if counters["test-client"] == (2, 1, 1) {
+                break;
+            }
+        }
 
-        let cfg = SourceConfig {
-            dbus_name: format!("org.wispd.{suffix}.{unique}"),
-            ..SourceConfig::default()
-        };
+        bus.mark_seen("test-client").await;
+        assert_eq!(bus.get_app_counters().await["test-client"], (0, 1, 1));
+        let signal = bus.expect_signal("CountersChanged").await;
+        let counters: HashMap<String, (u32, u32, u32)> = signal.body().deserialize().unwrap();
+        assert_eq!(counters["test-client"], (0, 1, 1));
+    }
 
-        let Ok((source, rx, service)) = WispSource::start_dbus(cfg.clone()).await else {
-            eprintln!("skipping dbus integration test: session bus unavailable");
-            return None;
-        };
+    #[tokio::test]
+    async fn escalate_relays_the_stored_notification_on_the_control_interface() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+        let id = bus.notify(0, "disk full", &[], 0).await;
+        test.expect_received().await;
 
-        let Ok(client) = zbus::Connection::session().await else {
-            eprintln!("skipping dbus integration test: session bus unavailable");
-            return None;
-        };
+        test.source().escalate(id, 2).await.unwrap();
 
-        Some((cfg, source, rx, service, client))
+        let signal = bus.expect_signal("Escalated").await;
+        let escalated: (u32, String, String, String, u32) = signal.body().deserialize().unwrap();
+        assert_eq!(
+            escalated,
+            (
+                id,
+                "test-client".to_string(),
+                "disk full".to_string(),
+                String::new(),
+                2
+            )
+        );
+        assert_eq!(
+            test.source().escalate(id + 1, 1).await,
+            Err(CommandError::NotFound)
+        );
     }
 
-    async fn make_notifications_proxy<'a>(
-        client: &'a zbus::Connection,
-        cfg: &'a SourceConfig,
-    ) -> zbus::Result<zbus::Proxy<'a>> {
-        zbus::Proxy::new(
-            client,
-            cfg.dbus_name.as_str(),
-            cfg.dbus_path.as_str(),
-            DBUS_INTERFACE,
-        )
-        .await
+    #[tokio::test]
+    async fn control_interface_lists_closes_all_and_reports_do_not_disturb() {
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+        let timed = bus.notify(0, "one", &["default", "Open"], 10_000).await;
+        let persistent = bus.notify(0, "two", &[], 0).await;
+
+        let listed = bus.list_notifications().await;
+        assert_eq!(
+            listed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [timed, persistent]
+        );
+        let (_, fields) = &listed[0];
+        assert_eq!(<&str>::try_from(&fields["summary"]).unwrap(), "one");
+        assert_eq!(
+            <&str>::try_from(&fields["app_name"]).unwrap(),
+            "test-client"
+        );
+        assert_eq!(u8::try_from(&fields["urgency"]).unwrap(), 1);
+        assert_eq!(i32::try_from(&fields["expire_timeout"]).unwrap(), 10_000);
+        assert_eq!(
+            Vec::<String>::try_from(fields["actions"].try_clone().unwrap()).unwrap(),
+            ["default", "Open"]
+        );
+        assert!(fields.contains_key("remaining_ms"));
+        assert!(!listed[1].1.contains_key("remaining_ms"), "never expires");
+
+        assert!(!bus.get_do_not_disturb().await);
+        bus.set_do_not_disturb(true).await;
+        let signal = bus.expect_signal("DndChanged").await;
+        assert!(signal.body().deserialize::<bool>().unwrap());
+        assert!(bus.get_do_not_disturb().await);
+
+        assert_eq!(bus.close_all(2).await, [timed, persistent]);
+        assert!(bus.list_notifications().await.is_empty());
+        let closed: Vec<_> = test
+            .drain()
+            .into_iter()
+            .filter(|event| matches!(event, NotificationEvent::Closed { .. }))
+            .collect();
+        assert_eq!(
+            closed,
+            [timed, persistent].map(|id| NotificationEvent::Closed {
+                id,
+                reason: CloseReason::Dismissed
+            })
+        );
     }
 
     #[tokio::test]
-    async fn dbus_notify_emits_received_event() {
-        let Some((cfg, _source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("Notify").await
-        else {
-            return;
+    async fn count_and_do_not_disturb_properties_announce_their_changes() {
+        let (_test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+        let count = |changed: &HashMap<String, zvariant::OwnedValue>| {
+            u32::try_from(&changed["Count"]).expect("Count is u")
         };
-
-        let mut hints = HashMap::<String, zvariant::OwnedValue>::new();
-        hints.insert("urgency".to_string(), zvariant::OwnedValue::from(2_u8));
-        hints.insert(
-            "category".to_string(),
-            zvariant::OwnedValue::from(zvariant::Str::from("mail.arrived")),
+        assert_eq!(
+            u32::try_from(bus.control_property("Count").await).unwrap(),
+            0
         );
-        hints.insert(
-            "desktop-entry".to_string(),
-            zvariant::OwnedValue::from(zvariant::Str::from("org.example.Mail")),
+
+        let id = bus.notify(0, "one", &[], 0).await;
+        assert_eq!(count(&bus.expect_properties_changed().await), 1);
+        assert_eq!(
+            u32::try_from(bus.control_property("Count").await).unwrap(),
+            1
         );
-        hints.insert("transient".to_string(), zvariant::OwnedValue::from(true));
-        hints.insert("x-foo".to_string(), zvariant::OwnedValue::from(42_i32));
 
-        let msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::from("test-icon"),
-                    String::from("hello"),
-                    String::from("world"),
-                    Vec::<String>::new(),
-                    hints,
-                    2_500_i32,
-                ),
-            )
-            .await
-            .unwrap();
+        bus.close_notification(id).await;
+        assert_eq!(count(&bus.expect_properties_changed().await), 0);
 
-        let id: u32 = msg.body().deserialize().unwrap();
+        bus.set_do_not_disturb(true).await;
+        let changed = bus.expect_properties_changed().await;
+        assert!(bool::try_from(&changed["DoNotDisturb"]).unwrap());
+        assert!(!changed.contains_key("Count"));
+        assert!(bool::try_from(bus.control_property("DoNotDisturb").await).unwrap());
+    }
 
-        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
+    #[tokio::test]
+    async fn manual_do_not_disturb_is_reported_in_get_state() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
+        let dnd_keys = |state: &HashMap<String, zvariant::OwnedValue>| {
+            ["dnd", "dnd_scheduled", "dnd_override"]
+                .map(|key| bool::try_from(&state[key]).expect("dnd keys are b"))
+        };
+        assert_eq!(dnd_keys(&bus.get_state().await), [false, false, false]);
 
-        match event {
-            NotificationEvent::Received {
-                id: event_id,
-                notification,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(notification.app_icon, "test-icon");
-                assert_eq!(notification.urgency, Urgency::Critical);
-                assert_eq!(notification.hints.category.as_deref(), Some("mail.arrived"));
-                assert_eq!(
-                    notification.hints.desktop_entry.as_deref(),
-                    Some("org.example.Mail")
-                );
-                assert_eq!(notification.hints.transient, Some(true));
-                assert!(notification.hints.extra.contains_key("x-foo"));
-            }
-            other => panic!("unexpected event: {other:?}"),
-        }
+        bus.set_do_not_disturb(true).await;
+        assert_eq!(
+            test.next_event().await,
+            NotificationEvent::DndChanged { enabled: true }
+        );
+        assert_eq!(dnd_keys(&bus.get_state().await), [true, false, true]);
+
+        // Switching to what the schedule says drops the override.
+        bus.set_do_not_disturb(false).await;
+        assert_eq!(
+            test.next_event().await,
+            NotificationEvent::DndChanged { enabled: false }
+        );
+        assert_eq!(dnd_keys(&bus.get_state().await), [false, false, false]);
     }
 
     #[tokio::test]
-    async fn dbus_close_notification_emits_closed_event() {
-        let Some((cfg, _source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("Close").await
-        else {
-            return;
+    async fn a_schedule_covering_now_enables_do_not_disturb_until_overridden() {
+        let test = TestSource::new();
+        let now = chrono::Local::now().time();
+        // Wraps past midnight when needed; either way the window contains `now`.
+        let window = DndWindow {
+            days: Vec::new(),
+            from: now - chrono::TimeDelta::hours(1),
+            until: now + chrono::TimeDelta::hours(1),
         };
+        test.source().set_dnd_schedule(vec![window]);
+        assert_eq!(
+            test.source().do_not_disturb(),
+            DndStatus {
+                enabled: true,
+                scheduled: true,
+                overridden: false,
+            }
+        );
 
-        let notify_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::new(),
-                    String::from("hello"),
-                    String::from("world"),
-                    Vec::<String>::new(),
-                    HashMap::<String, zvariant::OwnedValue>::new(),
-                    10_000_i32,
-                ),
-            )
-            .await
-            .unwrap();
-        let id: u32 = notify_msg.body().deserialize().unwrap();
-        let _ = rx.recv().await;
-
-        client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "CloseNotification",
-                &(id),
-            )
-            .await
-            .unwrap();
+        test.source().set_do_not_disturb(false);
+        assert!(!test.source().is_do_not_disturb());
+        assert!(test.source().do_not_disturb().overridden);
 
-        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
-            .await
-            .unwrap()
-            .unwrap();
+        test.source().set_dnd_schedule(Vec::new());
+        assert_eq!(test.source().do_not_disturb(), DndStatus::default());
+    }
 
-        match event {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::ClosedByCall);
-            }
-            other => panic!("unexpected event: {other:?}"),
+    /// `silent` of the next event, which must be a `Received`.
+    async fn received_silent(rx: &mut mpsc::Receiver<NotificationEvent>) -> bool {
+        match rx.recv().await.unwrap() {
+            NotificationEvent::Received { silent, .. } => silent,
+            other => panic!("expected Received, got {other:?}"),
         }
     }
 
-    #[tokio::test]
-    async fn dbus_close_notification_emits_notification_closed_signal() {
-        let Some((cfg, _source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("CloseSignal").await
-        else {
-            return;
-        };
+    #[tokio::test(start_paused = true)]
+    async fn notifications_under_do_not_disturb_are_stored_silently() {
+        let (source, mut rx) = WispSource::new(SourceConfig {
+            default_timeout_ms: Some(50),
+            ..SourceConfig::default()
+        });
+        source.notify(test_notification("before"), 0).await.unwrap();
+        assert!(!received_silent(&mut rx).await);
 
-        let proxy = make_notifications_proxy(&client, &cfg).await.unwrap();
-        let mut closed_stream = proxy.receive_signal("NotificationClosed").await.unwrap();
+        source.set_do_not_disturb(true);
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            NotificationEvent::DndChanged { enabled: true }
+        );
+        let quiet = source.notify(test_notification("during"), 0).await.unwrap();
+        assert!(received_silent(&mut rx).await);
+        let critical = Notification {
+            urgency: Urgency::Critical,
+            ..test_notification("critical")
+        };
+        source.notify(critical, 0).await.unwrap();
+        assert!(
+            !received_silent(&mut rx).await,
+            "critical bypasses by default"
+        );
 
-        let notify_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::new(),
-                    String::from("hello"),
-                    String::from("world"),
-                    Vec::<String>::new(),
-                    HashMap::<String, zvariant::OwnedValue>::new(),
-                    10_000_i32,
-                ),
-            )
-            .await
-            .unwrap();
-        let id: u32 = notify_msg.body().deserialize().unwrap();
+        source.set_do_not_disturb(false);
         let _ = rx.recv().await;
+        source.notify(test_notification("after"), 0).await.unwrap();
+        assert!(!received_silent(&mut rx).await);
 
-        client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "CloseNotification",
-                &(id),
-            )
-            .await
-            .unwrap();
-
-        let signal = tokio::time::timeout(Duration::from_secs(2), closed_stream.next())
-            .await
-            .unwrap()
-            .unwrap();
-        let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
-        assert_eq!(signal_id, id);
-        assert_eq!(reason_code, 3);
+        assert_eq!(source.snapshot().await.len(), 4, "silent ones are stored");
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(
+            drain_events(&mut rx).contains(&NotificationEvent::Closed {
+                id: quiet,
+                reason: CloseReason::Expired,
+            }),
+            "and expire as usual"
+        );
     }
 
     #[tokio::test]
-    async fn invoke_action_emits_action_invoked_signal() {
-        let Some((cfg, source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("ActionSignal").await
-        else {
-            return;
+    async fn critical_notifications_can_be_silenced_too() {
+        let (source, mut rx) = WispSource::new(SourceConfig {
+            critical_bypasses_dnd: false,
+            ..SourceConfig::default()
+        });
+        source.set_do_not_disturb(true);
+        let _ = rx.recv().await;
+        let critical = Notification {
+            urgency: Urgency::Critical,
+            ..test_notification("critical")
         };
+        source.notify(critical, 0).await.unwrap();
+        assert!(received_silent(&mut rx).await);
+    }
 
-        let proxy = make_notifications_proxy(&client, &cfg).await.unwrap();
-        let mut action_stream = proxy.receive_signal("ActionInvoked").await.unwrap();
-
-        let notify_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::new(),
-                    String::from("hello"),
-                    String::from("world"),
-                    vec![String::from("open"), String::from("Open")],
-                    HashMap::<String, zvariant::OwnedValue>::new(),
-                    10_000_i32,
-                ),
-            )
-            .await
-            .unwrap();
-        let id: u32 = notify_msg.body().deserialize().unwrap();
-        let _ = rx.recv().await;
+    #[tokio::test]
+    async fn app_counters_survive_runtime_config_reload() {
+        let test = TestSource::new();
+        test.notify(test_notification("kept")).await;
+        let before = test.source().app_counters();
 
-        let invoked = source.invoke_action(id, "open").await.unwrap();
-        assert!(invoked);
+        test.source()
+            .update_runtime_config(vec!["body".to_string()], Some(25));
 
-        let signal = tokio::time::timeout(Duration::from_secs(2), action_stream.next())
-            .await
-            .unwrap()
-            .unwrap();
-        let (signal_id, action_key): (u32, String) = signal.body().deserialize().unwrap();
-        assert_eq!(signal_id, id);
-        assert_eq!(action_key, "open");
+        assert_eq!(test.source().app_counters(), before);
     }
 
     #[tokio::test]
     async fn dbus_get_capabilities_returns_configured_capabilities() {
-        let Some((cfg, _source, _rx, _service, client)) =
-            setup_dbus_source_for_test("Capabilities").await
-        else {
-            return;
-        };
-
-        let msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "GetCapabilities",
-                &(),
-            )
-            .await
-            .unwrap();
+        let cfg = SourceConfig::default();
+        let (_test, bus) = PeerBus::start(cfg.clone()).await;
 
-        let capabilities: Vec<String> = msg.body().deserialize().unwrap();
-        assert_eq!(capabilities, cfg.capabilities);
+        assert_eq!(bus.get_capabilities().await, cfg.capabilities);
     }
 
     #[tokio::test]
     async fn dbus_get_server_information_returns_configured_values() {
-        let Some((cfg, _source, _rx, _service, client)) =
-            setup_dbus_source_for_test("ServerInfo").await
-        else {
-            return;
-        };
-
-        let msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "GetServerInformation",
-                &(),
-            )
-            .await
-            .unwrap();
+        let cfg = SourceConfig::default();
+        let (_test, bus) = PeerBus::start(cfg.clone()).await;
 
-        let info: (String, String, String, String) = msg.body().deserialize().unwrap();
         assert_eq!(
-            info,
+            bus.get_server_information().await,
             (
                 cfg.server_name,
                 cfg.server_vendor,
@@ -1353,43 +5568,18 @@ mod tests {
 
     #[tokio::test]
     async fn dbus_capabilities_and_server_info_stay_correct_after_runtime_reload() {
-        let Some((cfg, source, _rx, _service, client)) =
-            setup_dbus_source_for_test("ReloadInfo").await
-        else {
-            return;
-        };
+        let cfg = SourceConfig::default();
+        let (test, bus) = PeerBus::start(cfg.clone()).await;
 
-        source.update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(50));
+        test.source()
+            .update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(50));
 
-        let caps_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "GetCapabilities",
-                &(),
-            )
-            .await
-            .unwrap();
-        let capabilities: Vec<String> = caps_msg.body().deserialize().unwrap();
         assert_eq!(
-            capabilities,
+            bus.get_capabilities().await,
             vec!["body".to_string(), "actions".to_string()]
         );
-
-        let info_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "GetServerInformation",
-                &(),
-            )
-            .await
-            .unwrap();
-        let info: (String, String, String, String) = info_msg.body().deserialize().unwrap();
         assert_eq!(
-            info,
+            bus.get_server_information().await,
             (
                 cfg.server_name,
                 cfg.server_vendor,
@@ -1400,200 +5590,143 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn runtime_config_update_changes_capabilities_and_default_timeout() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn dbus_get_capabilities_reflects_toggled_features() {
+        let (test, bus) = PeerBus::start(SourceConfig::default()).await;
+        let source = test.source();
+
+        let mut features = Features::from_capabilities(source.capabilities());
+        features.body_markup = true;
+        features.persistence = true;
+        source.set_capabilities(features.clone());
+        let capabilities = bus.get_capabilities().await;
+        assert!(capabilities.contains(&"body-markup".to_string()));
+        assert!(capabilities.contains(&"persistence".to_string()));
+
+        features.body_markup = false;
+        source.set_capabilities(features);
+        let capabilities = bus.get_capabilities().await;
+        assert!(!capabilities.contains(&"body-markup".to_string()));
+        assert!(capabilities.contains(&"persistence".to_string()));
+    }
 
-        source.update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(25));
-        assert_eq!(
-            source.capabilities(),
-            vec!["body".to_string(), "actions".to_string()]
-        );
+    #[tokio::test]
+    async fn action_icons_are_advertised_next_to_actions_when_configured() {
+        let (source, _rx) = WispSource::new(SourceConfig {
+            capabilities: vec!["body".to_string(), "actions".to_string()],
+            action_icons: true,
+            ..SourceConfig::default()
+        });
+        assert_eq!(source.capabilities(), ["body", "actions", "action-icons"]);
 
-        let id = source
-            .notify(test_notification("reload-timeout"), 0)
-            .await
-            .unwrap();
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
-        }
+        source.set_capabilities(Features::from_capabilities(["body"]));
+        assert_eq!(source.capabilities(), ["body"]);
+        source.update_runtime_config(
+            vec!["actions".to_string(), "action-icons".to_string()],
+            None,
+        );
+        assert_eq!(source.capabilities(), ["actions", "action-icons"]);
 
-        match tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap()
-        {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::Expired);
-            }
-            other => panic!("unexpected event: {other:?}"),
-        }
+        let (source, _rx) = WispSource::new(SourceConfig {
+            capabilities: vec!["body".to_string(), "actions".to_string()],
+            ..SourceConfig::default()
+        });
+        assert_eq!(source.capabilities(), ["body", "actions"]);
     }
 
     #[tokio::test]
-    async fn runtime_config_update_does_not_duplicate_or_drop_active_timers() {
-        let (source, mut rx) = WispSource::new(SourceConfig::default());
+    async fn dbus_control_invoke_action_emits_action_invoked() {
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
 
-        let id = source
-            .notify(
-                Notification {
-                    timeout_ms: 40,
-                    ..test_notification("active-timer")
-                },
-                0,
-            )
-            .await
-            .unwrap();
+        let id = test
+            .notify(test_notification_with_action("control", "open"))
+            .await;
+        test.expect_received().await;
 
-        match rx.recv().await.unwrap() {
-            NotificationEvent::Received { id: event_id, .. } => assert_eq!(event_id, id),
-            other => panic!("unexpected event: {other:?}"),
-        }
+        assert_eq!(
+            bus.control_invoke_action(id, "missing").await,
+            Err(CommandError::UnknownAction)
+        );
+        assert_eq!(bus.control_invoke_action(id, "open").await, Ok(true));
 
-        source.update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(5));
+        test.expect_action_invoked(id, "open").await;
+        assert_eq!(
+            bus.control_invoke_action(id, "open").await,
+            Err(CommandError::NotFound),
+            "notification is gone after the action"
+        );
+    }
 
-        match tokio::time::timeout(Duration::from_secs(1), rx.recv())
-            .await
-            .unwrap()
-            .unwrap()
-        {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::Expired);
-            }
-            other => panic!("unexpected event: {other:?}"),
-        }
+    #[tokio::test(start_paused = true)]
+    async fn runtime_config_update_changes_capabilities_and_default_timeout() {
+        let mut test = TestSource::new();
 
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(
-            maybe_event.is_err(),
-            "unexpected extra timeout event was emitted"
+        test.source()
+            .update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(25));
+        assert_eq!(
+            test.source().capabilities(),
+            vec!["body".to_string(), "actions".to_string()]
         );
+
+        let id = test.notify(test_notification("reload-timeout")).await;
+        assert_eq!(test.expect_received().await.0, id);
+
+        test.advance(25).await;
+        test.expect_closed(id, CloseReason::Expired).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runtime_config_update_does_not_duplicate_or_drop_active_timers() {
+        let mut test = TestSource::new();
+
+        let id = test
+            .notify(Notification {
+                timeout_ms: 40,
+                ..test_notification("active-timer")
+            })
+            .await;
+        assert_eq!(test.expect_received().await.0, id);
+
+        test.source()
+            .update_runtime_config(vec!["body".to_string(), "actions".to_string()], Some(5));
+
+        test.advance(40).await;
+        test.expect_closed(id, CloseReason::Expired).await;
+        test.advance(1_000).await;
+        test.expect_no_event().await;
     }
 
     #[tokio::test]
     async fn dbus_notify_burst_preserves_order_and_ids() {
-        let Some((cfg, _source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("Burst").await
-        else {
-            return;
-        };
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
 
         let mut ids = Vec::new();
         for summary in ["one", "two", "three"] {
-            let msg = client
-                .call_method(
-                    Some(cfg.dbus_name.as_str()),
-                    cfg.dbus_path.as_str(),
-                    Some(DBUS_INTERFACE),
-                    "Notify",
-                    &(
-                        String::from("test-client"),
-                        0_u32,
-                        String::new(),
-                        String::from(summary),
-                        String::new(),
-                        Vec::<String>::new(),
-                        HashMap::<String, zvariant::OwnedValue>::new(),
-                        10_000_i32,
-                    ),
-                )
-                .await
-                .unwrap();
-            ids.push(msg.body().deserialize::<u32>().unwrap());
+            ids.push(bus.notify(0, summary, &[], 10_000).await);
         }
 
         assert_eq!(ids.len(), 3);
         assert!(ids[0] < ids[1] && ids[1] < ids[2]);
 
         for (expected_id, expected_summary) in ids.into_iter().zip(["one", "two", "three"]) {
-            match tokio::time::timeout(Duration::from_secs(2), rx.recv())
-                .await
-                .unwrap()
-                .unwrap()
-            {
-                NotificationEvent::Received { id, notification } => {
-                    assert_eq!(id, expected_id);
-                    assert_eq!(notification.summary, expected_summary);
-                }
-                other => panic!("unexpected event: {other:?}"),
-            }
+            let (id, notification) = test.expect_received().await;
+            assert_eq!(id, expected_id);
+            assert_eq!(notification.summary, expected_summary);
         }
     }
 
     #[tokio::test]
     async fn dbus_replace_storm_leaves_single_final_live_notification() {
-        let Some((cfg, source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("ReplaceStorm").await
-        else {
-            return;
-        };
+        let (mut test, bus) = PeerBus::start(SourceConfig::default()).await;
 
-        let first = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::new(),
-                    String::from("first"),
-                    String::new(),
-                    Vec::<String>::new(),
-                    HashMap::<String, zvariant::OwnedValue>::new(),
-                    10_000_i32,
-                ),
-            )
-            .await
-            .unwrap();
-        let id: u32 = first.body().deserialize().unwrap();
-        let _ = rx.recv().await;
+        let id = bus.notify(0, "first", &[], 10_000).await;
+        test.expect_received().await;
 
         for summary in ["second", "third", "final"] {
-            let msg = client
-                .call_method(
-                    Some(cfg.dbus_name.as_str()),
-                    cfg.dbus_path.as_str(),
-                    Some(DBUS_INTERFACE),
-                    "Notify",
-                    &(
-                        String::from("test-client"),
-                        id,
-                        String::new(),
-                        String::from(summary),
-                        String::new(),
-                        Vec::<String>::new(),
-                        HashMap::<String, zvariant::OwnedValue>::new(),
-                        10_000_i32,
-                    ),
-                )
-                .await
-                .unwrap();
-            let replaced_id: u32 = msg.body().deserialize().unwrap();
-            assert_eq!(replaced_id, id);
-            match rx.recv().await.unwrap() {
-                NotificationEvent::Replaced {
-                    id: event_id,
-                    current,
-                    ..
-                } => {
-                    assert_eq!(event_id, id);
-                    assert_eq!(current.summary, summary);
-                }
-                other => panic!("unexpected event: {other:?}"),
-            }
+            assert_eq!(bus.notify(id, summary, &[], 10_000).await, id);
+            assert_eq!(test.expect_replaced(id).await.summary, summary);
         }
 
-        let snapshot = source.snapshot().await;
+        let snapshot = test.source().snapshot().await;
         assert_eq!(snapshot.len(), 1);
         assert_eq!(snapshot[0].0, id);
         assert_eq!(snapshot[0].1.summary, "final");
@@ -1601,85 +5734,23 @@ mod tests {
 
     #[tokio::test]
     async fn close_notification_during_active_timeout_emits_single_final_close() {
-        let Some((cfg, _source, mut rx, _service, client)) =
-            setup_dbus_source_for_test("CloseVsTimeout").await
-        else {
-            return;
-        };
-
-        let proxy = make_notifications_proxy(&client, &cfg).await.unwrap();
-        let mut closed_stream = proxy.receive_signal("NotificationClosed").await.unwrap();
-
-        let notify_msg = client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "Notify",
-                &(
-                    String::from("test-client"),
-                    0_u32,
-                    String::new(),
-                    String::from("hello"),
-                    String::new(),
-                    Vec::<String>::new(),
-                    HashMap::<String, zvariant::OwnedValue>::new(),
-                    200_i32,
-                ),
-            )
-            .await
-            .unwrap();
-        let id: u32 = notify_msg.body().deserialize().unwrap();
-        let _ = rx.recv().await;
+        let (mut test, mut bus) = PeerBus::start(SourceConfig::default()).await;
 
-        client
-            .call_method(
-                Some(cfg.dbus_name.as_str()),
-                cfg.dbus_path.as_str(),
-                Some(DBUS_INTERFACE),
-                "CloseNotification",
-                &(id),
-            )
-            .await
-            .unwrap();
+        let id = bus.notify(0, "hello", &[], 200).await;
+        test.expect_received().await;
 
-        match tokio::time::timeout(Duration::from_secs(2), rx.recv())
-            .await
-            .unwrap()
-            .unwrap()
-        {
-            NotificationEvent::Closed {
-                id: event_id,
-                reason,
-            } => {
-                assert_eq!(event_id, id);
-                assert_eq!(reason, CloseReason::ClosedByCall);
-            }
-            other => panic!("unexpected event: {other:?}"),
-        }
+        bus.close_notification(id).await;
+        test.expect_closed(id, CloseReason::ClosedByCall).await;
 
-        let signal = tokio::time::timeout(Duration::from_secs(2), closed_stream.next())
-            .await
-            .unwrap()
-            .unwrap();
+        let signal = bus.expect_signal("NotificationClosed").await;
         let (signal_id, reason_code): (u32, u32) = signal.body().deserialize().unwrap();
         assert_eq!(signal_id, id);
         assert_eq!(reason_code, 3);
 
-        tokio::time::sleep(Duration::from_millis(250)).await;
-
-        let maybe_event = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
-        assert!(
-            maybe_event.is_err(),
-            "unexpected extra close event was emitted"
-        );
-
-        let maybe_signal =
-            tokio::time::timeout(Duration::from_millis(50), closed_stream.next()).await;
-        assert!(
-            maybe_signal.is_err(),
-            "unexpected extra close signal was emitted"
-        );
+        // Past the original timeout: neither a second event nor a second signal.
+        test.advance(250).await;
+        test.expect_no_event().await;
+        bus.expect_no_signal("NotificationClosed");
     }
 
     #[tokio::test]
@@ -1704,11 +5775,11 @@ mod tests {
         assert_eq!(replaced_id, id);
         let _ = rx.recv().await;
 
-        let old_action_result = source.invoke_action(id, "open").await.unwrap();
-        assert!(!old_action_result);
-
-        let new_action_result = source.invoke_action(id, "reply").await.unwrap();
-        assert!(new_action_result);
+        assert_eq!(
+            source.invoke_action(id, "open").await,
+            Err(CommandError::UnknownAction)
+        );
+        source.invoke_action(id, "reply").await.unwrap();
 
         match rx.recv().await.unwrap() {
             NotificationEvent::ActionInvoked {
@@ -1758,8 +5829,7 @@ mod tests {
             .unwrap();
         let _ = rx.recv().await;
 
-        let invoked = source.invoke_action(id, "open").await.unwrap();
-        assert!(invoked);
+        source.invoke_action(id, "open").await.unwrap();
 
         match rx.recv().await.unwrap() {
             NotificationEvent::ActionInvoked {
@@ -1783,4 +5853,435 @@ mod tests {
             other => panic!("unexpected event: {other:?}"),
         }
     }
+
+    /// Deadlines in the expiry queue.
+    fn armed(source: &WispSource) -> usize {
+        source.inner.expiries.lock().unwrap().len()
+    }
+
+    /// Tasks alive on the test runtime besides the test itself, less the debounced
+    /// `CountersChanged` flush that may be pending.
+    fn alive_tasks(source: &WispSource) -> usize {
+        let flush = source.inner.counters_flush_pending.load(Ordering::Acquire);
+        Handle::current().metrics().num_alive_tasks() - usize::from(flush)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closing_or_replacing_disarms_the_pending_expiry() {
+        let test = TestSource::new();
+        let long = |summary| Notification {
+            timeout_ms: 60_000,
+            ..test_notification_with_action(summary, "open")
+        };
+
+        let id = test.notify(long("first")).await;
+        test.replace(long("second"), id).await;
+        let queue = |generation| {
+            test.source()
+                .inner
+                .expiries
+                .lock()
+                .unwrap()
+                .deadline(id, generation)
+        };
+        assert_eq!(queue(0), None, "the replacement's deadline takes over");
+        assert!(queue(1).is_some());
+        assert_eq!(armed(test.source()), 1);
+
+        test.source()
+            .close(id, CloseReason::Dismissed)
+            .await
+            .unwrap();
+        assert_eq!(armed(test.source()), 0, "nothing waits out a dismissed one");
+
+        let id = test.notify(long("third")).await;
+        test.source().invoke_action(id, "open").await.unwrap();
+        assert_eq!(armed(test.source()), 0);
+        test.advance(1).await;
+        assert_eq!(alive_tasks(test.source()), 1, "only the expiry driver");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ten_thousand_timeouts_share_one_task_and_all_expire() {
+        const COUNT: usize = 10_000;
+        let (source, mut rx) = WispSource::new(SourceConfig {
+            channel_capacity: 2 * COUNT,
+            ..SourceConfig::default()
+        });
+        for n in 0..COUNT {
+            source
+                .notify(
+                    Notification {
+                        timeout_ms: 60_000 + (n % 100) as i32,
+                        ..test_notification("flood")
+                    },
+                    0,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(armed(&source), COUNT);
+        assert_eq!(
+            alive_tasks(&source),
+            1,
+            "one driver, not a task per notification"
+        );
+
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        let mut expired = 0;
+        while expired < COUNT {
+            match rx.recv().await.unwrap() {
+                NotificationEvent::Closed {
+                    reason: CloseReason::Expired,
+                    ..
+                } => expired += 1,
+                NotificationEvent::Received { .. } => {}
+                other => panic!("unexpected event {other:?}"),
+            }
+        }
+        assert_eq!(armed(&source), 0, "the queue empties again");
+        assert!(source.snapshot_detailed().is_empty());
+        assert_eq!(alive_tasks(&source), 1);
+    }
+
+    #[tokio::test]
+    async fn expiry_waits_for_gate_even_after_deadline() {
+        let (source, mut rx) = WispSource::new(SourceConfig::default());
+        let (id, expiry) = source
+            .store_notification(
+                Notification {
+                    timeout_ms: 1,
+                    ..test_notification("gated")
+                },
+                0,
+                None,
+            )
+            .unwrap();
+        let _ = rx.recv().await;
+
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        source.arm_expiry_after(expiry, async move {
+            let _ = release_rx.await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(rx.try_recv().is_err(), "expired before gate released");
+        assert_eq!(source.snapshot().await.len(), 1);
+
+        release_tx.send(()).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            NotificationEvent::Closed {
+                id,
+                reason: CloseReason::Expired,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn dbus_notify_reply_precedes_instant_expiry_signal() {
+        let (_test, mut bus) = PeerBus::start(SourceConfig::default()).await;
+
+        for _ in 0..20 {
+            let notify_msg = bus.notify_call(0, "blink", &[], HashMap::new(), 1).await;
+            let id: u32 = notify_msg.body().deserialize().unwrap();
+
+            let mut saw_reply = false;
+            loop {
+                let msg = bus.next_message().await;
+                let header = msg.header();
+                match msg.message_type() {
+                    zbus::message::Type::MethodReturn
+                        if header.reply_serial() == notify_msg.header().reply_serial() =>
+                    {
+                        saw_reply = true;
+                    }
+                    zbus::message::Type::Signal
+                        if header
+                            .member()
+                            .is_some_and(|m| m.as_str() == "NotificationClosed") =>
+                    {
+                        let (signal_id, reason): (u32, u32) = msg.body().deserialize().unwrap();
+                        if signal_id == id {
+                            assert!(
+                                saw_reply,
+                                "NotificationClosed({id}) arrived before Notify reply"
+                            );
+                            assert_eq!(reason, 1);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// xorshift64; enough to replay a failing interleaving from its seed.
+    struct SeededRng(u64);
+
+    impl SeededRng {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    fn drain_events(rx: &mut mpsc::Receiver<NotificationEvent>) -> Vec<NotificationEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    /// Every id must go `Received`, any number of `Replaced`, then at most one `Closed`
+    /// with nothing after it; ids still in the store are exactly those never closed.
+    fn assert_consistent_lifecycles(events: &[NotificationEvent], live: &[u32], context: &str) {
+        #[derive(PartialEq)]
+        enum State {
+            Live,
+            Closed,
+        }
+        let mut states: HashMap<u32, State> = HashMap::new();
+        for event in events {
+            match event {
+                NotificationEvent::Received { id, .. } => {
+                    assert!(
+                        states.insert(*id, State::Live).is_none(),
+                        "{context}: id {id} received twice"
+                    );
+                }
+                NotificationEvent::Replaced { id, .. }
+                | NotificationEvent::ActionInvoked { id, .. } => {
+                    assert!(
+                        states.get(id) == Some(&State::Live),
+                        "{context}: {event:?} for an id that is not live"
+                    );
+                }
+                NotificationEvent::Closed { id, .. } => {
+                    assert!(
+                        states.insert(*id, State::Closed) == Some(State::Live),
+                        "{context}: {event:?} for an id that is not live"
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let mut expected_live: Vec<u32> = states
+            .iter()
+            .filter(|(_, state)| **state == State::Live)
+            .map(|(id, _)| *id)
+            .collect();
+        expected_live.sort_unstable();
+        let mut live = live.to_vec();
+        live.sort_unstable();
+        assert_eq!(
+            live, expected_live,
+            "{context}: store and event stream disagree"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_timer_does_not_expire_replacement_with_paused_clock() {
+        let mut test = TestSource::new();
+        let timed = |summary| Notification {
+            timeout_ms: 10,
+            ..test_notification(summary)
+        };
+
+        let id = test.notify(timed("first")).await;
+        test.advance(9).await;
+        assert_eq!(test.replace(timed("second"), id).await, id);
+
+        // The first generation's deadline passes without closing the replacement.
+        test.advance(2).await;
+        assert_eq!(test.source().snapshot().await.len(), 1);
+
+        test.advance(9).await;
+        assert!(test.source().snapshot().await.is_empty());
+
+        let events = test.drain();
+        assert!(matches!(events[0], NotificationEvent::Received { .. }));
+        assert!(matches!(events[1], NotificationEvent::Replaced { .. }));
+        assert_eq!(
+            events[2],
+            NotificationEvent::Closed {
+                id,
+                reason: CloseReason::Expired
+            }
+        );
+        assert_eq!(events.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pinned_notification_outlives_its_timeout_until_unpinned() {
+        let mut test = TestSource::with_config(SourceConfig {
+            default_timeout_ms: Some(50),
+            ..SourceConfig::default()
+        });
+        let source = test.source().clone();
+        let id = test
+            .notify(Notification {
+                timeout_ms: 10,
+                ..test_notification("meeting link")
+            })
+            .await;
+
+        assert!(source.set_pinned(id, true));
+        assert!(!source.set_pinned(id + 1, true));
+        assert_eq!(
+            source.state(),
+            SourceState {
+                count: 1,
+                pinned: 1
+            }
+        );
+
+        // Neither the original deadline nor a pinned replacement's closes it.
+        test.advance(20).await;
+        let replacement = Notification {
+            timeout_ms: 10,
+            ..test_notification("meeting link (moved)")
+        };
+        assert_eq!(source.notify(replacement, id).await.unwrap(), id);
+        test.advance(60_000).await;
+        assert_eq!(
+            source.state(),
+            SourceState {
+                count: 1,
+                pinned: 1
+            }
+        );
+
+        // Unpinning restarts expiry with the default timeout, not the sender's.
+        assert!(source.set_pinned(id, false));
+        assert_eq!(
+            source.state(),
+            SourceState {
+                count: 1,
+                pinned: 0
+            }
+        );
+        test.advance(40).await;
+        assert_eq!(source.snapshot().await.len(), 1);
+        test.advance(11).await;
+        assert!(source.snapshot().await.is_empty());
+
+        let events = test.drain();
+        assert_eq!(
+            events.last(),
+            Some(&NotificationEvent::Closed {
+                id,
+                reason: CloseReason::Expired
+            })
+        );
+        assert_eq!(events.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn seeded_interleavings_of_replace_close_and_expiry_stay_consistent() {
+        for seed in 0..64 {
+            let (source, mut rx) = WispSource::new(SourceConfig {
+                channel_capacity: 4096,
+                ..SourceConfig::default()
+            });
+            let first = source.notify(test_notification("seed"), 0).await.unwrap();
+
+            let mut rng = SeededRng::new(seed);
+            let mut ops = Vec::new();
+            for step in 0..24 {
+                // Ops land on a 1 ms grid so they collide with expiry deadlines.
+                let at = Duration::from_millis(rng.below(12));
+                let target = first + rng.below(3) as u32;
+                let op = rng.below(4);
+                let timeout_ms = [0, 1, 3, 5][rng.below(4) as usize];
+                let source = source.clone();
+                ops.push(tokio::spawn(async move {
+                    tokio::time::sleep(at).await;
+                    if op == 0 {
+                        let _ = source.close(target, CloseReason::ClosedByCall).await;
+                    } else {
+                        let notification = Notification {
+                            timeout_ms,
+                            ..test_notification(&format!("step {step}"))
+                        };
+                        let _ = source.notify(notification, target).await;
+                    }
+                }));
+            }
+            for op in ops {
+                op.await.unwrap();
+            }
+            // Let every armed timer run out.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            let live: Vec<u32> = source
+                .snapshot()
+                .await
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let events = drain_events(&mut rx);
+            assert_consistent_lifecycles(&events, &live, &format!("seed {seed}"));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_replace_and_close_stress_keeps_event_order_consistent() {
+        for iteration in 0..20 {
+            let (source, mut rx) = WispSource::new(SourceConfig {
+                channel_capacity: 16_384,
+                ..SourceConfig::default()
+            });
+            let first = source.notify(test_notification("stress"), 0).await.unwrap();
+
+            let workers: Vec<_> = (0..4)
+                .map(|worker| {
+                    let source = source.clone();
+                    tokio::spawn(async move {
+                        let mut rng = SeededRng::new(iteration * 4 + worker);
+                        for step in 0..100 {
+                            let target = first + rng.below(4) as u32;
+                            if rng.below(3) == 0 {
+                                let _ = source.close(target, CloseReason::ClosedByCall).await;
+                            } else {
+                                let notification = Notification {
+                                    timeout_ms: rng.below(3) as i32,
+                                    ..test_notification(&format!("{worker}/{step}"))
+                                };
+                                let _ = source.notify(notification, target).await;
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            let live: Vec<u32> = source
+                .snapshot()
+                .await
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let events = drain_events(&mut rx);
+            assert_consistent_lifecycles(&events, &live, &format!("iteration {iteration}"));
+            let visible: u32 = source.app_counters().values().map(|c| c.visible).sum();
+            assert_eq!(visible as usize, live.len(), "iteration {iteration}");
+        }
+    }
 }
@@ -0,0 +1,265 @@
+//! What the source does with events its consumer has no room for. Events that do not fit
+//! the channel are dropped or held back in a [`Backlog`] according to the
+//! [`OverflowPolicy`]; once anything was dropped, the next event to get through is a
+//! [`NotificationEvent::Overflowed`] so the consumer knows to resynchronize.
+
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+use wisp_types::NotificationEvent;
+
+/// How the source treats an event when the event channel is full.
+///
+/// Whatever the policy, the consumer sees [`NotificationEvent::Overflowed`] in place of
+/// the events it lost, and can rebuild its state from [`crate::WispSource::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    /// Drops the event that does not fit.
+    #[default]
+    DropNewest,
+    /// Holds up to `channel_capacity` events that do not fit, dropping the oldest held
+    /// one to make room for a newer one.
+    DropOldest,
+    /// Holds every event that does not fit and waits up to `timeout` for room for each,
+    /// dropping it when none comes. Notifying never waits for the consumer.
+    Block {
+        /// How long a held event waits for room before it is dropped.
+        timeout: Duration,
+    },
+}
+
+/// The consumer went away; nothing more can be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Closed;
+
+/// Outcome of [`Backlog::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Pushed {
+    /// Events dropped by this push, the pushed one or a held one.
+    pub(crate) dropped: u64,
+    /// Something still waits for room, so a flusher has to be started.
+    pub(crate) start_flusher: bool,
+}
+
+/// Events held back from a full channel, in order, and the count of those dropped since
+/// the consumer last heard about a drop.
+#[derive(Debug, Default)]
+pub(crate) struct Backlog {
+    held: VecDeque<NotificationEvent>,
+    dropped: u64,
+    flusher_running: bool,
+}
+
+impl Backlog {
+    /// Sends `event` behind whatever is held, or holds or drops it under `policy` when
+    /// the channel is full.
+    pub(crate) fn push(
+        &mut self,
+        sender: &mpsc::Sender<NotificationEvent>,
+        policy: OverflowPolicy,
+        capacity: usize,
+        event: NotificationEvent,
+    ) -> Result<Pushed, Closed> {
+        self.flush(sender)?;
+        let mut dropped = 0;
+        let event = if self.is_clear() {
+            match sender.try_send(event) {
+                Ok(()) => None,
+                Err(TrySendError::Full(event)) => Some(event),
+                Err(TrySendError::Closed(_)) => return Err(Closed),
+            }
+        } else {
+            Some(event)
+        };
+        if let Some(event) = event {
+            match policy {
+                OverflowPolicy::DropNewest => dropped += 1,
+                OverflowPolicy::DropOldest => {
+                    self.held.push_back(event);
+                    if self.held.len() > capacity {
+                        self.held.pop_front();
+                        dropped += 1;
+                    }
+                }
+                OverflowPolicy::Block { .. } => self.held.push_back(event),
+            }
+        }
+        self.dropped += dropped;
+        let start_flusher = !self.is_clear() && !self.flusher_running;
+        self.flusher_running |= start_flusher;
+        Ok(Pushed {
+            dropped,
+            start_flusher,
+        })
+    }
+
+    /// Sends as much as fits, the drop notice first.
+    pub(crate) fn flush(&mut self, sender: &mpsc::Sender<NotificationEvent>) -> Result<(), Closed> {
+        while !self.is_clear() {
+            match sender.try_reserve() {
+                Ok(permit) => permit.send(self.take_next().expect("backlog is not clear")),
+                Err(TrySendError::Full(())) => break,
+                Err(TrySendError::Closed(())) => return Err(Closed),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands `permit` the next event, the drop notice first. Returns whether more waits;
+    /// when not, the flusher is done.
+    pub(crate) fn send_next(&mut self, permit: mpsc::Permit<'_, NotificationEvent>) -> bool {
+        if let Some(event) = self.take_next() {
+            permit.send(event);
+        }
+        self.settle_flusher()
+    }
+
+    /// Drops the oldest held event after it waited out a `Block` timeout. Returns whether
+    /// one was dropped; the drop notice itself is never dropped.
+    pub(crate) fn drop_oldest(&mut self) -> bool {
+        let dropped = self.held.pop_front().is_some();
+        if dropped {
+            self.dropped += 1;
+        }
+        dropped
+    }
+
+    /// Whether only the drop notice waits, which no timeout applies to.
+    pub(crate) fn only_notice_left(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Marks the flusher stopped when nothing waits; returns whether something does.
+    pub(crate) fn settle_flusher(&mut self) -> bool {
+        self.flusher_running = !self.is_clear();
+        self.flusher_running
+    }
+
+    fn is_clear(&self) -> bool {
+        self.held.is_empty() && self.dropped == 0
+    }
+
+    fn take_next(&mut self) -> Option<NotificationEvent> {
+        if self.dropped > 0 {
+            let dropped = std::mem::take(&mut self.dropped);
+            return Some(NotificationEvent::Overflowed { dropped });
+        }
+        self.held.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wisp_types::CloseReason;
+
+    fn closed(id: u32) -> NotificationEvent {
+        NotificationEvent::Closed {
+            id,
+            reason: CloseReason::Expired,
+        }
+    }
+
+    fn push_all(
+        backlog: &mut Backlog,
+        sender: &mpsc::Sender<NotificationEvent>,
+        policy: OverflowPolicy,
+        ids: std::ops::RangeInclusive<u32>,
+    ) -> u64 {
+        ids.map(|id| backlog.push(sender, policy, 2, closed(id)).unwrap().dropped)
+            .sum()
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<NotificationEvent>) -> Vec<NotificationEvent> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn drop_newest_loses_what_does_not_fit_and_reports_it_first() {
+        let (sender, mut rx) = mpsc::channel(2);
+        let mut backlog = Backlog::default();
+
+        assert_eq!(
+            push_all(&mut backlog, &sender, OverflowPolicy::DropNewest, 1..=4),
+            2
+        );
+        assert_eq!(drain(&mut rx), [closed(1), closed(2)]);
+
+        push_all(&mut backlog, &sender, OverflowPolicy::DropNewest, 5..=5);
+        assert_eq!(
+            drain(&mut rx),
+            [NotificationEvent::Overflowed { dropped: 2 }, closed(5)]
+        );
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_held_events() {
+        let (sender, mut rx) = mpsc::channel(2);
+        let mut backlog = Backlog::default();
+
+        assert_eq!(
+            push_all(&mut backlog, &sender, OverflowPolicy::DropOldest, 1..=6),
+            2
+        );
+        assert_eq!(drain(&mut rx), [closed(1), closed(2)]);
+
+        backlog.flush(&sender).unwrap();
+        assert_eq!(
+            drain(&mut rx),
+            [NotificationEvent::Overflowed { dropped: 2 }, closed(5)]
+        );
+        backlog.flush(&sender).unwrap();
+        assert_eq!(drain(&mut rx), [closed(6)]);
+        assert!(!backlog.settle_flusher());
+    }
+
+    #[test]
+    fn block_holds_everything_until_it_is_dropped_oldest_first() {
+        let (sender, mut rx) = mpsc::channel(1);
+        let mut backlog = Backlog::default();
+        let policy = OverflowPolicy::Block {
+            timeout: Duration::from_millis(10),
+        };
+
+        assert_eq!(push_all(&mut backlog, &sender, policy, 1..=4), 0);
+        assert!(backlog.drop_oldest());
+        assert_eq!(drain(&mut rx), [closed(1)]);
+
+        backlog.flush(&sender).unwrap();
+        assert_eq!(
+            drain(&mut rx),
+            [NotificationEvent::Overflowed { dropped: 1 }]
+        );
+        for id in 3..=4 {
+            backlog.flush(&sender).unwrap();
+            assert_eq!(drain(&mut rx), [closed(id)]);
+        }
+        assert!(!backlog.drop_oldest());
+    }
+
+    #[test]
+    fn only_the_first_push_past_a_full_channel_asks_for_a_flusher() {
+        let (sender, _rx) = mpsc::channel(1);
+        let mut backlog = Backlog::default();
+        let starts: Vec<_> = (1..=3)
+            .map(|id| {
+                backlog
+                    .push(&sender, OverflowPolicy::DropOldest, 4, closed(id))
+                    .unwrap()
+                    .start_flusher
+            })
+            .collect();
+        assert_eq!(starts, [false, true, false]);
+    }
+
+    #[test]
+    fn a_gone_consumer_is_reported() {
+        let (sender, rx) = mpsc::channel(1);
+        drop(rx);
+        assert_eq!(
+            Backlog::default().push(&sender, OverflowPolicy::DropNewest, 1, closed(1)),
+            Err(Closed)
+        );
+    }
+}
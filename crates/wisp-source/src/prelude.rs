@@ -0,0 +1,19 @@
+//! The supported surface for embedding the source: `use wisp_source::prelude::*;`.
+//!
+//! Everything here follows semver: removals and signature changes wait for a major
+//! version, and the `#[non_exhaustive]` types ([`SourceConfig`], [`NotificationEvent`],
+//! [`CloseReason`], [`NotificationHints`], [`OverflowPolicy`]) may grow in minor ones. `tests/public_api.rs`
+//! pins these signatures. Other public items exist for the workspace's own binaries and
+//! may change with them.
+
+pub use wisp_types::{
+    CloseReason, Fingerprint, ImageData, Notification, NotificationAction, NotificationEvent,
+    NotificationHints, ReplaceChain, SoundHint, Urgency, dnd::DndWindow,
+};
+
+pub use crate::{
+    AppCounters, CommandError, DbusService, Dedup, DndStatus, Features, HistoryEntry, HistoryStore,
+    OverflowPolicy, RateLimit, SnapshotEntry, SourceConfig, SourceConfigBuilder, SourceError,
+    SourceState, SourceStats, StartupError, StartupStage, TimeoutClamp, TimeoutRule, WispSource,
+    parse_raw_hints,
+};
@@ -0,0 +1,112 @@
+//! Rate-limited warnings for hot failure paths.
+
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Minimum spacing between two warnings from the same call site.
+pub(crate) const WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Lets one warning through per window and counts the ones it holds back.
+#[derive(Debug)]
+pub(crate) struct WarnLimiter {
+    window: Duration,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    last_logged: Option<Instant>,
+    suppressed: u64,
+}
+
+/// Warnings held back since the last one logged; displays as a message trailer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Suppressed(pub(crate) u64);
+
+impl fmt::Display for Suppressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => Ok(()),
+            1 => f.write_str(" (suppressed 1 similar message)"),
+            n => write!(f, " (suppressed {n} similar messages)"),
+        }
+    }
+}
+
+impl Default for WarnLimiter {
+    fn default() -> Self {
+        Self::new(WARN_INTERVAL)
+    }
+}
+
+impl WarnLimiter {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// Returns `Some` when a warning may be logged at `now`, carrying how many were
+    /// suppressed since the previous one; otherwise counts this one as suppressed.
+    pub(crate) fn check(&self, now: Instant) -> Option<Suppressed> {
+        let mut state = self.state.lock().expect("warn limiter poisoned");
+        let due = state
+            .last_logged
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.window);
+        if due {
+            state.last_logged = Some(now);
+            Some(Suppressed(std::mem::take(&mut state.suppressed)))
+        } else {
+            state.suppressed += 1;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_warning_passes_and_the_rest_of_the_window_is_counted() {
+        let limiter = WarnLimiter::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(limiter.check(start), Some(Suppressed(0)));
+        for ms in [1, 500, 9_999] {
+            assert_eq!(limiter.check(start + Duration::from_millis(ms)), None);
+        }
+        assert_eq!(
+            limiter.check(start + Duration::from_secs(10)),
+            Some(Suppressed(3))
+        );
+        assert_eq!(
+            limiter.check(start + Duration::from_secs(25)),
+            Some(Suppressed(0)),
+            "the count resets once reported"
+        );
+    }
+
+    #[test]
+    fn window_restarts_from_the_last_logged_warning() {
+        let limiter = WarnLimiter::new(Duration::from_secs(10));
+        let start = Instant::now();
+        limiter.check(start);
+        limiter.check(start + Duration::from_secs(12));
+        assert_eq!(limiter.check(start + Duration::from_secs(20)), None);
+        assert!(limiter.check(start + Duration::from_secs(22)).is_some());
+    }
+
+    #[test]
+    fn trailer_only_mentions_suppressed_messages() {
+        assert_eq!(Suppressed(0).to_string(), "");
+        assert_eq!(Suppressed(1).to_string(), " (suppressed 1 similar message)");
+        assert_eq!(
+            Suppressed(7).to_string(),
+            " (suppressed 7 similar messages)"
+        );
+    }
+}
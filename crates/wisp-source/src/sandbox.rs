@@ -0,0 +1,128 @@
+//! Best-effort identification of sandboxed (Flatpak) senders.
+
+use std::{fs, path::Path};
+
+/// Resolves the Flatpak app id of `pid` by reading procfs under `proc_root`.
+///
+/// Tries `<pid>/root/.flatpak-info` first, then the `app-flatpak-<id>-<n>.scope` unit in
+/// `<pid>/cgroup`. Any failure (no permission, process gone, not sandboxed) yields `None`.
+pub(crate) fn flatpak_app_id(proc_root: &Path, pid: u32) -> Option<String> {
+    let pid_dir = proc_root.join(pid.to_string());
+
+    if let Ok(info) = fs::read_to_string(pid_dir.join("root").join(".flatpak-info"))
+        && let Some(app_id) = app_id_from_flatpak_info(&info)
+    {
+        return Some(app_id);
+    }
+
+    let cgroup = fs::read_to_string(pid_dir.join("cgroup")).ok()?;
+    app_id_from_cgroup(&cgroup)
+}
+
+fn app_id_from_flatpak_info(info: &str) -> Option<String> {
+    let mut in_application = false;
+    for line in info.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_application = line == "[Application]";
+            continue;
+        }
+        if in_application
+            && let Some(name) = line.strip_prefix("name=")
+            && !name.trim().is_empty()
+        {
+            return Some(name.trim().to_string());
+        }
+    }
+    None
+}
+
+fn app_id_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .flat_map(|line| line.rsplit('/'))
+        .find_map(|unit| {
+            let rest = unit.strip_prefix("app-flatpak-")?;
+            let rest = rest.strip_suffix(".scope")?;
+            let (app_id, instance) = rest.rsplit_once('-')?;
+            (!app_id.is_empty() && instance.chars().all(|c| c.is_ascii_digit()))
+                .then(|| app_id.to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture_proc(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("wisp-source-proc-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn flatpak_info_name_wins() {
+        let proc_root = fixture_proc("info");
+        let pid_dir = proc_root.join("42");
+        fs::create_dir_all(pid_dir.join("root")).unwrap();
+        fs::write(
+            pid_dir.join("root").join(".flatpak-info"),
+            "[Application]\nname=org.gnome.Maps\nruntime=runtime/org.gnome.Platform\n\n[Instance]\nname=ignored\n",
+        )
+        .unwrap();
+        fs::write(
+            pid_dir.join("cgroup"),
+            "0::/user.slice/app.slice/app-flatpak-org.other.App-99.scope\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            flatpak_app_id(&proc_root, 42).as_deref(),
+            Some("org.gnome.Maps")
+        );
+        let _ = fs::remove_dir_all(&proc_root);
+    }
+
+    #[test]
+    fn cgroup_scope_is_used_when_flatpak_info_is_unreadable() {
+        let proc_root = fixture_proc("cgroup");
+        let pid_dir = proc_root.join("7");
+        fs::create_dir_all(&pid_dir).unwrap();
+        fs::write(
+            pid_dir.join("cgroup"),
+            "0::/user.slice/user-1000.slice/user@1000.service/app.slice/app-flatpak-com.slack.Slack-1234.scope\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            flatpak_app_id(&proc_root, 7).as_deref(),
+            Some("com.slack.Slack")
+        );
+        let _ = fs::remove_dir_all(&proc_root);
+    }
+
+    #[test]
+    fn unsandboxed_or_missing_processes_yield_none() {
+        let proc_root = fixture_proc("none");
+        let pid_dir = proc_root.join("5");
+        fs::create_dir_all(&pid_dir).unwrap();
+        fs::write(
+            pid_dir.join("cgroup"),
+            "0::/user.slice/user-1000.slice/session-2.scope\n",
+        )
+        .unwrap();
+
+        assert_eq!(flatpak_app_id(&proc_root, 5), None);
+        assert_eq!(flatpak_app_id(&proc_root, 6), None);
+        let _ = fs::remove_dir_all(&proc_root);
+    }
+
+    #[test]
+    fn flatpak_info_without_application_name_is_ignored() {
+        assert_eq!(app_id_from_flatpak_info("[Instance]\nname=x\n"), None);
+        assert_eq!(app_id_from_flatpak_info("[Application]\nname=\n"), None);
+    }
+}
@@ -0,0 +1,549 @@
+//! Hermetic harness for tests against [`WispSource`].
+//!
+//! [`TestSource`] pairs a source with its event stream and assertion helpers; under a
+//! paused clock (`#[tokio::test(start_paused = true)]`) [`TestSource::advance`] fires
+//! expirations deterministically. [`PeerBus`] serves the D-Bus interfaces to an
+//! in-process peer over a socket pair, so no session bus is needed.
+//!
+//! Compiled for this crate's tests; other crates enable the `testing` feature.
+
+use std::{collections::HashMap, os::unix::net::UnixStream, time::Duration};
+
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use wisp_types::{CloseReason, Notification, NotificationEvent};
+use zbus::{
+    Guid, Message, MessageStream, connection::Builder as ConnectionBuilder,
+    message::Type as MessageType, zvariant,
+};
+
+use crate::{
+    CONTROL_INTERFACE, CONTROL_PATH, CommandError, DBUS_INTERFACE, DbusService, SourceConfig,
+    WispSource,
+};
+
+/// Real-clock bound on waiting for an event or message before the test fails.
+const WAIT: Duration = Duration::from_secs(2);
+/// Yields that let tasks woken by the same timer tick run to completion.
+const SETTLE_YIELDS: usize = 16;
+
+/// A [`WispSource`] with its event receiver.
+#[derive(Debug)]
+pub struct TestSource {
+    source: WispSource,
+    events: mpsc::Receiver<NotificationEvent>,
+}
+
+impl Default for TestSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestSource {
+    pub fn new() -> Self {
+        Self::with_config(SourceConfig::default())
+    }
+
+    pub fn with_config(cfg: SourceConfig) -> Self {
+        let (source, events) = WispSource::new(cfg);
+        Self { source, events }
+    }
+
+    pub fn source(&self) -> &WispSource {
+        &self.source
+    }
+
+    /// Stores `notification` under a fresh id.
+    pub async fn notify(&self, notification: Notification) -> u32 {
+        self.replace(notification, 0).await
+    }
+
+    /// Stores `notification` with `replaces_id`, as `Notify` would.
+    pub async fn replace(&self, notification: Notification, replaces_id: u32) -> u32 {
+        self.source
+            .notify(notification, replaces_id)
+            .await
+            .expect("event channel closed")
+    }
+
+    /// Lets `ms` pass. Under a paused clock this jumps there at once, firing every
+    /// expiry due on the way in deadline order; on a real clock it sleeps.
+    pub async fn advance(&self, ms: u64) {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        settle().await;
+    }
+
+    /// The next event. When none is queued this waits for one, which under a paused
+    /// clock lets time run forward to the next expiry.
+    pub async fn next_event(&mut self) -> NotificationEvent {
+        settle().await;
+        if let Ok(event) = self.events.try_recv() {
+            return event;
+        }
+        tokio::time::timeout(WAIT, self.events.recv())
+            .await
+            .expect("timed out waiting for an event")
+            .expect("event channel closed")
+    }
+
+    pub async fn expect_received(&mut self) -> (u32, Notification) {
+        match self.next_event().await {
+            NotificationEvent::Received {
+                id, notification, ..
+            } => (id, *notification),
+            other => panic!("expected Received, got {other:?}"),
+        }
+    }
+
+    /// Expects `id` to be replaced and returns the new payload.
+    pub async fn expect_replaced(&mut self, id: u32) -> Notification {
+        match self.next_event().await {
+            NotificationEvent::Replaced {
+                id: event_id,
+                current,
+                ..
+            } if event_id == id => *current,
+            other => panic!("expected Replaced for {id}, got {other:?}"),
+        }
+    }
+
+    pub async fn expect_closed(&mut self, id: u32, reason: CloseReason) {
+        assert_eq!(
+            self.next_event().await,
+            NotificationEvent::Closed { id, reason }
+        );
+    }
+
+    pub async fn expect_action_invoked(&mut self, id: u32, action_key: &str) {
+        assert_eq!(
+            self.next_event().await,
+            NotificationEvent::ActionInvoked {
+                id,
+                action_key: action_key.to_string(),
+            }
+        );
+    }
+
+    pub async fn expect_replied(&mut self, id: u32, text: &str) {
+        assert_eq!(
+            self.next_event().await,
+            NotificationEvent::Replied {
+                id,
+                text: text.to_string(),
+            }
+        );
+    }
+
+    /// Asserts that nothing is queued once ready tasks have run. Does not wait; call
+    /// [`Self::advance`] first to cover a stretch of time.
+    pub async fn expect_no_event(&mut self) {
+        settle().await;
+        if let Ok(event) = self.events.try_recv() {
+            panic!("unexpected event: {event:?}");
+        }
+    }
+
+    /// Every queued event, oldest first.
+    pub fn drain(&mut self) -> Vec<NotificationEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+}
+
+async fn settle() {
+    for _ in 0..SETTLE_YIELDS {
+        tokio::task::yield_now().await;
+    }
+}
+
+/// The source's D-Bus interfaces served to one in-process peer over a socket pair.
+///
+/// Use a real clock: the peer's traffic is driven outside tokio, so a paused clock would
+/// jump ahead while a call is in flight.
+#[derive(Debug)]
+pub struct PeerBus {
+    dbus_path: String,
+    client: zbus::Connection,
+    /// Everything the peer receives, in wire order; drained by a task so unread
+    /// messages never stall the connection.
+    incoming: mpsc::UnboundedReceiver<Message>,
+    _service: DbusService,
+}
+
+impl PeerBus {
+    /// Serves a source built from `cfg` and connects the peer.
+    pub async fn start(cfg: SourceConfig) -> (TestSource, Self) {
+        let (server_end, client_end) = UnixStream::pair().expect("socket pair");
+        let dbus_path = cfg.dbus_path.clone();
+        let server = ConnectionBuilder::async_io_unix_stream(server_end)
+            .server(Guid::generate())
+            .expect("generated guid")
+            .p2p();
+        let client = ConnectionBuilder::async_io_unix_stream(client_end)
+            .p2p()
+            .build();
+        let (served, client) = tokio::join!(WispSource::serve(cfg, server), client);
+        let (source, events, service) = served.expect("serve source over the socket pair");
+        let client = client.expect("connect peer");
+
+        let mut stream = MessageStream::from(&client);
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                if incoming_tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+
+        (
+            TestSource { source, events },
+            Self {
+                dbus_path,
+                client,
+                incoming,
+                _service: service,
+            },
+        )
+    }
+
+    pub fn client(&self) -> &zbus::Connection {
+        &self.client
+    }
+
+    /// Calls `Notify` with no hints and returns the id; `actions` alternates key, label.
+    pub async fn notify(
+        &self,
+        replaces_id: u32,
+        summary: &str,
+        actions: &[&str],
+        expire_timeout: i32,
+    ) -> u32 {
+        self.notify_call(
+            replaces_id,
+            summary,
+            actions,
+            HashMap::new(),
+            expire_timeout,
+        )
+        .await
+        .body()
+        .deserialize()
+        .expect("Notify returns an id")
+    }
+
+    /// Calls `Notify` and returns the reply itself, e.g. for ordering checks.
+    pub async fn notify_call(
+        &self,
+        replaces_id: u32,
+        summary: &str,
+        actions: &[&str],
+        hints: HashMap<&str, zvariant::Value<'_>>,
+        expire_timeout: i32,
+    ) -> Message {
+        self.client
+            .call_method(
+                None::<&str>,
+                self.dbus_path.as_str(),
+                Some(DBUS_INTERFACE),
+                "Notify",
+                &(
+                    "test-client",
+                    replaces_id,
+                    "",
+                    summary,
+                    "",
+                    actions,
+                    hints,
+                    expire_timeout,
+                ),
+            )
+            .await
+            .expect("Notify")
+    }
+
+    pub async fn close_notification(&self, id: u32) {
+        self.client
+            .call_method(
+                None::<&str>,
+                self.dbus_path.as_str(),
+                Some(DBUS_INTERFACE),
+                "CloseNotification",
+                &(id,),
+            )
+            .await
+            .expect("CloseNotification");
+    }
+
+    pub async fn get_capabilities(&self) -> Vec<String> {
+        self.client
+            .call_method(
+                None::<&str>,
+                self.dbus_path.as_str(),
+                Some(DBUS_INTERFACE),
+                "GetCapabilities",
+                &(),
+            )
+            .await
+            .expect("GetCapabilities")
+            .body()
+            .deserialize()
+            .expect("GetCapabilities returns as")
+    }
+
+    pub async fn get_server_information(&self) -> (String, String, String, String) {
+        self.client
+            .call_method(
+                None::<&str>,
+                self.dbus_path.as_str(),
+                Some(DBUS_INTERFACE),
+                "GetServerInformation",
+                &(),
+            )
+            .await
+            .expect("GetServerInformation")
+            .body()
+            .deserialize()
+            .expect("GetServerInformation returns ssss")
+    }
+
+    /// Calls `org.wispd.Control1.InvokeAction`; panics on errors outside the
+    /// [`CommandError`] taxonomy.
+    pub async fn control_invoke_action(
+        &self,
+        id: u32,
+        action_key: &str,
+    ) -> Result<bool, CommandError> {
+        match self
+            .client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "InvokeAction",
+                &(id, action_key),
+            )
+            .await
+        {
+            Ok(reply) => Ok(reply.body().deserialize().expect("InvokeAction returns b")),
+            Err(err) => Err(CommandError::from_dbus_error(&err)
+                .unwrap_or_else(|| panic!("not a control error: {err:?}"))),
+        }
+    }
+
+    /// Calls `org.wispd.Control1.ShowHistoric`; panics on errors outside the
+    /// [`CommandError`] taxonomy.
+    pub async fn show_historic(&self, index_or_id: u32) -> Result<u32, CommandError> {
+        match self
+            .client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "ShowHistoric",
+                &(index_or_id,),
+            )
+            .await
+        {
+            Ok(reply) => Ok(reply.body().deserialize().expect("ShowHistoric returns u")),
+            Err(err) => Err(CommandError::from_dbus_error(&err)
+                .unwrap_or_else(|| panic!("not a control error: {err:?}"))),
+        }
+    }
+
+    /// Calls `org.wispd.Control1.GetAppCounters`.
+    pub async fn get_app_counters(&self) -> HashMap<String, (u32, u32, u32)> {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "GetAppCounters",
+                &(),
+            )
+            .await
+            .expect("GetAppCounters")
+            .body()
+            .deserialize()
+            .expect("GetAppCounters returns a{s(uuu)}")
+    }
+
+    /// Calls `org.wispd.Control1.GetStats`.
+    pub async fn get_stats(&self) -> HashMap<String, zvariant::OwnedValue> {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "GetStats",
+                &(),
+            )
+            .await
+            .expect("GetStats")
+            .body()
+            .deserialize()
+            .expect("GetStats returns a{sv}")
+    }
+
+    /// Calls `org.wispd.Control1.MarkSeen`.
+    pub async fn mark_seen(&self, app: &str) {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "MarkSeen",
+                &(app,),
+            )
+            .await
+            .expect("MarkSeen");
+    }
+
+    /// Calls `org.wispd.Control1.GetState`.
+    pub async fn get_state(&self) -> HashMap<String, zvariant::OwnedValue> {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "GetState",
+                &(),
+            )
+            .await
+            .expect("GetState")
+            .body()
+            .deserialize()
+            .expect("GetState returns a{sv}")
+    }
+
+    /// Calls `org.wispd.Control1.SetDoNotDisturb`.
+    pub async fn set_do_not_disturb(&self, enabled: bool) {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "SetDoNotDisturb",
+                &(enabled,),
+            )
+            .await
+            .expect("SetDoNotDisturb");
+    }
+
+    /// Calls `org.wispd.Control1.GetDoNotDisturb`.
+    pub async fn get_do_not_disturb(&self) -> bool {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "GetDoNotDisturb",
+                &(),
+            )
+            .await
+            .expect("GetDoNotDisturb")
+            .body()
+            .deserialize()
+            .expect("GetDoNotDisturb returns b")
+    }
+
+    /// Calls `org.wispd.Control1.CloseAll` with a freedesktop reason code.
+    pub async fn close_all(&self, reason: u32) -> Vec<u32> {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "CloseAll",
+                &(reason,),
+            )
+            .await
+            .expect("CloseAll")
+            .body()
+            .deserialize()
+            .expect("CloseAll returns au")
+    }
+
+    /// Calls `org.wispd.Control1.ListNotifications`.
+    pub async fn list_notifications(&self) -> Vec<(u32, HashMap<String, zvariant::OwnedValue>)> {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some(CONTROL_INTERFACE),
+                "ListNotifications",
+                &(),
+            )
+            .await
+            .expect("ListNotifications")
+            .body()
+            .deserialize()
+            .expect("ListNotifications returns a(ua{sv})")
+    }
+
+    /// Reads property `name` of `org.wispd.Control1`.
+    pub async fn control_property(&self, name: &str) -> zvariant::OwnedValue {
+        self.client
+            .call_method(
+                None::<&str>,
+                CONTROL_PATH,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(CONTROL_INTERFACE, name),
+            )
+            .await
+            .expect("Properties.Get")
+            .body()
+            .deserialize()
+            .expect("Properties.Get returns v")
+    }
+
+    /// Skips ahead to the next `PropertiesChanged` and returns its changed properties.
+    pub async fn expect_properties_changed(&mut self) -> HashMap<String, zvariant::OwnedValue> {
+        let signal = self.expect_signal("PropertiesChanged").await;
+        let (interface, changed, _invalidated): (
+            String,
+            HashMap<String, zvariant::OwnedValue>,
+            Vec<String>,
+        ) = signal
+            .body()
+            .deserialize()
+            .expect("PropertiesChanged carries sa{sv}as");
+        assert_eq!(interface, CONTROL_INTERFACE);
+        changed
+    }
+
+    /// The next message the peer received, replies included.
+    pub async fn next_message(&mut self) -> Message {
+        tokio::time::timeout(WAIT, self.incoming.recv())
+            .await
+            .expect("timed out waiting for a message")
+            .expect("peer connection closed")
+    }
+
+    /// Skips ahead to the next `member` signal and returns it.
+    pub async fn expect_signal(&mut self, member: &str) -> Message {
+        loop {
+            let msg = self.next_message().await;
+            if is_signal(&msg, member) {
+                return msg;
+            }
+        }
+    }
+
+    /// Asserts that no `member` signal has arrived. Does not wait.
+    pub fn expect_no_signal(&mut self, member: &str) {
+        while let Ok(msg) = self.incoming.try_recv() {
+            assert!(!is_signal(&msg, member), "unexpected {member} signal");
+        }
+    }
+}
+
+fn is_signal(msg: &Message, member: &str) -> bool {
+    msg.message_type() == MessageType::Signal
+        && msg.header().member().is_some_and(|m| m.as_str() == member)
+}
@@ -0,0 +1,438 @@
+//! Freedesktop notification spec checks, runnable against any daemon.
+//!
+//! By default the checks run against a [`WispSource`] served over a private peer-to-peer
+//! connection, so no session bus is needed. With `WISP_COMPLIANCE_DEST` set (e.g. to
+//! `org.freedesktop.Notifications`) they call that name on the session bus instead.
+//!
+//! ```text
+//! cargo test -p wisp-source --test compliance -- --nocapture
+//! WISP_COMPLIANCE_DEST=org.freedesktop.Notifications cargo test -p wisp-source --test compliance -- --nocapture
+//! ```
+//!
+//! Every check runs even when an earlier one fails; the report lists each with its
+//! outcome and the test fails if any did.
+
+use std::{collections::HashMap, env, fmt::Write as _, os::unix::net::UnixStream, time::Duration};
+
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use wisp_source::{
+    DBUS_INTERFACE, DEFAULT_DBUS_PATH, DbusService, Features, SourceConfig, WispSource,
+};
+use wisp_types::NotificationEvent;
+use zbus::{
+    Guid, MatchRule, Message, MessageStream, connection::Builder as ConnectionBuilder,
+    fdo::DBusProxy, message::Type as MessageType, zvariant,
+};
+
+/// How long a check waits for a reply or signal.
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Capabilities the spec defines but [`Features`] has no flag for.
+const OTHER_SPEC_CAPABILITIES: [&str; 1] = ["icon-multi"];
+
+type CheckResult = Result<(), String>;
+
+/// The daemon under test and everything it sent us, in wire order.
+struct Target {
+    conn: zbus::Connection,
+    /// `None` on the peer-to-peer connection, which has no names.
+    dest: Option<String>,
+    incoming: mpsc::UnboundedReceiver<Message>,
+    /// What the local source handed its UI; `None` for a remote daemon.
+    events: Option<mpsc::Receiver<NotificationEvent>>,
+    _served: Option<(WispSource, DbusService)>,
+}
+
+impl Target {
+    async fn local() -> Self {
+        let (server_end, client_end) = UnixStream::pair().expect("socket pair");
+        let server = ConnectionBuilder::async_io_unix_stream(server_end)
+            .server(Guid::generate())
+            .expect("generated guid")
+            .p2p();
+        let client = ConnectionBuilder::async_io_unix_stream(client_end)
+            .p2p()
+            .build();
+        let (served, client) =
+            tokio::join!(WispSource::serve(SourceConfig::default(), server), client);
+        let (source, events, service) = served.expect("serve source over the socket pair");
+        let conn = client.expect("connect peer");
+        Self {
+            incoming: drain(&conn),
+            conn,
+            dest: None,
+            events: Some(events),
+            _served: Some((source, service)),
+        }
+    }
+
+    async fn remote(dest: String) -> Self {
+        let conn = zbus::Connection::session()
+            .await
+            .expect("WISP_COMPLIANCE_DEST needs a session bus");
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface(DBUS_INTERFACE)
+            .expect("valid interface")
+            .build();
+        DBusProxy::new(&conn)
+            .await
+            .expect("bus proxy")
+            .add_match_rule(rule)
+            .await
+            .expect("subscribe to notification signals");
+        Self {
+            incoming: drain(&conn),
+            conn,
+            dest: Some(dest),
+            events: None,
+            _served: None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.dest {
+            Some(dest) => format!("{dest} on the session bus"),
+            None => "WispSource on a private peer-to-peer bus".to_string(),
+        }
+    }
+
+    async fn call<B>(&self, method: &str, body: &B) -> Result<Message, String>
+    where
+        B: serde::Serialize + zvariant::DynamicType,
+    {
+        let call = self.conn.call_method(
+            self.dest.as_deref(),
+            DEFAULT_DBUS_PATH,
+            Some(DBUS_INTERFACE),
+            method,
+            body,
+        );
+        tokio::time::timeout(WAIT, call)
+            .await
+            .map_err(|_| format!("{method} timed out"))?
+            .map_err(|err| format!("{method} failed: {err}"))
+    }
+
+    /// Calls `Notify` and returns the reply.
+    async fn notify_call(
+        &self,
+        replaces_id: u32,
+        summary: &str,
+        body: &str,
+        expire_timeout: i32,
+    ) -> Result<Message, String> {
+        let args = (
+            "wisp-compliance",
+            replaces_id,
+            "",
+            summary,
+            body,
+            Vec::<&str>::new(),
+            HashMap::<&str, zvariant::Value<'_>>::new(),
+            expire_timeout,
+        );
+        self.call("Notify", &args).await
+    }
+
+    async fn notify(
+        &self,
+        replaces_id: u32,
+        summary: &str,
+        expire_timeout: i32,
+    ) -> Result<u32, String> {
+        let reply = self
+            .notify_call(replaces_id, summary, "", expire_timeout)
+            .await?;
+        reply
+            .body()
+            .deserialize()
+            .map_err(|err| format!("Notify did not return a u32: {err}"))
+    }
+
+    async fn close(&self, id: u32) -> CheckResult {
+        self.call("CloseNotification", &(id,)).await.map(drop)
+    }
+
+    /// Waits for `NotificationClosed` for `id` and returns its reason; skips everything
+    /// else.
+    async fn closed(&mut self, id: u32) -> Result<u32, String> {
+        loop {
+            let msg = self
+                .next_message(&format!("NotificationClosed for {id}"))
+                .await?;
+            if let Some((closed, reason)) = closed_signal(&msg)
+                && closed == id
+            {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Asserts that no `NotificationClosed` for `id` is queued. Does not wait.
+    fn expect_not_closed(&mut self, id: u32) -> CheckResult {
+        while let Ok(msg) = self.incoming.try_recv() {
+            if closed_signal(&msg).is_some_and(|(closed, _)| closed == id) {
+                return Err(format!("unexpected NotificationClosed for {id}"));
+            }
+        }
+        Ok(())
+    }
+
+    async fn next_message(&mut self, waiting_for: &str) -> Result<Message, String> {
+        tokio::time::timeout(WAIT, self.incoming.recv())
+            .await
+            .map_err(|_| format!("timed out waiting for {waiting_for}"))?
+            .ok_or_else(|| "connection closed".to_string())
+    }
+}
+
+/// Forwards every incoming message to a channel, so unread ones never stall the
+/// connection.
+fn drain(conn: &zbus::Connection) -> mpsc::UnboundedReceiver<Message> {
+    let mut stream = MessageStream::from(conn);
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(Ok(msg)) = stream.next().await {
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn closed_signal(msg: &Message) -> Option<(u32, u32)> {
+    let header = msg.header();
+    (msg.message_type() == MessageType::Signal
+        && header
+            .interface()
+            .is_some_and(|i| i.as_str() == DBUS_INTERFACE)
+        && header
+            .member()
+            .is_some_and(|m| m.as_str() == "NotificationClosed"))
+    .then(|| msg.body().deserialize().ok())
+    .flatten()
+}
+
+fn expect_reason(id: u32, reason: u32, expected: u32) -> CheckResult {
+    match reason {
+        r if r == expected => Ok(()),
+        1..=4 => Err(format!(
+            "{id} closed with reason {reason}, expected {expected}"
+        )),
+        _ => Err(format!(
+            "{id} closed with reason {reason}, outside the spec's 1..=4"
+        )),
+    }
+}
+
+async fn notify_returns_a_nonzero_id(target: &mut Target) -> CheckResult {
+    let first = target.notify(0, "compliance: first", 0).await?;
+    let second = target.notify(0, "compliance: second", 0).await?;
+    target.close(first).await?;
+    target.close(second).await?;
+    if first == 0 || second == 0 {
+        return Err(format!("got ids {first} and {second}; 0 is reserved"));
+    }
+    if first == second {
+        return Err(format!("two notifications both got id {first}"));
+    }
+    Ok(())
+}
+
+async fn replaces_id_keeps_the_notification(target: &mut Target) -> CheckResult {
+    let id = target.notify(0, "compliance: original", 0).await?;
+    let replaced = target.notify(id, "compliance: replacement", 0).await?;
+    if replaced != id {
+        return Err(format!("replacing {id} returned {replaced}"));
+    }
+    target.expect_not_closed(id)?;
+    target.close(id).await?;
+    expect_reason(id, target.closed(id).await?, 3)?;
+
+    // The spec leaves an unknown `replaces_id` open; it must still yield a usable id.
+    let fresh = target.notify(id, "compliance: after close", 0).await?;
+    target.close(fresh).await?;
+    if fresh == 0 {
+        return Err("replacing a closed id returned 0".to_string());
+    }
+    Ok(())
+}
+
+async fn close_unknown_id_is_not_an_error(target: &mut Target) -> CheckResult {
+    target.close(u32::MAX - 7).await
+}
+
+async fn close_notification_emits_reason_3(target: &mut Target) -> CheckResult {
+    let id = target.notify(0, "compliance: closed by call", 0).await?;
+    target.close(id).await?;
+    expect_reason(id, target.closed(id).await?, 3)
+}
+
+async fn expiry_emits_reason_1(target: &mut Target) -> CheckResult {
+    let id = target.notify(0, "compliance: expires", 200).await?;
+    expect_reason(id, target.closed(id).await?, 1)
+}
+
+async fn server_information_has_four_strings(target: &mut Target) -> CheckResult {
+    let (name, vendor, version, spec_version): (String, String, String, String) = target
+        .call("GetServerInformation", &())
+        .await?
+        .body()
+        .deserialize()
+        .map_err(|err| format!("GetServerInformation did not return (ssss): {err}"))?;
+    if name.is_empty() || vendor.is_empty() || version.is_empty() {
+        return Err(format!(
+            "empty field in ({name:?}, {vendor:?}, {version:?})"
+        ));
+    }
+    let dotted = spec_version.split_once('.').is_some_and(|(major, minor)| {
+        [major, minor]
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    });
+    if !dotted {
+        return Err(format!("spec version {spec_version:?} is not MAJOR.MINOR"));
+    }
+    Ok(())
+}
+
+async fn capabilities_are_valid(target: &mut Target) -> CheckResult {
+    let capabilities: Vec<String> = target
+        .call("GetCapabilities", &())
+        .await?
+        .body()
+        .deserialize()
+        .map_err(|err| format!("GetCapabilities did not return as: {err}"))?;
+    let mut problems = Vec::new();
+    for (i, capability) in capabilities.iter().enumerate() {
+        if capability.is_empty() || capability.contains(char::is_whitespace) {
+            problems.push(format!("{capability:?} is not a capability name"));
+        }
+        if capabilities[..i].contains(capability) {
+            problems.push(format!("{capability:?} is listed twice"));
+        }
+    }
+    let unknown = Features::from_capabilities(&capabilities)
+        .extra
+        .into_iter()
+        .filter(|c| !c.starts_with("x-") && !OTHER_SPEC_CAPABILITIES.contains(&c.as_str()));
+    problems.extend(unknown.map(|c| format!("{c:?} is neither in the spec nor x- prefixed")));
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+async fn utf8_text_round_trips(target: &mut Target) -> CheckResult {
+    let summary = "compliance: héllo — 你好 🎉";
+    let body = "Zürich → Łódź\ncombining: e\u{301}, zero-width: a\u{200b}b";
+    let reply = target.notify_call(0, summary, body, 0).await?;
+    let id: u32 = reply
+        .body()
+        .deserialize()
+        .map_err(|err| format!("Notify did not return a u32: {err}"))?;
+    target.close(id).await?;
+    if id == 0 {
+        return Err("Notify returned 0".to_string());
+    }
+    // Only the local source shows what it made of the text.
+    let Some(events) = target.events.as_mut() else {
+        return Ok(());
+    };
+    while let Ok(event) = events.try_recv() {
+        if let NotificationEvent::Received {
+            id: received,
+            notification,
+            ..
+        } = event
+            && received == id
+        {
+            return if notification.summary == summary && notification.body == body {
+                Ok(())
+            } else {
+                Err(format!(
+                    "stored {:?} / {:?}",
+                    notification.summary, notification.body
+                ))
+            };
+        }
+    }
+    Err(format!("no Received event for {id}"))
+}
+
+async fn notify_reply_precedes_its_close_signal(target: &mut Target) -> CheckResult {
+    // Drop leftovers so the scan below sees only this exchange.
+    while target.incoming.try_recv().is_ok() {}
+    let reply = target
+        .notify_call(0, "compliance: instant expiry", "", 1)
+        .await?;
+    let id: u32 = reply
+        .body()
+        .deserialize()
+        .map_err(|err| format!("Notify did not return a u32: {err}"))?;
+    let serial = reply.header().reply_serial();
+    loop {
+        let msg = target
+            .next_message(&format!("the Notify reply for {id}"))
+            .await?;
+        if msg.message_type() == MessageType::MethodReturn && msg.header().reply_serial() == serial
+        {
+            break;
+        }
+        if closed_signal(&msg).is_some_and(|(closed, _)| closed == id) {
+            return Err(format!(
+                "NotificationClosed for {id} arrived before its Notify reply"
+            ));
+        }
+    }
+    expect_reason(id, target.closed(id).await?, 1)
+}
+
+fn render(target: &str, report: &[(&'static str, CheckResult)]) -> String {
+    let mut out = format!("notification spec compliance: {target}\n");
+    for (name, outcome) in report {
+        let _ = match outcome {
+            Ok(()) => writeln!(out, "  PASS  {name}"),
+            Err(reason) => writeln!(out, "  FAIL  {name}: {reason}"),
+        };
+    }
+    let passed = report.iter().filter(|(_, outcome)| outcome.is_ok()).count();
+    let _ = writeln!(out, "{passed}/{} checks passed", report.len());
+    out
+}
+
+#[tokio::test]
+async fn compliance() {
+    let mut target = match env::var("WISP_COMPLIANCE_DEST") {
+        Ok(dest) if !dest.is_empty() => Target::remote(dest).await,
+        _ => Target::local().await,
+    };
+
+    let mut report = Vec::new();
+    macro_rules! check {
+        ($check:ident) => {
+            let outcome = $check(&mut target).await;
+            report.push((stringify!($check), outcome));
+        };
+    }
+    check!(notify_returns_a_nonzero_id);
+    check!(replaces_id_keeps_the_notification);
+    check!(close_unknown_id_is_not_an_error);
+    check!(close_notification_emits_reason_3);
+    check!(expiry_emits_reason_1);
+    check!(server_information_has_four_strings);
+    check!(capabilities_are_valid);
+    check!(utf8_text_round_trips);
+    check!(notify_reply_precedes_its_close_signal);
+
+    let rendered = render(&target.describe(), &report);
+    println!("{rendered}");
+    assert!(
+        report.iter().all(|(_, outcome)| outcome.is_ok()),
+        "{rendered}"
+    );
+}
@@ -0,0 +1,372 @@
+//! Pins the supported surface in `wisp_source::prelude`. Every function is named with
+//! its full signature and every public struct is taken apart field by field, so an
+//! unintended change to the surface stops this file from compiling. A deliberate one
+//! updates it in the same change.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+
+use futures_util::Stream;
+use tokio::sync::mpsc::Receiver;
+use wisp_source::prelude::*;
+use zbus::{connection::Builder as ConnectionBuilder, zvariant::OwnedValue};
+
+type Started = Result<(WispSource, Receiver<NotificationEvent>, DbusService), StartupError>;
+
+/// A [`HistoryStore`] keeping nothing, implemented the way an embedder would.
+#[derive(Debug)]
+struct NoHistory;
+
+impl HistoryStore for NoHistory {
+    fn load(&self) -> Vec<HistoryEntry> {
+        Vec::new()
+    }
+
+    fn append(&self, _: &HistoryEntry) {}
+
+    fn rewrite(&self, _: &[HistoryEntry]) {}
+
+    fn wants_compaction(&self) -> bool {
+        false
+    }
+}
+
+/// Accepts what [`WispSource::subscribe`] promises to return.
+fn event_stream(_: impl Stream<Item = NotificationEvent> + Send + 'static) {}
+
+/// Never run: it only has to compile.
+async fn async_signatures(source: &WispSource, cfg: SourceConfig, peer: ConnectionBuilder<'_>) {
+    let _: Started = WispSource::start_dbus(cfg.clone()).await;
+    let _: Started = WispSource::start_dbus_with_progress(cfg.clone(), |_: StartupStage| {}).await;
+    let _: Started = WispSource::serve(cfg, peer).await;
+    let _: Result<u32, SourceError> = source.notify(Notification::default(), 0).await;
+    let _: Result<Vec<u32>, SourceError> = source.notify_batch(Vec::new()).await;
+    let _: Result<(), CommandError> = source.close(1, CloseReason::Dismissed).await;
+    let _: Result<Vec<u32>, SourceError> = source.close_all(CloseReason::Undefined).await;
+    let _: Vec<u32> = source.shutdown().await;
+    let _: Result<(), CommandError> = source.invoke_action(1, "default").await;
+    let _: Result<(), CommandError> = source.reply(1, "ok").await;
+    let _: Vec<(u32, Notification)> = source.snapshot().await;
+    let _: Result<(), CommandError> = source.escalate(1, 2).await;
+    let _: Result<u32, CommandError> = source.reshow_historic(0).await;
+    let _: Result<(), CommandError> = source.invoke_historic_action(1, "default").await;
+}
+
+#[test]
+fn source_methods_keep_their_signatures() {
+    let _ = async_signatures;
+
+    let _: fn(SourceConfig) -> (WispSource, Receiver<NotificationEvent>) = WispSource::new;
+    let _ = |source: &WispSource| event_stream(source.subscribe());
+    let _: fn(&WispSource) -> Vec<String> = WispSource::capabilities;
+    let _: fn(&WispSource) -> SourceStats = WispSource::stats;
+    let _: fn(&WispSource, Vec<String>, Option<i32>) = WispSource::update_runtime_config;
+    let _: fn(&WispSource, Features) = WispSource::set_capabilities;
+    let _: fn(&WispSource, Option<i32>) = WispSource::set_default_timeout;
+    let _: fn(&WispSource, TimeoutClamp) = WispSource::set_timeout_clamp;
+    let _: fn(&WispSource, Vec<TimeoutRule>) = WispSource::set_timeout_rules;
+    let _: fn(&WispSource, bool) = WispSource::set_critical_never_expires;
+    let _: fn(&WispSource) -> Vec<SnapshotEntry> = WispSource::snapshot_detailed;
+    let _: fn(&WispSource, u32) -> Option<Duration> = WispSource::remaining_timeout;
+    let _: fn(&WispSource, u32) -> Option<Notification> = WispSource::get;
+    let _: fn(&WispSource, u32, bool) -> bool = WispSource::set_pinned;
+    let _: fn(&WispSource) -> SourceState = WispSource::state;
+    let _: fn(&WispSource) -> BTreeMap<String, AppCounters> = WispSource::app_counters;
+    let _: fn(&WispSource, &str) -> bool = WispSource::mark_seen;
+    let _: fn(&WispSource) -> Vec<HistoryEntry> = WispSource::history;
+    let _: fn(&WispSource, u32) -> Option<HistoryEntry> = WispSource::historic;
+    let _: fn(&WispSource, u32, bool) -> Result<u32, CommandError> =
+        WispSource::set_history_starred;
+    let _: fn(&WispSource, &[u32]) -> usize = WispSource::remove_history;
+    let _: fn(&WispSource) -> usize = WispSource::clear_history;
+    let _: fn(&WispSource, Option<Duration>) = WispSource::set_history_ttl;
+    let _: fn(&WispSource, u32, bool) -> Result<u32, CommandError> = WispSource::show_historic;
+    let _: fn(&WispSource, bool) = WispSource::set_do_not_disturb;
+    let _: fn(&WispSource) -> bool = WispSource::is_do_not_disturb;
+    let _: fn(&WispSource) -> DndStatus = WispSource::do_not_disturb;
+    let _: fn(&WispSource, Vec<DndWindow>) = WispSource::set_dnd_schedule;
+    let _: fn(&WispSource) -> (String, String, String, String) = WispSource::server_information;
+
+    let _: fn(&DbusService) -> &zbus::Connection = DbusService::connection;
+    let _: fn(&TimeoutClamp, Option<u32>, bool) -> Option<u32> = TimeoutClamp::apply;
+    let _: fn(&TimeoutClamp, &[TimeoutRule], &Notification) -> TimeoutClamp =
+        TimeoutClamp::for_notification;
+    let _: fn(&Notification, bool) -> Fingerprint<'_> = Notification::fingerprint;
+    let _: fn(&CommandError) -> &'static str = CommandError::dbus_name;
+    let _: fn(&zbus::Error) -> Option<CommandError> = CommandError::from_dbus_error;
+    let _: fn(&HashMap<String, OwnedValue>) -> (Urgency, NotificationHints) = parse_raw_hints;
+    let _: fn() -> SourceConfigBuilder = SourceConfig::builder;
+    let _: fn(SourceConfigBuilder) -> SourceConfig = SourceConfigBuilder::build;
+}
+
+#[test]
+fn public_structs_keep_their_fields() {
+    let SourceStats {
+        oversized_hints: _,
+        oversized_hints_by_app: _,
+        dropped_events: _,
+        failed_signal_emissions: _,
+        rate_limited: _,
+    } = SourceStats::default();
+    let SourceState {
+        count: _,
+        pinned: _,
+    } = SourceState::default();
+    let TimeoutClamp {
+        min_ms: _,
+        max_ms: _,
+        exempt_critical: _,
+    } = TimeoutClamp::default();
+    let TimeoutRule {
+        matcher: _,
+        min_ms: _,
+        max_ms: _,
+        exempt_critical: _,
+    } = TimeoutRule::default();
+    let Dedup {
+        window: _,
+        match_body: _,
+    } = Dedup::default();
+    let RateLimit {
+        per_app_per_minute: _,
+        burst: _,
+    } = RateLimit {
+        per_app_per_minute: 60,
+        burst: 10,
+    };
+    let fields = |entry: SnapshotEntry| {
+        let SnapshotEntry {
+            id: _,
+            notification: _,
+            generation: _,
+            remaining: _,
+        } = entry;
+    };
+    let _ = fields;
+    let fields = |entry: HistoryEntry| {
+        let HistoryEntry {
+            id: _,
+            notification: _,
+            sender: _,
+            reason: _,
+            received_at: _,
+            chain: _,
+            starred: _,
+        } = entry;
+    };
+    let _ = fields;
+    let fields = |counters: AppCounters, dnd: DndStatus| {
+        let AppCounters {
+            unseen: _,
+            visible: _,
+            history: _,
+        } = counters;
+        let DndStatus {
+            enabled: _,
+            scheduled: _,
+            overridden: _,
+        } = dnd;
+    };
+    let _ = fields;
+    let Notification {
+        app_name: _,
+        app_icon: _,
+        summary: _,
+        body: _,
+        urgency: _,
+        timeout_ms: _,
+        actions: _,
+        hints: _,
+    } = Notification::default();
+    let ReplaceChain {
+        replace_count: _,
+        intermediate_summaries: _,
+        lifetime_ms: _,
+    } = ReplaceChain::default();
+    let Fingerprint {
+        app_name: _,
+        summary: _,
+        body: _,
+    } = Notification::default().fingerprint(true);
+}
+
+#[test]
+fn non_exhaustive_types_keep_their_known_shapes() {
+    // Matching outside the crate needs the wildcard arm; the known variants and fields
+    // must still be there.
+    let describe = |event: &NotificationEvent| match event {
+        NotificationEvent::Received {
+            id,
+            notification: _,
+            silent: _,
+            remaining_ms: _,
+        } => format!("received {id}"),
+        NotificationEvent::Closed { id, reason } => format!("closed {id} {reason:?}"),
+        NotificationEvent::ActionInvoked { id, action_key } => format!("{id} {action_key}"),
+        NotificationEvent::Replied { id, text } => format!("replied {id} {text}"),
+        NotificationEvent::Replaced {
+            id,
+            previous: _,
+            current: _,
+            remaining_ms: _,
+            repeat_count: _,
+        } => format!("replaced {id}"),
+        NotificationEvent::DndChanged { enabled } => format!("dnd {enabled}"),
+        NotificationEvent::Historic {
+            id,
+            notification: _,
+            actions_enabled: _,
+            chain: _,
+        } => format!("historic {id}"),
+        NotificationEvent::BatchStarted { batch_id }
+        | NotificationEvent::BatchEnded { batch_id } => {
+            format!("batch {batch_id}")
+        }
+        NotificationEvent::Overflowed { dropped } => format!("overflowed {dropped}"),
+        NotificationEvent::RateLimited {
+            app_name,
+            suppressed,
+        } => format!("rate-limited {app_name} {suppressed}"),
+        _ => "other".to_string(),
+    };
+    assert_eq!(
+        describe(&NotificationEvent::Closed {
+            id: 3,
+            reason: CloseReason::Expired,
+        }),
+        "closed 3 Expired"
+    );
+    let code = |reason: &CloseReason| match reason {
+        CloseReason::Expired => 1,
+        CloseReason::Dismissed => 2,
+        CloseReason::ClosedByCall => 3,
+        CloseReason::Undefined => 4,
+        _ => 0,
+    };
+    assert_eq!(code(&CloseReason::ClosedByCall), 3);
+    let waits = |policy: &OverflowPolicy| match policy {
+        OverflowPolicy::DropNewest | OverflowPolicy::DropOldest => None,
+        OverflowPolicy::Block { timeout } => Some(*timeout),
+        _ => None,
+    };
+    assert_eq!(
+        waits(&OverflowPolicy::Block {
+            timeout: Duration::from_millis(50)
+        }),
+        Some(Duration::from_millis(50))
+    );
+    assert_eq!(OverflowPolicy::default(), OverflowPolicy::DropNewest);
+
+    let mut hints = NotificationHints::default();
+    hints.category = Some("email.arrived".to_string());
+    hints.desktop_entry = None;
+    hints.display_app_name = None;
+    hints.urls = Vec::new();
+    hints.origin_name = None;
+    hints.sound = SoundHint {
+        file: Some(PathBuf::from("/tmp/ding.oga")),
+        name: None,
+        suppress: false,
+    };
+    hints.transient = Some(true);
+    hints.resident = None;
+    hints.action_icons = None;
+    hints.progress = Some(40);
+    hints.position = Some((120, 40));
+    hints.sandbox_app_id = None;
+    hints.image = None::<ImageData>;
+    hints.image_path = None;
+    hints.icon_data = None;
+    hints.extra.clear();
+    assert_eq!(hints.category.as_deref(), Some("email.arrived"));
+}
+
+#[test]
+fn the_config_builder_sets_every_field_over_the_defaults() {
+    let cfg = SourceConfig::builder()
+        .capabilities(vec!["body".to_string(), "actions".to_string()])
+        .channel_capacity(8_usize)
+        .overflow_policy(OverflowPolicy::DropOldest)
+        .dbus_name("org.example.Notifications")
+        .dbus_path("/org/example/Notifications")
+        .server_name("shell")
+        .server_vendor("example")
+        .server_version("2.0")
+        .spec_version("1.2")
+        .default_timeout_ms(5_000)
+        .max_hint_value_len(64_usize)
+        .max_total_hints_bytes(1_024_usize)
+        .keep_extra_hints(false)
+        .proc_root("/tmp/proc")
+        .clamp_timeout(TimeoutClamp {
+            min_ms: Some(1_000),
+            ..TimeoutClamp::default()
+        })
+        .critical_never_expires(true)
+        .dnd_schedule(Vec::<DndWindow>::new())
+        .critical_bypasses_dnd(false)
+        .rate_limit(RateLimit {
+            per_app_per_minute: 30,
+            burst: 5,
+        })
+        .dedup(Dedup {
+            match_body: false,
+            ..Dedup::default()
+        })
+        .close_on_reply(false)
+        .action_icons(true)
+        .history_len(10_usize)
+        .history_intermediate_summaries(2_usize)
+        .history_ttl(Duration::from_secs(60))
+        .history_store(NoHistory)
+        .log_content(false)
+        .build();
+
+    assert_eq!(cfg.capabilities, ["body", "actions"]);
+    assert_eq!(cfg.channel_capacity, 8);
+    assert_eq!(cfg.overflow_policy, OverflowPolicy::DropOldest);
+    assert_eq!(cfg.dbus_name, "org.example.Notifications");
+    assert_eq!(cfg.dbus_path, "/org/example/Notifications");
+    assert_eq!(
+        (
+            cfg.server_name.as_str(),
+            cfg.server_vendor.as_str(),
+            cfg.server_version.as_str(),
+            cfg.spec_version.as_str(),
+        ),
+        ("shell", "example", "2.0", "1.2")
+    );
+    assert_eq!(cfg.default_timeout_ms, Some(5_000));
+    assert_eq!(
+        (cfg.max_hint_value_len, cfg.max_total_hints_bytes),
+        (64, 1_024)
+    );
+    assert!(!cfg.keep_extra_hints);
+    assert_eq!(cfg.proc_root, PathBuf::from("/tmp/proc"));
+    assert_eq!(cfg.clamp_timeout.min_ms, Some(1_000));
+    assert!(cfg.critical_never_expires);
+    assert!(cfg.dnd_schedule.is_empty());
+    assert!(!cfg.critical_bypasses_dnd);
+    assert_eq!(cfg.rate_limit.map(|limit| limit.burst), Some(5));
+    assert_eq!(cfg.dedup.map(|dedup| dedup.match_body), Some(false));
+    assert!(!cfg.close_on_reply);
+    assert!(cfg.action_icons);
+    assert_eq!(
+        (cfg.history_len, cfg.history_intermediate_summaries),
+        (10, 2)
+    );
+    assert_eq!(cfg.history_ttl, Some(Duration::from_secs(60)));
+    assert!(cfg.history_store.is_some());
+    assert!(!cfg.log_content);
+
+    let defaults = SourceConfig::default();
+    let untouched = SourceConfig::builder()
+        .dbus_name("org.example.Other")
+        .build();
+    assert_eq!(untouched.dbus_path, defaults.dbus_path);
+    assert_eq!(untouched.history_len, defaults.history_len);
+    assert!(untouched.log_content);
+}
@@ -0,0 +1,252 @@
+//! Do-not-disturb schedule: weekly windows of local wall-clock time.
+//!
+//! A window runs from `from` to `until` on each of its `days`; when `until` is not after
+//! `from` it ends the next morning, and `days` names the day it starts on. Checks take the
+//! current local time and look only at its wall-clock reading, so DST changes need no
+//! special handling beyond re-checking periodically.
+
+use std::fmt;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeZone, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// One scheduled window. In config: `{ days = ["mon-fri"], from = "22:30", until = "07:00" }`,
+/// where `days` holds day names and inclusive ranges and may be left out for every day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "WindowSpec", into = "WindowSpec")]
+pub struct DndWindow {
+    /// Days the window starts on; empty means every day.
+    pub days: Vec<Weekday>,
+    pub from: NaiveTime,
+    pub until: NaiveTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WindowSpec {
+    #[serde(default)]
+    days: Vec<String>,
+    from: String,
+    until: String,
+}
+
+impl TryFrom<WindowSpec> for DndWindow {
+    type Error = String;
+
+    fn try_from(spec: WindowSpec) -> Result<Self, Self::Error> {
+        let mut days = Vec::new();
+        for entry in &spec.days {
+            for day in parse_days(entry)? {
+                if !days.contains(&day) {
+                    days.push(day);
+                }
+            }
+        }
+        let from = parse_time(&spec.from)?;
+        let until = parse_time(&spec.until)?;
+        if from == until {
+            return Err(format!(
+                "empty window: from and until are both {}",
+                spec.from
+            ));
+        }
+        Ok(Self { days, from, until })
+    }
+}
+
+impl From<DndWindow> for WindowSpec {
+    fn from(window: DndWindow) -> Self {
+        Self {
+            days: window
+                .days
+                .iter()
+                .map(|day| day_name(*day).to_string())
+                .collect(),
+            from: ClockTime(window.from).to_string(),
+            until: ClockTime(window.until).to_string(),
+        }
+    }
+}
+
+impl DndWindow {
+    /// Whether the wall-clock time `at` falls inside this window.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let (day, time) = (at.weekday(), at.time());
+        if self.from < self.until {
+            self.starts_on(day) && time >= self.from && time < self.until
+        } else {
+            (self.starts_on(day) && time >= self.from)
+                || (self.starts_on(day.pred()) && time < self.until)
+        }
+    }
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// Whether any window covers `now`, read as local wall-clock time in its own zone.
+pub fn is_dnd_scheduled<Tz: TimeZone>(now: &DateTime<Tz>, schedule: &[DndWindow]) -> bool {
+    let local = now.naive_local();
+    schedule.iter().any(|window| window.contains(local))
+}
+
+/// `HH:MM`, formatted with two-digit fields.
+struct ClockTime(NaiveTime);
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0.hour(), self.0.minute())
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M")
+        .map_err(|_| format!("invalid time {s:?} (expected HH:MM)"))
+}
+
+/// A day name (`mon`, `Monday`, ...) or an inclusive range such as `mon-fri` or `sat-sun`;
+/// ranges may wrap past Sunday.
+fn parse_days(entry: &str) -> Result<Vec<Weekday>, String> {
+    let parse = |name: &str| {
+        name.trim()
+            .parse::<Weekday>()
+            .map_err(|_| format!("invalid day {name:?}"))
+    };
+    let Some((first, last)) = entry.split_once('-') else {
+        return Ok(vec![parse(entry)?]);
+    };
+    let (mut day, last) = (parse(first)?, parse(last)?);
+    let mut days = vec![day];
+    while day != last {
+        day = day.succ();
+        days.push(day);
+    }
+    Ok(days)
+}
+
+fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "mon",
+        Weekday::Tue => "tue",
+        Weekday::Wed => "wed",
+        Weekday::Thu => "thu",
+        Weekday::Fri => "fri",
+        Weekday::Sat => "sat",
+        Weekday::Sun => "sun",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDate, Utc};
+
+    use super::*;
+
+    fn window(days: &[&str], from: &str, until: &str) -> DndWindow {
+        DndWindow::try_from(WindowSpec {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            from: from.to_string(),
+            until: until.to_string(),
+        })
+        .unwrap()
+    }
+
+    /// 2026-10-12 is a Monday.
+    fn at(day_of_october: u32, time: &str) -> DateTime<Utc> {
+        let date = NaiveDate::from_ymd_opt(2026, 10, day_of_october).unwrap();
+        date.and_time(parse_time(time).unwrap()).and_utc()
+    }
+
+    #[test]
+    fn overnight_windows_wrap_midnight_and_belong_to_their_start_day() {
+        let schedule = [window(&["mon-fri"], "22:30", "07:00")];
+
+        assert!(!is_dnd_scheduled(&at(12, "22:29"), &schedule));
+        assert!(is_dnd_scheduled(&at(12, "22:30"), &schedule));
+        assert!(is_dnd_scheduled(&at(13, "00:00"), &schedule));
+        assert!(is_dnd_scheduled(&at(13, "06:59"), &schedule));
+        assert!(!is_dnd_scheduled(&at(13, "07:00"), &schedule));
+        // Friday night runs into Saturday morning, but Saturday night starts nothing.
+        assert!(is_dnd_scheduled(&at(17, "03:00"), &schedule));
+        assert!(!is_dnd_scheduled(&at(17, "23:00"), &schedule));
+        // Monday morning is the tail of Sunday night, which is not scheduled.
+        assert!(!is_dnd_scheduled(&at(12, "03:00"), &schedule));
+    }
+
+    #[test]
+    fn day_filters_accept_names_ranges_and_wrapping_ranges() {
+        let weekend = window(&["fri-sun"], "09:00", "12:00");
+        assert_eq!(weekend.days, [Weekday::Fri, Weekday::Sat, Weekday::Sun]);
+        assert!(is_dnd_scheduled(
+            &at(18, "10:00"),
+            std::slice::from_ref(&weekend)
+        ));
+        assert!(!is_dnd_scheduled(&at(19, "10:00"), &[weekend]));
+
+        let wrapped = window(&["sat-mon", "Wednesday"], "09:00", "12:00");
+        assert_eq!(
+            wrapped.days,
+            [Weekday::Sat, Weekday::Sun, Weekday::Mon, Weekday::Wed]
+        );
+
+        let every_day = window(&[], "12:00", "13:00");
+        assert!(
+            (12..=18)
+                .all(|day| is_dnd_scheduled(&at(day, "12:30"), std::slice::from_ref(&every_day)))
+        );
+    }
+
+    #[test]
+    fn dst_changes_follow_the_local_wall_clock() {
+        // Europe/Berlin springs forward at 01:00 UTC on 2026-03-29: 02:00 becomes 03:00.
+        let (cet, cest) = (
+            FixedOffset::east_opt(3600).unwrap(),
+            FixedOffset::east_opt(7200).unwrap(),
+        );
+        let utc = |month, day, h, m| {
+            NaiveDate::from_ymd_opt(2026, month, day)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let schedule = [window(&["sun"], "00:30", "03:00")];
+
+        let before = utc(3, 29, 0, 59).with_timezone(&cet);
+        let after = utc(3, 29, 1, 1).with_timezone(&cest);
+        assert_eq!(before.naive_local().time(), parse_time("01:59").unwrap());
+        assert!(is_dnd_scheduled(&before, &schedule));
+        // Two minutes later the wall clock reads 03:01, past the window's end.
+        assert!(!is_dnd_scheduled(&after, &schedule));
+
+        // Falling back at 01:00 UTC on 2026-10-25 repeats 02:00-03:00; both readings are
+        // inside a 01:00-03:00 window.
+        let schedule = [window(&["sun"], "01:00", "03:00")];
+        let first = utc(10, 25, 0, 30).with_timezone(&cest);
+        let repeat = utc(10, 25, 1, 30).with_timezone(&cet);
+        assert_eq!(first.naive_local(), repeat.naive_local());
+        assert!(is_dnd_scheduled(&first, &schedule) && is_dnd_scheduled(&repeat, &schedule));
+    }
+
+    #[test]
+    fn invalid_windows_are_rejected_and_valid_ones_round_trip() {
+        let spec = |days: &[&str], from: &str, until: &str| WindowSpec {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            from: from.to_string(),
+            until: until.to_string(),
+        };
+        assert!(DndWindow::try_from(spec(&["funday"], "22:00", "07:00")).is_err());
+        assert!(DndWindow::try_from(spec(&[], "25:00", "07:00")).is_err());
+        assert!(DndWindow::try_from(spec(&[], "7am", "08:00")).is_err());
+        assert!(DndWindow::try_from(spec(&[], "08:00", "08:00")).is_err());
+
+        let window = window(&["mon-wed"], "7:05", "09:00");
+        let spec = WindowSpec::from(window.clone());
+        assert_eq!(spec.days, ["mon", "tue", "wed"]);
+        assert_eq!(
+            (spec.from.as_str(), spec.until.as_str()),
+            ("07:05", "09:00")
+        );
+        assert_eq!(DndWindow::try_from(spec), Ok(window));
+    }
+}
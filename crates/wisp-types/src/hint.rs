@@ -0,0 +1,171 @@
+//! Values of hints the source has no field for, kept in [`crate::NotificationHints::extra`]
+//! with their type so consumers can act on them (`value`, `resident`,
+//! `x-canonical-private-synchronous`, ...). Scalars, strings and byte arrays keep their
+//! value; anything else is kept as text.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// One hint value. Serialized untagged, so JSON shows `true`, `42` or `"text"` and
+/// string values stored before hints were typed still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HintValue {
+    Bool(bool),
+    /// `n`, `i` and `x`.
+    Int64(i64),
+    /// `y`, `q`, `u` and `t`.
+    UInt64(u64),
+    /// A finite `d`.
+    Double(f64),
+    /// `s`, `o` and `g`.
+    String(String),
+    /// `ay`.
+    Bytes(Vec<u8>),
+    /// Any other type, such as `as` or `a{sv}`, in GVariant text format.
+    Other {
+        signature: String,
+        text: String,
+    },
+    /// A value left out: an image payload, or one over the source's hint size limits.
+    Omitted {
+        signature: String,
+        reason: String,
+    },
+}
+
+impl HintValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Integer values of either signedness.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int64(value) => Some(*value),
+            Self::UInt64(value) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for HintValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Int64(a), Self::Int64(b)) => a == b,
+            (Self::UInt64(a), Self::UInt64(b)) => a == b,
+            // Bitwise, so equality is reflexive and `Eq` holds.
+            (Self::Double(a), Self::Double(b)) => a.to_bits() == b.to_bits(),
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            (
+                Self::Other { signature, text },
+                Self::Other {
+                    signature: other_signature,
+                    text: other_text,
+                },
+            ) => signature == other_signature && text == other_text,
+            (
+                Self::Omitted { signature, reason },
+                Self::Omitted {
+                    signature: other_signature,
+                    reason: other_reason,
+                },
+            ) => signature == other_signature && reason == other_reason,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for HintValue {}
+
+impl fmt::Display for HintValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Int64(value) => write!(f, "{value}"),
+            Self::UInt64(value) => write!(f, "{value}"),
+            Self::Double(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "{value:?}"),
+            Self::Bytes(bytes) => write!(f, "<{} bytes>", bytes.len()),
+            Self::Other { text, .. } => f.write_str(text),
+            Self::Omitted { signature, reason } => write!(f, "<omitted {signature}: {reason}>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn values_serialize_as_plain_json_and_load_back() {
+        let values = HashMap::from([
+            ("resident".to_string(), HintValue::Bool(true)),
+            ("value".to_string(), HintValue::Int64(-3)),
+            ("x-big".to_string(), HintValue::UInt64(u64::MAX)),
+            ("x-ratio".to_string(), HintValue::Double(0.5)),
+            (
+                "x-canonical-private-synchronous".to_string(),
+                HintValue::String("volume".to_string()),
+            ),
+            ("x-blob".to_string(), HintValue::Bytes(vec![1, 2])),
+            (
+                "x-list".to_string(),
+                HintValue::Other {
+                    signature: "as".to_string(),
+                    text: "['a']".to_string(),
+                },
+            ),
+        ]);
+        let json = serde_json::to_value(&values).unwrap();
+        assert_eq!(json["resident"], true);
+        assert_eq!(json["value"], -3);
+        assert_eq!(json["x-canonical-private-synchronous"], "volume");
+        assert_eq!(
+            serde_json::from_value::<HashMap<String, HintValue>>(json).unwrap(),
+            values
+        );
+
+        let legacy: HashMap<String, HintValue> =
+            serde_json::from_str(r#"{"x-foo":"Str(\"bar\")"}"#).unwrap();
+        assert_eq!(
+            legacy["x-foo"],
+            HintValue::String("Str(\"bar\")".to_string()),
+            "debug strings from older history files load as strings"
+        );
+    }
+
+    #[test]
+    fn accessors_and_display() {
+        assert_eq!(HintValue::UInt64(7).as_i64(), Some(7));
+        assert_eq!(HintValue::UInt64(u64::MAX).as_i64(), None);
+        assert_eq!(HintValue::Bool(true).as_bool(), Some(true));
+        assert_eq!(HintValue::Int64(1).as_str(), None);
+        assert_eq!(HintValue::String("hi".to_string()).to_string(), "\"hi\"");
+        assert_eq!(HintValue::Bytes(vec![0; 3]).to_string(), "<3 bytes>");
+        assert_eq!(
+            HintValue::Omitted {
+                signature: "(iiibiiay)".to_string(),
+                reason: "image payload".to_string(),
+            }
+            .to_string(),
+            "<omitted (iiibiiay): image payload>"
+        );
+        assert_eq!(HintValue::Double(f64::NAN), HintValue::Double(f64::NAN));
+    }
+}
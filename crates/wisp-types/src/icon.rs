@@ -0,0 +1,132 @@
+//! Where a notification's icon comes from. A notification can name several: inline
+//! pixels, an `image-path` hint, its `app_icon`, and the legacy `icon_data` hint.
+//! [`Notification::icon_sources`] lists them in the precedence the spec gives, so each
+//! consumer can take the first one it is able to draw.
+
+use std::path::Path;
+
+use crate::{ImageData, Notification};
+
+/// One source of a notification's icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconSource<'a> {
+    /// An icon-theme name, e.g. `mail-unread`.
+    Name(&'a str),
+    /// An image file, given as an absolute path or a `file://` URI.
+    Path(&'a Path),
+    /// Pixels sent with the notification.
+    Data(&'a ImageData),
+}
+
+impl<'a> IconSource<'a> {
+    /// Reads an `app_icon` or `image-path` value: a `file://` URI or an absolute path
+    /// names a file, anything else an icon-theme name. `None` when blank.
+    pub fn from_reference(raw: &'a str) -> Option<Self> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return None;
+        }
+        if let Some(path) = raw.strip_prefix("file://") {
+            return Some(Self::Path(Path::new(path)));
+        }
+        if raw.starts_with('/') {
+            return Some(Self::Path(Path::new(raw)));
+        }
+        Some(Self::Name(raw))
+    }
+}
+
+impl Notification {
+    /// Every icon source, most preferred first: `image-data` (or `image_data`),
+    /// `image-path` (or `image_path`), `app_icon`, then `icon_data`.
+    pub fn icon_sources(&self) -> impl Iterator<Item = IconSource<'_>> {
+        let hints = &self.hints;
+        hints
+            .image
+            .iter()
+            .map(IconSource::Data)
+            .chain(
+                hints
+                    .image_path
+                    .as_deref()
+                    .and_then(IconSource::from_reference),
+            )
+            .chain(IconSource::from_reference(&self.app_icon))
+            .chain(hints.icon_data.iter().map(IconSource::Data))
+    }
+
+    /// The icon the spec says to show: the first of [`Self::icon_sources`].
+    pub fn effective_icon(&self) -> Option<IconSource<'_>> {
+        self.icon_sources().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NotificationHints;
+
+    fn pixels(width: u32) -> ImageData {
+        ImageData {
+            width,
+            height: 1,
+            rowstride: width * 3,
+            has_alpha: false,
+            channels: 3,
+            data: vec![0; width as usize * 3],
+        }
+    }
+
+    #[test]
+    fn references_name_files_or_theme_icons() {
+        assert_eq!(
+            IconSource::from_reference("file:///tmp/art.png"),
+            Some(IconSource::Path(Path::new("/tmp/art.png")))
+        );
+        assert_eq!(
+            IconSource::from_reference(" /usr/share/pixmaps/a.svg "),
+            Some(IconSource::Path(Path::new("/usr/share/pixmaps/a.svg")))
+        );
+        assert_eq!(
+            IconSource::from_reference("firefox"),
+            Some(IconSource::Name("firefox"))
+        );
+        assert_eq!(IconSource::from_reference("  "), None);
+    }
+
+    #[test]
+    fn effective_icon_follows_the_spec_precedence() {
+        let image = pixels(1);
+        let icon_data = pixels(2);
+        // Every combination of image-data, image-path, app_icon and icon_data.
+        for present in 0..16_u8 {
+            let has = |bit: u8| present & (1 << bit) != 0;
+            let notification = Notification {
+                app_icon: if has(2) { "mail-unread" } else { "" }.to_string(),
+                hints: NotificationHints {
+                    image: has(0).then(|| image.clone()),
+                    image_path: has(1).then(|| "/tmp/art.png".to_string()),
+                    icon_data: has(3).then(|| icon_data.clone()),
+                    ..NotificationHints::default()
+                },
+                ..Notification::default()
+            };
+            let expected = if has(0) {
+                Some(IconSource::Data(&image))
+            } else if has(1) {
+                Some(IconSource::Path(Path::new("/tmp/art.png")))
+            } else if has(2) {
+                Some(IconSource::Name("mail-unread"))
+            } else if has(3) {
+                Some(IconSource::Data(&icon_data))
+            } else {
+                None
+            };
+            assert_eq!(notification.effective_icon(), expected, "{present:04b}");
+            assert_eq!(
+                notification.icon_sources().count(),
+                present.count_ones() as usize
+            );
+        }
+    }
+}
@@ -1,9 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use hint::HintValue;
+
+pub mod dnd;
+pub mod hint;
+pub mod icon;
+pub mod loggable;
+pub mod matcher;
+pub mod origin;
+pub mod privacy;
+pub mod systemd;
+pub mod template;
+
 /// Notification urgency level as defined by freedesktop notifications.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+///
+/// Ordered from least to most urgent (`Low < Normal < Critical`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum Urgency {
     /// Low-priority notification.
     Low,
@@ -15,7 +29,10 @@ pub enum Urgency {
 }
 
 /// Reason why a notification was closed.
+///
+/// More reasons may be added; matches outside this crate need a wildcard arm.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CloseReason {
     /// Notification timed out and expired.
     Expired,
@@ -37,16 +54,87 @@ pub struct NotificationAction {
 }
 
 /// Parsed/normalized hint fields from the freedesktop `hints` map.
+///
+/// More fields may be added; outside this crate, start from
+/// [`NotificationHints::default`] and set fields on it.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[non_exhaustive]
 pub struct NotificationHints {
     /// Notification category (e.g. `email.arrived`).
     pub category: Option<String>,
     /// Desktop entry identifier for matching app metadata.
     pub desktop_entry: Option<String>,
+    /// `x-kde-display-appname`: the sender's human-readable name, e.g. `Dolphin`.
+    pub display_app_name: Option<String>,
+    /// `x-kde-urls`: files or URLs the notification is about, e.g. a finished download.
+    pub urls: Vec<String>,
+    /// `x-kde-origin-name`: the device a relayed notification came from, e.g. a phone
+    /// paired through KDE Connect.
+    pub origin_name: Option<String>,
+    /// `sound-file`, `sound-name` and `suppress-sound`: what the notification asks to
+    /// sound like.
+    #[serde(default)]
+    pub sound: SoundHint,
     /// Whether this is marked transient by sender.
     pub transient: Option<bool>,
-    /// Unrecognized hints preserved as debug strings.
-    pub extra: HashMap<String, String>,
+    /// `resident`: invoking an action leaves the notification up, e.g. a music player's
+    /// previous/next buttons; it goes when the user or the sender closes it.
+    pub resident: Option<bool>,
+    /// `action-icons`: action keys are icon names to draw in place of the labels, which
+    /// then only annotate the icons.
+    pub action_icons: Option<bool>,
+    /// `value`: progress in percent, e.g. a volume or brightness level. Kept as sent,
+    /// outside `0..=100` too; consumers clamp when drawing.
+    pub progress: Option<i32>,
+    /// `x` and `y`: the screen point the popup should appear near, e.g. where a
+    /// screenshot tool's selection was. Set only when both are sent as integers that fit
+    /// `i32`.
+    pub position: Option<(i32, i32)>,
+    /// Flatpak app id of the sending process, when it runs sandboxed.
+    ///
+    /// Derived by the source from the sender's process rather than sent as a hint, so it
+    /// cannot be spoofed through `app_name` or `desktop-entry`.
+    pub sandbox_app_id: Option<String>,
+    /// `image-data` (or the older `image_data`): raw pixels such as album art, decoded
+    /// and checked by the source.
+    ///
+    /// Not serialized, so pixels stay out of the history file, dumps and webhooks.
+    #[serde(skip)]
+    pub image: Option<ImageData>,
+    /// `image-path` (or the older `image_path`): a file path, `file://` URI or icon name;
+    /// see [`icon::IconSource::from_reference`].
+    pub image_path: Option<String>,
+    /// The legacy `icon_data` hint, which ranks below `app_icon`. Not serialized either.
+    #[serde(skip)]
+    pub icon_data: Option<ImageData>,
+    /// Unrecognized hints with their typed values.
+    pub extra: HashMap<String, HintValue>,
+}
+
+/// The sound hints of a notification. Playing them is up to the consumer.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct SoundHint {
+    /// `sound-file`: a sound file to play, as sent.
+    pub file: Option<PathBuf>,
+    /// `sound-name`: a themed event sound to play, e.g. `message-new-instant`.
+    pub name: Option<String>,
+    /// `suppress-sound`: play nothing, not even a default sound.
+    pub suppress: bool,
+}
+
+/// Pixels sent inline with a notification: `height` rows of `rowstride` bytes, each
+/// starting with `width` pixels of `channels` 8-bit samples (RGB, or RGBA when
+/// `has_alpha`). The last row may lack its padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of one row to the next.
+    pub rowstride: u32,
+    pub has_alpha: bool,
+    /// 3, or 4 with alpha.
+    pub channels: u8,
+    pub data: Vec<u8>,
 }
 
 /// Normalized notification data used by `wisp` components.
@@ -70,8 +158,69 @@ pub struct Notification {
     pub hints: NotificationHints,
 }
 
+impl Notification {
+    /// What a resent copy of this notification shares with it; the body only counts
+    /// with `with_body`.
+    pub fn fingerprint(&self, with_body: bool) -> Fingerprint<'_> {
+        Fingerprint {
+            app_name: &self.app_name,
+            summary: &self.summary,
+            body: with_body.then_some(self.body.as_str()),
+        }
+    }
+}
+
+/// Identity of a notification's content, compared to spot senders resending the same
+/// notification instead of replacing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint<'a> {
+    /// Name of the sending application.
+    pub app_name: &'a str,
+    /// Notification title/summary.
+    pub summary: &'a str,
+    /// Notification body text; `None` when it does not take part.
+    pub body: Option<&'a str>,
+}
+
+/// How a notification reached its last content through replacements, as kept in the
+/// source's history.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct ReplaceChain {
+    /// Replacements after the notification was first received.
+    pub replace_count: u32,
+    /// The latest summaries it was replaced away from, oldest first. The source keeps a
+    /// bounded number and skips a summary equal to the one before it.
+    pub intermediate_summaries: Vec<String>,
+    /// Time from first received to closed, in milliseconds.
+    pub lifetime_ms: u64,
+}
+
+impl ReplaceChain {
+    /// `"200 updates, 3m"`, or `None` for a notification that was never replaced.
+    pub fn describe(&self) -> Option<String> {
+        if self.replace_count == 0 {
+            return None;
+        }
+        let updates = match self.replace_count {
+            1 => "1 update".to_string(),
+            count => format!("{count} updates"),
+        };
+        let secs = self.lifetime_ms / 1000;
+        let span = match (secs / 3600, secs / 60 % 60) {
+            (0, 0) => format!("{secs}s"),
+            (0, mins) => format!("{mins}m"),
+            (hours, 0) => format!("{hours}h"),
+            (hours, mins) => format!("{hours}h {mins}m"),
+        };
+        Some(format!("{updates}, {span}"))
+    }
+}
+
 /// Event emitted by the source daemon lifecycle.
+///
+/// More kinds may be added; matches outside this crate need a wildcard arm.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum NotificationEvent {
     /// A new notification was received.
     Received {
@@ -79,6 +228,14 @@ pub enum NotificationEvent {
         id: u32,
         /// Notification payload.
         notification: Box<Notification>,
+        /// Received under do-not-disturb: it is stored, has an id and expires as usual,
+        /// but no popup should open for it.
+        #[serde(default)]
+        silent: bool,
+        /// Time left until the source expires it, counted from this event; `None` when it
+        /// never expires or the producer does not know.
+        #[serde(default)]
+        remaining_ms: Option<u64>,
     },
     /// A notification was closed.
     Closed {
@@ -94,6 +251,13 @@ pub enum NotificationEvent {
         /// Invoked action key.
         action_key: String,
     },
+    /// A quick reply was sent from the notification (`inline-reply`).
+    Replied {
+        /// Notification id replied to.
+        id: u32,
+        /// Reply text as typed.
+        text: String,
+    },
     /// An existing notification was replaced in-place.
     Replaced {
         /// Notification id that was replaced.
@@ -102,5 +266,55 @@ pub enum NotificationEvent {
         previous: Box<Notification>,
         /// New notification payload.
         current: Box<Notification>,
+        /// Time left until the source expires the new payload, counted from this event;
+        /// `None` when it never expires or the producer does not know.
+        #[serde(default)]
+        remaining_ms: Option<u64>,
+        /// Identical copies the sender has resent and the source folded into this
+        /// notification, this one included; 0 for an ordinary replacement.
+        #[serde(default)]
+        repeat_count: u32,
+    },
+    /// Do-not-disturb was switched on or off, manually or by the schedule.
+    DndChanged {
+        /// Whether do-not-disturb is now on.
+        enabled: bool,
+    },
+    /// A closed notification was asked to be shown again. It is not stored again and
+    /// its id is no longer valid for commands.
+    Historic {
+        /// Id the notification had while it was stored.
+        id: u32,
+        /// Last content it had.
+        notification: Box<Notification>,
+        /// Whether its original sender is still connected to receive `ActionInvoked`.
+        actions_enabled: bool,
+        /// The replacements that led to `notification`.
+        chain: ReplaceChain,
+    },
+    /// The `Received`/`Replaced` events up to the matching `BatchEnded` were stored
+    /// together by one `notify_batch` call and arrive back to back.
+    BatchStarted {
+        /// Shared by the markers of one batch.
+        batch_id: u64,
+    },
+    /// Ends the batch opened by the `BatchStarted` with the same id.
+    BatchEnded {
+        /// Shared by the markers of one batch.
+        batch_id: u64,
+    },
+    /// Events were lost to a full event channel before this one; state built from the
+    /// stream may be stale and should be rebuilt from a snapshot.
+    Overflowed {
+        /// Events lost since the previous `Overflowed`.
+        dropped: u64,
+    },
+    /// New notifications from one app went past the source's rate limit and were dropped;
+    /// their senders still got ids. Coalesced, so a flood yields a few of these.
+    RateLimited {
+        /// `app_name` the dropped notifications were sent with.
+        app_name: String,
+        /// Notifications dropped since the previous `RateLimited` for this app.
+        suppressed: u64,
     },
 }
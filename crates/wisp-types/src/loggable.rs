@@ -0,0 +1,143 @@
+//! Notification content in log statements. Summaries, bodies and action labels go into
+//! tracing fields only through [`Loggable`] (and whole events through
+//! [`LoggableEvent`]), so switching content logging off with [`set_log_content`] covers
+//! every statement, including ones added later. Without content a field shows the text's
+//! length and a stable hash, which still tells equal messages apart.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::NotificationEvent;
+
+static LOG_CONTENT: AtomicBool = AtomicBool::new(true);
+
+/// Whether logs show notification text; on by default.
+pub fn log_content() -> bool {
+    LOG_CONTENT.load(Ordering::Relaxed)
+}
+
+/// Switches notification text in logs on or off for the whole process.
+pub fn set_log_content(enabled: bool) {
+    LOG_CONTENT.store(enabled, Ordering::Relaxed);
+}
+
+/// Notification text for a log field.
+#[derive(Clone, Copy)]
+pub struct Loggable<'a>(pub &'a str);
+
+impl Loggable<'_> {
+    /// The form used without content: `<N chars #hash>`.
+    pub fn redacted(&self) -> String {
+        format!(
+            "<{} chars #{:08x}>",
+            self.0.chars().count(),
+            text_hash(self.0)
+        )
+    }
+}
+
+impl fmt::Display for Loggable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if log_content() {
+            f.write_str(self.0)
+        } else {
+            f.write_str(&self.redacted())
+        }
+    }
+}
+
+impl fmt::Debug for Loggable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if log_content() {
+            fmt::Debug::fmt(self.0, f)
+        } else {
+            f.write_str(&self.redacted())
+        }
+    }
+}
+
+/// A whole event for a log field: its `Debug` form with content, else its kind and id.
+#[derive(Clone, Copy)]
+pub struct LoggableEvent<'a>(pub &'a NotificationEvent);
+
+impl fmt::Debug for LoggableEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if log_content() {
+            return fmt::Debug::fmt(self.0, f);
+        }
+        match self.0 {
+            NotificationEvent::Received {
+                id, notification, ..
+            } => {
+                write!(
+                    f,
+                    "Received {{ id: {id}, summary: {:?} }}",
+                    Loggable(&notification.summary)
+                )
+            }
+            NotificationEvent::Replaced { id, current, .. } => {
+                write!(
+                    f,
+                    "Replaced {{ id: {id}, summary: {:?} }}",
+                    Loggable(&current.summary)
+                )
+            }
+            NotificationEvent::Historic { id, .. } => write!(f, "Historic {{ id: {id} }}"),
+            NotificationEvent::ActionInvoked { id, action_key } => {
+                write!(
+                    f,
+                    "ActionInvoked {{ id: {id}, action_key: {action_key:?} }}"
+                )
+            }
+            NotificationEvent::Replied { id, text } => {
+                write!(f, "Replied {{ id: {id}, text: {:?} }}", Loggable(text))
+            }
+            other => fmt::Debug::fmt(other, f),
+        }
+    }
+}
+
+/// 32-bit FNV-1a; the same on every run and build, unlike `std`'s hashers.
+fn text_hash(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Notification;
+
+    #[test]
+    fn content_is_replaced_by_length_and_a_stable_hash() {
+        let event = NotificationEvent::Received {
+            id: 3,
+            notification: Box::new(Notification {
+                summary: "Alice: lunch?".to_string(),
+                body: "secret".to_string(),
+                ..Notification::default()
+            }),
+            silent: false,
+            remaining_ms: None,
+        };
+
+        set_log_content(true);
+        assert_eq!(Loggable("Alice: lunch?").to_string(), "Alice: lunch?");
+        assert!(format!("{:?}", LoggableEvent(&event)).contains("secret"));
+
+        set_log_content(false);
+        let shown = Loggable("Alice: lunch?").to_string();
+        assert_eq!(shown, Loggable("Alice: lunch?").redacted());
+        assert!(shown.starts_with("<13 chars #"));
+        assert_ne!(shown, Loggable("Alice: lunch!").to_string());
+        let event = format!("{:?}", LoggableEvent(&event));
+        assert!(!event.contains("secret") && !event.contains("Alice"));
+        assert!(event.starts_with("Received { id: 3, summary: <13 chars #"));
+
+        set_log_content(true);
+        assert_eq!(text_hash(""), 0x811c_9dc5);
+    }
+}
@@ -0,0 +1,567 @@
+//! Text patterns shared by every rule that picks notifications: privacy rules, action
+//! confirmation, sink filters and the forwarder all parse and match them the same way.
+//!
+//! A pattern is written as a string:
+//! - `"Spotify"`: the whole text, exactly;
+//! - `"glob:Fire*"`: a glob, where `*` is any run of characters and `?` exactly one;
+//! - `"re:^org\\.gnome\\."`: a regular expression, matching anywhere unless anchored;
+//! - `"exact:a*b"`: the whole text exactly, for text that would otherwise read as a glob.
+//!
+//! A bare string with `*` or `?` in it is a glob, so rules written before the prefixes
+//! existed keep their meaning. Case is ignored unless [`Case::Sensitive`] is asked for; a
+//! regex can also set its own flags, e.g. `"re:(?-i)^Mail"`.
+
+use std::{fmt, str::FromStr};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Notification;
+
+const EXACT_PREFIX: &str = "exact:";
+const GLOB_PREFIX: &str = "glob:";
+const REGEX_PREFIX: &str = "re:";
+
+/// Whether a pattern tells upper and lower case apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Case {
+    #[default]
+    Insensitive,
+    Sensitive,
+}
+
+/// A pattern that failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// Nothing to match, e.g. `""` or `"glob:"`.
+    Empty,
+    /// A `re:` pattern that is not a valid regular expression.
+    Regex { pattern: String, message: String },
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty pattern"),
+            Self::Regex { pattern, message } => {
+                write!(f, "invalid regex {pattern:?}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// One parsed pattern; see the module docs for the syntax.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Exact { text: String, case: Case },
+    Glob { glob: String, case: Case },
+    Regex { regex: Regex, case: Case },
+}
+
+impl Pattern {
+    /// Parses `raw`, ignoring case.
+    pub fn parse(raw: &str) -> Result<Self, PatternError> {
+        Self::parse_with(raw, Case::Insensitive)
+    }
+
+    /// Parses `raw` with the given case handling. Surrounding whitespace is dropped.
+    pub fn parse_with(raw: &str, case: Case) -> Result<Self, PatternError> {
+        let raw = raw.trim();
+        let pattern = if let Some(source) = raw.strip_prefix(REGEX_PREFIX) {
+            if source.is_empty() {
+                return Err(PatternError::Empty);
+            }
+            let regex = RegexBuilder::new(source)
+                .case_insensitive(case == Case::Insensitive)
+                .build()
+                .map_err(|err| PatternError::Regex {
+                    pattern: source.to_string(),
+                    message: err.to_string(),
+                })?;
+            Self::Regex { regex, case }
+        } else if let Some(glob) = raw.strip_prefix(GLOB_PREFIX) {
+            Self::Glob {
+                glob: glob.to_string(),
+                case,
+            }
+        } else if let Some(text) = raw.strip_prefix(EXACT_PREFIX) {
+            Self::Exact {
+                text: text.to_string(),
+                case,
+            }
+        } else if raw.contains(['*', '?']) {
+            Self::Glob {
+                glob: raw.to_string(),
+                case,
+            }
+        } else {
+            Self::Exact {
+                text: raw.to_string(),
+                case,
+            }
+        };
+        match &pattern {
+            Self::Exact { text: s, .. } | Self::Glob { glob: s, .. } if s.is_empty() => {
+                Err(PatternError::Empty)
+            }
+            _ => Ok(pattern),
+        }
+    }
+
+    /// Whether `text` matches.
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Exact { text: want, case } => {
+                want.chars().count() == text.chars().count()
+                    && want
+                        .chars()
+                        .zip(text.chars())
+                        .all(|(a, b)| same(a, b, *case))
+            }
+            Self::Glob { glob, case } => glob_match_with(glob, text, *case),
+            Self::Regex { regex, .. } => regex.is_match(text),
+        }
+    }
+
+    pub fn case(&self) -> Case {
+        match self {
+            Self::Exact { case, .. } | Self::Glob { case, .. } | Self::Regex { case, .. } => *case,
+        }
+    }
+}
+
+/// The string form; parsing it again gives the same pattern.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefixed = |s: &str| {
+            [EXACT_PREFIX, GLOB_PREFIX, REGEX_PREFIX]
+                .iter()
+                .any(|prefix| s.starts_with(prefix))
+                || s.trim() != s
+        };
+        match self {
+            Self::Exact { text, .. } if !text.contains(['*', '?']) && !prefixed(text) => {
+                f.write_str(text)
+            }
+            Self::Exact { text, .. } => write!(f, "{EXACT_PREFIX}{text}"),
+            Self::Glob { glob, .. } if glob.contains(['*', '?']) && !prefixed(glob) => {
+                f.write_str(glob)
+            }
+            Self::Glob { glob, .. } => write!(f, "{GLOB_PREFIX}{glob}"),
+            Self::Regex { regex, .. } => write!(f, "{REGEX_PREFIX}{}", regex.as_str()),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Exact { text: a, case: ca }, Self::Exact { text: b, case: cb })
+            | (Self::Glob { glob: a, case: ca }, Self::Glob { glob: b, case: cb }) => {
+                a == b && ca == cb
+            }
+            (Self::Regex { regex: a, case: ca }, Self::Regex { regex: b, case: cb }) => {
+                a.as_str() == b.as_str() && ca == cb
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Pattern {}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Self::parse(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Patterns over several notification fields; a notification matches when every set
+/// field does. In config:
+///
+/// ```toml
+/// { app = "glob:Fire*", summary = "re:^Download", case_sensitive = false }
+/// ```
+///
+/// `app_name` is accepted as another spelling of `app`. `sandbox_app_id` is the Flatpak
+/// app id the source read for the sender, so sandboxed apps can be picked whatever name
+/// they send.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MatcherSpec", into = "MatcherSpec")]
+pub struct Matcher {
+    pub app: Option<Pattern>,
+    pub desktop_entry: Option<Pattern>,
+    pub sandbox_app_id: Option<Pattern>,
+    pub category: Option<Pattern>,
+    pub summary: Option<Pattern>,
+    pub body: Option<Pattern>,
+}
+
+impl Matcher {
+    /// Whether no field is constrained, so every notification matches.
+    pub fn is_empty(&self) -> bool {
+        self.fields().all(|pattern| pattern.is_none())
+    }
+
+    /// Whether `notification` matches every set field. A field the notification lacks,
+    /// such as a missing category, matches nothing.
+    pub fn matches(&self, notification: &Notification) -> bool {
+        self.fields_of(notification).all(|(pattern, value)| {
+            pattern.is_none_or(|pattern| value.is_some_and(|value| pattern.matches(value)))
+        })
+    }
+
+    fn fields(&self) -> impl Iterator<Item = Option<&Pattern>> {
+        [
+            self.app.as_ref(),
+            self.desktop_entry.as_ref(),
+            self.sandbox_app_id.as_ref(),
+            self.category.as_ref(),
+            self.summary.as_ref(),
+            self.body.as_ref(),
+        ]
+        .into_iter()
+    }
+
+    fn fields_of<'a>(
+        &'a self,
+        n: &'a Notification,
+    ) -> impl Iterator<Item = (Option<&'a Pattern>, Option<&'a str>)> {
+        [
+            (self.app.as_ref(), Some(n.app_name.as_str())),
+            (
+                self.desktop_entry.as_ref(),
+                n.hints.desktop_entry.as_deref(),
+            ),
+            (
+                self.sandbox_app_id.as_ref(),
+                n.hints.sandbox_app_id.as_deref(),
+            ),
+            (self.category.as_ref(), n.hints.category.as_deref()),
+            (self.summary.as_ref(), Some(n.summary.as_str())),
+            (self.body.as_ref(), Some(n.body.as_str())),
+        ]
+        .into_iter()
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct MatcherSpec {
+    #[serde(default, alias = "app_name", skip_serializing_if = "Option::is_none")]
+    app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    desktop_entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sandbox_app_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    case_sensitive: bool,
+}
+
+impl TryFrom<MatcherSpec> for Matcher {
+    type Error = String;
+
+    fn try_from(spec: MatcherSpec) -> Result<Self, Self::Error> {
+        let case = if spec.case_sensitive {
+            Case::Sensitive
+        } else {
+            Case::Insensitive
+        };
+        let parse = |field: &str, raw: Option<String>| {
+            raw.map(|raw| Pattern::parse_with(&raw, case))
+                .transpose()
+                .map_err(|err| format!("{field}: {err}"))
+        };
+        Ok(Self {
+            app: parse("app", spec.app)?,
+            desktop_entry: parse("desktop_entry", spec.desktop_entry)?,
+            sandbox_app_id: parse("sandbox_app_id", spec.sandbox_app_id)?,
+            category: parse("category", spec.category)?,
+            summary: parse("summary", spec.summary)?,
+            body: parse("body", spec.body)?,
+        })
+    }
+}
+
+impl From<Matcher> for MatcherSpec {
+    fn from(matcher: Matcher) -> Self {
+        let case_sensitive = matcher
+            .fields()
+            .flatten()
+            .any(|pattern| pattern.case() == Case::Sensitive);
+        let text = |pattern: Option<Pattern>| pattern.map(|pattern| pattern.to_string());
+        Self {
+            app: text(matcher.app),
+            desktop_entry: text(matcher.desktop_entry),
+            sandbox_app_id: text(matcher.sandbox_app_id),
+            category: text(matcher.category),
+            summary: text(matcher.summary),
+            body: text(matcher.body),
+            case_sensitive,
+        }
+    }
+}
+
+/// Matches `text` against a glob, ignoring case.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_with(pattern, text, Case::Insensitive)
+}
+
+fn glob_match_with(pattern: &str, text: &str, case: Case) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it currently absorbs up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || same(c, text[t], case) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, absorbed)) => {
+                    p = star + 1;
+                    t = absorbed + 1;
+                    backtrack = Some((star, absorbed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn same(a: char, b: char, case: Case) -> bool {
+    a == b || (case == Case::Insensitive && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NotificationHints;
+
+    fn pattern(raw: &str) -> Pattern {
+        Pattern::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn globs_match_wildcards_and_ignore_case() {
+        assert!(glob_match("Signal", "signal"));
+        assert!(glob_match("KeePass*", "KeePassXC"));
+        assert!(glob_match("*pass*", "1Password"));
+        assert!(glob_match("Sig?al", "Signal"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("Signal", "Signal Desktop"));
+        assert!(!glob_match("Sig?al", "Sigal"));
+        assert!(!glob_match("a*b*c", "aXbY"));
+        assert!(!glob_match("", "x"));
+    }
+
+    #[test]
+    fn strings_parse_to_the_pattern_their_prefix_names() {
+        assert!(matches!(pattern("Spotify"), Pattern::Exact { ref text, .. } if text == "Spotify"));
+        assert!(matches!(pattern("glob:Fire*"), Pattern::Glob { ref glob, .. } if glob == "Fire*"));
+        assert!(
+            matches!(pattern("KeePass*"), Pattern::Glob { .. }),
+            "bare wildcards are globs"
+        );
+        assert!(matches!(
+            pattern("re:^org\\.gnome\\."),
+            Pattern::Regex { .. }
+        ));
+        assert!(matches!(pattern("exact:a*b"), Pattern::Exact { ref text, .. } if text == "a*b"));
+        assert!(matches!(pattern(" Signal "), Pattern::Exact { ref text, .. } if text == "Signal"));
+        assert_eq!(pattern("glob:Fire*").case(), Case::Insensitive);
+    }
+
+    #[test]
+    fn each_kind_matches_by_its_own_rules() {
+        let exact = pattern("Spotify");
+        assert!(exact.matches("Spotify") && exact.matches("SPOTIFY"));
+        assert!(!exact.matches("Spotify Free"));
+
+        let glob = pattern("glob:Fire*");
+        assert!(glob.matches("Firefox") && glob.matches("firefox-esr"));
+        assert!(!glob.matches("Waterfox"));
+
+        let regex = pattern("re:^org\\.gnome\\.");
+        assert!(regex.matches("org.gnome.Nautilus") && regex.matches("ORG.GNOME.Maps"));
+        assert!(!regex.matches("org.kde.dolphin"));
+        assert!(pattern("re:down").matches("Download done"), "unanchored");
+
+        let literal = pattern("exact:a*b");
+        assert!(literal.matches("A*B") && !literal.matches("axb"));
+
+        assert!(pattern("Ärger").matches("ärger"), "case folds beyond ASCII");
+    }
+
+    #[test]
+    fn case_sensitive_patterns_tell_case_apart() {
+        for raw in ["Mail", "glob:Ma?l", "re:^Mail$"] {
+            let sensitive = Pattern::parse_with(raw, Case::Sensitive).unwrap();
+            assert!(sensitive.matches("Mail"), "{raw}");
+            assert!(!sensitive.matches("mail"), "{raw}");
+            assert_eq!(sensitive.case(), Case::Sensitive);
+        }
+        assert!(
+            !pattern("re:(?-i)^Mail").matches("mail"),
+            "inline flags win"
+        );
+    }
+
+    #[test]
+    fn bad_patterns_say_what_is_wrong() {
+        assert_eq!(Pattern::parse(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("  "), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("glob:"), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("exact:"), Err(PatternError::Empty));
+        assert_eq!(Pattern::parse("re:"), Err(PatternError::Empty));
+
+        let err = Pattern::parse("re:(unclosed").unwrap_err();
+        assert!(matches!(&err, PatternError::Regex { pattern, .. } if pattern == "(unclosed"));
+        let message = err.to_string();
+        assert!(
+            message.starts_with("invalid regex \"(unclosed\": "),
+            "{message}"
+        );
+        assert!(message.contains("unclosed group"), "{message}");
+    }
+
+    #[test]
+    fn the_string_form_round_trips() {
+        for raw in [
+            "Spotify",
+            "KeePass*",
+            "glob:Signal",
+            "re:^org\\.gnome\\.",
+            "exact:a*b",
+            "exact:glob:x",
+            "glob:re:*",
+        ] {
+            let parsed = pattern(raw);
+            assert_eq!(parsed.to_string(), raw);
+            assert_eq!(pattern(&parsed.to_string()), parsed);
+        }
+        assert_eq!(pattern("glob:Fire*").to_string(), "Fire*");
+        assert_ne!(pattern("Fire"), pattern("glob:Fire"));
+        assert_ne!(
+            Pattern::parse_with("Fire", Case::Sensitive).unwrap(),
+            pattern("Fire")
+        );
+    }
+
+    #[test]
+    fn patterns_and_matchers_deserialize_with_their_errors() {
+        #[derive(Debug, Deserialize)]
+        struct Rules {
+            apps: Vec<Pattern>,
+            rule: Matcher,
+        }
+
+        let rules: Rules = serde_json::from_str(
+            r#"{
+                "apps": ["Spotify", "glob:Fire*"],
+                "rule": { "app": "re:^thunder", "summary": "Invoice*", "case_sensitive": true }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(rules.apps, [pattern("Spotify"), pattern("glob:Fire*")]);
+        assert_eq!(
+            rules.rule.summary.as_ref().map(Pattern::case),
+            Some(Case::Sensitive)
+        );
+
+        let err = serde_json::from_str::<Vec<Pattern>>(r#"["re:[a-"]"#).unwrap_err();
+        assert!(err.to_string().contains("invalid regex \"[a-\""), "{err}");
+        let err = serde_json::from_str::<Matcher>(r#"{ "category": "re:(" }"#).unwrap_err();
+        assert!(err.to_string().contains("category: invalid regex"), "{err}");
+        let err = serde_json::from_str::<Matcher>(r#"{ "title": "x" }"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field"), "{err}");
+        let alias: Matcher = serde_json::from_str(r#"{ "app_name": "Firefox" }"#).unwrap();
+        assert_eq!(alias.app, Some(pattern("Firefox")));
+
+        let round_trip: Matcher =
+            serde_json::from_str(&serde_json::to_string(&rules.rule).unwrap()).unwrap();
+        assert_eq!(round_trip, rules.rule);
+    }
+
+    #[test]
+    fn a_matcher_needs_every_set_field_to_match() {
+        let matcher: Matcher = serde_json::from_str(
+            r#"{ "app": "glob:Thunder*", "category": "re:^email\\.", "body": "re:invoice" }"#,
+        )
+        .unwrap();
+        let mut n = Notification {
+            app_name: "Thunderbird".to_string(),
+            summary: "New mail".to_string(),
+            body: "Your invoice is ready".to_string(),
+            hints: NotificationHints {
+                category: Some("email.arrived".to_string()),
+                ..NotificationHints::default()
+            },
+            ..Notification::default()
+        };
+        assert!(matcher.matches(&n));
+
+        n.body = "Lunch?".to_string();
+        assert!(!matcher.matches(&n));
+        n.body = "INVOICE attached".to_string();
+        n.hints.category = None;
+        assert!(!matcher.matches(&n), "a missing field matches nothing");
+
+        assert!(Matcher::default().is_empty());
+        assert!(Matcher::default().matches(&n));
+        assert!(!matcher.is_empty());
+    }
+
+    #[test]
+    fn sandboxed_senders_match_by_their_flatpak_id() {
+        let matcher: Matcher =
+            serde_json::from_str(r#"{ "sandbox_app_id": "glob:org.signal.*" }"#).unwrap();
+        let mut n = Notification {
+            app_name: "Signal".to_string(),
+            hints: NotificationHints {
+                sandbox_app_id: Some("org.signal.Signal".to_string()),
+                ..NotificationHints::default()
+            },
+            ..Notification::default()
+        };
+        assert!(matcher.matches(&n));
+
+        n.hints.sandbox_app_id = None;
+        assert!(!matcher.matches(&n), "unsandboxed senders have no app id");
+        assert_eq!(
+            serde_json::to_string(&matcher).unwrap(),
+            r#"{"sandbox_app_id":"org.signal.*"}"#
+        );
+    }
+}
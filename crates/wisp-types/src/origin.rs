@@ -0,0 +1,77 @@
+//! Identity of forwarded notifications: the machine they first appeared on and a hash
+//! of their content, carried as hints so every hop can recognise loops and duplicates.
+
+use crate::{Notification, Urgency};
+
+/// Hint naming the instance a forwarded notification first appeared on.
+pub const ORIGIN_HINT: &str = "x-wispd-origin";
+/// Hint carrying [`content_hash`] of a forwarded notification, as 16 hex digits.
+pub const HASH_HINT: &str = "x-wispd-hash";
+
+/// Hash of `app_name`, `summary`, `body` and `urgency` (`low`/`normal`/`critical`).
+///
+/// 64-bit FNV-1a with a separator after each field, so it is the same on every machine
+/// and build, unlike `std`'s hashers.
+pub fn content_hash(app_name: &str, summary: &str, body: &str, urgency: &str) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    [app_name, summary, body, urgency]
+        .iter()
+        .flat_map(|field| field.bytes().chain([0]))
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        })
+}
+
+/// [`content_hash`] of a notification, as the forwarder computes it for the same content.
+pub fn notification_hash(notification: &Notification) -> u64 {
+    let urgency = match notification.urgency {
+        Urgency::Low => "low",
+        Urgency::Normal => "normal",
+        Urgency::Critical => "critical",
+    };
+    content_hash(
+        &notification.app_name,
+        &notification.summary,
+        &notification.body,
+        urgency,
+    )
+}
+
+/// The [`HASH_HINT`] form of a hash.
+pub fn format_hash(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+/// Reads a [`HASH_HINT`] value back.
+pub fn parse_hash(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.trim(), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_stable_and_separates_fields() {
+        assert_eq!(content_hash("", "", "", ""), 0x4d25_767f_9dce_13f5);
+        let hash = content_hash("mail", "Ada", "see you", "normal");
+        assert_eq!(hash, content_hash("mail", "Ada", "see you", "normal"));
+        assert_ne!(hash, content_hash("mail", "Ada", "see you", "critical"));
+        assert_ne!(
+            content_hash("ab", "c", "", "normal"),
+            content_hash("a", "bc", "", "normal")
+        );
+        assert_eq!(parse_hash(&format_hash(hash)), Some(hash));
+        assert_eq!(format_hash(1), "0000000000000001");
+        assert_eq!(parse_hash("nope"), None);
+
+        let notification = Notification {
+            app_name: "mail".to_string(),
+            summary: "Ada".to_string(),
+            body: "see you".to_string(),
+            ..Notification::default()
+        };
+        assert_eq!(notification_hash(&notification), hash);
+    }
+}
@@ -0,0 +1,161 @@
+//! Per-app content redaction shared by the popup UI and the forwarder.
+//!
+//! A rule matches `app_name` against a [`Pattern`] and hides either the body or the
+//! whole text behind a placeholder. The first matching rule wins.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::matcher::Pattern;
+
+/// Default for the text shown in place of hidden content.
+pub const DEFAULT_PLACEHOLDER: &str = "New message";
+
+/// What a matching rule hides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Redaction {
+    /// The body becomes the placeholder.
+    #[default]
+    Body,
+    /// The summary becomes the placeholder and the body is dropped.
+    All,
+}
+
+/// One privacy rule. In config either a bare pattern (`"Signal"`, redacting the body) or
+/// a table (`{ app = "KeePass*", redact = "all" }`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RuleSpec")]
+pub struct PrivacyRule {
+    pub app: Pattern,
+    pub redact: Redaction,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RuleSpec {
+    App(String),
+    Rule {
+        app: String,
+        #[serde(default)]
+        redact: Redaction,
+    },
+}
+
+impl TryFrom<RuleSpec> for PrivacyRule {
+    type Error = String;
+
+    fn try_from(spec: RuleSpec) -> Result<Self, Self::Error> {
+        let (app, redact) = match spec {
+            RuleSpec::App(app) => (app, Redaction::Body),
+            RuleSpec::Rule { app, redact } => (app, redact),
+        };
+        let app = app
+            .parse()
+            .map_err(|err| format!("privacy rule {app:?}: {err}"))?;
+        Ok(Self { app, redact })
+    }
+}
+
+/// Parses the compact `pattern` or `pattern:body` / `pattern:all` form used in
+/// environment variables. A prefixed pattern such as `re:^org\.signal` may contain
+/// colons of its own.
+impl FromStr for PrivacyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let prefixed = ["exact:", "glob:", "re:"]
+            .iter()
+            .any(|prefix| s.trim_start().starts_with(prefix));
+        let (app, redact) = match s.rsplit_once(':') {
+            Some((app, "body")) => (app, Redaction::Body),
+            Some((app, "all")) => (app, Redaction::All),
+            Some(_) if prefixed => (s, Redaction::Body),
+            Some((_, other)) => return Err(format!("unknown redaction {other:?} (body, all)")),
+            None => (s, Redaction::Body),
+        };
+        let app = app
+            .parse()
+            .map_err(|err| format!("app pattern {:?}: {err}", app.trim()))?;
+        Ok(Self { app, redact })
+    }
+}
+
+impl PrivacyRule {
+    pub fn matches(&self, app_name: &str) -> bool {
+        self.app.matches(app_name)
+    }
+}
+
+/// Applies the first rule matching `app_name` to `summary` and `body`. Returns whether
+/// anything was hidden.
+pub fn redact(
+    rules: &[PrivacyRule],
+    placeholder: &str,
+    app_name: &str,
+    summary: &mut String,
+    body: &mut String,
+) -> bool {
+    let Some(rule) = rules.iter().find(|rule| rule.matches(app_name)) else {
+        return false;
+    };
+    match rule.redact {
+        Redaction::Body => *body = placeholder.to_string(),
+        Redaction::All => {
+            *summary = placeholder.to_string();
+            body.clear();
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let rules = [
+            "KeePass*:all".parse::<PrivacyRule>().unwrap(),
+            "*".parse().unwrap(),
+        ];
+        let (mut summary, mut body) = ("Vault unlocked".to_string(), "hunter2".to_string());
+        assert!(redact(
+            &rules,
+            "hidden",
+            "KeePassXC",
+            &mut summary,
+            &mut body
+        ));
+        assert_eq!((summary.as_str(), body.as_str()), ("hidden", ""));
+
+        let (mut summary, mut body) = ("Ada".to_string(), "see you".to_string());
+        assert!(redact(&rules, "hidden", "Signal", &mut summary, &mut body));
+        assert_eq!((summary.as_str(), body.as_str()), ("Ada", "hidden"));
+
+        assert!(!redact(&[], "hidden", "Signal", &mut summary, &mut body));
+    }
+
+    #[test]
+    fn rules_parse_from_the_compact_form() {
+        let rule: PrivacyRule = "Signal".parse().unwrap();
+        assert_eq!(rule.redact, Redaction::Body);
+        let rule: PrivacyRule = " Bitwarden :all".parse().unwrap();
+        assert_eq!(
+            (rule.app.to_string().as_str(), rule.redact),
+            ("Bitwarden", Redaction::All)
+        );
+        assert!("Signal:summary".parse::<PrivacyRule>().is_err());
+        assert!(":all".parse::<PrivacyRule>().is_err());
+
+        let rule: PrivacyRule = "re:^org\\.signal:all".parse().unwrap();
+        assert_eq!(rule.redact, Redaction::All);
+        assert!(rule.matches("org.signal.Signal"));
+        let rule: PrivacyRule = "re:^(signal|threema)$".parse().unwrap();
+        assert_eq!(rule.redact, Redaction::Body);
+        assert!(rule.matches("Threema"));
+        let err = "re:(".parse::<PrivacyRule>().unwrap_err();
+        assert!(err.contains("invalid regex"), "{err}");
+    }
+}
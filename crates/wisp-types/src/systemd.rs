@@ -0,0 +1,168 @@
+//! systemd integration shared by the daemons: the `sd_notify` protocol and quoting for
+//! `Exec` lines in generated unit and service files.
+//!
+//! A [`Notifier`] sends state lines such as `READY=1` as one datagram to the socket named
+//! by `$NOTIFY_SOCKET`. systemd only sets that variable for `Type=notify` units (or with
+//! `NotifyAccess=`), so everywhere else notifying is a no-op.
+
+use std::{env, io, os::unix::net::UnixDatagram, path::PathBuf, time::Duration};
+
+/// Where notifications go: a socket path, or a Linux abstract socket name (`@name`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Socket {
+    Path(PathBuf),
+    Abstract(String),
+}
+
+/// Sends state to the service manager.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    socket: Option<Socket>,
+}
+
+impl Notifier {
+    /// The manager named by `$NOTIFY_SOCKET`; disabled when it is unset or empty.
+    pub fn from_env() -> Self {
+        Self {
+            socket: env::var("NOTIFY_SOCKET")
+                .ok()
+                .filter(|socket| !socket.is_empty())
+                .map(|socket| match socket.strip_prefix('@') {
+                    Some(name) => Socket::Abstract(name.to_string()),
+                    None => Socket::Path(PathBuf::from(socket)),
+                }),
+        }
+    }
+
+    /// Notifies the socket at `path`.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: Some(Socket::Path(path.into())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Sends newline-separated `KEY=value` assignments in one datagram.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        let Some(socket) = &self.socket else {
+            return Ok(());
+        };
+        let sender = UnixDatagram::unbound()?;
+        match socket {
+            Socket::Path(path) => sender.send_to(state.as_bytes(), path)?,
+            Socket::Abstract(name) => send_abstract(&sender, name, state)?,
+        };
+        Ok(())
+    }
+
+    /// Startup finished.
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Free-form status shown by `systemctl status`; kept to its first line.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        let line = status.lines().next().unwrap_or_default();
+        self.notify(&format!("STATUS={line}"))
+    }
+
+    /// Resets the watchdog timer.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    /// Shutdown began.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(sender: &UnixDatagram, name: &str, state: &str) -> io::Result<usize> {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let addr = SocketAddr::from_abstract_name(name)?;
+    sender.send_to_addr(state.as_bytes(), &addr)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_: &UnixDatagram, name: &str, _: &str) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("abstract notify socket @{name} needs Linux"),
+    ))
+}
+
+/// The unit's `WatchdogSec=` from `$WATCHDOG_USEC`, when it is meant for this process
+/// (`$WATCHDOG_PID` unset or ours). Ping at least twice per interval.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.trim().parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    env::var("WATCHDOG_USEC")
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Quotes one argument of an `Exec` line. Both D-Bus service files and systemd units
+/// split those on whitespace but honour double quotes.
+pub fn quote_exec_arg(arg: &str) -> String {
+    if arg
+        .chars()
+        .any(|c| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_arrive_as_single_datagrams() {
+        let dir = env::temp_dir().join(format!("wisp-sd-notify-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        let mut buf = [0; 256];
+        let mut received = || {
+            let len = manager.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        };
+
+        let notifier = Notifier::at(&path);
+        assert!(notifier.is_enabled());
+        notifier.ready().unwrap();
+        assert_eq!(received(), "READY=1");
+        notifier.status("forwarded=3\nignored").unwrap();
+        assert_eq!(received(), "STATUS=forwarded=3");
+        notifier.watchdog().unwrap();
+        assert_eq!(received(), "WATCHDOG=1");
+
+        let disabled = Notifier::default();
+        assert!(!disabled.is_enabled());
+        disabled.ready().unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exec_args_are_quoted_only_when_needed() {
+        assert_eq!(quote_exec_arg("/usr/bin/wispd"), "/usr/bin/wispd");
+        assert_eq!(quote_exec_arg("/opt/wisp d/wispd"), "\"/opt/wisp d/wispd\"");
+        assert_eq!(quote_exec_arg("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
@@ -0,0 +1,135 @@
+//! `{placeholder}` templates shared by the popup format, the layer-shell namespace and the
+//! forwarder.
+//!
+//! A placeholder is `{name}` or `{name|filter|...}`. The only filter is `trunc:N`, which
+//! keeps at most `N` graphemes and marks a cut with `…`. Unknown placeholders and
+//! malformed filters are left in the output verbatim so typos are visible.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Renders `template`, resolving placeholder names through `lookup`.
+pub fn render<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let placeholder = &rest[1..end];
+        match expand(placeholder, &lookup) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn expand<F>(placeholder: &str, lookup: &F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut parts = placeholder.split('|');
+    let mut value = lookup(parts.next()?.trim())?;
+    for filter in parts {
+        let (name, arg) = filter.trim().split_once(':').unwrap_or((filter.trim(), ""));
+        value = match name {
+            "trunc" => truncate(&value, arg.trim().parse().ok()?),
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Cuts `text` to at most `max` graphemes, the last being `…` when anything was dropped.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.graphemes(true).count() <= max {
+        return text.to_string();
+    }
+    let mut kept: String = text.graphemes(true).take(max.saturating_sub(1)).collect();
+    kept.truncate(kept.trim_end().len());
+    if max > 0 {
+        kept.push('…');
+    }
+    kept
+}
+
+/// `text` as a lowercase slug for identifiers such as layer-shell namespaces: each run of
+/// characters other than letters and digits becomes one `-`, with none at either end.
+/// Letters outside ASCII are kept; a text without letters or digits gives `unknown`.
+pub fn slug(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut gap = false;
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            gap = true;
+            continue;
+        }
+        if gap && !out.is_empty() {
+            out.push('-');
+        }
+        gap = false;
+        out.extend(c.to_lowercase());
+    }
+    if out.is_empty() {
+        return "unknown".to_string();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "app_name" => Some("thunderbird".to_string()),
+            "summary" => Some("New message".to_string()),
+            "body" => Some("Grüße aus Köln 👩‍👩‍👧 und 👍🏽 bis bald".to_string()),
+            "empty" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn placeholders_are_substituted() {
+        assert_eq!(
+            render("[desktop] {app_name}: {summary}", lookup),
+            "[desktop] thunderbird: New message"
+        );
+        assert_eq!(render("{empty}", lookup), "");
+    }
+
+    #[test]
+    fn trunc_filter_is_grapheme_safe() {
+        assert_eq!(render("{body|trunc:17}", lookup), "Grüße aus Köln 👩‍👩‍👧…");
+        assert_eq!(render("{body|trunc:200}", lookup), lookup("body").unwrap());
+        assert_eq!(render("{summary | trunc:4}", lookup), "New…");
+        assert_eq!(truncate("👍🏽👍🏽👍🏽", 2), "👍🏽…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+
+    #[test]
+    fn unknown_placeholders_and_filters_stay_verbatim() {
+        assert_eq!(render("{nope} {summary}", lookup), "{nope} New message");
+        assert_eq!(render("{body|upper}", lookup), "{body|upper}");
+        assert_eq!(render("{body|trunc:x}", lookup), "{body|trunc:x}");
+        assert_eq!(render("open {brace", lookup), "open {brace");
+    }
+
+    #[test]
+    fn slugs_keep_letters_and_digits_and_collapse_the_rest() {
+        assert_eq!(slug("Signal Desktop"), "signal-desktop");
+        assert_eq!(slug("org.gnome.Nautilus"), "org-gnome-nautilus");
+        assert_eq!(slug("  Télégramme ✉ Beta!! "), "télégramme-beta");
+        assert_eq!(slug("ÄPFEL_Saft 2"), "äpfel-saft-2");
+        assert_eq!(slug("👍🏽 "), "unknown");
+        assert_eq!(slug(""), "unknown");
+    }
+}